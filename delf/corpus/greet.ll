@@ -0,0 +1,26 @@
+; The program every file in the corpus is built from, by `make corpus` at the top of the repo.
+; No libc and no syscalls, so the same IR compiles for every target; `_start` is only there to
+; give the linked ones an entry point.
+
+@message = constant [23 x i8] c"Hello from the corpus!\0A"
+@step = global i32 1
+; A pointer the linker fills in, and a word-sized absolute relocation until it does
+@greeting = global i8* getelementptr ([23 x i8], [23 x i8]* @message, i32 0, i32 0)
+@calls = global i32 0
+@scratch = global [64 x i8] zeroinitializer
+
+define i8* @greet() {
+  %n = load i32, i32* @calls
+  %step = load i32, i32* @step
+  %m = add i32 %n, %step
+  store i32 %m, i32* @calls
+  ret i8* getelementptr ([23 x i8], [23 x i8]* @message, i32 0, i32 0)
+}
+
+define void @_start() {
+  %message = call i8* @greet()
+  br label %spin
+
+spin:
+  br label %spin
+}
@@ -0,0 +1,83 @@
+use alloc::collections::BTreeSet;
+
+use crate::{note::GNU_PROPERTY_X86_FEATURE_1_AND, prelude::*, types::*, u32_at, FileHeader};
+
+// The landing pads indirect calls and jumps have to hit once IBT is enforced
+pub const ENDBR64: [u8; 4] = [0xf3, 0x0f, 0x1e, 0xfa];
+pub const ENDBR32: [u8; 4] = [0xf3, 0x0f, 0x1e, 0xfb];
+// IBT's bit in GNU_PROPERTY_X86_FEATURE_1_AND, SHSTK is the next
+const FEATURE_1_IBT: u32 = 1;
+
+// How a file's functions line up with the IBT bit of its GNU property note. Only functions other
+// objects can call are looked at: compilers leave endbr out of local functions whose address
+// is never taken.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EndbrReport {
+    pub ibt: bool,
+    pub functions: usize,
+    // Functions not starting with endbr, by name and address
+    pub missing: Vec<(String, Addr)>,
+}
+
+impl EndbrReport {
+    // The kernel enables IBT for the file, and the first indirect call to one of `missing` will
+    // fault with a control protection exception
+    pub fn is_mismatch(&self) -> bool {
+        self.ibt && !self.missing.is_empty()
+    }
+}
+
+impl FileHeader {
+    // Whether the file's GNU property note marks it as built for indirect branch tracking
+    pub fn ibt(&self) -> bool {
+        self.notes()
+            .iter()
+            .flat_map(|(_, note)| note.gnu_properties(self.class))
+            .any(|property| {
+                property.typ == GNU_PROPERTY_X86_FEATURE_1_AND
+                    && u32_at(&property.data, 0).unwrap_or(0) & FEATURE_1_IBT != 0
+            })
+    }
+
+    // Checks that global functions start with endbr64 (endbr32 for i386). None for other
+    // machines, which have no IBT.
+    pub fn endbr_report(&self) -> Option<EndbrReport> {
+        let endbr = match self.machine {
+            Machine::X86_64 => ENDBR64,
+            Machine::X86 => ENDBR32,
+            _ => return None,
+        };
+        let mut report = EndbrReport {
+            ibt: self.ibt(),
+            ..Default::default()
+        };
+        // Aliases share an entry point
+        let mut seen = BTreeSet::new();
+        for sym in self.read_section_syms() {
+            if sym.typ() != Some(SymType::Func)
+                || sym.bind() == Some(SymBind::Local)
+                || !seen.insert((sym.section_index(), sym.value.0))
+            {
+                continue;
+            }
+            let code = match (self.typ, sym.shndx) {
+                (_, SectionIdx::Undef) => continue,
+                // Relocatable objects hold section offsets
+                (Type::Rel, _) => sym.section_index().and_then(|index| {
+                    let sh = self.section_headers.get(index)?;
+                    sh.data.get(sym.value.0 as usize..)
+                }),
+                _ => self.bytes_at(sym.value),
+            };
+            let code = match code {
+                Some(code) => code,
+                None => continue,
+            };
+            report.functions += 1;
+            if !code.starts_with(&endbr) {
+                report.missing.push((sym.name.clone(), sym.value));
+            }
+        }
+        Some(report)
+    }
+}
@@ -0,0 +1,54 @@
+// Printable runs this long are text, not instructions that happen to be printable
+const MIN_STRING: usize = 8;
+// Padding and fill: the same byte this many times over
+const MIN_FILL: usize = 8;
+// Below this many bytes there is too little to judge
+pub const MIN_SCORED: usize = 256;
+// Executable segments scoring less are flagged as data
+pub const MIN_CODE_LIKENESS: f64 = 0.3;
+
+// Share of `data` that could be machine code: everything but zeroes, text and runs of one
+// repeated byte. Compiled x86-64 scores well above 0.5; string tables, zero fill and other data
+// mapped executable score near 0. Packed or encrypted code scores high too, this only tells
+// data from not-data.
+pub fn code_likeness(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut other = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let rest = &data[i..];
+        let text = rest
+            .iter()
+            .take_while(|&&b| (0x20..=0x7e).contains(&b))
+            .count();
+        let fill = rest.iter().take_while(|&&b| b == rest[0]).count();
+        let run = if text >= MIN_STRING {
+            text
+        } else if fill >= MIN_FILL || rest[0] == 0 {
+            fill
+        } else {
+            other += 1;
+            1
+        };
+        i += run;
+    }
+    other as f64 / data.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn likeness() {
+        // push rbp; mov rbp, rsp; mov dword [rbp-4], edi; mov eax, 0; pop rbp; ret
+        let code = b"\x55\x48\x89\xe5\x89\x7d\xfc\xb8\x00\x00\x00\x00\x5d\xc3";
+        assert!(code_likeness(code) > 0.6);
+        let strings = b"GLIBC_2.2.5\0__libc_start_main\0\0\0\0\0\0\0\0";
+        assert_eq!(code_likeness(strings), 0.0);
+        assert_eq!(code_likeness(&[0xcc; 32]), 0.0);
+        assert_eq!(code_likeness(b""), 0.0);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    note::{MappedFile, PrStatus, PsInfo, SigInfo},
+    prelude::*,
+    types::{SegmentBits, SegmentType, Type},
+    FileHeader,
+};
+
+// A memory mapping of the dumped process, from a LOAD segment of the core
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub start: u64,
+    pub end: u64,
+    pub flags: SegmentBits,
+    // Bytes of it the core holds; the kernel leaves out file-backed pages nobody wrote to
+    pub dumped: u64,
+    // The file NT_FILE says it maps and the offset of `start` in it, None for anonymous memory
+    pub file: Option<(String, u64)>,
+}
+
+// What a core dump (ET_CORE) says about the process it was taken of, from its notes and LOAD
+// segments
+#[derive(Debug, Clone)]
+pub struct CoreFile {
+    pub process: Option<PsInfo>,
+    // One per thread, the one that took the signal first
+    pub threads: Vec<PrStatus>,
+    pub signal: Option<SigInfo>,
+    pub files: Vec<MappedFile>,
+    pub mappings: Vec<Mapping>,
+}
+
+impl CoreFile {
+    // None unless `file` is a core dump
+    pub fn parse(file: &FileHeader) -> Option<Self> {
+        if file.typ != Type::Core {
+            return None;
+        }
+        let notes: Vec<_> = file.notes().into_iter().map(|(_, note)| note).collect();
+        let files: Vec<MappedFile> = notes
+            .iter()
+            .flat_map(|note| note.mapped_files(file.class))
+            .collect();
+        let mappings = file
+            .segments_of_type(SegmentType::Load)
+            .map(|ph| {
+                let start = ph.virt_addr.0;
+                Mapping {
+                    start,
+                    end: start.saturating_add(ph.mem_size.0),
+                    flags: ph.flags,
+                    dumped: ph.file_size.0,
+                    file: file_at(&files, start).map(|(f, offset)| (f.path.clone(), offset)),
+                }
+            })
+            .collect();
+        Some(Self {
+            process: notes.iter().find_map(|note| note.psinfo(file.class)),
+            threads: notes
+                .iter()
+                .filter_map(|note| note.prstatus(file.class, file.machine))
+                .collect(),
+            signal: notes.iter().find_map(|note| note.siginfo(file.class)),
+            files,
+            mappings,
+        })
+    }
+
+    // The thread the fatal signal was delivered to
+    pub fn faulting_thread(&self) -> Option<&PrStatus> {
+        self.threads.first()
+    }
+
+    // The mapped file `addr` lies in, and its offset in that file
+    pub fn file_at(&self, addr: u64) -> Option<(&MappedFile, u64)> {
+        file_at(&self.files, addr)
+    }
+}
+
+fn file_at(files: &[MappedFile], addr: u64) -> Option<(&MappedFile, u64)> {
+    files
+        .iter()
+        .find(|f| (f.start..f.end).contains(&addr))
+        .map(|f| (f, addr - f.start + f.offset))
+}
@@ -0,0 +1,163 @@
+use alloc::sync::Arc;
+use core::{fmt, ops::Deref, ops::Range};
+
+use crate::prelude::*;
+
+// A range of the buffer a file was parsed from. Every segment and section points into the same
+// buffer instead of holding a copy of its bytes, so a file memory-mapped by the caller is only
+// read where something looks at it.
+#[derive(Clone)]
+pub struct Data {
+    buffer: Arc<dyn Buffer>,
+    range: Range<usize>,
+}
+
+// Where the bytes of a Data come from. Anything holding them all is one; a buffer that only has
+// some of them at hand, like a file being downloaded, fills a range in when it is looked at.
+pub trait Buffer: Send + Sync {
+    // Every byte, those not at hand yet reading as zeroes
+    fn present(&self) -> &[u8];
+
+    // `range` of it, filled in first
+    fn bytes(&self, range: Range<usize>) -> &[u8] {
+        &self.present()[range]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Buffer for T {
+    fn present(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
+
+impl Data {
+    // All of `buffer`, which can be anything holding bytes: a Vec, a memory map, ...
+    pub fn new(buffer: impl Buffer + 'static) -> Self {
+        let len = buffer.present().len();
+        Self {
+            buffer: Arc::new(buffer),
+            range: 0..len,
+        }
+    }
+
+    // The bytes as they are, without filling anything in. The parser reads the file through
+    // this, so only the headers and tables it looks into need to be at hand, and section and
+    // segment contents are filled in as they are used.
+    pub fn present(&self) -> &[u8] {
+        &self.buffer.present()[self.range.clone()]
+    }
+
+    // Without filling anything in, unlike the slice's
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
+    // A view of `range` within this one, sharing the buffer. Panics if it runs past the end,
+    // like indexing would.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(
+            range.start <= range.end && range.end <= self.range.len(),
+            "{:?} is out of bounds of {} bytes",
+            range,
+            self.range.len()
+        );
+        Self {
+            buffer: Arc::clone(&self.buffer),
+            range: self.range.start + range.start..self.range.start + range.end,
+        }
+    }
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.buffer.bytes(self.range.clone())
+    }
+}
+
+impl AsRef<[u8]> for Data {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Default for Data {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for Data {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+// Copies, for callers that only have a borrowed slice
+impl From<&[u8]> for Data {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes.to_vec())
+    }
+}
+
+impl PartialEq for Data {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Data {}
+
+impl fmt::Debug for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Data({:#x?}, {} bytes)", self.range, self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn views() {
+        let data = Data::from(b"\x7fELF\x02\x01".to_vec());
+        let tail = data.slice(2..6);
+        assert_eq!(&tail[..], b"LF\x02\x01");
+        assert_eq!(&tail.slice(1..3)[..], b"F\x02");
+        assert!(Arc::ptr_eq(&data.buffer, &tail.buffer));
+        assert!(data.slice(6..6).is_empty());
+    }
+
+    // Hands out zeroes until a range is looked at
+    struct Lazy(&'static [u8], [AtomicU8; 4]);
+
+    impl Buffer for Lazy {
+        fn present(&self) -> &[u8] {
+            unsafe { core::slice::from_raw_parts(self.1.as_ptr() as *const u8, self.1.len()) }
+        }
+
+        fn bytes(&self, range: Range<usize>) -> &[u8] {
+            for i in range.clone() {
+                self.1[i].store(self.0[i], Ordering::Relaxed);
+            }
+            &self.present()[range]
+        }
+    }
+
+    #[test]
+    fn lazy_buffers_fill_in_what_is_looked_at() {
+        let data = Data::new(Lazy(b"\x7fELF", Default::default()));
+        let tail = data.slice(2..4);
+        assert_eq!(data.present(), b"\0\0\0\0");
+        assert_eq!(&tail[..], b"LF");
+        assert_eq!(data.present(), b"\0\0LF");
+        assert_eq!(&data[..], b"\x7fELF");
+    }
+}
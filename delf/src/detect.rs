@@ -0,0 +1,235 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub enum Class {
+    Elf32,
+    Elf64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+    Lz4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Elf { class: Class, endian: Endian },
+    // ELF magic followed by an invalid class or data byte
+    BadElf,
+    Archive,
+    ThinArchive,
+    Cpio,
+    Tar,
+    Compressed(Compression),
+    Pe,
+    Dos,
+    MachO { class: Class, endian: Endian },
+    MachOFat,
+    JavaClass,
+    Wasm,
+    Script,
+    Empty,
+    Unknown,
+}
+
+const SIGNATURES: &[(&[u8], Format)] = &[
+    (b"!<arch>\n", Format::Archive),
+    (b"!<thin>\n", Format::ThinArchive),
+    (b"070701", Format::Cpio),
+    (b"070702", Format::Cpio),
+    (&[0x1f, 0x8b], Format::Compressed(Compression::Gzip)),
+    (b"\xfd7zXZ\0", Format::Compressed(Compression::Xz)),
+    (
+        &[0x28, 0xb5, 0x2f, 0xfd],
+        Format::Compressed(Compression::Zstd),
+    ),
+    (b"BZh", Format::Compressed(Compression::Bzip2)),
+    (
+        &[0x04, 0x22, 0x4d, 0x18],
+        Format::Compressed(Compression::Lz4),
+    ),
+    (b"\0asm", Format::Wasm),
+    (b"#!", Format::Script),
+];
+
+const MACHO: &[([u8; 4], Format)] = &[
+    (
+        [0xce, 0xfa, 0xed, 0xfe],
+        Format::MachO {
+            class: Class::Elf32,
+            endian: Endian::Little,
+        },
+    ),
+    (
+        [0xcf, 0xfa, 0xed, 0xfe],
+        Format::MachO {
+            class: Class::Elf64,
+            endian: Endian::Little,
+        },
+    ),
+    (
+        [0xfe, 0xed, 0xfa, 0xce],
+        Format::MachO {
+            class: Class::Elf32,
+            endian: Endian::Big,
+        },
+    ),
+    (
+        [0xfe, 0xed, 0xfa, 0xcf],
+        Format::MachO {
+            class: Class::Elf64,
+            endian: Endian::Big,
+        },
+    ),
+];
+
+// Classifies `input` from its first bytes, without parsing anything beyond the identification
+pub fn detect(input: &[u8]) -> Format {
+    if input.is_empty() {
+        return Format::Empty;
+    }
+    if input.starts_with(b"\x7fELF") {
+        let class = match input.get(4) {
+            Some(1) => Class::Elf32,
+            Some(2) => Class::Elf64,
+            _ => return Format::BadElf,
+        };
+        let endian = match input.get(5) {
+            Some(1) => Endian::Little,
+            Some(2) => Endian::Big,
+            _ => return Format::BadElf,
+        };
+        return Format::Elf { class, endian };
+    }
+    if let Some((_, format)) = SIGNATURES.iter().find(|(sig, _)| input.starts_with(sig)) {
+        return *format;
+    }
+    if let Some((_, format)) = MACHO.iter().find(|(sig, _)| input.starts_with(sig)) {
+        return *format;
+    }
+    if input.starts_with(&[0xca, 0xfe, 0xba, 0xbe]) {
+        // Fat Mach-O and Java class files share a magic; the next word is a small
+        // architecture count for the former and a class file version (>= 45) for the latter
+        return match input.get(4..8) {
+            Some(&[a, b, c, d]) if u32::from_be_bytes([a, b, c, d]) < 45 => Format::MachOFat,
+            _ => Format::JavaClass,
+        };
+    }
+    if input.get(257..262) == Some(b"ustar") {
+        return Format::Tar;
+    }
+    if input.starts_with(b"MZ") {
+        let pe_offset = input
+            .get(0x3c..0x40)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+        return match pe_offset.and_then(|o| input.get(o..o + 4)) {
+            Some(b"PE\0\0") => Format::Pe,
+            _ => Format::Dos,
+        };
+    }
+    Format::Unknown
+}
+
+impl Format {
+    // delf parses ELF of either class and byte order
+    pub fn is_supported(&self) -> bool {
+        matches!(self, Format::Elf { .. })
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Class::Elf32 => write!(f, "32-bit"),
+            Class::Elf64 => write!(f, "64-bit"),
+        }
+    }
+}
+
+impl fmt::Display for Endian {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Endian::Little => write!(f, "little-endian"),
+            Endian::Big => write!(f, "big-endian"),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Elf { class, endian } => write!(f, "{} {} ELF", class, endian),
+            Format::BadElf => write!(f, "ELF with an invalid class or data encoding"),
+            Format::Archive => write!(f, "ar archive"),
+            Format::ThinArchive => write!(f, "thin ar archive"),
+            Format::Cpio => write!(f, "cpio archive"),
+            Format::Tar => write!(f, "tar archive"),
+            Format::Compressed(c) => write!(f, "{:?} compressed data", c),
+            Format::Pe => write!(f, "PE/COFF executable"),
+            Format::Dos => write!(f, "DOS MZ executable"),
+            Format::MachO { class, endian } => write!(f, "{} {} Mach-O", class, endian),
+            Format::MachOFat => write!(f, "universal (fat) Mach-O"),
+            Format::JavaClass => write!(f, "Java class file"),
+            Format::Wasm => write!(f, "WebAssembly module"),
+            Format::Script => write!(f, "script with a #! interpreter line"),
+            Format::Empty => write!(f, "empty file"),
+            Format::Unknown => write!(f, "unknown data"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_common_formats() {
+        assert!(detect(b"\x7fELF\x02\x01\x01\0").is_supported());
+        assert!(detect(b"\x7fELF\x01\x01\x01\0").is_supported());
+        assert!(detect(b"\x7fELF\x01\x02\x01\0").is_supported());
+        assert_eq!(
+            detect(b"\x7fELF\x01\x02\x01\0"),
+            Format::Elf {
+                class: Class::Elf32,
+                endian: Endian::Big
+            }
+        );
+        assert_eq!(detect(b"\x7fELF\x03"), Format::BadElf);
+        assert_eq!(detect(b"!<arch>\nfoo.o/"), Format::Archive);
+        let mut tar = vec![0; 512];
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert_eq!(detect(&tar), Format::Tar);
+        assert_eq!(
+            detect(&[0x1f, 0x8b, 0x08]),
+            Format::Compressed(Compression::Gzip)
+        );
+        assert_eq!(
+            detect(&[0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 2]),
+            Format::MachOFat
+        );
+        assert_eq!(
+            detect(&[0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52]),
+            Format::JavaClass
+        );
+
+        let mut pe = vec![0; 0x84];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x80;
+        pe[0x80..].copy_from_slice(b"PE\0\0");
+        assert_eq!(detect(&pe), Format::Pe);
+        assert_eq!(detect(&pe[..0x40]), Format::Dos);
+        assert_eq!(detect(b""), Format::Empty);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{prelude::*, types::Machine};
+
+pub const EF_ARM_EABIMASK: u32 = 0xff00_0000;
+pub const EF_ARM_BE8: u32 = 0x0080_0000;
+pub const EF_ARM_ABI_FLOAT_SOFT: u32 = 0x200;
+pub const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+
+pub const EF_RISCV_RVC: u32 = 0x1;
+pub const EF_RISCV_FLOAT_ABI: u32 = 0x6;
+pub const EF_RISCV_RVE: u32 = 0x8;
+pub const EF_RISCV_TSO: u32 = 0x10;
+
+pub const EF_MIPS_NOREORDER: u32 = 0x1;
+pub const EF_MIPS_PIC: u32 = 0x2;
+pub const EF_MIPS_CPIC: u32 = 0x4;
+pub const EF_MIPS_ABI2: u32 = 0x20;
+pub const EF_MIPS_FP64: u32 = 0x200;
+pub const EF_MIPS_NAN2008: u32 = 0x400;
+pub const EF_MIPS_ABI: u32 = 0x0000_f000;
+pub const EF_MIPS_ARCH: u32 = 0xf000_0000;
+
+pub const EF_PPC64_ABI: u32 = 0x3;
+
+pub const EF_LARCH_ABI_MODIFIER: u32 = 0x7;
+pub const EF_LARCH_OBJABI_V1: u32 = 0x40;
+
+// What `flags`, the e_flags of a `machine` file, says. Its meaning is up to each psABI; machines
+// elk doesn't know the flags of, and x86 which defines none, give an empty list.
+pub fn decode(machine: Machine, flags: u32) -> Vec<String> {
+    match machine {
+        Machine::Arm => arm(flags),
+        Machine::RiscV => riscv(flags),
+        Machine::Mips => mips(flags),
+        Machine::PowerPC64 => match flags & EF_PPC64_ABI {
+            0 => vec![],
+            abi => vec![format!("ELFv{} ABI", abi)],
+        },
+        Machine::LoongArch => loongarch(flags),
+        _ => vec![],
+    }
+}
+
+// The top byte is the EABI version; before EABI the bits meant something else entirely
+fn arm(flags: u32) -> Vec<String> {
+    let version = (flags & EF_ARM_EABIMASK) >> 24;
+    if version == 0 {
+        return vec!["pre-EABI".into()];
+    }
+    let mut meaning = vec![format!("EABI version {}", version)];
+    if flags & EF_ARM_BE8 != 0 {
+        meaning.push("BE8".into());
+    }
+    if version >= 5 && flags & EF_ARM_ABI_FLOAT_HARD != 0 {
+        meaning.push("hard-float ABI".into());
+    } else if version >= 5 && flags & EF_ARM_ABI_FLOAT_SOFT != 0 {
+        meaning.push("soft-float ABI".into());
+    }
+    meaning
+}
+
+fn riscv(flags: u32) -> Vec<String> {
+    let abi = match flags & EF_RISCV_FLOAT_ABI {
+        0x0 => "soft-float ABI",
+        0x2 => "single-float ABI",
+        0x4 => "double-float ABI",
+        _ => "quad-float ABI",
+    };
+    let mut meaning = vec![abi.to_string()];
+    for (bit, name) in [
+        (EF_RISCV_RVC, "RVC"),
+        (EF_RISCV_RVE, "RVE"),
+        (EF_RISCV_TSO, "TSO"),
+    ] {
+        if flags & bit != 0 {
+            meaning.push(name.into());
+        }
+    }
+    meaning
+}
+
+fn mips(flags: u32) -> Vec<String> {
+    let arch = match (flags & EF_MIPS_ARCH) >> 28 {
+        0x0 => "MIPS I",
+        0x1 => "MIPS II",
+        0x2 => "MIPS III",
+        0x3 => "MIPS IV",
+        0x4 => "MIPS V",
+        0x5 => "MIPS32",
+        0x6 => "MIPS64",
+        0x7 => "MIPS32r2",
+        0x8 => "MIPS64r2",
+        0x9 => "MIPS32r6",
+        0xa => "MIPS64r6",
+        _ => "unknown ISA",
+    };
+    // n32 has a flag of its own, n64 is implied by ELFCLASS64 and leaves the ABI field at 0
+    let abi = match (flags & EF_MIPS_ABI, flags & EF_MIPS_ABI2 != 0) {
+        (_, true) => Some("n32"),
+        (0x1000, _) => Some("o32"),
+        (0x2000, _) => Some("o64"),
+        (0x3000, _) => Some("EABI32"),
+        (0x4000, _) => Some("EABI64"),
+        _ => None,
+    };
+    let mut meaning = vec![arch.to_string()];
+    meaning.extend(abi.map(String::from));
+    for (bit, name) in [
+        (EF_MIPS_NOREORDER, "noreorder"),
+        (EF_MIPS_PIC, "PIC"),
+        (EF_MIPS_CPIC, "CPIC"),
+        (EF_MIPS_FP64, "FP64"),
+        (EF_MIPS_NAN2008, "NaN 2008"),
+    ] {
+        if flags & bit != 0 {
+            meaning.push(name.into());
+        }
+    }
+    meaning
+}
+
+fn loongarch(flags: u32) -> Vec<String> {
+    let abi = match flags & EF_LARCH_ABI_MODIFIER {
+        0x1 => "soft-float ABI",
+        0x2 => "single-float ABI",
+        0x3 => "double-float ABI",
+        _ => "unknown float ABI",
+    };
+    let version = match flags & EF_LARCH_OBJABI_V1 {
+        0 => "object ABI v0",
+        _ => "object ABI v1",
+    };
+    vec![abi.into(), version.into()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_machine() {
+        // What gcc puts in a hard-float Linux binary
+        assert_eq!(
+            decode(Machine::Arm, 0x0500_0400),
+            ["EABI version 5", "hard-float ABI"]
+        );
+        assert_eq!(decode(Machine::Arm, 0x4), ["pre-EABI"]);
+        assert_eq!(decode(Machine::RiscV, 0x5), ["double-float ABI", "RVC"]);
+        assert_eq!(
+            decode(Machine::Mips, 0x7000_1007),
+            ["MIPS32r2", "o32", "noreorder", "PIC", "CPIC"]
+        );
+        assert_eq!(decode(Machine::PowerPC64, 0x2), ["ELFv2 ABI"]);
+        assert_eq!(
+            decode(Machine::LoongArch, 0x43),
+            ["double-float ABI", "object ABI v1"]
+        );
+        assert!(decode(Machine::X86_64, 0x1234).is_empty());
+    }
+}
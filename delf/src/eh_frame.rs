@@ -0,0 +1,131 @@
+use crate::{prelude::*, types::Addr};
+
+pub const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+
+// Contents of PT_GNU_EH_FRAME, the index unwinders binary search to find the FDE for a pc
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EhFrameHdr {
+    pub version: u8,
+    pub eh_frame: Option<Addr>,
+    pub fde_count: Option<u64>,
+    // (initial location, FDE address) pairs, sorted by location
+    pub table: Vec<(Addr, Addr)>,
+}
+
+impl EhFrameHdr {
+    // Lowest and highest initial location in the search table
+    pub fn range(&self) -> Option<(Addr, Addr)> {
+        Some((self.table.first()?.0, self.table.last()?.0))
+    }
+}
+
+// Reads one DW_EH_PE encoded value. `addr` is where `data` is mapped, which pc-relative and
+// data-relative values are measured from. Indirect and unknown encodings give None.
+fn read_encoded(data: &[u8], pos: &mut usize, enc: u8, addr: u64) -> Option<u64> {
+    let field = addr + *pos as u64;
+    let fixed = |size: usize, pos: &mut usize| -> Option<u64> {
+        let bytes = data.get(*pos..*pos + size)?;
+        *pos += size;
+        let mut buf = [0; 8];
+        buf[..size].copy_from_slice(bytes);
+        Some(u64::from_le_bytes(buf))
+    };
+    let sign = |value: u64, bits: u32| ((value << (64 - bits)) as i64 >> (64 - bits)) as u64;
+    let value = match enc & 0x0f {
+        0x00 | 0x04 | 0x0c => fixed(8, pos)?,
+        0x02 => fixed(2, pos)?,
+        0x03 => fixed(4, pos)?,
+        0x0a => sign(fixed(2, pos)?, 16),
+        0x0b => sign(fixed(4, pos)?, 32),
+        0x01 | 0x09 => {
+            let (mut value, mut shift) = (0u64, 0);
+            loop {
+                let byte = *data.get(*pos)?;
+                *pos += 1;
+                value |= u64::from(byte & 0x7f).checked_shl(shift).unwrap_or(0);
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            match enc & 0x0f {
+                0x09 if shift < 64 => sign(value, shift),
+                _ => value,
+            }
+        }
+        _ => return None,
+    };
+    match enc & 0x70 {
+        0 => Some(value),
+        DW_EH_PE_PCREL => Some(field.wrapping_add(value)),
+        DW_EH_PE_DATAREL => Some(addr.wrapping_add(value)),
+        _ => None,
+    }
+}
+
+// Parses the header and as much of the search table as the data holds. `addr` is the virtual
+// address of the segment.
+pub fn parse_eh_frame_hdr(data: &[u8], addr: Addr) -> Option<EhFrameHdr> {
+    let (&version, &ptr_enc, &count_enc, &table_enc) =
+        (data.first()?, data.get(1)?, data.get(2)?, data.get(3)?);
+    let mut pos = 4;
+    let read = |enc: u8, pos: &mut usize| match enc {
+        DW_EH_PE_OMIT => None,
+        enc => read_encoded(data, pos, enc, addr.0),
+    };
+    let eh_frame = read(ptr_enc, &mut pos).map(Addr);
+    // An unreadable pointer leaves the position of everything after it unknown
+    let fde_count = match (ptr_enc, eh_frame) {
+        (DW_EH_PE_OMIT, _) | (_, Some(_)) => read(count_enc, &mut pos),
+        _ => None,
+    };
+
+    let mut table = Vec::new();
+    if table_enc != DW_EH_PE_OMIT {
+        for _ in 0..fde_count.unwrap_or(0) {
+            match (read(table_enc, &mut pos), read(table_enc, &mut pos)) {
+                (Some(location), Some(fde)) => table.push((Addr(location), Addr(fde))),
+                _ => break,
+            }
+        }
+    }
+    Some(EhFrameHdr {
+        version,
+        eh_frame,
+        fde_count,
+        table,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datarel_search_table() {
+        // What GCC emits: pcrel sdata4 pointer, udata4 count, datarel sdata4 table
+        let mut data = vec![1, 0x1b, 0x03, 0x3b];
+        data.extend(&0x100i32.to_le_bytes());
+        data.extend(&2u32.to_le_bytes());
+        for (location, fde) in [(-0x800i32, 0x120i32), (-0x700, 0x140)] {
+            data.extend(&location.to_le_bytes());
+            data.extend(&fde.to_le_bytes());
+        }
+
+        let hdr = parse_eh_frame_hdr(&data, Addr(0x2000)).unwrap();
+        assert_eq!(hdr.version, 1);
+        assert_eq!(hdr.eh_frame, Some(Addr(0x2104)));
+        assert_eq!(hdr.fde_count, Some(2));
+        assert_eq!(
+            hdr.table,
+            vec![(Addr(0x1800), Addr(0x2120)), (Addr(0x1900), Addr(0x2140))]
+        );
+        assert_eq!(hdr.range(), Some((Addr(0x1800), Addr(0x1900))));
+
+        // A table cut short keeps the complete entries
+        let hdr = parse_eh_frame_hdr(&data[..data.len() - 4], Addr(0x2000)).unwrap();
+        assert_eq!(hdr.table.len(), 1);
+    }
+}
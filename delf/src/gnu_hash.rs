@@ -0,0 +1,258 @@
+use crate::{
+    detect::{Class, Endian},
+    types::*,
+    u32_at,
+    walk::Walk,
+    FileHeader,
+};
+
+// The hash function of DT_GNU_HASH tables (Bernstein's, h * 33 + c)
+pub fn hash(name: &str) -> u32 {
+    name.bytes()
+        .fold(5381u32, |h, c| h.wrapping_mul(33).wrapping_add(c as u32))
+}
+
+// A DT_GNU_HASH table, borrowed from the file. Only dynamic symbols from `symoffset` on are
+// hashed, sorted by bucket; each chain word holds a symbol's hash with the low bit marking the
+// last symbol of its bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct GnuHash<'a> {
+    pub nbuckets: u32,
+    pub symoffset: u32,
+    pub bloom_shift: u32,
+    // Bloom filter words are address-sized
+    word_bits: u32,
+    bloom: &'a [u8],
+    buckets: &'a [u8],
+    chains: &'a [u8],
+    // Entries of the dynamic symbol table, when known: no chain runs past it
+    symbols: Option<u32>,
+}
+
+impl<'a> GnuHash<'a> {
+    // `table` runs from the start of the table to wherever its chains might end
+    pub fn parse(table: &'a [u8], class: Class) -> Option<Self> {
+        let word = |i: usize| u32_at(table, i * 4);
+        let (nbuckets, symoffset, bloom_size, bloom_shift) =
+            (word(0)?, word(1)?, word(2)?, word(3)?);
+        let word_bits = match class {
+            Class::Elf32 => 32,
+            Class::Elf64 => 64,
+        };
+        let bloom_end = 16 + bloom_size as usize * word_bits as usize / 8;
+        let buckets_end = bloom_end + nbuckets as usize * 4;
+        if nbuckets == 0 || bloom_size == 0 || buckets_end > table.len() {
+            return None;
+        }
+        Some(Self {
+            nbuckets,
+            symoffset,
+            bloom_shift,
+            word_bits,
+            bloom: &table[16..bloom_end],
+            buckets: &table[bloom_end..buckets_end],
+            chains: &table[buckets_end..],
+            symbols: None,
+        })
+    }
+
+    // The table of a file whose dynamic symbol table has `symbols` entries
+    pub fn with_symbols(self, symbols: u32) -> Self {
+        Self {
+            symbols: Some(symbols),
+            ..self
+        }
+    }
+
+    fn bucket(&self, index: u32) -> Option<u32> {
+        u32_at(self.buckets, index as usize * 4)
+    }
+
+    fn chain(&self, sym: u32) -> Option<u32> {
+        u32_at(self.chains, sym.checked_sub(self.symoffset)? as usize * 4)
+    }
+
+    // A chain only ends at a word with its low bit set, which has to come before the end of
+    // the symbol table and of the bytes after the buckets
+    fn chain_walk(&self) -> Walk {
+        let words = self.chains.len() / 4;
+        let limit = match self.symbols {
+            Some(symbols) => words.min(symbols.saturating_sub(self.symoffset) as usize),
+            None => words,
+        };
+        Walk::new("hash chains", limit)
+    }
+
+    fn bloom_word(&self, hash: u32) -> Option<u64> {
+        let size = self.word_bits as usize / 8;
+        let count = self.bloom.len() / size;
+        let start = (hash / self.word_bits) as usize % count * size;
+        let bytes = self.bloom.get(start..start + size)?;
+        Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0u64, |word, &b| word << 8 | b as u64),
+        )
+    }
+
+    // Whether the bloom filter lets a symbol hashing to `hash` through. False positives are
+    // possible, false negatives aren't.
+    pub fn may_contain(&self, hash: u32) -> bool {
+        let mask =
+            1u64 << (hash % self.word_bits) | 1u64 << ((hash >> self.bloom_shift) % self.word_bits);
+        self.bloom_word(hash)
+            .is_some_and(|word| word & mask == mask)
+    }
+
+    // Indices of the dynamic symbols whose hash matches `name`'s, for the caller to compare names
+    pub fn candidates(self, name: &str) -> impl Iterator<Item = u32> + 'a {
+        let hash = hash(name);
+        let mut next = match self.may_contain(hash) {
+            true => self
+                .bucket(hash % self.nbuckets)
+                .filter(|&sym| sym >= self.symoffset),
+            false => None,
+        };
+        let mut walk = self.chain_walk();
+        core::iter::from_fn(move || loop {
+            let sym = next?;
+            walk.visit(sym as u64).ok()?;
+            let word = self.chain(sym)?;
+            next = match word & 1 {
+                0 => Some(sym + 1),
+                _ => None,
+            };
+            if word | 1 == hash | 1 {
+                return Some(sym);
+            }
+        })
+    }
+
+    // One past the highest symbol index the chains reach, the size of the dynamic symbol table
+    pub fn symbol_count(&self) -> Option<u32> {
+        let last = (0..self.nbuckets).filter_map(|i| self.bucket(i)).max()?;
+        if last < self.symoffset {
+            return Some(self.symoffset);
+        }
+        // The last chain ends at the entry with its low bit set
+        let (mut sym, mut walk) = (last, self.chain_walk());
+        while self.chain(sym)? & 1 == 0 {
+            walk.visit(sym as u64).ok()?;
+            sym += 1;
+        }
+        Some(sym + 1)
+    }
+}
+
+impl FileHeader {
+    // None for big-endian files too, whose tables this only reads little-endian
+    pub fn gnu_hash(&self) -> Option<GnuHash<'_>> {
+        if self.endian == Endian::Big {
+            return None;
+        }
+        let table = self.bytes_at(self.dynamic_entry(DynamicTag::GnuHash)?)?;
+        let gnu_hash = GnuHash::parse(table, self.class)?;
+        let dynsym = self
+            .section_headers
+            .iter()
+            .find(|sh| sh.typ == SectionType::DynSym && sh.entsize.0 != 0);
+        Some(match dynsym {
+            Some(sh) => gnu_hash.with_symbols((sh.size.0 / sh.entsize.0) as u32),
+            None => gnu_hash,
+        })
+    }
+
+    // The defined dynamic symbol called `name`, found through DT_GNU_HASH rather than by scanning
+    // the whole table. None when the file has no such symbol or no GNU hash table.
+    pub fn lookup(&self, name: &str) -> Option<Symbol> {
+        let gnu_hash = self.gnu_hash()?;
+        let symtab = self.dynamic_symtab()?;
+        let strtab = self.dynamic_strtab();
+        gnu_hash.candidates(name).find_map(|index| {
+            let (_, mut sym) = self.dynamic_symbol(symtab, index as usize).ok()?;
+            sym.name = crate::cstr_at(strtab?, sym.name_idx as usize).into_owned();
+            (sym.name == name).then_some(sym)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 64-bit table the way linkers lay it out: symbols from `symoffset` on sorted by bucket,
+    // every hash set in the bloom filter
+    fn build<'a>(names: &[&'a str], symoffset: u32, nbuckets: u32) -> (Vec<u8>, Vec<&'a str>) {
+        let (bloom_size, shift) = (2u32, 6);
+        let mut sorted = names.to_vec();
+        sorted.sort_by_key(|name| hash(name) % nbuckets);
+        let mut bloom = vec![0u64; bloom_size as usize];
+        let mut buckets = vec![0u32; nbuckets as usize];
+        let mut chains = Vec::new();
+        for (i, name) in sorted.iter().enumerate() {
+            let h = hash(name);
+            bloom[(h / 64 % bloom_size) as usize] |= 1 << (h % 64) | 1 << ((h >> shift) % 64);
+            let bucket = (h % nbuckets) as usize;
+            if buckets[bucket] == 0 {
+                buckets[bucket] = symoffset + i as u32;
+            }
+            let last = sorted
+                .get(i + 1)
+                .is_none_or(|n| hash(n) % nbuckets != h % nbuckets);
+            chains.push(h & !1 | last as u32);
+        }
+        let mut table = Vec::new();
+        for word in [nbuckets, symoffset, bloom_size, shift] {
+            table.extend(&word.to_le_bytes());
+        }
+        for word in bloom {
+            table.extend(&word.to_le_bytes());
+        }
+        for word in buckets.into_iter().chain(chains) {
+            table.extend(&word.to_le_bytes());
+        }
+        (table, sorted)
+    }
+
+    #[test]
+    fn lookup_through_buckets_and_chains() {
+        assert_eq!(hash(""), 5381);
+        assert_eq!(hash("printf"), 0x156b2bb8);
+
+        let names = ["malloc", "free", "printf", "puts", "exit", "main"];
+        let (table, sorted) = build(&names, 3, 4);
+        let gnu_hash = GnuHash::parse(&table, Class::Elf64).unwrap();
+        for name in names.iter() {
+            let index = sorted.iter().position(|n| n == name).unwrap() as u32 + 3;
+            assert!(gnu_hash.candidates(name).any(|sym| sym == index));
+        }
+        assert_eq!(gnu_hash.candidates("missing").count(), 0);
+        assert_eq!(gnu_hash.symbol_count(), Some(9));
+
+        // Nothing but the undefined symbols below symoffset
+        let (table, _) = build(&[], 2, 1);
+        let gnu_hash = GnuHash::parse(&table, Class::Elf64).unwrap();
+        assert_eq!(gnu_hash.candidates("malloc").count(), 0);
+        assert_eq!(gnu_hash.symbol_count(), Some(2));
+    }
+
+    #[test]
+    fn chains_stop_at_the_symbol_table() {
+        let names = ["malloc", "free"];
+        let (mut table, _) = build(&names, 1, 1);
+        // Neither chain word ends the chain, and what follows the table reads like more of it
+        let len = table.len();
+        table[len - 4] &= !1;
+        table[len - 8] &= !1;
+        for _ in 0..16 {
+            table.extend(&(hash("malloc") & !1).to_le_bytes());
+        }
+        let gnu_hash = GnuHash::parse(&table, Class::Elf64).unwrap();
+        assert_eq!(gnu_hash.candidates("malloc").count(), 17);
+        let gnu_hash = gnu_hash.with_symbols(3);
+        assert_eq!(gnu_hash.candidates("malloc").count(), 1);
+        assert_eq!(gnu_hash.candidates("free").count(), 1);
+        assert_eq!(gnu_hash.symbol_count(), None);
+    }
+}
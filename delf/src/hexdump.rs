@@ -0,0 +1,240 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write as _},
+    ops::Range,
+};
+
+use crate::{
+    style::{Theme, NONE},
+    types::{Addr, Machine},
+};
+
+const BYTES_PER_LINE: usize = 16;
+// Printable runs at least this long are tinted as strings
+const MIN_STRING: usize = 4;
+
+// How a byte is painted: relocated bytes stand out most, then strings, and zeroes fade
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Relocated,
+    String,
+    Zero,
+    Other,
+}
+
+// `xxd`-style lines for at most `limit` bytes of `data`, which lives at `addr`. Bytes in
+// `relocated` (addresses) are ones the loader overwrites.
+pub fn hexdump(
+    data: &[u8],
+    addr: Addr,
+    relocated: &[Range<u64>],
+    limit: usize,
+    theme: &Theme,
+) -> String {
+    let lines = data.len().min(limit) / BYTES_PER_LINE + 2;
+    let mut out = Vec::with_capacity(lines * PLAIN_LINE);
+    write_hexdump(&mut out, data, addr, relocated, limit, theme).expect("writing to a Vec");
+    String::from_utf8(out).expect("hexdump lines are ASCII")
+}
+
+// Address, hex bytes in two groups and the ASCII column, before any escapes
+const PLAIN_LINE: usize = 11 + 2 + BYTES_PER_LINE * 3 + 2 + BYTES_PER_LINE + 2;
+
+// Streams the lines `hexdump` returns to `out`, one write per line, for sections too big to
+// hold twice over as text
+pub fn write_hexdump(
+    out: &mut impl io::Write,
+    data: &[u8],
+    addr: Addr,
+    relocated: &[Range<u64>],
+    limit: usize,
+    theme: &Theme,
+) -> io::Result<()> {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let shown = &data[..data.len().min(limit)];
+    let kinds = classify(shown, addr, relocated);
+    let style = |kind: Kind| match kind {
+        Kind::Relocated => theme.highlight,
+        Kind::String => theme.label,
+        Kind::Zero => theme.dim,
+        Kind::Other => NONE,
+    };
+
+    let mut line = Vec::with_capacity(PLAIN_LINE);
+    for (n, chunk) in shown.chunks(BYTES_PER_LINE).enumerate() {
+        let start = n * BYTES_PER_LINE;
+        let kinds = &kinds[start..start + chunk.len()];
+        line.clear();
+        write!(line, "{:#010x} ", addr.0 + start as u64)?;
+        for (i, (&byte, &kind)) in chunk.iter().zip(kinds).enumerate() {
+            if i % 8 == 0 {
+                line.push(b' ');
+            }
+            let style = style(kind);
+            line.extend_from_slice(style.start.as_bytes());
+            line.extend_from_slice(&[HEX[usize::from(byte >> 4)], HEX[usize::from(byte & 0xf)]]);
+            line.extend_from_slice(style.end.as_bytes());
+            line.push(b' ');
+        }
+        let missing = BYTES_PER_LINE - chunk.len();
+        line.resize(line.len() + missing * 3 + usize::from(missing >= 8), b' ');
+        line.extend_from_slice(b" |");
+        for (&byte, &kind) in chunk.iter().zip(kinds) {
+            let style = style(kind);
+            line.extend_from_slice(style.start.as_bytes());
+            line.push(if printable(byte) { byte } else { b'.' });
+            line.extend_from_slice(style.end.as_bytes());
+        }
+        line.extend_from_slice(b"|\n");
+        out.write_all(&line)?;
+    }
+    if data.len() > shown.len() {
+        writeln!(out, "... {:#x} more bytes", data.len() - shown.len())?;
+    }
+    Ok(())
+}
+
+fn classify(data: &[u8], addr: Addr, relocated: &[Range<u64>]) -> Vec<Kind> {
+    let mut kinds: Vec<Kind> = data
+        .iter()
+        .map(|&byte| match byte {
+            0 => Kind::Zero,
+            _ => Kind::Other,
+        })
+        .collect();
+    let mut i = 0;
+    while i < data.len() {
+        let len = data[i..].iter().take_while(|&&b| printable(b)).count();
+        if len >= MIN_STRING {
+            kinds[i..i + len].fill(Kind::String);
+        }
+        i += len.max(1);
+    }
+    // Clipped to the shown bytes, so only relocations landing in them cost anything
+    let end = addr.0 + data.len() as u64;
+    for range in relocated {
+        let (start, stop) = (range.start.max(addr.0), range.end.min(end));
+        if start < stop {
+            kinds[(start - addr.0) as usize..(stop - addr.0) as usize].fill(Kind::Relocated);
+        }
+    }
+    kinds
+}
+
+// Bytes the instruction at the start of `code` takes, on ISAs where that doesn't need a decoder:
+// fixed 4-byte words, or RISC-V's 2-byte compressed ones told apart by their low bits. None for
+// x86 and anything else with variable length encodings.
+pub fn instruction_len(machine: Machine, code: &[u8]) -> Option<usize> {
+    match machine {
+        Machine::AArch64
+        | Machine::Arm
+        | Machine::Mips
+        | Machine::PowerPC
+        | Machine::PowerPC64
+        | Machine::Sparc
+        | Machine::SparcV9
+        | Machine::LoongArch => Some(4),
+        Machine::RiscV => match code.first()? & 0b11 {
+            0b11 => Some(4),
+            _ => Some(2),
+        },
+        _ => None,
+    }
+}
+
+// One line per instruction in ndisasm's layout, `ADDRESS  BYTES  annotation`, for code of
+// machines elk has no disassembler for. Instructions are shown as the little-endian word the CPU
+// fetches; with no way to split them, bytes go BYTES_PER_LINE to a line.
+pub fn instruction_dump(data: &[u8], addr: Addr, machine: Machine) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let rest = &data[offset..];
+        let len = instruction_len(machine, rest).filter(|&len| len <= rest.len());
+        let bytes = &rest[..len.unwrap_or_else(|| rest.len().min(BYTES_PER_LINE))];
+        let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let word = bytes
+            .iter()
+            .rev()
+            .fold(0u64, |word, &b| word << 8 | u64::from(b));
+        let note = match len {
+            Some(len) => format!(".inst 0x{:0width$x}", word, width = len * 2),
+            None => ".byte".to_string(),
+        };
+        let _ = writeln!(out, "{:08X}  {:<32}  {}", addr.0 + offset as u64, hex, note);
+        offset += bytes.len();
+    }
+    out
+}
+
+fn printable(byte: u8) -> bool {
+    (0x20..=0x7e).contains(&byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::THEMES;
+
+    #[test]
+    fn lines() {
+        let plain = THEMES.iter().find(|t| t.name == "plain").unwrap();
+        let data = b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00hi";
+        assert_eq!(
+            hexdump(data, Addr(0x1000), &[], 64, plain),
+            "0x00001000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|\n\
+             0x00001010  68 69                                             |hi|\n"
+        );
+        assert!(hexdump(data, Addr(0), &[], 4, plain).ends_with("... 0xe more bytes\n"));
+    }
+
+    #[test]
+    fn instructions() {
+        // ret; nop
+        let aarch64 = b"\xc0\x03\x5f\xd6\x1f\x20\x03\xd5\x00";
+        let listing = instruction_dump(aarch64, Addr(0x1000), Machine::AArch64);
+        let lines: Vec<Vec<_>> = listing
+            .lines()
+            .map(|l| l.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                vec!["00001000", "C0035FD6", ".inst", "0xd65f03c0"],
+                vec!["00001004", "1F2003D5", ".inst", "0xd503201f"],
+                vec!["00001008", "00", ".byte"],
+            ]
+        );
+        // c.ret, then addi a0, a0, 0
+        let riscv = b"\x82\x80\x13\x05\x05\x00";
+        assert_eq!(instruction_len(Machine::RiscV, riscv), Some(2));
+        assert_eq!(instruction_len(Machine::RiscV, &riscv[2..]), Some(4));
+        assert_eq!(instruction_len(Machine::X86_64, riscv), None);
+        let listing = instruction_dump(riscv, Addr(0), Machine::RiscV);
+        assert!(listing.contains(".inst 0x8082\n"));
+        assert!(listing.contains(".inst 0x00050513\n"));
+    }
+
+    #[test]
+    fn kinds() {
+        let data = b"ab\0/bin/sh\0\x01\x02\x03\x04";
+        let kinds = classify(data, Addr(0x10), core::slice::from_ref(&(0x1a..0x1c)));
+        assert_eq!(&kinds[..3], [Kind::Other, Kind::Other, Kind::Zero]);
+        assert!(kinds[3..10].iter().all(|&k| k == Kind::String));
+        assert_eq!(
+            &kinds[10..],
+            [
+                Kind::Relocated,
+                Kind::Relocated,
+                Kind::Other,
+                Kind::Other,
+                Kind::Other
+            ]
+        );
+        // Relocations straddling either end only count for the bytes shown
+        let kinds = classify(b"\x01\x02", Addr(0x10), &[0x8..0x11, 0x11..0x20]);
+        assert_eq!(kinds, [Kind::Relocated, Kind::Relocated]);
+        let kinds = classify(b"\x01\x02", Addr(0x10), &[0x0..0x10, 0x12..0x20]);
+        assert_eq!(kinds, [Kind::Other, Kind::Other]);
+    }
+}
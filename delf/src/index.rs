@@ -0,0 +1,144 @@
+use core::{cmp::Reverse, iter::FromIterator, ops::Range};
+
+use crate::{prelude::*, types::*, FileHeader};
+
+// Address ranges with something attached, sorted once so each lookup is a binary search rather
+// than a scan. Ranges may overlap and nest, as symbols and TLS sections do. An empty range only
+// matches its start, like a zero-sized symbol.
+#[derive(Debug, Clone)]
+pub struct AddressIndex<T> {
+    // By start; entries with the same start in the order they were given
+    entries: Vec<(Range<u64>, T)>,
+    // Highest end among entries[..=i], so lookups know when nothing further back can match
+    reach: Vec<u64>,
+}
+
+impl<T> AddressIndex<T> {
+    pub fn new(entries: impl IntoIterator<Item = (Range<u64>, T)>) -> Self {
+        let mut entries: Vec<_> = entries.into_iter().enumerate().collect();
+        // Lookups walk backwards, so of equal starts the first given comes last
+        entries.sort_by_key(|(i, (range, _))| (range.start, Reverse(*i)));
+        let entries: Vec<_> = entries.into_iter().map(|(_, entry)| entry).collect();
+        let reach = entries
+            .iter()
+            .scan(0, |reach, (range, _)| {
+                *reach = range.end.max(range.start.saturating_add(1)).max(*reach);
+                Some(*reach)
+            })
+            .collect();
+        Self { entries, reach }
+    }
+
+    // Every entry covering `addr`, the one starting closest below it first
+    pub fn containing(&self, addr: u64) -> impl Iterator<Item = (&Range<u64>, &T)> {
+        let end = self
+            .entries
+            .partition_point(|(range, _)| range.start <= addr);
+        (0..end)
+            .rev()
+            .take_while(move |&i| self.reach[i] > addr)
+            .map(move |i| &self.entries[i])
+            .filter(move |(range, _)| range.contains(&addr) || range.start == addr)
+            .map(|(range, value)| (range, value))
+    }
+
+    // The innermost entry covering `addr`; of several starting at the same place, the first given
+    pub fn get(&self, addr: u64) -> Option<(&Range<u64>, &T)> {
+        self.containing(addr).next()
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.get(addr).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Range<u64>, &T)> {
+        self.entries.iter().map(|(range, value)| (range, value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T> Default for AddressIndex<T> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<T> FromIterator<(Range<u64>, T)> for AddressIndex<T> {
+    fn from_iter<I: IntoIterator<Item = (Range<u64>, T)>>(entries: I) -> Self {
+        Self::new(entries)
+    }
+}
+
+// For looking up many addresses in one file. `segment_at`, `section_at` and `symbol_at` scan,
+// which is cheaper for a single address.
+impl FileHeader {
+    // LOAD segments by the memory they occupy
+    pub fn segment_index(&self) -> AddressIndex<&ProgramHeader> {
+        self.program_headers
+            .iter()
+            .filter(|ph| ph.typ == SegmentType::Load)
+            .map(|ph| (ph.virt_addr.0..ph.virt_addr.0 + ph.mem_size.0, ph))
+            .collect()
+    }
+
+    // Sections loaded into memory. .tbss is left out: its addresses belong to whatever follows
+    // it in the image, its contents live in each thread's TLS block.
+    pub fn section_index(&self) -> AddressIndex<&SectionHeader> {
+        self.section_headers
+            .iter()
+            .filter(|sh| sh.flags.contains(SectionFlags::Alloc))
+            .filter(|sh| !(sh.typ == SectionType::NoBits && sh.flags.contains(SectionFlags::Tls)))
+            .map(|sh| (sh.addr.0..sh.addr.0 + sh.size.0, sh))
+            .collect()
+    }
+
+    // Named function and data symbols by the addresses they cover. Functions come first among
+    // symbols starting at the same address.
+    pub fn symbol_index(&self) -> AddressIndex<Symbol> {
+        let mut symbols: Vec<_> = self
+            .read_section_syms()
+            .into_iter()
+            .filter(|sym| sym.section_index().is_some() && !sym.name.is_empty())
+            .filter(|sym| matches!(sym.typ(), Some(SymType::Func | SymType::Object)))
+            .collect();
+        symbols.sort_by_key(|sym| sym.typ() != Some(SymType::Func));
+        symbols
+            .into_iter()
+            .map(|sym| (sym.value.0..sym.value.0 + sym.size, sym))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AddressIndex;
+
+    #[test]
+    fn nested_and_empty() {
+        let index: AddressIndex<_> = vec![
+            (0x1000..0x2000, "outer"),
+            (0x1800..0x1900, "inner"),
+            (0x1800..0x1800, "marker"),
+            (0x3000..0x3000, "empty"),
+            (0x1000..0x1400, "first"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(index.get(0x1200).map(|(_, v)| *v), Some("outer"));
+        assert_eq!(index.get(0x1850).map(|(_, v)| *v), Some("inner"));
+        assert_eq!(index.get(0x1800).map(|(_, v)| *v), Some("inner"));
+        assert_eq!(index.get(0x1950).map(|(_, v)| *v), Some("outer"));
+        assert_eq!(index.get(0x3000).map(|(_, v)| *v), Some("empty"));
+        assert_eq!(index.get(0x3001), None);
+        assert_eq!(index.get(0x2000), None);
+        let all: Vec<_> = index.containing(0x1000).map(|(_, v)| *v).collect();
+        assert_eq!(all, ["outer", "first"]);
+    }
+}
@@ -0,0 +1,337 @@
+use core::{fmt, ops::Range};
+
+use crate::{
+    detect::Class,
+    prelude::*,
+    types::{Addr, ProgramHeader, SectionType, SegmentType},
+    FileHeader,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutItem {
+    pub file_size: u64,
+    pub mem_size: u64,
+    pub align: u64,
+    pub loadable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub offset: Addr,
+    pub vaddr: Addr,
+}
+
+pub struct Layout {
+    pub base: u64,
+    pub header_size: u64,
+    pub page_size: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    BadAlign(usize, u64),
+    Misaligned(usize),
+    Incongruent(usize),
+    FileOverlap(usize, usize),
+    MemOverlap(usize, usize),
+    Overflow(usize),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadAlign(a, b) => write!(
+                f,
+                "Item {} has alignment {:#x}, which is not a power of two",
+                a, b
+            ),
+            Self::Misaligned(a) => {
+                write!(f, "Item {} is placed at a misaligned offset or address", a)
+            }
+            Self::Incongruent(a) => write!(
+                f,
+                "Item {} offset and address are not congruent modulo its alignment",
+                a
+            ),
+            Self::FileOverlap(a, b) => write!(f, "Items {} and {} overlap in the file", a, b),
+            Self::MemOverlap(a, b) => write!(f, "Items {} and {} overlap in memory", a, b),
+            Self::Overflow(a) => write!(f, "Item {} does not fit in the address space", a),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LayoutError {}
+
+fn align_up(value: u64, align: u64) -> Option<u64> {
+    Some(value.checked_add(align - 1)? & !(align - 1))
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            base: 0,
+            header_size: 0x40,
+            page_size: 0x1000,
+        }
+    }
+}
+
+impl Layout {
+    pub fn place(&self, items: &[LayoutItem]) -> Result<Vec<Placement>, LayoutError> {
+        let mut offset = self.header_size;
+        let mut vaddr = self.base + self.header_size;
+        let mut placements = Vec::with_capacity(items.len());
+
+        for (i, item) in items.iter().enumerate() {
+            let align = item.align.max(1);
+            if !align.is_power_of_two() {
+                return Err(LayoutError::BadAlign(i, item.align));
+            }
+            offset = align_up(offset, align).ok_or(LayoutError::Overflow(i))?;
+
+            let placement = if item.loadable {
+                // Start every loadable item on a fresh page, keeping vaddr congruent to offset
+                let modulus = align.max(self.page_size);
+                let start = align_up(vaddr, modulus).ok_or(LayoutError::Overflow(i))?;
+                let item_vaddr = start + offset % modulus;
+                vaddr = item_vaddr
+                    .checked_add(item.mem_size)
+                    .ok_or(LayoutError::Overflow(i))?;
+                Placement {
+                    offset: Addr(offset),
+                    vaddr: Addr(item_vaddr),
+                }
+            } else {
+                Placement {
+                    offset: Addr(offset),
+                    vaddr: Addr(0),
+                }
+            };
+            offset = offset
+                .checked_add(item.file_size)
+                .ok_or(LayoutError::Overflow(i))?;
+            placements.push(placement);
+        }
+
+        validate(items, &placements)?;
+        Ok(placements)
+    }
+}
+
+pub fn validate(items: &[LayoutItem], placements: &[Placement]) -> Result<(), LayoutError> {
+    let pairs: Vec<_> = items.iter().zip(placements).collect();
+    for (i, (item, p)) in pairs.iter().enumerate() {
+        let align = item.align.max(1);
+        if !align.is_power_of_two() {
+            return Err(LayoutError::BadAlign(i, item.align));
+        }
+        if p.offset.0.checked_add(item.file_size).is_none()
+            || p.vaddr.0.checked_add(item.mem_size).is_none()
+        {
+            return Err(LayoutError::Overflow(i));
+        }
+        if item.loadable && p.offset.0 % align != p.vaddr.0 % align {
+            return Err(LayoutError::Incongruent(i));
+        }
+        if !item.loadable && p.offset.0 % align != 0 {
+            return Err(LayoutError::Misaligned(i));
+        }
+    }
+
+    for (i, (a, pa)) in pairs.iter().enumerate() {
+        for (j, (b, pb)) in pairs.iter().enumerate().skip(i + 1) {
+            if overlaps(pa.offset.0, a.file_size, pb.offset.0, b.file_size) {
+                return Err(LayoutError::FileOverlap(i, j));
+            }
+            if a.loadable && b.loadable && overlaps(pa.vaddr.0, a.mem_size, pb.vaddr.0, b.mem_size)
+            {
+                return Err(LayoutError::MemOverlap(i, j));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks the LOAD segments of a parsed file against the same rules
+pub fn validate_segments(headers: &[ProgramHeader]) -> Result<(), LayoutError> {
+    let (items, placements): (Vec<_>, Vec<_>) = headers
+        .iter()
+        .filter(|ph| ph.typ == SegmentType::Load)
+        .map(|ph| {
+            (
+                LayoutItem {
+                    file_size: ph.file_size.0,
+                    mem_size: ph.mem_size.0,
+                    align: ph.align.0,
+                    loadable: true,
+                },
+                Placement {
+                    offset: ph.offset,
+                    vaddr: ph.virt_addr,
+                },
+            )
+        })
+        .unzip();
+    validate(&items, &placements)
+}
+
+// File bytes mapped along with a LOAD segment, since mappings are whole pages, that no segment,
+// section or header table accounts for. Linkers leave them zero; anything else was put there
+// by whoever touched the file after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Padding {
+    pub segment: usize,
+    pub offset: u64,
+    pub len: u64,
+    pub nonzero: u64,
+}
+
+// Padding around each LOAD segment of `file`, read from `input`, the bytes it was parsed from.
+// Bytes in a page two segments share are reported once, for the first.
+pub fn padding(file: &FileHeader, input: &[u8]) -> Vec<Padding> {
+    let page = Layout::default().page_size;
+    let len = input.len() as u64;
+    let ehsize = match file.class {
+        Class::Elf32 => 52,
+        Class::Elf64 => 64,
+    };
+    let table = |info: &crate::HeaderInfo| {
+        let start = info.offset.0;
+        start..start.saturating_add((info.count * info.size) as u64)
+    };
+    let mut claimed: Vec<Range<u64>> = vec![
+        0..ehsize,
+        table(&file.program_header_info),
+        table(&file.section_header_info),
+    ];
+    let loads: Vec<(usize, &ProgramHeader)> = file
+        .program_headers
+        .iter()
+        .enumerate()
+        .filter(|(_, ph)| ph.typ == SegmentType::Load && ph.check_sizes().is_ok())
+        .collect();
+    claimed.extend(
+        loads
+            .iter()
+            .map(|(_, ph)| ph.offset.0..ph.offset.0.saturating_add(ph.file_size.0)),
+    );
+    claimed.extend(
+        file.section_headers
+            .iter()
+            .filter(|sh| !matches!(sh.typ, SectionType::Null | SectionType::NoBits))
+            .map(|sh| sh.offset.0..sh.offset.0.saturating_add(sh.size.0)),
+    );
+    claimed.sort_by_key(|range| range.start);
+
+    let mut found: Vec<Padding> = Vec::new();
+    for (index, ph) in loads {
+        if ph.file_size.0 == 0 {
+            continue;
+        }
+        let start = ph.offset.0 & !(page - 1);
+        let end = align_up(ph.offset.0 + ph.file_size.0, page).map_or(len, |end| end.min(len));
+        let mut at = start;
+        for range in claimed.iter().chain(core::iter::once(&(end..end))) {
+            if range.start > at && at < end {
+                let gap = at..range.start.min(end);
+                if !found.iter().any(|p| p.offset == gap.start) {
+                    let bytes = &input[gap.start as usize..gap.end as usize];
+                    found.push(Padding {
+                        segment: index,
+                        offset: gap.start,
+                        len: gap.end - gap.start,
+                        nonzero: bytes.iter().filter(|&&b| b != 0).count() as u64,
+                    });
+                }
+            }
+            at = at.max(range.end);
+        }
+    }
+    found.sort_by_key(|p| p.offset);
+    found
+}
+
+fn overlaps(a: u64, a_len: u64, b: u64, b_len: u64) -> bool {
+    a_len > 0 && b_len > 0 && a < b + b_len && b < a + a_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(file_size: u64, mem_size: u64, align: u64, loadable: bool) -> LayoutItem {
+        LayoutItem {
+            file_size,
+            mem_size,
+            align,
+            loadable,
+        }
+    }
+
+    #[test]
+    fn places_congruent_segments() {
+        let items = [
+            item(0x123, 0x123, 0x1000, true),
+            item(0x20, 0x2000, 0x1000, true),
+            item(0x40, 0, 8, false),
+        ];
+        let layout = Layout {
+            base: 0x400000,
+            ..Default::default()
+        };
+        let placed = layout.place(&items).unwrap();
+        assert_eq!(placed[0].offset, Addr(0x1000));
+        assert_eq!(placed[0].vaddr, Addr(0x401000));
+        assert_eq!(placed[1].offset, Addr(0x2000));
+        assert_eq!(placed[1].vaddr, Addr(0x402000));
+        assert_eq!(placed[2].offset, Addr(0x2020));
+        assert_eq!(placed[2].vaddr, Addr(0));
+    }
+
+    #[test]
+    fn small_alignment_packs_file_not_memory() {
+        let items = [item(0x10, 0x10, 0x10, true), item(0x10, 0x10, 0x10, true)];
+        let placed = Layout::default().place(&items).unwrap();
+        assert_eq!(placed[0].vaddr, Addr(0x1040));
+        assert_eq!(placed[1].offset, Addr(0x50));
+        assert_eq!(placed[1].vaddr, Addr(0x2050));
+    }
+
+    #[test]
+    fn rejects_bad_layouts() {
+        let items = [
+            item(0x10, 0x10, 0x1000, true),
+            item(0x10, 0x10, 0x1000, true),
+        ];
+        let overlapping = [
+            Placement {
+                offset: Addr(0x1000),
+                vaddr: Addr(0x1000),
+            },
+            Placement {
+                offset: Addr(0x2000),
+                vaddr: Addr(0x1000),
+            },
+        ];
+        assert_eq!(
+            validate(&items, &overlapping),
+            Err(LayoutError::MemOverlap(0, 1))
+        );
+        let incongruent = [
+            overlapping[0],
+            Placement {
+                offset: Addr(0x2000),
+                vaddr: Addr(0x3010),
+            },
+        ];
+        assert_eq!(
+            validate(&items, &incongruent),
+            Err(LayoutError::Incongruent(1))
+        );
+        assert_eq!(
+            Layout::default().place(&[item(1, 1, 3, false)]),
+            Err(LayoutError::BadAlign(0, 3))
+        );
+    }
+}
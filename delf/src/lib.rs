@@ -1,22 +1,81 @@
+// Without the std feature delf needs only core and alloc, for parsing inside kernels and
+// bootloaders
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod cancel;
+pub mod cet;
+pub mod content;
+pub mod coredump;
+pub mod data;
+pub mod detect;
+pub mod eflags;
+pub mod eh_frame;
+pub mod gnu_hash;
+#[cfg(feature = "std")]
+pub mod hexdump;
+pub mod index;
+pub mod layout;
+pub mod note;
 pub mod parse;
+pub mod patch;
+pub mod reloc;
+pub mod signature;
+pub mod startup;
+pub mod strtab;
+pub mod style;
+pub mod trace;
 pub mod types;
+pub mod version;
+pub mod view;
+pub mod walk;
+#[cfg(feature = "std")]
+pub mod write;
 
+use alloc::borrow::Cow;
+#[cfg(feature = "std")]
 use carpenter::*;
+use core::{
+    convert::TryFrom,
+    fmt::{self, Debug},
+    mem,
+};
 use nom::{
     branch::alt,
     bytes::complete::{tag, take},
-    combinator::{map, verify},
-    error::context,
+    combinator::{map, map_res, value, verify},
     multi::many0,
-    number::complete::{le_u16, le_u32},
     sequence::tuple,
     Offset,
 };
-use std::fmt::{self, Debug};
 
+use data::Data;
+use parse::{ParseOptions, Severity};
+use prelude::*;
+use strtab::StrTab;
+use trace::context;
 use types::*;
 
+// What the std prelude brings in, for modules to import without it
+mod prelude {
+    pub use alloc::{
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+}
+
+// DT_FLAGS bits
+const DF_TEXTREL: u64 = 0x4;
+const DF_BIND_NOW: u64 = 0x8;
+// DT_FLAGS_1 bit
+const DF_1_NOW: u64 = 0x1;
+
+#[cfg(feature = "std")]
 struct HexDump<'a>(&'a [u8]);
+#[cfg(feature = "std")]
 impl<'a> Debug for HexDump<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for &x in self.0.iter().take(20) {
@@ -26,46 +85,173 @@ impl<'a> Debug for HexDump<'a> {
     }
 }
 
-#[derive(PrettyTable)]
-#[header("")]
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Slices out a table of `count` entries of `entsize` bytes, failing instead of panicking or
+// truncating when entries are too small to hold a header or the table runs past the file
+fn header_table<'a>(
+    full: parse::Input<'a>,
+    offset: Addr,
+    entsize: usize,
+    count: usize,
+    min_entsize: usize,
+    what: &'static str,
+) -> parse::Result<'a, Vec<&'a [u8]>> {
+    if count == 0 {
+        return Ok((full, Vec::new()));
+    }
+    let start: usize = offset.into();
+    let table = entsize
+        .checked_mul(count)
+        .filter(|_| entsize >= min_entsize)
+        .and_then(|len| full.get(start..start.checked_add(len)?));
+    match table {
+        Some(table) => Ok((full, table.chunks(entsize).collect())),
+        None => Err(nom::Err::Failure(nom::error::VerboseError {
+            errors: vec![(
+                &full[start.min(full.len())..],
+                nom::error::VerboseErrorKind::Context(what),
+            )],
+        })),
+    }
+}
+
+// header_table, except that parsing tolerant of broken structure makes do with the entries that
+// are in the file
+fn header_entries<'a>(
+    full: parse::Input<'a>,
+    offset: Addr,
+    entsize: usize,
+    count: usize,
+    min_entsize: usize,
+    what: &'static str,
+    anomalies: &mut parse::Anomalies<'a>,
+) -> parse::Result<'a, Vec<&'a [u8]>> {
+    let table = header_table(full, offset, entsize, count, min_entsize, what);
+    if table.is_ok() || !anomalies.strictness.tolerates(Severity::Broken) {
+        return table;
+    }
+    let start: usize = offset.into();
+    let start = start.min(full.len());
+    let fit = match entsize >= min_entsize {
+        true => (full.len() - start) / entsize,
+        false => 0,
+    };
+    let message = format!(
+        "extends past EOF, truncated to {} of {} entries of {} bytes",
+        fit, count, entsize
+    );
+    anomalies.note(&full[start..], what, Severity::Broken, message)?;
+    header_table(full, offset, entsize, fit.min(count), min_entsize, what)
+}
+
+fn cstr_at(table: &[u8], offset: usize) -> Cow<'_, str> {
+    let bytes = table.get(offset..).unwrap_or_default();
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end])
+}
+
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
+#[cfg_attr(feature = "std", header(""))]
+#[cfg_attr(not(feature = "std"), derive(Debug))]
 pub struct HeaderInfo {
+    // e_phoff or e_shoff
+    #[cfg_attr(feature = "std", skip)]
+    pub offset: Addr,
     pub count: usize,
-    #[fmt("{:?}B")]
+    #[cfg_attr(feature = "std", fmt("{:?}B"))]
     pub size: usize,
+    // Bytes past the known header layout that some producers pad entries with
+    #[cfg_attr(feature = "std", fmt("{:?}B"))]
+    pub padding: usize,
 }
 
+#[cfg(feature = "std")]
 impl Debug for HeaderInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.get_table())
     }
 }
 
-#[derive(PrettyTable)]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
 pub struct FileHeader {
+    pub class: detect::Class,
+    pub endian: detect::Endian,
     pub typ: Type,
     pub machine: Machine,
+    // e_flags, whose meaning depends on `machine`; see eflags::decode
+    #[cfg_attr(feature = "std", fmt("{:#x}"))]
+    pub flags: u32,
     pub entry_point: Addr,
-    #[skip]
+    #[cfg_attr(feature = "std", skip)]
     pub program_headers: Vec<ProgramHeader>,
+    #[cfg_attr(feature = "std", skip)]
+    pub section_headers: Vec<SectionHeader>,
     pub program_header_info: HeaderInfo,
     pub section_header_info: HeaderInfo,
-    // pub section_headers: Vec<ProgramHeader>,
+    // What parsing let through, depending on the ParseOptions
+    #[cfg_attr(feature = "std", skip)]
+    pub anomalies: Vec<parse::Anomaly>,
+}
+
+// Why FileHeader::parse_checked turned down its input. `offset` is where in the file the bad
+// field or header starts, `field` the parser context that failed there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Unsupported(String),
+    Invalid { offset: usize, field: &'static str },
+    Incomplete,
+    Cancelled,
+    // Went over ParseOptions::max_footprint, which it holds
+    Footprint(usize),
 }
 
-#[derive(thiserror::Error, Debug)]
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(a) => write!(f, "{}", a),
+            Self::Invalid { offset, field } => {
+                write!(f, "failed parsing {} at {:#x}", field, offset)
+            }
+            Self::Incomplete => write!(f, "unexpected end of input"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Footprint(max) => write!(f, "parse results would take more than {} bytes", max),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
 pub enum RelaReadError {
-    #[error("Rela dynamic entry not found")]
     RelaNotFound,
-    #[error("Rela size entry not found")]
     RelaSizeNotFound,
-    #[error("Rela segment not found")]
     RelaSegmentNotFound,
-    #[error("Parsing failed")]
     RelaParseError(nom::error::VerboseErrorKind),
 }
 
+impl fmt::Display for RelaReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RelaNotFound => write!(f, "Rela dynamic entry not found"),
+            Self::RelaSizeNotFound => write!(f, "Rela size entry not found"),
+            Self::RelaSegmentNotFound => write!(f, "Rela segment not found"),
+            Self::RelaParseError(_) => write!(f, "Parsing failed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RelaReadError {}
+
 impl FileHeader {
-    const MAGIC: &'static [u8] = &[0x7f, b'E', b'L', b'F'];
+    pub const MAGIC: &'static [u8] = &[0x7f, b'E', b'L', b'F'];
 
     pub fn segment_at(&self, addr: Addr) -> Option<&ProgramHeader> {
         self.program_headers
@@ -74,35 +260,616 @@ impl FileHeader {
             .find(|ph| ph.mem_range().contains(&addr))
     }
 
-    pub fn segment_type(&self, typ: SegmentType) -> Option<&ProgramHeader> {
-        self.program_headers.iter().find(|ph| ph.typ == typ)
+    // The allocated section `addr` falls in, .tbss aside
+    pub fn section_at(&self, addr: Addr) -> Option<&SectionHeader> {
+        self.section_headers
+            .iter()
+            .filter(|sh| sh.flags.contains(SectionFlags::Alloc))
+            .filter(|sh| !(sh.typ == SectionType::NoBits && sh.flags.contains(SectionFlags::Tls)))
+            .find(|sh| sh.mem_range().contains(&addr))
+    }
+
+    // Symbol covering `addr` and the offset into it. Zero-sized symbols only match exactly.
+    pub fn symbol_at(&self, addr: Addr) -> Option<(Symbol, u64)> {
+        self.read_section_syms()
+            .into_iter()
+            .filter(|sym| sym.section_index().is_some() && !sym.name.is_empty())
+            .filter(|sym| {
+                sym.value == addr || (sym.value <= addr && addr.0 - sym.value.0 < sym.size)
+            })
+            .min_by_key(|sym| (sym.typ() != Some(SymType::Func), addr.0 - sym.value.0))
+            .map(|sym| {
+                let offset = addr.0 - sym.value.0;
+                (sym, offset)
+            })
+    }
+
+    pub fn entry_symbol(&self) -> Option<Symbol> {
+        self.symbol_at(self.entry_point).map(|(sym, _)| sym)
     }
 
+    // Describes where `addr` lives, e.g. `_start+0x4, in LOAD[2] RX`
+    pub fn annotate(&self, addr: Addr) -> Option<String> {
+        let symbol = self.symbol_at(addr).map(|(sym, offset)| match offset {
+            0 => sym.name,
+            _ => format!("{}+{:#x}", sym.name, offset),
+        });
+        let segment = self
+            .program_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, ph)| ph.typ == SegmentType::Load)
+            .find(|(_, ph)| ph.mem_range().contains(&addr))
+            .map(|(i, ph)| {
+                let flags: String = [
+                    (SegmentFlags::Read, 'R'),
+                    (SegmentFlags::Write, 'W'),
+                    (SegmentFlags::Execute, 'X'),
+                ]
+                .iter()
+                .filter(|(f, _)| ph.flags.contains(*f))
+                .map(|(_, c)| c)
+                .collect();
+                format!("in LOAD[{}] {}", i, flags)
+            });
+        match (symbol, segment) {
+            (Some(sym), Some(seg)) => Some(format!("{}, {}", sym, seg)),
+            (sym, seg) => sym.or(seg),
+        }
+    }
+
+    // The letter `nm` prints for a symbol: uppercase for global, lowercase for local
+    pub fn symbol_class(&self, sym: &Symbol) -> char {
+        let weak = sym.bind() == Some(SymBind::Weak);
+        let object = sym.typ() == Some(SymType::Object);
+        let class = match (sym.shndx, sym.section_index()) {
+            (SectionIdx::Undef, _) if weak && object => return 'v',
+            (SectionIdx::Undef, _) if weak => return 'w',
+            (SectionIdx::Undef, _) => return 'U',
+            _ if sym.typ() == Some(SymType::GnuIFunc) => return 'i',
+            _ if sym.bind() == Some(SymBind::GnuUnique) => return 'u',
+            _ if weak => return if object { 'V' } else { 'W' },
+            (SectionIdx::Abs, _) => 'A',
+            (SectionIdx::Common, _) => 'C',
+            (_, Some(index)) => match self.section_headers.get(index) {
+                Some(sh) if sh.flags.contains(SectionFlags::ExecInstr) => 'T',
+                Some(sh) if sh.flags.contains(SectionFlags::Alloc | SectionFlags::Write) => {
+                    match sh.typ {
+                        SectionType::NoBits => 'B',
+                        _ => 'D',
+                    }
+                }
+                Some(sh) if sh.flags.contains(SectionFlags::Alloc) => 'R',
+                Some(_) => 'N',
+                None => '?',
+            },
+            (_, None) => '?',
+        };
+        match sym.bind() {
+            Some(SymBind::Local) => class.to_ascii_lowercase(),
+            _ => class,
+        }
+    }
+
+    pub fn section_by_name(&self, name: &str) -> Option<&SectionHeader> {
+        self.section_headers.iter().find(|sh| sh.name == name)
+    }
+
+    pub fn read_section_syms(&self) -> Vec<Symbol> {
+        [SectionType::SymTab, SectionType::DynSym]
+            .iter()
+            .find_map(|&typ| self.section_headers.iter().position(|sh| sh.typ == typ))
+            .map(|idx| self.symbols_in(idx))
+            .unwrap_or_default()
+    }
+
+    // The dynamic symbol table the way the dynamic linker finds it, through DT_SYMTAB and
+    // DT_STRTAB, so it works on files whose section headers are stripped
+    pub fn read_syms(&self) -> Vec<Symbol> {
+        let symtab = match self.dynamic_symtab() {
+            Some(symtab) => symtab,
+            None => return Vec::new(),
+        };
+        let count = match self.dynamic_symbol_count(symtab) {
+            Some(count) => count,
+            None => return Vec::new(),
+        };
+        let mut syms: Vec<Symbol> = (0..count)
+            .map_while(|index| self.dynamic_symbol(symtab, index).ok())
+            .map(|(_, sym)| sym)
+            .collect();
+
+        if let Some(strtab) = self.dynamic_strtab() {
+            for sym in syms.iter_mut() {
+                sym.name = cstr_at(strtab, sym.name_idx as usize).to_string();
+            }
+        }
+        for (sym, version) in syms.iter_mut().zip(self.dynamic_symbol_versions()) {
+            sym.version = version;
+        }
+        syms
+    }
+
+    // DT_SYMTAB's address and entry size
+    fn dynamic_symtab(&self) -> Option<(Addr, usize)> {
+        let symtab = self.dynamic_entry(DynamicTag::SymTab)?;
+        let entsize = self
+            .dynamic_entry(DynamicTag::SymEnt)
+            .map_or(Symbol::size(self.class), |size| size.0 as usize);
+        Some((symtab, entsize.max(Symbol::size(self.class))))
+    }
+
+    // DT_STRTAB, cut to DT_STRSZ
+    fn dynamic_strtab(&self) -> Option<&[u8]> {
+        let bytes = self.bytes_at(self.dynamic_entry(DynamicTag::StrTab)?)?;
+        Some(match self.dynamic_entry(DynamicTag::StrSz) {
+            Some(size) => &bytes[..bytes.len().min(size.0 as usize)],
+            None => bytes,
+        })
+    }
+
+    // Entry `index` of the dynamic symbol table, its name left unresolved
+    fn dynamic_symbol(
+        &self,
+        (symtab, entsize): (Addr, usize),
+        index: usize,
+    ) -> parse::Result<'_, Symbol> {
+        let table = match self.bytes_at(symtab) {
+            Some(table) => table,
+            None => return parse::invalid(&[], "dynamic symbol table"),
+        };
+        // DT_SYMENT is whatever the file says, so the offset can overflow
+        match index
+            .checked_mul(entsize)
+            .and_then(|offset| table.get(offset..))
+        {
+            Some(entry) => Symbol::parse_as(self.class, self.endian)(entry),
+            None => parse::invalid(table, "dynamic symbol"),
+        }
+    }
+
+    // DT_SYMTAB carries no size. DT_HASH has it as nchain; with only DT_GNU_HASH it is one past
+    // the highest index its chains reach. Failing both, the table is assumed to run up to
+    // DT_STRTAB, where linkers put it.
+    fn dynamic_symbol_count(&self, (symtab, entsize): (Addr, usize)) -> Option<usize> {
+        if let Some(hash) = self.dynamic_entry(DynamicTag::Hash) {
+            return self
+                .u32_at(self.bytes_at(hash)?, 4)
+                .map(|nchain| nchain as usize);
+        }
+        if self.dynamic_entry(DynamicTag::GnuHash).is_some() {
+            return self.gnu_hash()?.symbol_count().map(|count| count as usize);
+        }
+        let strtab = self.dynamic_entry(DynamicTag::StrTab)?;
+        (strtab > symtab).then(|| (strtab - symtab).0 as usize / entsize)
+    }
+
+    pub fn symbols_in(&self, symtab_idx: usize) -> Vec<Symbol> {
+        let symtab = match self.section_headers.get(symtab_idx) {
+            Some(sh) => sh,
+            None => return Vec::new(),
+        };
+        let strtab = self.section_headers.get(symtab.link as usize);
+        let xindices = self
+            .section_headers
+            .iter()
+            .find(|sh| sh.typ == SectionType::SymTabShndx && sh.link as usize == symtab_idx);
+
+        let mut syms: Vec<Symbol> =
+            match many0(Symbol::parse_as(self.class, self.endian))(&symtab.data[..]) {
+                Ok((_, syms)) => syms,
+                Err(_) => return Vec::new(),
+            };
+        if let Some(strtab) = strtab {
+            for sym in syms.iter_mut() {
+                sym.name = cstr_at(&strtab.data, sym.name_idx as usize).to_string();
+            }
+        }
+        if let Some(xindices) = xindices {
+            let entries = xindices.data.chunks_exact(4).map(|c| self.u32_at(c, 0));
+            for (sym, xindex) in syms.iter_mut().zip(entries) {
+                if let (SectionIdx::Xindex, Some(index)) = (sym.shndx, xindex) {
+                    sym.shndx = SectionIdx::Index(index);
+                }
+            }
+        }
+        if symtab.typ == SectionType::DynSym {
+            for (sym, version) in syms.iter_mut().zip(self.dynamic_symbol_versions()) {
+                sym.version = version;
+            }
+        }
+        syms
+    }
+
+    pub fn section_groups(&self) -> Vec<SectionGroup> {
+        self.section_headers
+            .iter()
+            .filter(|sh| sh.typ == SectionType::Group)
+            .map(|sh| {
+                let signature = self
+                    .symbols_in(sh.link as usize)
+                    .get(sh.info as usize)
+                    .map(|sym| sym.name.clone())
+                    .unwrap_or_default();
+                let mut words = sh.data.chunks_exact(4).map(|c| self.u32_at(c, 0).unwrap());
+                let flags = words.next().unwrap_or(0);
+                let members = words
+                    .map(|idx| match self.section_headers.get(idx as usize) {
+                        Some(member) => member.name.clone(),
+                        None => format!("[{}]", idx),
+                    })
+                    .collect();
+                SectionGroup {
+                    name: sh.name.clone(),
+                    signature,
+                    comdat: flags & SectionGroup::GRP_COMDAT != 0,
+                    members,
+                }
+            })
+            .collect()
+    }
+
+    // Notes by the section they came from, or by segment for files without section headers.
+    // Those of big-endian files are left unparsed.
+    pub fn notes(&self) -> Vec<(String, note::Note)> {
+        let sections: Vec<_> = self
+            .section_headers
+            .iter()
+            .filter(|sh| sh.typ == SectionType::Note && self.endian == detect::Endian::Little)
+            .flat_map(|sh| {
+                note::parse_notes(&sh.data)
+                    .into_iter()
+                    .map(move |n| (sh.name.clone(), n))
+            })
+            .collect();
+        if !self.section_headers.is_empty() {
+            return sections;
+        }
+        self.program_headers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, ph)| match &ph.contents {
+                SegmentContent::Notes(notes) => notes
+                    .iter()
+                    .map(|n| (format!("NOTE[{}]", i), n.clone()))
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    // GNU build ID as lowercase hex, the key debuginfo stores and perf's cache are indexed by
+    pub fn build_id(&self) -> Option<String> {
+        self.notes()
+            .into_iter()
+            .map(|(_, note)| note)
+            .find(|note| note.name == "GNU" && note.typ == note::NT_GNU_BUILD_ID)
+            .and_then(|note| note.describe(self.class, self.machine))
+    }
+
+    // Where the program header table is in memory, which AT_PHDR tells a program: PT_PHDR says
+    // so when there is one, otherwise it is wherever a LOAD segment maps e_phoff
+    pub fn program_headers_addr(&self) -> Option<Addr> {
+        if let Some(ph) = self.segments_of_type(SegmentType::ProgHeader).next() {
+            return Some(ph.virt_addr);
+        }
+        self.offset_to_vaddr(self.program_header_info.offset)
+    }
+
+    // The object's thread-local storage, if it has any
+    pub fn tls(&self) -> Option<&TlsTemplate> {
+        self.segments_of_type(SegmentType::TLS)
+            .find_map(|ph| match &ph.contents {
+                SegmentContent::Tls(tls) => Some(tls),
+                _ => None,
+            })
+    }
+
+    // In file order. Most types appear at most once, but NOTE and LOAD routinely repeat.
+    pub fn segments_of_type(&self, typ: SegmentType) -> impl Iterator<Item = &ProgramHeader> {
+        self.program_headers.iter().filter(move |ph| ph.typ == typ)
+    }
+
+    // Files with only one of the two, or neither, pass
+    pub fn check_dynamic(&self) -> Result<(), DynamicMismatch> {
+        let segment = self.segments_of_type(SegmentType::Dynamic).next();
+        let section = self
+            .section_headers
+            .iter()
+            .find(|sh| sh.typ == SectionType::Dynamic);
+        let (ph, sh) = match (segment, section) {
+            (Some(ph), Some(sh)) => (ph, sh),
+            _ => return Ok(()),
+        };
+        if ph.offset != sh.offset {
+            return Err(DynamicMismatch::Offset(ph.offset.0, sh.offset.0));
+        }
+        if ph.virt_addr != sh.addr {
+            return Err(DynamicMismatch::Address(ph.virt_addr.0, sh.addr.0));
+        }
+        if ph.file_size != sh.size {
+            return Err(DynamicMismatch::Size(ph.file_size.0, sh.size.0));
+        }
+        Ok(())
+    }
+
+    // LOAD segments whose flags disagree with what they hold: relocation targets the loader
+    // would have to write through read-only pages, and executable segments that hold data
+    pub fn check_segment_contents(&self) -> Vec<SegmentMismatch> {
+        let relro: Vec<_> = self
+            .segments_of_type(SegmentType::GnuRelRo)
+            .filter(|ph| ph.check_sizes().is_ok())
+            .map(|ph| ph.mem_range())
+            .collect();
+        let slots: Vec<Addr> = vec![self.read_rela_entries(), self.read_plt_rela_entries()]
+            .into_iter()
+            .flat_map(Result::unwrap_or_default)
+            .map(|rela| rela.offset)
+            .filter(|slot| !relro.iter().any(|range| range.contains(slot)))
+            .collect();
+
+        let mut found = Vec::new();
+        for (i, ph) in self.program_headers.iter().enumerate() {
+            if ph.typ != SegmentType::Load || ph.check_sizes().is_err() {
+                continue;
+            }
+            if !ph.flags.contains(SegmentFlags::Write) {
+                let range = ph.mem_range();
+                let count = slots.iter().filter(|&slot| range.contains(slot)).count();
+                if count > 0 {
+                    found.push(SegmentMismatch::RelocatedReadOnly(i, count));
+                }
+            }
+            if !ph.flags.contains(SegmentFlags::Execute) {
+                continue;
+            }
+            // The headers and symbol tables of a small binary often share its one executable
+            // segment with the code, so the code sections are scored on their own when there
+            // are any to go by
+            let sections: Vec<u8>;
+            let code: &[u8] = match self.section_headers.is_empty() {
+                true => &ph.data,
+                false => {
+                    let range = ph.mem_range();
+                    sections = self
+                        .section_headers
+                        .iter()
+                        .filter(|sh| {
+                            sh.flags.contains(SectionFlags::ExecInstr)
+                                && sh.typ != SectionType::NoBits
+                                && range.contains(&sh.addr)
+                        })
+                        .flat_map(|sh| sh.data.iter().copied())
+                        .collect();
+                    &sections
+                }
+            };
+            if code.len() >= content::MIN_SCORED {
+                let score = content::code_likeness(code);
+                if score < content::MIN_CODE_LIKENESS {
+                    found.push(SegmentMismatch::NotCode(i, score));
+                }
+            }
+        }
+        found
+    }
+
+    // DT_TEXTREL, or DF_TEXTREL in DT_FLAGS: the object's relocations write to read-only
+    // segments, and the loader has to lift their protection while applying them
+    pub fn has_textrel(&self) -> bool {
+        self.dynamic_entry(DynamicTag::TextRel).is_some()
+            || self
+                .dynamic_entry(DynamicTag::Flags)
+                .is_some_and(|flags| flags.0 & DF_TEXTREL != 0)
+    }
+
+    // DT_BIND_NOW, DF_BIND_NOW in DT_FLAGS or DF_1_NOW in DT_FLAGS_1: every PLT slot is to be
+    // bound at load time rather than on first call
+    pub fn binds_now(&self) -> bool {
+        let flag = |tag, bit| self.dynamic_entry(tag).is_some_and(|a| a.0 & bit != 0);
+        self.dynamic_entry(DynamicTag::BindNow).is_some()
+            || flag(DynamicTag::Flags, DF_BIND_NOW)
+            || flag(DynamicTag::Flags1, DF_1_NOW)
+    }
+
+    // PT_GNU_STACK with PF_X, or no PT_GNU_STACK at all: the kernel maps the stack executable.
+    // It goes by the last one.
+    pub fn executable_stack(&self) -> bool {
+        match self.segments_of_type(SegmentType::GnuStack).last() {
+            Some(ph) => ph.flags.contains(SegmentFlags::Execute),
+            None => !self.program_headers.is_empty(),
+        }
+    }
+
+    // First `tag` entry, for tags that appear once. Repeating ones (DT_NEEDED, DT_RPATH, ...)
+    // go through `dynamic_entries`.
     pub fn dynamic_entry(&self, tag: DynamicTag) -> Option<Addr> {
-        match self.segment_type(SegmentType::Dynamic) {
+        self.dynamic_entries(tag).next()
+    }
+
+    pub fn dynamic_entries(&self, tag: DynamicTag) -> impl Iterator<Item = Addr> + '_ {
+        let entries: &[DynamicEntry] = match self.segments_of_type(SegmentType::Dynamic).next() {
             Some(ProgramHeader {
                 contents: SegmentContent::Dynamic(entries),
                 ..
-            }) => entries.iter().find(|e| e.tag == tag).map(|e| e.addr),
-            _ => None,
+            }) => entries,
+            _ => &[],
+        };
+        entries.iter().filter(move |e| e.tag == tag).map(|e| e.addr)
+    }
+
+    // File-backed bytes from `addr` to the end of the LOAD segment containing it
+    pub fn bytes_at(&self, addr: Addr) -> Option<&[u8]> {
+        let segment = self.segment_at(addr)?;
+        segment
+            .data
+            .get((addr - segment.mem_range().start).into()..)
+    }
+
+    // Where in the file the byte the loader maps at `addr` comes from, None for addresses outside
+    // every LOAD segment's file bytes
+    pub fn vaddr_to_offset(&self, addr: Addr) -> Option<Addr> {
+        let segment = self.segment_at(addr)?;
+        let delta = addr - segment.virt_addr;
+        match delta < segment.file_size {
+            true => Some(segment.offset + delta),
+            false => None,
         }
     }
 
+    // Where the loader maps the file byte at `offset`, through the first LOAD segment whose file
+    // bytes include it. None for headers and sections nothing maps.
+    pub fn offset_to_vaddr(&self, offset: Addr) -> Option<Addr> {
+        self.segments_of_type(SegmentType::Load)
+            .find(|ph| ph.file_range().contains(&offset))
+            .map(|ph| ph.virt_addr + (offset - ph.offset))
+    }
+
+    // `addr` in the image loaded at `base`. ET_EXEC files load at base 0, their addresses as
+    // they are.
+    pub fn vaddr_to_loaded(&self, addr: Addr, base: u64) -> u64 {
+        addr.0.wrapping_add(base)
+    }
+
+    // The address in the file behind `loaded`, an address in the image loaded at `base`. None
+    // outside every LOAD segment.
+    pub fn loaded_to_vaddr(&self, loaded: u64, base: u64) -> Option<Addr> {
+        let addr = Addr(loaded.checked_sub(base)?);
+        self.segment_at(addr).map(|_| addr)
+    }
+
+    pub fn offset_to_loaded(&self, offset: Addr, base: u64) -> Option<u64> {
+        Some(self.vaddr_to_loaded(self.offset_to_vaddr(offset)?, base))
+    }
+
+    pub fn loaded_to_offset(&self, loaded: u64, base: u64) -> Option<Addr> {
+        self.vaddr_to_offset(self.loaded_to_vaddr(loaded, base)?)
+    }
+
+    // DT_STRTAB, what DT_NEEDED, DT_SONAME and the search paths point into
+    pub fn dynamic_string_table(&self) -> Option<StrTab<'_>> {
+        self.dynamic_strtab().map(StrTab::new)
+    }
+
+    // Every `tag` entry (DT_NEEDED, DT_RPATH, ...) resolved through DT_STRTAB
+    pub fn dynamic_strings(&self, tag: DynamicTag) -> Vec<String> {
+        let strtab = match self.dynamic_string_table() {
+            Some(strtab) => strtab,
+            None => return Vec::new(),
+        };
+        self.dynamic_entries(tag)
+            .filter_map(|offset| Some(strtab.get(offset.0 as usize)?.into_owned()))
+            .collect()
+    }
+
+    // DT_RELA, or DT_REL where the ABI has no explicit addends, as on i386
     pub fn read_rela_entries(&self) -> Result<Vec<RelaEntry>, RelaReadError> {
-        let start = self
-            .dynamic_entry(DynamicTag::Rela)
-            .ok_or(RelaReadError::RelaNotFound)?;
-        let size = self
-            .dynamic_entry(DynamicTag::RelaSz)
-            .ok_or(RelaReadError::RelaSizeNotFound)?;
-        let segment = self
-            .segment_at(start)
+        match self.read_rela_table(DynamicTag::Rela, DynamicTag::RelaSz, false) {
+            Err(RelaReadError::RelaNotFound) => {
+                self.read_rela_table(DynamicTag::Rel, DynamicTag::RelSz, true)
+            }
+            entries => entries,
+        }
+    }
+
+    // DT_JMPREL, the PLT's own relocations, which the dynamic linker may apply lazily. Empty when
+    // the binary has none.
+    pub fn read_plt_rela_entries(&self) -> Result<Vec<RelaEntry>, RelaReadError> {
+        const DT_RELA: u64 = 7;
+        const DT_REL: u64 = 17;
+        let implicit_addend = match self.dynamic_entry(DynamicTag::PltRel) {
+            Some(Addr(DT_RELA)) => false,
+            Some(Addr(DT_REL)) => true,
+            _ => return Ok(Vec::new()),
+        };
+        self.read_rela_table(DynamicTag::JmpRel, DynamicTag::PltRelSz, implicit_addend)
+    }
+
+    // Some linkers emit one table per input section group, each with its own start and size
+    // entry; their relocations are concatenated in order. REL entries of types with an addend get
+    // the one stored in the slot they relocate.
+    fn read_rela_table(
+        &self,
+        start: DynamicTag,
+        size: DynamicTag,
+        implicit_addend: bool,
+    ) -> Result<Vec<RelaEntry>, RelaReadError> {
+        let starts: Vec<Addr> = self.dynamic_entries(start).collect();
+        let sizes: Vec<Addr> = self.dynamic_entries(size).collect();
+        if starts.is_empty() {
+            return Err(RelaReadError::RelaNotFound);
+        }
+        if sizes.len() < starts.len() {
+            return Err(RelaReadError::RelaSizeNotFound);
+        }
+        let mut entries = Vec::new();
+        for (start, size) in starts.into_iter().zip(sizes) {
+            entries.extend(self.read_rela_range(start, size, implicit_addend)?);
+        }
+        if implicit_addend {
+            // GOT and PLT slots hold whatever the linker left there, which is no addend
+            for entry in entries.iter_mut() {
+                if entry.typ.formula().uses(reloc::Term::A) {
+                    entry.addend = Addend(self.slot_value(entry.offset).unwrap_or(0));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    // The signed, address-sized value stored at `addr` in the file
+    fn slot_value(&self, addr: Addr) -> Option<i64> {
+        let (_, value) = parse::word(self.class, self.endian)(self.bytes_at(addr)?).ok()?;
+        Some(match self.class {
+            detect::Class::Elf32 => i64::from(value as u32 as i32),
+            detect::Class::Elf64 => value as i64,
+        })
+    }
+
+    // A 32-bit field at `offset` in `data`, in the file's byte order
+    fn u32_at(&self, data: &[u8], offset: usize) -> Option<u32> {
+        let (_, value) = parse::u32(self.endian)(data.get(offset..)?).ok()?;
+        Some(value)
+    }
+
+    fn read_rela_range(
+        &self,
+        start: Addr,
+        size: Addr,
+        implicit_addend: bool,
+    ) -> Result<Vec<RelaEntry>, RelaReadError> {
+        let input = self
+            .bytes_at(start)
+            .and_then(|bytes| bytes.get(..size.into()))
             .ok_or(RelaReadError::RelaSegmentNotFound)?;
+        self.parse_rela(input, implicit_addend)
+    }
 
-        let segstart = start - segment.mem_range().start;
-        let input = &segment.data[segstart.into()..][..size.into()];
+    // Entries of a SHT_RELA or SHT_REL section, as relocatable objects carry them. REL addends
+    // stay in the section they relocate and are left at zero here.
+    pub fn section_rela_entries(
+        &self,
+        sh: &SectionHeader,
+    ) -> Result<Vec<RelaEntry>, RelaReadError> {
+        match sh.typ {
+            SectionType::Rela => self.parse_rela(&sh.data, false),
+            SectionType::Rel => self.parse_rela(&sh.data, true),
+            _ => Err(RelaReadError::RelaNotFound),
+        }
+    }
 
-        match many0(RelaEntry::parse)(input) {
+    fn parse_rela(
+        &self,
+        input: parse::Input,
+        implicit_addend: bool,
+    ) -> Result<Vec<RelaEntry>, RelaReadError> {
+        match many0(RelaEntry::parse_as(
+            self.class,
+            self.endian,
+            self.machine,
+            implicit_addend,
+        ))(input)
+        {
             Ok((_, entries)) => Ok(entries),
             Err(nom::Err::Failure(err)) | Err(nom::Err::Error(err)) => {
                 let (_, e) = &err.errors[0];
@@ -112,57 +879,255 @@ impl FileHeader {
         }
     }
 
-    pub fn parse(input: parse::Input) -> parse::Result<Self> {
-        let full = input;
-        let (input, _) = tuple((
+    pub fn parse(data: &Data) -> parse::Result<'_, Self> {
+        Self::parse_with(data, &ParseOptions::default())
+    }
+
+    // Approximate bytes of memory the parse results take: the headers, what they own and the
+    // anomalies. The file's bytes aren't counted, segments and sections only point into them.
+    pub fn footprint(&self) -> usize {
+        let anomalies: usize = self
+            .anomalies
+            .iter()
+            .map(|a| mem::size_of::<parse::Anomaly>() + a.message.capacity())
+            .sum();
+        mem::size_of::<Self>()
+            + self
+                .program_headers
+                .iter()
+                .map(ProgramHeader::footprint)
+                .sum::<usize>()
+            + self
+                .section_headers
+                .iter()
+                .map(SectionHeader::footprint)
+                .sum::<usize>()
+            + anomalies
+    }
+
+    // Segments and sections keep pointing into `data` rather than copying their bytes out.
+    // Whatever `options` lets through is listed in `anomalies`.
+    pub fn parse_with<'a>(data: &'a Data, options: &ParseOptions) -> parse::Result<'a, Self> {
+        let full = data.present();
+        let mut anomalies = parse::Anomalies::new(full, options);
+        let input = full;
+        let class = alt((
+            value(detect::Class::Elf32, tag(&[0x1])),
+            value(detect::Class::Elf64, tag(&[0x2])),
+        ));
+        let endian = alt((
+            value(detect::Endian::Little, tag(&[0x1])),
+            value(detect::Endian::Big, tag(&[0x2])),
+        ));
+        let (input, (_, class, endian, _, _, _)) = tuple((
             context("Magic", tag(Self::MAGIC)),
-            context("Class", tag(&[0x2])),
-            context("Endianess", tag(&[0x1])),
+            context("Class", class),
+            context("Endianess", endian),
             context("Version", tag(&[0x1])),
             context("OS ABI", alt((tag(&[0x0]), tag(&[0x3])))),
             context("Padding", take(8usize)),
         ))(input)?;
 
-        let u16_usize = map(le_u16, |x| x as usize);
+        let u16_usize = |input| map(parse::u16(endian), |x| x as usize)(input);
+        let typ = map_res(parse::u16(endian), |x| {
+            Type::try_from(x).map_err(|_| nom::error::ErrorKind::Alt)
+        });
+
+        let (input, (typ, machine)) =
+            tuple((context("Type", typ), Machine::parse_as(endian)))(input)?;
 
-        let (input, (typ, machine)) = tuple((Type::parse, Machine::parse))(input)?;
+        let version = verify(parse::u32(endian), |&x| x == 1);
+        let (input, _) = context("Version (bis)", version)(input)?;
+        let (input, entry_point) = context("Entry point", Addr::parse_as(class, endian))(input)?;
 
-        let (input, _) = context("Version (bis)", verify(le_u32, |&x| x == 1))(input)?;
-        let (input, entry_point) = Addr::parse(input)?;
+        let (input, (pho, sho)) = tuple((
+            context("Program header offset", Addr::parse_as(class, endian)),
+            context("Section header offset", Addr::parse_as(class, endian)),
+        ))(input)?;
+        // e_flags; the rest of the fields are 2 bytes each
+        let sizes = input;
+        let (input, (flags, hsize)) = tuple((parse::u32(endian), parse::u16(endian)))(input)?;
+        let (input, (psize, pcount)) = tuple((u16_usize, u16_usize))(input)?;
+        let (input, (ssize, scount, name_idx)) = tuple((u16_usize, u16_usize, u16_usize))(input)?;
+
+        let expected = full.offset(input) as u16;
+        if hsize != expected {
+            let message = format!("e_ehsize is {} rather than {}", hsize, expected);
+            anomalies.note(&sizes[4..], "Header size", Severity::Unusual, message)?;
+        }
+        let entry_sizes = [
+            (
+                psize,
+                pcount,
+                ProgramHeader::size(class),
+                6,
+                "Program header size",
+            ),
+            (
+                ssize,
+                scount,
+                SectionHeader::size(class),
+                10,
+                "Section header size",
+            ),
+        ];
+        for (size, count, expected, at, field) in entry_sizes {
+            if count != 0 && size != expected {
+                let message = format!("entries of {} bytes rather than {}", size, expected);
+                anomalies.note(&sizes[at..], field, Severity::Unusual, message)?;
+            }
+        }
 
-        let (input, (pho, sho)) = tuple((Addr::parse, Addr::parse))(input)?;
-        let (input, (flags, hsize)) = tuple((le_u32, le_u16))(input)?;
-        let (input, (psize, pcount)) = tuple((&u16_usize, &u16_usize))(input)?;
-        let (input, (ssize, scount, name_idx)) =
-            tuple((&u16_usize, &u16_usize, &u16_usize))(input)?;
+        // Too many segments or sections to fit in e_phnum/e_shnum/e_shstrndx moves the real values
+        // to the info, size and link fields of section 0
+        let (mut pcount, mut scount, mut name_idx) = (pcount, scount, name_idx);
+        if sho.0 != 0
+            && (scount == 0
+                || name_idx == SectionHeader::SHN_XINDEX as usize
+                || pcount == ProgramHeader::PN_XNUM as usize)
+        {
+            let entsize = SectionHeader::size(class);
+            let (_, first) = header_table(full, sho, ssize, 1, entsize, "Section 0")?;
+            let (_, first) = context("Section 0", |entry| {
+                SectionHeader::parse_as(class, endian, data, entry, &mut anomalies)
+            })(first[0])?;
+            if scount == 0 {
+                scount = first.size.into();
+            }
+            if name_idx == SectionHeader::SHN_XINDEX as usize {
+                name_idx = first.link as usize;
+            }
+            if pcount == ProgramHeader::PN_XNUM as usize {
+                pcount = first.info as usize;
+            }
+        }
 
+        // Names and anomalies aside, which are small next to the headers
+        let mut footprint = mem::size_of::<Self>();
+        let over = |footprint| options.max_footprint.is_some_and(|max| footprint > max);
         let mut program_headers = Vec::new();
-        for pheader in (&full[pho.into()..]).chunks(psize).take(pcount) {
-            let (_, header) = ProgramHeader::parse(full, pheader)?;
+        let (_, entries) = header_entries(
+            full,
+            pho,
+            psize,
+            pcount,
+            ProgramHeader::size(class),
+            "Program header table",
+            &mut anomalies,
+        )?;
+        for (i, pheader) in entries.into_iter().enumerate() {
+            if options.cancel.is_cancelled() {
+                return parse::invalid(pheader, "Cancelled");
+            }
+            let since = anomalies.found.len();
+            let (_, header) = context("Program header", |entry| {
+                ProgramHeader::parse_as(class, endian, data, entry, &mut anomalies)
+            })(pheader)?;
+            anomalies.attribute(since, "segment", i);
+            footprint += header.footprint();
+            if over(footprint) {
+                return parse::too_large(pheader);
+            }
             program_headers.push(header);
         }
 
+        let mut section_headers = Vec::new();
+        let (_, entries) = header_entries(
+            full,
+            sho,
+            ssize,
+            if sho.0 == 0 { 0 } else { scount },
+            SectionHeader::size(class),
+            "Section header table",
+            &mut anomalies,
+        )?;
+        for (i, &sheader) in entries.iter().enumerate() {
+            if options.cancel.is_cancelled() {
+                return parse::invalid(sheader, "Cancelled");
+            }
+            let since = anomalies.found.len();
+            let (_, header) = context("Section header", |entry| {
+                SectionHeader::parse_as(class, endian, data, entry, &mut anomalies)
+            })(sheader)?;
+            anomalies.attribute(since, "section", i);
+            footprint += header.footprint();
+            if over(footprint) {
+                return parse::too_large(sheader);
+            }
+            section_headers.push(header);
+        }
+        match section_headers.get(name_idx).map(|sh| sh.data.clone()) {
+            Some(names) => {
+                for (i, sh) in section_headers.iter_mut().enumerate() {
+                    let name_idx = sh.name_idx as usize;
+                    if name_idx >= names.len() && name_idx != 0 {
+                        let message = format!(
+                            "section {}: name offset {:#x} is past the end of the string table",
+                            i, name_idx
+                        );
+                        anomalies.note(entries[i], "Section name", Severity::Unusual, message)?;
+                    }
+                    sh.name = cstr_at(&names, name_idx).to_string();
+                }
+            }
+            None if name_idx != 0 => {
+                let message = format!("e_shstrndx {} is past the last section", name_idx);
+                anomalies.note(
+                    &sizes[14..],
+                    "Section name index",
+                    Severity::Unusual,
+                    message,
+                )?;
+            }
+            None => {}
+        }
+
         Ok((
             input,
             Self {
+                class,
+                endian,
                 typ,
                 machine,
+                flags,
                 entry_point,
                 program_headers,
+                section_headers,
                 program_header_info: HeaderInfo {
+                    offset: pho,
                     size: psize,
                     count: pcount,
+                    padding: psize.saturating_sub(ProgramHeader::size(class)),
                 },
                 section_header_info: HeaderInfo {
+                    offset: sho,
                     size: ssize,
                     count: scount,
+                    padding: ssize.saturating_sub(SectionHeader::size(class)),
                 },
+                anomalies: anomalies.found,
             },
         ))
     }
 
-    pub fn parse_or_print_error(input: parse::Input) -> Option<Self> {
-        match Self::parse(input) {
+    // Explains why `input` can't be parsed at all, e.g. when it is a PE file or an archive
+    pub fn unsupported(input: parse::Input) -> Option<String> {
+        let format = detect::detect(input);
+        if format.is_supported() {
+            None
+        } else {
+            Some(format!("not an ELF file: detected {}", format))
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn parse_or_print_error(data: &Data) -> Option<Self> {
+        let input = data.present();
+        if let Some(reason) = Self::unsupported(input) {
+            eprintln!("{}", reason);
+            return None;
+        }
+        match Self::traced(input, || Self::parse(data)) {
             Ok((_, file)) => Some(file),
             Err(nom::Err::Failure(e)) | Err(nom::Err::Error(e)) => {
                 eprintln!("Failed parsing input!");
@@ -175,6 +1140,62 @@ impl FileHeader {
             Err(_) => panic!("Unexpected error occured while parsing."),
         }
     }
+
+    // Runs `parse` over `input`, printing the parser trace on stderr as trace::mode asks
+    #[cfg(feature = "std")]
+    fn traced<'a>(
+        input: parse::Input<'a>,
+        parse: impl FnOnce() -> parse::Result<'a, Self>,
+    ) -> parse::Result<'a, Self> {
+        match trace::mode() {
+            trace::Mode::Off => parse(),
+            mode => {
+                let (parsed, trace) = trace::record(input, parse);
+                if mode == trace::Mode::Always || parsed.is_err() {
+                    eprint!("Parser trace:\n{}", trace);
+                }
+                parsed
+            }
+        }
+    }
+
+    // Like parse, but never panics on truncated or corrupt input: every header offset and size is
+    // checked against the buffer and the innermost failure comes back as a ParseError. Prints
+    // the parser trace on stderr as trace::mode asks.
+    pub fn parse_checked(data: &Data, options: &ParseOptions) -> Result<Self, ParseError> {
+        let input = data.present();
+        if let Some(reason) = Self::unsupported(input) {
+            return Err(ParseError::Unsupported(reason));
+        }
+        #[cfg(feature = "std")]
+        let parsed = Self::traced(input, || Self::parse_with(data, options));
+        #[cfg(not(feature = "std"))]
+        let parsed = Self::parse_with(data, options);
+        match parsed {
+            Ok((_, file)) => Ok(file),
+            Err(_) if options.cancel.is_cancelled() => Err(ParseError::Cancelled),
+            Err(nom::Err::Failure(e)) | Err(nom::Err::Error(e)) => {
+                let (inp, _) = e.errors[0];
+                if let Some(max) = options.max_footprint.filter(|_| parse::is_too_large(&e)) {
+                    return Err(ParseError::Footprint(max));
+                }
+                let field = e.errors.iter().find_map(|(_, kind)| match kind {
+                    nom::error::VerboseErrorKind::Context(ctx) => Some(*ctx),
+                    _ => None,
+                });
+                Err(ParseError::Invalid {
+                    offset: input.offset(inp),
+                    field: field.unwrap_or("input"),
+                })
+            }
+            Err(nom::Err::Incomplete(_)) => Err(ParseError::Incomplete),
+        }
+    }
+
+    // Like parse_or_print_error, but summarizes the innermost failure in a single line
+    pub fn parse_or_describe(data: &Data) -> Result<Self, String> {
+        Self::parse_checked(data, &ParseOptions::default()).map_err(|e| e.to_string())
+    }
 }
 
 #[cfg(test)]
@@ -182,18 +1203,603 @@ mod tests {
     #[test]
     fn type_as_u16() {
         assert_eq!(super::Type::Core as u16, 0x4);
-        assert_eq!(super::Machine::X86_64 as u16, 0x3e);
+        assert_eq!(u16::from(super::Machine::X86_64), 0x3e);
     }
 
     #[test]
     fn type_from_u16() {
         use super::{Machine, Type};
-        use std::convert::TryFrom;
+        use core::convert::TryFrom;
         assert_eq!(Type::try_from(0x3), Ok(Type::Dyn));
-        assert_eq!(Machine::try_from(0x03), Ok(Machine::X86));
+        assert_eq!(Machine::from(0x03), Machine::X86);
+        assert_eq!(Machine::from(0x1234), Machine::Other(0x1234));
+        assert_eq!(u16::from(Machine::from(0x1234)), 0x1234);
         assert_eq!(Type::try_from(0x40), Err(0x40));
     }
 
+    fn section_header(name: usize, typ: u32, offset: usize, size: usize, link: u32) -> Vec<u8> {
+        let mut sh = Vec::new();
+        sh.extend(&(name as u32).to_le_bytes());
+        sh.extend(&typ.to_le_bytes());
+        sh.extend(&[0; 16]);
+        sh.extend(&(offset as u64).to_le_bytes());
+        sh.extend(&(size as u64).to_le_bytes());
+        sh.extend(&link.to_le_bytes());
+        sh.extend(&[0; 20]);
+        sh
+    }
+
+    fn symbol(name: u32, info: u8, shndx: u16, size: u64) -> Vec<u8> {
+        let mut sym = Vec::new();
+        sym.extend(&name.to_le_bytes());
+        sym.extend(&[info, 0]);
+        sym.extend(&shndx.to_le_bytes());
+        sym.extend(&[0; 8]);
+        sym.extend(&size.to_le_bytes());
+        sym
+    }
+
+    // An x86_64 ET_REL file made of the given (name, type, link, info, data) sections plus a
+    // trailing .shstrtab, optionally using the SHN_XINDEX escapes for e_shnum and e_shstrndx
+    fn build_rel(sections: Vec<(&str, u32, u32, u32, Vec<u8>)>, extended: bool) -> Vec<u8> {
+        let mut names = vec![0u8];
+        let mut sections: Vec<_> = sections
+            .into_iter()
+            .chain(core::iter::once((".shstrtab", 3, 0, 0, vec![])))
+            .map(|(name, typ, link, info, data)| {
+                names.extend(name.as_bytes());
+                names.push(0);
+                (names.len() - name.len() - 1, typ, link, info, data)
+            })
+            .collect();
+        sections.last_mut().unwrap().4 = names;
+        let (count, shstrndx) = (sections.len() + 1, sections.len());
+
+        let mut body: Vec<u8> = Vec::new();
+        let mut headers = section_header(0, 0, 0, 0, 0);
+        if extended {
+            headers = section_header(0, 0, 0, count, shstrndx as u32);
+        }
+        for (name, typ, link, info, data) in &sections {
+            let mut sh = section_header(*name, *typ, 64 + body.len(), data.len(), *link);
+            sh[44..48].copy_from_slice(&info.to_le_bytes());
+            headers.extend(sh);
+            body.extend(data);
+        }
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend(&1u16.to_le_bytes());
+        elf.extend(&0x3eu16.to_le_bytes());
+        elf.extend(&1u32.to_le_bytes());
+        elf.extend(&[0; 16]);
+        elf.extend(&(64 + body.len() as u64).to_le_bytes());
+        elf.extend(&0u32.to_le_bytes());
+        let (shnum, shstrndx) = match extended {
+            true => (0, 0xffff),
+            false => (count as u16, shstrndx as u16),
+        };
+        for half in &[64u16, 56, 0, 64, shnum, shstrndx] {
+            elf.extend(&half.to_le_bytes());
+        }
+        elf.extend(body);
+        elf.extend(headers);
+        elf
+    }
+
+    #[test]
+    fn extended_section_numbering() {
+        let syms = [symbol(0, 0, 0, 0), symbol(1, 0x12, 0xffff, 4)].concat();
+        let shndx = [0u32.to_le_bytes(), 1u32.to_le_bytes()].concat();
+        let input = build_rel(
+            vec![
+                (".text", 1, 0, 0, vec![0xc3; 4]),
+                (".symtab", 2, 3, 0, syms),
+                (".strtab", 3, 0, 0, b"\0foo\0".to_vec()),
+                (".symtab_shndx", 0x12, 2, 0, shndx),
+            ],
+            true,
+        );
+        let file = super::FileHeader::parse_or_print_error(&input.into()).unwrap();
+        assert_eq!(file.section_headers.len(), 6);
+        assert_eq!(file.section_header_info.count, 6);
+        assert!(file.section_by_name(".shstrtab").is_some());
+
+        let syms = file.read_section_syms();
+        assert_eq!(syms[1].name, "foo");
+        assert_eq!(syms[1].shndx, super::SectionIdx::Index(1));
+        assert_eq!(syms[1].section_index(), Some(1));
+        assert_eq!(syms[0].section_index(), None);
+    }
+
+    #[test]
+    fn section_contents() {
+        use super::{
+            parse::{ParseOptions, Strictness},
+            FileHeader, SectionContentError,
+        };
+        let mut input = build_rel(
+            vec![
+                (".text", 1, 0, 0, vec![0xc3; 4]),
+                (".bss", 8, 0, 0, vec![]),
+                (".data", 1, 0, 0, vec![1; 8]),
+            ],
+            false,
+        );
+        let shoff = super::u32_at(&input, 40).unwrap() as usize;
+        // 0x100 bytes of .bss, and .data starting past the end of the file
+        input[shoff + 2 * 64 + 32..][..8].copy_from_slice(&0x100u64.to_le_bytes());
+        input[shoff + 3 * 64 + 24..][..8].copy_from_slice(&0x10000u64.to_le_bytes());
+        // Only forensic parsing lets a section past EOF through
+        let options = ParseOptions {
+            strictness: Strictness::Forensic,
+            ..Default::default()
+        };
+        let file = FileHeader::parse_checked(&input.into(), &options).unwrap();
+
+        let section = |name| file.section_by_name(name).unwrap();
+        assert_eq!(
+            file.section_headers[0].contents(),
+            Err(SectionContentError::Null)
+        );
+        assert_eq!(section(".text").contents(), Ok(&[0xc3; 4][..]));
+        let bss = section(".bss");
+        assert_eq!(bss.contents(), Err(SectionContentError::NoBits(0x100)));
+        assert_eq!(bss.file_range().start, bss.file_range().end);
+        assert_eq!(bss.mem_range().end.0 - bss.mem_range().start.0, 0x100);
+        assert_eq!(
+            section(".data").contents(),
+            Err(SectionContentError::PastEof {
+                offset: 0x10000,
+                size: 8,
+                available: 0
+            })
+        );
+    }
+
+    #[test]
+    fn comdat_groups() {
+        let syms = [symbol(0, 0, 0, 0), symbol(1, 0x10, 2, 0)].concat();
+        let group = [1u32, 2, 3]
+            .iter()
+            .flat_map(|w| w.to_le_bytes().to_vec())
+            .collect();
+        let input = build_rel(
+            vec![
+                (".group", 0x11, 4, 1, group),
+                (".text._Z3foov", 1, 0, 0, vec![0xc3]),
+                (".rela.text._Z3foov", 4, 4, 2, vec![]),
+                (".symtab", 2, 5, 0, syms),
+                (".strtab", 3, 0, 0, b"\0_Z3foov\0".to_vec()),
+            ],
+            false,
+        );
+        let file = super::FileHeader::parse_or_print_error(&input.into()).unwrap();
+        let groups = file.section_groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].signature, "_Z3foov");
+        assert!(groups[0].comdat);
+        assert_eq!(
+            groups[0].members,
+            vec![".text._Z3foov", ".rela.text._Z3foov"]
+        );
+    }
+
+    #[test]
+    fn endbr_against_ibt() {
+        let function = |name, value: u64| {
+            let mut sym = symbol(name, 0x12, 1, 16);
+            sym[8..16].copy_from_slice(&value.to_le_bytes());
+            sym
+        };
+        let syms = [
+            symbol(0, 0, 0, 0),
+            function(1, 0),
+            function(6, 0x10),
+            // Local functions may leave it out
+            symbol(11, 0x02, 1, 1),
+        ]
+        .concat();
+        let mut text = vec![0xf3, 0x0f, 0x1e, 0xfa, 0xc3];
+        text.resize(0x10, 0x90);
+        text.extend(&[0x55, 0xc3]);
+        // NT_GNU_PROPERTY_TYPE_0 with GNU_PROPERTY_X86_FEATURE_1_AND = IBT
+        let mut note = [4u32, 16, 5]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        note.extend(b"GNU\0");
+        for word in [0xc000_0002u32, 4, 1, 0] {
+            note.extend(&word.to_le_bytes());
+        }
+        let build = |note: Vec<u8>| {
+            let input = build_rel(
+                vec![
+                    (".text", 1, 0, 0, text.clone()),
+                    (".symtab", 2, 3, 1, syms.clone()),
+                    (".strtab", 3, 0, 0, b"\0good\0nope\0stat\0".to_vec()),
+                    (".note.gnu.property", 7, 0, 0, note),
+                ],
+                false,
+            );
+            super::FileHeader::parse_or_print_error(&input.into())
+                .unwrap()
+                .endbr_report()
+                .unwrap()
+        };
+
+        let report = build(note);
+        assert!(report.ibt);
+        assert_eq!(report.functions, 2);
+        assert_eq!(report.missing, [("nope".to_string(), super::Addr(0x10))]);
+        assert!(report.is_mismatch());
+        // Without the property nothing enforces it
+        assert!(!build(Vec::new()).is_mismatch());
+    }
+
+    #[test]
+    fn symbol_edits() {
+        use super::patch::{SymbolEdit, SymbolEditError};
+        let add = |name: &str, bind| SymbolEdit::Add {
+            name: name.to_string(),
+            value: 0x10,
+            size: 4,
+            bind,
+            typ: super::SymType::Func,
+        };
+        let syms = [
+            symbol(0, 0, 0, 0),
+            symbol(1, 0, 1, 0),
+            symbol(5, 0x10, 1, 0),
+        ]
+        .concat();
+        let input = build_rel(
+            vec![
+                (".text", 1, 0, 0, vec![0xc3]),
+                (".symtab", 2, 3, 2, syms),
+                (".strtab", 3, 0, 0, b"\0foo\0bar\0".to_vec()),
+            ],
+            false,
+        );
+        let file = super::FileHeader::parse_or_print_error(&input.clone().into()).unwrap();
+        let rename = SymbolEdit::Rename {
+            from: "foo".to_string(),
+            to: "baz".to_string(),
+        };
+        let plan = file
+            .edit_symbols(&input, &[rename, add("qux", super::SymBind::Global)])
+            .unwrap();
+        let edited =
+            super::FileHeader::parse_or_print_error(&plan.apply(&input).unwrap().into()).unwrap();
+        let names: Vec<_> = edited
+            .read_section_syms()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, ["", "baz", "bar", "qux"]);
+        assert_eq!(edited.section_by_name(".symtab").unwrap().info, 2);
+        // Relocations in an object file refer to its global symbols by index
+        assert_eq!(
+            file.edit_symbols(&input, &[add("local", super::SymBind::Local)])
+                .unwrap_err(),
+            SymbolEditError::Renumbering
+        );
+
+        // A file without a symbol table gets one
+        let input = build_rel(vec![(".text", 1, 0, 0, vec![0xc3])], false);
+        let file = super::FileHeader::parse_or_print_error(&input.clone().into()).unwrap();
+        let plan = file
+            .edit_symbols(&input, &[add("label", super::SymBind::Local)])
+            .unwrap();
+        let edited =
+            super::FileHeader::parse_or_print_error(&plan.apply(&input).unwrap().into()).unwrap();
+        let syms = edited.read_section_syms();
+        assert_eq!(syms.len(), 2);
+        assert_eq!(syms[1].name, "label");
+        assert_eq!(syms[1].bind(), Some(super::SymBind::Local));
+        assert_eq!(edited.section_by_name(".symtab").unwrap().info, 2);
+        assert!(edited.section_by_name(".strtab").is_some());
+
+        // Nor, with e_shstrndx SHN_UNDEF, a table to name them in
+        let mut input = input;
+        input[0x3e..0x40].fill(0);
+        let file = super::FileHeader::parse_or_print_error(&input.clone().into()).unwrap();
+        assert_eq!(
+            file.edit_symbols(&input, &[add("label", super::SymBind::Local)])
+                .unwrap_err(),
+            SymbolEditError::NoSectionNames
+        );
+    }
+
+    #[test]
+    fn symbol_versions() {
+        let words =
+            |ws: &[u32]| -> Vec<u8> { ws.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect() };
+        let halves =
+            |hs: &[u16]| -> Vec<u8> { hs.iter().flat_map(|h| h.to_le_bytes().to_vec()).collect() };
+        // version 1, flags 0, index 2, one aux at +20; aux: name "FOO_1"
+        let verdef = [halves(&[1, 0, 2, 1]), words(&[0, 20, 0, 23, 0])].concat();
+        // version 1, one aux, file "libc.so.6", aux at +16; aux: index 3, name "GLIBC_2.2.5"
+        let verneed = [
+            halves(&[1, 1]),
+            words(&[1, 16, 0, 0]),
+            halves(&[0, 3]),
+            words(&[11, 0]),
+        ]
+        .concat();
+        let input = build_rel(
+            vec![
+                (
+                    ".dynstr",
+                    3,
+                    0,
+                    0,
+                    b"\0libc.so.6\0GLIBC_2.2.5\0FOO_1\0".to_vec(),
+                ),
+                (".gnu.version_d", 0x6fff_fffd, 1, 1, verdef),
+                (".gnu.version_r", 0x6fff_fffe, 1, 1, verneed),
+                (
+                    ".gnu.version",
+                    0x6fff_ffff,
+                    0,
+                    0,
+                    halves(&[0, 0x8002, 3, 1]),
+                ),
+            ],
+            false,
+        );
+        let file = super::FileHeader::parse_or_print_error(&input.into()).unwrap();
+        assert_eq!(file.version_definitions(), vec![(2, "FOO_1".to_string())]);
+
+        let versions = file.dynamic_symbol_versions();
+        assert_eq!(versions.len(), 4);
+        assert!(versions[0].is_none() && versions[3].is_none());
+        let foo = versions[1].as_ref().unwrap();
+        assert_eq!(
+            (foo.name.as_str(), foo.hidden, foo.file.as_ref()),
+            ("FOO_1", true, None)
+        );
+        let glibc = versions[2].as_ref().unwrap();
+        assert_eq!(glibc.name, "GLIBC_2.2.5");
+        assert_eq!(glibc.file.as_deref(), Some("libc.so.6"));
+        assert!(!glibc.hidden);
+    }
+
+    #[test]
+    fn malformed_header_tables() {
+        let input = build_rel(vec![(".text", 1, 0, 0, vec![0xc3; 4])], true);
+        let failed_in = |input: &[u8]| match super::FileHeader::parse(&input.into()) {
+            Err(nom::Err::Failure(e)) => match e.errors[0].1 {
+                nom::error::VerboseErrorKind::Context(ctx) => ctx,
+                _ => "other",
+            },
+            Err(_) => "other",
+            Ok(_) => "ok",
+        };
+
+        // One program header starting 8 bytes before the end of the file
+        let mut bad = input.clone();
+        bad[32..40].copy_from_slice(&(input.len() as u64 - 8).to_le_bytes());
+        bad[56..58].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(failed_in(&bad), "Program header table");
+        // Entries too small to hold a program header
+        bad[32..40].copy_from_slice(&64u64.to_le_bytes());
+        bad[54..56].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(failed_in(&bad), "Program header table");
+
+        // PN_XNUM defers to sh_info of section 0, which is 0 here
+        let mut xnum = input.clone();
+        xnum[56..58].copy_from_slice(&0xffffu16.to_le_bytes());
+        let file = super::FileHeader::parse_or_print_error(&xnum.into()).unwrap();
+        assert!(file.program_headers.is_empty());
+    }
+
+    #[test]
+    fn untrusted_input() {
+        use super::{FileHeader, ParseError};
+        let input = build_rel(vec![(".text", 1, 0, 0, vec![0xc3; 4])], false);
+        let shoff = super::u32_at(&input, 40).unwrap() as usize;
+        let options = super::ParseOptions::default();
+
+        for len in 0..input.len() {
+            assert!(FileHeader::parse_checked(&input[..len].into(), &options).is_err());
+        }
+
+        // .text running off the end of the address space
+        let mut bad = input.clone();
+        bad[shoff + 64 + 24..shoff + 64 + 32].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+        let err = FileHeader::parse_checked(&bad.into(), &options).err();
+        let (offset, field) = (shoff + 64, "Section offset and size");
+        assert_eq!(err, Some(ParseError::Invalid { offset, field }));
+
+        // A LOAD segment, in a header appended to the file, whose contents start where it ends
+        let mut bad = input.clone();
+        let end = input.len() as u64 + 56;
+        bad[32..40].copy_from_slice(&(input.len() as u64).to_le_bytes());
+        bad[56..58].copy_from_slice(&1u16.to_le_bytes());
+        for word in &[1u64 | 4 << 32, end, 0, 0, 16, 16, 0x1000] {
+            bad.extend(&word.to_le_bytes());
+        }
+        let err = FileHeader::parse_checked(&bad.into(), &options).err();
+        let (offset, field) = (input.len(), "Segment offset and size");
+        assert_eq!(err, Some(ParseError::Invalid { offset, field }));
+    }
+
+    // DT_SYMENT comes from the file, so its product with an index can overflow
+    #[test]
+    fn dynamic_symbol_offsets() {
+        use super::{Addr, FileHeader};
+        let input = include_bytes!("../corpus/greet-x86_64").to_vec();
+        let file = FileHeader::parse_or_print_error(&input.into()).unwrap();
+        let context = |result: super::parse::Result<'_, _>| match result {
+            Err(nom::Err::Failure(e)) => Some(e.errors[0].1.clone()),
+            _ => None,
+        };
+        let symtab = Addr(0x200000);
+        assert!(file.dynamic_symbol((symtab, 24), 0).is_ok());
+        assert_eq!(
+            context(file.dynamic_symbol((symtab, usize::MAX), 2)),
+            Some(nom::error::VerboseErrorKind::Context("dynamic symbol"))
+        );
+        assert_eq!(
+            context(file.dynamic_symbol((Addr(0x10), 24), 0)),
+            Some(nom::error::VerboseErrorKind::Context(
+                "dynamic symbol table"
+            ))
+        );
+    }
+
+    #[test]
+    fn padded_header_entries() {
+        let mut input = build_rel(vec![(".text", 1, 0, 0, vec![0xc3; 4])], false);
+        let shoff = super::u32_at(&input, 40).unwrap() as usize;
+        let padded: Vec<u8> = input[shoff..]
+            .chunks(64)
+            .flat_map(|sh| sh.iter().copied().chain([0xaa; 8]))
+            .collect();
+        input.truncate(shoff);
+        input.extend(padded);
+        input[58..60].copy_from_slice(&72u16.to_le_bytes());
+
+        let file = super::FileHeader::parse_or_print_error(&input.into()).unwrap();
+        assert_eq!(file.section_header_info.size, 72);
+        assert_eq!(file.section_header_info.padding, 8);
+        let names: Vec<_> = file.section_headers.iter().map(|sh| &sh.name[..]).collect();
+        assert_eq!(names, ["", ".text", ".shstrtab"]);
+    }
+
+    #[test]
+    fn strictness() {
+        use super::parse::{ParseOptions, Severity, Strictness};
+        use super::FileHeader;
+        let parse = |input: &Vec<u8>, strictness| {
+            let options = ParseOptions {
+                strictness,
+                ..Default::default()
+            };
+            FileHeader::parse_checked(&input.clone().into(), &options)
+        };
+        let input = build_rel(vec![(".text", 1, 0, 0, vec![0xc3; 4])], false);
+        let text = super::u32_at(&input, 40).unwrap() as usize + 64;
+
+        // A section type from no spec elk knows of
+        let mut unknown = input.clone();
+        unknown[text + 4..text + 8].copy_from_slice(&0x1234u32.to_le_bytes());
+        assert!(parse(&unknown, Strictness::Strict).is_err());
+        let file = parse(&unknown, Strictness::Permissive).unwrap();
+        assert_eq!(
+            file.section_headers[1].typ,
+            super::SectionType::Other(0x1234)
+        );
+        let severities: Vec<_> = file.anomalies.iter().map(|a| a.severity).collect();
+        assert_eq!(severities, [Severity::Unusual]);
+
+        // ...whose contents are past the end of the file
+        unknown[text + 24..text + 32].copy_from_slice(&0x10_0000u64.to_le_bytes());
+        assert!(parse(&unknown, Strictness::Permissive).is_err());
+        let file = parse(&unknown, Strictness::Forensic).unwrap();
+        assert!(file.section_headers[1].data.is_empty());
+        let messages: Vec<_> = file.anomalies.iter().map(|a| &a.message[..]).collect();
+        assert_eq!(
+            messages,
+            [
+                "section 1: unknown section type 0x1234",
+                "section 1: file range extends past EOF, truncated"
+            ]
+        );
+        assert_eq!(file.anomalies[1].severity, Severity::Broken);
+
+        // A section header table cut off after two entries
+        let truncated = input[..text + 64 + 32].to_vec();
+        assert!(parse(&truncated, Strictness::Permissive).is_err());
+        let file = parse(&truncated, Strictness::Forensic).unwrap();
+        assert_eq!(file.section_headers.len(), 2);
+        assert_eq!(file.anomalies[0].field, "Section header table");
+    }
+
+    #[test]
+    fn footprint_ceiling() {
+        use super::{parse::ParseOptions, FileHeader, ParseError};
+        let input = build_rel(
+            vec![
+                (".text", 1, 0, 0, vec![0xc3; 4]),
+                (".data", 1, 0, 0, vec![0; 64]),
+            ],
+            false,
+        )
+        .into();
+        let parse = |max_footprint| {
+            let options = ParseOptions {
+                max_footprint,
+                ..Default::default()
+            };
+            FileHeader::parse_checked(&input, &options)
+        };
+        let footprint = parse(None).unwrap().footprint();
+        assert!(footprint > 4 * core::mem::size_of::<super::SectionHeader>());
+        assert!(parse(Some(footprint)).is_ok());
+        assert!(matches!(parse(Some(400)), Err(ParseError::Footprint(400))));
+    }
+
+    #[test]
+    fn cancelled_parse() {
+        use super::{cancel::CancellationToken, parse::ParseOptions, FileHeader, ParseError};
+        let input = build_rel(vec![(".text", 1, 0, 0, vec![0xc3; 4])], false).into();
+        let options = ParseOptions::default();
+        assert!(FileHeader::parse_checked(&input, &options).is_ok());
+        let cancel = CancellationToken::new();
+        let options = ParseOptions {
+            cancel: cancel.clone(),
+            ..Default::default()
+        };
+        cancel.cancel();
+        assert_eq!(
+            FileHeader::parse_checked(&input, &options).err(),
+            Some(ParseError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn symbol_at_prefers_functions() {
+        let syms = [
+            symbol(0, 0, 0, 0),
+            symbol(1, 0x11, 1, 0x10),
+            symbol(5, 0x12, 1, 4),
+        ]
+        .concat();
+        let input = build_rel(
+            vec![
+                (".text", 1, 0, 0, vec![0xc3; 0x10]),
+                (".symtab", 2, 3, 0, syms),
+                (".strtab", 3, 0, 0, b"\0obj\0foo\0".to_vec()),
+            ],
+            false,
+        );
+        let file = super::FileHeader::parse_or_print_error(&input.into()).unwrap();
+        let at = |addr| {
+            file.symbol_at(super::Addr(addr))
+                .map(|(sym, offset)| (sym.name, offset))
+        };
+        assert_eq!(at(2), Some(("foo".to_string(), 2)));
+        assert_eq!(at(8), Some(("obj".to_string(), 8)));
+        assert_eq!(at(0x20), None);
+        assert_eq!(file.entry_symbol().unwrap().name, "foo");
+    }
+
+    #[test]
+    fn symbol_info() {
+        use super::{SymBind, SymType, SymVisibility, Symbol};
+
+        let info = Symbol::info(SymBind::Weak, SymType::Func);
+        assert_eq!(info, 0x22);
+        assert_eq!(SymBind::from_info(info), Some(SymBind::Weak));
+        assert_eq!(SymType::from_info(info), Some(SymType::Func));
+        // STB_LOPROC and STT_HIPROC have no names here
+        assert_eq!(SymBind::from_info(0xd0), None);
+        assert_eq!(SymType::from_info(0x0f), None);
+        // Only the low two bits of st_other are visibility
+        assert_eq!(SymVisibility::from_other(0xfe), SymVisibility::Hidden);
+    }
+
     #[test]
     fn bitflags() {
         use super::SegmentFlags;
@@ -204,4 +1810,269 @@ mod tests {
         assert_eq!(flags.bits(), flag_int);
         assert_eq!(SegmentFlags::Read | SegmentFlags::Write, flags);
     }
+
+    #[test]
+    fn section_bits() {
+        use super::{SectionBits, SectionFlags};
+
+        let bits = SectionBits::from_bits(0x3);
+        assert!(bits.contains(SectionFlags::Write | SectionFlags::Alloc));
+        assert_eq!(format!("{:?}", bits), "WA");
+
+        // .debug_str with an OS-specific bit and one nothing defines
+        let raw = 0x30 | 0x800 | 0x0010_0000 | 0x1000;
+        let bits = SectionBits::from_bits(raw);
+        assert_eq!(bits.bits(), raw);
+        assert_eq!(bits.unknown(), 0x0010_1000);
+        assert_eq!(format!("{:?}", bits), "MSCox");
+    }
+
+    #[test]
+    fn segment_sizes() {
+        use super::types::{ProgramHeader, SegmentSizeError};
+
+        let load = |vaddr: u64, file_size: u64, mem_size: u64| {
+            let mut raw = [1u32.to_le_bytes(), 6u32.to_le_bytes()].concat();
+            for field in [0, vaddr, vaddr, file_size, mem_size, 0x1000] {
+                raw.extend(field.to_le_bytes());
+            }
+            // Segment contents come from the file, long enough for any file_size here
+            let file = vec![0u8; 0x100].into();
+            let (_, ph) = ProgramHeader::parse(&file, &raw).unwrap();
+            ph.check_sizes()
+        };
+        assert_eq!(load(0x1000, 0x80, 0x2000), Ok(()));
+        assert_eq!(
+            load(u64::MAX - 0xfff, 0, 0x2000),
+            Err(SegmentSizeError::Overflow(u64::MAX - 0xfff, 0x2000))
+        );
+        assert_eq!(
+            load(0x1000, 0x80, 0x40),
+            Err(SegmentSizeError::Truncated(0x40, 0x80))
+        );
+        assert_eq!(
+            load(0x1000, 0, 1 << 40),
+            Err(SegmentSizeError::HugeBss(1 << 40))
+        );
+    }
+
+    #[test]
+    fn negative_addends() {
+        use super::types::{Addend, Addr, RelType, RelaEntry};
+
+        // R_X86_64_RELATIVE at 0x3ff0 pointing 8 bytes below 0x1000, as hand-written asm does
+        let mut raw = 0x3ff0u64.to_le_bytes().to_vec();
+        raw.extend(8u64.to_le_bytes());
+        raw.extend((-8i64).to_le_bytes());
+        let (_, entry) = RelaEntry::parse(&raw).unwrap();
+        assert_eq!(entry.typ, RelType::Relative);
+        assert_eq!(entry.addend, Addend(-8));
+        assert_eq!(Addr(0x1000) + entry.addend, Addr(0xff8));
+        assert_eq!(format!("{:?}", entry.addend), "-0x8");
+        assert_eq!(format!("{:?}", Addend(0x10)), "+0x10");
+        // Relocated against a base, the result wraps the way 64-bit arithmetic does
+        assert_eq!(Addr(4) + Addend(-8), Addr(u64::MAX - 3));
+    }
+
+    #[test]
+    fn elf32_layouts() {
+        use super::detect::{Class::Elf32, Endian::Little};
+        use super::types::{
+            Addr, Machine, ProgramHeader, RelType, RelaEntry, SegmentFlags, Symbol,
+        };
+
+        let words = |ws: &[u32]| -> Vec<u8> { ws.iter().flat_map(|w| w.to_le_bytes()).collect() };
+
+        // PT_LOAD with p_flags after p_memsz
+        let raw = words(&[1, 0, 0x1000, 0x1000, 0x10, 0x20, 5, 0x1000]);
+        let file: super::Data = vec![0u8; 0x10].into();
+        let mut anomalies = super::parse::Anomalies::new(&file[..], &Default::default());
+        let (rest, ph) =
+            ProgramHeader::parse_as(Elf32, Little, &file, &raw, &mut anomalies).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!((ph.virt_addr, ph.mem_size), (Addr(0x1000), Addr(0x20)));
+        assert!(ph.flags.contains(SegmentFlags::Read) && ph.flags.contains(SegmentFlags::Execute));
+
+        // st_value and st_size ahead of st_info
+        let mut raw = words(&[1, 0x1040, 4]);
+        raw.extend([0x12, 0, 7, 0]);
+        let (_, sym) = Symbol::parse_as(Elf32, Little)(&raw).unwrap();
+        assert_eq!((sym.value, sym.size, sym.info), (Addr(0x1040), 4, 0x12));
+
+        // R_386_32 against symbol 1, then R_386_IRELATIVE, which x86-64 numbers differently
+        let raw = words(&[0x400c, 1 << 8 | 1, 0x4010, 42]);
+        let (rest, rel) = RelaEntry::parse_as(Elf32, Little, Machine::X86, true)(&raw).unwrap();
+        assert_eq!(
+            (rel.offset, rel.typ, rel.sym),
+            (Addr(0x400c), RelType::Abs64, 1)
+        );
+        let (_, rel) = RelaEntry::parse_as(Elf32, Little, Machine::X86, true)(rest).unwrap();
+        assert_eq!(rel.typ, RelType::IRelative);
+        assert_eq!(RelaEntry::size(Elf32, true), 8);
+    }
+
+    #[test]
+    fn relocation_types_by_machine() {
+        use super::{
+            detect::Class::*,
+            types::{Machine, RelType},
+        };
+        // R_*_RELATIVE, R_*_JUMP_SLOT and the word-sized absolute type of each
+        let table = [
+            (Machine::X86_64, Elf64, [8, 7, 1]),
+            (Machine::X86, Elf32, [8, 7, 1]),
+            (Machine::Arm, Elf32, [23, 22, 2]),
+            (Machine::AArch64, Elf64, [1027, 1026, 257]),
+            (Machine::RiscV, Elf64, [3, 5, 2]),
+        ];
+        for (machine, class, [relative, jump_slot, abs]) in table {
+            let typ = |n| RelType::from_number(machine, class, n);
+            assert_eq!(typ(relative), RelType::Relative, "{:?}", machine);
+            assert_eq!(typ(jump_slot), RelType::JumpSlot, "{:?}", machine);
+            assert_eq!(typ(abs), RelType::Abs64, "{:?}", machine);
+        }
+        // Numbers x86-64 has names for mean something else elsewhere
+        assert_eq!(
+            RelType::from_number(Machine::AArch64, Elf64, 8),
+            RelType::Unknown(8)
+        );
+        assert_eq!(
+            RelType::from_number(Machine::Arm, Elf32, 8),
+            RelType::Unknown(8)
+        );
+        assert_eq!(
+            RelType::from_number(Machine::RiscV, Elf32, 1),
+            RelType::Abs64
+        );
+        // No table at all
+        assert_eq!(
+            RelType::from_number(Machine::Mips, Elf32, 3),
+            RelType::Unknown(3)
+        );
+    }
+
+    // The program in corpus/greet.ll as `make corpus` builds it for each architecture, linked
+    // and as the relocatable object it was linked from
+    #[test]
+    fn multi_arch_corpus() {
+        use super::{
+            data::Data,
+            detect::{self, Class::*, Endian::*, Format},
+            types::*,
+            FileHeader,
+        };
+        const MESSAGE: &[u8] = b"Hello from the corpus!\n";
+        // R_X86_64_64, R_386_32, R_AARCH64_ABS64, R_ARM_ABS32, R_RISCV_64 and R_MIPS_32, which
+        // delf has no MIPS names for
+        let corpus: &[(&str, Machine, detect::Class, detect::Endian, RelType)] = &[
+            ("x86_64", Machine::X86_64, Elf64, Little, RelType::Abs64),
+            ("i686", Machine::X86, Elf32, Little, RelType::Abs64),
+            ("aarch64", Machine::AArch64, Elf64, Little, RelType::Abs64),
+            ("armv7", Machine::Arm, Elf32, Little, RelType::Abs64),
+            ("riscv64", Machine::RiscV, Elf64, Little, RelType::Abs64),
+            ("mips", Machine::Mips, Elf32, Big, RelType::Unknown(2)),
+        ];
+        let files: &[(&str, &[u8])] = &[
+            ("greet-x86_64", include_bytes!("../corpus/greet-x86_64")),
+            ("greet-x86_64.o", include_bytes!("../corpus/greet-x86_64.o")),
+            ("greet-i686", include_bytes!("../corpus/greet-i686")),
+            ("greet-i686.o", include_bytes!("../corpus/greet-i686.o")),
+            ("greet-aarch64", include_bytes!("../corpus/greet-aarch64")),
+            (
+                "greet-aarch64.o",
+                include_bytes!("../corpus/greet-aarch64.o"),
+            ),
+            ("greet-armv7", include_bytes!("../corpus/greet-armv7")),
+            ("greet-armv7.o", include_bytes!("../corpus/greet-armv7.o")),
+            ("greet-riscv64", include_bytes!("../corpus/greet-riscv64")),
+            (
+                "greet-riscv64.o",
+                include_bytes!("../corpus/greet-riscv64.o"),
+            ),
+            ("greet-mips", include_bytes!("../corpus/greet-mips")),
+            ("greet-mips.o", include_bytes!("../corpus/greet-mips.o")),
+        ];
+        assert_eq!(files.len(), corpus.len() * 2);
+        for &(name, bytes) in files {
+            let arch = name.trim_start_matches("greet-").trim_end_matches(".o");
+            let &(_, machine, class, endian, abs) =
+                corpus.iter().find(|(a, ..)| *a == arch).unwrap();
+            assert_eq!(
+                detect::detect(bytes),
+                Format::Elf { class, endian },
+                "{}",
+                name
+            );
+            let file = FileHeader::parse_or_describe(&Data::new(bytes.to_vec()))
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(
+                (file.machine, file.class, file.endian),
+                (machine, class, endian),
+                "{}",
+                name
+            );
+            let syms = file.read_section_syms();
+            let sym = |wanted: &str| {
+                syms.iter()
+                    .find(|sym| sym.name == wanted)
+                    .unwrap_or_else(|| panic!("{}: no symbol {}", name, wanted))
+            };
+            assert_eq!(sym("message").size, MESSAGE.len() as u64, "{}", name);
+            assert_eq!(sym("greet").typ(), Some(SymType::Func), "{}", name);
+            let word = |bytes: &[u8]| {
+                let (_, word) = super::parse::word(class, endian)(bytes).unwrap();
+                word
+            };
+
+            if file.typ == Type::Rel {
+                assert!(file.program_headers.is_empty(), "{}", name);
+                let rodata = file.section_by_name(".rodata").unwrap();
+                assert!(rodata.data.starts_with(MESSAGE), "{}", name);
+                // The one relocation of `greeting`'s section is `greeting`'s, the address of
+                // `message`
+                let data_idx = sym("greeting").section_index().unwrap();
+                let reloc = file
+                    .section_headers
+                    .iter()
+                    .find(|sh| {
+                        matches!(sh.typ, SectionType::Rela | SectionType::Rel)
+                            && sh.info as usize == data_idx
+                    })
+                    .unwrap_or_else(|| panic!("{}: greeting has no relocation", name));
+                let entries = file.section_rela_entries(reloc).unwrap();
+                assert_eq!(entries.len(), 1, "{}", name);
+                assert_eq!(entries[0].typ, abs, "{}", name);
+                assert_eq!(entries[0].offset, sym("greeting").value, "{}", name);
+                let symtab = file.symbols_in(reloc.link as usize);
+                assert_eq!(symtab[entries[0].sym as usize].name, "message", "{}", name);
+                continue;
+            }
+
+            assert_eq!(file.typ, Type::Exec, "{}", name);
+            assert_eq!(file.entry_point, sym("_start").value, "{}", name);
+            let loads: Vec<_> = file.segments_of_type(SegmentType::Load).collect();
+            assert!(loads[0].flags.contains(SegmentFlags::Execute), "{}", name);
+            assert!(loads[0].mem_range().contains(&file.entry_point), "{}", name);
+            let data = loads
+                .iter()
+                .find(|ph| ph.mem_range().contains(&sym("step").value))
+                .unwrap_or_else(|| panic!("{}: step is in no segment", name));
+            assert!(data.flags.contains(SegmentFlags::Write), "{}", name);
+            let message = file.bytes_at(sym("message").value).unwrap();
+            assert!(message.starts_with(MESSAGE), "{}", name);
+            let step = file.bytes_at(sym("step").value).unwrap();
+            assert_eq!(super::parse::u32(endian)(step).unwrap().1, 1, "{}", name);
+            // Filled in by the linker, in the file's byte order and word size
+            let greeting = file.bytes_at(sym("greeting").value).unwrap();
+            assert_eq!(word(greeting), sym("message").value.0, "{}", name);
+            // Zero fill, in memory only
+            assert_eq!(file.vaddr_to_offset(sym("calls").value), None, "{}", name);
+            assert!(
+                file.segment_at(sym("scratch").value)
+                    .is_some_and(|ph| ph.flags.contains(SegmentFlags::Write)),
+                "{}",
+                name
+            );
+        }
+    }
 }
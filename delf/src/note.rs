@@ -0,0 +1,540 @@
+use crate::{detect::Class, prelude::*, types::Machine, u32_at, walk::Walk};
+
+pub const NT_GNU_ABI_TAG: u32 = 1;
+pub const NT_GNU_BUILD_ID: u32 = 3;
+pub const NT_GO_BUILD_ID: u32 = 4;
+pub const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+// .note.package of distribution builds: which package the file came from, as JSON
+pub const NT_FDO_PACKAGING_METADATA: u32 = 0xcafe_1a7e;
+
+// Core dump notes, owned by "CORE" except for NT_X86_XSTATE's "LINUX"
+pub const NT_PRSTATUS: u32 = 1;
+pub const NT_PRFPREG: u32 = 2;
+pub const NT_PRPSINFO: u32 = 3;
+pub const NT_AUXV: u32 = 6;
+pub const NT_X86_XSTATE: u32 = 0x202;
+pub const NT_SIGINFO: u32 = 0x5349_4749;
+pub const NT_FILE: u32 = 0x4649_4c45;
+
+// pr_type values of NT_GNU_PROPERTY_TYPE_0 entries. The 0xc0000000 range is processor specific.
+pub const GNU_PROPERTY_STACK_SIZE: u32 = 1;
+pub const GNU_PROPERTY_NO_COPY_ON_PROTECTED: u32 = 2;
+pub const GNU_PROPERTY_AARCH64_FEATURE_1_AND: u32 = 0xc000_0000;
+pub const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc000_0002;
+pub const GNU_PROPERTY_X86_ISA_1_NEEDED: u32 = 0xc000_8002;
+pub const GNU_PROPERTY_X86_ISA_1_USED: u32 = 0xc001_0002;
+
+// user_regs_struct of each machine, the pr_reg part of NT_PRSTATUS
+const X86_64_REGISTERS: [&str; 27] = [
+    "r15", "r14", "r13", "r12", "rbp", "rbx", "r11", "r10", "r9", "r8", "rax", "rcx", "rdx", "rsi",
+    "rdi", "orig_rax", "rip", "cs", "eflags", "rsp", "ss", "fs_base", "gs_base", "ds", "es", "fs",
+    "gs",
+];
+const I386_REGISTERS: [&str; 17] = [
+    "ebx", "ecx", "edx", "esi", "edi", "ebp", "eax", "ds", "es", "fs", "gs", "orig_eax", "eip",
+    "cs", "eflags", "esp", "ss",
+];
+const AARCH64_REGISTERS: [&str; 34] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "x29", "x30", "sp", "pc", "pstate",
+];
+
+// One entry of a SHT_NOTE section or PT_NOTE segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub name: String,
+    pub typ: u32,
+    pub desc: Vec<u8>,
+}
+
+// One property of a NT_GNU_PROPERTY_TYPE_0 note
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GnuProperty {
+    pub typ: u32,
+    pub data: Vec<u8>,
+}
+
+// A thread's signal and registers at the time of a core dump, from NT_PRSTATUS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrStatus {
+    pub signal: u16,
+    pub pid: u32,
+    pub ppid: u32,
+    pub registers: Vec<(&'static str, u64)>,
+}
+
+// The dumped process, from NT_PRPSINFO. `args` is the start of its command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsInfo {
+    pub pid: u32,
+    pub name: String,
+    pub args: String,
+}
+
+// The signal that ended the dumped process, from NT_SIGINFO. Faults (SIGILL, SIGBUS, SIGFPE and
+// SIGSEGV) carry the address they happened at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigInfo {
+    pub signal: u32,
+    pub code: i32,
+    pub addr: Option<u64>,
+}
+
+// A file-backed mapping of the dumped process, from NT_FILE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedFile {
+    pub start: u64,
+    pub end: u64,
+    // In bytes, NT_FILE counts it in pages
+    pub offset: u64,
+    pub path: String,
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    let mut word = [0; 8];
+    word.copy_from_slice(bytes);
+    Some(u64::from_le_bytes(word))
+}
+
+// An address-sized word
+fn word_at(class: Class, data: &[u8], offset: usize) -> Option<u64> {
+    match class {
+        Class::Elf32 => u32_at(data, offset).map(u64::from),
+        Class::Elf64 => u64_at(data, offset),
+    }
+}
+
+fn word_size(class: Class) -> usize {
+    match class {
+        Class::Elf32 => 4,
+        Class::Elf64 => 8,
+    }
+}
+
+fn fixed_str(data: &[u8], range: core::ops::Range<usize>) -> Option<String> {
+    let bytes = data.get(range)?;
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    Some(
+        String::from_utf8_lossy(&bytes[..end])
+            .trim_end()
+            .to_string(),
+    )
+}
+
+// Splits note data into entries. Name and descriptor are each padded to 4 bytes; parsing stops
+// at the first entry that runs past the end of the data.
+pub fn parse_notes(data: &[u8]) -> Vec<Note> {
+    let mut notes = Vec::new();
+    let mut pos = 0;
+    // Each note takes at least its 12-byte header
+    let mut walk = Walk::new("notes", data.len() / 12);
+    while let (Some(namesz), Some(descsz), Some(typ)) = (
+        u32_at(data, pos),
+        u32_at(data, pos + 4),
+        u32_at(data, pos + 8),
+    ) {
+        if walk.visit(pos as u64).is_err() {
+            break;
+        }
+        let name_start = pos + 12;
+        let desc_start = align4(name_start + namesz as usize);
+        let (name, desc) = match (
+            data.get(name_start..name_start + namesz as usize),
+            data.get(desc_start..desc_start + descsz as usize),
+        ) {
+            (Some(name), Some(desc)) => (name, desc),
+            _ => break,
+        };
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        notes.push(Note {
+            name: String::from_utf8_lossy(&name[..end]).into_owned(),
+            typ,
+            desc: desc.to_vec(),
+        });
+        pos = align4(desc_start + descsz as usize);
+    }
+    notes
+}
+
+impl Note {
+    // The NT_ constant's name, for the note types delf knows about
+    pub fn type_name(&self) -> Option<&'static str> {
+        Some(match (self.name.as_str(), self.typ) {
+            ("GNU", NT_GNU_ABI_TAG) => "NT_GNU_ABI_TAG",
+            ("GNU", NT_GNU_BUILD_ID) => "NT_GNU_BUILD_ID",
+            ("GNU", NT_GNU_PROPERTY_TYPE_0) => "NT_GNU_PROPERTY_TYPE_0",
+            ("Go", NT_GO_BUILD_ID) => "NT_GO_BUILD_ID",
+            ("FDO", NT_FDO_PACKAGING_METADATA) => "NT_FDO_PACKAGING_METADATA",
+            ("CORE", NT_PRSTATUS) => "NT_PRSTATUS",
+            ("CORE", NT_PRFPREG) => "NT_PRFPREG",
+            ("CORE", NT_PRPSINFO) => "NT_PRPSINFO",
+            ("CORE", NT_AUXV) => "NT_AUXV",
+            ("CORE", NT_SIGINFO) => "NT_SIGINFO",
+            ("CORE", NT_FILE) => "NT_FILE",
+            ("LINUX", NT_X86_XSTATE) => "NT_X86_XSTATE",
+            _ => return None,
+        })
+    }
+
+    // Human readable descriptor for the note types elk knows about. Property and core notes are
+    // laid out by the file's class and machine.
+    pub fn describe(&self, class: Class, machine: Machine) -> Option<String> {
+        match (self.name.as_str(), self.typ) {
+            ("GNU", NT_GNU_BUILD_ID) => {
+                Some(self.desc.iter().map(|b| format!("{:02x}", b)).collect())
+            }
+            ("GNU", NT_GNU_ABI_TAG) => {
+                let os = match u32_at(&self.desc, 0)? {
+                    0 => "Linux".to_string(),
+                    1 => "Hurd".to_string(),
+                    2 => "Solaris".to_string(),
+                    3 => "FreeBSD".to_string(),
+                    other => format!("OS {}", other),
+                };
+                let (major, minor, patch) = (
+                    u32_at(&self.desc, 4)?,
+                    u32_at(&self.desc, 8)?,
+                    u32_at(&self.desc, 12)?,
+                );
+                Some(format!("{} {}.{}.{}", os, major, minor, patch))
+            }
+            ("GNU", NT_GNU_PROPERTY_TYPE_0) => Some(
+                self.gnu_properties(class)
+                    .iter()
+                    .map(|property| property.describe(machine))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
+            ("Go", NT_GO_BUILD_ID) => Some(String::from_utf8_lossy(&self.desc).into_owned()),
+            ("FDO", NT_FDO_PACKAGING_METADATA) => {
+                let end = self
+                    .desc
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(self.desc.len());
+                Some(String::from_utf8_lossy(&self.desc[..end]).into_owned())
+            }
+            ("CORE", NT_PRSTATUS) => {
+                let status = self.prstatus(class, machine)?;
+                let mut out = format!("signal {} in thread {}", status.signal, status.pid);
+                if let Some((name, pc)) = status.pc() {
+                    out += &format!(", {} {:#x}", name, pc);
+                }
+                Some(out)
+            }
+            ("CORE", NT_PRPSINFO) => {
+                let info = self.psinfo(class)?;
+                Some(format!("pid {}: {}", info.pid, info.args))
+            }
+            ("CORE", NT_SIGINFO) => {
+                let info = self.siginfo(class)?;
+                match info.addr {
+                    Some(addr) => Some(format!(
+                        "signal {} (code {}) at {:#x}",
+                        info.signal, info.code, addr
+                    )),
+                    None => Some(format!("signal {} (code {})", info.signal, info.code)),
+                }
+            }
+            ("CORE", NT_FILE) => Some(format!("{} mapped files", self.mapped_files(class).len())),
+            ("CORE", NT_AUXV) => {
+                let entries = self.desc.len() / (2 * word_size(class));
+                Some(format!("{} auxv entries", entries))
+            }
+            _ => None,
+        }
+    }
+
+    fn is(&self, name: &str, typ: u32) -> bool {
+        self.name == name && self.typ == typ
+    }
+
+    // The properties of a NT_GNU_PROPERTY_TYPE_0 note, each padded to the address size
+    pub fn gnu_properties(&self, class: Class) -> Vec<GnuProperty> {
+        let mut properties = Vec::new();
+        if !self.is("GNU", NT_GNU_PROPERTY_TYPE_0) {
+            return properties;
+        }
+        let mut pos = 0;
+        while let (Some(typ), Some(size)) = (u32_at(&self.desc, pos), u32_at(&self.desc, pos + 4)) {
+            let data = match self.desc.get(pos + 8..pos + 8 + size as usize) {
+                Some(data) => data,
+                None => break,
+            };
+            properties.push(GnuProperty {
+                typ,
+                data: data.to_vec(),
+            });
+            let align = word_size(class);
+            pos = (pos + 8 + size as usize).div_ceil(align) * align;
+        }
+        properties
+    }
+
+    // NT_PRSTATUS of the machines delf knows the register layout of
+    pub fn prstatus(&self, class: Class, machine: Machine) -> Option<PrStatus> {
+        if !self.is("CORE", NT_PRSTATUS) {
+            return None;
+        }
+        // Offsets of pr_pid and pr_reg, past the siginfo, signal sets and four timevals
+        let (names, pid_at, regs_at): (&[&'static str], _, _) = match (machine, class) {
+            (Machine::X86_64, Class::Elf64) => (&X86_64_REGISTERS, 32, 112),
+            (Machine::AArch64, Class::Elf64) => (&AARCH64_REGISTERS, 32, 112),
+            (Machine::X86, Class::Elf32) => (&I386_REGISTERS, 24, 72),
+            _ => return None,
+        };
+        let size = word_size(class);
+        let registers = names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| Some((name, word_at(class, &self.desc, regs_at + i * size)?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(PrStatus {
+            signal: u16::from_le_bytes([*self.desc.get(12)?, *self.desc.get(13)?]),
+            pid: u32_at(&self.desc, pid_at)?,
+            ppid: u32_at(&self.desc, pid_at + 4)?,
+            registers,
+        })
+    }
+
+    // NT_PRPSINFO; 32-bit files have a shorter pr_flag and 16-bit uids
+    pub fn psinfo(&self, class: Class) -> Option<PsInfo> {
+        if !self.is("CORE", NT_PRPSINFO) {
+            return None;
+        }
+        let (pid_at, name_at) = match class {
+            Class::Elf32 => (12, 28),
+            Class::Elf64 => (24, 40),
+        };
+        Some(PsInfo {
+            pid: u32_at(&self.desc, pid_at)?,
+            name: fixed_str(&self.desc, name_at..name_at + 16)?,
+            args: fixed_str(&self.desc, name_at + 16..name_at + 96)?,
+        })
+    }
+
+    // NT_SIGINFO: a siginfo_t, whose fault address follows signo, errno and code, aligned to
+    // the address size
+    pub fn siginfo(&self, class: Class) -> Option<SigInfo> {
+        if !self.is("CORE", NT_SIGINFO) {
+            return None;
+        }
+        let signal = u32_at(&self.desc, 0)?;
+        let addr = match signal {
+            4 | 7 | 8 | 11 => Some(word_at(
+                class,
+                &self.desc,
+                12usize.next_multiple_of(word_size(class)),
+            )?),
+            _ => None,
+        };
+        Some(SigInfo {
+            signal,
+            code: u32_at(&self.desc, 8)? as i32,
+            addr,
+        })
+    }
+
+    // NT_FILE: a count and page size, the (start, end, page offset) of each mapping, then their
+    // paths
+    pub fn mapped_files(&self, class: Class) -> Vec<MappedFile> {
+        if !self.is("CORE", NT_FILE) {
+            return Vec::new();
+        }
+        let size = word_size(class);
+        let word = |i: usize| word_at(class, &self.desc, i * size);
+        let (count, page_size) = match (word(0), word(1)) {
+            (Some(count), Some(page_size)) => (count as usize, page_size),
+            _ => return Vec::new(),
+        };
+        let names_at = (2 + 3 * count) * size;
+        let mut paths = match self.desc.get(names_at..) {
+            Some(names) => names.split(|&b| b == 0),
+            None => return Vec::new(),
+        };
+        (0..count)
+            .map_while(|i| {
+                let entry = 2 + 3 * i;
+                Some(MappedFile {
+                    start: word(entry)?,
+                    end: word(entry + 1)?,
+                    offset: word(entry + 2)?.wrapping_mul(page_size),
+                    path: String::from_utf8_lossy(paths.next()?).into_owned(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl PrStatus {
+    pub fn register(&self, name: &str) -> Option<u64> {
+        self.registers
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, value)| value)
+    }
+
+    // The program counter, under the name the machine gives it
+    pub fn pc(&self) -> Option<(&'static str, u64)> {
+        self.registers
+            .iter()
+            .find(|(name, _)| ["rip", "eip", "pc"].contains(name))
+            .copied()
+    }
+}
+
+impl GnuProperty {
+    fn bits(&self) -> u32 {
+        u32_at(&self.data, 0).unwrap_or(0)
+    }
+
+    pub fn describe(&self, machine: Machine) -> String {
+        let flags = |label: &str, names: &[&str]| {
+            let bits = self.bits();
+            let set: Vec<String> = (0..32)
+                .filter(|bit| bits & (1 << bit) != 0)
+                .map(|bit| match names.get(bit) {
+                    Some(name) => name.to_string(),
+                    None => format!("bit {}", bit),
+                })
+                .collect();
+            format!("{}: {}", label, set.join(", "))
+        };
+        let x86 = matches!(machine, Machine::X86 | Machine::X86_64);
+        let isa = ["x86-64-baseline", "x86-64-v2", "x86-64-v3", "x86-64-v4"];
+        match self.typ {
+            GNU_PROPERTY_STACK_SIZE => {
+                let size = u64_at(&self.data, 0).unwrap_or_else(|| self.bits().into());
+                format!("stack size {:#x}", size)
+            }
+            GNU_PROPERTY_NO_COPY_ON_PROTECTED => "no copy relocations on protected".to_string(),
+            GNU_PROPERTY_X86_FEATURE_1_AND if x86 => flags("x86 feature", &["IBT", "SHSTK"]),
+            GNU_PROPERTY_X86_ISA_1_NEEDED if x86 => flags("x86 ISA needed", &isa),
+            GNU_PROPERTY_X86_ISA_1_USED if x86 => flags("x86 ISA used", &isa),
+            GNU_PROPERTY_AARCH64_FEATURE_1_AND if machine == Machine::AArch64 => {
+                flags("AArch64 feature", &["BTI", "PAC", "GCS"])
+            }
+            typ => format!("property {:#x}", typ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(name: &str, typ: u32, desc: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(&(name.len() as u32 + 1).to_le_bytes());
+        out.extend(&(desc.len() as u32).to_le_bytes());
+        out.extend(&typ.to_le_bytes());
+        out.extend(name.as_bytes());
+        out.push(0);
+        out.resize(align4(out.len()), 0);
+        out.extend(desc);
+        out.resize(align4(out.len()), 0);
+        out
+    }
+
+    #[test]
+    fn build_ids_and_abi_tags() {
+        let abi: Vec<u8> = [0u32, 3, 2, 0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes().to_vec())
+            .collect();
+        let mut data = [
+            note("GNU", NT_GNU_ABI_TAG, &abi),
+            note("GNU", NT_GNU_BUILD_ID, &[0xde, 0xad, 0xbe, 0xef, 0x01]),
+            note("Go", NT_GO_BUILD_ID, b"abc/def"),
+            note("FDO", NT_FDO_PACKAGING_METADATA, b"{\"type\":\"rpm\"}\0\0"),
+        ]
+        .concat();
+        // A truncated trailing entry is ignored
+        data.extend(&[8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, b'x']);
+
+        let notes = parse_notes(&data);
+        assert_eq!(notes.len(), 4);
+        assert_eq!(
+            notes[0].describe(Class::Elf64, Machine::X86_64).unwrap(),
+            "Linux 3.2.0"
+        );
+        assert_eq!(
+            notes[1].describe(Class::Elf64, Machine::X86_64).unwrap(),
+            "deadbeef01"
+        );
+        assert_eq!(notes[2].name, "Go");
+        assert_eq!(
+            notes[2].describe(Class::Elf64, Machine::X86_64).unwrap(),
+            "abc/def"
+        );
+        assert_eq!(
+            notes[3].describe(Class::Elf64, Machine::X86_64).unwrap(),
+            "{\"type\":\"rpm\"}"
+        );
+    }
+
+    #[test]
+    fn properties_and_core_notes() {
+        let words =
+            |ws: &[u64]| -> Vec<u8> { ws.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect() };
+        // IBT and SHSTK, then x86-64-v2 and v3, each padded to 8 bytes
+        let mut properties = [0xc000_0002u32, 4, 3, 0, 0xc000_8002, 4, 6, 0]
+            .iter()
+            .flat_map(|w| w.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        properties.extend(&[0xff; 4]);
+        let data = note("GNU", NT_GNU_PROPERTY_TYPE_0, &properties);
+        let notes = parse_notes(&data);
+        assert_eq!(
+            notes[0].describe(Class::Elf64, Machine::X86_64).unwrap(),
+            "x86 feature: IBT, SHSTK; x86 ISA needed: x86-64-v2, x86-64-v3"
+        );
+
+        let mut prstatus = vec![0u8; 112];
+        prstatus[12] = 11;
+        prstatus[32..36].copy_from_slice(&42u32.to_le_bytes());
+        prstatus.extend(words(&(0..27).collect::<Vec<_>>()));
+        let mut psinfo = vec![0u8; 40];
+        psinfo[24..28].copy_from_slice(&42u32.to_le_bytes());
+        psinfo.extend(b"sleep\0\0\0\0\0\0\0\0\0\0\0sleep 10 ");
+        psinfo.resize(136, 0);
+        let mut files = words(&[2, 0x1000, 0x400000, 0x401000, 0, 0x401000, 0x402000, 1]);
+        files.extend(b"/bin/sleep\0/bin/sleep\0");
+        // SIGSEGV, SEGV_MAPERR at 0xdead
+        let mut siginfo = words(&[11, 1, 0xdead]);
+        siginfo.resize(128, 0);
+        let data = [
+            note("CORE", NT_PRSTATUS, &prstatus),
+            note("CORE", NT_PRPSINFO, &psinfo),
+            note("CORE", NT_FILE, &files),
+            note("CORE", NT_SIGINFO, &siginfo),
+        ]
+        .concat();
+        let notes = parse_notes(&data);
+        let status = notes[0].prstatus(Class::Elf64, Machine::X86_64).unwrap();
+        assert_eq!((status.signal, status.pid), (11, 42));
+        assert_eq!(status.registers[16], ("rip", 16));
+        assert_eq!(
+            notes[0].describe(Class::Elf64, Machine::X86_64).unwrap(),
+            "signal 11 in thread 42, rip 0x10"
+        );
+        assert_eq!(notes[1].prstatus(Class::Elf64, Machine::X86_64), None);
+        let info = notes[1].psinfo(Class::Elf64).unwrap();
+        assert_eq!(
+            (info.name.as_str(), info.args.as_str()),
+            ("sleep", "sleep 10")
+        );
+        let files = notes[2].mapped_files(Class::Elf64);
+        assert_eq!(files.len(), 2);
+        assert_eq!((files[1].start, files[1].offset), (0x401000, 0x1000));
+        assert_eq!(files[1].path, "/bin/sleep");
+        let info = notes[3].siginfo(Class::Elf64).unwrap();
+        assert_eq!((info.signal, info.code, info.addr), (11, 1, Some(0xdead)));
+    }
+}
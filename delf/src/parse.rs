@@ -1,3 +1,20 @@
+use core::ops::Range;
+use nom::{
+    combinator::map,
+    error::{ErrorKind, VerboseError, VerboseErrorKind},
+    number::{complete, Endianness},
+    Offset,
+};
+
+use crate::{
+    cancel::CancellationToken,
+    detect::{Class, Endian},
+    prelude::*,
+};
+
+#[cfg(feature = "std")]
+use carpenter::*;
+
 pub type Input<'a> = &'a [u8];
 pub type Result<'a, O> = nom::IResult<Input<'a>, O, nom::error::VerboseError<Input<'a>>>;
 
@@ -28,3 +45,227 @@ macro_rules! impl_parse_for_bitflags {
         }
     };
 }
+
+// An enum of the values of a field elk has names for, plus Other for the rest, so files using
+// values from newer specs or other OSes still parse. Comes with From conversions both ways.
+#[macro_export]
+macro_rules! open_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $type:ident: $repr:ty, $number_parser:ident {
+            $($variant:ident = $value:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub enum $type {
+            $($variant,)*
+            Other($repr),
+        }
+
+        impl From<$repr> for $type {
+            fn from(value: $repr) -> Self {
+                match value {
+                    $($value => Self::$variant,)*
+                    other => Self::Other(other),
+                }
+            }
+        }
+
+        impl From<$type> for $repr {
+            fn from(value: $type) -> Self {
+                match value {
+                    $($type::$variant => $value,)*
+                    $type::Other(other) => other,
+                }
+            }
+        }
+
+        impl $type {
+            pub fn parse(input: parse::Input) -> parse::Result<Self> {
+                context(stringify!($type), map($number_parser, Self::from))(input)
+            }
+        }
+    };
+}
+
+impl From<Endian> for Endianness {
+    fn from(endian: Endian) -> Self {
+        match endian {
+            Endian::Little => Endianness::Little,
+            Endian::Big => Endianness::Big,
+        }
+    }
+}
+
+// Fields in the file's byte order, as EI_DATA gives it
+pub fn u16<'a>(endian: Endian) -> impl Fn(Input<'a>) -> Result<'a, u16> {
+    complete::u16(endian.into())
+}
+
+pub fn u32<'a>(endian: Endian) -> impl Fn(Input<'a>) -> Result<'a, u32> {
+    complete::u32(endian.into())
+}
+
+pub fn u64<'a>(endian: Endian) -> impl Fn(Input<'a>) -> Result<'a, u64> {
+    complete::u64(endian.into())
+}
+
+pub fn i32<'a>(endian: Endian) -> impl Fn(Input<'a>) -> Result<'a, i32> {
+    complete::i32(endian.into())
+}
+
+pub fn i64<'a>(endian: Endian) -> impl Fn(Input<'a>) -> Result<'a, i64> {
+    complete::i64(endian.into())
+}
+
+// An address-sized field: 4 bytes in ELF32, 8 in ELF64
+pub fn word<'a>(class: Class, endian: Endian) -> impl Fn(Input<'a>) -> Result<'a, u64> {
+    move |input| match class {
+        Class::Elf32 => map(u32(endian), u64::from)(input),
+        Class::Elf64 => u64(endian)(input),
+    }
+}
+
+// `size` bytes at `offset` as indices into a `len` byte input, if they fit in it
+pub fn within(offset: u64, size: u64, len: usize) -> Option<Range<usize>> {
+    let end = offset.checked_add(size)?;
+    (end <= len as u64).then_some(offset as usize..end as usize)
+}
+
+// The part of `size` bytes at `offset` that lies in a `len` byte input
+pub fn clamped(offset: u64, size: u64, len: usize) -> Range<usize> {
+    let len = len as u64;
+    offset.min(len) as usize..offset.saturating_add(size).min(len) as usize
+}
+
+// Aborts parsing with `field` as the context, so the error points at `input` rather than
+// wherever a later slice would have panicked
+pub fn invalid<'a, O>(input: Input<'a>, field: &'static str) -> Result<'a, O> {
+    Err(nom::Err::Failure(VerboseError {
+        errors: vec![(input, VerboseErrorKind::Context(field))],
+    }))
+}
+
+// Fails with ErrorKind::TooLarge, which parse_checked turns into ParseError::Footprint. nom
+// itself only uses that kind in its bit parsers, which delf doesn't.
+pub fn too_large<'a, O>(input: Input<'a>) -> Result<'a, O> {
+    Err(nom::Err::Failure(VerboseError {
+        errors: vec![(input, VerboseErrorKind::Nom(ErrorKind::TooLarge))],
+    }))
+}
+
+pub fn is_too_large(e: &VerboseError<Input>) -> bool {
+    e.errors
+        .iter()
+        .any(|(_, kind)| *kind == VerboseErrorKind::Nom(ErrorKind::TooLarge))
+}
+
+// How much FileHeader::parse_with lets through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    // Refuses anything unusual, values elk has no name for included
+    Strict,
+    // Takes unknown values as they come, refuses broken structure
+    #[default]
+    Permissive,
+    // Guesses its way past broken structure too, so as much as possible of a damaged or hostile
+    // file can be looked at
+    Forensic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParseOptions {
+    pub strictness: Strictness,
+    // Checked between headers; once cancelled, parsing fails with ParseError::Cancelled
+    pub cancel: CancellationToken,
+    // Most bytes the parse results may take, as FileHeader::footprint counts them. Checked
+    // between headers too, so a file claiming millions of them fails with ParseError::Footprint
+    // before they are all in memory.
+    pub max_footprint: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub enum Severity {
+    // Allowed, or at least harmless, but not what elk knows or expects
+    Unusual,
+    // Can't be taken at face value, like contents past the end of the file
+    Broken,
+}
+
+// Something parsing noticed and let through. `offset` is where in the file the header or field
+// holding it starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub struct Anomaly {
+    #[cfg_attr(feature = "std", fmt("{:#x}"))]
+    pub offset: usize,
+    pub field: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Strictness {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "strict" => Some(Self::Strict),
+            "permissive" => Some(Self::Permissive),
+            "forensic" => Some(Self::Forensic),
+            _ => None,
+        }
+    }
+
+    pub fn tolerates(self, severity: Severity) -> bool {
+        match (self, severity) {
+            (Self::Strict, _) => false,
+            (Self::Permissive, severity) => severity == Severity::Unusual,
+            (Self::Forensic, _) => true,
+        }
+    }
+}
+
+// Anomalies found so far in `full`, threaded through the header parsers
+pub struct Anomalies<'a> {
+    full: Input<'a>,
+    pub strictness: Strictness,
+    pub found: Vec<Anomaly>,
+}
+
+impl<'a> Anomalies<'a> {
+    pub fn new(full: Input<'a>, options: &ParseOptions) -> Self {
+        Self {
+            full,
+            strictness: options.strictness,
+            found: Vec::new(),
+        }
+    }
+
+    // Records an anomaly in `field`, which starts at `at`, or fails there like `invalid` when
+    // the strictness doesn't let `severity` through
+    pub fn note(
+        &mut self,
+        at: Input<'a>,
+        field: &'static str,
+        severity: Severity,
+        message: impl Into<String>,
+    ) -> Result<'a, ()> {
+        if !self.strictness.tolerates(severity) {
+            return invalid(at, field);
+        }
+        self.found.push(Anomaly {
+            offset: self.full.offset(at),
+            field,
+            severity,
+            message: message.into(),
+        });
+        Ok((at, ()))
+    }
+
+    // Says which header the anomalies noted since `since` are about: "segment 3: ..."
+    pub fn attribute(&mut self, since: usize, subject: &str, index: usize) {
+        for anomaly in &mut self.found[since..] {
+            anomaly.message = format!("{} {}: {}", subject, index, anomaly.message);
+        }
+    }
+}
@@ -0,0 +1,565 @@
+use core::{fmt, ops::Range};
+
+use crate::{
+    detect::{Class, Endian},
+    prelude::*,
+    types::*,
+    FileHeader,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub range: Range<usize>,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Default, Debug)]
+pub struct PatchPlan {
+    patches: Vec<Patch>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    Overlap(Range<usize>, Range<usize>),
+    OutOfBounds(Range<usize>, usize),
+    NotFileBacked(u64, usize),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overlap(a, b) => write!(f, "Patch {:#x?} overlaps patch {:#x?}", a, b),
+            Self::OutOfBounds(a, b) => {
+                write!(f, "Patch {:#x?} lies outside the {:#x} byte input", a, b)
+            }
+            Self::NotFileBacked(a, b) => write!(
+                f,
+                "{:#x} bytes at {:#x} aren't all loaded from the file by one LOAD segment",
+                b, a
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PatchError {}
+
+impl PatchPlan {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn patches(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    // Replace `range` of the original with `bytes`, which may have a different length
+    pub fn replace(&mut self, range: Range<usize>, bytes: &[u8]) -> Result<(), PatchError> {
+        let conflicting = |p: &&Patch| {
+            (p.range.start < range.end && range.start < p.range.end)
+                || (p.range.start == range.start && (p.range.is_empty() || range.is_empty()))
+        };
+        if let Some(p) = self.patches.iter().find(conflicting) {
+            return Err(PatchError::Overlap(range, p.range.clone()));
+        }
+        let pos = self
+            .patches
+            .iter()
+            .position(|p| p.range.start > range.start)
+            .unwrap_or(self.patches.len());
+        self.patches.insert(
+            pos,
+            Patch {
+                range,
+                bytes: bytes.to_vec(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), PatchError> {
+        self.replace(offset..offset + bytes.len(), bytes)
+    }
+
+    pub fn insert(&mut self, offset: usize, bytes: &[u8]) -> Result<(), PatchError> {
+        self.replace(offset..offset, bytes)
+    }
+
+    // Every byte outside the patched ranges is copied verbatim from `original`
+    pub fn apply(&self, original: &[u8]) -> Result<Vec<u8>, PatchError> {
+        if let Some(p) = self.patches.iter().find(|p| p.range.end > original.len()) {
+            return Err(PatchError::OutOfBounds(p.range.clone(), original.len()));
+        }
+
+        let grown: usize = self.patches.iter().map(|p| p.bytes.len()).sum();
+        let mut out = Vec::with_capacity(original.len() + grown);
+        let mut cursor = 0;
+        for p in &self.patches {
+            out.extend_from_slice(&original[cursor..p.range.start]);
+            out.extend_from_slice(&p.bytes);
+            cursor = p.range.end;
+        }
+        out.extend_from_slice(&original[cursor..]);
+        Ok(out)
+    }
+}
+
+// A change to the file's .symtab
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolEdit {
+    // A symbol at `value`, in whichever section holds that address or absolute if none does
+    Add {
+        name: String,
+        value: u64,
+        size: u64,
+        bind: SymBind,
+        typ: SymType,
+    },
+    // Every .symtab symbol called `from`
+    Rename {
+        from: String,
+        to: String,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SymbolEditError {
+    NoSectionHeaders,
+    NoSuchSymbol(String),
+    Renumbering,
+    ExtendedIndices,
+    BigEndian,
+    NoSectionNames,
+    Patch(PatchError),
+}
+
+impl fmt::Display for SymbolEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSectionHeaders => {
+                write!(f, "File has no section headers to hold a symbol table")
+            }
+            Self::NoSuchSymbol(a) => write!(f, "No symbol called {:?} in .symtab", a),
+            Self::Renumbering => write!(
+                f,
+                "Adding local symbols would renumber the global ones relocations refer to"
+            ),
+            Self::ExtendedIndices => write!(
+                f,
+                "Symbol table has SHT_SYMTAB_SHNDX entries, which adding symbols would misalign"
+            ),
+            Self::BigEndian => write!(f, "Symbol tables are only written little-endian"),
+            Self::NoSectionNames => write!(
+                f,
+                "File has no section name string table to name a new .symtab in"
+            ),
+            Self::Patch(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SymbolEditError {}
+
+impl From<PatchError> for SymbolEditError {
+    fn from(e: PatchError) -> Self {
+        Self::Patch(e)
+    }
+}
+
+fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+// A class-sized word: e_shoff, sh_offset, sh_size and the like
+fn word(class: Class, value: u64) -> Vec<u8> {
+    match class {
+        Class::Elf32 => (value as u32).to_le_bytes().to_vec(),
+        Class::Elf64 => value.to_le_bytes().to_vec(),
+    }
+}
+
+fn align8(value: usize) -> usize {
+    (value + 7) & !7
+}
+
+fn encode_symbol(class: Class, name: u32, info: u8, shndx: u16, value: u64, size: u64) -> Vec<u8> {
+    let mut out = name.to_le_bytes().to_vec();
+    match class {
+        Class::Elf32 => {
+            out.extend(&(value as u32).to_le_bytes());
+            out.extend(&(size as u32).to_le_bytes());
+            out.extend(&[info, 0]);
+            out.extend(&shndx.to_le_bytes());
+        }
+        Class::Elf64 => {
+            out.extend(&[info, 0]);
+            out.extend(&shndx.to_le_bytes());
+            out.extend(&value.to_le_bytes());
+            out.extend(&size.to_le_bytes());
+        }
+    }
+    out
+}
+
+// Rewrites the fields of a raw section header that edit_symbols changes. Field offsets are
+// those of Elf32_Shdr and Elf64_Shdr.
+struct ShdrFields {
+    offset: usize,
+    size: usize,
+    link: usize,
+    info: usize,
+}
+
+impl ShdrFields {
+    fn of(class: Class) -> Self {
+        match class {
+            Class::Elf32 => Self {
+                offset: 16,
+                size: 20,
+                link: 24,
+                info: 28,
+            },
+            Class::Elf64 => Self {
+                offset: 24,
+                size: 32,
+                link: 40,
+                info: 44,
+            },
+        }
+    }
+}
+
+// A new section header of `typ`, with its data at `offset`
+fn encode_section(
+    class: Class,
+    name: u32,
+    typ: u32,
+    offset: u64,
+    size: u64,
+    entsize: u64,
+) -> Vec<u8> {
+    let align = match typ {
+        2 => 8,
+        _ => 1,
+    };
+    let mut out = name.to_le_bytes().to_vec();
+    out.extend(&typ.to_le_bytes());
+    for value in [0, 0, offset, size] {
+        out.extend(word(class, value));
+    }
+    out.extend(&[0; 8]);
+    out.extend(word(class, align));
+    out.extend(word(class, entsize));
+    out
+}
+
+impl FileHeader {
+    // A plan that overwrites the file bytes the loader maps at `addr` with `bytes`. They have to
+    // come from one LOAD segment's file bytes: past them is zero-filled memory with nothing in
+    // the file to patch.
+    pub fn patch_at(&self, addr: Addr, bytes: &[u8]) -> Result<PatchPlan, PatchError> {
+        let not_backed = || PatchError::NotFileBacked(addr.0, bytes.len());
+        let offset = self.vaddr_to_offset(addr).ok_or_else(not_backed)?;
+        if self.bytes_at(addr).ok_or_else(not_backed)?.len() < bytes.len() {
+            return Err(not_backed());
+        }
+        let mut plan = PatchPlan::new();
+        plan.write(offset.into(), bytes)?;
+        Ok(plan)
+    }
+
+    // A plan that rewrites `original`, the bytes this header was parsed from, with `edits` made
+    // to its .symtab. The new symbol and string tables and a copy of the section header table go
+    // at the end of the file; the old ones are left in place, unreferenced. A stripped file gets
+    // a new .symtab and .strtab.
+    pub fn edit_symbols(
+        &self,
+        original: &[u8],
+        edits: &[SymbolEdit],
+    ) -> Result<PatchPlan, SymbolEditError> {
+        let class = self.class;
+        if self.section_headers.is_empty() {
+            return Err(SymbolEditError::NoSectionHeaders);
+        }
+        if self.endian == Endian::Big {
+            return Err(SymbolEditError::BigEndian);
+        }
+        let symtab_idx = self
+            .section_headers
+            .iter()
+            .position(|sh| sh.typ == SectionType::SymTab);
+        let entsize = Symbol::size(class);
+        let (mut entries, mut strtab, locals) = match symtab_idx {
+            Some(idx) => {
+                let symtab = &self.section_headers[idx];
+                if self
+                    .section_headers
+                    .iter()
+                    .any(|sh| sh.typ == SectionType::SymTabShndx && sh.link as usize == idx)
+                    && edits.iter().any(|e| matches!(e, SymbolEdit::Add { .. }))
+                {
+                    return Err(SymbolEditError::ExtendedIndices);
+                }
+                let size = (symtab.entsize.0 as usize).max(entsize);
+                let entries: Vec<Vec<u8>> =
+                    symtab.data.chunks_exact(size).map(|e| e.to_vec()).collect();
+                let strtab = self
+                    .section_headers
+                    .get(symtab.link as usize)
+                    .map_or_else(|| vec![0], |sh| sh.data.to_vec());
+                (entries, strtab, symtab.info as usize)
+            }
+            None => (vec![vec![0; entsize]], vec![0], 1),
+        };
+        let names: Vec<String> = match symtab_idx {
+            Some(idx) => self
+                .symbols_in(idx)
+                .into_iter()
+                .map(|sym| sym.name)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut add_name = |name: &str| {
+            let offset = strtab.len() as u32;
+            strtab.extend(name.as_bytes());
+            strtab.push(0);
+            offset
+        };
+        let (mut new_locals, mut new_globals) = (Vec::new(), Vec::new());
+        for edit in edits {
+            match edit {
+                SymbolEdit::Rename { from, to } => {
+                    let renamed: Vec<usize> =
+                        (1..names.len()).filter(|&i| names[i] == *from).collect();
+                    if renamed.is_empty() {
+                        return Err(SymbolEditError::NoSuchSymbol(from.clone()));
+                    }
+                    let name = add_name(to);
+                    for i in renamed {
+                        put(&mut entries[i], 0, &name.to_le_bytes());
+                    }
+                }
+                SymbolEdit::Add {
+                    name,
+                    value,
+                    size,
+                    bind,
+                    typ,
+                } => {
+                    let shndx = self
+                        .section_headers
+                        .iter()
+                        .position(|sh| {
+                            sh.flags.contains(SectionFlags::Alloc)
+                                && (sh.addr.0..sh.addr.0 + sh.size.0).contains(value)
+                        })
+                        .map_or(SectionHeader::SHN_ABS, |idx| idx as u16);
+                    let info = Symbol::info(*bind, *typ);
+                    let entry = encode_symbol(class, add_name(name), info, shndx, *value, *size);
+                    match bind {
+                        SymBind::Local => new_locals.push(entry),
+                        _ => new_globals.push(entry),
+                    }
+                }
+            }
+        }
+        if !new_locals.is_empty() && self.typ == Type::Rel && entries.len() > locals {
+            return Err(SymbolEditError::Renumbering);
+        }
+        let info = locals + new_locals.len();
+        let globals = entries.split_off(locals.min(entries.len()));
+        let symtab: Vec<u8> = entries
+            .into_iter()
+            .chain(new_locals)
+            .chain(globals)
+            .chain(new_globals)
+            .flatten()
+            .collect();
+
+        // The raw section header table, to be patched and copied past the new tables
+        let shentsize = self.section_header_info.size;
+        let shoff = self.section_header_info.offset.0 as usize;
+        let mut headers: Vec<Vec<u8>> = (0..self.section_headers.len())
+            .map(|i| original[shoff + i * shentsize..shoff + (i + 1) * shentsize].to_vec())
+            .collect();
+        let fields = ShdrFields::of(class);
+        let mut blob = vec![0; align8(original.len()) - original.len()];
+        let place = |blob: &mut Vec<u8>, bytes: &[u8]| {
+            let offset = original.len() + blob.len();
+            blob.extend(bytes);
+            blob.resize(align8(blob.len() + original.len()) - original.len(), 0);
+            offset as u64
+        };
+        let symtab_offset = place(&mut blob, &symtab);
+        let strtab_offset = place(&mut blob, &strtab);
+        match symtab_idx {
+            Some(idx) => {
+                let header = &mut headers[idx];
+                put(header, fields.offset, &word(class, symtab_offset));
+                put(header, fields.size, &word(class, symtab.len() as u64));
+                put(header, fields.info, &(info as u32).to_le_bytes());
+                let link = self.section_headers[idx].link as usize;
+                if let Some(header) = headers.get_mut(link).filter(|_| link != 0) {
+                    put(header, fields.offset, &word(class, strtab_offset));
+                    put(header, fields.size, &word(class, strtab.len() as u64));
+                }
+            }
+            None => {
+                // e_shstrndx, or section 0's sh_link when it is SHN_XINDEX
+                let shstrndx = match class {
+                    Class::Elf32 => u16::from_le_bytes([original[0x32], original[0x33]]),
+                    Class::Elf64 => u16::from_le_bytes([original[0x3e], original[0x3f]]),
+                };
+                let shstrndx = match shstrndx {
+                    SectionHeader::SHN_XINDEX => self.section_headers[0].link as usize,
+                    index => index as usize,
+                };
+                // SHN_UNDEF: there is nowhere to put the new sections' names
+                let names = match shstrndx {
+                    0 => None,
+                    index => self.section_headers.get(index),
+                };
+                let mut shstrtab = names.ok_or(SymbolEditError::NoSectionNames)?.data.to_vec();
+                let symtab_name = shstrtab.len() as u32;
+                shstrtab.extend(b".symtab\0.strtab\0");
+                let shstrtab_offset = place(&mut blob, &shstrtab);
+                let header = &mut headers[shstrndx];
+                put(header, fields.offset, &word(class, shstrtab_offset));
+                put(header, fields.size, &word(class, shstrtab.len() as u64));
+
+                let mut new = encode_section(
+                    class,
+                    symtab_name,
+                    2,
+                    symtab_offset,
+                    symtab.len() as u64,
+                    entsize as u64,
+                );
+                put(
+                    &mut new,
+                    fields.link,
+                    &(headers.len() as u32 + 1).to_le_bytes(),
+                );
+                put(&mut new, fields.info, &(info as u32).to_le_bytes());
+                headers.push(new);
+                headers.push(encode_section(
+                    class,
+                    symtab_name + 8,
+                    3,
+                    strtab_offset,
+                    strtab.len() as u64,
+                    0,
+                ));
+            }
+        }
+        for header in headers.iter_mut() {
+            header.resize(shentsize, 0);
+        }
+
+        // e_shnum, with counts past SHN_LORESERVE moved to section 0's sh_size
+        let count = headers.len();
+        let shnum = match count < SectionHeader::SHN_LORESERVE as usize {
+            true => count as u16,
+            false => {
+                put(&mut headers[0], fields.size, &word(class, count as u64));
+                0
+            }
+        };
+        let new_shoff = original.len() + blob.len();
+        blob.extend(headers.concat());
+
+        let (shoff_at, shnum_at) = match class {
+            Class::Elf32 => (0x20, 0x30),
+            Class::Elf64 => (0x28, 0x3c),
+        };
+        let mut plan = PatchPlan::new();
+        plan.write(shoff_at, &word(class, new_shoff as u64))?;
+        plan.write(shnum_at, &shnum.to_le_bytes())?;
+        plan.insert(original.len(), &blob)?;
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PatchError, PatchPlan};
+    use crate::{
+        data::Data,
+        detect::Class,
+        types::*,
+        write::{FileBuilder, SegmentBuilder, SegmentData},
+        FileHeader,
+    };
+
+    #[test]
+    fn untouched_bytes_are_preserved() {
+        let original: Vec<u8> = (0..=255).collect();
+        let mut plan = PatchPlan::new();
+        plan.write(0x80, &[0xaa, 0xbb]).unwrap();
+        plan.write(0x10, &[0xcc]).unwrap();
+        plan.insert(original.len(), b"tail").unwrap();
+
+        let out = plan.apply(&original).unwrap();
+        assert_eq!(out.len(), 256 + 4);
+        assert_eq!(&out[..0x10], &original[..0x10]);
+        assert_eq!(out[0x10], 0xcc);
+        assert_eq!(&out[0x11..0x80], &original[0x11..0x80]);
+        assert_eq!(&out[0x80..0x82], &[0xaa, 0xbb]);
+        assert_eq!(&out[0x82..256], &original[0x82..]);
+        assert_eq!(&out[256..], b"tail");
+    }
+
+    #[test]
+    fn conflicts_are_rejected() {
+        let mut plan = PatchPlan::new();
+        plan.write(4, &[0; 4]).unwrap();
+        assert_eq!(
+            plan.write(6, &[0; 4]),
+            Err(PatchError::Overlap(6..10, 4..8))
+        );
+        assert_eq!(plan.insert(4, &[1]), Err(PatchError::Overlap(4..4, 4..8)));
+        plan.write(8, &[0; 4]).unwrap();
+        assert_eq!(
+            plan.apply(&[0; 10]),
+            Err(PatchError::OutOfBounds(8..12, 10))
+        );
+    }
+
+    #[test]
+    fn patches_at_an_address() {
+        let mut image = FileBuilder::new(Class::Elf64, Type::Exec, Machine::X86_64);
+        image.segments = vec![SegmentBuilder {
+            typ: SegmentType::Load,
+            flags: (SegmentFlags::Read | SegmentFlags::Write).into(),
+            align: 0x1000,
+            data: SegmentData::Bytes {
+                vaddr: Addr(0x400000),
+                data: vec![0; 0x100],
+                mem_size: 0x200,
+                headers: true,
+            },
+        }];
+        let original = image.to_bytes().unwrap();
+        let file = FileHeader::parse_or_describe(&Data::new(original.clone())).unwrap();
+
+        let patched = file
+            .patch_at(Addr(0x4000f0), b"delf")
+            .unwrap()
+            .apply(&original)
+            .unwrap();
+        assert_eq!(&patched[0xf0..0xf4], b"delf");
+        assert_eq!(patched.len(), original.len());
+        // Runs into the zero-filled part of the segment
+        assert_eq!(
+            file.patch_at(Addr(0x4000fe), b"delf").unwrap_err(),
+            PatchError::NotFileBacked(0x4000fe, 4)
+        );
+        assert!(file.patch_at(Addr(0x500000), b"delf").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use core::fmt;
+
+use crate::types::RelType;
+
+// The terms relocation formulas are written in, named as in the x86-64 psABI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    // Value of the symbol the relocation refers to
+    S,
+    // The addend
+    A,
+    // Place: address of the slot being relocated
+    P,
+    // Base the object was loaded at
+    B,
+    // Address of the global offset table
+    Got,
+    // Address of the symbol's PLT entry
+    L,
+    // ID of the module defining a thread-local symbol, its index in the DTV
+    Module,
+    // Offset of that module's TLS block from the thread pointer, negative on x86-64
+    Tls,
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Term::Got => "GOT",
+            Term::Tls => "TLS",
+            term => return fmt::Debug::fmt(term, f),
+        };
+        f.write_str(name)
+    }
+}
+
+// Values for the terms of one relocation. Terms a formula doesn't use can be left at zero.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Terms {
+    pub s: u64,
+    pub a: i64,
+    pub p: u64,
+    pub b: u64,
+    pub got: u64,
+    pub l: u64,
+    pub module: u64,
+    pub tls: i64,
+}
+
+impl Terms {
+    fn get(&self, term: Term) -> u64 {
+        match term {
+            Term::S => self.s,
+            Term::A => self.a as u64,
+            Term::P => self.p,
+            Term::B => self.b,
+            Term::Got => self.got,
+            Term::L => self.l,
+            Term::Module => self.module,
+            Term::Tls => self.tls as u64,
+        }
+    }
+}
+
+// A sum of terms, each added or subtracted: `S + A - P` is
+// `[(Sign::Plus, Term::S), (Sign::Plus, Term::A), (Sign::Minus, Term::P)]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Formula(pub &'static [(Sign, Term)]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sign {
+    Plus,
+    Minus,
+}
+
+impl Formula {
+    pub fn uses(&self, term: Term) -> bool {
+        self.0.iter().any(|&(_, t)| t == term)
+    }
+
+    // Wraps like the 64-bit arithmetic the psABI defines the formulas in
+    pub fn eval(&self, terms: &Terms) -> u64 {
+        self.0.iter().fold(0u64, |acc, &(sign, term)| match sign {
+            Sign::Plus => acc.wrapping_add(terms.get(term)),
+            Sign::Minus => acc.wrapping_sub(terms.get(term)),
+        })
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, &(sign, term)) in self.0.iter().enumerate() {
+            match (i, sign) {
+                (0, Sign::Plus) => {}
+                (0, Sign::Minus) => f.write_str("-")?,
+                (_, Sign::Plus) => f.write_str(" + ")?,
+                (_, Sign::Minus) => f.write_str(" - ")?,
+            }
+            write!(f, "{}", term)?;
+        }
+        Ok(())
+    }
+}
+
+impl RelType {
+    // What gets written to the slot, from the psABI's relocation table. A new type only needs
+    // its line here, unless like Copy and IRelative it does more than write the value. For the
+    // TLS types, S is the symbol's offset in its module's TLS block rather than an address.
+    pub fn formula(self) -> Formula {
+        use {Sign::*, Term::*};
+        Formula(match self {
+            RelType::Abs64 => &[(Plus, S), (Plus, A)],
+            // Copies the symbol's bytes into the slot rather than writing a value
+            RelType::Copy => &[],
+            RelType::GlobalData => &[(Plus, S)],
+            RelType::JumpSlot => &[(Plus, S)],
+            RelType::Relative => &[(Plus, B), (Plus, A)],
+            RelType::DtpMod64 => &[(Plus, Module)],
+            RelType::DtpOff64 => &[(Plus, S), (Plus, A)],
+            RelType::TpOff64 => &[(Plus, S), (Plus, A), (Plus, Tls)],
+            // The resolver's address; what it returns goes in the slot
+            RelType::IRelative => &[(Plus, B), (Plus, A)],
+            // Nothing known to write
+            RelType::Unknown(_) => &[],
+        })
+    }
+
+    // Types whose symbol is a thread-local, resolved to a module and an offset in its block
+    pub fn is_tls(self) -> bool {
+        matches!(
+            self,
+            RelType::DtpMod64 | RelType::DtpOff64 | RelType::TpOff64
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sign::*, Term::*, *};
+
+    #[test]
+    fn formulas() {
+        let terms = Terms {
+            s: 0x1000,
+            a: -0x8,
+            p: 0x2000,
+            b: 0x7f00_0000_0000,
+            ..Default::default()
+        };
+        assert_eq!(RelType::Relative.formula().eval(&terms), 0x7eff_ffff_fff8);
+        assert_eq!(RelType::Abs64.formula().eval(&terms), 0xff8);
+        assert_eq!(RelType::JumpSlot.formula().eval(&terms), 0x1000);
+        assert!(!RelType::Relative.formula().uses(S));
+        assert_eq!(RelType::IRelative.formula().eval(&terms), 0x7eff_ffff_fff8);
+        assert!(RelType::Copy.formula().0.is_empty());
+
+        let tls = Terms {
+            s: 0x10,
+            a: 0x8,
+            module: 2,
+            tls: -0x40,
+            ..Default::default()
+        };
+        assert_eq!(RelType::TpOff64.formula().eval(&tls), (-0x28i64) as u64);
+        assert_eq!(RelType::TpOff64.formula().to_string(), "S + A + TLS");
+        assert_eq!(RelType::DtpMod64.formula().eval(&tls), 2);
+        assert_eq!(RelType::DtpOff64.formula().eval(&tls), 0x18);
+
+        let pc32 = Formula(&[(Plus, S), (Plus, A), (Minus, P)]);
+        assert_eq!(pc32.eval(&terms), (-0x1008i64) as u64);
+        assert_eq!(pc32.to_string(), "S + A - P");
+        assert_eq!(Formula(&[(Plus, Got), (Minus, P)]).to_string(), "GOT - P");
+    }
+}
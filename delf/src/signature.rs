@@ -0,0 +1,401 @@
+use core::fmt;
+
+use crate::{detect::Class, prelude::*, types::SectionType, FileHeader};
+
+// What sign-file ends a signed kernel module with
+pub const MODULE_SIG_MAGIC: &[u8] = b"~Module signature appended~\n";
+// struct module_signature, between the signature and the magic
+const MODULE_SIG_INFO: usize = 12;
+
+// module_signature.id_type
+pub const PKEY_ID_PGP: u8 = 0;
+pub const PKEY_ID_X509: u8 = 1;
+pub const PKEY_ID_PKCS7: u8 = 2;
+
+// DER tags of the PKCS#7 structures sign-file writes
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const OID: u8 = 0x06;
+const EXPLICIT_0: u8 = 0xa0;
+const EXPLICIT_1: u8 = 0xa1;
+const IMPLICIT_0: u8 = 0x80;
+
+// Attribute types of an X.509 name
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_ORGANIZATION: &[u8] = &[0x55, 0x04, 0x0a];
+// 2.16.840.1.101.3.4.2, the NIST hash algorithms
+const OID_NIST_HASH: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02];
+const OID_SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+
+// A signature sign-file appended to a kernel module. The module is `payload_len` bytes from the
+// start of the file, as they were before signing; everything after is signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleSignature {
+    pub payload_len: u64,
+    // Of the signature itself, without the module_signature and magic that follow it
+    pub sig_len: u64,
+    pub id_type: u8,
+    pub digest: Option<&'static str>,
+    // The CN of the signing certificate's issuer, or its O without one. PKCS#7 signatures, all
+    // sign-file has written since 4.3, name the issuer of the certificate rather than its
+    // subject; for the kernel's own keys the two are the same.
+    pub signer: Option<String>,
+    // The certificate's serial number, or the key identifier of the older formats, in hex
+    pub key_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    // module_signature claims more bytes than there are before it
+    Truncated { claimed: u64, available: u64 },
+    // The ELF contents run past `payload_len`, into the signature
+    Overlap { payload_len: u64, elf_end: u64 },
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated { claimed, available } => write!(
+                f,
+                "module signature claims {:#x} bytes, but only {:#x} come before it",
+                claimed, available
+            ),
+            Self::Overlap {
+                payload_len,
+                elf_end,
+            } => write!(
+                f,
+                "signed payload ends at {:#x}, but the ELF contents go on to {:#x}",
+                payload_len, elf_end
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SignatureError {}
+
+// The signature appended to `input`, the bytes of a kernel module, if it has one
+pub fn appended(input: &[u8]) -> Result<Option<ModuleSignature>, SignatureError> {
+    let info_at = match input
+        .len()
+        .checked_sub(MODULE_SIG_MAGIC.len() + MODULE_SIG_INFO)
+    {
+        Some(info_at) if input.ends_with(MODULE_SIG_MAGIC) => info_at,
+        _ => return Ok(None),
+    };
+    let info = &input[info_at..info_at + MODULE_SIG_INFO];
+    let (id_type, signer_len, key_id_len) = (info[2], info[3] as usize, info[4] as usize);
+    let sig_len = u32::from_be_bytes([info[8], info[9], info[10], info[11]]) as usize;
+    let claimed = signer_len + key_id_len + sig_len;
+    let payload_len = info_at
+        .checked_sub(claimed)
+        .ok_or(SignatureError::Truncated {
+            claimed: claimed as u64,
+            available: info_at as u64,
+        })?;
+
+    // The signer's name, the key identifier, then the signature
+    let signer = &input[payload_len..payload_len + signer_len];
+    let key_id = &input[payload_len + signer_len..payload_len + signer_len + key_id_len];
+    let sig = &input[info_at - sig_len..info_at];
+    let mut signature = ModuleSignature {
+        payload_len: payload_len as u64,
+        sig_len: sig_len as u64,
+        id_type,
+        digest: None,
+        signer: None,
+        key_id: None,
+    };
+    match id_type {
+        PKEY_ID_PKCS7 => {
+            pkcs7_signer(sig, &mut signature);
+        }
+        _ => {
+            signature.digest = hash_algo(info[1]);
+            signature.signer =
+                Some(String::from_utf8_lossy(signer).into_owned()).filter(|s| !s.is_empty());
+            signature.key_id = Some(hex(key_id)).filter(|s| !s.is_empty());
+        }
+    }
+    Ok(Some(signature))
+}
+
+impl ModuleSignature {
+    pub fn id_type_name(&self) -> &'static str {
+        match self.id_type {
+            PKEY_ID_PGP => "PGP",
+            PKEY_ID_X509 => "X.509",
+            PKEY_ID_PKCS7 => "PKCS#7",
+            _ => "unknown",
+        }
+    }
+
+    // Whether everything `file` says is in it lies within the signed payload
+    pub fn check(&self, file: &FileHeader) -> Result<(), SignatureError> {
+        let elf_end = elf_end(file);
+        match elf_end > self.payload_len {
+            true => Err(SignatureError::Overlap {
+                payload_len: self.payload_len,
+                elf_end,
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+// Where the last of the headers, header tables, sections and segments of `file` ends
+fn elf_end(file: &FileHeader) -> u64 {
+    let ehsize = match file.class {
+        Class::Elf32 => 52,
+        Class::Elf64 => 64,
+    };
+    let table = |info: &crate::HeaderInfo| info.offset.0 + (info.count * info.size) as u64;
+    let sections = file
+        .section_headers
+        .iter()
+        .filter(|sh| !matches!(sh.typ, SectionType::Null | SectionType::NoBits))
+        .map(|sh| sh.offset.0.saturating_add(sh.size.0));
+    let segments = file
+        .program_headers
+        .iter()
+        .map(|ph| ph.offset.0.saturating_add(ph.file_size.0));
+    sections
+        .chain(segments)
+        .chain([
+            ehsize,
+            table(&file.program_header_info),
+            table(&file.section_header_info),
+        ])
+        .max()
+        .unwrap_or(ehsize)
+}
+
+// enum hash_algo of the kernel, which the pre-PKCS#7 formats name their digest by
+fn hash_algo(id: u8) -> Option<&'static str> {
+    Some(match id {
+        0 => "md4",
+        1 => "md5",
+        2 => "sha1",
+        3 => "rmd160",
+        4 => "sha256",
+        5 => "sha384",
+        6 => "sha512",
+        7 => "sha224",
+        _ => return None,
+    })
+}
+
+// The contents of the DER value at the start of `data` if it is tagged `tag`, and what follows
+// it. Lengths of up to four bytes, which is all a module signature needs.
+fn der(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    if *data.first()? != tag {
+        return None;
+    }
+    let (len, header) = match *data.get(1)? {
+        short @ 0..=0x7f => (short as usize, 2),
+        long @ 0x81..=0x84 => {
+            let count = (long & 0x7f) as usize;
+            let bytes = data.get(2..2 + count)?;
+            let len = bytes.iter().fold(0usize, |len, &b| len << 8 | b as usize);
+            (len, 2 + count)
+        }
+        _ => return None,
+    };
+    let end = header.checked_add(len)?;
+    Some((data.get(header..end)?, data.get(end..)?))
+}
+
+// The first signer of a PKCS#7 SignedData, as sign-file writes them: no certificates, one
+// SignerInfo naming its certificate by issuer and serial number or by subject key identifier.
+// Leaves what it can't find unset.
+fn pkcs7_signer(blob: &[u8], signature: &mut ModuleSignature) -> Option<()> {
+    let (content_info, _) = der(blob, SEQUENCE)?;
+    let (_, rest) = der(content_info, OID)?;
+    let (explicit, _) = der(rest, EXPLICIT_0)?;
+    let (signed_data, _) = der(explicit, SEQUENCE)?;
+    let (_, rest) = der(signed_data, INTEGER)?;
+    let (_, rest) = der(rest, SET)?;
+    let (_, mut rest) = der(rest, SEQUENCE)?;
+    // Certificates and CRLs, when there are any
+    for optional in [EXPLICIT_0, EXPLICIT_1] {
+        if let Some((_, after)) = der(rest, optional) {
+            rest = after;
+        }
+    }
+    let (signer_infos, _) = der(rest, SET)?;
+    let (signer_info, _) = der(signer_infos, SEQUENCE)?;
+    let (_, rest) = der(signer_info, INTEGER)?;
+    let rest = match der(rest, SEQUENCE) {
+        Some((issuer_and_serial, rest)) => {
+            let (name, after) = der(issuer_and_serial, SEQUENCE)?;
+            signature.signer = signer_name(name);
+            let (serial, _) = der(after, INTEGER)?;
+            signature.key_id = Some(hex(serial));
+            rest
+        }
+        None => {
+            let (key_id, rest) = der(rest, IMPLICIT_0)?;
+            signature.key_id = Some(hex(key_id));
+            rest
+        }
+    };
+    let (algorithm, _) = der(rest, SEQUENCE)?;
+    let (oid, _) = der(algorithm, OID)?;
+    signature.digest = digest_name(oid);
+    Some(())
+}
+
+// CN, or O when a name has no CN
+fn signer_name(mut name: &[u8]) -> Option<String> {
+    let (mut common_name, mut organization) = (None, None);
+    while let Some((rdn, rest)) = der(name, SET) {
+        name = rest;
+        let (attribute, _) = match der(rdn, SEQUENCE) {
+            Some(attribute) => attribute,
+            None => continue,
+        };
+        let (typ, value) = match der(attribute, OID) {
+            Some(found) => found,
+            None => continue,
+        };
+        // UTF8String, PrintableString or whichever string type the issuer picked
+        let text = match value.first().and_then(|&tag| der(value, tag)) {
+            Some((text, _)) => String::from_utf8_lossy(text).into_owned(),
+            None => continue,
+        };
+        match typ {
+            OID_COMMON_NAME => common_name = Some(text),
+            OID_ORGANIZATION => organization = Some(text),
+            _ => {}
+        }
+    }
+    common_name.or(organization)
+}
+
+fn digest_name(oid: &[u8]) -> Option<&'static str> {
+    if oid == OID_SHA1 {
+        return Some("sha1");
+    }
+    match oid.strip_prefix(OID_NIST_HASH)? {
+        [1] => Some("sha256"),
+        [2] => Some("sha384"),
+        [3] => Some("sha512"),
+        [4] => Some("sha224"),
+        [8] => Some("sha3-256"),
+        [9] => Some("sha3-384"),
+        [10] => Some("sha3-512"),
+        _ => None,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+        let contents = parts.concat();
+        let mut out = vec![tag];
+        match contents.len() {
+            len @ 0..=0x7f => out.push(len as u8),
+            len => out.extend([0x82, (len >> 8) as u8, len as u8]),
+        }
+        out.extend(contents);
+        out
+    }
+
+    // `appended` is the signer's name and key id, as long as `info` says, then the signature
+    fn signed(payload: &[u8], appended: &[u8], info: [u8; 8]) -> Vec<u8> {
+        let sig_len = appended.len() - info[3] as usize - info[4] as usize;
+        let mut out = payload.to_vec();
+        out.extend(appended);
+        out.extend(info);
+        out.extend((sig_len as u32).to_be_bytes());
+        out.extend(MODULE_SIG_MAGIC);
+        out
+    }
+
+    #[test]
+    fn pkcs7_signatures() {
+        let attribute = |oid: &[u8], text: &[u8]| {
+            der(
+                SET,
+                &[&der(SEQUENCE, &[&der(OID, &[oid]), &der(0x0c, &[text])])],
+            )
+        };
+        let issuer = der(
+            SEQUENCE,
+            &[
+                &attribute(OID_ORGANIZATION, b"Acme"),
+                &attribute(OID_COMMON_NAME, b"Acme kernel key"),
+            ],
+        );
+        let signer_info = der(
+            SEQUENCE,
+            &[
+                &der(INTEGER, &[&[1]]),
+                &der(SEQUENCE, &[&issuer, &der(INTEGER, &[&[0x12, 0x34]])]),
+                &der(SEQUENCE, &[&der(OID, &[OID_NIST_HASH, &[1]])]),
+                &der(0x04, &[&[0xaa; 256]]),
+            ],
+        );
+        let signed_data = der(
+            SEQUENCE,
+            &[
+                &der(INTEGER, &[&[1]]),
+                &der(SET, &[]),
+                &der(SEQUENCE, &[]),
+                &der(SET, &[&signer_info]),
+            ],
+        );
+        let blob = der(
+            SEQUENCE,
+            &[&der(OID, &[&[0x2a; 9]]), &der(EXPLICIT_0, &[&signed_data])],
+        );
+        let input = signed(
+            b"\x7fELF module",
+            &blob,
+            [0, 0, PKEY_ID_PKCS7, 0, 0, 0, 0, 0],
+        );
+
+        let signature = appended(&input).unwrap().unwrap();
+        assert_eq!(signature.payload_len, 11);
+        assert_eq!(signature.sig_len, blob.len() as u64);
+        assert_eq!(signature.signer.as_deref(), Some("Acme kernel key"));
+        assert_eq!(signature.key_id.as_deref(), Some("1234"));
+        assert_eq!(signature.digest, Some("sha256"));
+
+        assert_eq!(appended(b"\x7fELF unsigned"), Ok(None));
+        // A signature longer than the file
+        let mut input = signed(b"", &[0; 4], [0, 0, PKEY_ID_PKCS7, 0, 0, 0, 0, 0]);
+        input[12] = 0x10;
+        assert_eq!(
+            appended(&input),
+            Err(SignatureError::Truncated {
+                claimed: 0x1000_0004,
+                available: 4
+            })
+        );
+    }
+
+    #[test]
+    fn older_signatures() {
+        // RSA, SHA-512 and X.509, with the signer and key id ahead of the signature
+        let input = signed(
+            b"payload",
+            b"Build key\xca\xfe\x01\x02\x03",
+            [0, 6, PKEY_ID_X509, 9, 2, 0, 0, 0],
+        );
+        let signature = appended(&input).unwrap().unwrap();
+        assert_eq!(signature.payload_len, 7);
+        assert_eq!(signature.signer.as_deref(), Some("Build key"));
+        assert_eq!(signature.key_id.as_deref(), Some("cafe"));
+        assert_eq!(signature.digest, Some("sha512"));
+        assert_eq!(signature.id_type_name(), "X.509");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{prelude::*, types::*, FileHeader};
+
+// How far past the entry point to look for the call; glibc's _start makes it within 0x30 bytes
+const SCAN_LEN: usize = 0x40;
+
+// The call at the end of a glibc-style _start:
+//
+//     lea rdi, [rip+main]        or  mov rdi, main
+//     call [rip+__libc_start_main@GOT]  or  call __libc_start_main
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartMainCall {
+    pub main: Addr,
+    pub callee: Callee,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Callee {
+    // Through a GOT slot, filled in by the dynamic linker
+    Slot(Addr),
+    // A direct call, into the binary itself or its PLT
+    Direct(Addr),
+}
+
+// Looks for the main-into-rdi, call pair in `code`, which is loaded at `addr`
+pub fn scan_start(code: &[u8], addr: Addr) -> Option<StartMainCall> {
+    let code = &code[..code.len().min(SCAN_LEN)];
+    let disp = |at: usize| -> Option<i64> {
+        let bytes = code.get(at..at + 4)?;
+        Some(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64)
+    };
+    let rel = |next: usize, disp: i64| Addr((addr.0 + next as u64).wrapping_add_signed(disp));
+    (0..code.len()).find_map(|i| {
+        let (main, call) = match code.get(i..i + 3)? {
+            // lea rdi, [rip+disp32]
+            [0x48, 0x8d, 0x3d] => (rel(i + 7, disp(i + 3)?), i + 7),
+            // mov rdi, imm32 (sign-extended)
+            [0x48, 0xc7, 0xc7] => (Addr(disp(i + 3)? as u64), i + 7),
+            _ => return None,
+        };
+        let callee = match code.get(call..call + 2)? {
+            [0xff, 0x15] => Callee::Slot(rel(call + 6, disp(call + 2)?)),
+            [0xe8, _] => Callee::Direct(rel(call + 5, disp(call + 1)?)),
+            _ => return None,
+        };
+        Some(StartMainCall { main, callee })
+    })
+}
+
+impl FileHeader {
+    // Address of main in a binary whose _start hands it to __libc_start_main. When the callee
+    // can be named, through the relocation of its GOT slot or a symbol, it has to be
+    // __libc_start_main; stripped static binaries are trusted on the pattern alone.
+    pub fn find_main(&self) -> Option<Addr> {
+        let call = scan_start(self.bytes_at(self.entry_point)?, self.entry_point)?;
+        let callee = match call.callee {
+            Callee::Slot(slot) => self.slot_symbol(slot),
+            Callee::Direct(target) => self.symbol_at(target).map(|(sym, _)| sym.name),
+        };
+        match callee {
+            Some(name) if name != "__libc_start_main" => None,
+            _ => Some(call.main),
+        }
+    }
+
+    // Name of the dynamic symbol whose address the dynamic linker writes to `slot`
+    fn slot_symbol(&self, slot: Addr) -> Option<String> {
+        let mut relas = self.read_rela_entries().unwrap_or_default();
+        relas.extend(self.read_plt_rela_entries().unwrap_or_default());
+        let rela = relas.iter().find(|r| r.offset == slot && r.sym != 0)?;
+        let dynsym = self
+            .section_headers
+            .iter()
+            .position(|sh| sh.typ == SectionType::DynSym)?;
+        self.symbols_in(dynsym)
+            .into_iter()
+            .nth(rela.sym as usize)
+            .map(|sym| sym.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glibc_start() {
+        // _start of a PIE linked against glibc 2.34+, loaded at 0x1050
+        #[rustfmt::skip]
+        let pie = [
+            0xf3, 0x0f, 0x1e, 0xfa,                     // endbr64
+            0x31, 0xed,                                 // xor ebp, ebp
+            0x49, 0x89, 0xd1,                           // mov r9, rdx
+            0x5e,                                       // pop rsi
+            0x48, 0x89, 0xe2,                           // mov rdx, rsp
+            0x48, 0x83, 0xe4, 0xf0,                     // and rsp, -16
+            0x50, 0x54,                                 // push rax; push rsp
+            0x45, 0x31, 0xc0, 0x31, 0xc9,               // xor r8d, r8d; xor ecx, ecx
+            0x48, 0x8d, 0x3d, 0xe0, 0x00, 0x00, 0x00,   // lea rdi, [rip+0xe0]
+            0xff, 0x15, 0x4f, 0x2f, 0x00, 0x00,         // call [rip+0x2f4f]
+            0xf4,                                       // hlt
+        ];
+        assert_eq!(
+            scan_start(&pie, Addr(0x1050)),
+            Some(StartMainCall {
+                main: Addr(0x1050 + 31 + 0xe0),
+                callee: Callee::Slot(Addr(0x1050 + 37 + 0x2f4f)),
+            })
+        );
+
+        // Static, non-PIE: main as an immediate and a direct call going backwards
+        #[rustfmt::skip]
+        let exec = [
+            0x31, 0xed,
+            0x48, 0xc7, 0xc7, 0x36, 0x12, 0x40, 0x00,   // mov rdi, 0x401236
+            0xe8, 0xf2, 0xff, 0xff, 0xff,               // call -0xe
+            0xf4,
+        ];
+        assert_eq!(
+            scan_start(&exec, Addr(0x401000)),
+            Some(StartMainCall {
+                main: Addr(0x401236),
+                callee: Callee::Direct(Addr(0x401000)),
+            })
+        );
+
+        // A lea into rdi that isn't followed by a call
+        assert_eq!(scan_start(&pie[24..31], Addr(0)), None);
+    }
+}
@@ -0,0 +1,202 @@
+use alloc::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use crate::prelude::*;
+
+// A string table as found in a file: NUL-terminated strings looked up by their offset
+#[derive(Debug, Clone, Copy)]
+pub struct StrTab<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StrTab<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    // The string starting at `offset`, None past the end of the table. A string missing its
+    // NUL runs to the end.
+    pub fn get(&self, offset: usize) -> Option<Cow<'a, str>> {
+        let bytes = self.data.get(offset..).filter(|b| !b.is_empty())?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Some(String::from_utf8_lossy(&bytes[..end]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+#[derive(Default)]
+pub struct StrTabBuilder {
+    strings: Vec<String>,
+    requested: usize,
+}
+
+pub struct BuiltStrTab {
+    pub data: Vec<u8>,
+    offsets: BTreeMap<String, usize>,
+    naive_size: usize,
+}
+
+impl StrTabBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Seed the builder with every string of an existing table, so the result can replace it
+    pub fn from_table(table: &[u8]) -> Self {
+        let mut builder = Self::new();
+        for s in table.split(|&b| b == 0).filter(|s| !s.is_empty()) {
+            builder.add(&String::from_utf8_lossy(s));
+        }
+        builder
+    }
+
+    pub fn add(&mut self, s: &str) {
+        self.requested += s.len() + 1;
+        if !s.is_empty() && !self.strings.iter().any(|x| x == s) {
+            self.strings.push(s.to_string());
+        }
+    }
+
+    pub fn build(&self) -> BuiltStrTab {
+        // Sorting on the reversed bytes puts every string right after the longest string it is
+        // a suffix of, so tail-merging only needs to look at the previous entry.
+        let mut sorted: Vec<&String> = self.strings.iter().collect();
+        sorted.sort_by(|a, b| b.bytes().rev().cmp(a.bytes().rev()));
+
+        let mut data = vec![0u8];
+        let mut offsets = BTreeMap::new();
+        offsets.insert(String::new(), 0);
+        let mut prev: Option<(&String, usize)> = None;
+        for s in sorted {
+            let offset = match prev {
+                Some((p, p_offset)) if p.ends_with(s.as_str()) => p_offset + p.len() - s.len(),
+                _ => {
+                    let offset = data.len();
+                    data.extend_from_slice(s.as_bytes());
+                    data.push(0);
+                    prev = Some((s, offset));
+                    offset
+                }
+            };
+            offsets.insert(s.clone(), offset);
+        }
+
+        BuiltStrTab {
+            data,
+            offsets,
+            naive_size: self.requested + 1,
+        }
+    }
+}
+
+impl BuiltStrTab {
+    pub fn offset(&self, s: &str) -> Option<usize> {
+        self.offsets.get(s).copied()
+    }
+
+    // Bytes saved compared to appending every requested string verbatim
+    pub fn saved(&self) -> usize {
+        self.naive_size.saturating_sub(self.data.len())
+    }
+}
+
+// One shared copy of each distinct string. Symbol names repeat across .symtab, .dynsym, the
+// objects of a process and the reports built from them; interned, each of those holds a pointer
+// instead of its own allocation.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: BTreeSet<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(s);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::{Interner, StrTab, StrTabBuilder};
+
+    fn read(data: &[u8], offset: usize) -> &[u8] {
+        let rest = &data[offset..];
+        &rest[..rest.iter().position(|&b| b == 0).unwrap()]
+    }
+
+    #[test]
+    fn tail_merging() {
+        let mut builder = StrTabBuilder::new();
+        for s in &["bar", "foobar", ".rela.text", ".text", "bar", ""] {
+            builder.add(s);
+        }
+        let table = builder.build();
+        assert_eq!(table.data, b"\0.rela.text\0foobar\0");
+        for s in &["bar", "foobar", ".rela.text", ".text", ""] {
+            assert_eq!(read(&table.data, table.offset(s).unwrap()), s.as_bytes());
+        }
+        assert_eq!(table.saved(), 34 - 19);
+    }
+
+    #[test]
+    fn rebuild_existing() {
+        let mut builder = StrTabBuilder::from_table(b"\0libc.so.6\0libfoo.so\0");
+        builder.add("c.so.6");
+        let table = builder.build();
+        assert_eq!(table.data.len(), 1 + 10 + 10);
+        assert_eq!(
+            read(&table.data, table.offset("c.so.6").unwrap()),
+            b"c.so.6"
+        );
+        assert_eq!(table.offset("missing"), None);
+    }
+
+    #[test]
+    fn lookup_by_offset() {
+        let table = StrTab::new(b"\0libc.so.6\0$ORIGIN/lib");
+        assert_eq!(table.get(0).as_deref(), Some(""));
+        assert_eq!(table.get(1).as_deref(), Some("libc.so.6"));
+        assert_eq!(table.get(6).as_deref(), Some("so.6"));
+        assert_eq!(table.get(11).as_deref(), Some("$ORIGIN/lib"));
+        assert_eq!(table.get(table.len()), None);
+    }
+
+    #[test]
+    fn interned_once() {
+        let mut names = Interner::new();
+        let a = names.intern("memcpy");
+        let b = names.intern(&String::from("memcpy"));
+        let c = names.intern("memmove");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(names.len(), 2);
+    }
+}
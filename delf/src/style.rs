@@ -0,0 +1,192 @@
+use core::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+#[cfg(feature = "std")]
+use std::{io::IsTerminal, path::Path};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub start: &'static str,
+    pub end: &'static str,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub dim: Style,
+    pub title: Style,
+    pub label: Style,
+    pub highlight: Style,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+const fn style(start: &'static str, end: &'static str) -> Style {
+    Style { start, end }
+}
+
+pub const NONE: Style = style("", "");
+
+pub const THEMES: &[Theme] = &[
+    Theme {
+        name: "default",
+        dim: style("\x1b[2m", "\x1b[22m"),
+        title: style("\x1b[1;34m", "\x1b[0m"),
+        label: style("\x1b[1;35m", "\x1b[0m"),
+        highlight: style("\x1b[1;31m", "\x1b[0m"),
+    },
+    // Blue/orange only, distinguishable with the common forms of color blindness
+    Theme {
+        name: "colorblind",
+        dim: style("\x1b[2m", "\x1b[22m"),
+        title: style("\x1b[1;38;5;33m", "\x1b[0m"),
+        label: style("\x1b[1;38;5;208m", "\x1b[0m"),
+        highlight: style("\x1b[1;4;38;5;208m", "\x1b[0m"),
+    },
+    Theme {
+        name: "plain",
+        dim: NONE,
+        title: NONE,
+        label: NONE,
+        highlight: NONE,
+    },
+];
+
+#[cfg(feature = "std")]
+const PLAIN: usize = 2;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static HYPERLINKS: AtomicBool = AtomicBool::new(false);
+
+impl Style {
+    pub fn paint(&self, content: impl Display) -> String {
+        format!("{}{}{}", self.start, content, self.end)
+    }
+}
+
+impl ColorChoice {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Self::Auto),
+            "always" => Some(Self::Always),
+            "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+pub fn theme() -> &'static Theme {
+    &THEMES[CURRENT.load(Ordering::Relaxed)]
+}
+
+pub fn theme_by_name(name: &str) -> Option<usize> {
+    THEMES.iter().position(|t| t.name == name)
+}
+
+// Picks the active theme. `name` falls back to $ELK_THEME, and `Auto` disables colors when
+// stdout is not a terminal or $NO_COLOR is set.
+#[cfg(feature = "std")]
+pub fn init(choice: ColorChoice, name: Option<&str>) -> Result<(), String> {
+    let name = name
+        .map(String::from)
+        .or_else(|| std::env::var("ELK_THEME").ok())
+        .unwrap_or_else(|| THEMES[0].name.to_string());
+    let index = theme_by_name(&name).ok_or_else(|| {
+        let names: Vec<_> = THEMES.iter().map(|t| t.name).collect();
+        format!(
+            "unknown theme {:?}, expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })?;
+
+    let colored = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+        }
+    };
+    CURRENT.store(if colored { index } else { PLAIN }, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn enable_hyperlinks(enabled: bool) {
+    HYPERLINKS.store(enabled, Ordering::Relaxed);
+}
+
+// Wraps `text` in an OSC-8 hyperlink when hyperlinks are enabled
+pub fn hyperlink(url: &str, text: impl Display) -> String {
+    if HYPERLINKS.load(Ordering::Relaxed) {
+        format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+    } else {
+        text.to_string()
+    }
+}
+
+// Link to a location inside an ELF file, as `file:///abs/path#fragment`
+#[cfg(feature = "std")]
+pub fn file_link(path: &Path, fragment: &str, text: impl Display) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    hyperlink(&format!("file://{}#{}", path.display(), fragment), text)
+}
+
+// `s` without CSI (colors) and OSC (hyperlinks) escapes
+pub fn strip(s: &str) -> String {
+    visible(s).collect()
+}
+
+// Number of terminal columns `s` occupies
+pub fn visible_width(s: &str) -> usize {
+    visible(s).count()
+}
+
+// The characters of `s` outside escape sequences
+fn visible(s: &str) -> impl Iterator<Item = char> + '_ {
+    let mut chars = s.chars().peekable();
+    core::iter::from_fn(move || loop {
+        let c = chars.next()?;
+        if c != '\x1b' {
+            return Some(c);
+        }
+        match chars.next() {
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn visible_width_skips_escapes() {
+        assert_eq!(super::visible_width("plain"), 5);
+        assert_eq!(super::visible_width("\x1b[1;34mblue\x1b[0m"), 4);
+        assert_eq!(
+            super::visible_width("\x1b]8;;file:///bin/ls#main\x1b\\main\x1b]8;;\x1b\\ █"),
+            6
+        );
+        assert_eq!(super::strip("\x1b[2m0000\x1b[22m3dd0"), "00003dd0");
+    }
+}
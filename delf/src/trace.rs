@@ -0,0 +1,273 @@
+// An opt-in record of every parser context entered while parsing, where it started, where it
+// stopped and how it ended, for following a failure down to the field that caused it. Parsers
+// name their contexts with `trace::context` in place of nom's; nothing is recorded outside of
+// `record`, and without the std feature nothing is recorded at all.
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use nom::error::VerboseError;
+#[cfg(feature = "std")]
+use nom::error::VerboseErrorKind;
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+
+use crate::{parse, prelude::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Outcome {
+    Parsed,
+    // Failed in a way an alt() may get past by trying something else
+    Error,
+    // Failed for good
+    Failure,
+    Incomplete,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Parsed => "ok",
+            Self::Error => "error",
+            Self::Failure => "failure",
+            Self::Incomplete => "incomplete",
+        })
+    }
+}
+
+// A context entered once. Offsets are into the input given to `record`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub struct Span {
+    pub context: &'static str,
+    pub start: usize,
+    // Past what the parser consumed, or where the innermost failure is
+    pub end: usize,
+    pub outcome: Outcome,
+    // For failures, what the innermost error says: the context that failed or nom's error kind
+    pub reason: Option<String>,
+    pub children: Vec<Span>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+pub struct Trace {
+    pub spans: Vec<Span>,
+}
+
+impl Trace {
+    // The innermost span of the last failure, the one to look at first
+    pub fn failed(&self) -> Option<&Span> {
+        let mut failed = self
+            .spans
+            .iter()
+            .rev()
+            .find(|s| s.outcome != Outcome::Parsed)?;
+        while let Some(child) = failed
+            .children
+            .iter()
+            .rev()
+            .find(|s| s.outcome != Outcome::Parsed)
+        {
+            failed = child;
+        }
+        Some(failed)
+    }
+}
+
+// One span per line, children indented under their parent
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_span(f: &mut fmt::Formatter<'_>, span: &Span, depth: usize) -> fmt::Result {
+            write!(f, "{:indent$}{} ", "", span.context, indent = depth * 2)?;
+            match (&span.outcome, &span.reason) {
+                (Outcome::Parsed, _) => write!(f, "{:#x}..{:#x} ok", span.start, span.end)?,
+                (outcome, Some(reason)) => write!(
+                    f,
+                    "{:#x} {} at {:#x}: {}",
+                    span.start, outcome, span.end, reason
+                )?,
+                (outcome, None) => write!(f, "{:#x} {} at {:#x}", span.start, outcome, span.end)?,
+            }
+            writeln!(f)?;
+            for child in &span.children {
+                write_span(f, child, depth + 1)?;
+            }
+            Ok(())
+        }
+        for span in &self.spans {
+            write_span(f, span, 0)?;
+        }
+        Ok(())
+    }
+}
+
+// nom's context, recording when the parser is entered and how it comes back
+pub fn context<'a, O, F>(
+    name: &'static str,
+    parser: F,
+) -> impl FnMut(parse::Input<'a>) -> parse::Result<'a, O>
+where
+    F: nom::Parser<parse::Input<'a>, O, VerboseError<parse::Input<'a>>>,
+{
+    let mut parser = nom::error::context(name, parser);
+    move |input| {
+        enter(name, input);
+        let result = parser(input);
+        exit(&result);
+        result
+    }
+}
+
+#[cfg(feature = "std")]
+struct Recorder {
+    base: usize,
+    open: Vec<Span>,
+    done: Vec<Span>,
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+// Runs `f`, recording the contexts its parsers on this thread enter in `input`
+#[cfg(feature = "std")]
+pub fn record<T>(input: parse::Input, f: impl FnOnce() -> T) -> (T, Trace) {
+    let recorder = Recorder {
+        base: input.as_ptr() as usize,
+        open: Vec::new(),
+        done: Vec::new(),
+    };
+    let outer = RECORDER.with(|r| r.replace(Some(recorder)));
+    let result = f();
+    let recorder = RECORDER.with(|r| r.replace(outer));
+    let spans = recorder.map(|r| r.done).unwrap_or_default();
+    (result, Trace { spans })
+}
+
+#[cfg(feature = "std")]
+fn enter(context: &'static str, input: parse::Input) {
+    RECORDER.with(|r| {
+        if let Some(recorder) = r.borrow_mut().as_mut() {
+            let start = (input.as_ptr() as usize).saturating_sub(recorder.base);
+            recorder.open.push(Span {
+                context,
+                start,
+                end: start,
+                outcome: Outcome::Parsed,
+                reason: None,
+                children: Vec::new(),
+            });
+        }
+    })
+}
+
+#[cfg(feature = "std")]
+fn exit<O>(result: &parse::Result<O>) {
+    RECORDER.with(|r| {
+        let mut recorder = r.borrow_mut();
+        let recorder = match recorder.as_mut() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let mut span = match recorder.open.pop() {
+            Some(span) => span,
+            None => return,
+        };
+        let stopped = match result {
+            Ok((rest, _)) => Some(*rest),
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                let (at, kind) = &e.errors[0];
+                span.reason = Some(match kind {
+                    VerboseErrorKind::Context(context) => context.to_string(),
+                    VerboseErrorKind::Char(c) => format!("expected {:?}", c),
+                    VerboseErrorKind::Nom(kind) => kind.description().to_string(),
+                });
+                Some(*at)
+            }
+            Err(nom::Err::Incomplete(_)) => None,
+        };
+        span.outcome = match result {
+            Ok(_) => Outcome::Parsed,
+            Err(nom::Err::Error(_)) => Outcome::Error,
+            Err(nom::Err::Failure(_)) => Outcome::Failure,
+            Err(nom::Err::Incomplete(_)) => Outcome::Incomplete,
+        };
+        if let Some(stopped) = stopped {
+            span.end = (stopped.as_ptr() as usize).saturating_sub(recorder.base);
+        }
+        match recorder.open.last_mut() {
+            Some(parent) => parent.children.push(span),
+            None => recorder.done.push(span),
+        }
+    })
+}
+
+#[cfg(not(feature = "std"))]
+fn enter(_: &'static str, _: parse::Input) {}
+
+#[cfg(not(feature = "std"))]
+fn exit<O>(_: &parse::Result<O>) {}
+
+// When FileHeader::parse_checked records a trace and prints it on stderr. Set once by the
+// program, like the style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    OnFailure,
+    Always,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => Mode::Off,
+        1 => Mode::OnFailure,
+        _ => Mode::Always,
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use nom::{bytes::complete::tag, number::complete::le_u16, sequence::tuple};
+
+    #[test]
+    fn records_nested_contexts_and_where_they_failed() {
+        let input = [0x7f, b'E', 0x01, 0x00, 0xff];
+        let mut parser = context(
+            "Header",
+            tuple((
+                context("Magic", tag(&[0x7f, b'E'])),
+                context("Count", le_u16),
+            )),
+        );
+        let (result, trace) = record(&input, || parser(&input[..]).map(|(_, v)| v));
+        assert!(result.is_ok());
+        assert_eq!(trace.spans.len(), 1);
+        assert_eq!((trace.spans[0].start, trace.spans[0].end), (0, 4));
+        let fields: Vec<_> = trace.spans[0]
+            .children
+            .iter()
+            .map(|s| (s.context, s.start, s.end))
+            .collect();
+        assert_eq!(fields, vec![("Magic", 0, 2), ("Count", 2, 4)]);
+
+        let (result, trace) = record(&input, || parser(&input[3..]).map(|(_, v)| v));
+        assert!(result.is_err());
+        let failed = trace.failed().unwrap();
+        assert_eq!((failed.context, failed.start), ("Magic", 3));
+        assert_eq!(failed.outcome, Outcome::Error);
+        assert!(trace
+            .to_string()
+            .starts_with("Header 0x3 error at 0x3: Tag\n"));
+    }
+}
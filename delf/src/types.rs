@@ -1,23 +1,38 @@
+use core::{
+    convert::TryFrom,
+    fmt::{self, Debug},
+    mem::size_of,
+    ops::Range,
+};
 use derive_more::*;
 use derive_try_from_primitive::TryFromPrimitive;
 use enumflags2::*;
 use nom::{
-    combinator::{map, map_res, verify},
-    error::{context, ErrorKind},
-    multi::many_till,
-    number::complete::{le_u16, le_u32, le_u64},
+    combinator::{map, map_res},
+    error::ErrorKind,
+    number::complete::{le_i64, le_u16, le_u32, le_u64, le_u8},
     sequence::tuple,
 };
-use std::{
-    convert::TryFrom,
-    fmt::{self, Debug},
-    ops::Range,
-};
 
-use crate::{impl_parse_for_bitflags, impl_parse_for_enum, parse};
+use crate::{
+    data::Data,
+    detect::{Class, Endian},
+    eh_frame::{parse_eh_frame_hdr, EhFrameHdr},
+    impl_parse_for_bitflags, impl_parse_for_enum,
+    note::{self, Note},
+    open_enum,
+    parse::{self, Severity},
+    prelude::*,
+    style,
+    trace::context,
+    version::SymbolVersion,
+    walk::Walk,
+};
 
+#[cfg(feature = "std")]
 use carpenter::*;
 
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 #[repr(u16)]
 pub enum Type {
@@ -29,33 +44,50 @@ pub enum Type {
     Proc = 0xff00,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
-#[repr(u16)]
+// e_machine. Architectures delf doesn't special-case still parse, as Other.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Machine {
-    X86 = 0x03,
-    X86_64 = 0x3e,
+    Sparc,
+    X86,
+    M68k,
+    Mips,
+    PowerPC,
+    PowerPC64,
+    S390,
+    Arm,
+    SuperH,
+    SparcV9,
+    IA64,
+    X86_64,
+    AArch64,
+    RiscV,
+    Bpf,
+    LoongArch,
+    Other(u16),
 }
 
-#[repr(u32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
-#[rustfmt::skip]
-pub enum SegmentType {
-    Null        = 0x0,
-    Load        = 0x1,
-    Dynamic     = 0x2,
-    Interp      = 0x3,
-    Note        = 0x4,
-    ShLib       = 0x5,
-    ProgHeader  = 0x6,
-    TLS         = 0x7,
-    LoOS        = 0x6000_0000,
-    HiOS        = 0x6fff_ffff,
-    LoProc      = 0x7000_0000,
-    HiProc      = 0x7fff_ffff,
-    GnuEhFrame  = 0x6474_e550,
-    GnuStack    = 0x6474_e551,
-    GnuRelRo    = 0x6474_e552,
-    GnuProperty = 0x6474_e553,
+open_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+    pub enum SegmentType: u32, le_u32 {
+        Null        = 0x0,
+        Load        = 0x1,
+        Dynamic     = 0x2,
+        Interp      = 0x3,
+        Note        = 0x4,
+        ShLib       = 0x5,
+        ProgHeader  = 0x6,
+        TLS         = 0x7,
+        LoOS        = 0x6000_0000,
+        HiOS        = 0x6fff_ffff,
+        LoProc      = 0x7000_0000,
+        HiProc      = 0x7fff_ffff,
+        GnuEhFrame  = 0x6474_e550,
+        GnuStack    = 0x6474_e551,
+        GnuRelRo    = 0x6474_e552,
+        GnuProperty = 0x6474_e553,
+    }
 }
 
 #[repr(u32)]
@@ -65,85 +97,155 @@ pub enum SegmentFlags {
     Write = 0x2,
     Read = 0x4,
 }
-pub struct SegmentBits(BitFlags<SegmentFlags>);
+// Flags plus whatever bits SegmentFlags has no name for, PF_MASKOS and PF_MASKPROC ones mostly
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SegmentBits {
+    flags: BitFlags<SegmentFlags>,
+    unknown: u32,
+}
+
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitFlags)]
+#[rustfmt::skip]
+pub enum SectionFlags {
+    Write           = 0x1,
+    Alloc           = 0x2,
+    ExecInstr       = 0x4,
+    Merge           = 0x10,
+    Strings         = 0x20,
+    InfoLink        = 0x40,
+    LinkOrder       = 0x80,
+    OsNonconforming = 0x100,
+    Group           = 0x200,
+    Tls             = 0x400,
+    Compressed      = 0x800,
+    GnuRetain       = 0x20_0000,
+    X86_64Large     = 0x1000_0000,
+    Exclude         = 0x8000_0000,
+}
+// Flags plus whatever bits SectionFlags has no name for, so nothing in sh_flags is lost
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SectionBits {
+    flags: BitFlags<SectionFlags>,
+    unknown: u64,
+}
 
 #[derive(Debug)]
 pub enum SegmentContent {
     Unknown,
     Dynamic(Vec<DynamicEntry>),
+    EhFrameHdr(EhFrameHdr),
+    Tls(TlsTemplate),
+    Notes(Vec<Note>),
+}
+
+// What PT_TLS describes: the initial image every thread's copy of the object's thread-locals
+// starts from. `file_size` bytes of .tdata at `image`, then .tbss zeroes up to `mem_size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlsTemplate {
+    pub image: Addr,
+    pub file_size: u64,
+    pub mem_size: u64,
+    // At least 1, whatever p_align says
+    pub align: u64,
 }
 
-#[derive(Debug, PrettyTable)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug)]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
 pub struct DynamicEntry {
     pub tag: DynamicTag,
     pub addr: Addr,
 }
 
-#[repr(u64)]
-#[derive(Debug, PartialEq, Eq, TryFromPrimitive)]
-#[rustfmt::skip]
-pub enum DynamicTag {
-    Null           = 0,
-    Needed         = 1,
-    PltRelSz       = 2,
-    PltGot         = 3,
-    Hash           = 4,
-    StrTab         = 5,
-    SymTab         = 6,
-    Rela           = 7,
-    RelaSz         = 8,
-    RelaEnt        = 9,
-    StrSz          = 10,
-    SymEnt         = 11,
-    Init           = 12,
-    FIni           = 13,
-    SOName         = 14,
-    RPath          = 15,
-    Symbolic       = 16,
-    Rel            = 17,
-    RelSz          = 18,
-    RelEnt         = 19,
-    PltRel         = 20,
-    Debug          = 21,
-    TextRel        = 22,
-    JmpRel         = 23,
-    BindNow        = 24,
-    InitArray      = 25,
-    FiniArray      = 26,
-    InitArraysz    = 27,
-    FiniArraysz    = 28,
-    Runpath        = 29,
-    Flags          = 30,
-    Encoding       = 31,
-    PreinitArray   = 32,
-    PreinitArraySz = 33,
-    MaxPosTags     = 34,
-    LoOS           = 0x60000000,
-    HiOS           = 0x6fffffff,
-    LoProc         = 0x70000000,
-    HiProc         = 0x7fffffff,
-    GnuHash        = 0x6ffffef5,
-    Flags1         = 0x6ffffffb,
-    RelaCount      = 0x6ffffff9,
-}
-
-#[derive(PrettyTable)]
+open_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+    pub enum DynamicTag: u64, le_u64 {
+        Null           = 0,
+        Needed         = 1,
+        PltRelSz       = 2,
+        PltGot         = 3,
+        Hash           = 4,
+        StrTab         = 5,
+        SymTab         = 6,
+        Rela           = 7,
+        RelaSz         = 8,
+        RelaEnt        = 9,
+        StrSz          = 10,
+        SymEnt         = 11,
+        Init           = 12,
+        FIni           = 13,
+        SOName         = 14,
+        RPath          = 15,
+        Symbolic       = 16,
+        Rel            = 17,
+        RelSz          = 18,
+        RelEnt         = 19,
+        PltRel         = 20,
+        Debug          = 21,
+        TextRel        = 22,
+        JmpRel         = 23,
+        BindNow        = 24,
+        InitArray      = 25,
+        FiniArray      = 26,
+        InitArraysz    = 27,
+        FiniArraysz    = 28,
+        Runpath        = 29,
+        Flags          = 30,
+        Encoding       = 31,
+        PreinitArray   = 32,
+        PreinitArraySz = 33,
+        MaxPosTags     = 34,
+        RelrSz         = 35,
+        Relr           = 36,
+        RelrEnt        = 37,
+        LoOS           = 0x60000000,
+        LoProc         = 0x70000000,
+        HiProc         = 0x7fffffff,
+        GnuHash        = 0x6ffffef5,
+        TlsDescPlt     = 0x6ffffef6,
+        TlsDescGot     = 0x6ffffef7,
+        VerSym         = 0x6ffffff0,
+        RelaCount      = 0x6ffffff9,
+        RelCount       = 0x6ffffffa,
+        Flags1         = 0x6ffffffb,
+        VerDef         = 0x6ffffffc,
+        VerDefNum      = 0x6ffffffd,
+        VerNeed        = 0x6ffffffe,
+        VerNeedNum     = 0x6fffffff,
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
 pub struct RelaEntry {
     pub offset: Addr,
     pub typ: RelType,
     pub sym: u32,
-    pub addend: Addr,
+    pub addend: Addend,
 }
 
-#[repr(u32)]
-#[derive(Debug, TryFromPrimitive, Clone, Copy, PartialEq, Eq)]
+// The dynamic relocation types, named after x86-64's. Other machines' numbers for the same
+// operations decode to them too, see `from_number`.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RelType {
-    GlobalData = 6,
-    JumpSlot = 7,
-    Relative = 8,
+    Abs64,
+    Copy,
+    GlobalData,
+    JumpSlot,
+    Relative,
+    DtpMod64,
+    DtpOff64,
+    TpOff64,
+    IRelative,
+    // A number with no name on its machine, or from a machine without a table
+    Unknown(u32),
 }
 
-#[derive(PrettyTable)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
 pub struct ProgramHeader {
     pub typ: SegmentType,
     pub flags: SegmentBits,
@@ -153,29 +255,321 @@ pub struct ProgramHeader {
     pub file_size: Addr,
     pub mem_size: Addr,
     pub align: Addr,
-    #[skip]
+    #[cfg_attr(feature = "std", skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub contents: SegmentContent,
-    #[skip]
-    pub data: Vec<u8>,
+    #[cfg_attr(feature = "std", skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub data: Data,
+}
+
+// Sizes a segment can't have, caught before anything tries to map it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentSizeError {
+    Overflow(u64, u64),
+    Truncated(u64, u64),
+    HugeBss(u64),
+}
+
+impl fmt::Display for SegmentSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow(a, b) => write!(
+                f,
+                "virt_addr {:#x} + mem_size {:#x} overflows the address space",
+                a, b
+            ),
+            Self::Truncated(a, b) => {
+                write!(f, "mem_size {:#x} is smaller than file_size {:#x}", a, b)
+            }
+            Self::HugeBss(a) => write!(
+                f,
+                "{:#x} bytes of zero fill, more than any real program needs",
+                a
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SegmentSizeError {}
+
+// Why a section has no bytes of its own in the file to hand out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionContentError {
+    // SHT_NULL, an unused entry
+    Null,
+    // SHT_NOBITS: this many zeroes in memory, nothing in the file
+    NoBits(u64),
+    // The file ends before the section does; `available` is what is left of it past `offset`
+    PastEof {
+        offset: u64,
+        size: u64,
+        available: u64,
+    },
+}
+
+impl fmt::Display for SectionContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "SHT_NULL sections have no contents"),
+            Self::NoBits(size) => write!(
+                f,
+                "SHT_NOBITS section, {:#x} bytes of zeroes in memory and none in the file",
+                size
+            ),
+            Self::PastEof {
+                offset,
+                size,
+                available,
+            } => write!(
+                f,
+                "{:#x} bytes at offset {:#x}, but the file only has {:#x} of them",
+                size, offset, available
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SectionContentError {}
+
+// PT_DYNAMIC and the .dynamic section disagreeing about where the dynamic table is. The
+// loader only reads the segment, so a section that points elsewhere hides the real table
+// from tools that go by sections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicMismatch {
+    Offset(u64, u64),
+    Address(u64, u64),
+    Size(u64, u64),
+}
+
+impl fmt::Display for DynamicMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Offset(a, b) => write!(
+                f,
+                "PT_DYNAMIC is at offset {:#x} but .dynamic at {:#x}",
+                a, b
+            ),
+            Self::Address(a, b) => write!(
+                f,
+                "PT_DYNAMIC is at address {:#x} but .dynamic at {:#x}",
+                a, b
+            ),
+            Self::Size(a, b) => write!(f, "PT_DYNAMIC holds {:#x} bytes but .dynamic {:#x}", a, b),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DynamicMismatch {}
+
+// A LOAD segment whose contents don't match its flags; the index is into program_headers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentMismatch {
+    RelocatedReadOnly(usize, usize),
+    NotCode(usize, f64),
+}
+
+impl fmt::Display for SegmentMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RelocatedReadOnly(a, b) => write!(
+                f,
+                "segment {} is read-only but {} relocations write to it outside RELRO",
+                a, b
+            ),
+            Self::NotCode(a, b) => write!(
+                f,
+                "segment {} is executable but looks like data (code-likeness {:.2})",
+                a, b
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SegmentMismatch {}
+
+open_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+    pub enum SectionType: u32, le_u32 {
+        Null          = 0x0,
+        ProgBits      = 0x1,
+        SymTab        = 0x2,
+        StrTab        = 0x3,
+        Rela          = 0x4,
+        Hash          = 0x5,
+        Dynamic       = 0x6,
+        Note          = 0x7,
+        NoBits        = 0x8,
+        Rel           = 0x9,
+        ShLib         = 0xa,
+        DynSym        = 0xb,
+        InitArray     = 0xe,
+        FiniArray     = 0xf,
+        PreinitArray  = 0x10,
+        Group         = 0x11,
+        SymTabShndx   = 0x12,
+        Relr          = 0x13,
+        LlvmAddrsig   = 0x6fff_4c03,
+        GnuAttributes = 0x6fff_fff5,
+        GnuHash       = 0x6fff_fff6,
+        GnuLibList    = 0x6fff_fff7,
+        Checksum      = 0x6fff_fff8,
+        GnuVerDef     = 0x6fff_fffd,
+        GnuVerNeed    = 0x6fff_fffe,
+        GnuVerSym     = 0x6fff_ffff,
+        X86_64Unwind  = 0x7000_0001,
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
+pub struct SectionHeader {
+    pub name: String,
+    #[cfg_attr(feature = "std", skip)]
+    pub name_idx: u32,
+    pub typ: SectionType,
+    pub flags: SectionBits,
+    pub addr: Addr,
+    pub offset: Addr,
+    pub size: Addr,
+    pub link: u32,
+    pub info: u32,
+    pub align: Addr,
+    pub entsize: Addr,
+    #[cfg_attr(feature = "std", skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub data: Data,
+}
+
+// High nibble of st_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum SymBind {
+    Local = 0,
+    Global = 1,
+    Weak = 2,
+    GnuUnique = 10,
+}
+
+// Low nibble of st_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum SymType {
+    NoType = 0,
+    Object = 1,
+    Func = 2,
+    Section = 3,
+    File = 4,
+    Common = 5,
+    Tls = 6,
+    GnuIFunc = 10,
+}
+
+// Low two bits of st_other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum SymVisibility {
+    Default = 0,
+    Internal = 1,
+    Hidden = 2,
+    Protected = 3,
+}
+
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "std", derive(PrettyTable))]
+pub struct Symbol {
+    pub name: String,
+    #[cfg_attr(feature = "std", skip)]
+    pub name_idx: u32,
+    pub value: Addr,
+    pub size: u64,
+    #[cfg_attr(feature = "std", fmt("{:#x}"))]
+    pub info: u8,
+    #[cfg_attr(feature = "std", fmt("{:#x}"))]
+    pub other: u8,
+    pub shndx: SectionIdx,
+    // Only known for dynamic symbols of files with version tables
+    #[cfg_attr(feature = "std", skip)]
+    pub version: Option<SymbolVersion>,
+}
+
+// st_shndx, with the reserved values kept apart from real section indices
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SectionIdx {
+    Undef,
+    Abs,
+    Common,
+    Index(u32),
+    // SHN_XINDEX with no SHT_SYMTAB_SHNDX entry to say what it stands for
+    Xindex,
+    // Other OS and processor specific values, such as SHN_X86_64_LCOMMON
+    Reserved(u16),
+}
+
+#[cfg_attr(feature = "std", derive(PrettyTable))]
+pub struct SectionGroup {
+    pub name: String,
+    pub signature: String,
+    pub comdat: bool,
+    pub members: Vec<String>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Sub, Add)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Addr(pub u64);
 
+// r_addend, which unlike the addresses it is added to is signed
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Addend(pub i64);
+
 //------------------------------------------------------------
 //-------------------- Implementations -----------------------
 //------------------------------------------------------------
 impl_parse_for_enum!(Type, le_u16);
-impl_parse_for_enum!(Machine, le_u16);
-impl_parse_for_enum!(SegmentType, le_u32);
-impl_parse_for_enum!(RelType, le_u32);
-impl_parse_for_enum!(DynamicTag, le_u64);
 impl_parse_for_bitflags!(SegmentFlags, le_u32);
+impl_parse_for_bitflags!(SectionFlags, le_u64);
 
-impl std::ops::Deref for SegmentBits {
+impl core::ops::Deref for SegmentBits {
     type Target = BitFlags<SegmentFlags>;
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.flags
+    }
+}
+
+impl SegmentBits {
+    pub const MASK_OS: u32 = 0x0ff0_0000;
+    pub const MASK_PROC: u32 = 0xf000_0000;
+
+    pub fn from_bits(bits: u32) -> Self {
+        let flags = BitFlags::<SegmentFlags>::from_bits_truncate(bits);
+        Self {
+            flags,
+            unknown: bits & !flags.bits(),
+        }
+    }
+
+    // The raw p_flags value
+    pub fn bits(&self) -> u32 {
+        self.flags.bits() | self.unknown
+    }
+
+    pub fn unknown(&self) -> u32 {
+        self.unknown
+    }
+}
+
+impl From<BitFlags<SegmentFlags>> for SegmentBits {
+    fn from(flags: BitFlags<SegmentFlags>) -> Self {
+        Self { flags, unknown: 0 }
     }
 }
 
@@ -189,12 +583,108 @@ impl fmt::Debug for SegmentBits {
                 (SegmentFlags::Write, "W"),
                 (SegmentFlags::Execute, "X"),
             ]
-            .map(|(f, l)| if self.0.contains(f) { l } else { &"-" })
+            .map(|(f, l)| if self.flags.contains(f) { l } else { &"-" })
             .join(" ")
         )
     }
 }
 
+// Both serialize as the letters their Debug impls print
+#[cfg(feature = "serde")]
+impl serde::Serialize for SegmentBits {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", self).replace(' ', ""))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SectionBits {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:?}", self))
+    }
+}
+
+impl core::ops::Deref for SectionBits {
+    type Target = BitFlags<SectionFlags>;
+    fn deref(&self) -> &Self::Target {
+        &self.flags
+    }
+}
+
+impl SectionBits {
+    pub const MASK_OS: u64 = 0x0ff0_0000;
+    pub const MASK_PROC: u64 = 0xf000_0000;
+
+    // Unlike SectionFlags::parse, keeps bits it has no name for instead of failing
+    pub fn parse(input: parse::Input) -> parse::Result<Self> {
+        map(le_u64, Self::from_bits)(input)
+    }
+
+    pub fn parse_as<'a>(
+        class: Class,
+        endian: Endian,
+    ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |input| map(parse::word(class, endian), Self::from_bits)(input)
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        let flags = BitFlags::<SectionFlags>::from_bits_truncate(bits);
+        Self {
+            flags,
+            unknown: bits & !flags.bits(),
+        }
+    }
+
+    // The raw sh_flags value
+    pub fn bits(&self) -> u64 {
+        self.flags.bits() | self.unknown
+    }
+
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+}
+
+// readelf's key: W A X M S I L O G T C R l E, then o, p and x for unnamed OS, processor and
+// other bits
+impl fmt::Debug for SectionBits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let named = [
+            (SectionFlags::Write, 'W'),
+            (SectionFlags::Alloc, 'A'),
+            (SectionFlags::ExecInstr, 'X'),
+            (SectionFlags::Merge, 'M'),
+            (SectionFlags::Strings, 'S'),
+            (SectionFlags::InfoLink, 'I'),
+            (SectionFlags::LinkOrder, 'L'),
+            (SectionFlags::OsNonconforming, 'O'),
+            (SectionFlags::Group, 'G'),
+            (SectionFlags::Tls, 'T'),
+            (SectionFlags::Compressed, 'C'),
+            (SectionFlags::GnuRetain, 'R'),
+            (SectionFlags::X86_64Large, 'l'),
+            (SectionFlags::Exclude, 'E'),
+        ];
+        let unnamed = [
+            (self.unknown & Self::MASK_OS, 'o'),
+            (self.unknown & Self::MASK_PROC, 'p'),
+            (self.unknown & !(Self::MASK_OS | Self::MASK_PROC), 'x'),
+        ];
+        let letters: String = named
+            .iter()
+            .filter(|(flag, _)| self.flags.contains(*flag))
+            .map(|(_, l)| *l)
+            .chain(
+                unnamed
+                    .iter()
+                    .filter(|(bits, _)| *bits != 0)
+                    .map(|(_, l)| *l),
+            )
+            .collect();
+        write!(f, "{}", letters)
+    }
+}
+
 impl fmt::Display for Addr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(&self, f)
@@ -204,7 +694,8 @@ impl fmt::Debug for Addr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let hex = format!("{:08X}", self.0);
         let rest = hex.trim_start_matches('0');
-        write!(f, "[2m{}[22m{}", "0".repeat(8 - rest.len()), rest)
+        let padding = "0".repeat(hex.len() - rest.len());
+        write!(f, "{}{}", style::theme().dim.paint(padding), rest)
     }
 }
 impl Into<u64> for Addr {
@@ -226,32 +717,390 @@ impl Addr {
     pub fn parse(input: crate::parse::Input) -> crate::parse::Result<Self> {
         map(le_u64, From::from)(input)
     }
+
+    pub fn parse_as<'a>(
+        class: Class,
+        endian: Endian,
+    ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |input| map(parse::word(class, endian), Addr)(input)
+    }
+}
+
+// Shown with its sign, as `+0x10` or `-0x8`
+impl fmt::Debug for Addend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { '-' } else { '+' };
+        write!(f, "{}{:#x}", sign, self.0.unsigned_abs())
+    }
+}
+impl fmt::Display for Addend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
+// Wraps like the 64-bit arithmetic relocations are defined in
+impl core::ops::Add<Addend> for Addr {
+    type Output = Addr;
+    fn add(self, addend: Addend) -> Addr {
+        Addr(self.0.wrapping_add_signed(addend.0))
+    }
+}
+impl Addend {
+    pub fn parse(input: crate::parse::Input) -> crate::parse::Result<Self> {
+        map(le_i64, Addend)(input)
+    }
+}
+
+impl DynamicTag {
+    // Tags whose value is an offset in DT_STRTAB rather than an address or a count
+    pub fn is_string(self) -> bool {
+        matches!(
+            self,
+            DynamicTag::Needed | DynamicTag::SOName | DynamicTag::RPath | DynamicTag::Runpath
+        )
+    }
+
+    // Tags whose value is a virtual address rather than a size, count or flag word
+    pub fn is_address(self) -> bool {
+        matches!(
+            self,
+            DynamicTag::PltGot
+                | DynamicTag::Hash
+                | DynamicTag::StrTab
+                | DynamicTag::SymTab
+                | DynamicTag::Rela
+                | DynamicTag::Init
+                | DynamicTag::FIni
+                | DynamicTag::Rel
+                | DynamicTag::Debug
+                | DynamicTag::JmpRel
+                | DynamicTag::InitArray
+                | DynamicTag::FiniArray
+                | DynamicTag::PreinitArray
+                | DynamicTag::Relr
+                | DynamicTag::GnuHash
+                | DynamicTag::TlsDescPlt
+                | DynamicTag::TlsDescGot
+                | DynamicTag::VerSym
+                | DynamicTag::VerDef
+                | DynamicTag::VerNeed
+        )
+    }
 }
 
 impl DynamicEntry {
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 8,
+            Class::Elf64 => 16,
+        }
+    }
+
     pub fn parse(input: parse::Input) -> parse::Result<Self> {
-        let (input, (tag, addr)) = tuple((DynamicTag::parse, Addr::parse))(input)?;
-        Ok((input, Self { tag, addr }))
+        Self::parse_as(Class::Elf64, Endian::Little)(input)
+    }
+
+    pub fn parse_as<'a>(
+        class: Class,
+        endian: Endian,
+    ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |input| {
+            let tag = map(parse::word(class, endian), DynamicTag::from);
+            let (input, (tag, addr)) =
+                tuple((context("DynamicTag", tag), Addr::parse_as(class, endian)))(input)?;
+            Ok((input, Self { tag, addr }))
+        }
+    }
+
+    // What a string-valued entry's offset points to in DT_STRTAB, None for other tags or when
+    // the offset is past the end of the table
+    pub fn string_value(&self, file: &crate::FileHeader) -> Option<String> {
+        if !self.tag.is_string() {
+            return None;
+        }
+        let strtab = file.dynamic_string_table()?;
+        Some(strtab.get(self.addr.0 as usize)?.into_owned())
     }
 }
 
 impl RelaEntry {
+    pub const SIZE: usize = 24;
+
+    // An x86-64 Elf64_Rela
     pub fn parse(input: parse::Input) -> parse::Result<Self> {
-        let (input, (offset, typ, sym, addend)) =
-            tuple((Addr::parse, RelType::parse, le_u32, Addr::parse))(input)?;
-        Ok((
-            input,
-            Self {
-                offset,
-                typ,
-                sym,
-                addend,
+        Self::parse_as(Class::Elf64, Endian::Little, Machine::X86_64, false)(input)
+    }
+
+    // Entries of a DT_RELA table, or with `implicit_addend` of a DT_REL one, whose addends are
+    // stored in the slots instead and are left at zero here. ELF32 packs the symbol and type into
+    // one 32-bit r_info, symbol in the top 24 bits. Types are `machine`'s.
+    pub fn parse_as<'a>(
+        class: Class,
+        endian: Endian,
+        machine: Machine,
+        implicit_addend: bool,
+    ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |input| {
+            let (input, (offset, info)) =
+                tuple((Addr::parse_as(class, endian), parse::word(class, endian)))(input)?;
+            let (sym, typ) = match class {
+                Class::Elf32 => ((info >> 8) as u32, info & 0xff),
+                Class::Elf64 => ((info >> 32) as u32, info & 0xffff_ffff),
+            };
+            let typ = RelType::from_number(machine, class, typ as u32);
+            let (input, addend) = match (implicit_addend, class) {
+                (true, _) => (input, Addend(0)),
+                (false, Class::Elf32) => map(parse::i32(endian), |a| Addend(a.into()))(input)?,
+                (false, Class::Elf64) => map(parse::i64(endian), Addend)(input)?,
+            };
+            Ok((
+                input,
+                Self {
+                    offset,
+                    typ,
+                    sym,
+                    addend,
+                },
+            ))
+        }
+    }
+
+    pub fn size(class: Class, implicit_addend: bool) -> usize {
+        let word = match class {
+            Class::Elf32 => 4,
+            Class::Elf64 => 8,
+        };
+        word * if implicit_addend { 2 } else { 3 }
+    }
+}
+
+impl RelType {
+    // r_info's type as `machine` numbers it. The word-sized S + A (R_386_32, R_ARM_ABS32,
+    // R_RISCV_32 or _64 by class) is Abs64 and the other TLS types are the word-sized ones
+    // likewise. RISC-V has no GLOB_DAT, its objects use R_RISCV_64 for GOT slots.
+    pub fn from_number(machine: Machine, class: Class, typ: u32) -> Self {
+        use RelType::*;
+        let known = match machine {
+            Machine::X86_64 => match typ {
+                1 => Some(Abs64),
+                5 => Some(Copy),
+                6 => Some(GlobalData),
+                7 => Some(JumpSlot),
+                8 => Some(Relative),
+                16 => Some(DtpMod64),
+                17 => Some(DtpOff64),
+                18 => Some(TpOff64),
+                37 => Some(IRelative),
+                _ => None,
+            },
+            Machine::X86 => match typ {
+                1 => Some(Abs64),
+                5 => Some(Copy),
+                6 => Some(GlobalData),
+                7 => Some(JumpSlot),
+                8 => Some(Relative),
+                14 => Some(TpOff64),
+                35 => Some(DtpMod64),
+                36 => Some(DtpOff64),
+                42 => Some(IRelative),
+                _ => None,
             },
-        ))
+            Machine::Arm => match typ {
+                2 => Some(Abs64),
+                17 => Some(DtpMod64),
+                18 => Some(DtpOff64),
+                19 => Some(TpOff64),
+                20 => Some(Copy),
+                21 => Some(GlobalData),
+                22 => Some(JumpSlot),
+                23 => Some(Relative),
+                160 => Some(IRelative),
+                _ => None,
+            },
+            Machine::AArch64 => match typ {
+                257 => Some(Abs64),
+                1024 => Some(Copy),
+                1025 => Some(GlobalData),
+                1026 => Some(JumpSlot),
+                1027 => Some(Relative),
+                1028 => Some(DtpMod64),
+                1029 => Some(DtpOff64),
+                1030 => Some(TpOff64),
+                1032 => Some(IRelative),
+                _ => None,
+            },
+            Machine::RiscV => match (class, typ) {
+                (Class::Elf32, 1) | (Class::Elf64, 2) => Some(Abs64),
+                (_, 3) => Some(Relative),
+                (_, 4) => Some(Copy),
+                (_, 5) => Some(JumpSlot),
+                (Class::Elf32, 6) | (Class::Elf64, 7) => Some(DtpMod64),
+                (Class::Elf32, 8) | (Class::Elf64, 9) => Some(DtpOff64),
+                (Class::Elf32, 10) | (Class::Elf64, 11) => Some(TpOff64),
+                (_, 58) => Some(IRelative),
+                _ => None,
+            },
+            _ => None,
+        };
+        known.unwrap_or(Unknown(typ))
     }
 }
 
+impl SymBind {
+    pub fn from_info(info: u8) -> Option<Self> {
+        Self::try_from(info >> 4).ok()
+    }
+}
+
+impl SymType {
+    pub fn from_info(info: u8) -> Option<Self> {
+        Self::try_from(info & 0xf).ok()
+    }
+}
+
+impl SymVisibility {
+    pub fn from_other(other: u8) -> Self {
+        match other & 0x3 {
+            1 => Self::Internal,
+            2 => Self::Hidden,
+            3 => Self::Protected,
+            _ => Self::Default,
+        }
+    }
+}
+
+impl Symbol {
+    pub const SIZE: usize = 24;
+
+    pub fn info(bind: SymBind, typ: SymType) -> u8 {
+        (bind as u8) << 4 | typ as u8
+    }
+
+    // None for OS and processor specific values this crate has no name for
+    pub fn typ(&self) -> Option<SymType> {
+        SymType::from_info(self.info)
+    }
+
+    pub fn bind(&self) -> Option<SymBind> {
+        SymBind::from_info(self.info)
+    }
+
+    pub fn visibility(&self) -> SymVisibility {
+        SymVisibility::from_other(self.other)
+    }
+
+    // Whether the dynamic linker would let other objects bind to this symbol
+    pub fn is_exported(&self) -> bool {
+        self.shndx != SectionIdx::Undef
+            && !self.name.is_empty()
+            && self.bind() != Some(SymBind::Local)
+            && matches!(
+                self.visibility(),
+                SymVisibility::Default | SymVisibility::Protected
+            )
+    }
+
+    // Index of the defining section, or None for undefined and special (ABS, COMMON) symbols
+    pub fn section_index(&self) -> Option<usize> {
+        match self.shndx {
+            SectionIdx::Index(i) => Some(i as usize),
+            _ => None,
+        }
+    }
+
+    // Where the symbol ends up when its object is loaded at `base`. Absolute symbols don't
+    // move; undefined and common ones have no address of their own.
+    pub fn address(&self, base: u64) -> Option<u64> {
+        match self.shndx {
+            SectionIdx::Abs => Some(self.value.0),
+            SectionIdx::Index(_) | SectionIdx::Xindex => Some(self.value.0 + base),
+            SectionIdx::Undef | SectionIdx::Common | SectionIdx::Reserved(_) => None,
+        }
+    }
+
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 16,
+            Class::Elf64 => Self::SIZE,
+        }
+    }
+
+    pub fn parse(input: parse::Input) -> parse::Result<Self> {
+        Self::parse_as(Class::Elf64, Endian::Little)(input)
+    }
+
+    // ELF32 moves st_value and st_size ahead of st_info
+    pub fn parse_as<'a>(
+        class: Class,
+        endian: Endian,
+    ) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |input| {
+            let shndx = map(parse::u16(endian), SectionIdx::from);
+            let word = || Addr::parse_as(class, endian);
+            let (input, (name_idx, info, other, shndx, value, size)) = match class {
+                Class::Elf32 => {
+                    let (input, (name_idx, value, size, info, other, shndx)) =
+                        tuple((
+                            parse::u32(endian),
+                            word(),
+                            map(parse::u32(endian), u64::from),
+                            le_u8,
+                            le_u8,
+                            shndx,
+                        ))(input)?;
+                    (input, (name_idx, info, other, shndx, value, size))
+                }
+                Class::Elf64 => tuple((
+                    parse::u32(endian),
+                    le_u8,
+                    le_u8,
+                    shndx,
+                    word(),
+                    parse::u64(endian),
+                ))(input)?,
+            };
+            Ok((
+                input,
+                Self {
+                    name: String::new(),
+                    name_idx,
+                    value,
+                    size,
+                    info,
+                    other,
+                    shndx,
+                    version: None,
+                },
+            ))
+        }
+    }
+}
+
+impl SectionGroup {
+    pub const GRP_COMDAT: u32 = 0x1;
+}
+
 impl ProgramHeader {
+    pub const SIZE: usize = 56;
+    pub const PN_XNUM: u16 = 0xffff;
+    // Most mem_size beyond file_size a segment may ask for, all of it zero-filled
+    pub const MAX_BSS: u64 = 64 << 30;
+
+    // mem_range panics on segments that fail this
+    pub fn check_sizes(&self) -> Result<(), SegmentSizeError> {
+        let (mem, file) = (self.mem_size.0, self.file_size.0);
+        if self.virt_addr.0.checked_add(mem).is_none() {
+            return Err(SegmentSizeError::Overflow(self.virt_addr.0, mem));
+        }
+        if mem < file {
+            return Err(SegmentSizeError::Truncated(mem, file));
+        }
+        if mem - file > Self::MAX_BSS {
+            return Err(SegmentSizeError::HugeBss(mem - file));
+        }
+        Ok(())
+    }
+
     pub fn file_range(&self) -> Range<Addr> {
         self.offset..self.offset + self.file_size
     }
@@ -260,36 +1109,101 @@ impl ProgramHeader {
         self.virt_addr..self.virt_addr + self.mem_size
     }
 
-    pub fn parse<'a>(
-        full_inp: parse::Input<'a>,
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 32,
+            Class::Elf64 => Self::SIZE,
+        }
+    }
+
+    pub fn parse<'a>(full: &'a Data, input: parse::Input<'a>) -> crate::parse::Result<'a, Self> {
+        let mut anomalies = parse::Anomalies::new(full, &Default::default());
+        Self::parse_as(Class::Elf64, Endian::Little, full, input, &mut anomalies)
+    }
+
+    // ELF32 has p_flags after p_memsz rather than after p_type. Notes and .eh_frame_hdr of
+    // big-endian files are left unparsed.
+    pub fn parse_as<'a>(
+        class: Class,
+        endian: Endian,
+        full: &'a Data,
         input: parse::Input<'a>,
+        anomalies: &mut parse::Anomalies<'a>,
     ) -> crate::parse::Result<'a, Self> {
+        let entry = input;
+        let word = || Addr::parse_as(class, endian);
+        let typ = || context("SegmentType", map(parse::u32(endian), SegmentType::from));
         let (input, (typ, flags, offset, virt_addr, phys_addr, file_size, mem_size, align)) =
-            tuple((
-                SegmentType::parse,
-                SegmentFlags::parse,
-                Addr::parse,
-                Addr::parse,
-                Addr::parse,
-                Addr::parse,
-                Addr::parse,
-                Addr::parse,
-            ))(input)?;
-        let slice = &full_inp[offset.into()..][..file_size.into()];
-        let (_, contents) = match typ {
-            SegmentType::Dynamic => map(
-                many_till(
-                    DynamicEntry::parse,
-                    verify(DynamicEntry::parse, |e| e.tag == DynamicTag::Null),
-                ),
-                |(entries, _nulls)| SegmentContent::Dynamic(entries),
-            )(slice)?,
-            _ => (slice, SegmentContent::Unknown),
+            match class {
+                Class::Elf32 => {
+                    let (input, (typ, offset, virt_addr, phys_addr, file_size, mem_size)) =
+                        tuple((typ(), word(), word(), word(), word(), word()))(input)?;
+                    let (input, (flags, align)) = tuple((parse::u32(endian), word()))(input)?;
+                    let fields = (
+                        typ, flags, offset, virt_addr, phys_addr, file_size, mem_size, align,
+                    );
+                    (input, fields)
+                }
+                Class::Elf64 => tuple((
+                    typ(),
+                    parse::u32(endian),
+                    word(),
+                    word(),
+                    word(),
+                    word(),
+                    word(),
+                    word(),
+                ))(input)?,
+            };
+        if let SegmentType::Other(typ) = typ {
+            let message = format!("unknown segment type {:#x}", typ);
+            anomalies.note(entry, "Segment type", Severity::Unusual, message)?;
+        }
+        let flags = SegmentBits::from_bits(flags);
+        if flags.unknown() != 0 {
+            let message = format!("unknown segment flags {:#x}", flags.unknown());
+            anomalies.note(entry, "Segment flags", Severity::Unusual, message)?;
+        }
+        let range = match parse::within(offset.0, file_size.0, full.len()) {
+            Some(range) => range,
+            None => {
+                let message = "file range extends past EOF, truncated";
+                anomalies.note(entry, "Segment offset and size", Severity::Broken, message)?;
+                parse::clamped(offset.0, file_size.0, full.len())
+            }
+        };
+        let slice = &full[range.clone()];
+        let contents = match typ {
+            // Separate debuginfo keeps the program headers but none of the segment contents
+            SegmentType::Dynamic if !slice.is_empty() => {
+                match Self::parse_dynamic(class, endian, slice, anomalies) {
+                    Ok((_, contents)) => contents,
+                    Err(e) if !anomalies.strictness.tolerates(Severity::Broken) => return Err(e),
+                    Err(_) => {
+                        let message = "dynamic table has no DT_NULL or malformed entries, left out";
+                        anomalies.note(slice, "Dynamic table", Severity::Broken, message)?;
+                        SegmentContent::Unknown
+                    }
+                }
+            }
+            SegmentType::GnuEhFrame | SegmentType::Note if endian == Endian::Big => {
+                SegmentContent::Unknown
+            }
+            SegmentType::GnuEhFrame => parse_eh_frame_hdr(slice, virt_addr)
+                .map_or(SegmentContent::Unknown, SegmentContent::EhFrameHdr),
+            SegmentType::TLS => SegmentContent::Tls(TlsTemplate {
+                image: virt_addr,
+                file_size: file_size.0,
+                mem_size: mem_size.0,
+                align: align.0.max(1),
+            }),
+            SegmentType::Note => SegmentContent::Notes(note::parse_notes(slice)),
+            _ => SegmentContent::Unknown,
         };
 
         let res = Self {
             typ,
-            flags: SegmentBits(flags),
+            flags,
             offset,
             virt_addr,
             phys_addr,
@@ -297,7 +1211,245 @@ impl ProgramHeader {
             mem_size,
             align,
             contents,
-            data: slice.to_vec(),
+            data: full.slice(range),
+        };
+        Ok((input, res))
+    }
+}
+
+impl ProgramHeader {
+    // Bytes the header takes with everything it owns. Its bytes in the file are left out, they
+    // belong to the buffer it was parsed from.
+    pub fn footprint(&self) -> usize {
+        let contents = match &self.contents {
+            SegmentContent::Dynamic(entries) => entries.capacity() * size_of::<DynamicEntry>(),
+            SegmentContent::EhFrameHdr(hdr) => hdr.table.capacity() * size_of::<(Addr, Addr)>(),
+            SegmentContent::Notes(notes) => notes
+                .iter()
+                .map(|note| note.name.capacity() + note.desc.capacity())
+                .sum::<usize>()
+                .saturating_add(notes.capacity() * size_of::<Note>()),
+            SegmentContent::Unknown | SegmentContent::Tls(_) => 0,
+        };
+        size_of::<Self>() + contents
+    }
+
+    fn parse_dynamic<'a>(
+        class: Class,
+        endian: Endian,
+        slice: parse::Input<'a>,
+        anomalies: &mut parse::Anomalies<'a>,
+    ) -> crate::parse::Result<'a, SegmentContent> {
+        let size = DynamicEntry::size(class);
+        let mut walk = Walk::new("dynamic entries", slice.len() / size);
+        let (mut rest, mut entries) = (slice, Vec::new());
+        loop {
+            if let Err(e) = walk.visit((slice.len() - rest.len()) as u64) {
+                anomalies.note(rest, "Dynamic table", Severity::Broken, e.to_string())?;
+                break;
+            }
+            let (next, entry) = DynamicEntry::parse_as(class, endian)(rest)?;
+            rest = next;
+            if entry.tag == DynamicTag::Null {
+                break;
+            }
+            entries.push(entry);
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            if let DynamicTag::Other(tag) = entry.tag {
+                let message = format!("unknown dynamic tag {:#x}", tag);
+                anomalies.note(&slice[i * size..], "DynamicTag", Severity::Unusual, message)?;
+            }
+        }
+        Ok((rest, SegmentContent::Dynamic(entries)))
+    }
+}
+
+impl Machine {
+    pub fn parse(input: parse::Input) -> parse::Result<Self> {
+        Self::parse_as(Endian::Little)(input)
+    }
+
+    pub fn parse_as<'a>(endian: Endian) -> impl Fn(parse::Input<'a>) -> parse::Result<'a, Self> {
+        move |input| context("Machine", map(parse::u16(endian), Self::from))(input)
+    }
+}
+
+impl From<u16> for Machine {
+    fn from(machine: u16) -> Self {
+        match machine {
+            0x02 => Self::Sparc,
+            0x03 => Self::X86,
+            0x04 => Self::M68k,
+            0x08 => Self::Mips,
+            0x14 => Self::PowerPC,
+            0x15 => Self::PowerPC64,
+            0x16 => Self::S390,
+            0x28 => Self::Arm,
+            0x2a => Self::SuperH,
+            0x2b => Self::SparcV9,
+            0x32 => Self::IA64,
+            0x3e => Self::X86_64,
+            0xb7 => Self::AArch64,
+            0xf3 => Self::RiscV,
+            0xf7 => Self::Bpf,
+            0x102 => Self::LoongArch,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<Machine> for u16 {
+    fn from(machine: Machine) -> Self {
+        match machine {
+            Machine::Sparc => 0x02,
+            Machine::X86 => 0x03,
+            Machine::M68k => 0x04,
+            Machine::Mips => 0x08,
+            Machine::PowerPC => 0x14,
+            Machine::PowerPC64 => 0x15,
+            Machine::S390 => 0x16,
+            Machine::Arm => 0x28,
+            Machine::SuperH => 0x2a,
+            Machine::SparcV9 => 0x2b,
+            Machine::IA64 => 0x32,
+            Machine::X86_64 => 0x3e,
+            Machine::AArch64 => 0xb7,
+            Machine::RiscV => 0xf3,
+            Machine::Bpf => 0xf7,
+            Machine::LoongArch => 0x102,
+            Machine::Other(other) => other,
+        }
+    }
+}
+
+impl From<u16> for SectionIdx {
+    fn from(shndx: u16) -> Self {
+        match shndx {
+            0 => Self::Undef,
+            SectionHeader::SHN_ABS => Self::Abs,
+            SectionHeader::SHN_COMMON => Self::Common,
+            SectionHeader::SHN_XINDEX => Self::Xindex,
+            i if i >= SectionHeader::SHN_LORESERVE => Self::Reserved(i),
+            i => Self::Index(u32::from(i)),
+        }
+    }
+}
+
+// readelf's Ndx column
+impl fmt::Debug for SectionIdx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Undef => write!(f, "UND"),
+            Self::Abs => write!(f, "ABS"),
+            Self::Common => write!(f, "COM"),
+            Self::Index(i) => write!(f, "{}", i),
+            Self::Xindex => write!(f, "XINDEX"),
+            Self::Reserved(i) => write!(f, "{:#x}", i),
+        }
+    }
+}
+
+impl SectionHeader {
+    pub const SIZE: usize = 64;
+    pub const SHN_LORESERVE: u16 = 0xff00;
+    pub const SHN_ABS: u16 = 0xfff1;
+    pub const SHN_COMMON: u16 = 0xfff2;
+    pub const SHN_XINDEX: u16 = 0xffff;
+
+    pub fn file_range(&self) -> Range<Addr> {
+        match self.typ {
+            SectionType::NoBits => self.offset..self.offset,
+            _ => self.offset..self.offset + self.size,
+        }
+    }
+
+    pub fn mem_range(&self) -> Range<Addr> {
+        self.addr..self.addr + self.size
+    }
+
+    // Like ProgramHeader::footprint
+    pub fn footprint(&self) -> usize {
+        size_of::<Self>() + self.name.capacity()
+    }
+
+    // The section's bytes in the file, if all of them are there. `data` holds whatever the file
+    // has, which is nothing for NULL and NOBITS sections and a short slice for truncated ones.
+    pub fn contents(&self) -> Result<&[u8], SectionContentError> {
+        match self.typ {
+            SectionType::Null => Err(SectionContentError::Null),
+            SectionType::NoBits => Err(SectionContentError::NoBits(self.size.0)),
+            _ if (self.data.len() as u64) < self.size.0 => Err(SectionContentError::PastEof {
+                offset: self.offset.0,
+                size: self.size.0,
+                available: self.data.len() as u64,
+            }),
+            _ => Ok(&self.data),
+        }
+    }
+
+    pub fn size(class: Class) -> usize {
+        match class {
+            Class::Elf32 => 40,
+            Class::Elf64 => Self::SIZE,
+        }
+    }
+
+    pub fn parse<'a>(full: &'a Data, input: parse::Input<'a>) -> crate::parse::Result<'a, Self> {
+        let mut anomalies = parse::Anomalies::new(full, &Default::default());
+        Self::parse_as(Class::Elf64, Endian::Little, full, input, &mut anomalies)
+    }
+
+    // Same fields in both classes, the address-sized ones narrower in ELF32
+    pub fn parse_as<'a>(
+        class: Class,
+        endian: Endian,
+        full: &'a Data,
+        input: parse::Input<'a>,
+        anomalies: &mut parse::Anomalies<'a>,
+    ) -> crate::parse::Result<'a, Self> {
+        let entry = input;
+        let word = || Addr::parse_as(class, endian);
+        let u32 = || parse::u32(endian);
+        let (input, (name_idx, typ, flags, addr, offset, size)) = tuple((
+            u32(),
+            context("SectionType", map(u32(), SectionType::from)),
+            SectionBits::parse_as(class, endian),
+            word(),
+            word(),
+            word(),
+        ))(input)?;
+        let (input, (link, info, align, entsize)) = tuple((u32(), u32(), word(), word()))(input)?;
+
+        if let SectionType::Other(typ) = typ {
+            let message = format!("unknown section type {:#x}", typ);
+            anomalies.note(entry, "Section type", Severity::Unusual, message)?;
+        }
+        let data = match typ {
+            SectionType::NoBits | SectionType::Null => Data::default(),
+            _ => match parse::within(offset.0, size.0, full.len()) {
+                Some(range) => full.slice(range),
+                None => {
+                    let message = "file range extends past EOF, truncated";
+                    anomalies.note(entry, "Section offset and size", Severity::Broken, message)?;
+                    full.slice(parse::clamped(offset.0, size.0, full.len()))
+                }
+            },
+        };
+
+        let res = Self {
+            name: String::new(),
+            name_idx,
+            typ,
+            flags,
+            addr,
+            offset,
+            size,
+            link,
+            info,
+            align,
+            entsize,
+            data,
         };
         Ok((input, res))
     }
@@ -0,0 +1,215 @@
+use crate::{
+    cstr_at,
+    detect::Endian,
+    prelude::*,
+    types::*,
+    u32_at,
+    walk::{Walk, WalkError},
+    FileHeader,
+};
+
+// Version index 0 marks local symbols, 1 unversioned global ones
+pub const VER_NDX_GLOBAL: u16 = 1;
+pub const VERSYM_HIDDEN: u16 = 0x8000;
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolVersion {
+    pub name: String,
+    // Only reachable by an explicit `sym@VERSION` reference, not by default
+    pub hidden: bool,
+    // Library expected to define the version, for versions the file requires
+    pub file: Option<String>,
+}
+
+// A version the file requires from one of its dependencies (an SHT_GNU_verneed entry)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionNeed {
+    pub file: String,
+    pub name: String,
+    pub index: u16,
+}
+
+impl FileHeader {
+    // A version table and its string table, from the section headers or, for files stripped of
+    // them, through the dynamic section the way ld.so finds it
+    // Version tables are only read little-endian
+    fn version_section(&self, typ: SectionType, tag: DynamicTag) -> Option<(&[u8], &[u8])> {
+        if self.endian == Endian::Big {
+            return None;
+        }
+        if let Some(sh) = self.section_headers.iter().find(|sh| sh.typ == typ) {
+            let strtab = self.section_headers.get(sh.link as usize)?;
+            return Some((&sh.data, &strtab.data));
+        }
+        Some((
+            self.bytes_at(self.dynamic_entry(tag)?)?,
+            self.dynamic_strtab()?,
+        ))
+    }
+
+    // How many entries a version table says it has: sh_info, or DT_VERDEFNUM and DT_VERNEEDNUM
+    // without section headers. Failing both, as many as its bytes have room for.
+    fn version_count(&self, typ: SectionType, tag: DynamicTag, room: usize) -> usize {
+        match self.section_headers.iter().find(|sh| sh.typ == typ) {
+            Some(sh) => sh.info as usize,
+            None => self
+                .dynamic_entry(tag)
+                .map_or(room, |count| count.0 as usize),
+        }
+    }
+
+    // Version names defined by the file (SHT_GNU_verdef), by version index
+    pub fn version_definitions(&self) -> Vec<(u16, String)> {
+        self.walk_version_definitions().0
+    }
+
+    // The definitions up to where the table stops making sense, and why it does
+    pub fn walk_version_definitions(&self) -> (Vec<(u16, String)>, Option<WalkError>) {
+        let (data, strtab) = match self.version_section(SectionType::GnuVerDef, DynamicTag::VerDef)
+        {
+            Some(section) => section,
+            None => return (Vec::new(), None),
+        };
+        // An Elf_Verdef is 20 bytes
+        let count = self.version_count(
+            SectionType::GnuVerDef,
+            DynamicTag::VerDefNum,
+            data.len() / 20,
+        );
+        let mut walk = Walk::new("version definitions", count);
+        let mut defs = Vec::new();
+        let mut pos = 0;
+        while let (Some(index), Some(aux), Some(next)) = (
+            u16_at(data, pos + 4),
+            u32_at(data, pos + 12),
+            u32_at(data, pos + 16),
+        ) {
+            if let Err(e) = walk.visit(pos as u64) {
+                return (defs, Some(e));
+            }
+            if let Some(name) = u32_at(data, pos + aux as usize) {
+                defs.push((index, cstr_at(strtab, name as usize).into_owned()));
+            }
+            if next == 0 {
+                break;
+            }
+            pos += next as usize;
+        }
+        (defs, None)
+    }
+
+    // Versions the file requires (SHT_GNU_verneed), with the library each must come from
+    pub fn version_needs(&self) -> Vec<VersionNeed> {
+        self.walk_version_needs().0
+    }
+
+    // The needed versions up to where the table stops making sense, and why it does
+    pub fn walk_version_needs(&self) -> (Vec<VersionNeed>, Option<WalkError>) {
+        let (data, strtab) =
+            match self.version_section(SectionType::GnuVerNeed, DynamicTag::VerNeed) {
+                Some(section) => section,
+                None => return (Vec::new(), None),
+            };
+        // An Elf_Verneed is 16 bytes
+        let count = self.version_count(
+            SectionType::GnuVerNeed,
+            DynamicTag::VerNeedNum,
+            data.len() / 16,
+        );
+        let mut walk = Walk::new("version needs", count);
+        let mut needs = Vec::new();
+        let mut pos = 0;
+        while let (Some(count), Some(file), Some(aux), Some(next)) = (
+            u16_at(data, pos + 2),
+            u32_at(data, pos + 4),
+            u32_at(data, pos + 8),
+            u32_at(data, pos + 12),
+        ) {
+            if let Err(e) = walk.visit(pos as u64) {
+                return (needs, Some(e));
+            }
+            let file = cstr_at(strtab, file as usize).into_owned();
+            let mut aux_pos = pos + aux as usize;
+            for _ in 0..count {
+                let (index, name, aux_next) = match (
+                    u16_at(data, aux_pos + 6),
+                    u32_at(data, aux_pos + 8),
+                    u32_at(data, aux_pos + 12),
+                ) {
+                    (Some(index), Some(name), Some(next)) => (index, name, next),
+                    _ => break,
+                };
+                needs.push(VersionNeed {
+                    file: file.clone(),
+                    name: cstr_at(strtab, name as usize).into_owned(),
+                    index,
+                });
+                if aux_next == 0 {
+                    break;
+                }
+                aux_pos += aux_next as usize;
+            }
+            if next == 0 {
+                break;
+            }
+            pos += next as usize;
+        }
+        (needs, None)
+    }
+
+    // SHT_GNU_versym, else DT_VERSYM cut to the size of the dynamic symbol table it parallels
+    fn version_symbols(&self) -> Option<&[u8]> {
+        if self.endian == Endian::Big {
+            return None;
+        }
+        if let Some(sh) = self
+            .section_headers
+            .iter()
+            .find(|sh| sh.typ == SectionType::GnuVerSym)
+        {
+            return Some(&sh.data);
+        }
+        let versym = self.bytes_at(self.dynamic_entry(DynamicTag::VerSym)?)?;
+        let count = self.dynamic_symbol_count(self.dynamic_symtab()?)?;
+        versym.get(..count * 2)
+    }
+
+    // Version of every .dynsym entry, in symbol table order. Local and unversioned symbols
+    // map to None.
+    pub fn dynamic_symbol_versions(&self) -> Vec<Option<SymbolVersion>> {
+        let versym = match self.version_symbols() {
+            Some(versym) => versym,
+            None => return Vec::new(),
+        };
+        let defs = self.version_definitions();
+        let needs = self.version_needs();
+        versym
+            .chunks_exact(2)
+            .map(|c| {
+                let raw = u16::from_le_bytes([c[0], c[1]]);
+                let index = raw & !VERSYM_HIDDEN;
+                if index <= VER_NDX_GLOBAL {
+                    return None;
+                }
+                let (name, file) = match defs.iter().find(|(i, _)| *i == index) {
+                    Some((_, name)) => (name.clone(), None),
+                    None => {
+                        let need = needs.iter().find(|n| n.index == index)?;
+                        (need.name.clone(), Some(need.file.clone()))
+                    }
+                };
+                Some(SymbolVersion {
+                    name,
+                    hidden: raw & VERSYM_HIDDEN != 0,
+                    file,
+                })
+            })
+            .collect()
+    }
+}
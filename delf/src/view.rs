@@ -0,0 +1,112 @@
+use core::ops::Range;
+
+use crate::{
+    prelude::*,
+    types::{Addr, SegmentType},
+    FileHeader,
+};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+// How addresses are written out. Addr's own Debug is for any value of the type (sizes and file
+// offsets are Addrs too), so commands that print addresses go through an AddrView instead.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AddrMode {
+    // As the file has them
+    #[default]
+    File,
+    // As `base+0x1a0`, or the runtime address when a load base is known
+    Base,
+    // As `LOAD[2]+0x1a0`, relative to the segment they fall in
+    Segment,
+}
+
+impl AddrMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "file" => Some(Self::File),
+            "base" => Some(Self::Base),
+            "segment" => Some(Self::Segment),
+            _ => None,
+        }
+    }
+}
+
+pub struct AddrView {
+    mode: AddrMode,
+    // Where the first LOAD segment's page starts in the file's own address space
+    link_base: u64,
+    load_base: Option<u64>,
+    segments: Vec<(usize, Range<Addr>)>,
+}
+
+impl AddrView {
+    pub fn new(file: &FileHeader, mode: AddrMode, load_base: Option<u64>) -> Self {
+        let segments: Vec<_> = file
+            .program_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, ph)| ph.typ == SegmentType::Load)
+            .map(|(i, ph)| (i, ph.mem_range()))
+            .collect();
+        let link_base = segments
+            .iter()
+            .map(|(_, range)| range.start.0)
+            .min()
+            .map_or(0, |start| start & !(PAGE_SIZE - 1));
+        Self {
+            mode,
+            link_base,
+            load_base,
+            segments,
+        }
+    }
+
+    pub fn show(&self, addr: Addr) -> String {
+        match self.mode {
+            AddrMode::File => format!("{:#x}", addr.0),
+            AddrMode::Base => match (addr.0.checked_sub(self.link_base), self.load_base) {
+                (Some(offset), Some(load)) => format!("{:#x}", load.wrapping_add(offset)),
+                (Some(offset), None) => format!("base+{:#x}", offset),
+                (None, _) => format!("{:#x}", addr.0),
+            },
+            AddrMode::Segment => match self
+                .segments
+                .iter()
+                .find(|(_, range)| range.contains(&addr))
+            {
+                Some((i, range)) => format!("LOAD[{}]+{:#x}", i, addr.0 - range.start.0),
+                None => format!("{:#x}", addr.0),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views() {
+        let view = |mode, load_base| AddrView {
+            mode,
+            link_base: 0x40_0000,
+            load_base,
+            segments: vec![
+                (2, Addr(0x40_0000)..Addr(0x40_0800)),
+                (3, Addr(0x40_1000)..Addr(0x40_1200)),
+            ],
+        };
+        let addr = Addr(0x40_11a0);
+        assert_eq!(view(AddrMode::File, None).show(addr), "0x4011a0");
+        assert_eq!(view(AddrMode::Base, None).show(addr), "base+0x11a0");
+        assert_eq!(
+            view(AddrMode::Base, Some(0x7f00_0000_0000)).show(addr),
+            "0x7f00000011a0"
+        );
+        assert_eq!(view(AddrMode::Segment, None).show(addr), "LOAD[3]+0x1a0");
+        // Outside every segment and below the base, addresses print as they are
+        assert_eq!(view(AddrMode::Segment, None).show(Addr(0x10)), "0x10");
+        assert_eq!(view(AddrMode::Base, None).show(Addr(0x10)), "0x10");
+    }
+}
@@ -0,0 +1,94 @@
+use alloc::collections::BTreeSet;
+use core::fmt;
+
+use crate::{prelude::*, FileHeader};
+
+// Keeps a walk over a list linked through the file itself, like the version tables chained by
+// `vd_next`, from going on forever when a malformed or hostile file points an entry back at one
+// already seen, or chains more entries than the list says it has
+pub struct Walk {
+    what: &'static str,
+    limit: usize,
+    seen: BTreeSet<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalkError {
+    Cycle { what: &'static str, at: u64 },
+    TooLong { what: &'static str, limit: usize },
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cycle { what, at } => write!(f, "{} loop back to the entry at {:#x}", what, at),
+            Self::TooLong { what, limit } => write!(f, "{} run past {} entries", what, limit),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WalkError {}
+
+impl Walk {
+    // `what` names the list in errors, in the plural; `limit` is the most entries it can
+    // reasonably have, usually what the bytes holding it have room for
+    pub fn new(what: &'static str, limit: usize) -> Self {
+        Self {
+            what,
+            limit,
+            seen: BTreeSet::new(),
+        }
+    }
+
+    // To be called on reaching each entry, with where it is
+    pub fn visit(&mut self, at: u64) -> Result<(), WalkError> {
+        if self.seen.len() >= self.limit {
+            return Err(WalkError::TooLong {
+                what: self.what,
+                limit: self.limit,
+            });
+        }
+        if !self.seen.insert(at) {
+            return Err(WalkError::Cycle {
+                what: self.what,
+                at,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl FileHeader {
+    // Where the file's linked tables stop making sense. Their readers stop there too, keeping
+    // the entries before it.
+    pub fn walk_errors(&self) -> Vec<WalkError> {
+        self.walk_version_definitions()
+            .1
+            .into_iter()
+            .chain(self.walk_version_needs().1)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Walk, WalkError};
+
+    #[test]
+    fn stops_cycles_and_runaways() {
+        let mut walk = Walk::new("chains", 3);
+        assert_eq!(walk.visit(0x10), Ok(()));
+        assert_eq!(walk.visit(0x20), Ok(()));
+        let cycle = walk.visit(0x10).unwrap_err();
+        assert_eq!(cycle.to_string(), "chains loop back to the entry at 0x10");
+        assert_eq!(walk.visit(0x30), Ok(()));
+        assert_eq!(
+            walk.visit(0x40),
+            Err(WalkError::TooLong {
+                what: "chains",
+                limit: 3
+            })
+        );
+    }
+}
@@ -0,0 +1,881 @@
+use std::io::{self, Write};
+
+use crate::{
+    detect::Class,
+    layout::{self, LayoutError, LayoutItem, Placement},
+    types::*,
+    FileHeader,
+};
+
+// Where a segment's bytes come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentData {
+    // Bytes of its own at `vaddr`, zero-filled in memory up to `mem_size`. A LOAD segment with
+    // `headers` goes at file offset 0 and its first bytes are replaced by the ELF and program
+    // headers, the way linkers map them with the first page.
+    Bytes {
+        vaddr: Addr,
+        data: Vec<u8>,
+        mem_size: u64,
+        headers: bool,
+    },
+    // A part of a segment with bytes of its own, as PT_DYNAMIC and PT_TLS are of a LOAD segment
+    Within {
+        segment: usize,
+        offset: u64,
+        file_size: u64,
+        mem_size: u64,
+    },
+    // A segment of a type the writer has no name for, carried over as found: its bytes, sizes and
+    // addresses, p_paddr included, are written back unchanged, only its file offset moves. Never
+    // counted as loadable, whatever the OS that made it does with it.
+    Passthrough {
+        vaddr: Addr,
+        paddr: Addr,
+        data: Vec<u8>,
+        mem_size: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentBuilder {
+    pub typ: SegmentType,
+    pub flags: SegmentBits,
+    pub align: u64,
+    pub data: SegmentData,
+}
+
+// Where a section's bytes come from. Offsets into a segment are in memory, so .bss can sit past
+// the end of its segment's file bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SectionData {
+    Bytes {
+        addr: Addr,
+        data: Vec<u8>,
+    },
+    NoBits {
+        addr: Addr,
+        size: u64,
+    },
+    Within {
+        segment: usize,
+        offset: u64,
+        size: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionBuilder {
+    pub name: String,
+    pub typ: SectionType,
+    pub flags: SectionBits,
+    pub align: u64,
+    pub entsize: u64,
+    pub link: u32,
+    pub info: u32,
+    pub data: SectionData,
+}
+
+// An ELF image to write out. The writer picks every file offset: segment and section bytes are
+// laid out in order after the headers, each LOAD segment at an offset congruent to its address,
+// and the section header table goes last. Addresses are kept as given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileBuilder {
+    pub class: Class,
+    pub typ: Type,
+    pub machine: Machine,
+    pub flags: u32,
+    pub entry: Addr,
+    pub segments: Vec<SegmentBuilder>,
+    // Everything after the null section, which is written first regardless
+    pub sections: Vec<SectionBuilder>,
+    // Where among `sections` the generated .shstrtab goes, last when None
+    pub shstrtab: Option<usize>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WriteError {
+    #[error("Segment {0} is inside segment {1}, which has no bytes of its own")]
+    BadParent(usize, usize),
+    #[error("Only segment {0} or segment {1} can start with the headers")]
+    SecondHeaders(usize, usize),
+    #[error("Segment {0} starts with the headers but is {1:#x} bytes, they need {2:#x}")]
+    HeadersDontFit(usize, u64, u64),
+    #[error("{what} runs past the end of segment {segment}")]
+    OutOfSegment { what: String, segment: usize },
+    #[error("{0} segments or {1} sections is more than the ELF header can count")]
+    TooMany(usize, usize),
+    #[error(transparent)]
+    Layout(#[from] LayoutError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// Sizes of the ELF header, a program header and a section header
+fn entry_sizes(class: Class) -> (u64, u64, u64) {
+    match class {
+        Class::Elf32 => (52, 32, 40),
+        Class::Elf64 => (64, 56, 64),
+    }
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    (value + align - 1) & !(align - 1)
+}
+
+fn checked_align(index: usize, align: u64) -> Result<u64, LayoutError> {
+    match align.max(1) {
+        align if align.is_power_of_two() => Ok(align),
+        _ => Err(LayoutError::BadAlign(index, align)),
+    }
+}
+
+// Where the writer put a segment or section: file offset, address, file and memory size
+#[derive(Debug, Clone, Copy, Default)]
+struct Placed {
+    offset: u64,
+    addr: u64,
+    file_size: u64,
+    mem_size: u64,
+}
+
+impl FileBuilder {
+    pub fn new(class: Class, typ: Type, machine: Machine) -> Self {
+        Self {
+            class,
+            typ,
+            machine,
+            flags: 0,
+            entry: Addr(0),
+            segments: Vec::new(),
+            sections: Vec::new(),
+            shstrtab: None,
+        }
+    }
+
+    // Everything needed to write `file` back out. Segments and allocated sections that lie in a
+    // LOAD segment become parts of it, so they move with it; .shstrtab is regenerated in place.
+    pub fn from_file(file: &FileHeader) -> Self {
+        let loads: Vec<(usize, &ProgramHeader)> = file
+            .program_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, ph)| ph.typ == SegmentType::Load)
+            .collect();
+        // The LOAD segment mapping `addr..addr + mem_size` from the file bytes at `offset`
+        let load_holding = |addr: Addr, offset: Option<Addr>, mem_size: u64| {
+            loads.iter().copied().find(|(_, load)| {
+                addr >= load.virt_addr
+                    && addr.0 + mem_size <= load.virt_addr.0 + load.mem_size.0
+                    && offset.is_none_or(|offset| {
+                        offset >= load.offset
+                            && offset.0 - load.offset.0 == addr.0 - load.virt_addr.0
+                    })
+            })
+        };
+
+        let segments = file
+            .program_headers
+            .iter()
+            .map(|ph| {
+                let holder = match (ph.typ, ph.mem_size.0) {
+                    (SegmentType::Load, _) | (_, 0) => None,
+                    _ => load_holding(ph.virt_addr, Some(ph.offset), ph.mem_size.0),
+                };
+                let data = match (holder, ph.typ) {
+                    (Some((segment, load)), _) => SegmentData::Within {
+                        segment,
+                        offset: ph.virt_addr.0 - load.virt_addr.0,
+                        file_size: ph.file_size.0,
+                        mem_size: ph.mem_size.0,
+                    },
+                    (None, SegmentType::Other(_)) => SegmentData::Passthrough {
+                        vaddr: ph.virt_addr,
+                        paddr: ph.phys_addr,
+                        data: ph.data.to_vec(),
+                        mem_size: ph.mem_size.0,
+                    },
+                    (None, _) => SegmentData::Bytes {
+                        vaddr: ph.virt_addr,
+                        data: ph.data.to_vec(),
+                        mem_size: ph.mem_size.0,
+                        headers: ph.typ == SegmentType::Load && ph.offset.0 == 0,
+                    },
+                };
+                SegmentBuilder {
+                    typ: ph.typ,
+                    flags: ph.flags,
+                    align: ph.align.0,
+                    data,
+                }
+            })
+            .collect();
+
+        let mut shstrtab = None;
+        let mut sections = Vec::new();
+        for sh in file.section_headers.iter().skip(1) {
+            if sh.name == ".shstrtab" && sh.typ == SectionType::StrTab {
+                shstrtab = Some(sections.len());
+                continue;
+            }
+            let offset = match sh.typ {
+                SectionType::NoBits => None,
+                _ => Some(sh.offset),
+            };
+            let holder = match sh.flags.contains(SectionFlags::Alloc) {
+                true => load_holding(sh.addr, offset, sh.size.0),
+                false => None,
+            };
+            let data = match (holder, sh.typ) {
+                (Some((segment, load)), _) => SectionData::Within {
+                    segment,
+                    offset: sh.addr.0 - load.virt_addr.0,
+                    size: sh.size.0,
+                },
+                (None, SectionType::NoBits) => SectionData::NoBits {
+                    addr: sh.addr,
+                    size: sh.size.0,
+                },
+                (None, _) => SectionData::Bytes {
+                    addr: sh.addr,
+                    data: sh.data.to_vec(),
+                },
+            };
+            sections.push(SectionBuilder {
+                name: sh.name.clone(),
+                typ: sh.typ,
+                flags: sh.flags,
+                align: sh.align.0,
+                entsize: sh.entsize.0,
+                link: sh.link,
+                info: sh.info,
+                data,
+            });
+        }
+
+        Self {
+            class: file.class,
+            typ: file.typ,
+            machine: file.machine,
+            flags: file.flags,
+            entry: file.entry_point,
+            segments,
+            sections,
+            shstrtab,
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), WriteError> {
+        w.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WriteError> {
+        let class = self.class;
+        let (ehsize, phentsize, shentsize) = entry_sizes(class);
+        let (sections, shstrndx) = self.sections_with_names();
+        // Section count and index need to fit e_shnum and e_shstrndx without the SHN_XINDEX escapes
+        if self.segments.len() >= 0xffff || sections.len() + 1 >= 0xff00 {
+            return Err(WriteError::TooMany(self.segments.len(), sections.len()));
+        }
+        let header_size = ehsize + phentsize * self.segments.len() as u64;
+
+        let mut cursor = header_size;
+        let mut items = Vec::new();
+        let mut placements = Vec::new();
+        let mut segments = vec![Placed::default(); self.segments.len()];
+
+        // The segment carrying the headers goes first, at offset 0
+        let mut carrier = None;
+        for (i, segment) in self.segments.iter().enumerate() {
+            if let SegmentData::Bytes {
+                vaddr,
+                data,
+                mem_size,
+                headers: true,
+            } = &segment.data
+            {
+                if let Some(first) = carrier {
+                    return Err(WriteError::SecondHeaders(first, i));
+                }
+                if (data.len() as u64) < header_size {
+                    return Err(WriteError::HeadersDontFit(
+                        i,
+                        data.len() as u64,
+                        header_size,
+                    ));
+                }
+                carrier = Some(i);
+                segments[i] = Placed {
+                    offset: 0,
+                    addr: vaddr.0,
+                    file_size: data.len() as u64,
+                    mem_size: *mem_size,
+                };
+                cursor = data.len() as u64;
+            }
+        }
+        if carrier.is_none() {
+            items.push(LayoutItem {
+                file_size: header_size,
+                mem_size: 0,
+                align: 1,
+                loadable: false,
+            });
+            placements.push(Placement {
+                offset: Addr(0),
+                vaddr: Addr(0),
+            });
+        }
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            let loadable = segment.typ == SegmentType::Load;
+            let (vaddr, data, mem_size) = match &segment.data {
+                SegmentData::Bytes { headers: true, .. } | SegmentData::Within { .. } => continue,
+                SegmentData::Bytes {
+                    vaddr,
+                    data,
+                    mem_size,
+                    ..
+                }
+                | SegmentData::Passthrough {
+                    vaddr,
+                    data,
+                    mem_size,
+                    ..
+                } => (vaddr.0, data, *mem_size),
+            };
+            let align = checked_align(i, segment.align)?;
+            let offset = match (loadable, data.is_empty()) {
+                // Nothing in the file, as PT_GNU_STACK
+                (false, true) => 0,
+                (true, _) => cursor + (vaddr.wrapping_sub(cursor) & (align - 1)),
+                (false, false) => align_up(cursor, align),
+            };
+            cursor = cursor.max(offset + data.len() as u64);
+            segments[i] = Placed {
+                offset,
+                addr: vaddr,
+                file_size: data.len() as u64,
+                mem_size,
+            };
+        }
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            let (parent, offset, file_size, mem_size) = match segment.data {
+                SegmentData::Within {
+                    segment,
+                    offset,
+                    file_size,
+                    mem_size,
+                } => (segment, offset, file_size, mem_size),
+                SegmentData::Bytes { .. } | SegmentData::Passthrough { .. } => {
+                    items.push(LayoutItem {
+                        file_size: segments[i].file_size,
+                        mem_size: segments[i].mem_size,
+                        align: segment.align,
+                        loadable: segment.typ == SegmentType::Load,
+                    });
+                    placements.push(Placement {
+                        offset: Addr(segments[i].offset),
+                        vaddr: match segment.typ {
+                            SegmentType::Load => Addr(segments[i].addr),
+                            _ => Addr(0),
+                        },
+                    });
+                    continue;
+                }
+            };
+            let holder = match self.segments.get(parent) {
+                Some(SegmentBuilder {
+                    data: SegmentData::Bytes { .. },
+                    ..
+                }) => segments[parent],
+                _ => return Err(WriteError::BadParent(i, parent)),
+            };
+            if offset + file_size > holder.file_size
+                || offset + mem_size > holder.mem_size.max(holder.file_size)
+            {
+                return Err(WriteError::OutOfSegment {
+                    what: format!("Segment {}", i),
+                    segment: parent,
+                });
+            }
+            segments[i] = Placed {
+                offset: holder.offset + offset,
+                addr: holder.addr + offset,
+                file_size,
+                mem_size,
+            };
+        }
+
+        let mut placed_sections = Vec::with_capacity(sections.len());
+        for (i, (_, section)) in sections.iter().enumerate() {
+            let placed = match &section.data {
+                SectionData::Bytes { addr, data } => {
+                    let align = checked_align(i, section.align)?;
+                    let offset = align_up(cursor, align);
+                    cursor = offset + data.len() as u64;
+                    items.push(LayoutItem {
+                        file_size: data.len() as u64,
+                        mem_size: 0,
+                        align,
+                        loadable: false,
+                    });
+                    placements.push(Placement {
+                        offset: Addr(offset),
+                        vaddr: Addr(0),
+                    });
+                    Placed {
+                        offset,
+                        addr: addr.0,
+                        file_size: data.len() as u64,
+                        mem_size: 0,
+                    }
+                }
+                SectionData::NoBits { addr, size } => Placed {
+                    offset: cursor,
+                    addr: addr.0,
+                    file_size: *size,
+                    mem_size: 0,
+                },
+                SectionData::Within {
+                    segment,
+                    offset,
+                    size,
+                } => {
+                    let holder = *segments.get(*segment).ok_or(WriteError::OutOfSegment {
+                        what: format!("Section {}", section.name),
+                        segment: *segment,
+                    })?;
+                    let limit = match section.typ {
+                        SectionType::NoBits => holder.mem_size,
+                        _ => holder.file_size,
+                    };
+                    if offset + size > limit {
+                        return Err(WriteError::OutOfSegment {
+                            what: format!("Section {}", section.name),
+                            segment: *segment,
+                        });
+                    }
+                    Placed {
+                        offset: holder.offset + offset,
+                        addr: holder.addr + offset,
+                        file_size: *size,
+                        mem_size: 0,
+                    }
+                }
+            };
+            placed_sections.push(placed);
+        }
+
+        let shoff = align_up(cursor, 8);
+        let shnum = sections.len() as u64 + 1;
+        items.push(LayoutItem {
+            file_size: shnum * shentsize,
+            mem_size: 0,
+            align: 8,
+            loadable: false,
+        });
+        placements.push(Placement {
+            offset: Addr(shoff),
+            vaddr: Addr(0),
+        });
+        layout::validate(&items, &placements)?;
+
+        let mut out = vec![0; (shoff + shnum * shentsize) as usize];
+        for (segment, placed) in self.segments.iter().zip(&segments) {
+            if let SegmentData::Bytes { data, .. } | SegmentData::Passthrough { data, .. } =
+                &segment.data
+            {
+                put(&mut out, placed.offset, data);
+            }
+        }
+        for ((_, section), placed) in sections.iter().zip(&placed_sections) {
+            if let SectionData::Bytes { data, .. } = &section.data {
+                put(&mut out, placed.offset, data);
+            }
+        }
+
+        let mut header = vec![0x7f, b'E', b'L', b'F'];
+        header.push(match class {
+            Class::Elf32 => 1,
+            Class::Elf64 => 2,
+        });
+        header.extend(&[1, 1]);
+        header.resize(16, 0);
+        header.extend(&(self.typ as u16).to_le_bytes());
+        header.extend(&u16::from(self.machine).to_le_bytes());
+        header.extend(&1u32.to_le_bytes());
+        header.extend(word(class, self.entry.0));
+        let phoff = match self.segments.is_empty() {
+            true => 0,
+            false => ehsize,
+        };
+        header.extend(word(class, phoff));
+        header.extend(word(class, shoff));
+        header.extend(&self.flags.to_le_bytes());
+        for half in [
+            ehsize,
+            phentsize,
+            self.segments.len() as u64,
+            shentsize,
+            shnum,
+            shstrndx as u64,
+        ] {
+            header.extend(&(half as u16).to_le_bytes());
+        }
+        for (segment, placed) in self.segments.iter().zip(&segments) {
+            header.extend(program_header(class, segment, placed));
+        }
+        put(&mut out, 0, &header);
+
+        let mut table = vec![0; shentsize as usize];
+        for ((name, section), placed) in sections.iter().zip(&placed_sections) {
+            table.extend(section_header(class, *name, section, placed));
+        }
+        put(&mut out, shoff, &table);
+        Ok(out)
+    }
+
+    // Sections with the offset of their name in .shstrtab, .shstrtab itself included, and the
+    // section index it ends up at
+    fn sections_with_names(&self) -> (Vec<(u32, SectionBuilder)>, usize) {
+        let at = self
+            .shstrtab
+            .unwrap_or(self.sections.len())
+            .min(self.sections.len());
+        let mut sections: Vec<SectionBuilder> = self.sections.clone();
+        sections.insert(
+            at,
+            SectionBuilder {
+                name: ".shstrtab".into(),
+                typ: SectionType::StrTab,
+                flags: SectionBits::from_bits(0),
+                align: 1,
+                entsize: 0,
+                link: 0,
+                info: 0,
+                data: SectionData::Bytes {
+                    addr: Addr(0),
+                    data: Vec::new(),
+                },
+            },
+        );
+        let mut names = vec![0u8];
+        let offsets: Vec<u32> = sections
+            .iter()
+            .map(|section| {
+                let offset = names.len() as u32;
+                names.extend(section.name.as_bytes());
+                names.push(0);
+                offset
+            })
+            .collect();
+        sections[at].data = SectionData::Bytes {
+            addr: Addr(0),
+            data: names,
+        };
+        (offsets.into_iter().zip(sections).collect(), at + 1)
+    }
+}
+
+fn put(buf: &mut [u8], offset: u64, bytes: &[u8]) {
+    let offset = offset as usize;
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn word(class: Class, value: u64) -> Vec<u8> {
+    match class {
+        Class::Elf32 => (value as u32).to_le_bytes().to_vec(),
+        Class::Elf64 => value.to_le_bytes().to_vec(),
+    }
+}
+
+// Elf32_Phdr moves p_flags after p_memsz; Elf64_Phdr has it second
+fn program_header(class: Class, segment: &SegmentBuilder, placed: &Placed) -> Vec<u8> {
+    let flags = segment.flags.bits().to_le_bytes();
+    let paddr = match &segment.data {
+        SegmentData::Passthrough { paddr, .. } => paddr.0,
+        _ => placed.addr,
+    };
+    let mut out = u32::from(segment.typ).to_le_bytes().to_vec();
+    if class == Class::Elf64 {
+        out.extend(&flags);
+    }
+    for value in [
+        placed.offset,
+        placed.addr,
+        paddr,
+        placed.file_size,
+        placed.mem_size,
+    ] {
+        out.extend(word(class, value));
+    }
+    if class == Class::Elf32 {
+        out.extend(&flags);
+    }
+    out.extend(word(class, segment.align));
+    out
+}
+
+fn section_header(class: Class, name: u32, section: &SectionBuilder, placed: &Placed) -> Vec<u8> {
+    let mut out = name.to_le_bytes().to_vec();
+    out.extend(&u32::from(section.typ).to_le_bytes());
+    for value in [
+        section.flags.bits(),
+        placed.addr,
+        placed.offset,
+        placed.file_size,
+    ] {
+        out.extend(word(class, value));
+    }
+    out.extend(&section.link.to_le_bytes());
+    out.extend(&section.info.to_le_bytes());
+    out.extend(word(class, section.align));
+    out.extend(word(class, section.entsize));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+
+    fn section(name: &str, typ: SectionType, flags: u64, data: SectionData) -> SectionBuilder {
+        SectionBuilder {
+            name: name.into(),
+            typ,
+            flags: SectionBits::from_bits(flags),
+            align: 1,
+            entsize: 0,
+            link: 0,
+            info: 0,
+            data,
+        }
+    }
+
+    // A static executable: the headers and code in one RX page, data and bss in an RW one
+    fn executable() -> FileBuilder {
+        let mut code = vec![0; 0x40 + 2 * 56];
+        code.extend(&[0xb8, 0x3c, 0, 0, 0, 0x0f, 0x05]);
+        let mut file = FileBuilder::new(Class::Elf64, Type::Exec, Machine::X86_64);
+        file.entry = Addr(0x400000 + 0x40 + 2 * 56);
+        file.segments = vec![
+            SegmentBuilder {
+                typ: SegmentType::Load,
+                flags: (SegmentFlags::Read | SegmentFlags::Execute).into(),
+                align: 0x1000,
+                data: SegmentData::Bytes {
+                    vaddr: Addr(0x400000),
+                    mem_size: code.len() as u64,
+                    data: code,
+                    headers: true,
+                },
+            },
+            SegmentBuilder {
+                typ: SegmentType::Load,
+                flags: (SegmentFlags::Read | SegmentFlags::Write).into(),
+                align: 0x1000,
+                data: SegmentData::Bytes {
+                    vaddr: Addr(0x401234),
+                    data: vec![1, 2, 3, 4],
+                    mem_size: 0x20,
+                    headers: false,
+                },
+            },
+        ];
+        file.sections = vec![
+            section(
+                ".text",
+                SectionType::ProgBits,
+                0x6,
+                SectionData::Within {
+                    segment: 0,
+                    offset: 0x40 + 2 * 56,
+                    size: 7,
+                },
+            ),
+            section(
+                ".data",
+                SectionType::ProgBits,
+                0x3,
+                SectionData::Within {
+                    segment: 1,
+                    offset: 0,
+                    size: 4,
+                },
+            ),
+            section(
+                ".bss",
+                SectionType::NoBits,
+                0x3,
+                SectionData::Within {
+                    segment: 1,
+                    offset: 4,
+                    size: 0x1c,
+                },
+            ),
+            section(
+                ".comment",
+                SectionType::ProgBits,
+                0x30,
+                SectionData::Bytes {
+                    addr: Addr(0),
+                    data: b"delf\0".to_vec(),
+                },
+            ),
+        ];
+        file
+    }
+
+    fn parse(bytes: Vec<u8>) -> FileHeader {
+        FileHeader::parse_or_describe(&Data::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn writes_a_parseable_image() {
+        let file = parse(executable().to_bytes().unwrap());
+        assert_eq!(file.entry_point, Addr(0x4000b0));
+        let data = &file.program_headers[1];
+        assert_eq!(data.virt_addr, Addr(0x401234));
+        assert_eq!(data.offset.0 % 0x1000, 0x234);
+        assert_eq!((data.file_size, data.mem_size), (Addr(4), Addr(0x20)));
+        assert_eq!(&data.data[..], &[1, 2, 3, 4]);
+
+        let names: Vec<_> = file.section_headers.iter().map(|sh| &sh.name[..]).collect();
+        assert_eq!(
+            names,
+            ["", ".text", ".data", ".bss", ".comment", ".shstrtab"]
+        );
+        let text = file.section_by_name(".text").unwrap();
+        assert_eq!(text.addr, Addr(0x4000b0));
+        assert_eq!(&text.data[..], &[0xb8, 0x3c, 0, 0, 0, 0x0f, 0x05]);
+        assert_eq!(file.section_by_name(".bss").unwrap().addr, Addr(0x401238));
+    }
+
+    #[test]
+    fn translates_addresses() {
+        let file = parse(executable().to_bytes().unwrap());
+        let data = file.program_headers[1].offset;
+        assert_eq!(file.vaddr_to_offset(Addr(0x4000b0)), Some(Addr(0xb0)));
+        assert_eq!(file.vaddr_to_offset(Addr(0x401234)), Some(data));
+        // .bss has nothing in the file
+        assert_eq!(file.vaddr_to_offset(Addr(0x401238)), None);
+        assert_eq!(file.offset_to_vaddr(Addr(0xb0)), Some(Addr(0x4000b0)));
+        assert_eq!(file.offset_to_vaddr(data), Some(Addr(0x401234)));
+        assert_eq!(file.program_headers_addr(), Some(Addr(0x400040)));
+
+        let base = 0x7f00_0000_0000;
+        assert_eq!(file.vaddr_to_loaded(Addr(0x4000b0), base), base + 0x4000b0);
+        assert_eq!(
+            file.loaded_to_vaddr(base + 0x401238, base),
+            Some(Addr(0x401238))
+        );
+        assert_eq!(file.loaded_to_vaddr(base + 0x10, base), None);
+        assert_eq!(file.loaded_to_vaddr(0x10, base), None);
+        assert_eq!(file.loaded_to_offset(base + 0x401234, base), Some(data));
+        assert_eq!(
+            file.offset_to_loaded(Addr(0xb0), base),
+            Some(base + 0x4000b0)
+        );
+    }
+
+    #[test]
+    fn parse_write_parse_is_stable() {
+        let first = executable().to_bytes().unwrap();
+        let again = FileBuilder::from_file(&parse(first.clone()));
+        assert_eq!(again.shstrtab, Some(4));
+        assert_eq!(again.to_bytes().unwrap(), first);
+    }
+
+    #[test]
+    fn carries_vendor_sections_and_segments() {
+        let mut file = FileBuilder::new(Class::Elf64, Type::Dyn, Machine::X86_64);
+        // An OS-specific segment type, with an OS flag bit and a physical address of its own
+        file.segments = vec![SegmentBuilder {
+            typ: SegmentType::Other(0x65a3_dbe6),
+            flags: SegmentBits::from_bits(0x0010_0004),
+            align: 8,
+            data: SegmentData::Passthrough {
+                vaddr: Addr(0x500000),
+                paddr: Addr(0x9000),
+                data: vec![0xde, 0xad, 0xbe, 0xef],
+                mem_size: 0x10,
+            },
+        }];
+        let mut note = [5u32, 4, 1].map(u32::to_le_bytes).concat();
+        note.extend(b"ACME\0\0\0\0\x01\x02\x03\x04");
+        file.sections = vec![
+            section(
+                ".note.acme",
+                SectionType::Note,
+                0,
+                SectionData::Bytes {
+                    addr: Addr(0),
+                    data: note.clone(),
+                },
+            ),
+            section(
+                ".acme.blob",
+                SectionType::Other(0x6fff_4c00),
+                0x0010_0000,
+                SectionData::Bytes {
+                    addr: Addr(0x1234),
+                    data: b"vendor\0".to_vec(),
+                },
+            ),
+        ];
+        let first = file.to_bytes().unwrap();
+        let parsed = parse(first.clone());
+
+        let segment = &parsed.program_headers[0];
+        assert_eq!(segment.typ, SegmentType::Other(0x65a3_dbe6));
+        assert_eq!(segment.flags.bits(), 0x0010_0004);
+        assert_eq!(
+            (segment.virt_addr, segment.phys_addr),
+            (Addr(0x500000), Addr(0x9000))
+        );
+        assert_eq!(&segment.data[..], &[0xde, 0xad, 0xbe, 0xef]);
+        let blob = parsed.section_by_name(".acme.blob").unwrap();
+        assert_eq!(blob.typ, SectionType::Other(0x6fff_4c00));
+        assert_eq!(
+            (blob.flags.unknown(), blob.addr),
+            (0x0010_0000, Addr(0x1234))
+        );
+        assert_eq!(
+            &parsed.section_by_name(".note.acme").unwrap().data[..],
+            &note[..]
+        );
+
+        let again = FileBuilder::from_file(&parsed);
+        assert_eq!(again.segments, file.segments);
+        assert_eq!(again.to_bytes().unwrap(), first);
+    }
+
+    #[test]
+    fn rejects_headers_that_do_not_fit() {
+        let mut file = executable();
+        if let SegmentData::Bytes { data, .. } = &mut file.segments[0].data {
+            data.truncate(0x20);
+        }
+        assert!(matches!(
+            file.to_bytes(),
+            Err(WriteError::HeadersDontFit(0, 0x20, 0xb0))
+        ));
+    }
+
+    #[test]
+    fn leaves_padding_zero() {
+        let mut bytes = executable().to_bytes().unwrap();
+        let padding = layout::padding(&parse(bytes.clone()), &bytes);
+        // After the code to the end of its page, and from the second page to the data
+        assert_eq!(padding.len(), 2);
+        assert_eq!((padding[0].segment, padding[0].offset), (0, 0xb7));
+        assert!(padding.iter().all(|p| p.nonzero == 0));
+
+        bytes[0xc0..0xc4].copy_from_slice(&[0xcc; 4]);
+        let padding = layout::padding(&parse(bytes.clone()), &bytes);
+        assert_eq!(padding[0].nonzero, 4);
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    ops::Deref,
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use delf::{data::Data, parse::ParseOptions, FileHeader, ParseError};
+
+// Address space set aside for arena chunks, so any pointer can be told apart as arena memory
+// whichever thread frees it. Only reserved: pages are made usable a chunk at a time.
+const RESERVE: usize = 1 << 36;
+const CHUNK: usize = 1 << 20;
+// Allocations bigger than this (the header tables of a huge binary, say) come from the system
+// allocator as usual, and are freed one by one when the arena goes
+const MAX_ALLOCATION: usize = CHUNK / 4;
+// Room for the link to the arena's next chunk
+const HEADER: usize = 16;
+
+static BASE: AtomicUsize = AtomicUsize::new(0);
+// Chunks handed out so far, free or not
+static CARVED: AtomicUsize = AtomicUsize::new(0);
+// Chunks of dropped arenas, linked through their headers, for the next arena to reuse
+static FREE: Mutex<usize> = Mutex::new(0);
+
+std::thread_local! {
+    static CURRENT: Cell<*const Arena> = const { Cell::new(ptr::null()) };
+}
+
+// The global allocator of the elk binary. Outside `in_arena` it is the system allocator; inside,
+// small allocations of that thread are bumped out of the arena's chunks, and freeing them does
+// nothing until the arena goes.
+pub struct Allocator;
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match current() {
+            Some(arena) => arena.alloc(layout),
+            None => System.alloc(layout),
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        match current() {
+            Some(arena) => {
+                let ptr = arena.alloc(layout);
+                if !ptr.is_null() {
+                    ptr.write_bytes(0, layout.size());
+                }
+                ptr
+            }
+            None => System.alloc_zeroed(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !is_arena(ptr) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if !is_arena(ptr) {
+            return System.realloc(ptr, layout, new_size);
+        }
+        let new = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+        if !new.is_null() {
+            ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size));
+        }
+        new
+    }
+}
+
+// `FileHeader::parse_checked` in an arena, so the whole parse goes at once when dropped: how elk
+// check gets rid of the headers, symbols and relocations of each file it parses. The parse
+// reads `input` through `Data::present`, which never fills a buffer in, and allocates nothing
+// but what it returns, so nothing outside the result ends up in the arena.
+pub fn parse_checked(
+    input: &Data,
+    options: &ParseOptions,
+) -> InArena<Result<FileHeader, ParseError>> {
+    unsafe { in_arena(|| FileHeader::parse_checked(input, options)) }
+}
+
+// What `f` returns, with whatever it allocated on this thread while it ran. Dropping it drops the
+// value without freeing anything, then hands the arena's chunks back at once, for the next arena
+// to reuse. Nothing allocated in `f` may outlive the result, which is why it only derefs.
+// Without `Allocator` registered as the global allocator, this is just `f()`.
+//
+// Safety: `f` must not grow, create or initialise anything that outlives it other than its
+// result: no pushes to the caller's collections, no lazily initialised statics, no buffers
+// filled in. Those would be allocated in the arena too, and overwritten once its chunks are
+// reused.
+unsafe fn in_arena<T>(f: impl FnOnce() -> T) -> InArena<T> {
+    let arena = Arena::default();
+    let value = arena.enter(f);
+    InArena {
+        value: Some(value),
+        arena,
+    }
+}
+
+pub struct InArena<T> {
+    value: Option<T>,
+    arena: Arena,
+}
+
+impl<T> Deref for InArena<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().unwrap()
+    }
+}
+
+impl<T> Drop for InArena<T> {
+    fn drop(&mut self) {
+        // Whatever dropping it allocates goes with the arena too
+        let value = self.value.take();
+        self.arena.enter(|| drop(value));
+    }
+}
+
+#[derive(Default)]
+struct Arena {
+    // Chunks taken, linked through their headers
+    chunks: Cell<usize>,
+    cursor: Cell<usize>,
+    end: Cell<usize>,
+}
+
+impl Arena {
+    fn enter<T>(&self, f: impl FnOnce() -> T) -> T {
+        // Restores the outer arena, if any, when `f` returns or unwinds
+        struct Leave(*const Arena);
+        impl Drop for Leave {
+            fn drop(&mut self) {
+                CURRENT.with(|c| c.set(self.0));
+            }
+        }
+        let _leave = Leave(CURRENT.with(|c| c.replace(self)));
+        f()
+    }
+
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() > MAX_ALLOCATION || layout.align() > HEADER {
+            return System.alloc(layout);
+        }
+        let mut start = align(self.cursor.get(), layout.align());
+        if self.cursor.get() == 0 || start + layout.size() > self.end.get() {
+            let chunk = match take_chunk() {
+                Some(chunk) => chunk,
+                None => return System.alloc(layout),
+            };
+            *(chunk as *mut usize) = self.chunks.replace(chunk);
+            self.end.set(chunk + CHUNK);
+            start = align(chunk + HEADER, layout.align());
+        }
+        self.cursor.set(start + layout.size());
+        start as *mut u8
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        let first = self.chunks.get();
+        // A panic payload may have been allocated in the arena and still be on its way up, so
+        // the chunks are never reused after one
+        if first == 0 || thread::panicking() {
+            return;
+        }
+        let mut last = first;
+        unsafe {
+            while *(last as *const usize) != 0 {
+                last = *(last as *const usize);
+            }
+            let mut free = FREE.lock().unwrap_or_else(|e| e.into_inner());
+            *(last as *mut usize) = *free;
+            *free = first;
+        }
+    }
+}
+
+fn current() -> Option<&'static Arena> {
+    // Threads being torn down have no arena
+    let arena = CURRENT.try_with(|c| c.get()).unwrap_or(ptr::null());
+    unsafe { arena.as_ref() }
+}
+
+fn is_arena(ptr: *mut u8) -> bool {
+    let base = BASE.load(Ordering::Relaxed);
+    base != 0 && (base..base + RESERVE).contains(&(ptr as usize))
+}
+
+fn align(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+// A free chunk, or a new one out of the reservation; None once that runs out
+fn take_chunk() -> Option<usize> {
+    {
+        let mut free = FREE.lock().unwrap_or_else(|e| e.into_inner());
+        if *free != 0 {
+            let chunk = *free;
+            *free = unsafe { *(chunk as *const usize) };
+            return Some(chunk);
+        }
+    }
+    let base = reserve()?;
+    let offset = CARVED.fetch_add(CHUNK, Ordering::Relaxed);
+    if offset + CHUNK > RESERVE {
+        return None;
+    }
+    let chunk = base + offset;
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    match unsafe { libc::mprotect(chunk as *mut libc::c_void, CHUNK, prot) } {
+        0 => Some(chunk),
+        _ => None,
+    }
+}
+
+fn reserve() -> Option<usize> {
+    let base = BASE.load(Ordering::Acquire);
+    if base != 0 {
+        return Some(base);
+    }
+    let map = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            RESERVE,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+            0,
+        )
+    };
+    if map == libc::MAP_FAILED {
+        return None;
+    }
+    match BASE.compare_exchange(0, map as usize, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => Some(map as usize),
+        // Another thread reserved it first
+        Err(base) => {
+            unsafe { libc::munmap(map, RESERVE) };
+            Some(base)
+        }
+    }
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arenas_hold_what_is_allocated_in_them() {
+        let outside = String::from("outside");
+        let parsed = unsafe {
+            in_arena(|| {
+                let names: Vec<String> = (0..1000).map(|i| format!("section{}", i)).collect();
+                let big = vec![0u8; MAX_ALLOCATION + 1];
+                (names, big)
+            })
+        };
+        let (names, big) = &*parsed;
+        assert!(is_arena(names.as_ptr() as *mut u8));
+        assert!(names.iter().all(|n| is_arena(n.as_ptr() as *mut u8)));
+        assert_eq!(names[999], "section999");
+        // Too big for a chunk, and allocated before the arena
+        assert!(!is_arena(big.as_ptr() as *mut u8));
+        assert!(!is_arena(outside.as_ptr() as *mut u8));
+
+        // Growing it afterwards moves it out, so it can outlive the arena
+        let mut moved = names.clone();
+        assert!(!is_arena(moved.as_ptr() as *mut u8));
+        moved.push("last".to_string());
+        drop(parsed);
+        assert_eq!(moved[1000], "last");
+    }
+
+    #[test]
+    fn panics_out_of_arenas_keep_their_payload() {
+        let caught = std::panic::catch_unwind(|| {
+            let _ = unsafe {
+                in_arena(|| -> () { std::panic::panic_any(format!("broken at {:#x}", 0x40)) })
+            };
+        });
+        let payload = caught.err().unwrap();
+        assert!(is_arena(&*payload as *const _ as *const u8 as *mut u8));
+        assert_eq!(payload.downcast_ref::<String>().unwrap(), "broken at 0x40");
+    }
+
+    // The parse is all in the arena, the input it was parsed from isn't
+    #[test]
+    fn parses_go_in_the_arena() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/3-needed");
+        let input = Data::from(std::fs::read(path).unwrap());
+        let parsed = parse_checked(&input, &ParseOptions::default());
+        let file = parsed.as_ref().unwrap();
+        assert!(is_arena(file.section_headers.as_ptr() as *mut u8));
+        assert!(is_arena(file.program_headers.as_ptr() as *mut u8));
+        assert!(!is_arena(input.present().as_ptr() as *mut u8));
+        assert_eq!(file.section_by_name(".dynamic").unwrap().name, ".dynamic");
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use delf::parse::ParseOptions;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    check::{self, Outcome},
+    cli::FormatArg,
+    exit::{Failure, Status},
+    interrupt, progress, schema,
+    tables::Table,
+};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Check the executable and libraries of every running process")]
+pub struct Args {
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 20,
+        help = "Objects with findings to list, most used first"
+    )]
+    top: usize,
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = "/proc",
+        value_hint = clap::ValueHint::DirPath,
+        help = "procfs to read processes from"
+    )]
+    proc: PathBuf,
+    #[command(flatten)]
+    format: FormatArg,
+}
+
+#[derive(Serialize, JsonSchema, Default)]
+struct RuleCount {
+    objects: usize,
+    processes: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ObjectFindings {
+    path: String,
+    rules: Vec<String>,
+    // Processes that have the object mapped
+    pids: Vec<u32>,
+}
+
+#[derive(Serialize, JsonSchema, Default)]
+struct AuditReport {
+    processes: usize,
+    // Processes whose maps couldn't be read, usually for lack of permission
+    unreadable: usize,
+    objects: usize,
+    // Objects Ctrl-C kept from being checked
+    skipped: usize,
+    rules: BTreeMap<String, RuleCount>,
+    // Objects that are mapped but no longer readable, or not ELF the parser accepts
+    failures: BTreeMap<String, String>,
+    findings: Vec<ObjectFindings>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<AuditReport>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    if args.format.json() {
+        progress::silence();
+    }
+    let mut report = AuditReport::default();
+    // Object path to the processes mapping it
+    let mut users: BTreeMap<PathBuf, BTreeSet<u32>> = BTreeMap::new();
+    for pid in pids(&args.proc)? {
+        match mapped_files(&args.proc.join(pid.to_string())) {
+            Some(files) => {
+                for file in files {
+                    users.entry(file).or_default().insert(pid);
+                }
+                report.processes += 1;
+            }
+            None => report.unreadable += 1,
+        }
+    }
+
+    let paths: Vec<PathBuf> = users.keys().cloned().collect();
+    report.objects = paths.len();
+    report.rules = check::descriptions()
+        .into_iter()
+        .map(|(id, _)| (id, RuleCount::default()))
+        .collect();
+    let _interrupts = interrupt::catch();
+    let options = ParseOptions {
+        cancel: interrupt::token().clone(),
+        ..Default::default()
+    };
+    for (path, outcome) in paths.iter().zip(check::scan(&paths, &options)) {
+        let pids = &users[path];
+        match outcome {
+            Outcome::NotElf => {}
+            Outcome::Skipped => report.skipped += 1,
+            Outcome::Unreadable(error) | Outcome::Undecodable(error) | Outcome::Failed(error) => {
+                report.failures.insert(path.display().to_string(), error);
+            }
+            Outcome::Checked(rules, _) if rules.is_empty() => {}
+            Outcome::Checked(rules, _) => {
+                for rule in &rules {
+                    let count = report.rules.entry(rule.clone()).or_default();
+                    count.objects += 1;
+                }
+                report.findings.push(ObjectFindings {
+                    path: path.display().to_string(),
+                    rules,
+                    pids: pids.iter().copied().collect(),
+                });
+            }
+        }
+    }
+    // A process counts once per rule, however many of its objects break it
+    for (rule, count) in report.rules.iter_mut() {
+        let affected: BTreeSet<u32> = report
+            .findings
+            .iter()
+            .filter(|f| f.rules.contains(rule))
+            .flat_map(|f| f.pids.iter().copied())
+            .collect();
+        count.processes = affected.len();
+    }
+    report
+        .findings
+        .sort_by(|a, b| b.pids.len().cmp(&a.pids.len()).then(a.path.cmp(&b.path)));
+
+    if args.format.json() {
+        schema::print_json("audit-system", &report)?;
+    } else {
+        print_report(&report, args.top);
+    }
+    if report.skipped > 0 {
+        return Err(Failure::new(
+            Status::Interrupted,
+            format!(
+                "interrupted, {} of {} mapped objects not checked",
+                report.skipped, report.objects
+            ),
+        )
+        .into());
+    }
+    match report.findings.len() {
+        0 => Ok(()),
+        findings => Err(Failure::findings(format!(
+            "{} of {} mapped objects with findings",
+            findings, report.objects
+        ))
+        .into()),
+    }
+}
+
+fn pids(proc: &Path) -> Result<Vec<u32>, Box<dyn Error>> {
+    let mut pids: Vec<u32> = fs::read_dir(proc)
+        .map_err(|e| format!("Could not list {}: {}", proc.display(), e))?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect();
+    pids.sort();
+    Ok(pids)
+}
+
+// Files a process has mapped, from /proc/<pid>/maps: its executable, the interpreter and
+// every shared object. Kernel threads have none.
+fn mapped_files(dir: &Path) -> Option<BTreeSet<PathBuf>> {
+    let maps = fs::read_to_string(dir.join("maps")).ok()?;
+    Some(
+        maps.lines()
+            // address perms offset dev inode path
+            .filter_map(|line| line.splitn(6, ' ').nth(5))
+            .map(str::trim)
+            .filter(|path| path.starts_with('/') && !path.ends_with(" (deleted)"))
+            .map(PathBuf::from)
+            .collect(),
+    )
+}
+
+fn print_report(report: &AuditReport, top: usize) {
+    let descriptions: BTreeMap<String, String> = check::descriptions().into_iter().collect();
+    Table {
+        header: format!(
+            "Hardening across {} processes, {} mapped objects",
+            report.processes, report.objects
+        ),
+        labels: vec![
+            "Rule".into(),
+            "Objects".into(),
+            "Processes".into(),
+            "Description".into(),
+        ],
+        rows: report
+            .rules
+            .iter()
+            .map(|(rule, count)| {
+                vec![
+                    rule.clone(),
+                    count.objects.to_string(),
+                    count.processes.to_string(),
+                    descriptions.get(rule).cloned().unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    }
+    .print();
+
+    if !report.findings.is_empty() {
+        Table {
+            header: format!(
+                "Objects with findings ({} of {}), most used first",
+                top.min(report.findings.len()),
+                report.findings.len()
+            ),
+            labels: vec!["Object".into(), "Processes".into(), "Rules".into()],
+            rows: report
+                .findings
+                .iter()
+                .take(top)
+                .map(|f| vec![f.path.clone(), f.pids.len().to_string(), f.rules.join(", ")])
+                .collect(),
+        }
+        .print();
+    }
+    if report.unreadable > 0 {
+        println!(
+            "{} processes could not be read; run as root to include them",
+            report.unreadable
+        );
+    }
+    if report.skipped > 0 {
+        println!("Interrupted: {} objects were not checked", report.skipped);
+    }
+}
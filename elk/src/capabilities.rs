@@ -0,0 +1,79 @@
+use std::error::Error;
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg,
+    preflight::{Fidelity, CAPABILITIES},
+    schema,
+    tables::Table,
+};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "What glibc's ld.so does that elk does too, and how faithfully")]
+pub struct Args {
+    #[command(flatten)]
+    format: FormatArg,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Capability")]
+struct Entry {
+    id: &'static str,
+    // What ld.so does
+    behavior: &'static str,
+    fidelity: Fidelity,
+    // Where elk falls short of ld.so or differs from it
+    notes: &'static str,
+    // `elk run` refuses programs needing more of this than elk has, unless run with --force
+    preflight: bool,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Vec<Entry>>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<Entry> = CAPABILITIES
+        .iter()
+        .map(|capability| Entry {
+            id: capability.id,
+            behavior: capability.behavior,
+            fidelity: capability.fidelity,
+            notes: capability.notes,
+            preflight: capability.check.is_some(),
+        })
+        .collect();
+    if args.format.json() {
+        return schema::print_json("capabilities", &entries);
+    }
+    Table {
+        header: "Capabilities".into(),
+        labels: vec![
+            "Id".into(),
+            "Behavior".into(),
+            "Fidelity".into(),
+            "Preflight".into(),
+            "Notes".into(),
+        ],
+        rows: entries
+            .iter()
+            .map(|entry| {
+                vec![
+                    entry.id.into(),
+                    entry.behavior.into(),
+                    entry.fidelity.to_string(),
+                    match entry.preflight {
+                        true => "checked".into(),
+                        false => String::new(),
+                    },
+                    entry.notes.into(),
+                ]
+            })
+            .collect(),
+    }
+    .print();
+    Ok(())
+}
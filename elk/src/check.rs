@@ -0,0 +1,626 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use delf::{
+    hexdump::hexdump,
+    layout,
+    parse::{ParseOptions, Strictness},
+    signature, style,
+    types::*,
+    FileHeader, ParseError,
+};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    arena,
+    cli::FormatArg,
+    exit::{self, Status},
+    interrupt, plugin,
+    progress::{self, Progress},
+    schema, source,
+    tables::Table,
+};
+
+struct Rule {
+    id: &'static str,
+    description: &'static str,
+    check: fn(&FileHeader) -> bool,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        id: "wx-segment",
+        description: "LOAD segment is both writable and executable",
+        check: |file| {
+            file.program_headers.iter().any(|ph| {
+                ph.typ == SegmentType::Load
+                    && ph.flags.contains(SegmentFlags::Write)
+                    && ph.flags.contains(SegmentFlags::Execute)
+            })
+        },
+    },
+    Rule {
+        id: "exec-stack",
+        description: "Stack is executable (PT_GNU_STACK missing or X)",
+        check: FileHeader::executable_stack,
+    },
+    Rule {
+        id: "no-pie",
+        description: "Executable is not position independent",
+        check: |file| file.typ == Type::Exec,
+    },
+    Rule {
+        id: "no-relro",
+        description: "No PT_GNU_RELRO segment",
+        check: |file| {
+            file.segments_of_type(SegmentType::Dynamic).next().is_some()
+                && file
+                    .segments_of_type(SegmentType::GnuRelRo)
+                    .next()
+                    .is_none()
+        },
+    },
+    Rule {
+        id: "lazy-binding",
+        description: "Dynamic symbols are bound lazily (no BIND_NOW)",
+        check: |file| {
+            file.segments_of_type(SegmentType::Dynamic).next().is_some() && !file.binds_now()
+        },
+    },
+    Rule {
+        id: "bad-mem-size",
+        description: "LOAD segment mem_size overflows or dwarfs its file size",
+        check: |file| {
+            file.program_headers
+                .iter()
+                .any(|ph| ph.typ == SegmentType::Load && ph.check_sizes().is_err())
+        },
+    },
+    Rule {
+        id: "textrel",
+        description: "Relocations write to read-only text",
+        check: |file| file.has_textrel(),
+    },
+    Rule {
+        id: "dynamic-mismatch",
+        description: "PT_DYNAMIC and .dynamic describe different bytes",
+        check: |file| file.check_dynamic().is_err(),
+    },
+    Rule {
+        id: "reloc-readonly",
+        description: "Relocations target a read-only LOAD segment outside RELRO",
+        check: |file| {
+            file.check_segment_contents()
+                .iter()
+                .any(|m| matches!(m, SegmentMismatch::RelocatedReadOnly(..)))
+        },
+    },
+    Rule {
+        id: "ibt-missing-endbr",
+        description: "IBT is enabled but global functions don't start with endbr64",
+        check: |file| file.endbr_report().is_some_and(|r| r.is_mismatch()),
+    },
+    Rule {
+        id: "parse-anomalies",
+        description: "Headers hold values or structure the parser had to let through",
+        check: |file| !file.anomalies.is_empty(),
+    },
+    Rule {
+        id: "runaway-tables",
+        description: "Linked tables loop back on themselves or chain more entries than they claim",
+        check: |file| !file.walk_errors().is_empty(),
+    },
+    Rule {
+        id: "exec-data",
+        description: "Executable LOAD segment holds little that looks like code",
+        check: |file| {
+            file.check_segment_contents()
+                .iter()
+                .any(|m| matches!(m, SegmentMismatch::NotCode(..)))
+        },
+    },
+];
+
+// Unlike the rules above it needs the file's bytes, not just what the parser kept of them
+const STOWAWAY_PADDING: (&str, &str) = (
+    "stowaway-padding",
+    "Page padding mapped along with a LOAD segment holds non-zero bytes",
+);
+
+const BAD_SIGNATURE: (&str, &str) = (
+    "bad-module-signature",
+    "Appended module signature is truncated or doesn't cover the whole module",
+);
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "CheckFailure")]
+struct Failure {
+    path: String,
+    error: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Findings {
+    path: String,
+    rules: Vec<String>,
+    // What anomalies and plugin analyses said about the file, by rule
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    details: BTreeMap<String, plugin::Findings>,
+}
+
+#[derive(Serialize, JsonSchema, Default)]
+#[schemars(rename = "CheckReport")]
+struct Report {
+    files_scanned: usize,
+    elf_files: usize,
+    // Files Ctrl-C kept from being checked
+    skipped: usize,
+    rules: BTreeMap<String, usize>,
+    // Files that couldn't be read, or unpacked when compressed
+    unreadable: Vec<Failure>,
+    failures: Vec<Failure>,
+    findings: Vec<Findings>,
+}
+
+pub enum Outcome {
+    NotElf,
+    // Couldn't be read at all
+    Unreadable(String),
+    // Compressed, or inside an archive, and it wouldn't unpack
+    Undecodable(String),
+    Failed(String),
+    Checked(Vec<String>, BTreeMap<String, plugin::Findings>),
+    // Cancelled before or while it was checked
+    Skipped,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Audit binaries for hardening and hygiene problems")]
+pub struct Args {
+    #[arg(short, long, help = "Descend into subdirectories")]
+    recursive: bool,
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        value_parser = ["strict", "permissive", "forensic"],
+        default_value = "permissive",
+        help = "Reject spec violations, tolerate unknown values, or parse past anything"
+    )]
+    strictness: String,
+    #[arg(
+        long,
+        help = "Hex dump the non-zero padding of files flagged stowaway-padding"
+    )]
+    dump_padding: bool,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(required = true, value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<PathBuf>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Report>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut paths = Vec::new();
+    for root in &args.paths {
+        if root.is_dir() {
+            walk(root, args.recursive, &mut paths);
+        } else {
+            paths.push(root.clone());
+        }
+    }
+    paths.sort();
+    if args.format.json() {
+        progress::silence();
+    }
+
+    let _interrupts = interrupt::catch();
+    let options = ParseOptions {
+        strictness: Strictness::parse(&args.strictness).unwrap_or_default(),
+        cancel: interrupt::token().clone(),
+        ..Default::default()
+    };
+    let report = aggregate(&paths, scan(&paths, &options));
+    if args.format.json() {
+        schema::print_json("check", &report)?;
+    } else {
+        print_report(&report);
+        if args.dump_padding && !interrupt::interrupted() {
+            dump_padding(&report, &options)?;
+        }
+    }
+    if report.skipped > 0 {
+        return Err(exit::Failure::new(
+            Status::Interrupted,
+            format!(
+                "interrupted, {} of {} files not checked",
+                report.skipped, report.files_scanned
+            ),
+        )
+        .into());
+    }
+    // Files that failed to parse are findings too: the parser choked on something real
+    match (report.findings.len(), report.failures.len()) {
+        (0, 0) => Ok(()),
+        (findings, failures) => Err(exit::Failure::findings(format!(
+            "{} files with findings, {} that failed to parse",
+            findings, failures
+        ))
+        .into()),
+    }
+}
+
+// Regular files under `dir`; symlinks are left out, so each file is listed once
+pub fn walk(dir: &Path, recursive: bool, paths: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        match entry.file_type() {
+            Ok(t) if t.is_dir() && recursive => walk(&entry.path(), recursive, paths),
+            Ok(t) if t.is_file() => paths.push(entry.path()),
+            _ => {}
+        }
+    }
+}
+
+// Checks every path on a pool of worker threads, keeping results in input order. Once
+// `options.cancel` is cancelled, workers stop taking paths and the rest come back Skipped.
+pub fn scan(paths: &[PathBuf], options: &ParseOptions) -> Vec<Outcome> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let progress = Progress::new("Checking", paths.len() as u64);
+
+    // Malformed files can still trip panics in the parser; report them as failures instead
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results: Vec<(usize, Outcome)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        if options.cancel.is_cancelled() {
+                            return done;
+                        }
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match paths.get(i) {
+                            Some(path) => {
+                                done.push((i, check_file(path, options)));
+                                progress.advance(1);
+                            }
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_default())
+            .collect()
+    });
+    panic::set_hook(hook);
+
+    let mut outcomes: Vec<_> = paths.iter().map(|_| Outcome::Skipped).collect();
+    for (i, outcome) in results {
+        outcomes[i] = outcome;
+    }
+    outcomes
+}
+
+fn check_file(path: &Path, options: &ParseOptions) -> Outcome {
+    let input = match source::read(&path.to_string_lossy()) {
+        Ok(input) if input.starts_with(FileHeader::MAGIC) => input,
+        Ok(_) => return Outcome::NotElf,
+        Err(_) if options.cancel.is_cancelled() => return Outcome::Skipped,
+        Err(e) if e.is::<io::Error>() => return Outcome::Unreadable(e.to_string()),
+        Err(e) => return Outcome::Undecodable(e.to_string()),
+    };
+
+    let checked = panic::catch_unwind(AssertUnwindSafe(|| {
+        // Each file's parse goes at once when it is done with, rather than entry by entry
+        let parsed = arena::parse_checked(&input, options);
+        let file = match &*parsed {
+            Ok(file) => file,
+            Err(ParseError::Cancelled) => return Outcome::Skipped,
+            Err(e) => return Outcome::Failed(e.to_string()),
+        };
+        let mut rules: Vec<String> = RULES
+            .iter()
+            .filter(|rule| (rule.check)(file))
+            .map(|rule| rule.id.to_string())
+            .collect();
+        let mut details = BTreeMap::new();
+        if !file.anomalies.is_empty() {
+            let anomalies = file
+                .anomalies
+                .iter()
+                .map(|a| format!("{} at {:#x}: {}", a.field, a.offset, a.message));
+            details.insert("parse-anomalies".to_string(), anomalies.collect());
+        }
+        let stowaways: Vec<String> = layout::padding(file, &input)
+            .iter()
+            .filter(|p| p.nonzero > 0)
+            .map(|p| {
+                format!(
+                    "{} non-zero of {:#x} bytes at {:#x}, mapped with segment {}",
+                    p.nonzero, p.len, p.offset, p.segment
+                )
+            })
+            .collect();
+        if !stowaways.is_empty() {
+            rules.push(STOWAWAY_PADDING.0.to_string());
+            details.insert(STOWAWAY_PADDING.0.to_string(), stowaways);
+        }
+        let broken = match signature::appended(&input) {
+            Ok(Some(signature)) => signature
+                .check(file)
+                .err()
+                .map(|e| match &signature.signer {
+                    Some(signer) => format!("signed by {}: {}", signer, e),
+                    None => e.to_string(),
+                }),
+            Ok(None) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(broken) = broken {
+            rules.push(BAD_SIGNATURE.0.to_string());
+            details.insert(BAD_SIGNATURE.0.to_string(), vec![broken]);
+        }
+        if let Some(report) = file.endbr_report().filter(|r| r.is_mismatch()) {
+            let missing = report
+                .missing
+                .iter()
+                .map(|(name, addr)| format!("{} at {:#x} has no endbr64", name, addr.0));
+            details.insert("ibt-missing-endbr".to_string(), missing.collect());
+        }
+        let runaways: Vec<String> = file.walk_errors().iter().map(|e| e.to_string()).collect();
+        if !runaways.is_empty() {
+            details.insert("runaway-tables".to_string(), runaways);
+        }
+        for analysis in plugin::analyses().iter() {
+            let findings = analysis.run(file);
+            if !findings.is_empty() {
+                rules.push(analysis.name().to_string());
+                details.insert(analysis.name().to_string(), findings);
+            }
+        }
+        Outcome::Checked(rules, details)
+    }));
+    checked.unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Outcome::Failed(format!("parser panicked: {}", message))
+    })
+}
+
+// Built-in rules, then registered analyses, as (id, description)
+pub fn descriptions() -> Vec<(String, String)> {
+    let builtin = RULES
+        .iter()
+        .map(|rule| (rule.id, rule.description))
+        .chain([STOWAWAY_PADDING, BAD_SIGNATURE])
+        .map(|(id, description)| (id.to_string(), description.to_string()));
+    let plugins = plugin::analyses()
+        .iter()
+        .map(|a| (a.name().to_string(), a.description().to_string()))
+        .collect::<Vec<_>>();
+    builtin.chain(plugins).collect()
+}
+
+fn aggregate(paths: &[PathBuf], outcomes: Vec<Outcome>) -> Report {
+    let mut report = Report {
+        files_scanned: paths.len(),
+        rules: descriptions().into_iter().map(|(id, _)| (id, 0)).collect(),
+        ..Default::default()
+    };
+    for (path, outcome) in paths.iter().zip(outcomes) {
+        let path = path.display().to_string();
+        match outcome {
+            Outcome::NotElf => continue,
+            Outcome::Unreadable(error) | Outcome::Undecodable(error) => {
+                report.unreadable.push(Failure { path, error });
+                continue;
+            }
+            Outcome::Skipped => {
+                report.skipped += 1;
+                continue;
+            }
+            Outcome::Failed(error) => report.failures.push(Failure { path, error }),
+            Outcome::Checked(rules, details) => {
+                for id in &rules {
+                    *report.rules.entry(id.clone()).or_default() += 1;
+                }
+                if !rules.is_empty() {
+                    report.findings.push(Findings {
+                        path,
+                        rules,
+                        details,
+                    });
+                }
+            }
+        }
+        report.elf_files += 1;
+    }
+    report
+}
+
+// The padding behind each stowaway-padding finding, read again from the flagged files
+fn dump_padding(report: &Report, options: &ParseOptions) -> Result<(), Box<dyn Error>> {
+    let theme = style::theme();
+    for findings in &report.findings {
+        if !findings.rules.iter().any(|rule| rule == STOWAWAY_PADDING.0) {
+            continue;
+        }
+        let input = source::read(&findings.path)?;
+        let file = FileHeader::parse_checked(&input, options)?;
+        for padding in layout::padding(&file, &input)
+            .iter()
+            .filter(|p| p.nonzero > 0)
+        {
+            let title = format!(
+                "{}: padding of segment {} at {:#x}",
+                findings.path, padding.segment, padding.offset
+            );
+            println!("{}", theme.title.paint(title));
+            // From the line holding the first non-zero byte to the one holding the last
+            let bytes = &input[padding.offset as usize..(padding.offset + padding.len) as usize];
+            let first = bytes.iter().position(|&b| b != 0).unwrap_or(0) & !0xf;
+            let last = bytes.iter().rposition(|&b| b != 0).unwrap_or(0);
+            let shown = &bytes[first..(last | 0xf).min(bytes.len() - 1) + 1];
+            print!(
+                "{}",
+                hexdump(
+                    shown,
+                    Addr(padding.offset + first as u64),
+                    &[],
+                    usize::MAX,
+                    theme
+                )
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_report(report: &Report) {
+    let rules = Table {
+        header: format!(
+            "Findings across {} ELF files ({} scanned)",
+            report.elf_files, report.files_scanned
+        ),
+        labels: vec!["Rule".into(), "Files".into(), "Description".into()],
+        rows: descriptions()
+            .into_iter()
+            .map(|(id, description)| {
+                let count = report.rules[&id].to_string();
+                vec![id, count, description]
+            })
+            .collect(),
+    };
+    rules.print();
+
+    let details: Vec<_> = report
+        .findings
+        .iter()
+        .flat_map(|f| {
+            f.details.iter().flat_map(move |(rule, findings)| {
+                findings
+                    .iter()
+                    .map(move |finding| vec![f.path.clone(), rule.clone(), finding.clone()])
+            })
+        })
+        .collect();
+    if !details.is_empty() {
+        Table {
+            header: "Finding details".into(),
+            labels: vec!["File".into(), "Rule".into(), "Finding".into()],
+            rows: details,
+        }
+        .print();
+    }
+
+    if !report.failures.is_empty() {
+        let failures = Table {
+            header: format!("{} files failed to parse", report.failures.len()),
+            labels: vec!["File".into(), "Error".into()],
+            rows: report
+                .failures
+                .iter()
+                .map(|f| vec![f.path.clone(), f.error.clone()])
+                .collect(),
+        };
+        failures.print();
+    }
+    if !report.unreadable.is_empty() {
+        Table {
+            header: format!("{} files could not be read", report.unreadable.len()),
+            labels: vec!["File".into(), "Error".into()],
+            rows: report
+                .unreadable
+                .iter()
+                .map(|f| vec![f.path.clone(), f.error.clone()])
+                .collect(),
+        }
+        .print();
+    }
+
+    if report.skipped > 0 {
+        println!("Interrupted: {} files were not checked", report.skipped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/");
+
+    fn check(path: &Path) -> Outcome {
+        check_file(path, &ParseOptions::default())
+    }
+
+    #[test]
+    fn files_that_cant_be_read_or_unpacked_are_not_called_not_elf() {
+        let dir = std::env::temp_dir().join(format!("elk-check-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text = dir.join("notes.txt");
+        fs::write(&text, "not an ELF file").unwrap();
+        // gzip magic, then garbage
+        let gzip = dir.join("broken.ko.gz");
+        fs::write(
+            &gzip,
+            [0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 3, 0xff, 0xff, 0xff],
+        )
+        .unwrap();
+
+        assert!(matches!(check(&text), Outcome::NotElf));
+        assert!(matches!(
+            check(&dir.join("missing")),
+            Outcome::Unreadable(_)
+        ));
+        assert!(matches!(check(&gzip), Outcome::Undecodable(_)));
+        assert!(matches!(
+            check(Path::new(&format!("{}ladder/1-static", SAMPLES))),
+            Outcome::Checked(..)
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // The ladder's small PIEs keep their headers and symbol tables in their one executable
+    // segment; their code sections are what gets scored, unless the section headers are gone
+    #[test]
+    fn only_code_sections_are_scored_for_code_likeness() {
+        let not_code = |input: &[u8]| {
+            let file = FileHeader::parse_or_describe(&input.to_vec().into()).unwrap();
+            file.check_segment_contents()
+                .into_iter()
+                .any(|m| matches!(m, SegmentMismatch::NotCode(..)))
+        };
+        for rung in ["1-static", "2-pie", "3-needed", "4-backref", "5-lazy"] {
+            let input = fs::read(format!("{}ladder/{}", SAMPLES, rung)).unwrap();
+            assert!(!not_code(&input), "{}", rung);
+        }
+        // e_shoff, e_shnum and e_shstrndx zeroed: all there is to go by is the whole segment
+        let mut stripped = fs::read(format!("{}ladder/2-pie", SAMPLES)).unwrap();
+        stripped[0x28..0x30].fill(0);
+        stripped[0x3c..0x40].fill(0);
+        assert!(not_code(&stripped));
+    }
+}
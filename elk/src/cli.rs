@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeSet,
+    env,
+    error::Error,
+    ffi::OsStr,
+    io::{stdout, Write},
+};
+
+use clap_complete::{engine::CompletionCandidate, env::Shells};
+use delf::{
+    types::*,
+    view::{AddrMode, AddrView},
+    FileHeader,
+};
+
+use crate::{
+    config::{self, Format},
+    parse_number,
+};
+
+// Environment variable the registration scripts set when calling back into elk to complete
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+// `--format` for commands that print either tables or JSON
+#[derive(clap::Args, Debug)]
+pub struct FormatArg {
+    #[arg(
+        long,
+        value_enum,
+        help = "Output format, defaults to the config file's or text"
+    )]
+    pub format: Option<Format>,
+}
+
+impl FormatArg {
+    pub fn json(&self) -> bool {
+        match self.format {
+            Some(format) => format == Format::Json,
+            None => config::get().json(),
+        }
+    }
+}
+
+// `--addresses` for commands that print addresses inside the file
+#[derive(clap::Args, Debug)]
+pub struct AddrArgs {
+    #[arg(
+        long,
+        value_name = "VIEW",
+        value_parser = ["file", "base", "segment"],
+        default_value = "file",
+        help = "Print addresses as in the file, as base+offset, or as LOAD[n]+offset"
+    )]
+    pub addresses: String,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_parser = parse_number,
+        help = "Runtime load base; with --addresses base, prints the addresses the program sees"
+    )]
+    pub load_base: Option<u64>,
+}
+
+impl AddrArgs {
+    pub fn mode(&self) -> AddrMode {
+        AddrMode::parse(&self.addresses).unwrap_or_default()
+    }
+
+    pub fn view(&self, file: &FileHeader) -> AddrView {
+        AddrView::new(file, self.mode(), self.load_base)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CompletionsArgs {
+    #[arg(value_parser = ["bash", "elvish", "fish", "powershell", "zsh"])]
+    pub shell: String,
+}
+
+// The script calls back into elk on every completion, so values like section names come from
+// the file on the command line instead of a list baked in here
+pub fn completions(args: CompletionsArgs) -> Result<(), Box<dyn Error>> {
+    let shells = Shells::builtins();
+    let shell = shells.completer(&args.shell).ok_or_else(|| {
+        format!(
+            "unknown shell {:?}, expected one of {}",
+            args.shell,
+            shells.names().collect::<Vec<_>>().join(", ")
+        )
+    })?;
+    let elk = env::current_exe()?;
+    let out = stdout();
+    let mut out = out.lock();
+    shell.write_registration(COMPLETE_VAR, "elk", "elk", &elk.to_string_lossy(), &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+// While completing, elk's arguments are the words on the command line being completed, so the
+// file a flag refers to is the first of them that parses as ELF
+fn target_file() -> Option<FileHeader> {
+    env::args().skip(1).find_map(|arg| {
+        let input = crate::source::map_raw(&arg).ok()?;
+        if !input.starts_with(FileHeader::MAGIC) {
+            return None;
+        }
+        FileHeader::parse_or_describe(&input).ok()
+    })
+}
+
+fn candidates(names: BTreeSet<String>, current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+    names
+        .into_iter()
+        .filter(|name| name.starts_with(current.as_ref()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+pub fn section_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    let names = target_file()
+        .map(|file| {
+            file.section_headers
+                .iter()
+                .filter(|sh| sh.typ != SectionType::Null)
+                .map(|sh| sh.name.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    candidates(names, current)
+}
+
+pub fn symbol_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    let names = target_file()
+        .map(|file| {
+            let dynsyms = file
+                .section_headers
+                .iter()
+                .position(|sh| sh.typ == SectionType::DynSym)
+                .map(|idx| file.symbols_in(idx))
+                .unwrap_or_default();
+            file.read_section_syms()
+                .into_iter()
+                .chain(dynsyms)
+                .map(|sym| sym.name)
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    candidates(names, current)
+}
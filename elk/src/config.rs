@@ -0,0 +1,137 @@
+use std::{convert::TryFrom, env, error::Error, fs, io, path::PathBuf, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::{exit::Failure, parse_number};
+
+const CONFIG_FILE: &str = "elk/config.toml";
+// Where `base = "random"` places objects, far from anything elk itself has mapped
+const RANDOM_BASE: u64 = 0x1000_0000_0000;
+const RANDOM_PAGES: u64 = 1 << 24;
+const PAGE_SIZE: u64 = 0x1000;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// Defaults read from the config file; every setting is overridden by its command line flag
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub format: Option<Format>,
+    pub color: Option<String>,
+    pub theme: Option<String>,
+    // Searched after LD_LIBRARY_PATH and RUNPATH, ahead of the system directories
+    pub library_path: Vec<PathBuf>,
+    pub sandbox: Sandbox,
+    pub base: Base,
+    // Loader limits, the loader's defaults when unset
+    pub max_mapped: Option<u64>,
+    pub max_objects: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Text,
+    Json,
+}
+
+// How much `elk run` isolates the program it loads
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Sandbox {
+    // In elk's own process, the way elk always has
+    #[default]
+    None,
+    // In a forked child, so a crash leaves elk standing
+    Fork,
+    // Forked, and with every relocation slot checked before it is written
+    Strict,
+}
+
+// Where `elk run` maps position independent objects when no --base is given: somewhere random
+// unless the config file says otherwise, `default` being loader::DEFAULT_BASE
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Base {
+    Default,
+    #[default]
+    Random,
+    Fixed(u64),
+}
+
+impl TryFrom<String> for Base {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "default" => Ok(Base::Default),
+            "random" => Ok(Base::Random),
+            _ => parse_number(&value).map(Base::Fixed).map_err(|_| {
+                format!(
+                    "base must be default, random or an address, got {:?}",
+                    value
+                )
+            }),
+        }
+    }
+}
+
+impl Base {
+    // None leaves the choice to the loader
+    pub fn pick(self) -> Option<u64> {
+        match self {
+            Base::Default => None,
+            Base::Fixed(base) => Some(base),
+            Base::Random => {
+                let mut random = 0u64;
+                let filled = unsafe {
+                    libc::getrandom(
+                        &mut random as *mut u64 as *mut libc::c_void,
+                        std::mem::size_of::<u64>(),
+                        0,
+                    )
+                };
+                match filled {
+                    8 => Some(RANDOM_BASE + random % RANDOM_PAGES * PAGE_SIZE),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl Config {
+    pub fn json(&self) -> bool {
+        self.format == Some(Format::Json)
+    }
+}
+
+// Reads `path`, or the user's config file when there is none. Only an explicit path has to exist.
+pub fn init(path: Option<&str>) -> Result<&'static Config, Box<dyn Error>> {
+    let (path, required) = match path {
+        Some(path) => (Some(PathBuf::from(path)), true),
+        None => (default_path(), false),
+    };
+    let config = match path {
+        Some(path) => match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|e| Failure::parse(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Config::default(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        },
+        None => Config::default(),
+    };
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join(CONFIG_FILE))
+}
@@ -0,0 +1,211 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Component, Path},
+};
+
+use delf::detect::{detect, Format};
+
+use crate::{source, tables::Table};
+
+const CPIO_HEADER: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const TAR_BLOCK: usize = 512;
+
+pub struct Member {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "List and extract the ELF members of a cpio or tar image")]
+pub struct Args {
+    #[arg(long, help = "List every member, not only ELF files")]
+    all: bool,
+    #[arg(long, value_name = "DIR", value_hint = clap::ValueHint::DirPath)]
+    extract: Option<String>,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    image: String,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let (image, all, extract) = (&args.image, args.all, &args.extract);
+
+    let input = source::read(image)?;
+    let members = members(&input).ok_or_else(|| {
+        format!(
+            "{}: expected a cpio or tar archive, found {}",
+            image,
+            detect(&input)
+        )
+    })?;
+    let shown: Vec<(&Member, Format)> = members
+        .iter()
+        .map(|m| (m, detect(&m.data)))
+        .filter(|(_, format)| all || matches!(format, Format::Elf { .. }))
+        .collect();
+
+    let table = Table {
+        header: format!("{} of {} members in {}", shown.len(), members.len(), image),
+        labels: vec!["Member".into(), "Size".into(), "Format".into()],
+        rows: shown
+            .iter()
+            .map(|(m, format)| {
+                vec![
+                    format!("{}:{}", image, m.name),
+                    crate::size::human(m.data.len() as u64),
+                    format.to_string(),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+
+    if let Some(dir) = extract {
+        for (m, _) in &shown {
+            // Never let a crafted member name escape the destination
+            let name = Path::new(&m.name);
+            if name
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+            {
+                eprintln!("Skipping unsafe member name {:?}", m.name);
+                continue;
+            }
+            let dest = Path::new(dir).join(name);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&dest, &m.data)?;
+        }
+        println!("Extracted {} members to {}", shown.len(), dir);
+    }
+    Ok(())
+}
+
+// Regular files of a cpio or tar archive, or None if `input` is neither
+pub fn members(input: &[u8]) -> Option<Vec<Member>> {
+    match detect(input) {
+        Format::Cpio => Some(cpio(input)),
+        Format::Tar => Some(tar(input)),
+        _ => None,
+    }
+}
+
+pub fn member(input: &[u8], name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let wanted = name.trim_start_matches("./").trim_start_matches('/');
+    members(input)
+        .ok_or_else(|| format!("not a cpio or tar archive: detected {}", detect(input)))?
+        .into_iter()
+        .find(|m| m.name == wanted)
+        .map(|m| m.data)
+        .ok_or_else(|| format!("no member named {:?}", name).into())
+}
+
+fn hex_field(header: &[u8], index: usize) -> Option<usize> {
+    let start = 6 + 8 * index;
+    let field = std::str::from_utf8(header.get(start..start + 8)?).ok()?;
+    usize::from_str_radix(field, 16).ok()
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+// newc/crc cpio. Initramfs images often concatenate an uncompressed archive (early microcode)
+// with a compressed one, so continue with whatever follows the trailer.
+fn cpio(input: &[u8]) -> Vec<Member> {
+    let mut members = Vec::new();
+    let mut pos = 0;
+    while let Some(header) = input.get(pos..pos + CPIO_HEADER) {
+        if detect(header) != Format::Cpio {
+            break;
+        }
+        let (mode, file_size, name_size) = match (
+            hex_field(header, 1),
+            hex_field(header, 6),
+            hex_field(header, 11),
+        ) {
+            (Some(m), Some(f), Some(n)) => (m, f, n),
+            _ => break,
+        };
+        let name_start = pos + CPIO_HEADER;
+        let data_start = align4(name_start + name_size);
+        let (name, data) = match (
+            input.get(name_start..name_start + name_size.saturating_sub(1)),
+            input.get(data_start..data_start + file_size),
+        ) {
+            (Some(name), Some(data)) => (String::from_utf8_lossy(name), data),
+            _ => break,
+        };
+        pos = align4(data_start + file_size);
+
+        if name == CPIO_TRAILER {
+            while input.get(pos) == Some(&0) {
+                pos += 1;
+            }
+            if let Ok(rest) = source::decode(input[pos..].to_vec()) {
+                if detect(&rest) == Format::Cpio {
+                    members.extend(cpio(&rest));
+                }
+            }
+            break;
+        }
+        if mode & 0o170000 == 0o100000 {
+            members.push(Member {
+                name: name.trim_start_matches("./").to_string(),
+                data: data.to_vec(),
+            });
+        }
+    }
+    members
+}
+
+fn octal_field(field: &[u8]) -> Option<usize> {
+    let digits = std::str::from_utf8(field).ok()?;
+    usize::from_str_radix(digits.trim_matches(|c: char| c == '\0' || c == ' '), 8).ok()
+}
+
+fn c_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// ustar, plus GNU long names
+fn tar(input: &[u8]) -> Vec<Member> {
+    let mut members = Vec::new();
+    let mut long_name = None;
+    let mut pos = 0;
+    while let Some(header) = input.get(pos..pos + TAR_BLOCK) {
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = match octal_field(&header[124..136]) {
+            Some(size) => size,
+            None => break,
+        };
+        let data = match input.get(pos + TAR_BLOCK..pos + TAR_BLOCK + size) {
+            Some(data) => data,
+            None => break,
+        };
+        pos += TAR_BLOCK + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+        let name = long_name.take().unwrap_or_else(|| {
+            let (prefix, name) = (c_string(&header[345..500]), c_string(&header[..100]));
+            if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            }
+        });
+        match header[156] {
+            b'L' => long_name = Some(c_string(data)),
+            b'0' | 0 => members.push(Member {
+                name: name.trim_start_matches("./").to_string(),
+                data: data.to_vec(),
+            }),
+            _ => {}
+        }
+    }
+    members
+}
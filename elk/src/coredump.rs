@@ -0,0 +1,296 @@
+use std::{collections::HashMap, error::Error, ops::Range, path::PathBuf};
+
+use delf::{coredump::CoreFile, types::SegmentType, FileHeader};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg,
+    crash,
+    exit::Failure,
+    schema, source,
+    symbolize::{Symbolizer, Target},
+    tables::Table,
+};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Show the signal, registers and memory map a core dump was taken with")]
+pub struct Args {
+    #[arg(
+        long = "debug-dir",
+        value_name = "DIR",
+        value_hint = clap::ValueHint::DirPath,
+        help = "Extra directory to look for separate debuginfo in"
+    )]
+    debug_dirs: Vec<PathBuf>,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    dump: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "CoreReport")]
+struct Report {
+    pid: Option<u32>,
+    // The start of the command line, as the kernel recorded it
+    command: Option<String>,
+    signal: Option<Signal>,
+    // The thread the signal was delivered to first
+    threads: Vec<Thread>,
+    mappings: Vec<Mapping>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Signal {
+    number: u32,
+    name: Option<String>,
+    code: i32,
+    // Where the fault happened, for SIGSEGV, SIGBUS, SIGILL and SIGFPE
+    address: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Thread {
+    pid: u32,
+    signal: u16,
+    pc: Option<u64>,
+    // symbol+offset, or file+offset when the file has no symbols
+    location: Option<String>,
+    registers: Vec<Register>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Register {
+    name: String,
+    value: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Mapping {
+    start: u64,
+    end: u64,
+    permissions: String,
+    // Bytes the core holds of it; unchanged file-backed pages are left out
+    dumped: u64,
+    file: Option<String>,
+    offset: Option<u64>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Report>()
+}
+
+// Turns addresses of the dumped process into locations in the files it had mapped
+struct Locator<'a> {
+    core: &'a CoreFile,
+    symbolizer: Symbolizer,
+    // LOAD segments of each mapped file, as file range and link-time address
+    segments: HashMap<String, Vec<(Range<u64>, u64)>>,
+}
+
+impl Locator<'_> {
+    fn locate(&mut self, addr: u64) -> Option<String> {
+        let (file, offset) = self.core.file_at(addr)?;
+        let segments = self
+            .segments
+            .entry(file.path.clone())
+            .or_insert_with(|| load_segments(&file.path));
+        let vaddr = segments
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map(|(range, vaddr)| vaddr + offset - range.start);
+        let object = self.symbolizer.object_path(&file.path, None);
+        vaddr
+            .and_then(|vaddr| self.symbolizer.locate(Some(&object), Target::Addr(vaddr)))
+            .map(|location| location.to_string())
+            .or_else(|| Some(format!("{}+{:#x}", file.path, offset)))
+    }
+}
+
+// Empty for files that can't be read anymore
+fn load_segments(path: &str) -> Vec<(Range<u64>, u64)> {
+    let input = match source::read(path) {
+        Ok(input) => input,
+        Err(_) => return Vec::new(),
+    };
+    match FileHeader::parse_or_describe(&input) {
+        Ok(file) => file
+            .segments_of_type(SegmentType::Load)
+            .map(|ph| (ph.offset.0..ph.offset.0 + ph.file_size.0, ph.virt_addr.0))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.dump;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let core = CoreFile::parse(&file)
+        .ok_or_else(|| Failure::parse(format!("{}: not a core dump, but {:?}", path, file.typ)))?;
+    let mut locator = Locator {
+        core: &core,
+        symbolizer: Symbolizer::new(Vec::new(), args.debug_dirs),
+        segments: HashMap::new(),
+    };
+
+    let report = Report {
+        pid: core.process.as_ref().map(|p| p.pid),
+        command: core.process.as_ref().map(|p| p.args.clone()),
+        signal: core.signal.as_ref().map(|s| Signal {
+            number: s.signal,
+            name: crash::signal_name(s.signal as i32).map(str::to_string),
+            code: s.code,
+            address: s.addr,
+        }),
+        threads: core
+            .threads
+            .iter()
+            .map(|thread| {
+                let pc = thread.pc().map(|(_, pc)| pc);
+                Thread {
+                    pid: thread.pid,
+                    signal: thread.signal,
+                    pc,
+                    location: pc.and_then(|pc| locator.locate(pc)),
+                    registers: thread
+                        .registers
+                        .iter()
+                        .map(|&(name, value)| Register {
+                            name: name.to_string(),
+                            value,
+                        })
+                        .collect(),
+                }
+            })
+            .collect(),
+        mappings: core
+            .mappings
+            .iter()
+            .map(|m| Mapping {
+                start: m.start,
+                end: m.end,
+                permissions: format!("{:?}", m.flags).replace(' ', ""),
+                dumped: m.dumped,
+                file: m.file.as_ref().map(|(path, _)| path.clone()),
+                offset: m.file.as_ref().map(|&(_, offset)| offset),
+            })
+            .collect(),
+    };
+
+    if args.format.json() {
+        return schema::print_json("core", &report);
+    }
+    print_report(path, &report, &mut locator);
+    Ok(())
+}
+
+fn print_report(path: &str, report: &Report, locator: &mut Locator) {
+    let process = match (report.pid, &report.command) {
+        (Some(pid), Some(command)) => format!("pid {}, {}", pid, command),
+        _ => "unknown process".to_string(),
+    };
+    let signal = report.signal.as_ref().map_or_else(
+        || "-".to_string(),
+        |s| match &s.name {
+            Some(name) => format!("{} {} (code {})", s.number, name, s.code),
+            None => format!("{} (code {})", s.number, s.code),
+        },
+    );
+    let address = report.signal.as_ref().and_then(|s| s.address);
+    let faulting = report.threads.first();
+    Table {
+        header: format!("Core dump {} of {}", path, process),
+        labels: vec![
+            "Signal".into(),
+            "Fault address".into(),
+            "Thread".into(),
+            "PC".into(),
+            "Location".into(),
+        ],
+        rows: vec![vec![
+            signal,
+            address.map_or_else(|| "-".into(), |a| format!("{:#x}", a)),
+            faulting.map_or_else(|| "-".into(), |t| t.pid.to_string()),
+            faulting
+                .and_then(|t| t.pc)
+                .map_or_else(|| "-".into(), |pc| format!("{:#x}", pc)),
+            faulting
+                .and_then(|t| t.location.clone())
+                .unwrap_or_else(|| "-".into()),
+        ]],
+    }
+    .print();
+
+    if let Some(thread) = faulting {
+        Table {
+            header: format!("Registers of thread {}", thread.pid),
+            labels: vec!["Register".into(), "Value".into(), "Points to".into()],
+            rows: thread
+                .registers
+                .iter()
+                .map(|r| {
+                    vec![
+                        r.name.clone(),
+                        format!("{:#x}", r.value),
+                        locator.locate(r.value).unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        }
+        .print();
+    }
+    if report.threads.len() > 1 {
+        Table {
+            header: format!("{} threads", report.threads.len()),
+            labels: vec![
+                "Thread".into(),
+                "Signal".into(),
+                "PC".into(),
+                "Location".into(),
+            ],
+            rows: report
+                .threads
+                .iter()
+                .map(|t| {
+                    vec![
+                        t.pid.to_string(),
+                        t.signal.to_string(),
+                        t.pc.map_or_else(|| "-".into(), |pc| format!("{:#x}", pc)),
+                        t.location.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        }
+        .print();
+    }
+
+    Table {
+        header: format!("Memory map, {} mappings", report.mappings.len()),
+        labels: vec![
+            "Range".into(),
+            "Perms".into(),
+            "Dumped".into(),
+            "File".into(),
+        ],
+        rows: report
+            .mappings
+            .iter()
+            .map(|m| {
+                vec![
+                    format!("{:#x}..{:#x}", m.start, m.end),
+                    m.permissions.clone(),
+                    format!("{:#x}", m.dumped),
+                    match (&m.file, m.offset) {
+                        (Some(file), Some(offset)) => format!("{}+{:#x}", file, offset),
+                        _ => String::new(),
+                    },
+                ]
+            })
+            .collect(),
+    }
+    .print();
+}
@@ -0,0 +1,470 @@
+use std::{
+    error::Error,
+    ffi::CString,
+    fmt::{self, Write as _},
+    fs, io, mem,
+    os::raw::{c_int, c_void},
+    path::{Path, PathBuf},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use delf::index::AddressIndex;
+
+use crate::{
+    exit::{Failure, Status},
+    loader::Process,
+    symbolize::{Symbolizer, Target},
+    tables::Table,
+    tls,
+};
+
+const MAGIC: &str = "elk-crash-report 1";
+// Bytes of stack copied upwards from the stack pointer
+const STACK_SNIPPET: usize = 512;
+const STACK_LINE: usize = 32;
+const ALT_STACK_SIZE: usize = 64 * 1024;
+
+const SIGNALS: &[(c_int, &str)] = &[
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGTRAP, "SIGTRAP"),
+];
+
+const REGISTERS: &[(&str, c_int)] = &[
+    ("rip", libc::REG_RIP),
+    ("rsp", libc::REG_RSP),
+    ("rbp", libc::REG_RBP),
+    ("rax", libc::REG_RAX),
+    ("rbx", libc::REG_RBX),
+    ("rcx", libc::REG_RCX),
+    ("rdx", libc::REG_RDX),
+    ("rsi", libc::REG_RSI),
+    ("rdi", libc::REG_RDI),
+    ("r8", libc::REG_R8),
+    ("r9", libc::REG_R9),
+    ("r10", libc::REG_R10),
+    ("r11", libc::REG_R11),
+    ("r12", libc::REG_R12),
+    ("r13", libc::REG_R13),
+    ("r14", libc::REG_R14),
+    ("r15", libc::REG_R15),
+    ("eflags", libc::REG_EFL),
+];
+
+// Everything the signal handler needs, prepared while it is still safe to allocate: the handler
+// itself may only make async-signal-safe calls, so it can't lock the object list or build strings
+struct Armed {
+    path: CString,
+    // Module list and relocation log, already in report format
+    tail: Vec<u8>,
+}
+
+static ARMED: AtomicPtr<Armed> = AtomicPtr::new(ptr::null_mut());
+
+// Catches fatal signals and writes a crash report to `report` before exiting with
+// Status::Crashed.
+// Modules and relocations are captured now, so install right before jumping into the program.
+pub fn install(process: &Process, main_path: &str, report: &str) -> Result<(), Box<dyn Error>> {
+    let main_path = fs::canonicalize(main_path)
+        .map_or_else(|_| main_path.to_string(), |p| p.display().to_string());
+    let mut tail = String::new();
+    for module in process.modules() {
+        writeln!(
+            tail,
+            "module {:#x} {:#x} {:#x} {} {}",
+            module.base,
+            module.start,
+            module.end,
+            module.build_id.as_deref().unwrap_or("-"),
+            module.path.as_deref().unwrap_or(&main_path)
+        )?;
+    }
+    for reloc in process.relocation_log() {
+        writeln!(
+            tail,
+            "reloc {:#x} {:?} {:#x}",
+            reloc.addr, reloc.typ, reloc.value
+        )?;
+    }
+    let armed = Box::new(Armed {
+        path: CString::new(report)?,
+        tail: tail.into_bytes(),
+    });
+    // A previous report's state is leaked: a handler might be reading it right now
+    ARMED.store(Box::into_raw(armed), Ordering::SeqCst);
+
+    unsafe {
+        // Stack overflows fault on the guard page, the handler needs a stack of its own
+        let stack = Box::leak(vec![0u8; ALT_STACK_SIZE].into_boxed_slice());
+        let alt = libc::stack_t {
+            ss_sp: stack.as_mut_ptr() as *mut c_void,
+            ss_flags: 0,
+            ss_size: ALT_STACK_SIZE,
+        };
+        if libc::sigaltstack(&alt, ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handler as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK | libc::SA_RESETHAND;
+        libc::sigemptyset(&mut action.sa_mask);
+        for (signal, _) in SIGNALS {
+            if libc::sigaction(*signal, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+    }
+    Ok(())
+}
+
+// The SIG* name of the signals crash reports are written for
+pub fn signal_name(signal: c_int) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|(s, _)| *s == signal)
+        .map(|&(_, name)| name)
+}
+
+// Fixed-size line buffer, so formatting in the handler never allocates
+struct Line {
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl fmt::Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl Line {
+    fn new() -> Self {
+        Self {
+            buf: [0; 256],
+            len: 0,
+        }
+    }
+
+    unsafe fn flush(&mut self, fd: c_int) {
+        write_all(fd, &self.buf[..self.len]);
+        self.len = 0;
+    }
+}
+
+unsafe fn write_all(fd: c_int, mut data: &[u8]) {
+    while !data.is_empty() {
+        let written = libc::write(fd, data.as_ptr() as *const c_void, data.len());
+        if written <= 0 {
+            return;
+        }
+        data = &data[written as usize..];
+    }
+}
+
+// Copies our own memory through the kernel, which reports unmapped ranges instead of faulting
+unsafe fn read_memory(addr: u64, out: &mut [u8]) -> bool {
+    let local = libc::iovec {
+        iov_base: out.as_mut_ptr() as *mut c_void,
+        iov_len: out.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: addr as *mut c_void,
+        iov_len: out.len(),
+    };
+    libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) == out.len() as isize
+}
+
+extern "C" fn handler(signal: c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    let armed = ARMED.load(Ordering::SeqCst);
+    if armed.is_null() {
+        return;
+    }
+    // Formatting integers and strings through core::fmt doesn't allocate
+    unsafe {
+        // errno is one of elk's thread-locals, and the program may have %fs
+        tls::leave();
+        let armed = &*armed;
+        let fd = libc::open(
+            armed.path.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC,
+            0o644,
+        );
+        if fd >= 0 {
+            write_report(fd, armed, signal, info, context);
+            libc::close(fd);
+
+            let mut line = Line::new();
+            let _ = write!(
+                line,
+                "elk: caught signal {}, crash report written to ",
+                signal
+            );
+            line.flush(libc::STDERR_FILENO);
+            write_all(libc::STDERR_FILENO, armed.path.as_bytes());
+            write_all(libc::STDERR_FILENO, b"\n");
+        }
+        // The report records the signal; the exit status says the program crashed under elk
+        libc::_exit(Status::Crashed as c_int);
+    }
+}
+
+unsafe fn write_report(
+    fd: c_int,
+    armed: &Armed,
+    signal: c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut c_void,
+) {
+    let name = signal_name(signal).unwrap_or("?");
+    let gregs = &(*(context as *const libc::ucontext_t)).uc_mcontext.gregs;
+    let mut line = Line::new();
+    let _ = writeln!(line, "{}", MAGIC);
+    line.flush(fd);
+    let _ = writeln!(line, "signal {} {}", signal, name);
+    line.flush(fd);
+    let _ = writeln!(line, "fault-addr {:#x}", (*info).si_addr() as u64);
+    line.flush(fd);
+    for (name, reg) in REGISTERS {
+        let _ = writeln!(line, "reg {} {:#x}", name, gregs[*reg as usize] as u64);
+        line.flush(fd);
+    }
+
+    let sp = gregs[libc::REG_RSP as usize] as u64;
+    let mut chunk = [0u8; STACK_LINE];
+    for offset in (0..STACK_SNIPPET).step_by(STACK_LINE) {
+        let addr = sp + offset as u64;
+        if !read_memory(addr, &mut chunk) {
+            break;
+        }
+        let _ = write!(line, "stack {:#x} ", addr);
+        for byte in &chunk {
+            let _ = write!(line, "{:02x}", byte);
+        }
+        let _ = writeln!(line);
+        line.flush(fd);
+    }
+    write_all(fd, &armed.tail);
+}
+
+// A crash report read back from disk
+#[derive(Default)]
+struct Report {
+    signal: String,
+    fault_addr: u64,
+    registers: Vec<(String, u64)>,
+    stack: Vec<(u64, u64)>,
+    modules: Vec<ReportModule>,
+    relocations: Vec<(u64, String, u64)>,
+}
+
+struct ReportModule {
+    base: u64,
+    start: u64,
+    end: u64,
+    build_id: Option<String>,
+    path: String,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Symbolize a crash report written by elk run")]
+pub struct Args {
+    #[arg(
+        long = "debug-dir",
+        value_name = "DIR",
+        value_hint = clap::ValueHint::DirPath,
+        help = "Extra directory to look for separate debuginfo in"
+    )]
+    debug_dirs: Vec<PathBuf>,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    report: String,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.report;
+    let report = parse_report(&fs::read_to_string(path)?)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let mut symbolizer = Symbolizer::new(Vec::new(), args.debug_dirs);
+    let modules: AddressIndex<_> = report.modules.iter().map(|m| (m.start..m.end, m)).collect();
+    let mut describe = |addr: u64| -> Option<String> {
+        let (_, module) = modules.get(addr)?;
+        let object = symbolizer.object_path(&module.path, module.build_id.as_deref());
+        let location = symbolizer.locate(Some(&object), Target::Addr(addr - module.base))?;
+        Some(location.to_string())
+    };
+
+    let summary = Table {
+        header: format!("Crash report {}", path),
+        labels: vec!["Signal".into(), "Fault address".into(), "Location".into()],
+        rows: vec![vec![
+            report.signal.clone(),
+            format!("{:#x}", report.fault_addr),
+            describe(report.fault_addr).unwrap_or_else(|| "-".into()),
+        ]],
+    };
+    summary.print();
+
+    let registers = Table {
+        header: "Registers".into(),
+        labels: vec!["Register".into(), "Value".into(), "Points to".into()],
+        rows: report
+            .registers
+            .iter()
+            .map(|(name, value)| {
+                vec![
+                    name.clone(),
+                    format!("{:#x}", value),
+                    describe(*value).unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    };
+    registers.print();
+
+    // Without unwind tables the best guess at callers is every stack word pointing into a symbol
+    let mut frames = Vec::new();
+    if let Some((_, rip)) = report.registers.iter().find(|(name, _)| name == "rip") {
+        if let Some(location) = describe(*rip) {
+            frames.push(vec![
+                "#0".into(),
+                "rip".into(),
+                format!("{:#x}", rip),
+                location,
+            ]);
+        }
+    }
+    for (slot, value) in &report.stack {
+        if let Some(location) = describe(*value).filter(|l| !l.contains("!0x")) {
+            frames.push(vec![
+                format!("#{}", frames.len()),
+                format!("{:#x}", slot),
+                format!("{:#x}", value),
+                location,
+            ]);
+        }
+    }
+    let backtrace = Table {
+        header: "Likely backtrace (scanned from the stack)".into(),
+        labels: vec![
+            "Frame".into(),
+            "Stack slot".into(),
+            "Address".into(),
+            "Location".into(),
+        ],
+        rows: frames,
+    };
+    backtrace.print();
+
+    let modules = Table {
+        header: "Modules".into(),
+        labels: vec![
+            "Base".into(),
+            "Range".into(),
+            "Build ID".into(),
+            "Path".into(),
+        ],
+        rows: report
+            .modules
+            .iter()
+            .map(|m| {
+                vec![
+                    format!("{:#x}", m.base),
+                    format!("{:#x}..{:#x}", m.start, m.end),
+                    m.build_id.clone().unwrap_or_else(|| "-".into()),
+                    m.path.clone(),
+                ]
+            })
+            .collect(),
+    };
+    modules.print();
+
+    // A jump through a bad GOT slot shows up as a relocation whose value is the faulting address
+    let suspects: Vec<_> = report
+        .relocations
+        .iter()
+        .filter(|(slot, _, value)| *slot == report.fault_addr || *value == report.fault_addr)
+        .map(|(slot, typ, value)| {
+            vec![format!("{:#x}", slot), typ.clone(), format!("{:#x}", value)]
+        })
+        .collect();
+    if suspects.is_empty() {
+        println!(
+            "{} relocations applied, none involving the fault address",
+            report.relocations.len()
+        );
+    } else {
+        let relocations = Table {
+            header: "Relocations involving the fault address".into(),
+            labels: vec!["Slot".into(), "Type".into(), "Value".into()],
+            rows: suspects,
+        };
+        relocations.print();
+    }
+    Ok(())
+}
+
+// The signal number recorded in a crash report
+pub fn report_signal(path: &Path) -> Option<c_int> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut lines = text.lines();
+    if lines.next() != Some(MAGIC) {
+        return None;
+    }
+    lines.find_map(|line| match line.split(' ').collect::<Vec<_>>()[..] {
+        ["signal", number, _] => number.parse().ok(),
+        _ => None,
+    })
+}
+
+fn parse_report(text: &str) -> Result<Report, Box<dyn Error>> {
+    let mut lines = text.lines();
+    if lines.next() != Some(MAGIC) {
+        return Err("not an elk crash report".into());
+    }
+    let hex = |s: &str| u64::from_str_radix(s.trim_start_matches("0x"), 16);
+    let mut report = Report::default();
+    for line in lines {
+        let fields: Vec<_> = line.splitn(6, ' ').collect();
+        match fields[..] {
+            ["signal", number, name] => report.signal = format!("{} ({})", name, number),
+            ["fault-addr", addr] => report.fault_addr = hex(addr)?,
+            ["reg", name, value] => report.registers.push((name.to_string(), hex(value)?)),
+            ["stack", addr, bytes] => {
+                let addr = hex(addr)?;
+                for (i, word) in bytes.as_bytes().chunks_exact(16).enumerate() {
+                    // Hex dump of little-endian words, lowest address first
+                    let mut value = [0u8; 8];
+                    for (j, byte) in word.chunks_exact(2).enumerate() {
+                        value[j] = u8::from_str_radix(std::str::from_utf8(byte)?, 16)?;
+                    }
+                    report
+                        .stack
+                        .push((addr + i as u64 * 8, u64::from_le_bytes(value)));
+                }
+            }
+            ["module", base, start, end, build_id, path] => report.modules.push(ReportModule {
+                base: hex(base)?,
+                start: hex(start)?,
+                end: hex(end)?,
+                build_id: Some(build_id.to_string()).filter(|id| id != "-"),
+                path: path.to_string(),
+            }),
+            ["reloc", slot, typ, value] => {
+                report
+                    .relocations
+                    .push((hex(slot)?, typ.to_string(), hex(value)?))
+            }
+            _ => return Err(format!("unexpected line {:?}", line).into()),
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,58 @@
+use std::fmt::Write;
+
+use iced_x86::{Decoder, DecoderOptions, Formatter, Instruction, NasmFormatter};
+
+// Disassembles `input` loaded at `origin` in-process, in the layout ndisasm prints so listings
+// parse the same either way. Decoding restarts at `sync` when given, as with ndisasm's -s.
+pub fn listing(bitness: u32, input: &[u8], origin: u64, sync: Option<u64>) -> String {
+    let mut formatter = NasmFormatter::new();
+    let options = formatter.options_mut();
+    options.set_hex_prefix("0x");
+    options.set_hex_suffix("");
+    options.set_uppercase_hex(false);
+    options.set_show_branch_size(false);
+
+    let split = sync
+        .and_then(|sync| sync.checked_sub(origin))
+        .map_or(0, |offset| (offset as usize).min(input.len()));
+    let mut out = String::new();
+    let (before, after) = input.split_at(split);
+    decode(&mut formatter, bitness, before, origin, &mut out);
+    decode(
+        &mut formatter,
+        bitness,
+        after,
+        origin + split as u64,
+        &mut out,
+    );
+    out
+}
+
+fn decode(formatter: &mut NasmFormatter, bitness: u32, input: &[u8], ip: u64, out: &mut String) {
+    let mut decoder = Decoder::with_ip(bitness, input, ip, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut text = String::new();
+    while decoder.can_decode() {
+        let position = decoder.position();
+        decoder.decode_out(&mut instruction);
+        text.clear();
+        // ndisasm gives up on one byte at a time
+        let len = match instruction.is_invalid() {
+            true => {
+                decoder.set_position(position + 1).unwrap();
+                decoder.set_ip(ip + position as u64 + 1);
+                write!(text, "db 0x{:02x}", input[position]).unwrap();
+                1
+            }
+            false => {
+                formatter.format(&instruction, &mut text);
+                instruction.len()
+            }
+        };
+        let bytes: String = input[position..position + len]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        writeln!(out, "{:08X}  {:<18}{}", ip + position as u64, bytes, text).unwrap();
+    }
+}
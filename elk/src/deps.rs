@@ -0,0 +1,632 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    error::Error,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use delf::{types::*, FileHeader};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{cli::FormatArg, config, exit::Failure, schema, source, tables::Table};
+
+const LD_SO_CONF: &str = "/etc/ld.so.conf";
+const DEFAULT_DIRS: &[&str] = &[
+    "/lib64",
+    "/usr/lib64",
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib",
+    "/usr/lib",
+];
+// What $LIB and $PLATFORM stand for in search paths on x86-64
+const LIB: &str = "lib64";
+const PLATFORM: &str = "x86_64";
+
+pub struct Object {
+    pub name: String,
+    pub path: PathBuf,
+    pub file: FileHeader,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Dependency {
+    name: String,
+    path: Option<String>,
+    needed_by: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Unresolved {
+    object: String,
+    symbol: String,
+    version: Option<String>,
+    reason: String,
+}
+
+// A search path entry anyone can plant libraries in
+#[derive(Serialize, JsonSchema)]
+struct WritableEntry {
+    object: String,
+    // RPATH or RUNPATH
+    tag: String,
+    entry: String,
+    expanded: String,
+    // The expanded directory, or its closest existing parent when it doesn't exist
+    writable: String,
+}
+
+#[derive(Serialize, JsonSchema, Default)]
+#[schemars(rename = "DepsReport")]
+struct Report {
+    file: String,
+    libraries: Vec<Dependency>,
+    unresolved: Vec<Unresolved>,
+    // Undefined weak references nothing defines; these are allowed to stay null
+    unresolved_weak: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    writable_paths: Vec<WritableEntry>,
+}
+
+// One dynamic symbol definition: its version and whether it is reachable by default
+struct Definition {
+    version: Option<String>,
+    hidden: bool,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Resolve DT_NEEDED libraries the way the dynamic linker would")]
+pub struct Args {
+    #[arg(
+        long,
+        help = "Also check that every undefined symbol and version is provided"
+    )]
+    verify: bool,
+    #[arg(
+        long,
+        help = "Report RPATH and RUNPATH entries that expand to world-writable directories"
+    )]
+    audit_rpath: bool,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Report>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let (path, verify) = (&args.file, args.verify);
+
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let root = Object {
+        name: path.clone(),
+        path: PathBuf::from(path),
+        file,
+    };
+    let mut report = Report {
+        file: path.clone(),
+        ..Default::default()
+    };
+    let objects = closure(root, &mut report);
+    if verify {
+        check_symbols(&objects, &mut report);
+        check_versions(&objects, &mut report);
+    }
+    if args.audit_rpath {
+        audit_search_paths(&objects, &mut report);
+    }
+
+    if args.format.json() {
+        schema::print_json("deps", &report)?;
+    } else {
+        print_report(&report, verify, args.audit_rpath);
+    }
+
+    let missing = report.libraries.iter().filter(|d| d.path.is_none()).count();
+    match (
+        missing,
+        report.unresolved.len(),
+        report.writable_paths.len(),
+    ) {
+        (0, 0, 0) => Ok(()),
+        (missing, unresolved, writable) => Err(Failure::findings(format!(
+            "{}: {} missing libraries, {} unresolved symbols, {} world-writable search paths",
+            path, missing, unresolved, writable
+        ))
+        .into()),
+    }
+}
+
+// `file` followed by every library it pulls in, in load order. Missing libraries are left out.
+pub fn objects(path: &str, file: FileHeader) -> Vec<Object> {
+    let root = Object {
+        name: path.to_string(),
+        path: PathBuf::from(path),
+        file,
+    };
+    closure(root, &mut Report::default())
+}
+
+// Loads DT_NEEDED libraries breadth first, the order the dynamic linker builds its global scope in
+fn closure(root: Object, report: &mut Report) -> Vec<Object> {
+    let library_path: Vec<String> = env::var("LD_LIBRARY_PATH")
+        .map(|p| {
+            p.split(':')
+                .filter(|d| !d.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    let system_dirs = system_dirs();
+
+    let exe_rpath = root.file.dynamic_strings(DynamicTag::RPath);
+    let mut objects = vec![root];
+    let mut queue = VecDeque::from(vec![0]);
+    while let Some(index) = queue.pop_front() {
+        let needed = objects[index].file.dynamic_strings(DynamicTag::Needed);
+        for name in needed {
+            if objects.iter().any(|o| o.name == name)
+                || report.libraries.iter().any(|d| d.name == name)
+            {
+                continue;
+            }
+            let object = &objects[index];
+            let origin = object.path.parent().unwrap_or_else(|| Path::new("."));
+            let runpath = object.file.dynamic_strings(DynamicTag::Runpath);
+            // DT_RPATH is ignored once DT_RUNPATH is present, and the executable's RPATH applies
+            // to every library without a RUNPATH of its own
+            let mut dirs = Vec::new();
+            if runpath.is_empty() {
+                dirs.extend(expand(
+                    &object.file.dynamic_strings(DynamicTag::RPath),
+                    origin,
+                ));
+                dirs.extend(expand(&exe_rpath, origin));
+            }
+            dirs.extend(library_path.iter().cloned());
+            dirs.extend(expand(&runpath, origin));
+            dirs.extend(system_dirs.iter().cloned());
+
+            let found = find_library(&name, &dirs, &object.file);
+            report.libraries.push(Dependency {
+                name: name.clone(),
+                path: found.as_ref().map(|(p, _)| p.display().to_string()),
+                needed_by: object.name.clone(),
+            });
+            if let Some((path, file)) = found {
+                objects.push(Object { name, path, file });
+                queue.push_back(objects.len() - 1);
+            }
+        }
+    }
+    objects
+}
+
+// Where libraries are looked for after RPATH, LD_LIBRARY_PATH and RUNPATH: the config file's
+// library path, ld.so.conf, then the built-in directories
+pub fn system_dirs() -> Vec<String> {
+    let mut dirs: Vec<String> = config::get()
+        .library_path
+        .iter()
+        .map(|d| d.display().to_string())
+        .collect();
+    read_ld_so_conf(Path::new(LD_SO_CONF), &mut dirs);
+    dirs.extend(DEFAULT_DIRS.iter().map(|d| d.to_string()));
+    dirs
+}
+
+// Splits colon separated search paths and substitutes $ORIGIN, $LIB and $PLATFORM
+fn expand(paths: &[String], origin: &Path) -> Vec<String> {
+    entries(paths).map(|d| expand_tokens(d, origin)).collect()
+}
+
+fn entries(paths: &[String]) -> impl Iterator<Item = &str> {
+    paths
+        .iter()
+        .flat_map(|p| p.split(':'))
+        .filter(|d| !d.is_empty())
+}
+
+// Substitutes $ORIGIN, $LIB and $PLATFORM in one search path entry, as ld.so does. Braced tokens
+// (`${LIB}`) can be followed by anything; bare ones only when they end a path component, so
+// `$ORIGINAL` is left as it is, like any name ld.so doesn't know.
+pub fn expand_tokens(entry: &str, origin: &Path) -> String {
+    let origin = origin.to_string_lossy();
+    let value = |name: &str| match name {
+        "ORIGIN" => Some(&*origin),
+        "LIB" => Some(LIB),
+        "PLATFORM" => Some(PLATFORM),
+        _ => None,
+    };
+    let mut expanded = String::new();
+    let mut rest = entry;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let token = &rest[dollar + 1..];
+        // The token's name and how many bytes it takes up after the `$`
+        let (name, len) = match token.strip_prefix('{') {
+            Some(braced) => braced
+                .find('}')
+                .map_or(("", 0), |end| (&braced[..end], end + 2)),
+            None => {
+                let end = token.find('/').unwrap_or(token.len());
+                (&token[..end], end)
+            }
+        };
+        match value(name) {
+            Some(value) => {
+                expanded.push_str(value);
+                rest = &token[len..];
+            }
+            None => {
+                expanded.push('$');
+                rest = token;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// RPATH and RUNPATH entries of every object that expand to a directory anyone can write to, or
+// to a missing one anyone can create
+fn audit_search_paths(objects: &[Object], report: &mut Report) {
+    for object in objects {
+        // ld.so expands $ORIGIN to the absolute directory the object was loaded from
+        let origin = object.path.parent().unwrap_or_else(|| Path::new("."));
+        let origin = fs::canonicalize(origin).unwrap_or_else(|_| origin.to_path_buf());
+        for (tag, name) in [
+            (DynamicTag::RPath, "RPATH"),
+            (DynamicTag::Runpath, "RUNPATH"),
+        ] {
+            let paths = object.file.dynamic_strings(tag);
+            for entry in entries(&paths) {
+                let expanded = expand_tokens(entry, &origin);
+                if let Some(writable) = world_writable(Path::new(&expanded)) {
+                    let writable = writable.display().to_string();
+                    report.writable_paths.push(WritableEntry {
+                        object: object.name.clone(),
+                        tag: name.to_string(),
+                        entry: entry.to_string(),
+                        expanded,
+                        writable,
+                    });
+                }
+            }
+        }
+    }
+}
+
+// `dir`, or its closest parent that exists, if it is world-writable
+fn world_writable(dir: &Path) -> Option<&Path> {
+    let (dir, meta) = dir
+        .ancestors()
+        .find_map(|d| fs::metadata(d).ok().map(|meta| (d, meta)))?;
+    (meta.is_dir() && meta.permissions().mode() & 0o002 != 0).then_some(dir)
+}
+
+// Skips candidates of the wrong class or machine, like the dynamic linker does
+fn find_library(name: &str, dirs: &[String], parent: &FileHeader) -> Option<(PathBuf, FileHeader)> {
+    let candidates: Vec<PathBuf> = match name.contains('/') {
+        true => vec![PathBuf::from(name)],
+        false => dirs.iter().map(|d| Path::new(d).join(name)).collect(),
+    };
+    candidates.into_iter().find_map(|path| {
+        let input = source::map_raw(&path).ok()?;
+        let file = FileHeader::parse_or_describe(&input).ok()?;
+        match file.machine == parent.machine {
+            true => Some((path, file)),
+            false => None,
+        }
+    })
+}
+
+// Directories from ld.so.conf and its includes. The binary ld.so.cache is not consulted, it is
+// built from the same configuration.
+fn read_ld_so_conf(path: &Path, dirs: &mut Vec<String>) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        match line.strip_prefix("include") {
+            Some(pattern) if pattern.starts_with(char::is_whitespace) => {
+                let pattern = Path::new("/etc").join(pattern.trim());
+                for include in glob(&pattern) {
+                    read_ld_so_conf(&include, dirs);
+                }
+            }
+            _ if !line.is_empty() => dirs.push(line.to_string()),
+            _ => {}
+        }
+    }
+}
+
+// Enough of glob(3) for ld.so.conf includes: a single `*` in the file name
+fn glob(pattern: &Path) -> Vec<PathBuf> {
+    let (dir, name) = match (pattern.parent(), pattern.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.to_string_lossy()),
+        _ => return Vec::new(),
+    };
+    let (prefix, suffix) = match name.split_once('*') {
+        Some(parts) => parts,
+        None => return vec![pattern.to_path_buf()],
+    };
+    let mut matches: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| {
+                    let name = p.file_name().unwrap_or_default().to_string_lossy();
+                    name.len() >= prefix.len() + suffix.len()
+                        && name.starts_with(prefix)
+                        && name.ends_with(suffix)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    matches.sort();
+    matches
+}
+
+// Imports (undefined symbols) and exports of `file`, with their versions
+pub fn dynamic_symbols(file: &FileHeader) -> Vec<(Symbol, Option<delf::version::SymbolVersion>)> {
+    let index = match file
+        .section_headers
+        .iter()
+        .position(|sh| sh.typ == SectionType::DynSym)
+    {
+        Some(index) => index,
+        None => return Vec::new(),
+    };
+    let mut versions = file.dynamic_symbol_versions().into_iter();
+    file.symbols_in(index)
+        .into_iter()
+        .map(|sym| (sym, versions.next().flatten()))
+        .filter(|(sym, _)| {
+            !sym.name.is_empty() && (sym.shndx == SectionIdx::Undef || sym.is_exported())
+        })
+        .collect()
+}
+
+fn check_symbols(objects: &[Object], report: &mut Report) {
+    let mut scope: HashMap<String, Vec<Definition>> = HashMap::new();
+    let mut undefined = Vec::new();
+    for object in objects {
+        for (sym, version) in dynamic_symbols(&object.file) {
+            if sym.shndx == SectionIdx::Undef {
+                undefined.push((object, sym, version));
+                continue;
+            }
+            scope.entry(sym.name).or_default().push(Definition {
+                version: version.as_ref().map(|v| v.name.clone()),
+                hidden: version.is_some_and(|v| v.hidden),
+            });
+        }
+    }
+
+    let missing: Vec<&String> = report
+        .libraries
+        .iter()
+        .filter(|d| d.path.is_none())
+        .map(|d| &d.name)
+        .collect();
+    for (object, sym, version) in undefined {
+        // Symbols bound to a library that was not found are covered by its missing entry
+        if version
+            .as_ref()
+            .and_then(|v| v.file.as_ref())
+            .is_some_and(|file| missing.contains(&file))
+        {
+            continue;
+        }
+        let defs = scope.get(&sym.name).map(Vec::as_slice).unwrap_or_default();
+        let wanted = version.as_ref().map(|v| v.name.clone());
+        let resolved = match &wanted {
+            Some(v) => defs.iter().any(|d| d.version.as_ref() == Some(v)),
+            // Unversioned references bind to the default version
+            None => defs.iter().any(|d| !d.hidden),
+        };
+        if resolved {
+            continue;
+        }
+        if sym.bind() == Some(SymBind::Weak) {
+            report.unresolved_weak += 1;
+            continue;
+        }
+        let reason = match (&wanted, defs.is_empty()) {
+            (_, true) => "not defined by any library".to_string(),
+            (Some(v), false) => format!("defined, but not with version {}", v),
+            (None, false) => "only defined with hidden versions".to_string(),
+        };
+        report.unresolved.push(Unresolved {
+            object: object.name.clone(),
+            symbol: sym.name,
+            version: wanted,
+            reason,
+        });
+    }
+}
+
+// Every version an object requires must be defined by the library it names, even when no
+// symbol ends up binding to it
+fn check_versions(objects: &[Object], report: &mut Report) {
+    for object in objects {
+        for need in object.file.version_needs() {
+            let provider = objects.iter().find(|o| {
+                o.name == need.file
+                    || o.file.dynamic_strings(DynamicTag::SOName).first() == Some(&need.file)
+            });
+            let defined = match provider {
+                Some(provider) => provider
+                    .file
+                    .version_definitions()
+                    .iter()
+                    .any(|(_, name)| *name == need.name),
+                // Missing libraries are already reported
+                None => continue,
+            };
+            if !defined {
+                report.unresolved.push(Unresolved {
+                    object: object.name.clone(),
+                    symbol: String::new(),
+                    version: Some(need.name.clone()),
+                    reason: format!("version {} not defined by {}", need.name, need.file),
+                });
+            }
+        }
+    }
+}
+
+fn print_report(report: &Report, verify: bool, audit_rpath: bool) {
+    let libraries = Table {
+        header: format!("Dependencies of {}", report.file),
+        labels: vec!["Library".into(), "Path".into(), "Needed by".into()],
+        rows: report
+            .libraries
+            .iter()
+            .map(|d| {
+                vec![
+                    d.name.clone(),
+                    d.path.clone().unwrap_or_else(|| "not found".into()),
+                    d.needed_by.clone(),
+                ]
+            })
+            .collect(),
+    };
+    libraries.print();
+    if audit_rpath {
+        print_writable_paths(report);
+    }
+    if !verify {
+        return;
+    }
+    if report.unresolved.is_empty() {
+        println!(
+            "All symbols resolved ({} undefined weak references left null)",
+            report.unresolved_weak
+        );
+        return;
+    }
+    let unresolved = Table {
+        header: format!("{} unresolved symbols", report.unresolved.len()),
+        labels: vec![
+            "Object".into(),
+            "Symbol".into(),
+            "Version".into(),
+            "Reason".into(),
+        ],
+        rows: report
+            .unresolved
+            .iter()
+            .map(|u| {
+                vec![
+                    u.object.clone(),
+                    u.symbol.clone(),
+                    u.version.clone().unwrap_or_default(),
+                    u.reason.clone(),
+                ]
+            })
+            .collect(),
+    };
+    unresolved.print();
+}
+
+fn print_writable_paths(report: &Report) {
+    if report.writable_paths.is_empty() {
+        println!("No RPATH or RUNPATH entry expands to a world-writable directory");
+        return;
+    }
+    let writable = Table {
+        header: format!(
+            "{} search paths anyone can plant libraries in",
+            report.writable_paths.len()
+        ),
+        labels: vec![
+            "Object".into(),
+            "Tag".into(),
+            "Entry".into(),
+            "Expanded".into(),
+            "Writable directory".into(),
+        ],
+        rows: report
+            .writable_paths
+            .iter()
+            .map(|w| {
+                vec![
+                    w.object.clone(),
+                    w.tag.clone(),
+                    w.entry.clone(),
+                    w.expanded.clone(),
+                    w.writable.clone(),
+                ]
+            })
+            .collect(),
+    };
+    writable.print();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let origin = Path::new("/opt/app/bin");
+        assert_eq!(
+            expand_tokens("$ORIGIN/../lib", origin),
+            "/opt/app/bin/../lib"
+        );
+        assert_eq!(expand_tokens("${ORIGIN}lib", origin), "/opt/app/binlib");
+        assert_eq!(
+            expand_tokens("/usr/$LIB/${PLATFORM}", origin),
+            "/usr/lib64/x86_64"
+        );
+        assert_eq!(expand_tokens("$ORIGIN", origin), "/opt/app/bin");
+        // Not tokens: too long, unterminated, unknown
+        assert_eq!(expand_tokens("$ORIGINAL/lib", origin), "$ORIGINAL/lib");
+        assert_eq!(expand_tokens("/a/${LIB", origin), "/a/${LIB");
+        assert_eq!(expand_tokens("$HOME/lib/$", origin), "$HOME/lib/$");
+        assert_eq!(expand_tokens("/usr/lib", origin), "/usr/lib");
+    }
+
+    // 3-needed imports greet from libgreet.so; with another library standing in for it, the
+    // reference is unresolved
+    #[test]
+    fn verify_finds_imports_nothing_defines() {
+        let ladder = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/");
+        let parse = |name: &str| {
+            let input = source::read(&format!("{}{}", ladder, name)).unwrap();
+            FileHeader::parse_or_describe(&input).unwrap()
+        };
+        let path = format!("{}3-needed", ladder);
+        let mut objects = objects(&path, parse("3-needed"));
+        assert_eq!(objects.len(), 2);
+        let mut report = Report::default();
+        check_symbols(&objects, &mut report);
+        assert!(report.unresolved.is_empty());
+
+        objects[1].file = parse("libouter.so");
+        check_symbols(&objects, &mut report);
+        let unresolved: Vec<_> = report
+            .unresolved
+            .iter()
+            .map(|u| (u.object.as_str(), u.symbol.as_str(), u.reason.as_str()))
+            .collect();
+        assert_eq!(
+            unresolved,
+            [(path.as_str(), "greet", "not defined by any library")]
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::Read,
+    os::unix::{fs::PermissionsExt, process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use delf::FileHeader;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg,
+    crash,
+    exit::{Failure, Status},
+    schema, source,
+    tables::Table,
+};
+
+const DEFAULT_TIMEOUT: u64 = 10;
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// What one run of a fixture produced
+struct Run {
+    status: String,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Outcome {
+    fixture: String,
+    native: String,
+    elk: String,
+    // Empty when both runs agree
+    divergences: Vec<String>,
+    crash_report: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Run fixtures natively and under elk and compare what they do")]
+pub struct Args {
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_TIMEOUT, help = "Per run time limit")]
+    timeout: u64,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(
+        required = true,
+        value_hint = clap::ValueHint::AnyPath,
+        help = "Executables, or directories of them"
+    )]
+    fixtures: Vec<PathBuf>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Vec<Outcome>>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let timeout = Duration::from_secs(args.timeout);
+    let mut fixtures = Vec::new();
+    for root in args.fixtures {
+        if root.is_dir() {
+            let mut entries: Vec<_> = fs::read_dir(&root)?
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| is_executable_elf(p))
+                .collect();
+            entries.sort();
+            fixtures.extend(entries);
+        } else {
+            fixtures.push(root);
+        }
+    }
+
+    let elk = env::current_exe()?;
+    let outcomes: Vec<_> = fixtures
+        .iter()
+        .enumerate()
+        .map(|(i, fixture)| compare(&elk, fixture, i, timeout))
+        .collect();
+    let diverged = outcomes
+        .iter()
+        .filter(|o| !o.divergences.is_empty())
+        .count();
+
+    if args.format.json() {
+        schema::print_json("difftest", &outcomes)?;
+    } else {
+        print_report(&outcomes);
+    }
+    match diverged {
+        0 => Ok(()),
+        n => Err(Failure::findings(format!(
+            "{} of {} fixtures diverge from the system loader",
+            n,
+            outcomes.len()
+        ))
+        .into()),
+    }
+}
+
+// Directory members worth running: executable regular files that are ELF executables
+fn is_executable_elf(path: &Path) -> bool {
+    let executable =
+        fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+    executable
+        && source::map_raw(path).is_ok_and(|input| {
+            input.starts_with(FileHeader::MAGIC)
+                && FileHeader::parse_or_describe(&input).is_ok_and(|file| file.entry_point.0 != 0)
+        })
+}
+
+fn compare(elk: &Path, fixture: &Path, index: usize, timeout: Duration) -> Outcome {
+    // Command::new only searches PATH for bare names
+    let program = match fixture.components().count() {
+        1 => Path::new(".").join(fixture),
+        _ => fixture.to_path_buf(),
+    };
+    let report = env::temp_dir().join(format!("elk-difftest-{}-{}.txt", std::process::id(), index));
+    let _ = fs::remove_file(&report);
+
+    let native = execute(Command::new(&program), timeout);
+    let mut command = Command::new(elk);
+    command
+        .args(["run", "--fork", "--quiet", "--crash-report"])
+        .arg(&report)
+        .arg(&program);
+    let mut elk = execute(command, timeout);
+    // elk exits with Status::Crashed rather than dying on the signal; the report says which
+    if elk.status == format!("exit {}", Status::Crashed as i32) {
+        if let Some(signal) = crash::report_signal(&report) {
+            elk.status = format!("signal {}", signal);
+        }
+    }
+    // Drop elk's own notices, such as where the crash report went
+    elk.stderr = String::from_utf8_lossy(&elk.stderr)
+        .lines()
+        .filter(|line| !line.starts_with("elk: "))
+        .flat_map(|line| format!("{}\n", line).into_bytes())
+        .collect();
+
+    Outcome {
+        fixture: fixture.display().to_string(),
+        divergences: divergences(&native, &elk),
+        native: native.status,
+        elk: elk.status,
+        crash_report: Some(report)
+            .filter(|r| r.exists())
+            .map(|r| r.display().to_string()),
+    }
+}
+
+// Every way the elk run differs from the native one
+fn divergences(native: &Run, elk: &Run) -> Vec<String> {
+    let mut divergences = Vec::new();
+    if native.status != elk.status {
+        divergences.push(format!(
+            "status: native {}, elk {}",
+            native.status, elk.status
+        ));
+    }
+    divergences.extend(diff_output("stdout", &native.stdout, &elk.stdout));
+    divergences.extend(diff_output("stderr", &native.stderr, &elk.stderr));
+    divergences
+}
+
+fn execute(mut command: Command, timeout: Duration) -> Run {
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            return Run {
+                status: format!("failed to start: {}", e),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            }
+        }
+    };
+    // Drain both pipes while waiting, a chatty program would otherwise block on a full pipe
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = wait_timeout(&mut child, timeout);
+    Run {
+        status: match status {
+            Some(status) => describe(status),
+            None => "timed out".into(),
+        },
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut out);
+        }
+        out
+    })
+}
+
+fn wait_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+}
+
+fn describe(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exit {}", code),
+        (None, Some(signal)) => format!("signal {}", signal),
+        _ => "unknown".into(),
+    }
+}
+
+// First line where the two outputs disagree, if any
+fn diff_output(stream: &str, native: &[u8], elk: &[u8]) -> Option<String> {
+    if native == elk {
+        return None;
+    }
+    let native = String::from_utf8_lossy(native);
+    let elk = String::from_utf8_lossy(elk);
+    let (mut native_lines, mut elk_lines) = (native.lines(), elk.lines());
+    let mut line = 1;
+    loop {
+        match (native_lines.next(), elk_lines.next()) {
+            (Some(a), Some(b)) if a == b => line += 1,
+            (Some(a), Some(b)) => {
+                return Some(format!(
+                    "{} line {}: native {:?}, elk {:?}",
+                    stream, line, a, b
+                ))
+            }
+            (Some(a), None) => {
+                return Some(format!(
+                    "{} line {}: elk stops before {:?}",
+                    stream, line, a
+                ))
+            }
+            (None, Some(b)) => return Some(format!("{} line {}: elk adds {:?}", stream, line, b)),
+            // Same lines, different trailing newline
+            (None, None) => return Some(format!("{}: trailing newline differs", stream)),
+        }
+    }
+}
+
+fn print_report(outcomes: &[Outcome]) {
+    let table = Table {
+        header: "Differential test against the system loader".into(),
+        labels: vec![
+            "Fixture".into(),
+            "Native".into(),
+            "elk".into(),
+            "Result".into(),
+        ],
+        rows: outcomes
+            .iter()
+            .map(|o| {
+                vec![
+                    o.fixture.clone(),
+                    o.native.clone(),
+                    o.elk.clone(),
+                    match o.divergences.len() {
+                        0 => "match".into(),
+                        n => format!("{} divergence(s)", n),
+                    },
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+    for outcome in outcomes.iter().filter(|o| !o.divergences.is_empty()) {
+        println!("{}:", outcome.fixture);
+        for divergence in &outcome.divergences {
+            println!("  {}", divergence);
+        }
+        if let Some(report) = &outcome.crash_report {
+            println!("  crash report: {}", report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LADDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/");
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn run(fixture: &str) -> Run {
+        execute(Command::new(format!("{}{}", LADDER, fixture)), TIMEOUT)
+    }
+
+    #[test]
+    fn the_same_fixture_does_not_diverge() {
+        let (a, b) = (run("1-static"), run("1-static"));
+        assert_eq!(a.status, "exit 0");
+        assert!(divergences(&a, &b).is_empty());
+    }
+
+    // Two rungs standing in for a native run and an elk one that got the message wrong
+    #[test]
+    fn divergent_fixtures_report_where_they_differ() {
+        let (native, elk) = (run("1-static"), run("2-pie"));
+        assert_eq!(
+            divergences(&native, &elk),
+            [
+                r#"stdout line 1: native "Hello from a static executable!", elk "Hello from a relocated PIE!""#
+            ]
+        );
+    }
+
+    #[test]
+    fn status_and_missing_output_are_divergences() {
+        let native = run("1-static");
+        let elk = Run {
+            status: "signal 11".into(),
+            stdout: Vec::new(),
+            stderr: b"Segmentation fault\n".to_vec(),
+        };
+        assert_eq!(
+            divergences(&native, &elk),
+            [
+                "status: native exit 0, elk signal 11",
+                r#"stdout line 1: elk stops before "Hello from a static executable!""#,
+                r#"stderr line 1: elk adds "Segmentation fault""#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::{collections::BTreeMap, error::Error, fmt, ops::Range, sync::Arc};
+
+use delf::{
+    types::{RelType, SegmentType},
+    FileHeader,
+};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg,
+    deps,
+    exit::Failure,
+    loader::{LoadOptions, Process},
+    parse_number,
+    progress::Silenced,
+    schema, source,
+    stack::{self, Program, Stack},
+    tables::Table,
+};
+
+#[derive(clap::Args, Debug)]
+#[command(
+    about = "Say where bytes of a binary's image come from: the file, zero-fill, \
+                   a relocation or the loader"
+)]
+pub struct Args {
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+    #[arg(
+        value_name = "ADDR",
+        value_parser = parse_number,
+        help = "Addresses to look up; every region of the image without any"
+    )]
+    addrs: Vec<u64>,
+    #[arg(
+        long,
+        help = "Load the binary, its libraries, TLS and stack as `elk run` would, without running \
+                it, and take addresses as runtime ones"
+    )]
+    loaded: bool,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_parser = parse_number,
+        requires = "loaded",
+        help = "Load address for position independent binaries"
+    )]
+    base: Option<u64>,
+    #[command(flatten)]
+    format: FormatArg,
+}
+
+// Where a byte of a loaded image came from. Offsets and addresses are those of the first byte
+// of a region; `OriginMap::get` gives them for the byte asked about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Origin {
+    // Copied from the object's file at `offset`
+    File {
+        object: String,
+        offset: u64,
+    },
+    // Past the segment's bytes in the file, zero-filled: .bss and the like
+    Zero {
+        object: String,
+    },
+    // Between a segment and the page boundaries it is mapped on
+    Padding {
+        object: String,
+    },
+    // Written by the relocation at `slot`. Copy relocations copy the bytes at `value`.
+    Relocation {
+        object: String,
+        #[schemars(with = "String")]
+        typ: RelType,
+        slot: u64,
+        value: u64,
+    },
+    // The object's block in the main thread's TLS, copied from its image at runtime address
+    // `image`, or zero for .tbss
+    Tls {
+        object: String,
+        image: Option<u64>,
+    },
+    // Made up by the loader for no relocation: GOT entries for lazy binding, the DTV, the stack
+    Loader {
+        object: Option<String>,
+        what: &'static str,
+    },
+}
+
+impl Origin {
+    // The origin of the byte `by` bytes into a region of this one
+    fn advance(&self, by: u64) -> Origin {
+        match self.clone() {
+            Origin::File { object, offset } => Origin::File {
+                object,
+                offset: offset + by,
+            },
+            Origin::Tls {
+                object,
+                image: Some(image),
+            } => Origin::Tls {
+                object,
+                image: Some(image + by),
+            },
+            origin => origin,
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::File { object, offset } => write!(f, "{} at file offset {:#x}", object, offset),
+            Origin::Zero { object } => write!(f, "{}, zero-filled", object),
+            Origin::Padding { object } => write!(f, "{}, page padding", object),
+            Origin::Relocation {
+                object,
+                typ: RelType::Copy,
+                slot,
+                value,
+            } => write!(
+                f,
+                "{}, copy relocation at {:#x} from {:#x}",
+                object, slot, value
+            ),
+            Origin::Relocation {
+                object,
+                typ,
+                slot,
+                value,
+            } => write!(
+                f,
+                "{}, {:?} relocation at {:#x} writing {:#x}",
+                object, typ, slot, value
+            ),
+            Origin::Tls {
+                object,
+                image: Some(image),
+            } => write!(f, "{}'s TLS block, copied from {:#x}", object, image),
+            Origin::Tls {
+                object,
+                image: None,
+            } => write!(f, "{}'s TLS block, zero-filled", object),
+            Origin::Loader {
+                object: Some(object),
+                what,
+            } => write!(f, "{}, {}", object, what),
+            Origin::Loader { object: None, what } => f.write_str(what),
+        }
+    }
+}
+
+// Origins of an address space's bytes by range. Ranges never overlap: whatever is inserted last
+// wins for the bytes it covers, so writes go in after what they overwrite.
+#[derive(Debug, Default, Clone)]
+pub struct OriginMap {
+    regions: BTreeMap<u64, (u64, Origin)>,
+}
+
+impl OriginMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, range: Range<u64>, origin: Origin) {
+        if range.is_empty() {
+            return;
+        }
+        let mut covered: Vec<u64> = self
+            .regions
+            .range(range.start..range.end)
+            .map(|(&start, _)| start)
+            .collect();
+        if let Some((&start, &(end, _))) = self.regions.range(..range.start).next_back() {
+            if end > range.start {
+                covered.push(start);
+            }
+        }
+        for start in covered {
+            let (end, old) = match self.regions.remove(&start) {
+                Some(region) => region,
+                None => continue,
+            };
+            if start < range.start {
+                self.regions.insert(start, (range.start, old.clone()));
+            }
+            if end > range.end {
+                let rest = old.advance(range.end - start);
+                self.regions.insert(range.end, (end, rest));
+            }
+        }
+        self.regions.insert(range.start, (range.end, origin));
+    }
+
+    // The origin of the byte at `addr`, and the region it is part of
+    pub fn get(&self, addr: u64) -> Option<(Range<u64>, Origin)> {
+        let (&start, (end, origin)) = self.regions.range(..=addr).next_back()?;
+        match addr < *end {
+            true => Some((start..*end, origin.advance(addr - start))),
+            false => None,
+        }
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = (Range<u64>, &Origin)> {
+        self.regions
+            .iter()
+            .map(|(&start, (end, origin))| (start..*end, origin))
+    }
+}
+
+// Origins of the bytes of `file`'s LOAD segments at their link-time addresses
+pub fn file_origins(file: &FileHeader, object: &str) -> OriginMap {
+    let mut map = OriginMap::new();
+    for ph in file.segments_of_type(SegmentType::Load) {
+        let (start, copied) = (ph.virt_addr.0, ph.data.len() as u64);
+        map.insert(
+            start..start + copied,
+            Origin::File {
+                object: object.to_string(),
+                offset: ph.offset.0,
+            },
+        );
+        map.insert(
+            start + copied..start + ph.mem_size.0,
+            Origin::Zero {
+                object: object.to_string(),
+            },
+        );
+    }
+    map
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "OriginRegion")]
+struct Region {
+    start: u64,
+    end: u64,
+    #[serde(flatten)]
+    origin: Origin,
+}
+
+// `data` of the command's JSON output: the regions holding each address asked about, with the
+// origin of that byte, or the whole map without any
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Vec<Region>>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let input = source::read_whole(&args.file)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", args.file, e)))?;
+    let map = match args.loaded {
+        true => loaded_origins(&args, file)?,
+        false => file_origins(&file, &args.file),
+    };
+
+    let regions: Vec<Region> = match args.addrs.is_empty() {
+        true => map
+            .regions()
+            .map(|(range, origin)| Region {
+                start: range.start,
+                end: range.end,
+                origin: origin.clone(),
+            })
+            .collect(),
+        false => args
+            .addrs
+            .iter()
+            .map(|&addr| {
+                let (range, origin) = map.get(addr).ok_or_else(|| {
+                    Failure::findings(format!("{:#x} isn't part of {}'s image", addr, args.file))
+                })?;
+                Ok(Region {
+                    start: range.start,
+                    end: range.end,
+                    origin,
+                })
+            })
+            .collect::<Result<_, Failure>>()?,
+    };
+    if args.format.json() {
+        return schema::print_json("dig", &regions);
+    }
+    let mut labels = vec!["Range".into(), "Origin".into()];
+    if !args.addrs.is_empty() {
+        labels.insert(0, "Address".into());
+    }
+    Table {
+        header: format!("Origins of {}", args.file),
+        labels,
+        rows: regions
+            .iter()
+            .enumerate()
+            .map(|(i, region)| {
+                let mut row = vec![
+                    format!("{:#x}..{:#x}", region.start, region.end),
+                    region.origin.to_string(),
+                ];
+                if let Some(addr) = args.addrs.get(i) {
+                    row.insert(0, format!("{:#x}", addr));
+                }
+                row
+            })
+            .collect(),
+    }
+    .print();
+    Ok(())
+}
+
+// Loads `file` the way `elk run` does, stack frame included, stopping short of running
+// anything of it but IFUNC resolvers
+fn loaded_origins(args: &Args, file: FileHeader) -> Result<OriginMap, Box<dyn Error>> {
+    let base = args.base.unwrap_or_else(|| Process::default_base(&file));
+    let entry = file.entry_point;
+    let phdr = file.program_headers_addr();
+    let (phent, phnum) = (
+        file.program_header_info.size,
+        file.program_header_info.count,
+    );
+    let objects = deps::objects(&args.file, file);
+    // The loader says what it does on stdout, which is this command's output
+    let silenced = Silenced::new()?;
+    let loaded = Process::load_with_libraries(
+        &objects[0].file,
+        base,
+        LoadOptions::default(),
+        &objects[1..],
+    );
+    silenced.restore()?;
+    let process = loaded.map_err(|e| Failure::load(format!("{}: {}", args.file, e)))?;
+
+    let mut map = process.origins();
+    let program = Program {
+        entry: process.addr(entry),
+        phdr: phdr.map_or(0, |addr| process.addr(addr)),
+        phent,
+        phnum,
+        execfn: &args.file,
+    };
+    let stack = Stack::new_in(Arc::clone(process.space()), stack::DEFAULT_SIZE, None)?;
+    let frame = stack.frame(
+        &program,
+        &[args.file.as_bytes().to_vec()],
+        &stack::environment(false, &[]),
+    )?;
+    for (range, what) in frame.regions {
+        map.insert(range, Origin::Loader { object: None, what });
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_origins_split_earlier_ones() {
+        let file = |offset| Origin::File {
+            object: "main".into(),
+            offset,
+        };
+        let mut map = OriginMap::new();
+        map.insert(0x1000..0x1100, file(0x200));
+        map.insert(
+            0x1040..0x1048,
+            Origin::Relocation {
+                object: "main".into(),
+                typ: RelType::Relative,
+                slot: 0x1040,
+                value: 0x1234,
+            },
+        );
+        assert_eq!(map.regions().count(), 3);
+        assert_eq!(map.get(0x1010), Some((0x1000..0x1040, file(0x210))));
+        assert_eq!(map.get(0x1050), Some((0x1048..0x1100, file(0x250))));
+        assert!(matches!(
+            map.get(0x1047),
+            Some((_, Origin::Relocation { slot: 0x1040, .. }))
+        ));
+        assert_eq!(map.get(0x1100), None);
+    }
+}
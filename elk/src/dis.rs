@@ -0,0 +1,187 @@
+use std::{collections::HashMap, error::Error, ops::Range, path::Path};
+
+use clap_complete::engine::ArgValueCompleter;
+use delf::{strtab::Interner, types::*, FileHeader};
+
+use crate::{
+    cli, disasm_listing,
+    exit::Failure,
+    parse_number, relocs, source,
+    symbolize::SymbolIndex,
+    xref::{self, Target, PLT_ENTRY_SIZE},
+};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Disassemble a function, an address range or all code, naming call targets")]
+pub struct Args {
+    #[arg(
+        long,
+        conflicts_with_all = ["addr", "len"],
+        add = ArgValueCompleter::new(cli::symbol_names),
+        help = "Disassemble the function with this name"
+    )]
+    symbol: Option<String>,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_parser = parse_number,
+        requires = "len",
+        help = "Start of the range to disassemble"
+    )]
+    addr: Option<u64>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_number,
+        requires = "addr",
+        help = "Length of the range starting at --addr"
+    )]
+    len: Option<u64>,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+// What the addresses branches go to are called: symbols, then PLT stubs and GOT slots by the
+// symbol their relocation binds
+struct Names {
+    symbols: SymbolIndex,
+    stubs: HashMap<u64, String>,
+    slots: HashMap<u64, String>,
+}
+
+impl Names {
+    fn new(path: &str, file: &FileHeader) -> Result<Self, Box<dyn Error>> {
+        let name = Path::new(path)
+            .file_name()
+            .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned());
+        let slots: HashMap<u64, String> = relocs::annotate(file)?
+            .into_iter()
+            .filter(|reloc| reloc.typ != "Relative")
+            .filter_map(|reloc| Some((reloc.offset, reloc.symbol?)))
+            .collect();
+        // A PLT stub is named after the slot it jumps through
+        let mut stubs = HashMap::new();
+        for sh in file
+            .section_headers
+            .iter()
+            .filter(|sh| sh.name.starts_with(".plt") && sh.typ != SectionType::NoBits)
+        {
+            for branch in xref::branches(file.machine, sh)? {
+                if let Target::Slot(slot) = branch.target {
+                    if let Some(symbol) = slots.get(&slot) {
+                        let stub = (branch.addr - sh.addr.0) / PLT_ENTRY_SIZE * PLT_ENTRY_SIZE;
+                        stubs.insert(sh.addr.0 + stub, format!("{}@plt", symbol));
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            symbols: SymbolIndex::build(&name, file, &mut Interner::new()),
+            stubs,
+            slots,
+        })
+    }
+
+    fn direct(&self, addr: u64) -> Option<String> {
+        if let Some(stub) = self.stubs.get(&addr) {
+            return Some(stub.clone());
+        }
+        Some(match self.symbols.symbol(addr)? {
+            (name, 0) => name.to_string(),
+            (name, offset) => format!("{}+{:#x}", name, offset),
+        })
+    }
+
+    fn target(&self, target: Target) -> Option<String> {
+        match target {
+            Target::Direct(addr) => self.direct(addr),
+            Target::Slot(slot) => self.slots.get(&slot).map(|name| format!("{}@got", name)),
+        }
+    }
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let names = Names::new(path, &file)?;
+
+    let ranges = match (&args.symbol, args.addr, args.len) {
+        (Some(name), _, _) => vec![function(&file, name)
+            .ok_or_else(|| Failure::parse(format!("{}: no function called {}", path, name)))?],
+        (None, Some(addr), Some(len)) => vec![Range {
+            start: addr,
+            end: addr.saturating_add(len),
+        }],
+        _ => code_sections(&file),
+    };
+    for range in ranges {
+        let code = code_at(&file, &range).ok_or_else(|| {
+            Failure::parse(format!("{}: no code in the file at {:#x?}", path, range))
+        })?;
+        let listing = disasm_listing(file.machine, code, range.start, &[])?;
+        for line in listing.lines() {
+            let addr = line
+                .split_whitespace()
+                .next()
+                .and_then(|addr| u64::from_str_radix(addr, 16).ok());
+            if let Some((name, 0)) = addr.and_then(|addr| names.symbols.symbol(addr)) {
+                println!("{}:", name);
+            }
+            match xref::branch(line).and_then(|branch| names.target(branch.target)) {
+                Some(name) => println!("{}  ; {}", line, name),
+                None => println!("{}", line),
+            }
+        }
+    }
+    Ok(())
+}
+
+// The defined function called `name`, mangled or not. One whose size runs it past the end of
+// the address space is skipped.
+fn function(file: &FileHeader, name: &str) -> Option<Range<u64>> {
+    file.read_section_syms()
+        .into_iter()
+        .chain(file.read_syms())
+        .filter(|sym| sym.section_index().is_some() && sym.typ() == Some(SymType::Func))
+        .filter(|sym| sym.name == name || crate::size::demangle(&sym.name) == name)
+        .find_map(|sym| Some(sym.value.0..sym.value.0.checked_add(sym.size.max(1))?))
+}
+
+// The addresses `data` covers mapped at `addr`, unless it runs past the end of the address space
+fn extent(addr: u64, data: &[u8]) -> Option<Range<u64>> {
+    Some(addr..addr.checked_add(data.len() as u64)?)
+}
+
+// Every section holding code, or every executable LOAD segment without section headers
+fn code_sections(file: &FileHeader) -> Vec<Range<u64>> {
+    let sections: Vec<_> = file
+        .section_headers
+        .iter()
+        .filter(|sh| sh.flags.contains(SectionFlags::ExecInstr) && sh.typ != SectionType::NoBits)
+        .filter_map(|sh| extent(sh.addr.0, &sh.data))
+        .collect();
+    if !file.section_headers.is_empty() {
+        return sections;
+    }
+    file.segments_of_type(SegmentType::Load)
+        .filter(|ph| ph.flags.contains(SegmentFlags::Execute))
+        .filter_map(|ph| extent(ph.virt_addr.0, &ph.data))
+        .collect()
+}
+
+// File bytes of `range`, cut short where the section or segment holding its start ends
+fn code_at<'a>(file: &'a FileHeader, range: &Range<u64>) -> Option<&'a [u8]> {
+    let len = (range.end - range.start) as usize;
+    let section = file
+        .section_headers
+        .iter()
+        .filter(|sh| sh.flags.contains(SectionFlags::Alloc) && sh.typ != SectionType::NoBits)
+        .find(|sh| extent(sh.addr.0, &sh.data).is_some_and(|r| r.contains(&range.start)));
+    let bytes = match section {
+        Some(sh) => &sh.data[(range.start - sh.addr.0) as usize..],
+        None => file.bytes_at(Addr(range.start))?,
+    };
+    Some(&bytes[..bytes.len().min(len)]).filter(|bytes| !bytes.is_empty())
+}
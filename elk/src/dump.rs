@@ -0,0 +1,190 @@
+use std::error::Error;
+
+use delf::{
+    types::*,
+    view::{AddrMode, AddrView},
+    FileHeader,
+};
+
+use crate::{exit::Failure, relocs, similarity::fnv, source};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Print every header and table of a binary as plain lines, one record per line")]
+pub struct Args {
+    #[arg(
+        long,
+        help = "Make two builds diff cleanly: addresses relative to their segment, no file offsets"
+    )]
+    normalized: bool,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    for line in dump(&file, args.normalized)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// One line per record, in an order that only depends on the file's contents: tables keep the
+// file's order, symbols are sorted since linkers shuffle them between builds. Blobs show as a
+// length and hash.
+pub fn dump(file: &FileHeader, normalized: bool) -> Result<Vec<String>, Box<dyn Error>> {
+    let mode = match normalized {
+        true => AddrMode::Segment,
+        false => AddrMode::File,
+    };
+    let view = AddrView::new(file, mode, None);
+    let offset = |offset: Addr| match normalized {
+        true => String::new(),
+        false => format!(" offset={:#x}", offset.0),
+    };
+    let section_name = |index: usize| match file.section_headers.get(index) {
+        Some(sh) if sh.name.is_empty() => "-".to_string(),
+        Some(sh) => sh.name.clone(),
+        None => index.to_string(),
+    };
+    let mut lines = vec![format!(
+        "file class={:?} type={:?} machine={:?} entry={}",
+        file.class,
+        file.typ,
+        file.machine,
+        view.show(file.entry_point)
+    )];
+
+    for (i, ph) in file.program_headers.iter().enumerate() {
+        let addr = match normalized {
+            true => String::new(),
+            false => format!(" vaddr={:#x}", ph.virt_addr.0),
+        };
+        // Sections hash the same bytes in smaller pieces, which say more about what changed
+        let data = match file.section_headers.is_empty() {
+            true => format!(" {}", blob(&ph.data)),
+            false => String::new(),
+        };
+        lines.push(format!(
+            "segment {} {:?} {}{}{} filesz={:#x} memsz={:#x} align={:#x}{}",
+            i,
+            ph.typ,
+            format!("{:?}", ph.flags).replace(' ', ""),
+            addr,
+            offset(ph.offset),
+            ph.file_size.0,
+            ph.mem_size.0,
+            ph.align.0,
+            data
+        ));
+    }
+
+    for (index, sh) in file.section_headers.iter().enumerate() {
+        // Sections that aren't loaded have no address, and 0 would read as the first segment's
+        let addr = match sh.flags.contains(SectionFlags::Alloc) {
+            true => view.show(sh.addr),
+            false => "-".into(),
+        };
+        let flags = match format!("{:?}", sh.flags) {
+            flags if flags.is_empty() => "-".to_string(),
+            flags => flags,
+        };
+        let data = match sh.typ {
+            SectionType::NoBits => String::new(),
+            _ => format!(" {}", blob(&sh.data)),
+        };
+        lines.push(format!(
+            "section {} {:?} {} addr={}{} size={:#x} align={:#x} link={}{}",
+            section_name(index),
+            sh.typ,
+            flags,
+            addr,
+            offset(sh.offset),
+            sh.size.0,
+            sh.align.0,
+            section_name(sh.link as usize),
+            data
+        ));
+    }
+
+    if let Some(ProgramHeader {
+        contents: SegmentContent::Dynamic(entries),
+        ..
+    }) = file.segments_of_type(SegmentType::Dynamic).next()
+    {
+        for entry in entries {
+            let value = match entry.string_value(file) {
+                Some(string) => string,
+                None if entry.tag.is_address() && entry.addr.0 != 0 => view.show(entry.addr),
+                None => format!("{:#x}", entry.addr.0),
+            };
+            lines.push(format!("dynamic {:?} {}", entry.tag, value));
+        }
+    }
+
+    for (section, note) in file.notes() {
+        lines.push(format!(
+            "note {} {} type={:#x} {}",
+            section,
+            note.name,
+            note.typ,
+            blob(&note.desc)
+        ));
+    }
+
+    for (index, table) in file
+        .section_headers
+        .iter()
+        .enumerate()
+        .filter(|(_, sh)| matches!(sh.typ, SectionType::SymTab | SectionType::DynSym))
+    {
+        let mut syms: Vec<String> = file
+            .symbols_in(index)
+            .iter()
+            .filter(|sym| !sym.name.is_empty())
+            .map(|sym| {
+                let (section, value) = match sym.section_index() {
+                    Some(index) => (section_name(index), view.show(sym.value)),
+                    None => (format!("{:?}", sym.shndx), format!("{:#x}", sym.value.0)),
+                };
+                format!(
+                    "symbol {} {} {} {} {} {} size={:#x}",
+                    table.name,
+                    sym.name,
+                    sym.typ()
+                        .map_or_else(|| "?".into(), |typ| format!("{:?}", typ)),
+                    sym.bind()
+                        .map_or_else(|| "?".into(), |bind| format!("{:?}", bind)),
+                    section,
+                    value,
+                    sym.size
+                )
+            })
+            .collect();
+        syms.sort();
+        lines.extend(syms);
+    }
+
+    for reloc in relocs::annotate(file)? {
+        // The addend of a relative relocation is an address in the file
+        let addend = match reloc.typ.as_str() {
+            "Relative" | "IRelative" => view.show(Addr(reloc.addend as u64)),
+            _ => format!("{:#x}", reloc.addend),
+        };
+        lines.push(format!(
+            "reloc {} {} {} {} addend={}",
+            reloc.typ,
+            reloc.region,
+            view.show(Addr(reloc.offset)),
+            reloc.symbol.as_deref().unwrap_or("-"),
+            addend
+        ));
+    }
+    Ok(lines)
+}
+
+fn blob(data: &[u8]) -> String {
+    format!("len={:#x} fnv={:016x}", data.len(), fnv(data))
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    error::Error,
+};
+
+use delf::{strtab::Interner, types::*, FileHeader};
+use unicorn_engine::{
+    uc_error, Arch, HookType, MemType, Mode, Prot, RegisterARM64, RegisterRISCV, RegisterX86,
+    Unicorn, X86Insn,
+};
+
+use crate::{
+    exit::Failure, loader::Process, parse_number, source, symbolize::SymbolIndex, tables::Table,
+};
+
+const DEFAULT_STEPS: usize = 1000;
+const PAGE_SIZE: u64 = 0x1000;
+const STACK_TOP: u64 = 0x7fff_0000_0000;
+const STACK_SIZE: u64 = 0x10_0000;
+// Unresolved symbol relocations point here, one page per relocation, so a jump or load through
+// one faults at an address that says which relocation it was
+const POISON_BASE: u64 = 0xdead_0000_0000;
+const RECENT: usize = 16;
+
+// What a CPU needs from elk: how to start it, where its stack pointer lives, and which
+// relocation types mean what on it
+struct Target {
+    arch: Arch,
+    mode: Mode,
+    sp: i32,
+    relative: u32,
+    // S + A
+    absolute: u32,
+    // S, for GOT and PLT slots
+    slots: [u32; 2],
+}
+
+fn target(machine: Machine) -> Option<Target> {
+    let target = match machine {
+        Machine::X86_64 => Target {
+            arch: Arch::X86,
+            mode: Mode::MODE_64,
+            sp: RegisterX86::RSP.into(),
+            relative: 8,
+            absolute: 1,
+            slots: [6, 7],
+        },
+        Machine::X86 => Target {
+            arch: Arch::X86,
+            mode: Mode::MODE_32,
+            sp: RegisterX86::ESP.into(),
+            relative: 8,
+            absolute: 1,
+            slots: [6, 7],
+        },
+        Machine::AArch64 => Target {
+            arch: Arch::ARM64,
+            mode: Mode::LITTLE_ENDIAN,
+            sp: RegisterARM64::SP.into(),
+            relative: 1027,
+            absolute: 257,
+            slots: [1025, 1026],
+        },
+        Machine::RiscV => Target {
+            arch: Arch::RISCV,
+            mode: Mode::RISCV64,
+            sp: RegisterRISCV::SP.into(),
+            relative: 3,
+            absolute: 2,
+            slots: [5, 5],
+        },
+        _ => return None,
+    };
+    Some(target)
+}
+
+// A relocation as elk applied it inside the emulator
+struct Applied {
+    slot: u64,
+    typ: u32,
+    symbol: String,
+    value: u64,
+}
+
+#[derive(Default)]
+struct State {
+    steps: usize,
+    recent: VecDeque<u64>,
+    stop: Option<Stop>,
+}
+
+enum Stop {
+    Fault(MemType, u64, usize),
+    Interrupt(u32),
+    Syscall(u64),
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Execute the first instructions of a binary under unicorn to check relocations")]
+pub struct Args {
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_STEPS)]
+    steps: usize,
+    #[arg(long, value_name = "ADDR", value_parser = parse_number)]
+    base: Option<u64>,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let (path, steps, base) = (&args.file, args.steps, args.base);
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let base = base.unwrap_or_else(|| Process::default_base(&file));
+    let target = target(file.machine)
+        .ok_or_else(|| Failure::load(format!("{}: no emulator for {:?}", path, file.machine)))?;
+
+    let mut uc = Unicorn::new_with_data(target.arch, target.mode, State::default())
+        .map_err(|e| format!("could not create emulator: {:?}", e))?;
+    map_image(&mut uc, &file, base).map_err(|e| format!("could not map image: {:?}", e))?;
+    let applied = relocate(&mut uc, &file, base, &target)
+        .map_err(|e| format!("could not apply relocations: {:?}", e))?;
+    uc.mem_map(STACK_TOP - STACK_SIZE, STACK_SIZE, Prot::READ | Prot::WRITE)
+        .and_then(|_| uc.reg_write(target.sp, STACK_TOP - PAGE_SIZE))
+        .map_err(|e| format!("could not map stack: {:?}", e))?;
+    add_hooks(&mut uc, &file)?;
+
+    let entry = file.entry_point.0 + base;
+    let result = uc.emu_start(entry, 0, 0, steps);
+    let pc = uc.pc_read().unwrap_or(0);
+    let symbols = SymbolIndex::build(path, &file, &mut Interner::new());
+    let locate = |addr: u64| match addr.checked_sub(base).and_then(|a| symbols.symbol(a)) {
+        Some((name, 0)) => name.to_string(),
+        Some((name, offset)) => format!("{}+{:#x}", name, offset),
+        None => "?".into(),
+    };
+
+    let state = uc.get_data();
+    let outcome = match (&state.stop, result) {
+        (Some(stop), _) => describe_stop(stop, &applied),
+        (None, Ok(())) if state.steps >= steps => format!("ran {} instructions cleanly", steps),
+        (None, Ok(())) => "program returned".into(),
+        (None, Err(e)) => format!("emulator error {:?}", e),
+    };
+    let summary = Table {
+        header: format!("Emulating {} ({:?})", path, file.machine),
+        labels: vec![
+            "Entry".into(),
+            "Steps".into(),
+            "Stopped at".into(),
+            "Outcome".into(),
+        ],
+        rows: vec![vec![
+            format!("{:#x} ({})", entry, locate(entry)),
+            state.steps.to_string(),
+            format!("{:#x} ({})", pc, locate(pc)),
+            outcome,
+        ]],
+    };
+    summary.print();
+
+    let recent = Table {
+        header: format!("Last {} instructions", state.recent.len()),
+        labels: vec!["Address".into(), "Symbol".into()],
+        rows: state
+            .recent
+            .iter()
+            .map(|&addr| vec![format!("{:#x}", addr), locate(addr)])
+            .collect(),
+    };
+    recent.print();
+
+    let mut types: BTreeMap<u32, usize> = BTreeMap::new();
+    for reloc in &applied {
+        *types.entry(reloc.typ).or_default() += 1;
+    }
+    let relocations = Table {
+        header: "Relocations applied in the emulator".into(),
+        labels: vec!["Type".into(), "Count".into()],
+        rows: types
+            .iter()
+            .map(|(typ, n)| vec![typ.to_string(), n.to_string()])
+            .collect(),
+    };
+    relocations.print();
+    Ok(())
+}
+
+// Maps every page touched by a LOAD segment once, with the union of the permissions of the
+// segments sharing it, then copies in the file-backed bytes
+fn map_image(uc: &mut Unicorn<State>, file: &FileHeader, base: u64) -> Result<(), uc_error> {
+    let loads: Vec<_> = file
+        .program_headers
+        .iter()
+        .filter(|ph| ph.typ == SegmentType::Load && ph.mem_size.0 > 0)
+        .collect();
+    let mut pages: BTreeMap<u64, Prot> = BTreeMap::new();
+    for ph in &loads {
+        let prot = ph.flags.iter().fold(Prot::NONE, |acc, f| {
+            acc | match f {
+                SegmentFlags::Read => Prot::READ,
+                SegmentFlags::Write => Prot::WRITE,
+                SegmentFlags::Execute => Prot::EXEC,
+            }
+        });
+        let start = (ph.virt_addr.0 + base) & !(PAGE_SIZE - 1);
+        let end = ph.virt_addr.0 + base + ph.mem_size.0;
+        for page in (start..end).step_by(PAGE_SIZE as usize) {
+            let entry = pages.entry(page).or_insert(Prot::NONE);
+            *entry = *entry | prot;
+        }
+    }
+
+    // Coalesce runs of adjacent pages with equal permissions into one mapping
+    let mut runs: Vec<(u64, u64, Prot)> = Vec::new();
+    for (&page, &prot) in &pages {
+        match runs.last_mut() {
+            Some((start, len, last)) if *start + *len == page && *last == prot => *len += PAGE_SIZE,
+            _ => runs.push((page, PAGE_SIZE, prot)),
+        }
+    }
+    for (start, len, prot) in runs {
+        uc.mem_map(start, len, prot)?;
+    }
+    for ph in loads {
+        uc.mem_write(ph.virt_addr.0 + base, &ph.data)?;
+    }
+    Ok(())
+}
+
+// Raw RELA entries from every DT_RELA table, read without delf's RelType so foreign types
+// survive
+fn rela_entries(file: &FileHeader) -> Vec<(u64, u32, u32, u64)> {
+    let word = |c: &[u8]| u64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]);
+    file.dynamic_entries(DynamicTag::Rela)
+        .zip(file.dynamic_entries(DynamicTag::RelaSz))
+        .filter_map(|(start, size)| {
+            let data = file.bytes_at(start)?;
+            Some(&data[..(size.0 as usize).min(data.len())])
+        })
+        .flat_map(|data| data.chunks_exact(24))
+        .map(|entry| {
+            let info = word(&entry[8..]);
+            (
+                word(entry),
+                info as u32,
+                (info >> 32) as u32,
+                word(&entry[16..]),
+            )
+        })
+        .collect()
+}
+
+// Applies the relocations elk understands the way the loader does: relative ones against the
+// base, symbol ones against the object's own definitions. Anything else gets a poison address.
+fn relocate(
+    uc: &mut Unicorn<State>,
+    file: &FileHeader,
+    base: u64,
+    target: &Target,
+) -> Result<Vec<Applied>, uc_error> {
+    let syms = file
+        .section_headers
+        .iter()
+        .position(|sh| sh.typ == SectionType::DynSym)
+        .map(|index| file.symbols_in(index))
+        .unwrap_or_default();
+    let defined: HashMap<&str, u64> = syms
+        .iter()
+        .filter(|sym| !sym.name.is_empty())
+        .filter_map(|sym| Some((sym.name.as_str(), sym.address(base)?)))
+        .collect();
+
+    let mut applied = Vec::new();
+    for (i, (offset, typ, sym, addend)) in rela_entries(file).into_iter().enumerate() {
+        let name = syms.get(sym as usize).map_or("", |s| s.name.as_str());
+        let resolved = defined.get(name).copied();
+        let value = if typ == target.relative {
+            base.wrapping_add(addend)
+        } else if typ == target.absolute && resolved.is_some() {
+            resolved.unwrap_or_default().wrapping_add(addend)
+        } else if target.slots.contains(&typ) && resolved.is_some() {
+            resolved.unwrap_or_default()
+        } else {
+            POISON_BASE + i as u64 * PAGE_SIZE
+        };
+        uc.mem_write(offset + base, &value.to_le_bytes())?;
+        applied.push(Applied {
+            slot: offset + base,
+            typ,
+            symbol: name.to_string(),
+            value,
+        });
+    }
+    Ok(applied)
+}
+
+fn add_hooks(uc: &mut Unicorn<State>, file: &FileHeader) -> Result<(), Box<dyn Error>> {
+    let error = |e: uc_error| format!("could not install emulator hooks: {:?}", e);
+    uc.add_code_hook(1, 0, |uc, addr, _| {
+        let state = uc.get_data_mut();
+        state.steps += 1;
+        if state.recent.len() == RECENT {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(addr);
+    })
+    .map_err(error)?;
+    uc.add_mem_hook(
+        HookType::MEM_UNMAPPED | HookType::MEM_PROT,
+        1,
+        0,
+        |uc, typ, addr, size, _| {
+            uc.get_data_mut().stop = Some(Stop::Fault(typ, addr, size));
+            false
+        },
+    )
+    .map_err(error)?;
+    uc.add_intr_hook(|uc, number| {
+        uc.get_data_mut().stop = Some(Stop::Interrupt(number));
+        let _ = uc.emu_stop();
+    })
+    .map_err(error)?;
+    if file.machine == Machine::X86_64 {
+        uc.add_insn_sys_hook(X86Insn::SYSCALL, 1, 0, |uc| {
+            let number = uc.reg_read(RegisterX86::RAX).unwrap_or(u64::MAX);
+            uc.get_data_mut().stop = Some(Stop::Syscall(number));
+            let _ = uc.emu_stop();
+        })
+        .map_err(error)?;
+    }
+    Ok(())
+}
+
+fn describe_stop(stop: &Stop, applied: &[Applied]) -> String {
+    match stop {
+        Stop::Fault(typ, addr, size) => {
+            let mut text = format!("{:?} of {} bytes at {:#x}", typ, size, addr);
+            if let Some(reloc) = addr
+                .checked_sub(POISON_BASE)
+                .and_then(|off| applied.get((off / PAGE_SIZE) as usize))
+            {
+                text += &format!(
+                    ", through unresolved relocation type {} for {:?} at {:#x}",
+                    reloc.typ, reloc.symbol, reloc.slot
+                );
+            } else if let Some(reloc) = applied.iter().find(|r| r.value == *addr) {
+                text += &format!(", the value of the relocation at {:#x}", reloc.slot);
+            }
+            text
+        }
+        Stop::Interrupt(number) => format!("interrupt {} (likely a system call)", number),
+        Stop::Syscall(number) => format!("reached system call {}", number),
+    }
+}
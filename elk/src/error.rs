@@ -0,0 +1,104 @@
+use std::{io, ops::Range};
+
+use delf::{
+    detect::Class,
+    types::{Machine, SegmentSizeError},
+};
+
+// Why the loader couldn't load an object. Every variant names the object it is about, so
+// embedders loading several can tell which one failed and where.
+#[derive(thiserror::Error, Debug)]
+pub enum LoadError {
+    #[error("Could not open {path}: {reason}")]
+    Open { path: String, reason: String },
+    #[error("Could not parse {object}: {reason}")]
+    Parse { object: String, reason: String },
+    #[error("{0} is a {1} object, only 64-bit ones load into this process")]
+    Class(String, Class),
+    #[error("{0} is built for {1:?}, only x86-64 ones load into this process")]
+    Machine(String, Machine),
+    #[error("Segment of {object} at {addr:#x}: {source}")]
+    Segment {
+        object: String,
+        addr: u64,
+        source: SegmentSizeError,
+    },
+    #[error("Load base {0:#x} is not page aligned")]
+    Misaligned(u64),
+    #[error("ET_EXEC files only run at their link address, base {0:#x} would relocate them")]
+    NotRelocatable(u64),
+    #[error("Image does not fit in the address space at base {0:#x}")]
+    Overflow(u64),
+    #[error("Image range {0:#x?} overlaps existing mapping {1}")]
+    Conflict(Range<u64>, String),
+    #[error("No free address range for {0}")]
+    NoSpace(String),
+    #[error("Loading {0} would map more than the limit of {1:#x} bytes")]
+    MappedLimit(String, u64),
+    #[error("Loading {0} would go over the limit of {1} objects")]
+    ObjectLimit(String, usize),
+    #[error("Could not map {len:#x} bytes at {addr:#x} for {object}: {source}")]
+    Map {
+        object: String,
+        addr: u64,
+        len: usize,
+        source: io::Error,
+    },
+    #[error("Could not protect {len:#x} bytes at {addr:#x} for {object}: {source}")]
+    Protect {
+        object: String,
+        addr: u64,
+        len: usize,
+        source: io::Error,
+    },
+    #[error("Could not access {len:#x} bytes at {addr:#x} for {object}: {source}")]
+    Memory {
+        object: String,
+        addr: u64,
+        len: usize,
+        source: io::Error,
+    },
+    #[error("{0} has {1} relocations in read-only segments, load it with --allow-textrel")]
+    TextRel(String, usize),
+    #[error("Relocation of {object} at {slot:#x} rejected: {reason}")]
+    Reloc {
+        object: String,
+        slot: u64,
+        reason: String,
+    },
+    #[error("Undefined symbol {name:?} referenced by {object} in namespace {namespace}")]
+    SymbolNotFound {
+        name: String,
+        object: String,
+        namespace: usize,
+    },
+    #[error(
+        "Symbol {name:?} version {version} referenced by {object} in namespace {namespace} is \
+         only defined as {defined}"
+    )]
+    VersionNotFound {
+        name: String,
+        version: String,
+        object: String,
+        namespace: usize,
+        defined: String,
+    },
+    #[error("No loaded object named {0:?}")]
+    UnknownObject(String),
+    #[error("Could not start {object}: {reason}")]
+    Init { object: String, reason: String },
+    #[error("Thread-local storage of {object}: {reason}")]
+    Tls { object: String, reason: String },
+}
+
+impl LoadError {
+    // Whether loading failed only because something else was mapped where the image goes, so
+    // another base may do
+    pub fn is_occupied(&self) -> bool {
+        match self {
+            LoadError::Conflict(..) => true,
+            LoadError::Map { source, .. } => source.kind() == io::ErrorKind::AddrInUse,
+            _ => false,
+        }
+    }
+}
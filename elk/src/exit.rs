@@ -0,0 +1,123 @@
+use std::{error::Error, fmt, io};
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{error::LoadError, schema};
+
+// elk's exit codes. Scripts rely on these, so existing values never change meaning.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Ok = 0,
+    // The command line, an ELF file, a config file or a report could not be parsed
+    Parse = 1,
+    // The command ran and found problems: failed checks, missing libraries, divergences
+    Findings = 2,
+    // elk's loader refused or failed to map the program
+    Load = 3,
+    // The program elk ran died on a signal
+    Crashed = 4,
+    // Reading or writing a file failed
+    Io = 5,
+    Internal = 6,
+    // Ctrl-C stopped a batch command early, after it reported what it had done; the value
+    // shells give a process killed by SIGINT
+    Interrupted = 130,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+// An error that knows which exit status it maps to
+#[derive(Debug)]
+pub struct Failure {
+    pub status: Status,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn parse(message: impl Into<String>) -> Self {
+        Self::new(Status::Parse, message)
+    }
+
+    pub fn findings(message: impl Into<String>) -> Self {
+        Self::new(Status::Findings, message)
+    }
+
+    pub fn load(message: impl Into<String>) -> Self {
+        Self::new(Status::Load, message)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Failure {}
+
+pub fn classify(error: &(dyn Error + 'static)) -> Status {
+    if let Some(failure) = error.downcast_ref::<Failure>() {
+        failure.status
+    } else if error.is::<LoadError>() {
+        Status::Load
+    } else if error.is::<clap::Error>() {
+        Status::Parse
+    } else if error.is::<io::Error>() {
+        Status::Io
+    } else {
+        Status::Internal
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "ErrorReport")]
+struct Report<'a> {
+    status: Status,
+    code: i32,
+    message: &'a str,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Report<'static>>()
+}
+
+// Prints `error` to stderr in the requested format and returns the status to exit with
+pub fn report(error: &(dyn Error + 'static), format: ErrorFormat) -> Status {
+    let status = classify(error);
+    let message = match error.downcast_ref::<clap::Error>() {
+        // clap's own rendering carries the usage line and suggestions
+        Some(e) if format == ErrorFormat::Text => return e.print().map_or(status, |_| status),
+        Some(e) => e.render().to_string(),
+        None => error.to_string(),
+    };
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {}", message),
+        ErrorFormat::Json => {
+            let report = Report {
+                status,
+                code: status as i32,
+                message: message.trim_end(),
+            };
+            match serde_json::to_string(&schema::envelope("error", report)) {
+                Ok(json) => eprintln!("{}", json),
+                Err(_) => eprintln!("Error: {}", message),
+            }
+        }
+    }
+    status
+}
@@ -0,0 +1,323 @@
+use std::{error::Error, ops::Range};
+
+use delf::{data::Data, types::*, FileHeader};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Tabs},
+    DefaultTerminal, Frame,
+};
+
+const PANES: [&str; 4] = ["Segments", "Sections", "Symbols", "Relocations"];
+const HELP: &str = " ←/→ pane  ↑/↓ select  enter jump  d hex/disasm  PgUp/PgDn scroll  q quit ";
+
+#[derive(PartialEq)]
+enum View {
+    Hex,
+    Disasm,
+}
+
+struct Explorer {
+    input: Data,
+    file: FileHeader,
+    symbols: Vec<Symbol>,
+    dynsyms: Vec<Symbol>,
+    relocations: Vec<RelaEntry>,
+    pane: usize,
+    selection: [ListState; 4],
+    view: View,
+    hex_offset: usize,
+    highlight: Range<usize>,
+    disasm: String,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Browse segments, sections, symbols and relocations in a terminal UI")]
+pub struct Args {
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = crate::source::read(path)?;
+    let file = FileHeader::parse_or_print_error(&input)
+        .ok_or_else(|| crate::exit::Failure::parse(format!("could not parse {}", path)))?;
+
+    let mut explorer = Explorer {
+        symbols: file.read_section_syms(),
+        dynsyms: file
+            .section_headers
+            .iter()
+            .position(|sh| sh.typ == SectionType::DynSym)
+            .map(|idx| file.symbols_in(idx))
+            .unwrap_or_default(),
+        relocations: file.read_rela_entries().unwrap_or_default(),
+        input,
+        file,
+        pane: 0,
+        selection: Default::default(),
+        view: View::Hex,
+        hex_offset: 0,
+        highlight: 0..0,
+        disasm: String::new(),
+    };
+    for state in explorer.selection.iter_mut() {
+        state.select(Some(0));
+    }
+
+    let terminal = ratatui::init();
+    let res = explorer.event_loop(terminal);
+    ratatui::restore();
+    res
+}
+
+impl Explorer {
+    fn event_loop(&mut self, mut terminal: DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Right | KeyCode::Tab => self.pane = (self.pane + 1) % PANES.len(),
+                KeyCode::Left | KeyCode::BackTab => {
+                    self.pane = (self.pane + PANES.len() - 1) % PANES.len()
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::PageDown => {
+                    self.hex_offset = (self.hex_offset + 0x100).min(self.last_row())
+                }
+                KeyCode::PageUp => self.hex_offset = self.hex_offset.saturating_sub(0x100),
+                KeyCode::Enter => self.jump(),
+                KeyCode::Char('d') => {
+                    self.view = match self.view {
+                        View::Hex => View::Disasm,
+                        View::Disasm => View::Hex,
+                    };
+                    self.refresh_disasm();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn len(&self, pane: usize) -> usize {
+        match pane {
+            0 => self.file.program_headers.len(),
+            1 => self.file.section_headers.len(),
+            2 => self.symbols.len(),
+            _ => self.relocations.len(),
+        }
+    }
+
+    fn last_row(&self) -> usize {
+        self.input.len().saturating_sub(1) & !0xf
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = self.len(self.pane);
+        if len == 0 {
+            return;
+        }
+        let state = &mut self.selection[self.pane];
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+    }
+
+    // File range the current selection refers to
+    fn target(&self) -> Option<Range<usize>> {
+        let idx = self.selection[self.pane].selected()?;
+        match self.pane {
+            0 => {
+                let ph = self.file.program_headers.get(idx)?;
+                let range = ph.file_range();
+                Some(range.start.into()..range.end.into())
+            }
+            1 => {
+                let sh = self.file.section_headers.get(idx)?;
+                let range = sh.file_range();
+                Some(range.start.into()..range.end.into())
+            }
+            2 => {
+                let sym = self.symbols.get(idx)?;
+                let start: usize = self.file.vaddr_to_offset(sym.value)?.into();
+                Some(start..start + sym.size.max(1) as usize)
+            }
+            _ => {
+                let reloc = self.relocations.get(idx)?;
+                let start: usize = self.file.vaddr_to_offset(reloc.offset)?.into();
+                Some(start..start + 8)
+            }
+        }
+    }
+
+    fn jump(&mut self) {
+        if let Some(range) = self.target() {
+            self.hex_offset = range.start & !0xf;
+            self.highlight = range;
+            self.refresh_disasm();
+        }
+    }
+
+    fn refresh_disasm(&mut self) {
+        if self.view != View::Disasm {
+            return;
+        }
+        // Headers of a truncated file can point past its end
+        let start = self.highlight.start.min(self.input.len());
+        let end = (start + 0x200).min(self.input.len());
+        let origin = self
+            .file
+            .offset_to_vaddr(Addr(start as u64))
+            .map(|a| a.0)
+            .unwrap_or(start as u64);
+        let code = &self.input[start..end];
+        self.disasm = match crate::disasm_listing(self.file.machine, code, origin, &[]) {
+            Ok(listing) => listing,
+            Err(e) => format!("ndisasm failed: {}", e),
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+        let body = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(rows[1]);
+
+        let tabs = Tabs::new(PANES.to_vec())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" elk explore "),
+            )
+            .select(self.pane)
+            .highlight_style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            );
+        frame.render_widget(tabs, rows[0]);
+
+        let items: Vec<ListItem> = (0..self.len(self.pane))
+            .map(|i| ListItem::new(self.describe(i)))
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(PANES[self.pane]),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, body[0], &mut self.selection[self.pane]);
+
+        match self.view {
+            View::Hex => self.draw_hex(frame, body[1]),
+            View::Disasm => {
+                let text = Paragraph::new(self.disasm.as_str())
+                    .block(Block::default().borders(Borders::ALL).title("Disassembly"));
+                frame.render_widget(text, body[1]);
+            }
+        }
+        frame.render_widget(Paragraph::new(HELP), rows[2]);
+    }
+
+    fn describe(&self, i: usize) -> String {
+        match self.pane {
+            0 => {
+                let ph = &self.file.program_headers[i];
+                format!(
+                    "{:2} {:<12} {:?} {:#010x} {:#x}",
+                    i,
+                    format!("{:?}", ph.typ),
+                    ph.flags,
+                    ph.virt_addr.0,
+                    ph.mem_size.0
+                )
+            }
+            1 => {
+                let sh = &self.file.section_headers[i];
+                format!(
+                    "{:2} {:<20} {:#010x} {:#x}",
+                    i, sh.name, sh.addr.0, sh.size.0
+                )
+            }
+            2 => {
+                let sym = &self.symbols[i];
+                format!(
+                    "{:#010x} {:>6} {} {}",
+                    sym.value.0,
+                    sym.size,
+                    self.file.symbol_class(sym),
+                    crate::size::demangle(&sym.name)
+                )
+            }
+            _ => {
+                let rel = &self.relocations[i];
+                let sym = self
+                    .dynsyms
+                    .get(rel.sym as usize)
+                    .map(|s| s.name.clone())
+                    .unwrap_or_default();
+                format!(
+                    "{:#010x} {:?} {} {}",
+                    rel.offset.0, rel.typ, sym, rel.addend
+                )
+            }
+        }
+    }
+
+    fn draw_hex(&self, frame: &mut Frame, area: Rect) {
+        let rows = area.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = (0..rows)
+            .map(|row| self.hex_offset + row * 16)
+            .take_while(|&offset| offset < self.input.len())
+            .map(|offset| {
+                let chunk = &self.input[offset..(offset + 16).min(self.input.len())];
+                let mut spans = vec![Span::styled(
+                    format!("{:08x}  ", offset),
+                    Style::default().fg(Color::DarkGray),
+                )];
+                for (i, byte) in chunk.iter().enumerate() {
+                    let style = if self.highlight.contains(&(offset + i)) {
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::REVERSED)
+                    } else {
+                        Style::default()
+                    };
+                    spans.push(Span::styled(format!("{:02x}", byte), style));
+                    spans.push(Span::raw(" "));
+                }
+                let ascii: String = chunk
+                    .iter()
+                    .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                    .collect();
+                spans.push(Span::styled(
+                    format!(" {}", ascii),
+                    Style::default().fg(Color::DarkGray),
+                ));
+                Line::from(spans)
+            })
+            .collect();
+        let hex = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Hex @ {:#x}", self.hex_offset)),
+        );
+        frame.render_widget(hex, area);
+    }
+}
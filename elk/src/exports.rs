@@ -0,0 +1,170 @@
+use std::{collections::HashSet, error::Error, path::PathBuf};
+
+use delf::{types::*, FileHeader};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg,
+    deps::{self, Object},
+    exit::Failure,
+    schema,
+    size::demangle,
+    source,
+    tables::Table,
+};
+
+// Reached through DT_INIT and DT_FINI rather than by name
+const ENTRY_POINTS: &[&str] = &["_init", "_fini"];
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Find exports of shared libraries that nothing in a set of binaries imports")]
+pub struct Args {
+    #[arg(long, help = "Add every library the given files pull in to the set")]
+    deps: bool,
+    #[arg(long, help = "List the unused symbols, not just how many there are")]
+    list: bool,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    files: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Library {
+    name: String,
+    exports: usize,
+    unused: Vec<Export>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Export {
+    name: String,
+    version: Option<String>,
+    size: u64,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Vec<Library>>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut objects: Vec<Object> = Vec::new();
+    for path in &args.files {
+        let input = source::read(path)?;
+        let file = FileHeader::parse_or_describe(&input)
+            .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+        let found = match args.deps {
+            true => deps::objects(path, file),
+            false => vec![Object {
+                name: path.clone(),
+                path: PathBuf::from(path),
+                file,
+            }],
+        };
+        // The same library is usually pulled in by several of the files
+        for object in found {
+            if !objects.iter().any(|o| o.path == object.path) {
+                objects.push(object);
+            }
+        }
+    }
+
+    let imported: HashSet<String> = objects
+        .iter()
+        .flat_map(|o| deps::dynamic_symbols(&o.file))
+        .filter(|(sym, _)| sym.shndx == SectionIdx::Undef)
+        .map(|(sym, _)| sym.name)
+        .collect();
+    let libraries: Vec<Library> = objects
+        .iter()
+        .filter(|o| is_library(&o.file))
+        .map(|o| unused_exports(o, &imported))
+        .collect();
+
+    if args.format.json() {
+        schema::print_json("unused-exports", &libraries)?;
+    } else {
+        print_report(&libraries, args.list);
+    }
+    Ok(())
+}
+
+// Executables are where lookups start, so what they export doesn't count as surface. PIEs are
+// ET_DYN too, and libc and ld.so have a PT_INTERP, but only libraries have a DT_SONAME.
+fn is_library(file: &FileHeader) -> bool {
+    file.typ == Type::Dyn
+        && (file.dynamic_entry(DynamicTag::SOName).is_some()
+            || file.segments_of_type(SegmentType::Interp).next().is_none())
+}
+
+fn unused_exports(object: &Object, imported: &HashSet<String>) -> Library {
+    let exports: Vec<_> = deps::dynamic_symbols(&object.file)
+        .into_iter()
+        .filter(|(sym, _)| sym.is_exported())
+        // Version definitions show up as absolute symbols named after the version
+        .filter(|(sym, _)| !(sym.shndx == SectionIdx::Abs && sym.value.0 == 0))
+        .filter(|(sym, _)| !ENTRY_POINTS.contains(&sym.name.as_str()))
+        .collect();
+    let mut unused: Vec<Export> = exports
+        .iter()
+        .filter(|(sym, _)| !imported.contains(&sym.name))
+        .map(|(sym, version)| Export {
+            name: sym.name.clone(),
+            version: version.as_ref().map(|v| v.name.clone()),
+            size: sym.size,
+        })
+        .collect();
+    unused.sort_by(|a, b| a.name.cmp(&b.name));
+    Library {
+        name: object.name.clone(),
+        exports: exports.len(),
+        unused,
+    }
+}
+
+fn print_report(libraries: &[Library], list: bool) {
+    let summary = Table {
+        header: "Exports nothing in the set imports".into(),
+        labels: vec![
+            "Library".into(),
+            "Exports".into(),
+            "Unused".into(),
+            "Unused bytes".into(),
+        ],
+        rows: libraries
+            .iter()
+            .map(|lib| {
+                vec![
+                    lib.name.clone(),
+                    lib.exports.to_string(),
+                    lib.unused.len().to_string(),
+                    lib.unused.iter().map(|e| e.size).sum::<u64>().to_string(),
+                ]
+            })
+            .collect(),
+    };
+    summary.print();
+    if !list {
+        return;
+    }
+    for lib in libraries.iter().filter(|lib| !lib.unused.is_empty()) {
+        let table = Table {
+            header: format!("Unused exports of {}", lib.name),
+            labels: vec!["Symbol".into(), "Version".into(), "Size".into()],
+            rows: lib
+                .unused
+                .iter()
+                .map(|e| {
+                    vec![
+                        demangle(&e.name),
+                        e.version.clone().unwrap_or_default(),
+                        e.size.to_string(),
+                    ]
+                })
+                .collect(),
+        };
+        table.print();
+    }
+}
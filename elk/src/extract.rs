@@ -0,0 +1,84 @@
+use std::{error::Error, fs};
+
+use clap_complete::engine::ArgValueCompleter;
+use delf::{types::*, FileHeader};
+
+use crate::{cli, exit::Failure, parse_number, source};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Write the raw bytes of a section or segment to a file")]
+pub struct Args {
+    #[arg(
+        long,
+        required_unless_present_any = ["segment", "vaddr"],
+        conflicts_with_all = ["segment", "vaddr"],
+        add = ArgValueCompleter::new(cli::section_names),
+        help = "Extract the section with this name"
+    )]
+    section: Option<String>,
+    #[arg(
+        long,
+        value_name = "INDEX",
+        conflicts_with = "vaddr",
+        help = "Extract the segment with this index"
+    )]
+    segment: Option<usize>,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_parser = parse_number,
+        help = "Extract the LOAD segment mapping this address"
+    )]
+    vaddr: Option<u64>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Where to write the bytes"
+    )]
+    write: String,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let missing = |what: String| Failure::parse(format!("{}: {}", path, what));
+
+    let (what, data) = match (&args.section, args.segment, args.vaddr) {
+        (Some(name), _, _) => {
+            let sh = file
+                .section_by_name(name)
+                .ok_or_else(|| missing(format!("no section called {}", name)))?;
+            if sh.typ == SectionType::NoBits {
+                return Err(missing(format!("{} takes no room in the file", name)).into());
+            }
+            (format!("section {}", name), &sh.data[..])
+        }
+        (None, Some(index), _) => {
+            let ph = file
+                .program_headers
+                .get(index)
+                .ok_or_else(|| missing(format!("no segment {}", index)))?;
+            (format!("segment {} ({:?})", index, ph.typ), &ph.data[..])
+        }
+        // clap has made sure there is one
+        (None, None, vaddr) => {
+            let addr = vaddr.unwrap_or_default();
+            let (index, ph) = file
+                .program_headers
+                .iter()
+                .enumerate()
+                .filter(|(_, ph)| ph.typ == SegmentType::Load)
+                .find(|(_, ph)| ph.mem_range().contains(&Addr(addr)))
+                .ok_or_else(|| missing(format!("no LOAD segment maps {:#x}", addr)))?;
+            (format!("segment {} ({:?})", index, ph.typ), &ph.data[..])
+        }
+    };
+    fs::write(&args.write, data)?;
+    println!("Wrote {} bytes of {} to {}", data.len(), what, args.write);
+    Ok(())
+}
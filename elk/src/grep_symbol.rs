@@ -0,0 +1,216 @@
+use std::{error::Error, path::PathBuf};
+
+use delf::{parse::ParseOptions, types::*, FileHeader, ParseError};
+use regex::Regex;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    check,
+    cli::FormatArg,
+    deps,
+    exit::{Failure, Status},
+    interrupt,
+    progress::Progress,
+    schema,
+    size::demangle,
+    source,
+    tables::Table,
+};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Find the libraries that define or reference a symbol")]
+pub struct Args {
+    #[arg(
+        long,
+        help = "Take SYMBOL as a regular expression, matched against mangled and demangled names"
+    )]
+    regex: bool,
+    #[arg(long, help = "Only list objects that define the symbol")]
+    defined: bool,
+    #[arg(short, long, help = "Descend into subdirectories")]
+    recursive: bool,
+    #[command(flatten)]
+    format: FormatArg,
+    symbol: String,
+    #[arg(
+        value_hint = clap::ValueHint::AnyPath,
+        help = "Files and directories to search, by default the library search path"
+    )]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Hit {
+    object: String,
+    symbol: String,
+    // Whether the object defines the symbol or only references it
+    defined: bool,
+    version: Option<String>,
+    // Only reachable by an explicit `symbol@version` reference
+    hidden: bool,
+    // For references, the library the version is expected from
+    library: Option<String>,
+    size: u64,
+}
+
+#[derive(Serialize, JsonSchema, Default)]
+#[schemars(rename = "GrepSymbolReport")]
+struct Report {
+    files_scanned: usize,
+    // Files Ctrl-C kept from being searched
+    skipped: usize,
+    hits: Vec<Hit>,
+}
+
+enum Pattern {
+    Name(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Name(wanted) => name == wanted,
+            Self::Regex(regex) => regex.is_match(name) || regex.is_match(&demangle(name)),
+        }
+    }
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Report>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let pattern = match args.regex {
+        true => {
+            Pattern::Regex(Regex::new(&args.symbol).map_err(|e| Failure::parse(e.to_string()))?)
+        }
+        false => Pattern::Name(args.symbol.clone()),
+    };
+    let roots = match args.paths.is_empty() {
+        true => deps::system_dirs().into_iter().map(PathBuf::from).collect(),
+        false => args.paths.clone(),
+    };
+    let mut paths = Vec::new();
+    for root in &roots {
+        // /lib is often a link to /usr/lib
+        let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+        if root.is_dir() {
+            check::walk(&root, args.recursive, &mut paths);
+        } else {
+            paths.push(root);
+        }
+    }
+    // ld.so.conf and the built-in directories overlap
+    paths.sort();
+    paths.dedup();
+
+    let _interrupts = interrupt::catch();
+    let options = ParseOptions {
+        cancel: interrupt::token().clone(),
+        ..Default::default()
+    };
+    let mut report = Report::default();
+    let progress = Progress::new("Searching", paths.len() as u64);
+    for path in &paths {
+        if interrupt::interrupted() {
+            report.skipped += 1;
+            continue;
+        }
+        progress.advance(1);
+        report.files_scanned += 1;
+        let input = match source::map_raw(path) {
+            Ok(input) if input.starts_with(FileHeader::MAGIC) => input,
+            _ => continue,
+        };
+        let file = match FileHeader::parse_checked(&input, &options) {
+            Ok(file) => file,
+            Err(ParseError::Cancelled) => {
+                report.skipped += 1;
+                continue;
+            }
+            Err(_) => continue,
+        };
+        for (sym, version) in deps::dynamic_symbols(&file) {
+            let defined = sym.shndx != SectionIdx::Undef;
+            if (args.defined && !defined) || !pattern.matches(&sym.name) {
+                continue;
+            }
+            report.hits.push(Hit {
+                object: path.display().to_string(),
+                symbol: sym.name,
+                defined,
+                hidden: version.as_ref().is_some_and(|v| v.hidden),
+                library: version.as_ref().and_then(|v| v.file.clone()),
+                version: version.map(|v| v.name),
+                size: sym.size,
+            });
+        }
+    }
+    drop(progress);
+
+    if args.format.json() {
+        schema::print_json("grep-symbol", &report)?;
+    } else {
+        print_report(&report, &args.symbol);
+    }
+    if report.skipped > 0 {
+        return Err(Failure::new(
+            Status::Interrupted,
+            format!(
+                "interrupted, {} of {} files not searched",
+                report.skipped,
+                paths.len()
+            ),
+        )
+        .into());
+    }
+    match report.hits.is_empty() {
+        true => Err(Failure::findings(format!(
+            "nothing in {} files defines or references {}",
+            report.files_scanned, args.symbol
+        ))
+        .into()),
+        false => Ok(()),
+    }
+}
+
+fn print_report(report: &Report, symbol: &str) {
+    // Definitions first, the answer to which library provides the symbol
+    let mut hits: Vec<&Hit> = report.hits.iter().collect();
+    hits.sort_by_key(|hit| !hit.defined);
+    let table = Table {
+        header: format!("Objects with {}", symbol),
+        labels: vec![
+            "Object".into(),
+            "Symbol".into(),
+            "Kind".into(),
+            "Version".into(),
+            "Size".into(),
+        ],
+        rows: hits
+            .iter()
+            .map(|hit| {
+                let version = match (&hit.version, &hit.library) {
+                    (Some(version), Some(library)) => format!("{} from {}", version, library),
+                    (Some(version), None) if hit.hidden => format!("{} (hidden)", version),
+                    (Some(version), None) => version.clone(),
+                    (None, _) => String::new(),
+                };
+                vec![
+                    hit.object.clone(),
+                    demangle(&hit.symbol),
+                    match hit.defined {
+                        true => "defines".into(),
+                        false => "references".into(),
+                    },
+                    version,
+                    hit.size.to_string(),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
@@ -0,0 +1,388 @@
+use std::{borrow::Cow, collections::HashMap, error::Error, fs};
+
+use delf::{detect::Class, types::*, FileHeader};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Digest as _, Sha256};
+
+use crate::{cli::FormatArg, exit::Failure, schema, source, tables::Table};
+
+// lld hashes its output in pieces this big, then hashes the pieces' hashes
+const LLD_CHUNK: usize = 1 << 20;
+
+#[derive(clap::Args, Debug)]
+#[command(about = "SHA-256 of every segment and section, and the build ID worked out again")]
+pub struct Args {
+    #[arg(
+        long,
+        value_name = "MANIFEST",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Check the loaded segments and sections against the JSON output of an earlier \
+                `elk hash`; everything else is allowed to differ"
+    )]
+    verify: Option<String>,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+struct Digest {
+    // LOAD[n] for the n-th LOAD segment, the type and index for other segments, the name for
+    // sections
+    name: String,
+    // Mapped by the loader: LOAD segments and SHF_ALLOC sections
+    loaded: bool,
+    addr: u64,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct BuildId {
+    stored: String,
+    // The stored one again, when the file hashes to it the way a known linker hashes its output
+    recomputed: Option<String>,
+    linker: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "HashReport")]
+struct Report {
+    file: String,
+    segments: Vec<Digest>,
+    sections: Vec<Digest>,
+    build_id: Option<BuildId>,
+    // With --verify, the loaded parts that don't match the manifest
+    #[serde(default)]
+    mismatches: Vec<String>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Report>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let mut report = hash(path, &file, &input);
+    if let Some(manifest) = &args.verify {
+        let text = fs::read_to_string(manifest)?;
+        let value: Value = serde_json::from_str(&text)
+            .map_err(|e| Failure::parse(format!("{}: {}", manifest, e)))?;
+        // The whole envelope `--format json` prints, or just its data
+        let data = value.get("data").cloned().unwrap_or(value);
+        let expected: Report = serde_json::from_value(data)
+            .map_err(|e| Failure::parse(format!("{}: {}", manifest, e)))?;
+        report.mismatches = compare(&expected, &report);
+    }
+
+    if args.format.json() {
+        schema::print_json("hash", &report)?;
+    } else {
+        print_report(&report);
+    }
+    match (&args.verify, report.mismatches.len()) {
+        (Some(manifest), n) if n > 0 => Err(Failure::findings(format!(
+            "{} loaded parts of {} don't match {}",
+            n, path, manifest
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+fn hash(path: &str, file: &FileHeader, input: &[u8]) -> Report {
+    let mut loads = 0;
+    let segments = file
+        .program_headers
+        .iter()
+        .enumerate()
+        .map(|(i, ph)| {
+            let name = match ph.typ {
+                SegmentType::Load => {
+                    loads += 1;
+                    format!("LOAD[{}]", loads - 1)
+                }
+                typ => format!("{:?}[{}]", typ, i),
+            };
+            Digest {
+                name,
+                loaded: ph.typ == SegmentType::Load,
+                addr: ph.virt_addr.0,
+                size: ph.data.len() as u64,
+                sha256: sha256(&loaded_bytes(file, ph)),
+            }
+        })
+        .collect();
+    let sections = file
+        .section_headers
+        .iter()
+        .skip(1)
+        .map(|sh| Digest {
+            name: sh.name.clone(),
+            loaded: sh.flags.contains(SectionFlags::Alloc),
+            addr: sh.addr.0,
+            size: sh.data.len() as u64,
+            sha256: sha256(&sh.data),
+        })
+        .collect();
+    Report {
+        file: path.to_string(),
+        segments,
+        sections,
+        build_id: file.build_id().map(|stored| build_id(file, input, stored)),
+        mismatches: Vec::new(),
+    }
+}
+
+// The segment's bytes, except for the fields of an ELF header at its start that only say where
+// the section headers are. Stripping rewrites them, the program doesn't see them.
+fn loaded_bytes<'a>(file: &FileHeader, ph: &'a ProgramHeader) -> Cow<'a, [u8]> {
+    let fields = match file.class {
+        Class::Elf32 => [32..36, 46..52],
+        Class::Elf64 => [40..48, 58..64],
+    };
+    if ph.offset.0 != 0 || ph.data.len() < fields[1].end {
+        return Cow::Borrowed(&ph.data);
+    }
+    let mut bytes = ph.data.to_vec();
+    for range in fields {
+        bytes[range].fill(0);
+    }
+    Cow::Owned(bytes)
+}
+
+// Linkers hash their output with the build ID still zero. gold hashes the whole file at once,
+// lld in 1 MiB pieces. GNU ld hashes its own view of the headers and sections, which the file
+// alone doesn't give back, so its build IDs are never recomputed.
+fn build_id(file: &FileHeader, input: &[u8], stored: String) -> BuildId {
+    let unrecomputed = |stored| BuildId {
+        stored,
+        recomputed: None,
+        linker: None,
+    };
+    let desc = match hex(&stored) {
+        Some(desc) if desc.len() == 20 => desc,
+        // MD5, UUID and xxhash build IDs
+        _ => return unrecomputed(stored),
+    };
+    let at = file
+        .section_headers
+        .iter()
+        .filter(|sh| sh.typ == SectionType::Note)
+        .map(|sh| (sh.offset.0, &sh.data[..]))
+        .chain(
+            file.segments_of_type(SegmentType::Note)
+                .map(|ph| (ph.offset.0, &ph.data[..])),
+        )
+        .find_map(|(offset, data)| {
+            let i = data.windows(desc.len()).position(|w| w == &desc[..])?;
+            Some(offset as usize + i)
+        });
+    let at = match at {
+        Some(at) if at + desc.len() <= input.len() => at,
+        _ => return unrecomputed(stored),
+    };
+    let mut zeroed = input.to_vec();
+    zeroed[at..at + desc.len()].fill(0);
+    let gold = Sha1::digest(&zeroed);
+    let lld = Sha1::digest(
+        zeroed
+            .chunks(LLD_CHUNK)
+            .flat_map(|chunk| Sha1::digest(chunk).to_vec())
+            .collect::<Vec<u8>>(),
+    );
+    let linker = match () {
+        _ if gold[..] == desc[..] => Some("gold"),
+        _ if lld[..] == desc[..] => Some("lld"),
+        _ => None,
+    };
+    BuildId {
+        recomputed: linker.map(|_| stored.clone()),
+        stored,
+        linker: linker.map(String::from),
+    }
+}
+
+// What differs between the loaded parts of `expected` and `actual`
+fn compare(expected: &Report, actual: &Report) -> Vec<String> {
+    let (expected, actual) = (loaded(expected), loaded(actual));
+    let mut mismatches = Vec::new();
+    for (key, want) in &expected {
+        match actual.iter().find(|(other, _)| other == key) {
+            None => mismatches.push(format!("{}: missing", key)),
+            Some((_, got)) if got.addr != want.addr => mismatches.push(format!(
+                "{}: at {:#x}, expected {:#x}",
+                key, got.addr, want.addr
+            )),
+            Some((_, got)) if got.size != want.size => mismatches.push(format!(
+                "{}: {:#x} bytes, expected {:#x}",
+                key, got.size, want.size
+            )),
+            Some((_, got)) if got.sha256 != want.sha256 => {
+                mismatches.push(format!("{}: contents differ", key))
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, _) in &actual {
+        if !expected.iter().any(|(other, _)| other == key) {
+            mismatches.push(format!("{}: not in the manifest", key));
+        }
+    }
+    mismatches
+}
+
+// The loaded digests, each named for comparing: sections sharing a name pair up in section
+// header order, the second one as `name#1` and so on. Stripping only drops unloaded sections, so
+// the loaded ones keep their order.
+fn loaded(report: &Report) -> Vec<(String, &Digest)> {
+    let mut seen = HashMap::new();
+    report
+        .segments
+        .iter()
+        .chain(&report.sections)
+        .filter(|digest| digest.loaded)
+        .map(|digest| {
+            let n = seen.entry(&digest.name).or_insert(0);
+            *n += 1;
+            let key = match *n {
+                1 => digest.name.clone(),
+                n => format!("{}#{}", digest.name, n - 1),
+            };
+            (key, digest)
+        })
+        .collect()
+}
+
+fn print_report(report: &Report) {
+    let table = |header: &str, digests: &[Digest]| Table {
+        header: header.into(),
+        labels: vec![
+            "Name".into(),
+            "Loaded".into(),
+            "Address".into(),
+            "Size".into(),
+            "SHA-256".into(),
+        ],
+        rows: digests
+            .iter()
+            .map(|digest| {
+                vec![
+                    digest.name.clone(),
+                    match digest.loaded {
+                        true => "yes".into(),
+                        false => String::new(),
+                    },
+                    format!("{:#x}", digest.addr),
+                    format!("{:#x}", digest.size),
+                    digest.sha256.clone(),
+                ]
+            })
+            .collect(),
+    };
+    table("Segments", &report.segments).print();
+    if !report.sections.is_empty() {
+        table("Sections", &report.sections).print();
+    }
+    if let Some(id) = &report.build_id {
+        match &id.linker {
+            Some(linker) => println!("Build ID {} recomputed as {} does", id.stored, linker),
+            None => println!("Build ID {} not recomputed", id.stored),
+        }
+    }
+    for mismatch in &report.mismatches {
+        println!("{}", mismatch);
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn hex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(name: &str, loaded: bool, addr: u64, sha256: &str) -> Digest {
+        Digest {
+            name: name.into(),
+            loaded,
+            addr,
+            size: 0x10,
+            sha256: sha256.into(),
+        }
+    }
+
+    fn report(sections: Vec<Digest>) -> Report {
+        Report {
+            file: "a.out".into(),
+            segments: vec![digest("LOAD[0]", true, 0x1000, "load")],
+            sections,
+            build_id: None,
+            mismatches: Vec::new(),
+        }
+    }
+
+    // Stripped of .symtab, otherwise the same
+    #[test]
+    fn unloaded_parts_may_differ() {
+        let expected = report(vec![
+            digest(".text", true, 0x1000, "text"),
+            digest(".symtab", false, 0, "symtab"),
+        ]);
+        let actual = report(vec![digest(".text", true, 0x1000, "text")]);
+        assert!(compare(&expected, &actual).is_empty());
+    }
+
+    // Two sections named .data: only the second changed, and it is the one named
+    #[test]
+    fn sections_sharing_a_name_are_told_apart() {
+        let expected = report(vec![
+            digest(".data", true, 0x2000, "first"),
+            digest(".data", true, 0x2010, "second"),
+        ]);
+        let actual = report(vec![
+            digest(".data", true, 0x2000, "first"),
+            digest(".data", true, 0x2010, "changed"),
+        ]);
+        assert_eq!(compare(&expected, &actual), [".data#1: contents differ"]);
+    }
+
+    #[test]
+    fn moved_missing_and_new_sections_are_mismatches() {
+        let expected = report(vec![
+            digest(".text", true, 0x1000, "text"),
+            digest(".rodata", true, 0x1800, "rodata"),
+        ]);
+        let actual = report(vec![
+            digest(".text", true, 0x1100, "text"),
+            digest(".bss", true, 0x3000, "bss"),
+        ]);
+        assert_eq!(
+            compare(&expected, &actual),
+            [
+                ".text: at 0x1100, expected 0x1000",
+                ".rodata: missing",
+                ".bss: not in the manifest",
+            ]
+        );
+    }
+}
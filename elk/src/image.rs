@@ -0,0 +1,108 @@
+use std::{io, ops::Range, sync::Arc};
+
+use delf::types::{ProgramHeader, SegmentBits, SegmentFlags};
+use region::Protection;
+
+use crate::space::AddressSpace;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+// Bytes between the start of a segment's first page and the segment itself. Load bases are page
+// aligned, so it is the same at the link address and at runtime.
+pub fn padding(ph: &ProgramHeader) -> u64 {
+    ph.virt_addr.0 % PAGE_SIZE
+}
+
+// Pages `ph` occupies once loaded at `base`
+pub fn pages(ph: &ProgramHeader, base: u64) -> Range<u64> {
+    let start = ph.virt_addr.0 + base - padding(ph);
+    start..ph.virt_addr.0 + base + ph.mem_size.0
+}
+
+// A LOAD segment as mapped into the target address space. The ProgramHeader it came from
+// describes the file; this is where its bytes ended up, with what permissions, and the space
+// that keeps them there.
+pub struct Segment {
+    // Position of the originating header among the file's program headers
+    pub header: usize,
+    // Runtime address of the segment's first byte and of the page it starts on
+    pub start: u64,
+    pub page_start: u64,
+    pub mem_size: u64,
+    // Where the segment's bytes start in the file, and how many of them were copied. The rest,
+    // up to mem_size, is zero-filled.
+    pub offset: u64,
+    pub file_size: u64,
+    pub flags: SegmentBits,
+    // Dropping the segment unmaps it from here
+    space: Arc<dyn AddressSpace>,
+}
+
+impl Segment {
+    // Maps the `header`th program header of a file at `base` in `space`, writable so relocations
+    // can go in, and copies its file contents. `protect` gives it its own permissions afterwards.
+    pub fn map(
+        header: usize,
+        ph: &ProgramHeader,
+        base: u64,
+        space: &Arc<dyn AddressSpace>,
+    ) -> io::Result<Self> {
+        let pages = pages(ph, base);
+        space.map(Some(pages.start), pages.end - pages.start)?;
+        let segment = Self {
+            header,
+            start: ph.virt_addr.0 + base,
+            page_start: pages.start,
+            mem_size: ph.mem_size.0,
+            offset: ph.offset.0,
+            file_size: ph.data.len() as u64,
+            flags: ph.flags,
+            space: space.clone(),
+        };
+        space.write(segment.start, &ph.data)?;
+        Ok(segment)
+    }
+
+    // Runtime addresses of the segment's bytes, without the page padding
+    pub fn range(&self) -> Range<u64> {
+        self.start..self.start + self.mem_size
+    }
+
+    // Everything mapped for the segment, page padding included
+    pub fn pages(&self) -> Range<u64> {
+        self.page_start..self.start + self.mem_size
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.range().contains(&addr)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags.contains(SegmentFlags::Write)
+    }
+
+    pub fn protection(&self) -> Protection {
+        self.flags.iter().fold(Protection::NONE, |acc, f| {
+            acc | match f {
+                SegmentFlags::Read => Protection::READ,
+                SegmentFlags::Write => Protection::WRITE,
+                SegmentFlags::Execute => Protection::EXECUTE,
+            }
+        })
+    }
+
+    // Drops the write access the segment was mapped with, unless its flags ask for it. The
+    // program's own writes fault afterwards.
+    pub fn protect(&self) -> io::Result<()> {
+        let pages = self.pages();
+        self.space
+            .protect(pages.start, pages.end - pages.start, self.protection())
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        let pages = self.pages();
+        let _ = self.space.unmap(pages.start, pages.end - pages.start);
+    }
+}
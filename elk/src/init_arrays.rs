@@ -0,0 +1,239 @@
+use std::{collections::HashMap, convert::TryInto};
+
+use delf::{types::*, view::AddrView, FileHeader};
+
+use crate::{deps, relocs, source, tables::Table};
+
+// One function pointer out of .init_array and friends
+pub struct InitEntry {
+    pub list: &'static str,
+    pub slot: u64,
+    // Address the slot points at, when it is known before loading
+    pub target: Option<u64>,
+    pub function: Option<String>,
+    // Object defining the function; imports are looked up in the libraries the file pulls in
+    pub object: Option<String>,
+}
+
+// Every constructor and destructor of `file`, in the order they run under elk: the preinit
+// array, DT_INIT and the init array, then the fini array (last to first) and DT_FINI. Without
+// DT_INIT and DT_FINI, .ctors (last to first) and .dtors run in their place. elk runs them for
+// programs with an interpreter, the startup code of the rest does.
+pub fn entries(path: &str, file: &FileHeader) -> Vec<InitEntry> {
+    let relocs: HashMap<u64, relocs::Reloc> = relocs::annotate(file)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|reloc| (reloc.offset, reloc))
+        .collect();
+    let (init, fini) = (
+        dynamic_slot(file, DynamicTag::Init),
+        dynamic_slot(file, DynamicTag::FIni),
+    );
+    let (init, ctors) = match init {
+        Some(slot) => (vec![slot], Vec::new()),
+        None => (Vec::new(), legacy_list(file, ".ctors")),
+    };
+    let (fini, dtors) = match fini {
+        Some(slot) => (vec![slot], Vec::new()),
+        None => (Vec::new(), legacy_list(file, ".dtors")),
+    };
+    let lists = [
+        (
+            ".preinit_array",
+            array(
+                file,
+                DynamicTag::PreinitArray,
+                DynamicTag::PreinitArraySz,
+                SectionType::PreinitArray,
+            ),
+            false,
+        ),
+        ("DT_INIT", init, false),
+        (".ctors", ctors, true),
+        (
+            ".init_array",
+            array(
+                file,
+                DynamicTag::InitArray,
+                DynamicTag::InitArraysz,
+                SectionType::InitArray,
+            ),
+            false,
+        ),
+        (
+            ".fini_array",
+            array(
+                file,
+                DynamicTag::FiniArray,
+                DynamicTag::FiniArraysz,
+                SectionType::FiniArray,
+            ),
+            true,
+        ),
+        ("DT_FINI", fini, false),
+        (".dtors", dtors, false),
+    ];
+
+    let mut entries = Vec::new();
+    for (list, slots, reversed) in lists {
+        let mut list_entries: Vec<_> = slots
+            .into_iter()
+            .filter_map(|slot| entry(path, file, &relocs, list, slot))
+            .collect();
+        if reversed {
+            list_entries.reverse();
+        }
+        entries.extend(list_entries);
+    }
+
+    let imports: Vec<&str> = entries
+        .iter()
+        .filter(|e| e.object.is_none())
+        .filter_map(|e| e.function.as_deref())
+        .collect();
+    if !imports.is_empty() {
+        let providers = providers(path, &imports);
+        for entry in entries.iter_mut().filter(|e| e.object.is_none()) {
+            entry.object = entry
+                .function
+                .as_ref()
+                .and_then(|name| providers.get(name).cloned());
+        }
+    }
+    entries
+}
+
+fn entry(
+    path: &str,
+    file: &FileHeader,
+    relocs: &HashMap<u64, relocs::Reloc>,
+    list: &'static str,
+    slot: u64,
+) -> Option<InitEntry> {
+    let local = |target: u64| InitEntry {
+        list,
+        slot,
+        target: Some(target),
+        function: file
+            .symbol_at(Addr(target))
+            .map(|(sym, offset)| match offset {
+                0 => sym.name,
+                _ => format!("{}+{:#x}", sym.name, offset),
+            }),
+        object: Some(path.to_string()),
+    };
+    match relocs.get(&slot) {
+        Some(reloc) if reloc.typ == "Relative" => Some(local(reloc.addend as u64)),
+        // Bound to a symbol: the dynamic linker fills in its address, maybe from another object
+        Some(reloc) => {
+            let defined = deps::dynamic_symbols(file)
+                .into_iter()
+                .map(|(sym, _)| sym)
+                .find(|sym| Some(&sym.name) == reloc.symbol.as_ref() && sym.is_exported());
+            Some(match defined {
+                Some(sym) => local(sym.value.0),
+                None => InitEntry {
+                    list,
+                    slot,
+                    target: None,
+                    function: reloc.symbol.clone(),
+                    object: None,
+                },
+            })
+        }
+        None => {
+            let bytes = file.bytes_at(Addr(slot))?.get(..8)?;
+            let target = u64::from_le_bytes(bytes.try_into().ok()?);
+            // .ctors and .dtors are framed by -1 and 0
+            match target {
+                0 | u64::MAX => None,
+                target => Some(local(target)),
+            }
+        }
+    }
+}
+
+// Slots of an array found through the dynamic section, or its section in static files
+fn array(file: &FileHeader, addr: DynamicTag, size: DynamicTag, typ: SectionType) -> Vec<u64> {
+    let range = match (file.dynamic_entry(addr), file.dynamic_entry(size)) {
+        (Some(addr), Some(size)) => addr.0..addr.0 + size.0,
+        _ => match file.section_headers.iter().find(|sh| sh.typ == typ) {
+            Some(sh) => sh.addr.0..sh.addr.0 + sh.size.0,
+            None => return Vec::new(),
+        },
+    };
+    range.step_by(8).collect()
+}
+
+// Where the value of `tag` is in the dynamic section, for the entries naming a function
+// themselves
+fn dynamic_slot(file: &FileHeader, tag: DynamicTag) -> Option<u64> {
+    let dynamic = file.segments_of_type(SegmentType::Dynamic).next()?;
+    match &dynamic.contents {
+        SegmentContent::Dynamic(entries) => entries
+            .iter()
+            .position(|e| e.tag == tag && e.addr.0 != 0)
+            .map(|i| dynamic.virt_addr.0 + i as u64 * 16 + 8),
+        _ => None,
+    }
+}
+
+fn legacy_list(file: &FileHeader, name: &str) -> Vec<u64> {
+    match file.section_by_name(name) {
+        Some(sh) => (sh.addr.0..sh.addr.0 + sh.size.0).step_by(8).collect(),
+        None => Vec::new(),
+    }
+}
+
+// First library in load order that exports each of `names`
+fn providers(path: &str, names: &[&str]) -> HashMap<String, String> {
+    let file = match source::read(path)
+        .ok()
+        .and_then(|input| FileHeader::parse_or_describe(&input).ok())
+    {
+        Some(file) => file,
+        None => return HashMap::new(),
+    };
+    let mut providers = HashMap::new();
+    for object in deps::objects(path, file).iter().skip(1) {
+        for (sym, _) in deps::dynamic_symbols(&object.file) {
+            if sym.is_exported() && names.contains(&sym.name.as_str()) {
+                providers
+                    .entry(sym.name)
+                    .or_insert_with(|| object.name.clone());
+            }
+        }
+    }
+    providers
+}
+
+pub fn table(path: &str, entries: &[InitEntry], view: &AddrView) -> Table {
+    Table {
+        header: format!("Constructors and destructors of {}, in run order", path),
+        labels: vec![
+            "#".into(),
+            "List".into(),
+            "Slot".into(),
+            "Function".into(),
+            "Object".into(),
+        ],
+        rows: entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let function = match (&entry.function, entry.target) {
+                    (Some(name), _) => name.clone(),
+                    (None, Some(target)) => view.show(Addr(target)),
+                    (None, None) => "?".into(),
+                };
+                vec![
+                    (i + 1).to_string(),
+                    entry.list.into(),
+                    view.show(Addr(entry.slot)),
+                    function,
+                    entry.object.clone().unwrap_or_else(|| "?".into()),
+                ]
+            })
+            .collect(),
+    }
+}
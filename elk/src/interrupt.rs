@@ -0,0 +1,67 @@
+use std::{
+    mem, ptr,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+use delf::cancel::CancellationToken;
+use libc::c_int;
+
+static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+
+// Cancelled by the first Ctrl-C while a `Catch` is held
+pub fn token() -> &'static CancellationToken {
+    TOKEN.get_or_init(CancellationToken::new)
+}
+
+pub fn interrupted() -> bool {
+    token().is_cancelled()
+}
+
+extern "C" fn handler(_: c_int) {
+    const MESSAGE: &[u8] = b"\nInterrupted, finishing up. Ctrl-C again to quit right away.\n";
+    // The token exists by now: `catch` creates it before installing the handler
+    if let Some(token) = TOKEN.get() {
+        token.cancel();
+    }
+    unsafe { libc::write(libc::STDERR_FILENO, MESSAGE.as_ptr().cast(), MESSAGE.len()) };
+}
+
+// While one is alive, Ctrl-C cancels `token()` instead of killing elk, so batch commands can stop
+// between files and still report what they got through. The handler resets itself, so a
+// second Ctrl-C kills elk as usual. Once the last one is dropped SIGINT does whatever it did
+// before.
+pub struct Catch(());
+
+// Live `Catch`es, and the SIGINT action to put back when there are none
+static CATCHING: Mutex<Option<(usize, libc::sigaction)>> = Mutex::new(None);
+
+pub fn catch() -> Catch {
+    token();
+    let mut catching = CATCHING.lock().unwrap_or_else(PoisonError::into_inner);
+    match &mut *catching {
+        Some((count, _)) => *count += 1,
+        None => unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(c_int) as usize;
+            action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous = mem::zeroed();
+            libc::sigaction(libc::SIGINT, &action, &mut previous);
+            *catching = Some((1, previous));
+        },
+    }
+    Catch(())
+}
+
+impl Drop for Catch {
+    fn drop(&mut self) {
+        let mut catching = CATCHING.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((count, previous)) = &mut *catching {
+            *count -= 1;
+            if *count == 0 {
+                unsafe { libc::sigaction(libc::SIGINT, &*previous, ptr::null_mut()) };
+                *catching = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::{error::Error, fs};
+
+use delf::{patch::SymbolEdit, types::*, FileHeader};
+
+use crate::{exit::Failure, parse_number, source};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Add or rename .symtab symbols and write the result to a new file")]
+pub struct Args {
+    #[arg(
+        long,
+        value_name = "NAME=ADDR[:SIZE]",
+        value_parser = parse_label,
+        help = "Add a function symbol, local unless --global is given"
+    )]
+    add: Vec<(String, u64, u64)>,
+    #[arg(long, help = "Make the symbols given with --add global")]
+    global: bool,
+    #[arg(long, help = "Add --add symbols as data objects rather than functions")]
+    object: bool,
+    #[arg(
+        long,
+        value_name = "OLD=NEW",
+        value_parser = parse_rename,
+        help = "Rename the symbols called OLD"
+    )]
+    rename: Vec<(String, String)>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Where to write the edited binary"
+    )]
+    write: String,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+fn parse_label(value: &str) -> Result<(String, u64, u64), String> {
+    let (name, location) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=ADDR[:SIZE], got {:?}", value))?;
+    let (addr, size) = match location.split_once(':') {
+        Some((addr, size)) => (parse_number(addr)?, parse_number(size)?),
+        None => (parse_number(location)?, 0),
+    };
+    Ok((name.to_string(), addr, size))
+}
+
+fn parse_rename(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_string(), to.to_string()))
+        }
+        _ => Err(format!("expected OLD=NEW, got {:?}", value)),
+    }
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+
+    let bind = match args.global {
+        true => SymBind::Global,
+        false => SymBind::Local,
+    };
+    let typ = match args.object {
+        true => SymType::Object,
+        false => SymType::Func,
+    };
+    let mut edits: Vec<SymbolEdit> = args
+        .rename
+        .iter()
+        .map(|(from, to)| SymbolEdit::Rename {
+            from: from.clone(),
+            to: to.clone(),
+        })
+        .collect();
+    edits.extend(args.add.iter().map(|(name, value, size)| SymbolEdit::Add {
+        name: name.clone(),
+        value: *value,
+        size: *size,
+        bind,
+        typ,
+    }));
+    let plan = file
+        .edit_symbols(&input, &edits)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    fs::write(&args.write, plan.apply(&input)?)?;
+    // Keep an executable executable
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(&args.write, metadata.permissions())?;
+    }
+    println!(
+        "Wrote {} with {} symbols added and {} renamed",
+        args.write,
+        args.add.len(),
+        args.rename.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::BTreeMap,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+use crate::{exit::Status, tables::Table, tls};
+
+// Binds the PLT slot of the `index`th DT_JMPREL entry of one object and returns its target
+pub type Resolver = Box<dyn Fn(u64) -> Result<u64, String> + Send + Sync>;
+
+// Resolvers by the value of GOT[1] of the object they bind for
+static RESOLVERS: Mutex<BTreeMap<u64, Arc<Resolver>>> = Mutex::new(BTreeMap::new());
+static NEXT_LINK: AtomicU64 = AtomicU64::new(1);
+
+// What PLT0 jumps to through GOT[2], with GOT[1] and the relocation index pushed on top of the
+// caller's return address. Argument registers, rax for varargs and r10 for the static chain are
+// saved around the call into elk, the SSE argument registers too, then the target is jumped to
+// as if the caller had called it directly. The stack is aligned by hand: entry points like
+// _start call through the PLT with a stack the ABI doesn't promise anything about.
+std::arch::global_asm!(
+    ".globl elk_lazy_trampoline",
+    "elk_lazy_trampoline:",
+    "push rbp",
+    "mov rbp, rsp",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "and rsp, -16",
+    "sub rsp, 128",
+    "movdqu [rsp], xmm0",
+    "movdqu [rsp + 16], xmm1",
+    "movdqu [rsp + 32], xmm2",
+    "movdqu [rsp + 48], xmm3",
+    "movdqu [rsp + 64], xmm4",
+    "movdqu [rsp + 80], xmm5",
+    "movdqu [rsp + 96], xmm6",
+    "movdqu [rsp + 112], xmm7",
+    "mov rdi, [rbp + 8]",
+    "mov rsi, [rbp + 16]",
+    "call {resolve}",
+    "mov r11, rax",
+    "movdqu xmm0, [rsp]",
+    "movdqu xmm1, [rsp + 16]",
+    "movdqu xmm2, [rsp + 32]",
+    "movdqu xmm3, [rsp + 48]",
+    "movdqu xmm4, [rsp + 64]",
+    "movdqu xmm5, [rsp + 80]",
+    "movdqu xmm6, [rsp + 96]",
+    "movdqu xmm7, [rsp + 112]",
+    "lea rsp, [rbp - 64]",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    "pop rbp",
+    // GOT[1] and the index
+    "add rsp, 16",
+    "jmp r11",
+    resolve = sym elk_lazy_resolve,
+);
+
+extern "C" {
+    fn elk_lazy_trampoline();
+}
+
+// Called from the trampoline on the program's thread, so elk's own thread pointer has to be put
+// back first. There is no way to hand an error back to the caller of the PLT entry: a failed
+// binding ends the program like ld.so's "symbol lookup error" does.
+extern "C" fn elk_lazy_resolve(link: u64, index: u64) -> u64 {
+    tls::as_host(|| resolve(link, index))
+}
+
+fn resolve(link: u64, index: u64) -> u64 {
+    let resolver = RESOLVERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&link)
+        .cloned();
+    let result = match resolver {
+        Some(resolver) => resolver(index),
+        None => Err(format!("no object is linked as {:#x}", link)),
+    };
+    match result {
+        Ok(target) => target,
+        Err(reason) => {
+            eprintln!("elk: lazy binding failed: {}", reason);
+            process::exit(Status::Load as i32)
+        }
+    }
+}
+
+// How many times each function was bound through a PLT on first call, shared by the resolvers
+// of a process. Threads racing to the same entry each bind it.
+#[derive(Clone, Default)]
+pub struct Counts(Arc<Mutex<BTreeMap<String, usize>>>);
+
+impl Counts {
+    pub fn add(&self, function: &str) {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(function.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn get(&self) -> BTreeMap<String, usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn table(&self) -> Table {
+        Table {
+            header: "PLT entries bound on first call".into(),
+            labels: vec!["Function".into(), "Binds".into()],
+            rows: self
+                .get()
+                .into_iter()
+                .map(|(function, n)| vec![function, n.to_string()])
+                .collect(),
+        }
+    }
+}
+
+// Address to put in GOT[2]
+pub fn trampoline() -> u64 {
+    elk_lazy_trampoline as *const () as u64
+}
+
+// An object's entry in the resolver table, for as long as its PLT can be called. Dropping it
+// takes the entry out.
+pub struct Link {
+    id: u64,
+}
+
+impl Link {
+    pub fn new(resolver: Resolver) -> Self {
+        let id = NEXT_LINK.fetch_add(1, Ordering::Relaxed);
+        RESOLVERS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, Arc::new(resolver));
+        Self { id }
+    }
+
+    // Address to put in GOT[1]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        RESOLVERS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.id);
+    }
+}
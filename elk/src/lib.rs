@@ -0,0 +1,166 @@
+use std::error::Error;
+
+use delf::{
+    hexdump,
+    types::{Addr, Machine},
+};
+
+pub mod arena;
+pub mod audit;
+pub mod capabilities;
+pub mod check;
+pub mod cli;
+pub mod config;
+pub mod container;
+pub mod coredump;
+pub mod crash;
+#[cfg(feature = "iced")]
+pub mod decode;
+pub mod deps;
+pub mod difftest;
+pub mod dig;
+pub mod dis;
+pub mod dump;
+#[cfg(feature = "emulate")]
+pub mod emulate;
+pub mod error;
+pub mod exit;
+#[cfg(feature = "tui")]
+pub mod explore;
+pub mod exports;
+pub mod extract;
+pub mod grep_symbol;
+pub mod hash;
+pub mod image;
+pub mod init_arrays;
+pub mod interrupt;
+pub mod label;
+pub mod lazy;
+pub mod linkage;
+pub mod loader;
+pub mod patch;
+pub mod plugin;
+pub mod preflight;
+pub mod progress;
+pub mod provenance;
+pub mod record;
+pub mod relocs;
+#[cfg(feature = "http")]
+pub mod remote;
+pub mod rendezvous;
+pub mod report;
+pub mod schema;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod selfcheck;
+pub mod similarity;
+pub mod size;
+pub mod snapshot;
+pub mod source;
+pub mod space;
+pub mod stack;
+pub mod stacks;
+pub mod symbolize;
+pub mod tables;
+pub mod tls;
+pub mod trace_relro;
+pub mod vtables;
+#[cfg(any(test, feature = "xcheck"))]
+pub mod xcheck;
+pub mod xref;
+
+// Accepts hexadecimal with a 0x prefix, or decimal
+pub fn parse_number(value: &str) -> Result<u64, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+        None => value.replace('_', "").parse(),
+    };
+    parsed.map_err(|e| format!("invalid number {:?}: {}", value, e))
+}
+
+#[cfg(feature = "ndisasm")]
+pub fn ndisasm_listing(input: &[u8], args: &[&str]) -> Result<String, Box<dyn Error>> {
+    ndisasm_bits("64", input, args)
+}
+
+// Listing of `machine` code loaded at `origin`, in ndisasm's layout whatever the machine:
+// ndisasm's own for x86, instruction words for the rest rather than x86 misreadings of them.
+// `args` only go to ndisasm, and only -s is honoured when disassembling in-process.
+pub fn disasm_listing(
+    machine: Machine,
+    input: &[u8],
+    origin: u64,
+    args: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let bits = match machine {
+        Machine::X86_64 => "64",
+        Machine::X86 => "32",
+        _ => return Ok(hexdump::instruction_dump(input, Addr(origin), machine)),
+    };
+    x86_listing(bits, input, origin, args)
+}
+
+// Whether this build can disassemble `machine`'s code: x86 takes ndisasm or iced, everything
+// else is shown as instruction words
+pub fn can_disassemble(machine: Machine) -> bool {
+    cfg!(any(feature = "ndisasm", feature = "iced"))
+        || !matches!(machine, Machine::X86 | Machine::X86_64)
+}
+
+#[cfg(all(feature = "ndisasm", not(feature = "iced")))]
+fn x86_listing(
+    bits: &str,
+    input: &[u8],
+    origin: u64,
+    args: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let origin = origin.to_string();
+    let args: Vec<&str> = ["-o", &origin].iter().chain(args).copied().collect();
+    ndisasm_bits(bits, input, &args)
+}
+
+#[cfg(feature = "iced")]
+fn x86_listing(
+    bits: &str,
+    input: &[u8],
+    origin: u64,
+    args: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let sync = args
+        .windows(2)
+        .find(|pair| pair[0] == "-s")
+        .and_then(|pair| pair[1].parse().ok());
+    Ok(decode::listing(bits.parse()?, input, origin, sync))
+}
+
+#[cfg(not(any(feature = "ndisasm", feature = "iced")))]
+fn x86_listing(_: &str, _: &[u8], _: u64, _: &[&str]) -> Result<String, Box<dyn Error>> {
+    Err("disassembling x86 needs elk built with the ndisasm or iced feature".into())
+}
+
+#[cfg(feature = "ndisasm")]
+fn ndisasm_bits(bits: &str, input: &[u8], args: &[&str]) -> Result<String, Box<dyn Error>> {
+    use std::{
+        io::{self, Write},
+        process::{Command, Stdio},
+    };
+
+    let mut proc = Command::new("ndisasm")
+        .arg("-b")
+        .arg(bits)
+        .args(args)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                "ndisasm isn't on the PATH: install NASM, or build elk with the iced feature".into()
+            }
+            _ => Box::<dyn Error>::from(e),
+        })?;
+
+    proc.stdin.as_mut().unwrap().write_all(input)?;
+    let res = proc.wait_with_output()?;
+    Ok(String::from_utf8_lossy(&res.stdout).into_owned())
+}
@@ -0,0 +1,278 @@
+use std::{error::Error, fmt};
+
+use delf::{types::*, FileHeader};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{cli::FormatArg, exit::Failure, schema, source, tables::Table};
+
+const DF_1_PIE: u64 = 0x0800_0000;
+const GLIBC_RELEASE: &str = "stable release version ";
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Relocatable,
+    Static,
+    StaticPie,
+    Dynamic,
+    SharedObject,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Libc {
+    Glibc,
+    Musl,
+    Uclibc,
+    Bionic,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Linkage {
+    pub kind: Kind,
+    pub interpreter: Option<String>,
+    pub needed: Vec<String>,
+    pub libc: Option<Libc>,
+    // Exact for static glibc, otherwise the newest GLIBC_x.y symbol version required
+    pub libc_version: Option<String>,
+    // Why `libc` was picked, strongest hint first
+    pub evidence: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Entry {
+    path: String,
+    #[serde(flatten)]
+    linkage: Linkage,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Classify how binaries are linked and which libc they expect")]
+pub struct Args {
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    files: Vec<String>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Vec<Entry>>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for path in &args.files {
+        let input = source::read(path)?;
+        let file = FileHeader::parse_or_describe(&input)
+            .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+        entries.push(Entry {
+            path: path.clone(),
+            linkage: analyze(&file),
+        });
+    }
+    if args.format.json() {
+        schema::print_json("linkage", &entries)?;
+    } else {
+        for entry in &entries {
+            entry.linkage.table(&entry.path).print();
+        }
+    }
+    Ok(())
+}
+
+pub fn analyze(file: &FileHeader) -> Linkage {
+    let interpreter = file.segments_of_type(SegmentType::Interp).next().map(|ph| {
+        String::from_utf8_lossy(&ph.data)
+            .trim_end_matches('\0')
+            .to_string()
+    });
+    let needed = file.dynamic_strings(DynamicTag::Needed);
+    let pie = file
+        .dynamic_entry(DynamicTag::Flags1)
+        .is_some_and(|a| a.0 & DF_1_PIE != 0);
+
+    let kind = match file.typ {
+        Type::Rel => Kind::Relocatable,
+        _ if interpreter.is_some() => Kind::Dynamic,
+        Type::Dyn if needed.is_empty() && (pie || file.entry_point.0 != 0) => Kind::StaticPie,
+        Type::Dyn => Kind::SharedObject,
+        _ if needed.is_empty() => Kind::Static,
+        _ => Kind::Dynamic,
+    };
+
+    let mut linkage = Linkage {
+        kind,
+        interpreter,
+        needed,
+        libc: None,
+        libc_version: None,
+        evidence: Vec::new(),
+    };
+    identify_libc(file, &mut linkage);
+    linkage
+}
+
+fn identify_libc(file: &FileHeader, linkage: &mut Linkage) {
+    let mut votes: Vec<(Libc, String)> = Vec::new();
+
+    if let Some(interp) = &linkage.interpreter {
+        let libc = if interp.contains("ld-musl") {
+            Some(Libc::Musl)
+        } else if interp.contains("ld-uClibc") {
+            Some(Libc::Uclibc)
+        } else if interp.starts_with("/system/bin/linker") {
+            Some(Libc::Bionic)
+        } else if interp.contains("ld-linux") {
+            Some(Libc::Glibc)
+        } else {
+            None
+        };
+        if let Some(libc) = libc {
+            votes.push((libc, format!("interpreter {}", interp)));
+        }
+    }
+    for lib in &linkage.needed {
+        let libc = match lib.as_str() {
+            "libc.so.6" => Some(Libc::Glibc),
+            "libc.so.0" => Some(Libc::Uclibc),
+            lib if lib.starts_with("libc.musl-") => Some(Libc::Musl),
+            _ => None,
+        };
+        if let Some(libc) = libc {
+            votes.push((libc, format!("needs {}", lib)));
+        }
+    }
+    if file.notes().iter().any(|(_, note)| note.name == "Android") {
+        votes.push((Libc::Bionic, "Android ident note".into()));
+    }
+
+    // Entry points and internals that only one libc defines, for static binaries
+    const SYMBOLS: &[(&str, Libc)] = &[
+        ("__libc_start_call_main", Libc::Glibc),
+        ("_dl_relocate_static_pie", Libc::Glibc),
+        ("__libc_csu_init", Libc::Glibc),
+        ("__init_libc", Libc::Musl),
+        ("__libc_start_init", Libc::Musl),
+        ("__uClibc_main", Libc::Uclibc),
+        ("__libc_init", Libc::Bionic),
+    ];
+    let syms = file.read_section_syms();
+    for (name, libc) in SYMBOLS {
+        if syms.iter().any(|sym| sym.name == *name) {
+            votes.push((*libc, format!("defines or uses {}", name)));
+        }
+    }
+
+    let versions = glibc_versions(file);
+    if let Some(newest) = versions.last() {
+        votes.push((Libc::Glibc, format!("GLIBC_{} symbol versions", newest)));
+        linkage.libc_version = Some(format!(">= {}", newest));
+    }
+    if let Some(release) = file
+        .section_by_name(".rodata")
+        .and_then(|sh| glibc_release(&sh.data))
+    {
+        votes.push((Libc::Glibc, format!("glibc release string {}", release)));
+        linkage.libc_version = Some(release);
+    }
+
+    // The first hint is the most specific one (interpreter, then DT_NEEDED, then the rest)
+    linkage.libc = votes.first().map(|(libc, _)| *libc);
+    linkage.evidence = votes
+        .into_iter()
+        .filter(|(libc, _)| Some(*libc) == linkage.libc)
+        .map(|(_, why)| why)
+        .collect();
+    if linkage.libc != Some(Libc::Glibc) {
+        linkage.libc_version = None;
+    }
+}
+
+// GLIBC_x.y version names referenced by the file, oldest first
+fn glibc_versions(file: &FileHeader) -> Vec<String> {
+    let dynstr = match file.section_by_name(".dynstr") {
+        Some(sh) => &sh.data[..],
+        None => match file
+            .dynamic_entry(DynamicTag::StrTab)
+            .and_then(|addr| file.bytes_at(addr))
+        {
+            Some(strtab) => strtab,
+            None => return Vec::new(),
+        },
+    };
+    let parse = |v: &str| -> Vec<u32> { v.split('.').filter_map(|n| n.parse().ok()).collect() };
+    let mut versions: Vec<String> = dynstr
+        .split(|&b| b == 0)
+        .filter_map(|s| std::str::from_utf8(s).ok()?.strip_prefix("GLIBC_"))
+        .filter(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+        .collect();
+    versions.sort_by_key(|v| parse(v));
+    versions.dedup();
+    versions
+}
+
+// Static glibc carries "GNU C Library (...) stable release version 2.36." for `ld.so --version`
+fn glibc_release(rodata: &[u8]) -> Option<String> {
+    let needle = GLIBC_RELEASE.as_bytes();
+    let start = rodata.windows(needle.len()).position(|w| w == needle)? + needle.len();
+    let version: String = rodata[start..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit() || **b == b'.')
+        .map(|&b| b as char)
+        .collect();
+    match version.trim_end_matches('.') {
+        "" => None,
+        version => Some(version.to_string()),
+    }
+}
+
+impl Linkage {
+    pub fn table(&self, path: &str) -> Table {
+        let libc = match (self.libc, &self.libc_version) {
+            (Some(libc), Some(version)) => format!("{} {}", libc, version),
+            (Some(libc), None) => libc.to_string(),
+            (None, _) => "unknown".into(),
+        };
+        Table {
+            header: format!("Linkage of {}", path),
+            labels: vec![
+                "Kind".into(),
+                "Interpreter".into(),
+                "Libc".into(),
+                "Evidence".into(),
+            ],
+            rows: vec![vec![
+                self.kind.to_string(),
+                self.interpreter.clone().unwrap_or_else(|| "-".into()),
+                libc,
+                self.evidence.join(", "),
+            ]],
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Relocatable => write!(f, "relocatable object"),
+            Kind::Static => write!(f, "static"),
+            Kind::StaticPie => write!(f, "static-pie"),
+            Kind::Dynamic => write!(f, "dynamic"),
+            Kind::SharedObject => write!(f, "shared object"),
+        }
+    }
+}
+
+impl fmt::Display for Libc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Libc::Glibc => write!(f, "glibc"),
+            Libc::Musl => write!(f, "musl"),
+            Libc::Uclibc => write!(f, "uClibc"),
+            Libc::Bionic => write!(f, "bionic"),
+        }
+    }
+}
@@ -0,0 +1,2613 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    fs,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use delf::{
+    detect::Class,
+    reloc::{Term, Terms},
+    strtab::Interner,
+    types::*,
+    FileHeader, RelaReadError,
+};
+use region::Protection;
+
+use crate::{
+    deps,
+    dig::{Origin, OriginMap},
+    image::{self, Segment, PAGE_SIZE},
+    lazy,
+    progress::Progress,
+    record::{self, Placement, Recording},
+    rendezvous,
+    space::{AddressSpace, Local},
+    tables::Table,
+    tls,
+    trace_relro::{Phase, Timeline},
+};
+
+// Still reachable as loader::LoadError for existing embedders
+pub use crate::error::LoadError;
+
+pub const DEFAULT_BASE: u64 = 0x400000;
+// Every supported relocation but Copy writes one 64-bit word
+const SLOT_SIZE: u64 = 8;
+// Far above what real programs map, far below what a forged mem_size can claim
+pub const DEFAULT_MAX_MAPPED: u64 = 4 << 30;
+pub const DEFAULT_MAX_OBJECTS: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    // Validate every relocation slot against the object's segment map before writing to it, to
+    // catch loader bugs before they corrupt a neighbouring mapping
+    pub check_relocations: bool,
+    // Runtime address to report every loader write to, to find out which phase set or clobbered
+    // the value there
+    pub watch: Option<u64>,
+    // Caps on the bytes mapped for all objects together and on how many objects are loaded, so
+    // hostile headers get an error instead of exhausting the address space
+    pub max_mapped: u64,
+    pub max_objects: usize,
+    // Apply relocations that write to read-only segments, lifting their protection while doing
+    // so. Off by default: a page that was writable once may have been patched by anyone.
+    pub allow_textrel: bool,
+    // Once an object is relocated, work its relocations out again from the file alone and
+    // compare the slots in memory against the result, to catch engine bugs where they happen
+    pub verify_relocations: bool,
+    // Bind every PLT slot at load time. Otherwise slots of objects in elk's own address space
+    // are bound on first call, unless the object itself asks for BIND_NOW.
+    pub bind_now: bool,
+    // Decisions of an earlier load to make again: objects go at the bases recorded for them,
+    // and symbol references and relocation slots get the recorded values, each difference
+    // reported as it is overridden
+    pub replay: Option<Arc<Recording>>,
+    // Where to log every protection change the loader makes, mappings included, for
+    // `elk trace-relro`
+    pub protections: Option<Arc<Timeline>>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            check_relocations: false,
+            watch: None,
+            max_mapped: DEFAULT_MAX_MAPPED,
+            max_objects: DEFAULT_MAX_OBJECTS,
+            allow_textrel: false,
+            verify_relocations: false,
+            bind_now: false,
+            replay: None,
+            protections: None,
+        }
+    }
+}
+
+// Name of the object passed to `load`/`load_at`
+pub const MAIN_OBJECT: &str = "main";
+
+// A link-map list, like glibc's Lmid_t. Symbol references only bind to definitions in their own
+// namespace, so two copies of a library can be loaded side by side without interposing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Namespace(pub usize);
+
+impl Namespace {
+    // Where the main program lives (LM_ID_BASE)
+    pub const BASE: Namespace = Namespace(0);
+}
+
+// An object loaded with `dlopen`/`dlmopen`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handle(usize);
+
+// How many relocations were applied, per type and per page of the image. PLT slots bound on first
+// call are counted apart, in `Process::lazy_binds`.
+#[derive(Default, Debug, Clone)]
+pub struct RelocStats {
+    pub by_type: BTreeMap<String, usize>,
+    pub by_page: BTreeMap<u64, usize>,
+}
+
+// Where an object ended up, for crash reports and anything else that symbolizes live addresses
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    // None for the main object, whose path only the caller knows
+    pub path: Option<String>,
+    // Load bias, added to link-time addresses
+    pub base: u64,
+    // Mapped range of the object's LOAD segments
+    pub start: u64,
+    pub end: u64,
+    pub build_id: Option<String>,
+}
+
+// One relocation as applied: the slot written and the value written to it
+#[derive(Debug, Clone, Copy)]
+pub struct AppliedReloc {
+    pub addr: u64,
+    pub typ: RelType,
+    pub value: u64,
+    // Bytes written at `addr`: a word, or the size of the object for copy relocations
+    pub len: u64,
+}
+
+// A constructor or destructor of a loaded object: its runtime address and the dynamic entry
+// it came from
+#[derive(Debug, Clone)]
+pub struct InitCall {
+    pub object: String,
+    pub list: &'static str,
+    pub addr: u64,
+}
+
+// What is left once everything is loaded and relocated, up to the jump to the entry point,
+// worked out beforehand: running it allocates nothing unless it announces itself, so a fork of
+// a multithreaded elk can run it too. `elk run` and each run of a snapshot both go through it.
+pub struct Startup {
+    pub thread_pointer: Option<u64>,
+    pub initializers: Vec<InitCall>,
+    pub finalizers: Vec<InitCall>,
+}
+
+impl Startup {
+    // The `fini` to hand the program: 0 with nothing to do at exit, otherwise run_fini, which
+    // runs the destructors and then reports `binds`. elk sets it up once, later calls hand out
+    // the first one's.
+    pub fn fini(&self, binds: Option<lazy::Counts>, quiet: bool) -> u64 {
+        if self.finalizers.is_empty() && binds.is_none() {
+            return 0;
+        }
+        let _ = AT_EXIT.set(AtExit {
+            finalizers: self.finalizers.clone(),
+            binds,
+            quiet,
+        });
+        run_fini as extern "C" fn() as usize as u64
+    }
+
+    // Hands the thread pointer to the program when it runs in elk's own thread, a child's being
+    // set by whoever drives it, then runs the constructors with the program's argc, argv and
+    // envp, as ld.so does
+    pub fn initialize(
+        &self,
+        space: &dyn AddressSpace,
+        argc: u64,
+        argv: u64,
+        envp: u64,
+        quiet: bool,
+    ) -> std::io::Result<()> {
+        if let Some(tp) = self.thread_pointer {
+            if !quiet {
+                println!("Setting thread pointer to {:#x}", tp);
+            }
+            if space.in_process() {
+                tls::enter(tp)?;
+            }
+        }
+        // From here on elk runs on the program's thread pointer
+        for init in &self.initializers {
+            if !quiet {
+                tls::as_host(|| {
+                    println!(
+                        "Running {} entry of {} at {:#x}",
+                        init.list, init.object, init.addr
+                    )
+                });
+            }
+            space.call(init.addr, &[argc, argv, envp])?;
+        }
+        Ok(())
+    }
+}
+
+// What run_fini calls, and whether to keep quiet about it
+struct AtExit {
+    finalizers: Vec<InitCall>,
+    // With --profile, the PLT binds to report once the program is done
+    binds: Option<lazy::Counts>,
+    quiet: bool,
+}
+
+static AT_EXIT: OnceLock<AtExit> = OnceLock::new();
+
+// Handed to the program as its `fini` function, which its libc registers with atexit like the
+// one ld.so passes
+extern "C" fn run_fini() {
+    let exit = match AT_EXIT.get() {
+        Some(exit) => exit,
+        None => return,
+    };
+    for fini in &exit.finalizers {
+        if !exit.quiet {
+            tls::as_host(|| {
+                println!(
+                    "Running {} entry of {} at {:#x}",
+                    fini.list, fini.object, fini.addr
+                )
+            });
+        }
+        let function: extern "C" fn() = unsafe { std::mem::transmute(fini.addr) };
+        function();
+    }
+    if let Some(binds) = &exit.binds {
+        tls::as_host(|| binds.table().print());
+    }
+}
+
+struct Object {
+    name: String,
+    path: Option<String>,
+    build_id: Option<String>,
+    base: u64,
+    // Page-aligned start of the lowest LOAD segment and end of the highest, in memory
+    start: u64,
+    end: u64,
+    exports: Arc<Exports>,
+    // Its block in static TLS, and the thread-locals it defines by offset in that block
+    tls: Option<tls::Module>,
+    tls_symbols: HashMap<Arc<str>, u64>,
+    // DT_INIT and the init array, then the fini array last to first and DT_FINI, as relocated.
+    // .ctors last to first and .dtors stand in for DT_INIT and DT_FINI when there are none.
+    init: Vec<(&'static str, u64)>,
+    fini: Vec<(&'static str, u64)>,
+    relocations: RelocStats,
+    applied: Vec<AppliedReloc>,
+    // What the object's symbol references bound to, keyed as record::binding spells them
+    bindings: BTreeMap<String, u64>,
+    // Words the loader wrote for reasons of its own rather than for a relocation, and what each
+    // is
+    written: Vec<(u64, &'static str)>,
+    // Dropping a segment unmaps it, so the object owns them for as long as it lives
+    segments: Vec<Segment>,
+    // Pages of PT_GNU_RELRO, made read-only by `seal_relro` once nothing writes to them anymore
+    relro: Option<Range<u64>>,
+    // Its entry in the lazy resolver table, when its PLT is bound on first call
+    plt: Option<lazy::Link>,
+    // Runtime address of the dynamic section, 0 without one
+    dynamic: u64,
+    // Its place in the debugger rendezvous, once `announce`d
+    debug: Option<rendezvous::Entry>,
+}
+
+// What an object defines for others to bind to
+struct Exports {
+    namespace: Namespace,
+    // Default-version dynamic symbols, relocated
+    symbols: HashMap<Arc<str>, u64>,
+    // Every versioned definition by name, non-default ones included, with its version
+    versioned: HashMap<Arc<str>, Vec<(Arc<str>, u64)>>,
+}
+
+// The exports of every object in load order, as lazy PLT resolvers see them. Kept apart from the
+// object list: loads hold that list's lock while IFUNC resolvers run, and those may call through
+// a PLT.
+type Scope = RwLock<Vec<Arc<Exports>>>;
+
+// Where the resolver of a lazily bound PLT finds the relocation and symbol behind each entry,
+// as runtime addresses
+struct LazyPlt {
+    jmprel: u64,
+    symtab: u64,
+    strtab: u64,
+    // Version each dynamic symbol asks for, by symbol index
+    versions: Vec<Option<Arc<str>>>,
+}
+
+// Files mapped into an address space, elk's own unless loaded with `load_into`, relocated and
+// ready to jump into. Shareable between threads: lookups take a read lock on the object list,
+// loading and replacing take a write lock.
+pub struct Process {
+    pub base: u64,
+    space: Arc<dyn AddressSpace>,
+    objects: RwLock<Vec<Object>>,
+    // Shared with the lazy PLT resolvers, which look symbols up in it on first call
+    scope: Arc<Scope>,
+    // Filled in by those resolvers
+    binds: lazy::Counts,
+    // The main thread's TLS, for objects loaded with the program that have any
+    tls: Option<tls::Area>,
+    // The executable's preinit array, which runs ahead of every other constructor
+    preinit: Vec<u64>,
+    namespaces: AtomicUsize,
+    options: LoadOptions,
+    // Names the objects export, stored once however many objects export them
+    names: Mutex<Interner>,
+}
+
+// Compile-time check that embedders can share a Process between threads
+const _: fn() = || {
+    fn check<T: Send + Sync>() {}
+    check::<Process>();
+};
+
+impl Process {
+    // Position-dependent executables are pinned to their link address
+    pub fn default_base(file: &FileHeader) -> u64 {
+        match file.typ {
+            Type::Exec => 0,
+            _ => DEFAULT_BASE,
+        }
+    }
+
+    pub fn load(file: &FileHeader) -> Result<Self, LoadError> {
+        Self::load_at(file, Self::default_base(file))
+    }
+
+    pub fn load_at(file: &FileHeader, base: u64) -> Result<Self, LoadError> {
+        Self::load_with(file, base, LoadOptions::default())
+    }
+
+    // `options` also apply to everything opened later with `dlopen`/`dlmopen`
+    pub fn load_with(
+        file: &FileHeader,
+        base: u64,
+        options: LoadOptions,
+    ) -> Result<Self, LoadError> {
+        Self::load_with_libraries(file, base, options, &[])
+    }
+
+    // Maps `file` along with `libraries`, the objects `deps::objects` lists after it, each at
+    // its own base above the main image. Like ld.so, everything is mapped before anything is
+    // relocated, so every object binds against all of them, in load order: the main object,
+    // then the libraries breadth-first. TLS blocks are laid out in load order too.
+    pub fn load_with_libraries(
+        file: &FileHeader,
+        base: u64,
+        options: LoadOptions,
+        libraries: &[deps::Object],
+    ) -> Result<Self, LoadError> {
+        Self::load_into(Arc::new(Local), file, base, options, libraries)
+    }
+
+    // Like `load_with_libraries`, building the image in `space` instead of elk's own memory.
+    // Bases are still checked against elk's mappings, which a forked child shares.
+    pub fn load_into(
+        space: Arc<dyn AddressSpace>,
+        file: &FileHeader,
+        base: u64,
+        options: LoadOptions,
+        libraries: &[deps::Object],
+    ) -> Result<Self, LoadError> {
+        let floor = image_range(file).map_or(0, |image| image.end + base);
+        let names = Mutex::new(Interner::new());
+        let scope = Arc::new(Scope::default());
+        let binds = lazy::Counts::default();
+        let target = Target {
+            space: &space,
+            options: &options,
+            names: &names,
+            scope: &scope,
+            binds: &binds,
+        };
+        let files: Vec<_> = std::iter::once(file)
+            .chain(libraries.iter().map(|library| &library.file))
+            .collect();
+        let templates: Vec<_> = files.iter().map(|file| file.tls().copied()).collect();
+        let modules = tls::layout(&templates);
+        let mut objects = Vec::new();
+        let mut pending = Vec::new();
+        for (i, (&file, &module)) in files.iter().zip(&modules).enumerate() {
+            let (object, relocs) = match i.checked_sub(1).map(|i| &libraries[i]) {
+                None => map_object(
+                    target,
+                    MAIN_OBJECT,
+                    file,
+                    base,
+                    Namespace::BASE,
+                    module,
+                    &objects,
+                )?,
+                Some(library) => {
+                    let path = library.path.display().to_string();
+                    let lib_base = replayed_base(&options, &library.name, Namespace::BASE)
+                        .or_else(|| free_base(&objects, file, floor))
+                        .ok_or_else(|| LoadError::NoSpace(path.clone()))?;
+                    println!("Loading {} from {} at {:#x}", library.name, path, lib_base);
+                    let (mut object, relocs) = map_object(
+                        target,
+                        &library.name,
+                        file,
+                        lib_base,
+                        Namespace::BASE,
+                        module,
+                        &objects,
+                    )?;
+                    announce(&*space, &mut object, &path);
+                    object.path = Some(path);
+                    (object, relocs)
+                }
+            };
+            objects.push(object);
+            pending.push(relocs);
+        }
+        publish(&scope, &objects);
+        // Last-needed first, as ld.so does, so the data copy relocations copy out of libraries
+        // is relocated by then
+        for (at, (file, relocs)) in files.into_iter().zip(pending).enumerate().rev() {
+            relocate(target, file, &mut objects, at, relocs)?;
+        }
+        // Without an interpreter to run them, executables' startup code runs their own, as in
+        // static glibc programs
+        let preinit = match file.segments_of_type(SegmentType::Interp).next() {
+            Some(_) => dynamic_array(
+                &*space,
+                MAIN_OBJECT,
+                file,
+                base,
+                DynamicTag::PreinitArray,
+                DynamicTag::PreinitArraySz,
+            )?,
+            None => {
+                objects[0].init.clear();
+                objects[0].fini.clear();
+                Vec::new()
+            }
+        };
+        for object in &objects {
+            seal_relro(target, object)?;
+        }
+        // Initialized from the images as relocated
+        let present: Vec<_> = modules.iter().flatten().copied().collect();
+        let tls = match present.is_empty() {
+            true => None,
+            false => Some(tls_area(&*space, &objects, &present)?),
+        };
+        let process = Self {
+            base,
+            space,
+            objects: RwLock::new(objects),
+            scope,
+            binds,
+            tls,
+            preinit,
+            namespaces: AtomicUsize::new(1),
+            options,
+            names,
+        };
+        Ok(process)
+    }
+
+    // Constructors in the order the dynamic linker runs them: the executable's preinit array,
+    // then each object's DT_INIT and init array, libraries before the objects loading them and
+    // the executable last
+    pub fn initializers(&self) -> Vec<InitCall> {
+        let objects = self.objects();
+        let preinit = self.preinit.iter().map(|&addr| InitCall {
+            object: MAIN_OBJECT.to_string(),
+            list: "DT_PREINIT_ARRAY",
+            addr,
+        });
+        let init = objects.iter().rev().flat_map(|o| {
+            o.init.iter().map(move |&(list, addr)| InitCall {
+                object: o.name.clone(),
+                list,
+                addr,
+            })
+        });
+        preinit.chain(init).collect()
+    }
+
+    // Destructors in the order they run at exit, the reverse of the constructors'
+    pub fn finalizers(&self) -> Vec<InitCall> {
+        self.objects()
+            .iter()
+            .flat_map(|o| {
+                o.fini.iter().map(move |&(list, addr)| InitCall {
+                    object: o.name.clone(),
+                    list,
+                    addr,
+                })
+            })
+            .collect()
+    }
+
+    pub fn startup(&self) -> Startup {
+        Startup {
+            thread_pointer: self.thread_pointer(),
+            initializers: self.initializers(),
+            finalizers: self.finalizers(),
+        }
+    }
+
+    // Where the objects are mapped, for reading the image and calling into it
+    pub fn space(&self) -> &Arc<dyn AddressSpace> {
+        &self.space
+    }
+
+    // What to point %fs at before jumping to the program, if any of its objects use TLS
+    pub fn thread_pointer(&self) -> Option<u64> {
+        self.tls.as_ref().map(tls::Area::thread_pointer)
+    }
+
+    fn target(&self) -> Target<'_> {
+        Target {
+            space: &self.space,
+            options: &self.options,
+            names: &self.names,
+            scope: &self.scope,
+            binds: &self.binds,
+        }
+    }
+
+    // A panic while holding the lock leaves no half-updated state behind: objects are only
+    // pushed or swapped in once fully mapped
+    fn objects(&self) -> RwLockReadGuard<'_, Vec<Object>> {
+        self.objects.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn objects_mut(&self) -> RwLockWriteGuard<'_, Vec<Object>> {
+        self.objects.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // A fresh, empty namespace for `dlmopen` (LM_ID_NEWLM)
+    pub fn new_namespace(&self) -> Namespace {
+        Namespace(self.namespaces.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn dlopen(&self, path: &str) -> Result<Handle, LoadError> {
+        self.dlmopen(Namespace::BASE, path)
+    }
+
+    // Loads `path` into `namespace` at the next free address, binding its symbol references
+    // against objects of that namespace only. Opening the same file twice in one namespace
+    // returns the existing handle.
+    pub fn dlmopen(&self, namespace: Namespace, path: &str) -> Result<Handle, LoadError> {
+        let name = Path::new(path)
+            .file_name()
+            .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned());
+        // Held until the object is in the list, so concurrent loads can't pick the same range
+        let mut objects = self.objects_mut();
+        if let Some(index) = objects
+            .iter()
+            .position(|o| o.exports.namespace == namespace && o.name == name)
+        {
+            return Ok(Handle(index));
+        }
+        let input = crate::source::read(path).map_err(|e| LoadError::Open {
+            path: path.to_string(),
+            reason: e.to_string(),
+        })?;
+        let file = FileHeader::parse_or_describe(&input).map_err(|reason| LoadError::Parse {
+            object: path.to_string(),
+            reason,
+        })?;
+
+        let base = replayed_base(&self.options, &name, namespace)
+            .or_else(|| free_base(&objects, &file, 0))
+            .ok_or_else(|| LoadError::NoSpace(path.to_string()))?;
+        late_tls(path, &file)?;
+        let (mut object, relocs) =
+            map_object(self.target(), &name, &file, base, namespace, None, &objects)?;
+        announce(&*self.space, &mut object, path);
+        object.path = Some(path.to_string());
+        objects.push(object);
+        publish(&self.scope, &objects);
+        let at = objects.len() - 1;
+        if let Err(e) = relocate(self.target(), &file, &mut objects, at, relocs)
+            .and_then(|()| seal_relro(self.target(), &objects[at]))
+        {
+            objects.pop();
+            publish(&self.scope, &objects);
+            return Err(e);
+        }
+        Ok(Handle(at))
+    }
+
+    // Address of `name` as seen from `handle`: the object itself first, then the rest of its
+    // namespace in load order
+    pub fn dlsym(&self, handle: Handle, name: &str) -> Option<u64> {
+        let objects = self.objects();
+        let object = objects.get(handle.0)?;
+        object
+            .exports
+            .symbols
+            .get(name)
+            .copied()
+            .or_else(|| lookup(exports(&objects), object.exports.namespace, name, None))
+    }
+
+    pub fn lookup(&self, namespace: Namespace, name: &str) -> Option<u64> {
+        lookup(exports(&self.objects()), namespace, name, None)
+    }
+
+    // Where a link-time address of the file ended up in memory
+    pub fn addr(&self, vaddr: Addr) -> u64 {
+        vaddr.0 + self.base
+    }
+
+    // A handle on the counts of PLT entries bound on first call, which go on growing as the
+    // program runs
+    pub fn lazy_binds(&self) -> lazy::Counts {
+        self.binds.clone()
+    }
+
+    pub fn relocation_stats(&self) -> RelocStats {
+        let mut stats = RelocStats::default();
+        for object in self.objects().iter() {
+            stats.merge(&object.relocations);
+        }
+        stats
+    }
+
+    // Every relocation applied so far, across all objects in load order
+    pub fn relocation_log(&self) -> Vec<AppliedReloc> {
+        self.objects()
+            .iter()
+            .flat_map(|o| o.applied.iter().copied())
+            .collect()
+    }
+
+    // The decisions made loading `program` so far, for `LoadOptions::replay` to make again.
+    // PLT slots bound on first call are recorded as the loader left them, pointing at the PLT.
+    pub fn recording(&self, program: &str) -> Recording {
+        let objects = self
+            .objects()
+            .iter()
+            .map(|o| Placement {
+                name: o.name.clone(),
+                path: o.path.clone(),
+                build_id: o.build_id.clone(),
+                namespace: o.exports.namespace.0,
+                base: o.base,
+                bindings: o.bindings.clone(),
+                writes: o
+                    .applied
+                    .iter()
+                    .map(|reloc| record::Write {
+                        addr: reloc.addr,
+                        typ: format!("{:?}", reloc.typ),
+                        value: reloc.value,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Recording {
+            program: program.to_string(),
+            objects,
+        }
+    }
+
+    pub fn modules(&self) -> Vec<Module> {
+        self.objects()
+            .iter()
+            .map(|o| Module {
+                name: o.name.clone(),
+                path: o.path.clone(),
+                base: o.base,
+                start: o.start,
+                end: o.end,
+                build_id: o.build_id.clone(),
+            })
+            .collect()
+    }
+
+    // What every page mapped for an object should allow now that it is loaded, by object: its
+    // segment's flags, read-only where RELRO covers it
+    pub fn page_protections(&self) -> Vec<(String, u64, Protection)> {
+        let mut pages = Vec::new();
+        for object in self.objects().iter() {
+            for segment in &object.segments {
+                let range = segment.pages();
+                for page in (range.start..range.end).step_by(PAGE_SIZE as usize) {
+                    let protection = match &object.relro {
+                        Some(relro) if relro.contains(&page) => Protection::READ,
+                        _ => segment.protection(),
+                    };
+                    pages.push((object.name.clone(), page, protection));
+                }
+            }
+        }
+        pages
+    }
+
+    // Where each byte mapped for the objects and their TLS came from, as the loader left them:
+    // the file, zero-fill, padding, relocations and the loader's own writes. Writes made since,
+    // by lazy binding or the program itself, aren't in it.
+    pub fn origins(&self) -> OriginMap {
+        let mut map = OriginMap::new();
+        let objects = self.objects();
+        for object in objects.iter() {
+            let named = || object.name.clone();
+            for segment in &object.segments {
+                let copied = segment.start + segment.file_size;
+                let end = segment.start + segment.mem_size;
+                map.insert(
+                    segment.page_start..align_up(end, PAGE_SIZE),
+                    Origin::Padding { object: named() },
+                );
+                map.insert(
+                    segment.start..copied,
+                    Origin::File {
+                        object: named(),
+                        offset: segment.offset,
+                    },
+                );
+                map.insert(copied..end, Origin::Zero { object: named() });
+            }
+            for reloc in &object.applied {
+                let origin = Origin::Relocation {
+                    object: named(),
+                    typ: reloc.typ,
+                    slot: reloc.addr,
+                    value: reloc.value,
+                };
+                map.insert(reloc.addr..reloc.addr + reloc.len, origin);
+            }
+            for &(slot, what) in &object.written {
+                let origin = Origin::Loader {
+                    object: Some(named()),
+                    what,
+                };
+                map.insert(slot..slot + SLOT_SIZE, origin);
+            }
+        }
+        if let Some(area) = &self.tls {
+            let loader = |what| Origin::Loader { object: None, what };
+            let tp = area.thread_pointer();
+            map.insert(area.range(), loader("TLS padding"));
+            map.insert(area.dtv(), loader("the main thread's DTV"));
+            map.insert(tp..area.range().end, loader("the main thread's TCB"));
+            for object in objects.iter() {
+                let module = match &object.tls {
+                    Some(module) => module,
+                    None => continue,
+                };
+                let block = (tp as i64 + module.offset) as u64;
+                let copied = block + module.template.file_size;
+                let image = module.template.image.0 + object.base;
+                map.insert(
+                    block..copied,
+                    Origin::Tls {
+                        object: object.name.clone(),
+                        image: Some(image),
+                    },
+                );
+                map.insert(
+                    copied..block + module.template.mem_size,
+                    Origin::Tls {
+                        object: object.name.clone(),
+                        image: None,
+                    },
+                );
+            }
+        }
+        map
+    }
+
+    // The 8-byte word at a runtime address, if the whole of it is mapped
+    pub fn peek(&self, addr: u64) -> Option<u64> {
+        let objects = self.objects();
+        let mapped = objects
+            .iter()
+            .flat_map(|o| &o.segments)
+            .map(Segment::pages)
+            .any(|pages| pages.start <= addr && addr + SLOT_SIZE <= pages.end);
+        match mapped {
+            true => self.space.read_u64(addr).ok(),
+            false => None,
+        }
+    }
+
+    pub fn object_names(&self) -> Vec<String> {
+        self.objects().iter().map(|o| o.name.clone()).collect()
+    }
+
+    pub fn namespace_of(&self, handle: Handle) -> Option<Namespace> {
+        self.objects().get(handle.0).map(|o| o.exports.namespace)
+    }
+
+    // Lists the main object in the debugger rendezvous, under the path only the caller knows
+    pub fn announce_main(&self, path: &str) {
+        let mut objects = self.objects_mut();
+        if let Some(main) = objects.iter_mut().find(|o| o.name == MAIN_OBJECT) {
+            announce(&*self.space, main, path);
+        }
+    }
+
+    // Unmaps the object called `name` and maps the file at `new_path` in its place, at the same
+    // base so existing pointers into it stay meaningful. Its own relocations are re-applied, and
+    // the GOT and PLT slots of other objects that bound to the old version are pointed at the
+    // same symbols in the new one.
+    pub fn replace_object(&self, name: &str, new_path: &str) -> Result<(), LoadError> {
+        let input = crate::source::read(new_path).map_err(|e| LoadError::Open {
+            path: new_path.to_string(),
+            reason: e.to_string(),
+        })?;
+        let file = FileHeader::parse_or_describe(&input).map_err(|reason| LoadError::Parse {
+            object: new_path.to_string(),
+            reason,
+        })?;
+
+        let mut objects = self.objects_mut();
+        let index = objects
+            .iter()
+            .position(|o| o.name == name)
+            .ok_or_else(|| LoadError::UnknownObject(name.to_string()))?;
+        let (base, namespace) = (objects[index].base, objects[index].exports.namespace);
+        let old = objects[index].exports.clone();
+        let old_range = objects[index].start..objects[index].end;
+        late_tls(new_path, &file)?;
+        // Unmap the old version first, its range is likely the one the new version wants
+        drop(objects.remove(index));
+        let (mut object, relocs) =
+            map_object(self.target(), name, &file, base, namespace, None, &objects)?;
+        announce(&*self.space, &mut object, new_path);
+        object.path = Some(new_path.to_string());
+        objects.insert(index, object);
+        publish(&self.scope, &objects);
+        if let Err(e) = relocate(self.target(), &file, &mut objects, index, relocs)
+            .and_then(|()| seal_relro(self.target(), &objects[index]))
+        {
+            objects.remove(index);
+            publish(&self.scope, &objects);
+            return Err(e);
+        }
+        redirect(self.target(), &mut objects, index, &old, old_range)
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if let Some(area) = &self.tls {
+            let _ = self.space.unmap(area.start(), area.bytes().len() as u64);
+        }
+    }
+}
+
+// Points the slots of other objects that hold addresses in `old_range`, where `old` was mapped
+// before `objects[at]` replaced it, at the same symbols of the replacement, addends kept. Slots
+// bound lazily are found by what they hold, so only ones bound by then are redirected; later
+// binds go through the scope and find the replacement themselves.
+fn redirect(
+    target: Target,
+    objects: &mut [Object],
+    at: usize,
+    old: &Exports,
+    old_range: Range<u64>,
+) -> Result<(), LoadError> {
+    let new = objects[at].exports.clone();
+    let mut defined: Vec<_> = old
+        .symbols
+        .iter()
+        .map(|(name, &addr)| (addr, name))
+        .collect();
+    defined.sort();
+    for (i, object) in objects.iter_mut().enumerate() {
+        if i == at || object.exports.namespace != new.namespace {
+            continue;
+        }
+        let name = object.name.clone();
+        for reloc in &mut object.applied {
+            if !matches!(
+                reloc.typ,
+                RelType::GlobalData | RelType::JumpSlot | RelType::Abs64
+            ) {
+                continue;
+            }
+            let current =
+                target
+                    .space
+                    .read_u64(reloc.addr)
+                    .map_err(|source| LoadError::Memory {
+                        object: name.clone(),
+                        addr: reloc.addr,
+                        len: SLOT_SIZE as usize,
+                        source,
+                    })?;
+            if !old_range.contains(&current) {
+                continue;
+            }
+            // The symbol at or right below what the slot holds
+            let (addr, symbol) = match defined.iter().rev().find(|(addr, _)| *addr <= current) {
+                Some(&(addr, symbol)) => (addr, symbol),
+                None => continue,
+            };
+            let value =
+                new.symbols
+                    .get(symbol)
+                    .copied()
+                    .ok_or_else(|| LoadError::SymbolNotFound {
+                        name: symbol.to_string(),
+                        object: name.clone(),
+                        namespace: new.namespace.0,
+                    })?
+                    + (current - addr);
+            let sealed = object
+                .relro
+                .as_ref()
+                .is_some_and(|r| r.contains(&reloc.addr));
+            let segment = object.segments.iter().find(|s| s.contains(reloc.addr));
+            match segment {
+                Some(segment) if sealed || !segment.is_writable() => {
+                    let protection = match sealed {
+                        true => Protection::READ,
+                        false => segment.protection(),
+                    };
+                    write_text_slot(
+                        &**target.space,
+                        target.options,
+                        &object.name,
+                        reloc.addr,
+                        value,
+                        protection,
+                    )?
+                }
+                _ => write_slot(&**target.space, &object.name, reloc.addr, value)?,
+            }
+            reloc.value = value;
+            for bound in object.bindings.values_mut().filter(|v| **v == current) {
+                *bound = value;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Maps the main thread's TLS area in `space` and fills in the blocks of `objects`, initialized
+// from their images as relocated
+fn tls_area(
+    space: &dyn AddressSpace,
+    objects: &[Object],
+    modules: &[tls::Module],
+) -> Result<tls::Area, LoadError> {
+    let len = tls::Area::size(modules);
+    let tls_error = |reason: std::io::Error| LoadError::Tls {
+        object: MAIN_OBJECT.to_string(),
+        reason: reason.to_string(),
+    };
+    let mut area = tls::Area::new(modules, space.map(None, len as u64).map_err(tls_error)?);
+    for object in objects {
+        if let Some(module) = &object.tls {
+            let image = module.template.image.0 + object.base;
+            let len = module.template.file_size as usize;
+            let block = area.block(module);
+            space
+                .read(image, &mut block[..len])
+                .map_err(|source| LoadError::Memory {
+                    object: object.name.clone(),
+                    addr: image,
+                    len,
+                    source,
+                })?;
+        }
+    }
+    space.write(area.start(), area.bytes()).map_err(tls_error)?;
+    Ok(area)
+}
+
+// Static TLS and the DTV are laid out once, for the objects loaded with the program, with no
+// room for blocks of objects loaded later
+fn late_tls(path: &str, file: &FileHeader) -> Result<(), LoadError> {
+    match file.tls() {
+        Some(_) => Err(LoadError::Tls {
+            object: path.to_string(),
+            reason: "only objects loaded with the program can have thread-locals".into(),
+        }),
+        None => Ok(()),
+    }
+}
+
+// Where the replayed load put the object called `name` in `namespace`
+fn replayed_base(options: &LoadOptions, name: &str, namespace: Namespace) -> Option<u64> {
+    let base = options.replay.as_ref()?.object(name, namespace.0)?.base;
+    println!("Replaying {} at {:#x}", name, base);
+    Some(base)
+}
+
+// The value the replayed load gave to `what`, in place of `value` if they differ
+fn replayed(object: &str, what: &str, value: u64, recorded: Option<u64>) -> u64 {
+    match recorded {
+        Some(recorded) if recorded != value => {
+            eprintln!(
+                "replay: {} {} is {:#x}, recorded as {:#x}; using the recorded value",
+                object, what, value, recorded
+            );
+            recorded
+        }
+        _ => value,
+    }
+}
+
+// Base putting `file` above every loaded object, clear of anything else mapped
+// Nothing is placed below `floor`
+fn free_base(objects: &[Object], file: &FileHeader, floor: u64) -> Option<u64> {
+    let image = image_range(file)?;
+    let len = image.end - image.start;
+    let mut start = objects
+        .iter()
+        .map(|o| o.end)
+        .max()
+        .unwrap_or(DEFAULT_BASE)
+        .max(floor);
+    start = align_up(start, PAGE_SIZE) + PAGE_SIZE;
+    let maps = system_mappings();
+    while let Some((range, _)) = maps
+        .iter()
+        .find(|(range, _)| range.start < start + len && start < range.end)
+    {
+        start = align_up(range.end, PAGE_SIZE) + PAGE_SIZE;
+    }
+    Some(start - image.start)
+}
+
+fn write_slot(
+    space: &dyn AddressSpace,
+    name: &str,
+    slot: u64,
+    value: u64,
+) -> Result<(), LoadError> {
+    space
+        .write_u64(slot, value)
+        .map_err(|source| LoadError::Memory {
+            object: name.to_string(),
+            addr: slot,
+            len: SLOT_SIZE as usize,
+            source,
+        })
+}
+
+// A slot in an already protected text segment: writable for the one write, never writable and
+// executable at once
+fn write_text_slot(
+    space: &dyn AddressSpace,
+    options: &LoadOptions,
+    name: &str,
+    slot: u64,
+    value: u64,
+    protection: Protection,
+) -> Result<(), LoadError> {
+    let protect_error = |source| LoadError::Protect {
+        object: name.to_string(),
+        addr: slot,
+        len: SLOT_SIZE as usize,
+        source,
+    };
+    space
+        .protect(slot, SLOT_SIZE, Protection::READ_WRITE)
+        .map_err(protect_error)?;
+    log_protection(
+        options,
+        Phase::SlotWrite,
+        name,
+        slot..slot + SLOT_SIZE,
+        Protection::READ_WRITE,
+    );
+    write_slot(space, name, slot, value)?;
+    space
+        .protect(slot, SLOT_SIZE, protection)
+        .map_err(protect_error)?;
+    log_protection(
+        options,
+        Phase::SlotRestore,
+        name,
+        slot..slot + SLOT_SIZE,
+        protection,
+    );
+    Ok(())
+}
+
+fn log_protection(
+    options: &LoadOptions,
+    phase: Phase,
+    name: &str,
+    range: Range<u64>,
+    protection: Protection,
+) {
+    if let Some(timeline) = &options.protections {
+        timeline.record(phase, name, range, protection);
+    }
+}
+
+// The NUL-terminated string at `addr` in elk's own memory
+fn cstr(addr: u64) -> std::io::Result<String> {
+    let mut bytes = Vec::new();
+    loop {
+        let mut byte = [0];
+        Local.read(addr + bytes.len() as u64, &mut byte)?;
+        match byte[0] {
+            0 => return Ok(String::from_utf8_lossy(&bytes).into_owned()),
+            b => bytes.push(b),
+        }
+    }
+}
+
+// Lists `object` in the debugger rendezvous as loaded from `path`. Only a debugger attached to
+// elk can read the list, so objects mapped anywhere else are left out.
+fn announce(space: &dyn AddressSpace, object: &mut Object, path: &str) {
+    if space.in_process() {
+        object.debug = Some(rendezvous::Entry::new(object.base, path, object.dynamic));
+    }
+}
+
+// Makes the RELRO pages of `object` read-only. Like ld.so, the range is rounded down to whole
+// pages at both ends: the partial page at the end holds writable data too.
+fn seal_relro(target: Target, object: &Object) -> Result<(), LoadError> {
+    let range = match &object.relro {
+        Some(range) if range.end > range.start => range,
+        _ => return Ok(()),
+    };
+    target
+        .space
+        .protect(range.start, range.end - range.start, Protection::READ)
+        .map_err(|source| LoadError::Protect {
+            object: object.name.clone(),
+            addr: range.start,
+            len: (range.end - range.start) as usize,
+            source,
+        })?;
+    log_protection(
+        target.options,
+        Phase::Relro,
+        &object.name,
+        range.clone(),
+        Protection::READ,
+    );
+    Ok(())
+}
+
+// Whether `slot`, a link-time address, lies in a LOAD segment mapped without write permission
+fn read_only_segment(file: &FileHeader, slot: u64) -> bool {
+    file.segments_of_type(SegmentType::Load)
+        .find(|ph| ph.mem_range().contains(&Addr(slot)))
+        .is_some_and(|ph| !ph.flags.contains(SegmentFlags::Write))
+}
+
+// A reference asking for a version (`memcpy@GLIBC_2.14`) binds to the first object in scope
+// defining `name` either under that version or without one, as ld.so does for libraries without
+// version tables. Objects defining it under other versions only are passed over.
+fn lookup<'a>(
+    scope: impl Iterator<Item = &'a Exports>,
+    namespace: Namespace,
+    name: &str,
+    version: Option<&str>,
+) -> Option<u64> {
+    scope.filter(|e| e.namespace == namespace).find_map(|e| {
+        match (version, e.versioned.get(name)) {
+            (Some(version), Some(defined)) => defined
+                .iter()
+                .find(|(v, _)| &**v == version)
+                .map(|&(_, addr)| addr),
+            _ => e.symbols.get(name).copied(),
+        }
+    })
+}
+
+// The versions `name` is defined under in scope, for when none is the one asked for
+fn versions_of<'a>(
+    scope: impl Iterator<Item = &'a Exports>,
+    namespace: Namespace,
+    name: &str,
+) -> Vec<String> {
+    scope
+        .filter(|e| e.namespace == namespace)
+        .flat_map(|e| e.versioned.get(name).into_iter().flatten())
+        .map(|(version, _)| version.to_string())
+        .collect()
+}
+
+fn exports(objects: &[Object]) -> impl Iterator<Item = &Exports> {
+    objects.iter().map(|o| &*o.exports)
+}
+
+// Makes the exports of `objects` what the lazy PLT resolvers see
+fn publish(scope: &Scope, objects: &[Object]) {
+    *scope.write().unwrap_or_else(PoisonError::into_inner) =
+        objects.iter().map(|o| o.exports.clone()).collect();
+}
+
+// The module defining thread-local `name`, and its offset in that module's block
+fn lookup_tls(objects: &[Object], namespace: Namespace, name: &str) -> Option<(tls::Module, u64)> {
+    objects
+        .iter()
+        .filter(|o| o.exports.namespace == namespace)
+        .find_map(|o| Some((o.tls?, *o.tls_symbols.get(name)?)))
+}
+
+// Dynamic symbol table of `file`, indexed like the relocations referencing it
+fn dynamic_symbols(file: &FileHeader) -> Vec<Symbol> {
+    file.section_headers
+        .iter()
+        .position(|sh| sh.typ == SectionType::DynSym)
+        .map(|index| file.symbols_in(index))
+        .unwrap_or_default()
+}
+
+// Thread-locals are left out: their values are offsets in a TLS block, not addresses
+fn exported_symbols(syms: &[Symbol], base: u64, names: &mut Interner) -> HashMap<Arc<str>, u64> {
+    exported(syms)
+        .filter(|sym| sym.typ() != Some(SymType::Tls))
+        .filter_map(|sym| Some((names.intern(&sym.name), sym.address(base)?)))
+        .collect()
+}
+
+// Versions defined here, not ones needed from elsewhere, and hidden ones too: a reference asking
+// for `_res@GLIBC_2.2.5` by version still binds to it
+fn versioned_symbols(
+    syms: &[Symbol],
+    base: u64,
+    names: &mut Interner,
+) -> HashMap<Arc<str>, Vec<(Arc<str>, u64)>> {
+    let mut versioned: HashMap<_, Vec<_>> = HashMap::new();
+    for sym in syms
+        .iter()
+        .filter(|sym| sym.is_exported() && sym.typ() != Some(SymType::Tls))
+    {
+        let version = match sym.version.as_ref().filter(|v| v.file.is_none()) {
+            Some(version) => version,
+            None => continue,
+        };
+        if let Some(addr) = sym.address(base) {
+            versioned
+                .entry(names.intern(&sym.name))
+                .or_default()
+                .push((names.intern(&version.name), addr));
+        }
+    }
+    versioned
+}
+
+fn exported_tls(syms: &[Symbol], names: &mut Interner) -> HashMap<Arc<str>, u64> {
+    exported(syms)
+        .filter(|sym| sym.typ() == Some(SymType::Tls))
+        .map(|sym| (names.intern(&sym.name), sym.value.0))
+        .collect()
+}
+
+fn exported(syms: &[Symbol]) -> impl Iterator<Item = &Symbol> {
+    syms.iter()
+        .filter(|sym| sym.is_exported())
+        .filter(|sym| !sym.version.as_ref().is_some_and(|v| v.hidden))
+}
+
+// Where map_object maps an object, and how
+#[derive(Clone, Copy)]
+struct Target<'a> {
+    space: &'a Arc<dyn AddressSpace>,
+    options: &'a LoadOptions,
+    names: &'a Mutex<Interner>,
+    scope: &'a Arc<Scope>,
+    binds: &'a lazy::Counts,
+}
+
+// What `relocate` needs of an object's file, read while mapping it so a file whose relocations
+// can't be applied is refused before anything is mapped for it
+struct Pending {
+    syms: Vec<Symbol>,
+    relocs: Vec<RelaEntry>,
+    // Version each dynamic symbol asks for, by symbol index
+    versions: Vec<Option<Arc<str>>>,
+    // Whether its PLT slots are left for `lazy` to bind
+    lazy: bool,
+}
+
+// Maps the LOAD segments of `file` at `base`, writable until `relocate` is done with them.
+// `scope` holds the objects already loaded, which the limits count in. `tls` is the object's
+// block in static TLS, if it has thread-locals.
+fn map_object(
+    target: Target,
+    name: &str,
+    file: &FileHeader,
+    base: u64,
+    namespace: Namespace,
+    tls: Option<tls::Module>,
+    scope: &[Object],
+) -> Result<(Object, Pending), LoadError> {
+    let Target {
+        space,
+        options,
+        names,
+        ..
+    } = target;
+    if file.class != Class::Elf64 {
+        return Err(LoadError::Class(name.to_string(), file.class));
+    }
+    if file.machine != Machine::X86_64 {
+        return Err(LoadError::Machine(name.to_string(), file.machine));
+    }
+    // Every mem_range below relies on this, and copying the TLS image on it being inside
+    for ph in file
+        .program_headers
+        .iter()
+        .filter(|ph| matches!(ph.typ, SegmentType::Load | SegmentType::TLS))
+    {
+        ph.check_sizes().map_err(|source| LoadError::Segment {
+            object: name.to_string(),
+            addr: ph.virt_addr.0,
+            source,
+        })?;
+    }
+    if let Some(template) = file.tls() {
+        if !in_load_segment(file, template.image.0, template.file_size) {
+            return Err(LoadError::Tls {
+                object: name.to_string(),
+                reason: format!(
+                    "image at {:?} is outside every LOAD segment",
+                    template.image
+                ),
+            });
+        }
+    }
+    if scope.len() >= options.max_objects {
+        return Err(LoadError::ObjectLimit(
+            name.to_string(),
+            options.max_objects,
+        ));
+    }
+    let mapped = scope
+        .iter()
+        .flat_map(|o| &o.segments)
+        .map(|segment| segment.pages().end - segment.pages().start)
+        .sum::<u64>();
+    if mapped_size(file)
+        .and_then(|size| size.checked_add(mapped))
+        .is_none_or(|total| total > options.max_mapped)
+    {
+        return Err(LoadError::MappedLimit(name.to_string(), options.max_mapped));
+    }
+    validate_base(file, base)?;
+    let replay = options
+        .replay
+        .as_ref()
+        .and_then(|r| r.object(name, namespace.0));
+    if let Some(placement) = replay.filter(|p| p.build_id != file.build_id()) {
+        eprintln!(
+            "replay: {} has build ID {:?}, recorded as {:?}",
+            name,
+            file.build_id(),
+            placement.build_id
+        );
+    }
+    let parse_error = |e: RelaReadError| LoadError::Parse {
+        object: name.to_string(),
+        reason: e.to_string(),
+    };
+    // Static and fully prelinked objects have no relocation table at all
+    let mut rela_entries = match file.read_rela_entries() {
+        Ok(entries) => entries,
+        Err(RelaReadError::RelaNotFound) => Vec::new(),
+        Err(e) => return Err(parse_error(e)),
+    };
+    rela_entries.extend(file.read_plt_rela_entries().map_err(parse_error)?);
+    // Types the loader has no formula for, TLS descriptors for instance, aren't skipped: that
+    // would leave slots unset that IFUNC resolvers read as soon as they are called
+    if let Some(reloc) = rela_entries
+        .iter()
+        .find(|reloc| matches!(reloc.typ, RelType::Unknown(_)))
+    {
+        return Err(LoadError::Reloc {
+            object: name.to_string(),
+            slot: reloc.offset.0 + base,
+            reason: format!("{:?} is a type the loader can't apply", reloc.typ),
+        });
+    }
+    // Nor are entries past one the parser couldn't read
+    let total: u64 = file
+        .dynamic_entries(DynamicTag::RelaSz)
+        .chain(file.dynamic_entries(DynamicTag::PltRelSz))
+        .map(|size| size.0 / RelaEntry::SIZE as u64)
+        .sum();
+    if (rela_entries.len() as u64) < total {
+        return Err(LoadError::Parse {
+            object: name.to_string(),
+            reason: format!(
+                "only {} of {} relocations could be read",
+                rela_entries.len(),
+                total
+            ),
+        });
+    }
+    let text_relocs = rela_entries
+        .iter()
+        .filter(|reloc| read_only_segment(file, reloc.offset.0))
+        .count();
+    if text_relocs > 0 && !options.allow_textrel {
+        return Err(LoadError::TextRel(name.to_string(), text_relocs));
+    }
+    // Bound on first call through `lazy`'s trampoline, which only code in elk's own address
+    // space can reach
+    let lazy = !options.bind_now
+        && space.in_process()
+        && !file.binds_now()
+        && [
+            DynamicTag::PltGot,
+            DynamicTag::JmpRel,
+            DynamicTag::SymTab,
+            DynamicTag::StrTab,
+        ]
+        .iter()
+        .all(|&tag| file.dynamic_entry(tag).is_some());
+    let syms = dynamic_symbols(file);
+    let (symbols, versioned, tls_symbols, versions) = {
+        let mut names = names.lock().unwrap_or_else(PoisonError::into_inner);
+        let versions: Vec<_> = syms
+            .iter()
+            .map(|sym| sym.version.as_ref().map(|v| names.intern(&v.name)))
+            .collect();
+        (
+            exported_symbols(&syms, base, &mut names),
+            versioned_symbols(&syms, base, &mut names),
+            exported_tls(&syms, &mut names),
+            versions,
+        )
+    };
+
+    let mut segments = Vec::new();
+    for (index, ph) in file.program_headers.iter().enumerate() {
+        if ph.typ != SegmentType::Load || ph.mem_size.0 == 0 {
+            continue;
+        }
+        let pages = image::pages(ph, base);
+        println!(
+            "Mapping segment at {:#x?} with {:?}. Address: {:#x}",
+            pages, ph.flags, pages.start
+        );
+        println!("Copy segment data to memory region...");
+        let segment = Segment::map(index, ph, base, space).map_err(|source| LoadError::Map {
+            object: name.to_string(),
+            addr: pages.start,
+            len: (pages.end - pages.start) as usize,
+            source,
+        })?;
+        log_protection(
+            options,
+            Phase::Map,
+            name,
+            pages.clone(),
+            Protection::READ_WRITE,
+        );
+        if let Some(watch) = options.watch {
+            let copied = segment.start..segment.start + ph.data.len() as u64;
+            let zeroed = copied.end..segment.range().end;
+            if copied.contains(&watch) {
+                let phase = format!("copying {}'s segment at {:#x}", name, ph.virt_addr.0);
+                report_watch(&**space, watch, &phase, copied.end);
+            } else if zeroed.contains(&watch) {
+                let phase = format!("zero-filling {}'s segment at {:#x}", name, ph.virt_addr.0);
+                report_watch(&**space, watch, &phase, zeroed.end);
+            }
+        }
+        segments.push(segment);
+    }
+
+    let relro = file
+        .segments_of_type(SegmentType::GnuRelRo)
+        .find(|ph| ph.check_sizes().is_ok())
+        .map(|ph| {
+            let range = ph.mem_range();
+            (range.start.0 + base) & !(PAGE_SIZE - 1)..(range.end.0 + base) & !(PAGE_SIZE - 1)
+        });
+    let dynamic = file.segments_of_type(SegmentType::Dynamic).next();
+    let image = image_range(file).unwrap_or(0..0);
+    let object = Object {
+        name: name.to_string(),
+        path: None,
+        build_id: file.build_id(),
+        base,
+        start: image.start + base,
+        end: image.end + base,
+        exports: Arc::new(Exports {
+            namespace,
+            symbols,
+            versioned,
+        }),
+        tls,
+        tls_symbols,
+        init: Vec::new(),
+        fini: Vec::new(),
+        relocations: RelocStats::default(),
+        applied: Vec::new(),
+        bindings: BTreeMap::new(),
+        written: Vec::new(),
+        segments,
+        relro,
+        plt: None,
+        dynamic: dynamic.map_or(0, |ph| ph.virt_addr.0 + base),
+        debug: None,
+    };
+    let pending = Pending {
+        syms,
+        relocs: rela_entries,
+        versions,
+        lazy,
+    };
+    Ok((object, pending))
+}
+
+// Applies the relocations of `objects[at]`, mapped from `file`, and gives its segments their
+// own permissions. Symbol references bind to the first definition in the object's namespace, in
+// the order of `objects`, the object itself included, as in the dynamic linker's global scope.
+fn relocate(
+    target: Target,
+    file: &FileHeader,
+    objects: &mut [Object],
+    at: usize,
+    pending: Pending,
+) -> Result<(), LoadError> {
+    let Target {
+        space,
+        options,
+        scope: shared,
+        binds,
+        ..
+    } = target;
+    let Pending {
+        syms,
+        relocs: rela_entries,
+        versions,
+        lazy,
+    } = pending;
+    let scope: &[Object] = objects;
+    let object = &scope[at];
+    let (base, namespace, tls) = (object.base, object.exports.namespace, object.tls);
+    let name = object.name.clone();
+    let name = name.as_str();
+    let replay = options
+        .replay
+        .as_ref()
+        .and_then(|r| r.object(name, namespace.0));
+    let recorded_writes: HashMap<u64, u64> = replay
+        .iter()
+        .flat_map(|p| &p.writes)
+        .map(|write| (write.addr, write.value))
+        .collect();
+    // Filled in by `resolve`, which the relocation loop only borrows
+    let bindings = RefCell::new(BTreeMap::new());
+    let textrel = file.has_textrel();
+    // Copy relocations look past the executable making them, like ld.so's
+    // ELF_RTYPE_CLASS_COPY: the definition to copy from is the next one in scope
+    let resolve = |index: u32, copy: bool| -> Result<u64, LoadError> {
+        let sym = match syms.get(index as usize) {
+            Some(sym) => sym,
+            None => {
+                return Err(LoadError::SymbolNotFound {
+                    name: format!("#{}", index),
+                    object: name.to_string(),
+                    namespace: namespace.0,
+                })
+            }
+        };
+        // Local and hidden definitions bind within the object and never show up in any scope
+        if !sym.is_exported() {
+            if let Some(addr) = sym.address(base) {
+                return Ok(addr);
+            }
+        }
+        // Definitions under a non-default version (`_res@GLIBC_2.2.5`) aren't exported by name,
+        // but references from the defining object still bind to them
+        let version = sym.version.as_ref().map(|v| v.name.as_str());
+        let found = match copy {
+            true => lookup(exports(&scope[..at]), namespace, &sym.name, version)
+                .or_else(|| lookup(exports(&scope[at + 1..]), namespace, &sym.name, version)),
+            false => lookup(exports(scope), namespace, &sym.name, version),
+        };
+        let addr = match found.or_else(|| sym.address(base)) {
+            Some(addr) => addr,
+            None if sym.bind() == Some(SymBind::Weak) => 0,
+            None => {
+                let defined = versions_of(exports(scope), namespace, &sym.name);
+                return Err(match version {
+                    Some(version) if !defined.is_empty() => LoadError::VersionNotFound {
+                        name: sym.name.clone(),
+                        version: version.to_string(),
+                        object: name.to_string(),
+                        namespace: namespace.0,
+                        defined: defined.join(", "),
+                    },
+                    _ => LoadError::SymbolNotFound {
+                        name: sym.name.clone(),
+                        object: name.to_string(),
+                        namespace: namespace.0,
+                    },
+                });
+            }
+        };
+        let key = record::binding(&sym.name, version);
+        let recorded = replay.and_then(|p| p.bindings.get(&key).copied());
+        let addr = replayed(name, &key, addr, recorded);
+        bindings.borrow_mut().insert(key, addr);
+        Ok(addr)
+    };
+
+    // Symbol 0 stands for the object's own block, as in the local-dynamic model
+    let resolve_tls = |index: u32| -> Result<(tls::Module, u64), LoadError> {
+        let own = |offset| {
+            tls.map(|module| (module, offset))
+                .ok_or_else(|| LoadError::Tls {
+                    object: name.to_string(),
+                    reason: "TLS relocations but no PT_TLS segment".into(),
+                })
+        };
+        let sym = match (index, syms.get(index as usize)) {
+            (0, _) => return own(0),
+            (_, Some(sym)) => sym,
+            (_, None) => {
+                return Err(LoadError::SymbolNotFound {
+                    name: format!("#{}", index),
+                    object: name.to_string(),
+                    namespace: namespace.0,
+                })
+            }
+        };
+        if !sym.is_exported() && sym.shndx != SectionIdx::Undef {
+            return own(sym.value.0);
+        }
+        lookup_tls(scope, namespace, &sym.name).ok_or_else(|| LoadError::SymbolNotFound {
+            name: sym.name.clone(),
+            object: name.to_string(),
+            namespace: namespace.0,
+        })
+    };
+
+    // Values of the terms of `reloc`'s formula, with the object at `base`
+    let terms_of = |reloc: &RelaEntry| -> Result<Terms, LoadError> {
+        let mut terms = Terms {
+            a: reloc.addend.0,
+            p: file.vaddr_to_loaded(reloc.offset, base),
+            b: base,
+            ..Default::default()
+        };
+        if reloc.typ.is_tls() {
+            let (module, offset) = resolve_tls(reloc.sym)?;
+            terms.s = offset;
+            terms.module = module.id;
+            terms.tls = module.offset;
+        } else if reloc.typ.formula().uses(Term::S) {
+            terms.s = resolve(reloc.sym, false)?;
+        }
+        Ok(terms)
+    };
+
+    let mut written = Vec::new();
+    let mut relocations = RelocStats::default();
+    let mut applied = Vec::new();
+    // IRelative resolvers run once every segment is relocated and executable
+    let mut ifuncs = Vec::new();
+    let progress = Progress::new(format!("Relocating {}", name), rela_entries.len() as u64);
+    for segment in &object.segments {
+        let pages = segment.pages();
+        let slots: Vec<_> = rela_entries
+            .iter()
+            .filter(|reloc| segment.contains(file.vaddr_to_loaded(reloc.offset, base)))
+            .collect();
+        // Mapped writable like every other segment, so text relocations go in before the
+        // segment gets its own permissions back
+        if !segment.is_writable() && !slots.is_empty() {
+            println!(
+                "Applying {} text relocations with the segment writable...",
+                slots.len()
+            );
+        }
+        for reloc in slots {
+            progress.advance(1);
+            let slot = file.vaddr_to_loaded(reloc.offset, base);
+            println!(
+                "Apply {:?} relocation at {:#x}",
+                reloc.typ,
+                slot - segment.start
+            );
+            if options.check_relocations {
+                check_slot(name, file, base, reloc.offset.0, scope, at, textrel)?;
+            }
+            relocations.record(reloc.typ, slot);
+            let formula = reloc.typ.formula();
+            let mut len = SLOT_SIZE;
+            let value = match reloc.typ {
+                // The executable gets its own copy of a library's data object; the
+                // value recorded is where it was copied from
+                RelType::Copy => {
+                    let source = resolve(reloc.sym, true)?;
+                    let size = syms.get(reloc.sym as usize).map_or(0, |sym| sym.size);
+                    len = size;
+                    if source != slot {
+                        let mut bytes = vec![0; size as usize];
+                        space
+                            .read(source, &mut bytes)
+                            .and_then(|_| space.write(slot, &bytes))
+                            .map_err(|source| LoadError::Memory {
+                                object: name.to_string(),
+                                addr: slot,
+                                len: bytes.len(),
+                                source,
+                            })?;
+                    }
+                    source
+                }
+                // Left pointing at the PLT entry's push, relocated, so the first call goes
+                // through PLT0 to the resolver
+                RelType::JumpSlot if lazy => {
+                    let stub = space.read_u64(slot).map_err(|source| LoadError::Memory {
+                        object: name.to_string(),
+                        addr: slot,
+                        len: SLOT_SIZE as usize,
+                        source,
+                    })? + base;
+                    write_slot(&**space, name, slot, stub)?;
+                    stub
+                }
+                RelType::IRelative => {
+                    let restore = if segment.is_writable() {
+                        None
+                    } else {
+                        Some(segment.protection())
+                    };
+                    ifuncs.push((slot, formula.eval(&terms_of(reloc)?), restore));
+                    continue;
+                }
+                _ => {
+                    let value = formula.eval(&terms_of(reloc)?);
+                    let what = format!("{:?} slot at {:#x}", reloc.typ, slot);
+                    let value = replayed(name, &what, value, recorded_writes.get(&slot).copied());
+                    write_slot(&**space, name, slot, value)?;
+                    value
+                }
+            };
+            if let Some(watch) = options.watch {
+                if (slot..slot + SLOT_SIZE).contains(&watch) {
+                    let target = match syms.get(reloc.sym as usize) {
+                        Some(sym) if !sym.name.is_empty() => format!(" ({})", sym.name),
+                        _ => String::new(),
+                    };
+                    let phase = format!(
+                        "{:?} relocation of {} at {:#x}{}",
+                        reloc.typ, name, slot, target
+                    );
+                    report_watch(&**space, watch, &phase, segment.pages().end);
+                }
+            }
+            applied.push(AppliedReloc {
+                addr: slot,
+                typ: reloc.typ,
+                value,
+                len,
+            });
+        }
+
+        println!("setting permissions...");
+        segment.protect().map_err(|source| LoadError::Protect {
+            object: name.to_string(),
+            addr: pages.start,
+            len: (pages.end - pages.start) as usize,
+            source,
+        })?;
+        log_protection(options, Phase::Segment, name, pages, segment.protection());
+    }
+
+    // GOT[1] tells the resolver which object is calling, GOT[2] is where PLT0 jumps to. Bound
+    // before the IFUNC resolvers run, as those may call through the PLT.
+    let plt = match lazy {
+        true => {
+            let own = scope[at].exports.clone();
+            let at = |tag| file.dynamic_entry(tag).map_or(0, |addr| addr.0 + base);
+            let plt = LazyPlt {
+                jmprel: at(DynamicTag::JmpRel),
+                symtab: at(DynamicTag::SymTab),
+                strtab: at(DynamicTag::StrTab),
+                versions,
+            };
+            let resolver = plt_resolver(name, base, own, shared, binds.clone(), plt);
+            let link = lazy::Link::new(resolver);
+            let got = at(DynamicTag::PltGot);
+            write_slot(&**space, name, got + SLOT_SIZE, link.id())?;
+            write_slot(&**space, name, got + 2 * SLOT_SIZE, lazy::trampoline())?;
+            written.push((got + SLOT_SIZE, "GOT[1], the object's lazy binding link"));
+            written.push((got + 2 * SLOT_SIZE, "GOT[2], the lazy binding trampoline"));
+            Some(link)
+        }
+        false => None,
+    };
+
+    for (slot, resolver, restore) in ifuncs {
+        let value = space.call(resolver, &[]).map_err(|e| LoadError::Reloc {
+            object: name.to_string(),
+            slot,
+            reason: format!("IFUNC resolver at {:#x} failed: {}", resolver, e),
+        })?;
+        // Resolvers pick by CPU, so this is where replays on another machine differ
+        let what = format!("IRelative slot at {:#x}", slot);
+        let value = replayed(name, &what, value, recorded_writes.get(&slot).copied());
+        match restore {
+            Some(protection) => write_text_slot(&**space, options, name, slot, value, protection)?,
+            None => write_slot(&**space, name, slot, value)?,
+        }
+        if let Some(watch) = options.watch {
+            if (slot..slot + SLOT_SIZE).contains(&watch) {
+                let phase = format!("IRelative relocation of {} at {:#x}", name, slot);
+                report_watch(&**space, watch, &phase, slot + SLOT_SIZE);
+            }
+        }
+        applied.push(AppliedReloc {
+            addr: slot,
+            typ: RelType::IRelative,
+            value,
+            len: SLOT_SIZE,
+        });
+    }
+
+    if options.verify_relocations {
+        // Lazily bound slots only get their value on first call
+        let bound = rela_entries
+            .iter()
+            .filter(|reloc| !(lazy && reloc.typ == RelType::JumpSlot));
+        let expected = simulate(bound, &syms, scope, at)?;
+        verify_lockstep(&**space, name, &expected)?;
+    }
+
+    let (init, fini) = {
+        let function = |tag, list| {
+            file.dynamic_entry(tag)
+                .filter(|addr| addr.0 != 0)
+                .map(|addr| (list, addr.0 + base))
+        };
+        let array = |addr, size, list| -> Result<Vec<_>, LoadError> {
+            let entries = dynamic_array(&**space, name, file, base, addr, size)?;
+            Ok(entries.into_iter().map(|entry| (list, entry)).collect())
+        };
+        // Legacy toolchains' .ctors and .dtors are walked by the object's own _init and _fini,
+        // and by elk in their place when it has none
+        let legacy = |section, list| -> Result<Vec<_>, LoadError> {
+            let entries = legacy_list(&**space, name, file, base, section)?;
+            Ok(entries.into_iter().map(|entry| (list, entry)).collect())
+        };
+        let mut init = match function(DynamicTag::Init, "DT_INIT") {
+            Some(init) => vec![init],
+            None => {
+                let mut ctors = legacy(".ctors", ".ctors")?;
+                ctors.reverse();
+                ctors
+            }
+        };
+        init.extend(array(
+            DynamicTag::InitArray,
+            DynamicTag::InitArraysz,
+            "DT_INIT_ARRAY",
+        )?);
+        let mut fini = array(
+            DynamicTag::FiniArray,
+            DynamicTag::FiniArraysz,
+            "DT_FINI_ARRAY",
+        )?;
+        fini.reverse();
+        match function(DynamicTag::FIni, "DT_FINI") {
+            Some(function) => fini.push(function),
+            None => fini.extend(legacy(".dtors", ".dtors")?),
+        }
+        (init, fini)
+    };
+
+    // A DT_DEBUG entry asks for the address of the rendezvous, as ld.so fills it in
+    let dynamic = file.segments_of_type(SegmentType::Dynamic).next();
+    if let Some(ProgramHeader {
+        virt_addr,
+        contents: SegmentContent::Dynamic(entries),
+        ..
+    }) = dynamic.filter(|_| space.in_process())
+    {
+        if let Some(i) = entries.iter().position(|e| e.tag == DynamicTag::Debug) {
+            let slot = virt_addr.0 + i as u64 * 16 + 8;
+            if !read_only_segment(file, slot) {
+                write_slot(&**space, name, slot + base, rendezvous::address())?;
+                written.push((slot + base, "DT_DEBUG, the debugger rendezvous"));
+            }
+        }
+    }
+    let bindings = bindings.into_inner();
+    let object = &mut objects[at];
+    object.init = init;
+    object.fini = fini;
+    object.relocations = relocations;
+    object.applied = applied;
+    object.bindings = bindings;
+    object.written = written;
+    object.plt = plt;
+    Ok(())
+}
+
+// Binds the PLT entries of the object `name` at `base` on first call, looking their symbols up
+// in its namespace of `scope` as it is at the time of the call. Only the scope is locked, and
+// only for the lookup.
+fn plt_resolver(
+    name: &str,
+    base: u64,
+    own: Arc<Exports>,
+    scope: &Arc<Scope>,
+    binds: lazy::Counts,
+    plt: LazyPlt,
+) -> lazy::Resolver {
+    let scope = Arc::downgrade(scope);
+    let name = name.to_string();
+    Box::new(move |index| {
+        let read = |addr| {
+            Local
+                .read_u64(addr)
+                .map_err(|e| format!("{}: reading {:#x}: {}", name, addr, e))
+        };
+        let rela = plt.jmprel + index * RelaEntry::SIZE as u64;
+        let (offset, info) = (read(rela)?, read(rela + 8)?);
+        let typ = RelType::from_number(Machine::X86_64, Class::Elf64, info as u32);
+        if typ != RelType::JumpSlot {
+            return Err(format!(
+                "{}: PLT relocation {} has type {}, not JUMP_SLOT",
+                name, index, info as u32
+            ));
+        }
+        // st_name, st_info, st_other and st_shndx share the first word of an Elf64_Sym
+        let version = plt.versions.get((info >> 32) as usize).cloned().flatten();
+        let sym = plt.symtab + (info >> 32) * 24;
+        let word = read(sym)?;
+        let symbol = cstr(plt.strtab + (word & 0xffff_ffff)).map_err(|e| e.to_string())?;
+        let (bind, visibility, shndx) = ((word >> 36) & 0xf, (word >> 40) & 0x3, word >> 48);
+        // Local and hidden definitions bind within the object, as in `map_object`
+        let local = shndx != 0 && (bind == 0 || matches!(visibility, 1 | 2));
+        let target = match local {
+            true => Some(read(sym + 8)? + base),
+            false => {
+                let scope = scope.upgrade().ok_or("the process is gone")?;
+                let scope = scope.read().unwrap_or_else(PoisonError::into_inner);
+                let scope = scope.iter().map(|e| &**e);
+                // The object's own definitions too, for calls made before it is in the scope
+                lookup(scope, own.namespace, &symbol, version.as_deref())
+                    .or_else(|| own.symbols.get(symbol.as_str()).copied())
+            }
+        };
+        let target = target.ok_or_else(|| match &version {
+            Some(version) => format!("{}: undefined symbol {}@{}", name, symbol, version),
+            None => format!("{}: undefined symbol {}", name, symbol),
+        })?;
+        Local
+            .write_u64(offset + base, target)
+            .map_err(|e| e.to_string())?;
+        binds.add(&symbol);
+        Ok(target)
+    })
+}
+
+impl RelocStats {
+    fn record(&mut self, typ: RelType, addr: u64) {
+        *self.by_type.entry(format!("{:?}", typ)).or_default() += 1;
+        *self.by_page.entry(addr & !(PAGE_SIZE - 1)).or_default() += 1;
+    }
+
+    fn merge(&mut self, other: &RelocStats) {
+        for (typ, n) in &other.by_type {
+            *self.by_type.entry(typ.clone()).or_default() += n;
+        }
+        for (page, n) in &other.by_page {
+            *self.by_page.entry(*page).or_default() += n;
+        }
+    }
+
+    // Relocation counts per type, then the `top` pages that received the most writes
+    pub fn report(&self, top: usize) -> String {
+        let types = Table {
+            header: "Relocations applied".into(),
+            labels: vec!["Type".into(), "Count".into()],
+            rows: self
+                .by_type
+                .iter()
+                .map(|(typ, n)| vec![typ.clone(), n.to_string()])
+                .collect(),
+        };
+        let mut pages: Vec<_> = self.by_page.iter().collect();
+        pages.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let hot = Table {
+            header: format!("Relocation hot spots (top {})", top),
+            labels: vec!["Page".into(), "Count".into()],
+            rows: pages
+                .iter()
+                .take(top)
+                .map(|(page, n)| vec![format!("{:#x}", page), n.to_string()])
+                .collect(),
+        };
+        format!("{}\n{}", types.build(), hot.build())
+    }
+}
+
+// What the relocations of `scope[at]` leave in its slots, worked out from the file and what the
+// other objects export, without touching memory. Symbols are looked up here again rather than
+// through the terms the loader applied, so a mistake in those shows up as a difference instead
+// of being made twice; replayed values aren't either. Copy and IRelative are left out, as their
+// values come from running code or reading another object. A slot relocated twice keeps the last
+// value, as it does when applied.
+fn simulate<'a>(
+    entries: impl Iterator<Item = &'a RelaEntry>,
+    syms: &[Symbol],
+    scope: &[Object],
+    at: usize,
+) -> Result<BTreeMap<u64, u64>, LoadError> {
+    let object = &scope[at];
+    let (base, namespace) = (object.base, object.exports.namespace);
+    let missing = |name: &str| LoadError::SymbolNotFound {
+        name: name.to_string(),
+        object: object.name.clone(),
+        namespace: namespace.0,
+    };
+    let no_tls = || LoadError::Tls {
+        object: object.name.clone(),
+        reason: "TLS relocations but no PT_TLS segment".into(),
+    };
+    let mut slots = BTreeMap::new();
+    for reloc in entries {
+        if matches!(reloc.typ, RelType::Copy | RelType::IRelative) {
+            continue;
+        }
+        let mut terms = Terms {
+            a: reloc.addend.0,
+            p: base.wrapping_add(reloc.offset.0),
+            b: base,
+            ..Default::default()
+        };
+        let sym = match reloc.sym {
+            0 => None,
+            index => Some(
+                syms.get(index as usize)
+                    .ok_or_else(|| missing(&format!("#{}", index)))?,
+            ),
+        };
+        // Defined in the object and not visible outside it
+        let own = sym.filter(|sym| sym.shndx != SectionIdx::Undef && !sym.is_exported());
+        if reloc.typ.is_tls() {
+            let (module, offset) = match (sym, own) {
+                (None, _) => (object.tls.ok_or_else(no_tls)?, 0),
+                (_, Some(own)) => (object.tls.ok_or_else(no_tls)?, own.value.0),
+                (Some(sym), None) => {
+                    lookup_tls(scope, namespace, &sym.name).ok_or_else(|| missing(&sym.name))?
+                }
+            };
+            terms.s = offset;
+            terms.module = module.id;
+            terms.tls = module.offset;
+        } else if let Some(sym) = sym.filter(|_| reloc.typ.formula().uses(Term::S)) {
+            let version = sym.version.as_ref().map(|v| v.name.as_str());
+            terms.s = match own.and_then(|own| own.address(base)) {
+                Some(addr) => addr,
+                None => lookup(exports(scope), namespace, &sym.name, version)
+                    .or_else(|| sym.address(base))
+                    .or_else(|| (sym.bind() == Some(SymBind::Weak)).then_some(0))
+                    .ok_or_else(|| missing(&sym.name))?,
+            };
+        }
+        slots.insert(terms.p, reloc.typ.formula().eval(&terms));
+    }
+    Ok(slots)
+}
+
+// Compares each run of adjacent slots in memory against what `simulate` expects there, so a
+// loader bug shows up as a load error naming the slot rather than as a crash later
+fn verify_lockstep(
+    space: &dyn AddressSpace,
+    name: &str,
+    expected: &BTreeMap<u64, u64>,
+) -> Result<(), LoadError> {
+    let mut runs: Vec<Vec<(u64, u64)>> = Vec::new();
+    for (&slot, &value) in expected {
+        match runs.last_mut() {
+            Some(run)
+                if run
+                    .last()
+                    .is_some_and(|&(last, _)| last + SLOT_SIZE == slot) =>
+            {
+                run.push((slot, value))
+            }
+            _ => runs.push(vec![(slot, value)]),
+        }
+    }
+    for run in runs {
+        let start = run[0].0;
+        let mut memory = vec![0; run.len() * SLOT_SIZE as usize];
+        space
+            .read(start, &mut memory)
+            .map_err(|source| LoadError::Memory {
+                object: name.to_string(),
+                addr: start,
+                len: memory.len(),
+                source,
+            })?;
+        let simulated: Vec<u8> = run
+            .iter()
+            .flat_map(|&(_, value)| value.to_le_bytes())
+            .collect();
+        if let Some(at) = (0..memory.len()).find(|&i| memory[i] != simulated[i]) {
+            let word = at / SLOT_SIZE as usize * SLOT_SIZE as usize;
+            let actual = u64::from_le_bytes(memory[word..word + 8].try_into().unwrap());
+            let (slot, value) = run[word / SLOT_SIZE as usize];
+            return Err(LoadError::Reloc {
+                object: name.to_string(),
+                slot,
+                reason: format!(
+                    "memory holds {:#x} after loading, the relocation simulator expects {:#x}",
+                    actual, value
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Shadow check for one relocation write, done before the slot is touched. The whole slot must lie
+// in a single LOAD segment of the object being relocated, that segment must be writable unless
+// the object declares text relocations, and the slot must not land inside another loaded object.
+fn check_slot(
+    name: &str,
+    file: &FileHeader,
+    base: u64,
+    slot: u64,
+    scope: &[Object],
+    at: usize,
+    textrel: bool,
+) -> Result<(), LoadError> {
+    let reject = |reason: String| {
+        Err(LoadError::Reloc {
+            object: name.to_string(),
+            slot: slot + base,
+            reason,
+        })
+    };
+    let segment = match file
+        .program_headers
+        .iter()
+        .filter(|ph| ph.typ == SegmentType::Load)
+        .find(|ph| ph.mem_range().contains(&Addr(slot)))
+    {
+        Some(segment) => segment,
+        None => return reject("slot is outside every LOAD segment".into()),
+    };
+    let end = segment.mem_range().end.0;
+    if slot + SLOT_SIZE > end {
+        return reject(format!(
+            "{}-byte write runs {} bytes past its segment",
+            SLOT_SIZE,
+            slot + SLOT_SIZE - end
+        ));
+    }
+    if !segment.flags.contains(SegmentFlags::Write) && !textrel {
+        return reject("segment is read-only and the object has no DT_TEXTREL".into());
+    }
+    let runtime = slot + base..slot + base + SLOT_SIZE;
+    if let Some(other) = scope
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != at)
+        .map(|(_, o)| o)
+        .find(|o| runtime.start < o.end && o.start < runtime.end)
+    {
+        return reject(format!("slot overlaps {}", other.name));
+    }
+    Ok(())
+}
+
+// On stderr so it survives `--quiet`. `end` is where the mapping the write went to stops; a
+// watched address closer to it than a word shows as much of the word as is mapped.
+fn report_watch(space: &dyn AddressSpace, watch: u64, phase: &str, end: u64) {
+    let mut bytes = vec![0; (end.saturating_sub(watch)).min(SLOT_SIZE) as usize];
+    let _ = space.read(watch, &mut bytes);
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0u64, |value, &b| value << 8 | u64::from(b));
+    eprintln!("watch {:#x} = {:#x} after {}", watch, value, phase);
+}
+
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
+
+// Bytes mapping `file`'s LOAD segments takes, page padding included, or None past u64
+fn mapped_size(file: &FileHeader) -> Option<u64> {
+    file.program_headers
+        .iter()
+        .filter(|ph| ph.typ == SegmentType::Load)
+        .try_fold(0u64, |total, ph| {
+            total.checked_add(ph.mem_size.0.checked_add(image::padding(ph))?)
+        })
+}
+
+// Function pointers in the array the `addr` and `size` dynamic entries describe, read from the
+// mapped and relocated object at `base`. Null entries are left out.
+fn dynamic_array(
+    space: &dyn AddressSpace,
+    name: &str,
+    file: &FileHeader,
+    base: u64,
+    addr_tag: DynamicTag,
+    size_tag: DynamicTag,
+) -> Result<Vec<u64>, LoadError> {
+    let (addr, size) = match (file.dynamic_entry(addr_tag), file.dynamic_entry(size_tag)) {
+        (Some(addr), Some(size)) => (addr.0, size.0),
+        _ => return Ok(Vec::new()),
+    };
+    if !in_load_segment(file, addr, size) {
+        return Err(LoadError::Init {
+            object: name.to_string(),
+            reason: format!(
+                "{:?} at {:#x} is outside every LOAD segment",
+                addr_tag, addr
+            ),
+        });
+    }
+    let mut bytes = vec![0; size as usize / 8 * 8];
+    space
+        .read(addr + base, &mut bytes)
+        .map_err(|source| LoadError::Memory {
+            object: name.to_string(),
+            addr: addr + base,
+            len: bytes.len(),
+            source,
+        })?;
+    Ok(bytes
+        .chunks(8)
+        .map(|entry| u64::from_le_bytes(entry.try_into().unwrap()))
+        .filter(|&entry| entry != 0)
+        .collect())
+}
+
+// Entries of a mapped, relocated .ctors or .dtors, leaving out the -1 and 0 that frame them
+fn legacy_list(
+    space: &dyn AddressSpace,
+    name: &str,
+    file: &FileHeader,
+    base: u64,
+    section: &str,
+) -> Result<Vec<u64>, LoadError> {
+    let sh = match file.section_by_name(section) {
+        Some(sh) => sh,
+        None => return Ok(Vec::new()),
+    };
+    if !in_load_segment(file, sh.addr.0, sh.size.0) {
+        return Err(LoadError::Init {
+            object: name.to_string(),
+            reason: format!(
+                "{} at {:#x} is outside every LOAD segment",
+                section, sh.addr.0
+            ),
+        });
+    }
+    let mut bytes = vec![0; sh.size.0 as usize / 8 * 8];
+    space
+        .read(sh.addr.0 + base, &mut bytes)
+        .map_err(|source| LoadError::Memory {
+            object: name.to_string(),
+            addr: sh.addr.0 + base,
+            len: bytes.len(),
+            source,
+        })?;
+    Ok(bytes
+        .chunks(8)
+        .map(|entry| u64::from_le_bytes(entry.try_into().unwrap()))
+        .filter(|&entry| entry != 0 && entry != u64::MAX)
+        .collect())
+}
+
+// Whether `len` bytes at link-time address `addr` are all inside one LOAD segment
+fn in_load_segment(file: &FileHeader, addr: u64, len: u64) -> bool {
+    let end = match addr.checked_add(len) {
+        Some(end) => Addr(end),
+        None => return false,
+    };
+    file.segments_of_type(SegmentType::Load).any(|ph| {
+        let range = ph.mem_range();
+        range.start <= Addr(addr) && end <= range.end
+    })
+}
+
+// Page-aligned span of all LOAD segments, relative to the base
+fn image_range(file: &FileHeader) -> Option<Range<u64>> {
+    let loads = file
+        .program_headers
+        .iter()
+        .filter(|ph| ph.typ == SegmentType::Load && ph.mem_size.0 > 0);
+    let start = loads.clone().map(|ph| ph.virt_addr.0).min()?;
+    let end = loads
+        .map(|ph| ph.virt_addr.0.saturating_add(ph.mem_size.0))
+        .max()?;
+    Some(start & !(PAGE_SIZE - 1)..end)
+}
+
+fn validate_base(file: &FileHeader, base: u64) -> Result<(), LoadError> {
+    if !base.is_multiple_of(PAGE_SIZE) {
+        return Err(LoadError::Misaligned(base));
+    }
+    if file.typ == Type::Exec && base != 0 {
+        return Err(LoadError::NotRelocatable(base));
+    }
+    let image = match image_range(file) {
+        Some(image) => image,
+        None => return Ok(()),
+    };
+    let range = match (image.start.checked_add(base), image.end.checked_add(base)) {
+        (Some(start), Some(end)) if end <= 1 << 47 => start..end,
+        _ => return Err(LoadError::Overflow(base)),
+    };
+
+    // Mapping over our own memory would corrupt elk itself
+    for (mapped, line) in system_mappings() {
+        if mapped.start < range.end && range.start < mapped.end {
+            return Err(LoadError::Conflict(range, line));
+        }
+    }
+    Ok(())
+}
+
+// Everything currently mapped into elk, with the /proc/self/maps line describing it
+fn system_mappings() -> Vec<(Range<u64>, String)> {
+    let maps = fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    maps.lines()
+        .filter_map(|line| {
+            let (s, e) = line.split_whitespace().next()?.split_once('-')?;
+            let range = u64::from_str_radix(s, 16).ok()?..u64::from_str_radix(e, 16).ok()?;
+            Some((range, line.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::space::Buffer;
+
+    const LADDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/");
+
+    // Tests loading into this process map at the same default base, so they take turns
+    static IN_PROCESS: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    // The rung and the libraries it needs, in load order
+    fn ladder(rung: &str) -> Vec<deps::Object> {
+        let path = format!("{}{}", LADDER, rung);
+        let input = crate::source::read(&path).unwrap();
+        let file = FileHeader::parse_or_describe(&input).unwrap();
+        deps::objects(&path, file)
+    }
+
+    // libinner.so calls into libouter.so without needing it, so it can only bind once every
+    // object is mapped, libouter.so having been loaded first
+    #[test]
+    fn libraries_bind_to_objects_loaded_before_them() {
+        let objects = ladder("4-backref");
+        let main = &objects[0].file;
+        let process = Process::load_into(
+            Arc::new(Buffer::new()),
+            main,
+            Process::default_base(main),
+            LoadOptions::default(),
+            &objects[1..],
+        )
+        .unwrap();
+        assert_eq!(
+            process.object_names(),
+            [MAIN_OBJECT, "libouter.so", "libinner.so"]
+        );
+        let module = |name| {
+            process
+                .modules()
+                .into_iter()
+                .find(|m| m.name == name)
+                .map(|m| m.start..m.end)
+                .unwrap()
+        };
+        let outer_write = process.lookup(Namespace::BASE, "outer_write").unwrap();
+        assert!(module("libouter.so").contains(&outer_write));
+        let inner = module("libinner.so");
+        let slot = process
+            .relocation_log()
+            .into_iter()
+            .find(|reloc| reloc.typ == RelType::GlobalData && inner.contains(&reloc.addr))
+            .unwrap();
+        assert_eq!(slot.value, outer_write);
+        assert_eq!(process.space().read_u64(slot.addr).unwrap(), outer_write);
+    }
+
+    // A recording saved and read back makes the next load put everything where it went, and
+    // bind what it bound even where this load would have done otherwise
+    #[test]
+    fn replays_make_the_recorded_decisions() {
+        let objects = ladder("3-needed");
+        let main = &objects[0].file;
+        let base = Process::default_base(main);
+        let load = |options| {
+            Process::load_into(Arc::new(Buffer::new()), main, base, options, &objects[1..]).unwrap()
+        };
+        let first = load(LoadOptions::default());
+        let mut recording = first.recording("3-needed");
+        let greet = recording.objects[0].bindings["greet"];
+        assert_eq!(greet, first.lookup(Namespace::BASE, "greet").unwrap());
+        assert_eq!(recording.object("libgreet.so", 0).unwrap().namespace, 0);
+        assert!(recording.object("libgreet.so", 1).is_none());
+
+        // Somewhere else in libgreet.so, as a library of another build might have it; the
+        // write to the slot is dropped, or it would be replayed over what the binding gives
+        let slot = main.read_rela_entries().unwrap()[0].offset.0 + base;
+        recording.objects[0]
+            .bindings
+            .insert("greet".into(), greet + 0x10);
+        recording.objects[0]
+            .writes
+            .retain(|write| write.addr != slot);
+        let path = std::env::temp_dir().join(format!("elk-replay-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        recording.write(path).unwrap();
+        let read = Recording::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let replayed = load(LoadOptions {
+            replay: Some(Arc::new(read)),
+            ..Default::default()
+        });
+        let again = replayed.recording("3-needed");
+        let bases = |r: &Recording| r.objects.iter().map(|o| o.base).collect::<Vec<_>>();
+        assert_eq!(bases(&again), bases(&recording));
+        assert_eq!(again.objects[0].bindings["greet"], greet + 0x10);
+        assert_eq!(replayed.space().read_u64(slot).unwrap(), greet + 0x10);
+    }
+
+    // The simulator works the slots out on its own, so a slot the loader got wrong is named
+    #[test]
+    fn lockstep_verify_names_slots_memory_disagrees_on() {
+        let objects = ladder("3-needed");
+        let main = &objects[0].file;
+        let options = LoadOptions {
+            verify_relocations: true,
+            ..Default::default()
+        };
+        let process = Process::load_into(
+            Arc::new(Buffer::new()),
+            main,
+            Process::default_base(main),
+            options,
+            &objects[1..],
+        )
+        .unwrap();
+        let entries = main.read_rela_entries().unwrap();
+        let expected = simulate(
+            entries.iter(),
+            &dynamic_symbols(main),
+            &process.objects(),
+            0,
+        );
+        let expected = expected.unwrap();
+        let (&slot, &value) = expected.iter().next().unwrap();
+        assert_eq!(value, process.lookup(Namespace::BASE, "greet").unwrap());
+
+        let space = process.space();
+        verify_lockstep(&**space, MAIN_OBJECT, &expected).unwrap();
+        space.write_u64(slot, value + 1).unwrap();
+        match verify_lockstep(&**space, MAIN_OBJECT, &expected) {
+            Err(LoadError::Reloc { slot: wrong, .. }) => assert_eq!(wrong, slot),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    // elk run tries another random base when the kernel refuses to map over what is there
+    #[test]
+    fn loading_over_a_mapping_is_refused() {
+        let objects = ladder("4-backref");
+        let main = &objects[0].file;
+        let space: Arc<dyn AddressSpace> = Arc::new(Buffer::new());
+        let base = Process::default_base(main);
+        let load = |base| {
+            Process::load_into(
+                space.clone(),
+                main,
+                base,
+                LoadOptions::default(),
+                &objects[1..],
+            )
+        };
+        let _first = load(base).unwrap();
+        let refused = load(base).err().unwrap();
+        assert!(refused.is_occupied(), "{}", refused);
+        assert!(load(base + (1 << 30)).is_ok());
+        assert!(!LoadError::Misaligned(base + 1).is_occupied());
+    }
+
+    // liblazy.so's IFUNC resolver calls through its PLT while dlopen still holds the object
+    // list, and 5-lazy's PLT entry binds to liblazy.so, opened after it, on its first call
+    #[test]
+    fn plt_entries_bind_on_first_call() {
+        let _turn = IN_PROCESS.lock().unwrap_or_else(|e| e.into_inner());
+        let path = format!("{}5-lazy", LADDER);
+        let input = crate::source::read(&path).unwrap();
+        let file = FileHeader::parse_or_describe(&input).unwrap();
+        let process = Process::load(&file).unwrap();
+        let library = process.dlopen(&format!("{}liblazy.so", LADDER)).unwrap();
+        let space = process.space();
+
+        let ifunc = process
+            .relocation_log()
+            .into_iter()
+            .find(|reloc| reloc.typ == RelType::IRelative)
+            .unwrap();
+        let mut message = [0; 21];
+        space.read(ifunc.value, &mut message).unwrap();
+        assert_eq!(&message, b"Hello from liblazy.so");
+
+        let slot = file.read_plt_rela_entries().unwrap()[0].offset.0 + process.base;
+        let entry = file.section_by_name(".plt").unwrap().addr.0 + process.base + 16;
+        let lazy = process.dlsym(library, "lazy").unwrap();
+        assert_ne!(space.read_u64(slot).unwrap(), lazy);
+        assert_eq!(space.call(entry, &[]).unwrap(), ifunc.value);
+        assert_eq!(space.read_u64(slot).unwrap(), lazy);
+        assert_eq!(space.call(entry, &[]).unwrap(), ifunc.value);
+        let binds: Vec<_> = process.lazy_binds().get().into_iter().collect();
+        assert_eq!(binds, [("answer".to_string(), 1), ("lazy".to_string(), 1)]);
+    }
+
+    // 3-needed's GLOB_DAT slot holds libgreet.so's greet; once libgreet-v2.so replaces it, where
+    // greet is elsewhere and returns its message, calling through the slot reaches the new one
+    #[test]
+    fn replaced_objects_take_over_the_slots_bound_to_them() {
+        let _turn = IN_PROCESS.lock().unwrap_or_else(|e| e.into_inner());
+        let objects = ladder("3-needed");
+        let main = &objects[0].file;
+        let process = Process::load_with_libraries(
+            main,
+            Process::default_base(main),
+            LoadOptions::default(),
+            &objects[1..],
+        )
+        .unwrap();
+        let slot = main.read_rela_entries().unwrap()[0].offset.0 + process.base;
+        let old = process.lookup(Namespace::BASE, "greet").unwrap();
+        assert_eq!(process.space().read_u64(slot).unwrap(), old);
+
+        process
+            .replace_object("libgreet.so", &format!("{}libgreet-v2.so", LADDER))
+            .unwrap();
+        let greet = process.lookup(Namespace::BASE, "greet").unwrap();
+        assert_ne!(greet, old);
+        let space = process.space();
+        assert_eq!(space.read_u64(slot).unwrap(), greet);
+        let message = space.call(greet, &[]).unwrap();
+        let called = space.call(space.read_u64(slot).unwrap(), &[]).unwrap();
+        assert_eq!(called, message);
+        let mut hello = [0; 33];
+        space.read(message, &mut hello).unwrap();
+        assert_eq!(&hello, b"Hello from the second libgreet.so");
+    }
+
+    // Exports of an object in the base namespace, `versioned` as (name, version, address)
+    fn defining(symbols: &[(&str, u64)], versioned: &[(&str, &str, u64)]) -> Exports {
+        let mut exports = Exports {
+            namespace: Namespace::BASE,
+            symbols: symbols
+                .iter()
+                .map(|&(name, addr)| (name.into(), addr))
+                .collect(),
+            versioned: HashMap::new(),
+        };
+        for &(name, version, addr) in versioned {
+            exports
+                .versioned
+                .entry(name.into())
+                .or_default()
+                .push((version.into(), addr));
+        }
+        exports
+    }
+
+    #[test]
+    fn versioned_references_pass_over_other_versions() {
+        let newer = defining(&[("foo", 0x20)], &[("foo", "V2", 0x20)]);
+        let plain = defining(&[("foo", 0x30)], &[]);
+        let older = defining(&[], &[("foo", "V1", 0x10)]);
+        let find = |scope: &[&Exports], version| {
+            lookup(scope.iter().copied(), Namespace::BASE, "foo", version)
+        };
+
+        assert_eq!(find(&[&newer, &older], Some("V1")), Some(0x10));
+        assert_eq!(find(&[&newer, &older], Some("V2")), Some(0x20));
+        assert_eq!(find(&[&newer, &plain, &older], Some("V1")), Some(0x30));
+        assert_eq!(find(&[&newer, &older], None), Some(0x20));
+        assert_eq!(find(&[&newer], Some("V1")), None);
+        assert_eq!(
+            versions_of([&newer, &older].iter().copied(), Namespace::BASE, "foo"),
+            ["V2", "V1"]
+        );
+    }
+}
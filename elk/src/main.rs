@@ -1,134 +1,1429 @@
 use std::{
+    convert::TryFrom,
     env,
     error::Error,
     fs,
-    io::{stdin, Write},
-    mem::transmute,
-    process::{self, Command, Stdio},
-    slice::from_raw_parts_mut,
+    io::{self, stdin, IsTerminal, Read, Write},
+    os::raw::c_int,
+    process,
+    sync::Arc,
 };
 
-use carpenter::*;
-use delf::{types::*, FileHeader};
-use mmap::{MapOption, MemoryMap};
-use region::{protect, Protection};
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let base = 0x400000usize;
-    let path = env::args().nth(1).expect("Usage: elk <file_path>");
-    let input = fs::read(&path)?;
-    if let Some(file) = FileHeader::parse_or_print_error(&input[..]) {
-        println!("Disassembling {}...", &path);
-        let prog_header = file
-            .program_headers
-            .iter()
-            .find(|ph| ph.mem_range().contains(&file.entry_point))
-            .expect("entry point not found in program headers");
-        let code = &prog_header.data;
-        ndisasm(code, file.entry_point)?;
-
-        let rela_entries = &file.read_rela_entries().unwrap_or_else(|e| {
-            println!("couldn't read entries: {:?}", e);
-            Default::default()
-        });
-        file.print();
-        ProgramHeader::print_table(&file.program_headers);
-        if let Some(ds) = file
-            .program_headers
-            .iter()
-            .find(|h| h.typ == delf::types::SegmentType::Dynamic)
-        {
-            if let delf::types::SegmentContent::Dynamic(ref table) = ds.contents {
-                DynamicEntry::print_table(&table);
+use clap::{CommandFactory, Parser, Subcommand, ValueHint};
+use clap_complete::CompleteEnv;
+use delf::{
+    eflags,
+    eh_frame::EhFrameHdr,
+    hexdump::write_hexdump,
+    parse::Anomaly,
+    style::{self, ColorChoice},
+    trace,
+    types::*,
+    view::{AddrMode, AddrView},
+    FileHeader,
+};
+#[cfg(feature = "emulate")]
+use elk::emulate;
+#[cfg(feature = "tui")]
+use elk::explore;
+#[cfg(feature = "script")]
+use elk::script;
+#[cfg(feature = "xcheck")]
+use elk::xcheck;
+use elk::{
+    arena, audit, can_disassemble, capabilities, check, cli,
+    config::{self, Sandbox},
+    container, coredump, crash, deps, difftest, dig, dis, disasm_listing, dump,
+    error::LoadError,
+    exit::{self, ErrorFormat, Failure, Status},
+    exports, extract, grep_symbol, hash, init_arrays, label, linkage,
+    loader::{self, LoadOptions, Process},
+    parse_number, patch, plugin,
+    progress::{self, Silenced},
+    provenance,
+    record::Recording,
+    relocs, report, schema, selfcheck, similarity, size, source,
+    space::{AddressSpace, Child},
+    stack, stacks, symbolize, tables, trace_relro, vtables, xref,
+};
+use serde::Serialize;
+
+// Random bases tried before giving up, when one lands on something already mapped
+const RANDOM_BASE_TRIES: usize = 8;
+
+// The system allocator, except where elk check parses files in an arena
+#[global_allocator]
+static ALLOCATOR: arena::Allocator = arena::Allocator;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "elk",
+    about = "Inspect, load and run ELF binaries",
+    args_conflicts_with_subcommands = true,
+    arg_required_else_help = true
+)]
+struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Read defaults from FILE instead of ~/.config/elk/config.toml"
+    )]
+    config: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_name = "WHEN",
+        help = "auto, always or never"
+    )]
+    color: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        conflicts_with = "color",
+        help = "Same as --color never"
+    )]
+    no_color: bool,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_name = "NAME",
+        help = "Color theme"
+    )]
+    theme: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        help = "Link file names in terminals that support it"
+    )]
+    hyperlinks: bool,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        help = "Draw tables with ASCII borders, the default when $TERM is dumb"
+    )]
+    ascii: bool,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_name = "COLUMNS",
+        help = "Cut table cells wider than COLUMNS short"
+    )]
+    max_width: Option<usize>,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_name = "N",
+        help = "Print at most N rows of each table"
+    )]
+    max_rows: Option<usize>,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        help = "Don't draw progress bars for long operations on stderr"
+    )]
+    no_progress: bool,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Write tables to FILE instead of stdout, as HTML, Markdown, CSV or JSON by its extension"
+    )]
+    output: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_enum,
+        default_value = "text",
+        help = "How to print a failure on stderr; json gives one object with status, code and message"
+    )]
+    error_format: ErrorFormat,
+    #[arg(
+        long,
+        global = true,
+        help_heading = "Global options",
+        value_enum,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always",
+        help = "Print the parser contexts delf went through reading a file as a tree on stderr: \
+                always, or only when parsing fails with failure"
+    )]
+    trace_parse: Option<TraceParse>,
+    #[arg(long, help = "Print the JSON Schema of elk's JSON output and exit")]
+    schema: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(
+        value_hint = ValueHint::FilePath,
+        help = "Dump the headers of a binary, then load and run it"
+    )]
+    file: Option<String>,
+}
+
+// When --trace-parse prints the parser trace
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum TraceParse {
+    Always,
+    Failure,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Run(RunArgs),
+    Inspect(InspectArgs),
+    #[command(about = "List a binary's dynamic entries, string values as strings")]
+    Dyn {
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: String,
+    },
+    Size(size::Args),
+    Check(check::Args),
+    Capabilities(capabilities::Args),
+    TraceRelro(trace_relro::Args),
+    AuditSystem(audit::Args),
+    #[command(about = "Guess the format of files from their first bytes")]
+    Detect {
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        files: Vec<String>,
+    },
+    Provenance(provenance::Args),
+    Linkage(linkage::Args),
+    Relocs(relocs::Args),
+    Hash(hash::Args),
+    Report(report::Args),
+    Xref(xref::Args),
+    Vtables(vtables::Args),
+    Dig(dig::Args),
+    Dis(dis::Args),
+    Dump(dump::Args),
+    Deps(deps::Args),
+    UnusedExports(exports::Args),
+    GrepSymbol(grep_symbol::Args),
+    Match(similarity::Args),
+    Symbolize(symbolize::Args),
+    Stacks(stacks::Args),
+    Crash(crash::Args),
+    Core(coredump::Args),
+    Difftest(difftest::Args),
+    UnpackInitramfs(container::Args),
+    Label(label::Args),
+    Extract(extract::Args),
+    Patch(patch::Args),
+    Selfcheck(selfcheck::Args),
+    #[cfg(feature = "emulate")]
+    Emulate(emulate::Args),
+    #[cfg(feature = "tui")]
+    Explore(explore::Args),
+    #[cfg(feature = "script")]
+    Script(script::Args),
+    #[cfg(feature = "xcheck")]
+    Xcheck(xcheck::Args),
+    #[command(about = "Print a shell script that completes elk's commands, flags and values")]
+    Completions(cli::CompletionsArgs),
+}
+
+fn main() {
+    CompleteEnv::with_factory(Cli::command)
+        .var(cli::COMPLETE_VAR)
+        .complete();
+    let status = match Cli::try_parse() {
+        // --help and --version end up here as well, and print to stdout
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => exit::report(&e, raw_error_format()),
+        Ok(args) => {
+            let format = args.error_format;
+            match dispatch(args) {
+                Ok(()) => Status::Ok,
+                Err(e) => exit::report(&*e, format),
             }
-            RelaEntry::print_table(rela_entries);
         }
+    };
+    process::exit(status as i32);
+}
 
-        println!("Mapping segments...");
-        let mut mappings = Vec::new();
-        for ph in file
-            .program_headers
-            .iter()
-            .filter(|h| h.typ == SegmentType::Load)
-            .filter(|h| h.mem_size.0 as usize > 0)
-        {
-            let start = ph.virt_addr.0 as usize + base;
-            let aligned = align_down(start, 0x1000);
-            let padding = start - aligned;
-            let memory_range = aligned..(aligned + ph.mem_size.0 as usize + padding);
-            let addr: *mut u8 = aligned as _;
-            println!(
-                "Mapping segment at {:?} with {:?}. Address: {:p}",
-                memory_range, ph.flags, addr
-            );
-            let map = MemoryMap::new(
-                ph.mem_size.0 as usize + padding,
-                &[MapOption::MapWritable, MapOption::MapAddr(addr)],
-            )?;
-
-            println!("Copy segment data to memory region...");
-            {
-                let dst = unsafe { from_raw_parts_mut(addr.add(padding), ph.data.len()) };
-                dst.copy_from_slice(&ph.data[..]);
+// --error-format read straight from the arguments, for when clap could not parse them
+fn raw_error_format() -> ErrorFormat {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let json = args.iter().any(|a| a == "--error-format=json")
+        || args
+            .windows(2)
+            .any(|pair| pair[0] == "--error-format" && pair[1] == "json");
+    match json {
+        true => ErrorFormat::Json,
+        false => ErrorFormat::Text,
+    }
+}
+
+fn dispatch(args: Cli) -> Result<(), Box<dyn Error>> {
+    let config = config::init(args.config.as_deref())?;
+    let color = match args.color.or_else(|| config.color.clone()) {
+        _ if args.no_color => ColorChoice::Never,
+        Some(value) => ColorChoice::parse(&value).ok_or_else(|| {
+            Failure::parse(format!(
+                "--color expects auto, always or never, got {:?}",
+                value
+            ))
+        })?,
+        None => ColorChoice::Auto,
+    };
+    style::init(
+        color,
+        args.theme.or_else(|| config.theme.clone()).as_deref(),
+    )?;
+    if args.hyperlinks {
+        style::enable_hyperlinks(true);
+    }
+    match args.trace_parse {
+        None => {}
+        Some(TraceParse::Always) => trace::set_mode(trace::Mode::Always),
+        Some(TraceParse::Failure) => trace::set_mode(trace::Mode::OnFailure),
+    }
+    tables::set_layout(tables::Layout {
+        ascii: args.ascii || env::var_os("TERM").is_some_and(|term| term == "dumb"),
+        max_width: args.max_width,
+        max_rows: args.max_rows,
+    });
+    if let Some(output) = &args.output {
+        tables::export_to(output)?;
+    }
+    if args.no_progress || args.error_format == ErrorFormat::Json {
+        progress::silence();
+    }
+
+    if args.schema {
+        return schema::print_schema();
+    }
+    match (args.command, args.file) {
+        (Some(Command::Run(args)), _) => run_command(args),
+        (Some(Command::Inspect(args)), _) => inspect_command(args),
+        (Some(Command::Dyn { file }), _) => dynamic(&file),
+        (Some(Command::Size(args)), _) => size::run(args),
+        (Some(Command::Check(args)), _) => check::run(args),
+        (Some(Command::Capabilities(args)), _) => capabilities::run(args),
+        (Some(Command::TraceRelro(args)), _) => trace_relro::run(args),
+        (Some(Command::AuditSystem(args)), _) => audit::run(args),
+        (Some(Command::Detect { files }), _) => detect(&files),
+        (Some(Command::Provenance(args)), _) => provenance::run(args),
+        (Some(Command::Linkage(args)), _) => linkage::run(args),
+        (Some(Command::Relocs(args)), _) => relocs::run(args),
+        (Some(Command::Hash(args)), _) => hash::run(args),
+        (Some(Command::Report(args)), _) => report::run(args),
+        (Some(Command::Xref(args)), _) => xref::run(args),
+        (Some(Command::Vtables(args)), _) => vtables::run(args),
+        (Some(Command::Dig(args)), _) => dig::run(args),
+        (Some(Command::Dis(args)), _) => dis::run(args),
+        (Some(Command::Dump(args)), _) => dump::run(args),
+        (Some(Command::Deps(args)), _) => deps::run(args),
+        (Some(Command::UnusedExports(args)), _) => exports::run(args),
+        (Some(Command::GrepSymbol(args)), _) => grep_symbol::run(args),
+        (Some(Command::Match(args)), _) => similarity::run(args),
+        (Some(Command::Symbolize(args)), _) => symbolize::run(args),
+        (Some(Command::Stacks(args)), _) => stacks::run(args),
+        (Some(Command::Crash(args)), _) => crash::run(args),
+        (Some(Command::Core(args)), _) => coredump::run(args),
+        (Some(Command::Difftest(args)), _) => difftest::run(args),
+        (Some(Command::UnpackInitramfs(args)), _) => container::run(args),
+        (Some(Command::Label(args)), _) => label::run(args),
+        (Some(Command::Extract(args)), _) => extract::run(args),
+        (Some(Command::Patch(args)), _) => patch::run(args),
+        (Some(Command::Selfcheck(args)), _) => selfcheck::run(args),
+        #[cfg(feature = "emulate")]
+        (Some(Command::Emulate(args)), _) => emulate::run(args),
+        #[cfg(feature = "tui")]
+        (Some(Command::Explore(args)), _) => explore::run(args),
+        #[cfg(feature = "script")]
+        (Some(Command::Script(args)), _) => script::run(args),
+        #[cfg(feature = "xcheck")]
+        (Some(Command::Xcheck(args)), _) => xcheck::run(args),
+        (Some(Command::Completions(args)), _) => cli::completions(args),
+        (None, Some(path)) => run(&path, &RunOptions::default()),
+        (None, None) => {
+            Cli::command().print_help()?;
+            process::exit(Status::Parse as i32);
+        }
+    }
+}
+
+fn detect(paths: &[String]) -> Result<(), Box<dyn Error>> {
+    for path in paths {
+        let mut head = Vec::with_capacity(0x100);
+        match path.as_str() {
+            source::STDIN => head.extend(source::read_raw(path)?.into_iter().take(0x100)),
+            _ => {
+                fs::File::open(path)?.take(0x100).read_to_end(&mut head)?;
             }
+        }
+        println!("{}: {}", path, delf::detect::detect(&head));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct RunOptions {
+    base: Option<u64>,
+    // Load at loader::DEFAULT_BASE rather than a random base
+    no_aslr: bool,
+    // Print relocation counters before jumping to the entry point, and lazy binds at exit
+    profile: bool,
+    // Validate relocation slots against the segment map before writing them
+    check_relocations: bool,
+    // Compare relocated slots against the relocation simulator once each object is loaded
+    verify_relocations: bool,
+    // Resolve every PLT slot at load time instead of on first call
+    bind_now: bool,
+    // Apply relocations to read-only segments instead of refusing to load
+    allow_textrel: bool,
+    // Run even when the preflight finds something elk doesn't support
+    force: bool,
+    // Where to write a crash report if the program dies on a signal
+    crash_report: Option<String>,
+    // Run the program in a child process and exit the way it did
+    fork: bool,
+    // Build the image in a fresh child under ptrace instead of elk itself, then let it run there
+    in_child: bool,
+    // With `in_child`, leave the child stopped at the entry point instead of running it
+    suspended: bool,
+    // Keep elk's own output off stdout, leaving only the program's
+    quiet: bool,
+    // How the header dump prints addresses; the load base defaults to `base`
+    addresses: AddrMode,
+    load_base: Option<u64>,
+    // Runtime address whose every write by the loader is reported
+    watch: Option<u64>,
+    // argv past argv[0], which is the path
+    args: Vec<String>,
+    // envp, as KEY=VALUE
+    env: Vec<Vec<u8>>,
+    // Size of the stack the program starts on, stack::DEFAULT_SIZE when unset
+    stack_size: Option<usize>,
+    // Byte the program's stack is filled with before it starts
+    poison_stack: Option<u8>,
+    // Loader limits; the config file's, then the loader's defaults when unset
+    max_mapped: Option<u64>,
+    max_objects: Option<usize>,
+    // Hex dump this many bytes of each segment and section
+    hex: Option<usize>,
+    // Run the program this many times from one loaded image
+    repeat: Option<usize>,
+    // Where to save the loader's decisions, and a file of them to make again
+    record: Option<String>,
+    replay: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    about = "Load a binary with elk's own loader and jump to its entry point",
+    long_about = "Load a binary with elk's own loader and jump to its entry point.\n\n\
+                  First the program and the libraries it needs are checked against what the \
+                  loader does (see elk capabilities), and without --force it is refused over \
+                  anything the loader lacks. That includes most programs linked dynamically \
+                  against glibc: its libc uses GLIBC_PRIVATE internals that only glibc's ld.so \
+                  sets up. Libraries that can't be found are only warned about, and leave \
+                  their symbols unresolved."
+)]
+struct RunArgs {
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_parser = parse_number,
+        help = "Load address for position independent binaries"
+    )]
+    base: Option<u64>,
+    #[arg(
+        long,
+        conflicts_with = "base",
+        help = "Load position independent binaries at 0x400000 rather than a random base"
+    )]
+    no_aslr: bool,
+    #[arg(
+        long,
+        help = "Print relocation counters before jumping to the entry point, and the PLT \
+                entries bound on first call if the program exits through libc"
+    )]
+    profile: bool,
+    #[arg(
+        long = "check-relocs",
+        help = "Validate relocation slots against the segment map before writing them"
+    )]
+    check_relocations: bool,
+    #[arg(
+        long = "verify-relocs",
+        help = "Once each object is relocated, compare its slots in memory against the \
+                relocations worked out again from the file"
+    )]
+    verify_relocations: bool,
+    #[arg(
+        long,
+        help = "Resolve every PLT slot at load time instead of on first call, as LD_BIND_NOW does"
+    )]
+    bind_now: bool,
+    #[arg(
+        long,
+        help = "Apply text relocations, making read-only segments writable while they are patched"
+    )]
+    allow_textrel: bool,
+    #[arg(
+        long,
+        help = "Load and run the program even when it needs things elk doesn't support, such \
+                as glibc's GLIBC_PRIVATE internals"
+    )]
+    force: bool,
+    #[arg(
+        long,
+        help = "Run the program in a child process and exit the way it did"
+    )]
+    fork: bool,
+    #[arg(
+        long,
+        conflicts_with = "fork",
+        help = "Load the program into a fresh child process under ptrace and run it there"
+    )]
+    in_child: bool,
+    #[arg(
+        long,
+        conflicts_with = "fork",
+        help = "Like --in-child, but leave the child stopped at the entry point and print its PID \
+                for a debugger to attach to"
+    )]
+    spawn_suspended: bool,
+    #[arg(
+        long,
+        value_enum,
+        help = "Isolation, defaults to the config file's or none"
+    )]
+    sandbox: Option<Sandbox>,
+    #[arg(long, help = "Keep elk's own output off stdout")]
+    quiet: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Where to write a crash report if the program dies on a signal"
+    )]
+    crash_report: Option<String>,
+    #[arg(
+        long = "watch-addr",
+        value_name = "ADDR",
+        value_parser = parse_number,
+        help = "Report every write the loader makes to this runtime address, and which phase made it"
+    )]
+    watch: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_number,
+        help = "Run the program on a stack of this size, with a guard page below it, instead of elk's"
+    )]
+    stack_size: Option<u64>,
+    #[arg(
+        long,
+        value_name = "BYTE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0xa5",
+        value_parser = parse_number,
+        help = "Fill the program's stack with a byte so uninitialized reads behave the same every run"
+    )]
+    poison_stack: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_number,
+        help = "Refuse to map more than this many bytes for all objects together"
+    )]
+    max_mapped: Option<u64>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Refuse to load more than this many objects"
+    )]
+    max_objects: Option<usize>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "256",
+        value_parser = parse_number,
+        help = "Hex dump the first BYTES of each segment and section, relocated bytes highlighted"
+    )]
+    hex: Option<u64>,
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["fork", "in_child", "spawn_suspended"],
+        help = "Load and relocate once, then run the program N times, each in a fork with a \
+                copy-on-write copy of the image, and report the time each run took to start"
+    )]
+    repeat: Option<usize>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Save the bases, symbol bindings and relocation writes the loader chose to FILE"
+    )]
+    record: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["base", "no_aslr"],
+        help = "Make the loader's decisions saved with --record again, reporting where this load \
+                would have differed"
+    )]
+    replay: Option<String>,
+    #[arg(
+        long = "env",
+        value_name = "KEY=VALUE",
+        value_parser = parse_env,
+        help = "Set a variable in the program's environment; repeat for more"
+    )]
+    env: Vec<(String, String)>,
+    #[arg(
+        long,
+        help = "Start the program with an empty environment, apart from --env"
+    )]
+    clear_env: bool,
+    #[command(flatten)]
+    addresses: cli::AddrArgs,
+    #[arg(value_hint = ValueHint::FilePath)]
+    file: String,
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        help = "Arguments for the program, after FILE as its argv[0]"
+    )]
+    args: Vec<String>,
+}
+
+fn run_command(args: RunArgs) -> Result<(), Box<dyn Error>> {
+    let sandbox = args.sandbox.unwrap_or(config::get().sandbox);
+    let options = RunOptions {
+        base: args.base,
+        no_aslr: args.no_aslr,
+        profile: args.profile,
+        check_relocations: args.check_relocations || sandbox == Sandbox::Strict,
+        verify_relocations: args.verify_relocations,
+        bind_now: args.bind_now || env::var_os("LD_BIND_NOW").is_some_and(|v| !v.is_empty()),
+        allow_textrel: args.allow_textrel,
+        force: args.force,
+        crash_report: args.crash_report,
+        // A child built with --in-child is already apart from elk
+        fork: (args.fork || sandbox != Sandbox::None) && !args.in_child && !args.spawn_suspended,
+        in_child: args.in_child || args.spawn_suspended,
+        suspended: args.spawn_suspended,
+        quiet: args.quiet,
+        addresses: args.addresses.mode(),
+        load_base: args.addresses.load_base,
+        watch: args.watch,
+        // Poisoning needs a stack elk allocated
+        stack_size: match (args.stack_size, args.poison_stack) {
+            (Some(size), _) => Some(size as usize),
+            (None, Some(_)) => Some(stack::DEFAULT_SIZE),
+            (None, None) => None,
+        },
+        poison_stack: args
+            .poison_stack
+            .map(|byte| {
+                u8::try_from(byte).map_err(|_| {
+                    Failure::parse(format!("--poison-stack {:#x} is not a byte", byte))
+                })
+            })
+            .transpose()?,
+        max_mapped: args.max_mapped,
+        max_objects: args.max_objects,
+        hex: args.hex.map(|bytes| bytes as usize),
+        repeat: args.repeat,
+        record: args.record,
+        replay: args.replay,
+        args: args.args,
+        env: stack::environment(args.clear_env, &args.env),
+    };
+    run(&args.file, &options)
+}
+
+// `--env KEY=VALUE`
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {:?}", value)),
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Dump the headers, segments and tables of a binary without loading it")]
+struct InspectArgs {
+    #[arg(
+        long,
+        value_name = "BYTES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "256",
+        value_parser = parse_number,
+        help = "Hex dump the first BYTES of each segment and section, relocated bytes highlighted"
+    )]
+    hex: Option<u64>,
+    #[command(flatten)]
+    addresses: cli::AddrArgs,
+    #[command(flatten)]
+    format: cli::FormatArg,
+    #[arg(value_hint = ValueHint::FilePath)]
+    file: String,
+}
+
+// `elk inspect --format json`: delf's own structures, plus the tables parsed out of them
+#[derive(Serialize)]
+struct Inspection<'a> {
+    file: &'a FileHeader,
+    dynamic: &'a [DynamicEntry],
+    relocations: Vec<RelaEntry>,
+    symbols: Vec<Symbol>,
+    dynamic_symbols: Vec<Symbol>,
+}
+
+fn inspect_command(args: InspectArgs) -> Result<(), Box<dyn Error>> {
+    let input = source::read_whole(&args.file)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", args.file, e)))?;
+    if args.format.json() {
+        let dynamic = match file.segments_of_type(SegmentType::Dynamic).next() {
+            Some(ProgramHeader {
+                contents: SegmentContent::Dynamic(entries),
+                ..
+            }) => &entries[..],
+            _ => &[],
+        };
+        let mut relocations = file.read_rela_entries().unwrap_or_default();
+        relocations.extend(file.read_plt_rela_entries().unwrap_or_default());
+        let inspection = Inspection {
+            file: &file,
+            dynamic,
+            relocations,
+            symbols: file.read_section_syms(),
+            dynamic_symbols: file.read_syms(),
+        };
+        return schema::print_json("inspect", &inspection);
+    }
+    let view = args.addresses.view(&file);
+    inspect(
+        &args.file,
+        &file,
+        &view,
+        args.hex.map(|bytes| bytes as usize),
+    )
+}
+
+fn dynamic(path: &str) -> Result<(), Box<dyn Error>> {
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let entries = match file.segments_of_type(SegmentType::Dynamic).next() {
+        Some(ProgramHeader {
+            contents: SegmentContent::Dynamic(entries),
+            ..
+        }) => entries,
+        _ => {
+            println!("{} has no dynamic section", path);
+            return Ok(());
+        }
+    };
+    print_dynamic(&file, entries);
+    if let Err(e) = file.check_dynamic() {
+        eprintln!("Warning: {}", e);
+    }
+    Ok(())
+}
+
+// Everything `run` prints about a file before mapping it, which is all `elk inspect` does
+fn inspect(
+    path: &str,
+    file: &FileHeader,
+    view: &AddrView,
+    hex: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    // Relocatable objects have no segments for an entry point to be in, and libraries often
+    // have no entry point at all
+    if file.typ != Type::Rel && file.entry_point.0 != 0 {
+        // Only a first look at the code: inspect and run carry on without it, as when ndisasm
+        // isn't installed
+        if let Err(e) = disassemble_entry(path, file) {
+            eprintln!("Warning: could not disassemble the entry point: {}", e);
+        }
+    }
+
+    print_header(file, view);
+    if !file.anomalies.is_empty() {
+        print_anomalies(&file.anomalies);
+    }
+    linkage::analyze(file).table(path).print();
+    print_segments(&file.program_headers);
+    for mismatch in file.check_segment_contents() {
+        eprintln!("Warning: {}", mismatch);
+    }
+    let groups = file.section_groups();
+    if !groups.is_empty() {
+        print_groups(&groups);
+    }
+    print_notes(file);
+    if let Some(SegmentContent::EhFrameHdr(hdr)) = file
+        .segments_of_type(SegmentType::GnuEhFrame)
+        .next()
+        .map(|ph| &ph.contents)
+    {
+        print_eh_frame_hdr(hdr, view);
+    }
+    if let Some(ds) = file.segments_of_type(SegmentType::Dynamic).next() {
+        if let delf::types::SegmentContent::Dynamic(ref table) = ds.contents {
+            print_dynamic(file, table);
+        }
+        if let Err(e) = file.check_dynamic() {
+            eprintln!("Warning: {}", e);
+        }
+        // The full list is `elk relocs --entries`, tens of thousands of rows for big binaries
+        let relas = relocs::annotate(file).unwrap_or_default();
+        for group in [relocs::Group::Type, relocs::Group::Region] {
+            relocs::summary(&relas, group, view).print();
+        }
+    }
+    let init = init_arrays::entries(path, file);
+    if !init.is_empty() {
+        init_arrays::table(path, &init, view).print();
+    }
+    if let Some(limit) = hex {
+        print_hex(file, limit)?;
+    }
+    Ok(())
+}
+
+// The code at the entry point, labelled with it and main when they can be found
+fn disassemble_entry(path: &str, file: &FileHeader) -> Result<(), Box<dyn Error>> {
+    println!("Disassembling {}...", path);
+    let prog_header = file
+        .program_headers
+        .iter()
+        .find(|ph| ph.check_sizes().is_ok() && ph.mem_range().contains(&file.entry_point))
+        .ok_or_else(|| LoadError::Init {
+            object: path.to_string(),
+            reason: format!(
+                "entry point {:?} is outside every segment",
+                file.entry_point
+            ),
+        })?;
+    let code = &prog_header.data;
+    // Stripped release binaries have neither symbol; the names come from glibc's _start
+    let mut labels = vec![match file.entry_symbol() {
+        Some(sym) => (file.entry_point, sym.name, ""),
+        None => (file.entry_point, "_start".into(), "entry point, no symbol"),
+    }];
+    if let Some(main) = file.find_main() {
+        labels.push(match file.symbol_at(main) {
+            Some((sym, 0)) => (main, sym.name, ""),
+            _ => (main, "main".into(), "passed to __libc_start_main"),
+        });
+    }
+    // Builds without a disassembler still show everything else
+    if can_disassemble(file.machine) {
+        disasm(
+            file.machine,
+            code,
+            prog_header.virt_addr,
+            file.entry_point,
+            &labels,
+        )?;
+    }
+    Ok(())
+}
+
+// String-valued entries show the string rather than its offset in DT_STRTAB
+fn print_anomalies(anomalies: &[Anomaly]) {
+    let table = tables::Table {
+        header: "Anomalies".into(),
+        labels: vec![
+            "Offset".into(),
+            "Field".into(),
+            "Severity".into(),
+            "Message".into(),
+        ],
+        rows: anomalies
+            .iter()
+            .map(|a| {
+                vec![
+                    format!("{:#x}", a.offset),
+                    a.field.to_string(),
+                    format!("{:?}", a.severity),
+                    a.message.clone(),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
+
+fn print_segments(headers: &[ProgramHeader]) {
+    let table = tables::Table {
+        header: "Program headers".into(),
+        labels: [
+            "Type",
+            "Flags",
+            "Offset",
+            "Virt addr",
+            "Phys addr",
+            "File size",
+            "Mem size",
+            "Align",
+        ]
+        .iter()
+        .map(|label| label.to_string())
+        .collect(),
+        rows: headers
+            .iter()
+            .map(|ph| {
+                vec![
+                    format!("{:?}", ph.typ),
+                    format!("{:?}", ph.flags),
+                    format!("{:?}", ph.offset),
+                    format!("{:?}", ph.virt_addr),
+                    format!("{:?}", ph.phys_addr),
+                    format!("{:?}", ph.file_size),
+                    format!("{:?}", ph.mem_size),
+                    format!("{:?}", ph.align),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
 
-            for reloc in rela_entries {
-                if ph.mem_range().contains(&reloc.offset) {
-                    unsafe {
-                        let segment_start = addr.add(padding);
-                        let segment_offset = reloc.offset - ph.mem_range().start;
-                        println!("Apply {:?} relocation at {:?}", reloc.typ, segment_offset);
-                        let reloc_addr: *mut u64 =
-                            transmute(segment_start.add(segment_offset.into()));
-                        match reloc.typ {
-                            RelType::Relative => {
-                                let val = reloc.addend + Addr(base as u64);
-                                *reloc_addr = val.0;
-                            }
-                            _ => {
-                                panic!("Unsupported type {:?}", &reloc.typ)
-                            }
-                        }
-                    }
+fn print_groups(groups: &[SectionGroup]) {
+    let table = tables::Table {
+        header: "Section groups".into(),
+        labels: vec![
+            "Section".into(),
+            "Signature".into(),
+            "COMDAT".into(),
+            "Members".into(),
+        ],
+        rows: groups
+            .iter()
+            .map(|group| {
+                vec![
+                    group.name.clone(),
+                    group.signature.clone(),
+                    group.comdat.to_string(),
+                    group.members.join(", "),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
+
+fn print_dynamic(file: &FileHeader, entries: &[DynamicEntry]) {
+    let table = tables::Table {
+        header: "Dynamic entries".into(),
+        labels: vec!["Tag".into(), "Value".into()],
+        rows: entries
+            .iter()
+            .map(|entry| {
+                vec![
+                    format!("{:?}", entry.tag),
+                    entry
+                        .string_value(file)
+                        .unwrap_or_else(|| format!("{:?}", entry.addr)),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
+
+fn print_notes(file: &FileHeader) {
+    let notes = file.notes();
+    if notes.is_empty() {
+        return;
+    }
+    let table = tables::Table {
+        header: "Notes".into(),
+        labels: vec![
+            "Source".into(),
+            "Owner".into(),
+            "Type".into(),
+            "Description".into(),
+        ],
+        rows: notes
+            .into_iter()
+            .map(|(source, note)| {
+                vec![
+                    source,
+                    note.name.clone(),
+                    note.type_name()
+                        .map_or_else(|| format!("{:#x}", note.typ), String::from),
+                    note.describe(file.class, file.machine)
+                        .unwrap_or_else(|| format!("{} bytes", note.desc.len())),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
+
+fn print_eh_frame_hdr(hdr: &EhFrameHdr, view: &AddrView) {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".into());
+    let table = tables::Table {
+        header: "Exception Frame Header".into(),
+        labels: vec![
+            "Version".into(),
+            ".eh_frame".into(),
+            "FDEs".into(),
+            "Covers".into(),
+        ],
+        rows: vec![vec![
+            hdr.version.to_string(),
+            optional(hdr.eh_frame.map(|a| view.show(a))),
+            optional(hdr.fde_count.map(|n| n.to_string())),
+            optional(
+                hdr.range()
+                    .map(|(lo, hi)| format!("{}..={}", view.show(lo), view.show(hi))),
+            ),
+        ]],
+    };
+    table.print();
+    if hdr.table.is_empty() {
+        return;
+    }
+    let table = tables::Table {
+        header: "Binary Search Table".into(),
+        labels: vec!["Initial location".into(), "FDE".into()],
+        rows: hdr
+            .table
+            .iter()
+            .map(|(location, fde)| vec![view.show(*location), view.show(*fde)])
+            .collect(),
+    };
+    table.print();
+}
+
+// Segments by virtual address, then sections; sections outside memory by file offset
+fn print_hex(file: &FileHeader, limit: usize) -> io::Result<()> {
+    let theme = style::theme();
+    let relocated: Vec<_> = relocs::annotate(file)
+        .unwrap_or_default()
+        .iter()
+        .map(|reloc| reloc.offset..reloc.offset + 8)
+        .collect();
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    for (i, ph) in file.program_headers.iter().enumerate() {
+        if ph.data.is_empty() {
+            continue;
+        }
+        let title = format!("Segment {} ({:?}) at {:#x}", i, ph.typ, ph.virt_addr.0);
+        writeln!(out, "{}", theme.title.paint(title))?;
+        write_hexdump(&mut out, &ph.data, ph.virt_addr, &relocated, limit, theme)?;
+    }
+    for sh in &file.section_headers {
+        if sh.data.is_empty() {
+            continue;
+        }
+        let (addr, relocated) = match sh.addr.0 {
+            0 => (sh.offset, &[][..]),
+            _ => (sh.addr, &relocated[..]),
+        };
+        let title = format!("Section {} at {:#x}", sh.name, addr.0);
+        writeln!(out, "{}", theme.title.paint(title))?;
+        write_hexdump(&mut out, &sh.data, addr, relocated, limit, theme)?;
+    }
+    out.flush()
+}
+
+fn print_header(file: &FileHeader, view: &AddrView) {
+    let info = |i: &delf::HeaderInfo| match i.padding {
+        0 => format!("{} x {}B", i.count, i.size),
+        padding => format!("{} x {}B ({}B padding)", i.count, i.size, padding),
+    };
+    let entry = match file.annotate(file.entry_point) {
+        Some(note) => format!("{} ({})", view.show(file.entry_point), note),
+        None => view.show(file.entry_point),
+    };
+    let flags = match eflags::decode(file.machine, file.flags) {
+        meaning if meaning.is_empty() => format!("{:#x}", file.flags),
+        meaning => format!("{:#x} ({})", file.flags, meaning.join(", ")),
+    };
+    let table = tables::Table {
+        header: "File Header".into(),
+        labels: vec![
+            "Type".into(),
+            "Machine".into(),
+            "Flags".into(),
+            "Entry point".into(),
+            "Program headers".into(),
+            "Section headers".into(),
+        ],
+        rows: vec![vec![
+            format!("{:?}", file.typ),
+            format!("{:?}", file.machine),
+            flags,
+            entry,
+            info(&file.program_header_info),
+            info(&file.section_header_info),
+        ]],
+    };
+    table.print();
+}
+
+fn run(path: &str, options: &RunOptions) -> Result<(), Box<dyn Error>> {
+    // Forked ahead of silencing stdout, which the program should keep
+    let child = match options.in_child {
+        true => Some(Arc::new(Child::spawn(options.suspended)?)),
+        false => None,
+    };
+    let mut silenced = match options.quiet {
+        true => Some(Silenced::new()?),
+        false => None,
+    };
+    // On a terminal the loader's line per relocation says how far along it is already
+    if !options.quiet && io::stdout().is_terminal() {
+        progress::silence();
+    }
+    let input = source::read_whole(path)?;
+    if let Some(file) = FileHeader::parse_or_print_error(&input) {
+        let view = AddrView::new(&file, options.addresses, options.load_base.or(options.base));
+        inspect(path, &file, &view, options.hex)?;
+
+        let replay = match &options.replay {
+            Some(path) => Some(Arc::new(Recording::read(path)?)),
+            None => None,
+        };
+        println!("Mapping segments...");
+        // Executables only load at their link address, whatever the configured strategy
+        let chosen = options.base.or_else(|| {
+            Some(
+                replay
+                    .as_ref()?
+                    .object(loader::MAIN_OBJECT, loader::Namespace::BASE.0)?
+                    .base,
+            )
+        });
+        let random = chosen.is_none()
+            && file.typ != Type::Exec
+            && !options.no_aslr
+            && config::get().base == config::Base::Random;
+        let pick = || {
+            chosen
+                .or_else(|| match file.typ {
+                    Type::Exec => None,
+                    _ if options.no_aslr => None,
+                    _ => config::get().base.pick(),
+                })
+                .unwrap_or_else(|| Process::default_base(&file))
+        };
+        let load_options = LoadOptions {
+            check_relocations: options.check_relocations,
+            verify_relocations: options.verify_relocations,
+            bind_now: options.bind_now,
+            allow_textrel: options.allow_textrel,
+            watch: options.watch,
+            max_mapped: options
+                .max_mapped
+                .or(config::get().max_mapped)
+                .unwrap_or(loader::DEFAULT_MAX_MAPPED),
+            max_objects: options
+                .max_objects
+                .or(config::get().max_objects)
+                .unwrap_or(loader::DEFAULT_MAX_OBJECTS),
+            replay,
+            protections: None,
+        };
+        // Searched the way `elk deps` does; libraries it can't find leave their symbols
+        // unresolved
+        let objects = deps::objects(path, FileHeader::parse_or_describe(&input)?);
+        // Refused up front, rather than crashing somewhere after the jump
+        let unsupported = Process::preflight_objects(&objects, &load_options);
+        for reason in &unsupported {
+            let kind = match reason.refuses() {
+                true => "unsupported",
+                false => "warning",
+            };
+            eprintln!("{} ({}): {}", kind, reason.capability().id, reason);
+        }
+        if unsupported.iter().any(|reason| reason.refuses()) && !options.force {
+            return Err(Failure::load(format!(
+                "{} needs what elk doesn't support, --force runs it anyway",
+                path
+            ))
+            .into());
+        }
+        if let Some(child) = &child {
+            println!("Loading into child process {}", child.pid());
+        }
+        let mut tries = 0;
+        let process = loop {
+            let base = pick();
+            let loaded = match &child {
+                Some(child) => {
+                    let space: Arc<dyn AddressSpace> = child.clone();
+                    Process::load_into(space, &file, base, load_options.clone(), &objects[1..])
+                }
+                None => {
+                    Process::load_with_libraries(&file, base, load_options.clone(), &objects[1..])
+                }
+            };
+            // A random base can land on something already mapped; the kernel refuses to map
+            // over it, and another one is tried
+            match loaded {
+                Err(e) if random && e.is_occupied() && tries < RANDOM_BASE_TRIES => {
+                    eprintln!("{}, trying another base", e);
+                    tries += 1;
                 }
+                loaded => break loaded?,
+            }
+        };
+        process.announce_main(path);
+        // Before the program runs: in elk's own process it never comes back
+        if let Some(record) = &options.record {
+            process.recording(path).write(record)?;
+            println!("Recorded loader decisions to {}", record);
+        }
+        let space = process.space().clone();
+        if let Some(watch) = options.watch {
+            if process.peek(watch).is_none() {
+                eprintln!("watch {:#x} is not inside any loaded segment", watch);
             }
+        }
+        for analysis in plugin::analyses().iter() {
+            for finding in analysis.run_process(&process) {
+                eprintln!("{}: {}", analysis.name(), finding);
+            }
+        }
+        if options.profile {
+            // Most programs exit through a syscall and never return here, so report up front
+            println!("{}", process.relocation_stats().report(10));
+        }
 
-            println!("setting permissions...");
-            let protection = ph.flags.iter().fold(Protection::NONE, |acc, f| {
-                acc | match f {
-                    SegmentFlags::Read => Protection::READ,
-                    SegmentFlags::Write => Protection::WRITE,
-                    SegmentFlags::Execute => Protection::EXECUTE,
-                }
-            });
-            unsafe {
-                protect(addr, ph.data.len() + padding, protection)?;
+        if let Some(runs) = options.repeat {
+            if let Some(silenced) = silenced.take() {
+                silenced.restore()?;
             }
-            mappings.push(map);
+            return repeat(&process, &file, path, options, runs);
         }
 
-        let code_ptr = code.as_ptr();
-        unsafe {
-            protect(code_ptr, code.len(), Protection::READ_WRITE_EXECUTE)?;
+        let report = options
+            .crash_report
+            .clone()
+            .unwrap_or_else(|| format!("elk-crash-{}.txt", process::id()));
+        // A crash in the child shows up as its exit status instead
+        if child.is_none() {
+            crash::install(&process, path, &report)?;
         }
 
-        println!("Jumping to entry point: {:?}", file.entry_point);
+        if let Some(silenced) = silenced {
+            silenced.restore()?;
+        }
+        if options.fork {
+            unsafe { fork_and_wait(options.quiet)? };
+        }
+
+        if !options.quiet {
+            println!("Jumping to entry point: {:?}", file.entry_point);
+        }
 
-        unsafe { jmp((file.entry_point.0 as usize + base) as _) };
+        let size = options.stack_size.unwrap_or(stack::DEFAULT_SIZE);
+        let stack = stack::Stack::new_in(space.clone(), size, options.poison_stack)?;
+        // One object asking is enough, as with ld.so
+        if let Some(object) = objects.iter().find(|o| o.file.executable_stack()) {
+            eprintln!(
+                "Warning: {} asks for an executable stack (PT_GNU_STACK), mapping it RWX",
+                object.name
+            );
+            stack.make_executable()?;
+        }
+        if options.stack_size.is_some() && !options.quiet {
+            println!(
+                "Switching to a {:#x}-byte stack at {:#x}..{:#x}, guard page at {:#x}",
+                size,
+                stack.bottom(),
+                stack.top(),
+                stack.guard()
+            );
+        }
+        let entry = process.addr(file.entry_point);
+        let program = stack::Program {
+            entry,
+            phdr: file
+                .program_headers_addr()
+                .map_or(0, |addr| process.addr(addr)),
+            phent: file.program_header_info.size,
+            phnum: file.program_header_info.count,
+            execfn: path,
+        };
+        let args: Vec<Vec<u8>> = std::iter::once(path)
+            .chain(options.args.iter().map(String::as_str))
+            .map(|arg| arg.as_bytes().to_vec())
+            .collect();
+        let sp = stack.frame(&program, &args, &options.env)?.sp;
+        let (argv, envp) = (sp + 8, sp + 8 * (args.len() as u64 + 2));
+
+        // The entry point never returns, destructors run when the program exits instead. elk has
+        // no hold on a child by then.
+        let startup = process.startup();
+        // Lazy binding only happens in elk's own address space
+        let binds = (options.profile && child.is_none()).then(|| process.lazy_binds());
+        let fini = match &child {
+            Some(_) if !startup.finalizers.is_empty() => {
+                eprintln!(
+                    "Warning: {} destructors won't run, elk can't call them in the child",
+                    startup.finalizers.len()
+                );
+                0
+            }
+            Some(_) => 0,
+            None => startup.fini(binds, options.quiet),
+        };
+        if let (Some(child), Some(tp)) = (&child, startup.thread_pointer) {
+            child.set_thread_pointer(tp);
+        }
+        // Constructors get the program's argc, argv and envp, as from ld.so
+        startup.initialize(&*space, args.len() as u64, argv, envp, options.quiet)?;
+        match child {
+            Some(child) if options.suspended => {
+                child.detach_stopped(entry, sp, fini)?;
+                // On stderr so it survives `--quiet`
+                eprintln!(
+                    "Child {} is stopped at its entry point {:#x}, attach a debugger or resume it \
+                     with `kill -CONT {}`",
+                    child.pid(),
+                    entry,
+                    child.pid()
+                );
+                Ok(())
+            }
+            Some(child) => exit_like(child.start(entry, sp, fini)?, options.quiet),
+            None => unsafe { stack::jmp_on_stack(entry, sp, fini) },
+        }
     } else {
-        process::exit(1);
+        process::exit(Status::Parse as i32);
     }
+}
 
-    Ok(())
+// `elk run --repeat`: runs the loaded program `runs` times from a snapshot and reports how
+// long each run took to get to the entry point
+fn repeat(
+    process: &Process,
+    file: &FileHeader,
+    path: &str,
+    options: &RunOptions,
+    runs: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut snapshot = process.snapshot(file, path)?;
+    snapshot.stack_size = options.stack_size.unwrap_or(stack::DEFAULT_SIZE);
+    snapshot.poison_stack = options.poison_stack;
+    snapshot.env = options.env.clone();
+    let results = (0..runs)
+        .map(|_| snapshot.run(&options.args))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let failed = results.iter().filter(|run| run.status != 0).count();
+    if !options.quiet {
+        let status = |status: c_int| match libc::WIFSIGNALED(status) {
+            true => format!("signal {}", libc::WTERMSIG(status)),
+            false => format!("exit {}", libc::WEXITSTATUS(status)),
+        };
+        let table = tables::Table {
+            header: format!("{} runs of {}", runs, path),
+            labels: vec![
+                "Run".into(),
+                "Status".into(),
+                "Overhead".into(),
+                "Elapsed".into(),
+            ],
+            rows: results
+                .iter()
+                .enumerate()
+                .map(|(i, run)| {
+                    vec![
+                        (i + 1).to_string(),
+                        status(run.status),
+                        format!("{:?}", run.overhead),
+                        format!("{:?}", run.elapsed),
+                    ]
+                })
+                .collect(),
+        };
+        table.print();
+        if let (Some(min), Some(max)) = (
+            results.iter().map(|run| run.overhead).min(),
+            results.iter().map(|run| run.overhead).max(),
+        ) {
+            let total: std::time::Duration = results.iter().map(|run| run.overhead).sum();
+            println!(
+                "Overhead per run: min {:?}, mean {:?}, max {:?}",
+                min,
+                total / runs as u32,
+                max
+            );
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(Failure::findings(format!("{} of {} runs failed", failed, runs)).into()),
+    }
 }
 
-fn _align_up(addr: usize, align: usize) -> usize {
-    let aligned = (addr + align - 1) & !(align - 1);
-    aligned
+// Returns in the child; the parent waits for it and then exits with its status, or with
+// Status::Crashed if a signal killed it
+#[cfg(feature = "sandbox")]
+unsafe fn fork_and_wait(quiet: bool) -> Result<(), Box<dyn Error>> {
+    io::stdout().flush()?;
+    let child = match libc::fork() {
+        -1 => return Err(io::Error::last_os_error().into()),
+        0 => return Ok(()),
+        child => child,
+    };
+    let mut status = 0;
+    if libc::waitpid(child, &mut status, 0) < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    exit_like(status, quiet)
+}
+
+#[cfg(not(feature = "sandbox"))]
+unsafe fn fork_and_wait(_: bool) -> Result<(), Box<dyn Error>> {
+    Err("--fork and --sandbox need elk built with the sandbox feature".into())
+}
+
+// Exits with the status of a child that exited with wait status `status`, or with
+// Status::Crashed if a signal killed it
+fn exit_like(status: c_int, quiet: bool) -> ! {
+    if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        if !quiet {
+            eprintln!("Program killed by signal {}", signal);
+        }
+        process::exit(Status::Crashed as i32);
+    }
+    let code = libc::WEXITSTATUS(status);
+    if !quiet {
+        eprintln!("Program exited with status {}", code);
+    }
+    process::exit(code);
 }
 
-fn align_down(addr: usize, align: usize) -> usize {
-    let aligned = addr & !(align - 1);
+fn _align_up(addr: usize, align: usize) -> usize {
+    let aligned = (addr + align - 1) & !(align - 1);
     aligned
 }
 
@@ -141,24 +1436,33 @@ fn _pause(msg: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-unsafe fn jmp(addr: *const u8) {
-    let fptr: fn() = transmute(addr);
-    fptr();
-}
-
-fn ndisasm(input: &[u8], entry_offset: Addr) -> Result<(), Box<dyn Error>> {
-    let mut proc = Command::new("ndisasm")
-        .arg("-b")
-        .arg("64")
-        .arg("-s")
-        .arg(entry_offset.0.to_string())
-        .arg("-")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    proc.stdin.as_mut().unwrap().write_all(input)?;
-    let res = proc.wait_with_output()?;
-    println!("{}", String::from_utf8_lossy(&res.stdout));
+// Disassembles a segment loaded at `origin`, with a line naming each of `labels` above the
+// instruction at its address, followed by the label's note if it has one
+fn disasm(
+    machine: Machine,
+    input: &[u8],
+    origin: Addr,
+    entry_offset: Addr,
+    labels: &[(Addr, String, &str)],
+) -> Result<(), Box<dyn Error>> {
+    let listing = disasm_listing(
+        machine,
+        input,
+        origin.0,
+        &["-s", &entry_offset.0.to_string()],
+    )?;
+    for line in listing.lines() {
+        let addr = line
+            .split_whitespace()
+            .next()
+            .and_then(|addr| u64::from_str_radix(addr, 16).ok());
+        for (_, label, note) in labels.iter().filter(|(at, _, _)| Some(at.0) == addr) {
+            match note.is_empty() {
+                true => println!("{}:", label),
+                false => println!("{}:  ; {}", label, note),
+            }
+        }
+        println!("{}", line);
+    }
     Ok(())
 }
@@ -0,0 +1,113 @@
+use std::{error::Error, fs};
+
+use delf::{detect::Class, types::*, FileHeader};
+
+use crate::{exit::Failure, parse_number, relocs, source};
+
+// A single value to clap, which would take a Vec<u8> for a list of byte arguments
+type Bytes = Vec<u8>;
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Overwrite the bytes loaded at an address and write the result to a new file")]
+pub struct Args {
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_parser = parse_number,
+        help = "Address of the first byte to overwrite"
+    )]
+    at: u64,
+    #[arg(
+        long,
+        value_name = "HEX",
+        value_parser = parse_hex,
+        required_unless_present = "from",
+        conflicts_with = "from",
+        help = "Bytes to write, as hex digits, spaces allowed: \"90 90\""
+    )]
+    bytes: Option<Bytes>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Write the contents of this file instead"
+    )]
+    from: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Where to write the patched binary"
+    )]
+    write: String,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+fn parse_hex(value: &str) -> Result<Bytes, String> {
+    let digits: String = value.split_whitespace().collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {:?}", value));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|e| format!("invalid hex {:?}: {}", value, e))
+        })
+        .collect()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let bytes = match (args.bytes, &args.from) {
+        (Some(bytes), _) => bytes,
+        (None, from) => fs::read(from.as_deref().unwrap_or_default())?,
+    };
+
+    let plan = file
+        .patch_at(Addr(args.at), &bytes)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    // Relocated slots get overwritten again at load time, whatever the file holds
+    let slot = match file.class {
+        Class::Elf32 => 4,
+        Class::Elf64 => 8,
+    };
+    let at = args.at;
+    let end = at.checked_add(bytes.len() as u64).ok_or_else(|| {
+        Failure::parse(format!(
+            "{}: {:#x} bytes at {:#x} wrap around",
+            path,
+            bytes.len(),
+            at
+        ))
+    })?;
+    let patched = at..end;
+    for reloc in relocs::annotate(&file)? {
+        let overlaps = reloc
+            .offset
+            .checked_add(slot)
+            .is_some_and(|slot_end| reloc.offset < patched.end && patched.start < slot_end);
+        if overlaps {
+            eprintln!(
+                "{:#x} is a {} relocation slot, the loader will overwrite it",
+                reloc.offset, reloc.typ
+            );
+        }
+    }
+    fs::write(&args.write, plan.apply(&input)?)?;
+    // Keep an executable executable
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(&args.write, metadata.permissions())?;
+    }
+    println!(
+        "Wrote {} with {} bytes patched at {:#x}",
+        args.write,
+        bytes.len(),
+        args.at
+    );
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use std::sync::{RwLock, RwLockReadGuard};
+
+use delf::FileHeader;
+
+use crate::loader::Process;
+
+// What an analysis found, one line per problem. Empty means the file passes.
+pub type Findings = Vec<String>;
+
+// A check shipped outside elk. Registered analyses run in `elk check` next to the built-in
+// rules, under their own name, and show up in its table and JSON.
+pub trait Analysis: Send + Sync {
+    // Rule id in reports, such as `acme/fortify`
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+    fn run(&self, file: &FileHeader) -> Findings;
+    // For checks that need the loaded image, run by `elk run` once everything is mapped and
+    // relocated
+    fn run_process(&self, _process: &Process) -> Findings {
+        Vec::new()
+    }
+}
+
+static ANALYSES: RwLock<Vec<Box<dyn Analysis>>> = RwLock::new(Vec::new());
+
+// Adds `analysis` to every later `elk check` and `elk run`. Call it before handing the parsed
+// arguments to the command's `run`.
+pub fn register(analysis: Box<dyn Analysis>) {
+    ANALYSES.write().unwrap().push(analysis);
+}
+
+pub fn analyses() -> RwLockReadGuard<'static, Vec<Box<dyn Analysis>>> {
+    ANALYSES.read().unwrap()
+}
@@ -0,0 +1,574 @@
+use std::{collections::BTreeMap, convert::TryInto, fmt};
+
+use delf::{detect::Class, types::*, FileHeader};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    deps,
+    error::LoadError,
+    loader::{LoadOptions, Process},
+    source,
+};
+
+const DT_RELA: u64 = 7;
+const TLSDESC: u32 = 36;
+// The version glibc's libc and ld.so share their internals under
+const GLIBC_PRIVATE: &str = "GLIBC_PRIVATE";
+
+// Something an object needs that elk's loader doesn't do. Loading it either fails, or works and
+// leaves the program to crash once it runs.
+#[derive(Debug, Clone)]
+pub enum Unsupported {
+    Class {
+        object: String,
+        class: Class,
+    },
+    Machine {
+        object: String,
+        machine: Machine,
+    },
+    // A relocation type the loader has no formula for, by raw number
+    Relocation {
+        object: String,
+        typ: u32,
+        count: usize,
+    },
+    // Relocations in read-only segments, unless loading with `allow_textrel`
+    TextRel {
+        object: String,
+        count: usize,
+    },
+    Library {
+        object: String,
+        name: String,
+    },
+    // References to what glibc's ld.so sets up for libc before any code runs, which elk maps
+    // but never initializes
+    LoaderInternals {
+        object: String,
+        symbols: Vec<String>,
+    },
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Class { object, class } => {
+                write!(
+                    f,
+                    "{} is a {} object, elk only loads 64-bit ones",
+                    object, class
+                )
+            }
+            Self::Machine { object, machine } => write!(
+                f,
+                "{} is built for {:?}, elk only loads x86-64 ones",
+                object, machine
+            ),
+            Self::Relocation { object, typ, count } => write!(
+                f,
+                "{} has {} {} relocations, which elk can't apply",
+                object,
+                count,
+                relocation_name(*typ)
+            ),
+            Self::TextRel { object, count } => write!(
+                f,
+                "{} has {} relocations in read-only segments, which elk only applies with \
+                 --allow-textrel",
+                object, count
+            ),
+            Self::Library { object, name } => write!(
+                f,
+                "{} needs {}, which isn't in any directory elk searches",
+                object, name
+            ),
+            Self::LoaderInternals { object, symbols } => {
+                let shown = symbols.iter().take(3).cloned().collect::<Vec<_>>();
+                let more = match symbols.len().saturating_sub(shown.len()) {
+                    0 => String::new(),
+                    n => format!(" and {} more", n),
+                };
+                write!(
+                    f,
+                    "{} relies on glibc's ld.so having set up {}{}, which elk doesn't do",
+                    object,
+                    shown.join(", "),
+                    more
+                )
+            }
+        }
+    }
+}
+
+// How much of one of ld.so's behaviors elk has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fidelity {
+    Full,
+    // Some cases, `notes` says which
+    Partial,
+    // Only when asked for
+    OptIn,
+    Missing,
+}
+
+impl fmt::Display for Fidelity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Full => "full",
+            Self::Partial => "partial",
+            Self::OptIn => "opt-in",
+            Self::Missing => "missing",
+        })
+    }
+}
+
+// What a check looks at: one object, along with everything loaded with it
+pub struct Subject<'a> {
+    pub object: &'a deps::Object,
+    pub objects: &'a [deps::Object],
+    pub options: &'a LoadOptions,
+}
+
+// One thing glibc's ld.so does for a program, and how much of it elk does. `check`, where there
+// is one, lists the objects needing more of it than elk has; the preflight is those checks run on
+// every object.
+pub struct Capability {
+    pub id: &'static str,
+    pub behavior: &'static str,
+    pub fidelity: Fidelity,
+    pub notes: &'static str,
+    pub check: Option<fn(&Subject) -> Vec<Unsupported>>,
+}
+
+pub const CAPABILITIES: &[Capability] = &[
+    Capability {
+        id: "machine",
+        behavior: "Loads objects built for the machine it runs on",
+        fidelity: Fidelity::Partial,
+        notes: "64-bit x86-64 objects only, whatever the host",
+        check: Some(check_machine),
+    },
+    Capability {
+        id: "library-search",
+        behavior: "Finds DT_NEEDED libraries through DT_RPATH, LD_LIBRARY_PATH, DT_RUNPATH, \
+                   ld.so.conf and the default directories, expanding $ORIGIN, $LIB and $PLATFORM",
+        fidelity: Fidelity::Partial,
+        notes: "ld.so.cache and glibc-hwcaps subdirectories aren't consulted; the config file's \
+                library_path comes first",
+        check: Some(check_libraries),
+    },
+    Capability {
+        id: "preload",
+        behavior: "Loads LD_PRELOAD and /etc/ld.so.preload libraries ahead of the program's",
+        fidelity: Fidelity::Missing,
+        notes: "",
+        check: None,
+    },
+    Capability {
+        id: "relocations",
+        behavior: "Applies every x86-64 dynamic relocation type",
+        fidelity: Fidelity::Partial,
+        notes: "R_X86_64_64, COPY, GLOB_DAT, JUMP_SLOT, RELATIVE, DTPMOD64, DTPOFF64, TPOFF64 \
+                and IRELATIVE",
+        check: Some(check_relocations),
+    },
+    Capability {
+        id: "tls-descriptors",
+        behavior: "Resolves TLS descriptors (R_X86_64_TLSDESC)",
+        fidelity: Fidelity::Missing,
+        notes: "",
+        check: Some(check_tls_descriptors),
+    },
+    Capability {
+        id: "text-relocations",
+        behavior: "Relocates read-only segments, making them writable meanwhile",
+        fidelity: Fidelity::OptIn,
+        notes: "With --allow-textrel",
+        check: Some(check_text_relocations),
+    },
+    Capability {
+        id: "symbol-versions",
+        behavior: "Binds references to the symbol version they ask for",
+        fidelity: Fidelity::Partial,
+        notes: "Objects defining the name under other versions only are passed over, and \
+                binding fails when no other has it; DT_VERNEED isn't checked against the \
+                libraries at load time",
+        check: None,
+    },
+    Capability {
+        id: "lazy-binding",
+        behavior: "Binds PLT slots on first call",
+        fidelity: Fidelity::Partial,
+        notes: "Programs loaded into a child have every slot bound at load time, as with \
+                --bind-now or LD_BIND_NOW",
+        check: None,
+    },
+    Capability {
+        id: "ifunc",
+        behavior: "Calls IFUNC resolvers for IRELATIVE relocations",
+        fidelity: Fidelity::Full,
+        notes: "Once all of the object's segments are mapped",
+        check: None,
+    },
+    Capability {
+        id: "static-tls",
+        behavior: "Sets up thread-local storage and the thread pointer",
+        fidelity: Fidelity::Partial,
+        notes: "For objects loaded with the program; ones opened later can't have thread-locals",
+        check: None,
+    },
+    Capability {
+        id: "relro",
+        behavior: "Makes PT_GNU_RELRO read-only once relocated",
+        fidelity: Fidelity::Full,
+        notes: "",
+        check: None,
+    },
+    Capability {
+        id: "initializers",
+        behavior: "Runs DT_PREINIT_ARRAY, DT_INIT and DT_INIT_ARRAY, then the finalizers at exit",
+        fidelity: Fidelity::Partial,
+        notes: "Legacy .ctors and .dtors of objects without DT_INIT and DT_FINI too; finalizers \
+                don't run for programs in a child",
+        check: None,
+    },
+    Capability {
+        id: "executable-stack",
+        behavior: "Maps the stack executable when an object's PT_GNU_STACK asks for it",
+        fidelity: Fidelity::Full,
+        notes: "",
+        check: None,
+    },
+    Capability {
+        id: "auxv",
+        behavior: "Passes the program the auxiliary vector",
+        fidelity: Fidelity::Full,
+        notes: "AT_BASE is 0, as for a program without an interpreter",
+        check: None,
+    },
+    Capability {
+        id: "debugger-interface",
+        behavior: "Keeps r_debug and the link map current and fills in DT_DEBUG",
+        fidelity: Fidelity::Full,
+        notes: "",
+        check: None,
+    },
+    Capability {
+        id: "dlopen",
+        behavior: "Opens libraries at runtime with dlopen and dlmopen",
+        fidelity: Fidelity::Partial,
+        notes: "For embedders through Process; the program's own calls go to glibc, which needs \
+                ld.so",
+        check: None,
+    },
+    Capability {
+        id: "ld-so-internals",
+        behavior: "Sets up _rtld_global and the rest of ld.so's private interface to libc",
+        fidelity: Fidelity::Missing,
+        notes: "",
+        check: Some(check_internals),
+    },
+];
+
+impl Unsupported {
+    // The entry of CAPABILITIES whose check found this
+    pub fn capability(&self) -> &'static Capability {
+        let id = match self {
+            Self::Class { .. } | Self::Machine { .. } => "machine",
+            Self::Relocation { typ: TLSDESC, .. } => "tls-descriptors",
+            Self::Relocation { .. } => "relocations",
+            Self::TextRel { .. } => "text-relocations",
+            Self::Library { .. } => "library-search",
+            Self::LoaderInternals { .. } => "ld-so-internals",
+        };
+        CAPABILITIES
+            .iter()
+            .find(|capability| capability.id == id)
+            .expect("every id is in CAPABILITIES")
+    }
+}
+
+impl Unsupported {
+    // Whether elk run refuses the program over this without --force. Libraries it can't find
+    // only leave their symbols unresolved, which it has always gone ahead with.
+    pub fn refuses(&self) -> bool {
+        !matches!(self, Self::Library { .. })
+    }
+}
+
+impl Process {
+    // What keeps the program at `path` and the libraries it needs from running under elk, with
+    // the default load options. Empty when nothing does, as far as the files tell.
+    pub fn preflight(path: &str) -> Result<Vec<Unsupported>, LoadError> {
+        Self::preflight_with(path, &LoadOptions::default())
+    }
+
+    pub fn preflight_with(
+        path: &str,
+        options: &LoadOptions,
+    ) -> Result<Vec<Unsupported>, LoadError> {
+        let input = source::read(path).map_err(|e| LoadError::Open {
+            path: path.to_string(),
+            reason: e.to_string(),
+        })?;
+        let file = FileHeader::parse_or_describe(&input).map_err(|reason| LoadError::Parse {
+            object: path.to_string(),
+            reason,
+        })?;
+        Ok(Self::preflight_objects(&deps::objects(path, file), options))
+    }
+
+    // `objects` as `deps::objects` lists them, the program first
+    pub fn preflight_objects(objects: &[deps::Object], options: &LoadOptions) -> Vec<Unsupported> {
+        let mut unsupported = Vec::new();
+        for object in objects {
+            let subject = Subject {
+                object,
+                objects,
+                options,
+            };
+            for check in CAPABILITIES.iter().filter_map(|c| c.check) {
+                unsupported.extend(check(&subject));
+            }
+        }
+        unsupported
+    }
+}
+
+fn check_machine(subject: &Subject) -> Vec<Unsupported> {
+    let (object, file) = (subject.object.name.clone(), &subject.object.file);
+    if file.class != Class::Elf64 {
+        return vec![Unsupported::Class {
+            object,
+            class: file.class,
+        }];
+    }
+    if file.machine != Machine::X86_64 {
+        return vec![Unsupported::Machine {
+            object,
+            machine: file.machine,
+        }];
+    }
+    Vec::new()
+}
+
+fn check_libraries(subject: &Subject) -> Vec<Unsupported> {
+    subject
+        .object
+        .file
+        .dynamic_strings(DynamicTag::Needed)
+        .into_iter()
+        .filter(|name| !subject.objects.iter().any(|o| &o.name == name))
+        .map(|name| Unsupported::Library {
+            object: subject.object.name.clone(),
+            name,
+        })
+        .collect()
+}
+
+fn check_relocations(subject: &Subject) -> Vec<Unsupported> {
+    unknown_relocations(subject, |typ| typ != TLSDESC)
+}
+
+fn check_tls_descriptors(subject: &Subject) -> Vec<Unsupported> {
+    unknown_relocations(subject, |typ| typ == TLSDESC)
+}
+
+// Counts of the relocation types `wanted` picks that the loader has no formula for
+fn unknown_relocations(subject: &Subject, wanted: impl Fn(u32) -> bool) -> Vec<Unsupported> {
+    let mut unknown: BTreeMap<u32, usize> = BTreeMap::new();
+    for (_, typ) in raw_relocations(&subject.object.file) {
+        let known = RelType::from_number(Machine::X86_64, Class::Elf64, typ);
+        if typ != 0 && wanted(typ) && matches!(known, RelType::Unknown(_)) {
+            *unknown.entry(typ).or_default() += 1;
+        }
+    }
+    unknown
+        .into_iter()
+        .map(|(typ, count)| Unsupported::Relocation {
+            object: subject.object.name.clone(),
+            typ,
+            count,
+        })
+        .collect()
+}
+
+fn check_text_relocations(subject: &Subject) -> Vec<Unsupported> {
+    let file = &subject.object.file;
+    let count = raw_relocations(file)
+        .into_iter()
+        .filter(|&(offset, _)| read_only(file, offset))
+        .count();
+    match count {
+        0 => Vec::new(),
+        _ if subject.options.allow_textrel => Vec::new(),
+        count => vec![Unsupported::TextRel {
+            object: subject.object.name.clone(),
+            count,
+        }],
+    }
+}
+
+fn check_internals(subject: &Subject) -> Vec<Unsupported> {
+    let file = &subject.object.file;
+    let symbols: Vec<String> = file
+        .section_headers
+        .iter()
+        .position(|sh| sh.typ == SectionType::DynSym)
+        .map(|index| file.symbols_in(index))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|sym| sym.shndx == SectionIdx::Undef)
+        .filter(|sym| {
+            sym.version
+                .as_ref()
+                .is_some_and(|v| v.file.is_some() && v.name == GLIBC_PRIVATE)
+        })
+        .map(|sym| sym.name)
+        .collect();
+    match symbols.len() {
+        0 => Vec::new(),
+        _ => vec![Unsupported::LoaderInternals {
+            object: subject.object.name.clone(),
+            symbols,
+        }],
+    }
+}
+
+// Offset and type number of every entry of the DT_RELA and DT_JMPREL tables, straight from the
+// tables. Empty for objects of other machines, which the loader refuses anyway.
+fn raw_relocations(file: &FileHeader) -> Vec<(u64, u32)> {
+    if file.class != Class::Elf64 || file.machine != Machine::X86_64 {
+        return Vec::new();
+    }
+    let mut tables: Vec<(Addr, Addr)> = file
+        .dynamic_entries(DynamicTag::Rela)
+        .zip(file.dynamic_entries(DynamicTag::RelaSz))
+        .collect();
+    if file.dynamic_entry(DynamicTag::PltRel) == Some(Addr(DT_RELA)) {
+        tables.extend(
+            file.dynamic_entries(DynamicTag::JmpRel)
+                .zip(file.dynamic_entries(DynamicTag::PltRelSz)),
+        );
+    }
+    let mut relocations = Vec::new();
+    for (start, size) in tables {
+        let bytes = file.bytes_at(start).unwrap_or_default();
+        let bytes = &bytes[..bytes.len().min(size.0 as usize)];
+        for entry in bytes.chunks_exact(RelaEntry::SIZE) {
+            let offset = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let info = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+            relocations.push((offset, info as u32));
+        }
+    }
+    relocations
+}
+
+fn read_only(file: &FileHeader, addr: u64) -> bool {
+    file.segments_of_type(SegmentType::Load)
+        .find(|ph| ph.mem_range().contains(&Addr(addr)))
+        .is_some_and(|ph| !ph.flags.contains(SegmentFlags::Write))
+}
+
+// The x86-64 types compilers emit that elk has no formula for, by name
+fn relocation_name(typ: u32) -> String {
+    let name = match typ {
+        2 => "R_X86_64_PC32",
+        10 => "R_X86_64_32",
+        11 => "R_X86_64_32S",
+        21 => "R_X86_64_DTPOFF32",
+        23 => "R_X86_64_TPOFF32",
+        24 => "R_X86_64_PC64",
+        TLSDESC => "R_X86_64_TLSDESC",
+        _ => return format!("type {}", typ),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/");
+
+    fn sample(name: &str) -> Vec<deps::Object> {
+        let path = format!("{}{}", SAMPLES, name);
+        let input = source::read(&path).unwrap();
+        deps::objects(&path, FileHeader::parse_or_describe(&input).unwrap())
+    }
+
+    fn ids(unsupported: &[Unsupported]) -> Vec<&'static str> {
+        unsupported.iter().map(|u| u.capability().id).collect()
+    }
+
+    #[test]
+    fn capabilities_have_one_entry_each() {
+        for (i, capability) in CAPABILITIES.iter().enumerate() {
+            assert!(!CAPABILITIES[..i].iter().any(|c| c.id == capability.id));
+        }
+    }
+
+    #[test]
+    fn ladder_rungs_need_nothing_elk_lacks() {
+        for rung in ["1-static", "2-pie", "3-needed", "4-backref", "5-lazy"] {
+            let objects = sample(&format!("ladder/{}", rung));
+            let unsupported = Process::preflight_objects(&objects, &LoadOptions::default());
+            assert!(unsupported.is_empty(), "{}: {:?}", rung, unsupported);
+        }
+    }
+
+    #[test]
+    fn findings_name_the_capability_that_checks_them() {
+        let objects = sample("ladder/3-needed");
+        let unsupported = Process::preflight_objects(&objects[..1], &LoadOptions::default());
+        assert_eq!(ids(&unsupported), ["library-search"]);
+        assert!(unsupported[0].to_string().contains("libgreet.so"));
+        assert!(!unsupported[0].refuses());
+
+        let objects = sample("hello-mov-pie");
+        let unsupported = Process::preflight_objects(&objects, &LoadOptions::default());
+        assert_eq!(ids(&unsupported), ["text-relocations"]);
+        assert!(unsupported[0].refuses());
+        let options = LoadOptions {
+            allow_textrel: true,
+            ..Default::default()
+        };
+        assert!(Process::preflight_objects(&objects, &options).is_empty());
+
+        for unsupported in [
+            Unsupported::Relocation {
+                object: "a".into(),
+                typ: TLSDESC,
+                count: 1,
+            },
+            Unsupported::Relocation {
+                object: "a".into(),
+                typ: 2,
+                count: 1,
+            },
+        ] {
+            let capability = unsupported.capability();
+            assert_ne!(capability.fidelity, Fidelity::Full);
+            assert!(capability.check.is_some());
+        }
+    }
+
+    // glibc's libc takes what ld.so set up for it, so programs linked against it dynamically
+    // are refused; where the system has no libc.so.6, only missing it is found
+    #[test]
+    fn glibc_programs_are_refused() {
+        let objects = sample("entry_point");
+        let unsupported = Process::preflight_objects(&objects, &LoadOptions::default());
+        match objects.iter().any(|o| o.name == "libc.so.6") {
+            true => {
+                assert_eq!(ids(&unsupported), ["ld-so-internals"]);
+                assert!(unsupported[0].refuses());
+                assert!(unsupported[0].to_string().contains("libc.so.6"));
+            }
+            false => assert!(unsupported.iter().all(|u| !u.refuses())),
+        }
+    }
+}
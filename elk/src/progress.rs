@@ -0,0 +1,113 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    os::raw::c_int,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// Work done sooner than this never shows a bar
+const DELAY: Duration = Duration::from_millis(500);
+const REDRAW: Duration = Duration::from_millis(100);
+const WIDTH: u64 = 30;
+
+static SILENCED: AtomicBool = AtomicBool::new(false);
+
+// No bars for the rest of the run: --no-progress, JSON output, or stdout already saying as much
+pub fn silence() {
+    SILENCED.store(true, Ordering::Relaxed);
+}
+
+// Points stdout at /dev/null until restored. Child processes such as ndisasm inherit it too.
+pub struct Silenced(c_int);
+
+impl Silenced {
+    pub fn new() -> io::Result<Self> {
+        io::stdout().flush()?;
+        unsafe {
+            let saved = libc::dup(libc::STDOUT_FILENO);
+            let null = libc::open(b"/dev/null\0".as_ptr() as _, libc::O_WRONLY);
+            if saved < 0 || null < 0 || libc::dup2(null, libc::STDOUT_FILENO) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::close(null);
+            Ok(Self(saved))
+        }
+    }
+
+    pub fn restore(self) -> io::Result<()> {
+        io::stdout().flush()?;
+        unsafe {
+            if libc::dup2(self.0, libc::STDOUT_FILENO) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::close(self.0);
+        }
+        Ok(())
+    }
+}
+
+// A bar on stderr for work of `total` steps, which any thread can advance. Only drawn on a
+// terminal, once the work has gone on for a while, and erased again when dropped.
+pub struct Progress {
+    label: String,
+    total: u64,
+    done: AtomicU64,
+    started: Instant,
+    // When it was last drawn; None for a hidden bar
+    drawn: Option<Mutex<Option<Instant>>>,
+}
+
+impl Progress {
+    pub fn new(label: impl Into<String>, total: u64) -> Self {
+        let shown = !SILENCED.load(Ordering::Relaxed) && io::stderr().is_terminal();
+        Self {
+            label: label.into(),
+            total,
+            done: AtomicU64::new(0),
+            started: Instant::now(),
+            drawn: shown.then(|| Mutex::new(None)),
+        }
+    }
+
+    pub fn advance(&self, steps: u64) {
+        let done = self.done.fetch_add(steps, Ordering::Relaxed) + steps;
+        let drawn = match &self.drawn {
+            Some(drawn) => drawn,
+            None => return,
+        };
+        let now = Instant::now();
+        if now - self.started < DELAY {
+            return;
+        }
+        // Held by whichever thread is drawing it right now
+        let mut last = match drawn.try_lock() {
+            Ok(last) => last,
+            Err(_) => return,
+        };
+        if last.is_some_and(|last| now - last < REDRAW) && done < self.total {
+            return;
+        }
+        *last = Some(now);
+        let filled = done.min(self.total) * WIDTH / self.total.max(1);
+        eprint!(
+            "\r\x1b[K{} [{}{}] {}/{}",
+            self.label,
+            "=".repeat(filled as usize),
+            " ".repeat((WIDTH - filled) as usize),
+            done,
+            self.total
+        );
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let drawn = self.drawn.as_ref().and_then(|drawn| *drawn.lock().ok()?);
+        if drawn.is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::error::Error;
+
+use delf::{note, signature, FileHeader};
+use serde_json::Value;
+
+use crate::{exit::Failure, source, tables::Table};
+
+const GO_BUILDINFO_MAGIC: &[u8] = b"\xff Go buildinf:";
+const RUSTC_PATH: &[u8] = b"/rustc/";
+
+struct Finding {
+    source: String,
+    detail: String,
+    // Compiler or linker this finding points to, for the summary line
+    producer: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Report which toolchain produced binaries")]
+pub struct Args {
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    files: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    for path in &args.files {
+        let input = source::read(path)?;
+        let file = FileHeader::parse_or_describe(&input)
+            .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+        let findings = findings(&file, &input);
+
+        let mut producers: Vec<&str> = Vec::new();
+        for p in findings.iter().filter_map(|f| f.producer.as_deref()) {
+            // A bare "rustc" adds nothing once a versioned "rustc 1.x" is known
+            if !producers.iter().any(|known| known.starts_with(p)) {
+                producers.push(p);
+            }
+        }
+        let table = Table {
+            header: format!("Provenance of {}", path),
+            labels: vec!["Source".into(), "Detail".into()],
+            rows: findings
+                .iter()
+                .map(|f| vec![f.source.clone(), f.detail.clone()])
+                .collect(),
+        };
+        table.print();
+        match producers.len() {
+            0 => println!("Toolchain: unknown (no producer information found)"),
+            _ => println!("Toolchain: {}", producers.join(", ")),
+        }
+    }
+    Ok(())
+}
+
+fn findings(file: &FileHeader, input: &[u8]) -> Vec<Finding> {
+    let mut out = Vec::new();
+    let section = |name: &str| file.section_by_name(name).map(|sh| &sh.data[..]);
+
+    // One NUL separated entry per toolchain component that touched the file
+    for s in section(".comment").map(strings).unwrap_or_default() {
+        out.push(Finding {
+            source: ".comment".into(),
+            producer: producer(&s),
+            detail: s,
+        });
+    }
+    // -frecord-gcc-switches
+    if let Some(data) = section(".GCC.command.line") {
+        for s in strings(data) {
+            out.push(Finding {
+                source: ".GCC.command.line".into(),
+                detail: s,
+                producer: None,
+            });
+        }
+    }
+
+    for (source, n) in file.notes() {
+        let detail = match n.describe(file.class, file.machine) {
+            Some(detail) => detail,
+            None => continue,
+        };
+        let label = match (n.name.as_str(), n.typ) {
+            ("GNU", note::NT_GNU_BUILD_ID) => "GNU build ID",
+            ("GNU", note::NT_GNU_ABI_TAG) => "ABI tag",
+            ("Go", note::NT_GO_BUILD_ID) => "Go build ID",
+            ("GNU", note::NT_GNU_PROPERTY_TYPE_0) => "GNU properties",
+            ("FDO", note::NT_FDO_PACKAGING_METADATA) => "package",
+            _ => "note",
+        };
+        let detail = match label {
+            "package" => package(&detail).unwrap_or(detail),
+            _ => detail,
+        };
+        out.push(Finding {
+            source,
+            detail: format!("{}: {}", label, detail),
+            producer: None,
+        });
+    }
+
+    // sign-file's, past the end of a kernel module
+    let signature = signature::appended(input).map(|found| {
+        found.map(|signature| {
+            let mut detail = format!(
+                "{} signature, {}",
+                signature.id_type_name(),
+                signature.digest.unwrap_or("unknown digest")
+            );
+            if let Some(signer) = &signature.signer {
+                detail += &format!(", signed by {}", signer);
+            }
+            if let Some(key_id) = &signature.key_id {
+                detail += &format!(", key {}", key_id);
+            }
+            detail += &format!(
+                "; payload 0x0..{:#x}, signature {:#x}..{:#x}",
+                signature.payload_len,
+                signature.payload_len,
+                input.len()
+            );
+            match signature.check(file) {
+                Ok(()) => detail,
+                Err(e) => format!("{}; {}", detail, e),
+            }
+        })
+    });
+    let detail = match signature {
+        Ok(Some(detail)) => Some(detail),
+        Ok(None) => None,
+        Err(e) => Some(e.to_string()),
+    };
+    if let Some(detail) = detail {
+        out.push(Finding {
+            source: "module signature".into(),
+            detail,
+            producer: None,
+        });
+    }
+
+    if let Some(version) = section(".go.buildinfo").and_then(go_version) {
+        out.push(Finding {
+            source: ".go.buildinfo".into(),
+            producer: Some(format!("Go {}", version)),
+            detail: version,
+        });
+    }
+
+    // Crate metadata of Rust dylibs and proc macros
+    if let Some(data) = section(".rustc") {
+        out.push(Finding {
+            source: ".rustc".into(),
+            detail: format!(
+                "Rust crate metadata, {}",
+                crate::size::human(data.len() as u64)
+            ),
+            producer: Some("rustc".into()),
+        });
+    }
+    // Panic locations in the standard library embed the commit rustc was built from
+    let commit = [".rodata", ".debug_str", ".debug_line_str"]
+        .iter()
+        .filter_map(|name| section(name))
+        .find_map(rustc_commit);
+    if let Some(commit) = commit {
+        out.push(Finding {
+            source: "std paths".into(),
+            detail: format!("rustc commit {}", commit),
+            producer: Some("rustc".into()),
+        });
+    }
+
+    // DW_AT_producer strings of every compile unit
+    let mut units: Vec<String> = Vec::new();
+    for s in section(".debug_str").map(strings).unwrap_or_default() {
+        if dwarf_producer(&s) && !units.contains(&s) {
+            units.push(s);
+        }
+    }
+    for s in units {
+        out.push(Finding {
+            source: ".debug_str".into(),
+            producer: producer(&s),
+            detail: s,
+        });
+    }
+    out
+}
+
+// "systemd 254.5-1.fc39 (rpm, x86_64) for fedora 39" from .note.package's JSON
+fn package(json: &str) -> Option<String> {
+    let metadata: Value = serde_json::from_str(json).ok()?;
+    let field = |name: &str| metadata.get(name).and_then(Value::as_str);
+    let mut out = field("name")?.to_string();
+    if let Some(version) = field("version") {
+        out += &format!(" {}", version);
+    }
+    let kind: Vec<&str> = ["type", "architecture"]
+        .iter()
+        .filter_map(|name| field(name))
+        .collect();
+    if !kind.is_empty() {
+        out += &format!(" ({})", kind.join(", "));
+    }
+    let os: Vec<&str> = ["os", "osVersion"]
+        .iter()
+        .filter_map(|name| field(name))
+        .collect();
+    if !os.is_empty() {
+        out += &format!(" for {}", os.join(" "));
+    }
+    Some(out)
+}
+
+fn strings(data: &[u8]) -> Vec<String> {
+    data.split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).trim().to_string())
+        .collect()
+}
+
+// Names the compiler or linker behind a .comment or DW_AT_producer string
+fn producer(s: &str) -> Option<String> {
+    let version_after = |prefix: &str| {
+        let rest = &s[s.find(prefix)? + prefix.len()..];
+        rest.split_whitespace().next().map(str::to_string)
+    };
+    if s.starts_with("GCC:") {
+        // "GCC: (Ubuntu 11.4.0-1ubuntu1~22.04) 11.4.0"
+        return Some(match s.rsplit(' ').next() {
+            Some(version) => format!("GCC {}", version),
+            None => "GCC".into(),
+        });
+    }
+    if s.starts_with("GNU ") && dwarf_producer(s) {
+        // "GNU C17 11.4.0 -mtune=generic -O2"
+        let version = s.split_whitespace().nth(2).unwrap_or_default();
+        return Some(format!("GCC {}", version).trim_end().to_string());
+    }
+    if s.contains("clang version") {
+        return Some(format!("clang {}", version_after("clang version ")?));
+    }
+    if s.starts_with("rustc version") {
+        return Some(format!("rustc {}", version_after("rustc version ")?));
+    }
+    if let Some(linker) = s.strip_prefix("Linker:") {
+        // "Linker: LLD 17.0.6 (/checkout/src/llvm-project/llvm ...)"
+        let linker: Vec<_> = linker.split_whitespace().take(2).collect();
+        return Some(linker.join(" "));
+    }
+    if s.starts_with("GNU ld") || s.starts_with("GNU gold") {
+        return Some(s.to_string());
+    }
+    None
+}
+
+fn dwarf_producer(s: &str) -> bool {
+    const LANGUAGES: &[&str] = &["GNU C", "GNU C++", "GNU Fortran", "GNU Go", "GNU AS"];
+    LANGUAGES.iter().any(|l| s.starts_with(l)) || s.contains("clang version")
+}
+
+// Go 1.18+ stores the version as the first varint prefixed string after a 32 byte header
+fn go_version(data: &[u8]) -> Option<String> {
+    if !data.starts_with(GO_BUILDINFO_MAGIC) || data.get(15)? & 0x2 == 0 {
+        return None;
+    }
+    let (len, used) = uvarint(data.get(32..)?)?;
+    let start = 32 + used;
+    let version = data.get(start..start + len as usize)?;
+    Some(String::from_utf8_lossy(version).into_owned())
+}
+
+fn uvarint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &b) in data.iter().enumerate().take(10) {
+        value |= u64::from(b & 0x7f) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn rustc_commit(data: &[u8]) -> Option<String> {
+    data.windows(RUSTC_PATH.len() + 41)
+        .filter(|w| w.starts_with(RUSTC_PATH))
+        .map(|w| &w[RUSTC_PATH.len()..])
+        .find(|hash| hash[40] == b'/' && hash[..40].iter().all(u8::is_ascii_hexdigit))
+        .map(|hash| String::from_utf8_lossy(&hash[..40]).into_owned())
+}
@@ -0,0 +1,68 @@
+use std::{collections::BTreeMap, error::Error, fs};
+
+use serde::{Deserialize, Serialize};
+
+use crate::exit::Failure;
+
+// Every decision the loader made for one program: where each object went, what its symbol
+// references bound to and what each relocation wrote. `run --record` saves one once the program
+// is loaded; `run --replay` hands it back to the loader, which then makes the same decisions
+// whatever the address space or the CPU would have it do this time.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub program: String,
+    // In load order, the main object first
+    pub objects: Vec<Placement>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Placement {
+    pub name: String,
+    // Recordings from before namespaces were kept have everything in the base one
+    #[serde(default)]
+    pub namespace: usize,
+    pub path: Option<String>,
+    pub build_id: Option<String>,
+    pub base: u64,
+    // Symbol references of the object, keyed as `binding` spells them, and the runtime address
+    // each bound to
+    pub bindings: BTreeMap<String, u64>,
+    pub writes: Vec<Write>,
+}
+
+// One relocation slot and the value the loader wrote to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Write {
+    pub addr: u64,
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub value: u64,
+}
+
+impl Recording {
+    pub fn read(path: &str) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text).map_err(|e| Failure::parse(format!("{}: {}", path, e)))?)
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    // Two objects of one name only load into different namespaces
+    pub fn object(&self, name: &str, namespace: usize) -> Option<&Placement> {
+        self.objects
+            .iter()
+            .find(|o| o.name == name && o.namespace == namespace)
+    }
+}
+
+// The key of a reference to `name` in Placement::bindings: `name@version` when it asks for a
+// version, so references to two versions of one symbol are kept apart
+pub fn binding(name: &str, version: Option<&str>) -> String {
+    match version {
+        Some(version) => format!("{}@{}", name, version),
+        None => name.to_string(),
+    }
+}
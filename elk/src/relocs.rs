@@ -0,0 +1,240 @@
+use std::{collections::BTreeMap, error::Error};
+
+use clap_complete::engine::ArgValueCompleter;
+use delf::{types::*, view::AddrView, FileHeader, RelaReadError};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    cli::{self, AddrArgs, FormatArg},
+    exit::Failure,
+    schema, source,
+    tables::Table,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Group {
+    Type,
+    Region,
+    Symbol,
+}
+
+// One RELA entry with the names a reader wants instead of raw indices
+#[derive(Serialize, JsonSchema)]
+pub struct Reloc {
+    pub offset: u64,
+    pub typ: String,
+    // Section the slot lives in, such as .got or .init_array
+    pub region: String,
+    // Symbol the relocation binds, or the one its target lands in for relative relocations
+    pub symbol: Option<String>,
+    pub addend: i64,
+}
+
+#[derive(clap::Args, Debug)]
+struct Filter {
+    #[arg(
+        long = "type",
+        value_name = "TYPE",
+        help = "Only relocations of this type, such as Relative"
+    )]
+    typ: Option<String>,
+    #[arg(
+        long,
+        help = "Only relocations whose slot is in this section",
+        add = ArgValueCompleter::new(cli::section_names)
+    )]
+    region: Option<String>,
+    #[arg(
+        long,
+        help = "Only relocations against this symbol",
+        add = ArgValueCompleter::new(cli::symbol_names)
+    )]
+    symbol: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Summarize relocations by type, target section and symbol")]
+pub struct Args {
+    #[arg(
+        long,
+        value_enum,
+        help = "Summaries to print, by type and region when not given"
+    )]
+    by: Vec<Group>,
+    #[command(flatten)]
+    filter: Filter,
+    #[arg(long, help = "List the individual relocations")]
+    entries: bool,
+    #[command(flatten)]
+    format: FormatArg,
+    #[command(flatten)]
+    addresses: AddrArgs,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+impl Filter {
+    fn is_empty(&self) -> bool {
+        self.typ.is_none() && self.region.is_none() && self.symbol.is_none()
+    }
+
+    fn matches(&self, reloc: &Reloc) -> bool {
+        self.typ
+            .as_ref()
+            .is_none_or(|typ| reloc.typ.eq_ignore_ascii_case(typ))
+            && self
+                .region
+                .as_ref()
+                .is_none_or(|region| &reloc.region == region)
+            && self
+                .symbol
+                .as_ref()
+                .is_none_or(|symbol| reloc.symbol.as_ref() == Some(symbol))
+    }
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let (path, filter, entries) = (&args.file, &args.filter, args.entries);
+    let mut groups = args.by;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let relocs: Vec<_> = annotate(&file)?
+        .into_iter()
+        .filter(|reloc| filter.matches(reloc))
+        .collect();
+
+    if args.format.json() {
+        schema::print_json("relocs", &relocs)?;
+        return Ok(());
+    }
+    let view = args.addresses.view(&file);
+    // Narrowing down to a handful of entries is the point of the filters, so show them
+    if entries || !filter.is_empty() {
+        entry_table(path, &relocs, &view).print();
+    }
+    if groups.is_empty() && !entries {
+        groups = vec![Group::Type, Group::Region];
+    }
+    for group in groups {
+        summary(&relocs, group, &view).print();
+    }
+    Ok(())
+}
+
+pub fn annotate(file: &FileHeader) -> Result<Vec<Reloc>, Box<dyn Error>> {
+    let mut entries = match file.read_rela_entries() {
+        Err(RelaReadError::RelaNotFound) => Vec::new(),
+        entries => entries?,
+    };
+    entries.extend(file.read_plt_rela_entries()?);
+    let syms = file
+        .section_headers
+        .iter()
+        .position(|sh| sh.typ == SectionType::DynSym)
+        .map(|idx| file.symbols_in(idx))
+        .unwrap_or_default();
+
+    Ok(entries
+        .iter()
+        .map(|entry| {
+            let symbol = match entry.sym {
+                0 => file
+                    .symbol_at(Addr(0) + entry.addend)
+                    .map(|(sym, offset)| match offset {
+                        0 => sym.name,
+                        _ => format!("{}+{:#x}", sym.name, offset),
+                    }),
+                index => syms.get(index as usize).map(|sym| sym.name.clone()),
+            };
+            Reloc {
+                offset: entry.offset.0,
+                typ: format!("{:?}", entry.typ),
+                region: region(file, entry.offset),
+                symbol,
+                addend: entry.addend.0,
+            }
+        })
+        .collect())
+}
+
+pub fn region(file: &FileHeader, addr: Addr) -> String {
+    let section = file
+        .section_headers
+        .iter()
+        .filter(|sh| sh.addr.0 != 0)
+        .find(|sh| sh.mem_range().contains(&addr));
+    match section {
+        Some(sh) => sh.name.clone(),
+        None => file
+            .program_headers
+            .iter()
+            .position(|ph| ph.typ == SegmentType::Load && ph.mem_range().contains(&addr))
+            .map_or_else(|| "?".into(), |i| format!("LOAD[{}]", i)),
+    }
+}
+
+// Count and address span of the relocations sharing each key, largest group first
+pub fn summary(relocs: &[Reloc], group: Group, view: &AddrView) -> Table {
+    let mut groups: BTreeMap<String, (usize, u64, u64)> = BTreeMap::new();
+    for reloc in relocs {
+        let key = match group {
+            Group::Type => reloc.typ.clone(),
+            Group::Region => reloc.region.clone(),
+            Group::Symbol => reloc.symbol.clone().unwrap_or_else(|| "-".into()),
+        };
+        let entry = groups.entry(key).or_insert((0, reloc.offset, reloc.offset));
+        entry.0 += 1;
+        entry.1 = entry.1.min(reloc.offset);
+        entry.2 = entry.2.max(reloc.offset);
+    }
+    let mut rows: Vec<_> = groups.into_iter().collect();
+    rows.sort_by_key(|(_, (count, _, _))| std::cmp::Reverse(*count));
+
+    Table {
+        header: format!("{} relocations by {:?}", relocs.len(), group),
+        labels: vec![
+            format!("{:?}", group),
+            "Count".into(),
+            "Lowest slot".into(),
+            "Highest slot".into(),
+        ],
+        rows: rows
+            .into_iter()
+            .map(|(key, (count, low, high))| {
+                vec![
+                    key,
+                    count.to_string(),
+                    view.show(Addr(low)),
+                    view.show(Addr(high)),
+                ]
+            })
+            .collect(),
+    }
+}
+
+fn entry_table(path: &str, relocs: &[Reloc], view: &AddrView) -> Table {
+    Table {
+        header: format!("Relocations in {}", path),
+        labels: vec![
+            "Offset".into(),
+            "Type".into(),
+            "Region".into(),
+            "Symbol".into(),
+            "Addend".into(),
+        ],
+        rows: relocs
+            .iter()
+            .map(|reloc| {
+                vec![
+                    view.show(Addr(reloc.offset)),
+                    reloc.typ.clone(),
+                    reloc.region.clone(),
+                    reloc.symbol.clone().unwrap_or_else(|| "-".into()),
+                    Addend(reloc.addend).to_string(),
+                ]
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    error::Error,
+    io::{self, Read},
+    iter,
+    ops::Range,
+    ptr,
+    slice::from_raw_parts,
+    sync::Mutex,
+};
+
+use delf::data::{Buffer, Data};
+
+use crate::{
+    exit::{Failure, Status},
+    interrupt,
+};
+
+// Sections bigger than this are fetched when something looks at them rather than up front,
+// unless the parser reads them itself; .text and debug info of big binaries are what would make
+// a fetch take gigabytes
+const MAX_SECTION: u64 = 1 << 20;
+// Ranges closer than this are fetched in one request
+const MERGE_GAP: u64 = 0x1000;
+// What is fetched on demand is rounded out to this
+const CHUNK: u64 = 0x10000;
+// Largest file elk believes a server about, or downloads in full
+const MAX_SIZE: u64 = 1 << 36;
+const EHDR_SIZE: u64 = 0x40;
+
+const PT_LOAD: u32 = 1;
+const SHT_PROGBITS: u32 = 1;
+const SHT_NOBITS: u32 = 8;
+
+// The file at `url`. Unless `whole`, it is fetched with HTTP range requests as it is read: the
+// ELF header, the header tables and the sections and segments the parser looks into come first,
+// and the rest as sections and segments are looked at. Servers that ignore ranges, and files that
+// aren't plain ELF, are downloaded in full. Ctrl-C stops the first fetches between requests.
+pub fn fetch(url: &str, whole: bool) -> Result<Data, Box<dyn Error>> {
+    let _interrupts = interrupt::catch();
+    if whole {
+        return get(url).map(Data::from);
+    }
+    let (head, size) = match get_range(url, 0..EHDR_SIZE)? {
+        Ranged::Part(head, size) => (head, size),
+        Ranged::Whole(data) => return Ok(data.into()),
+    };
+    if head.len() < EHDR_SIZE as usize || head[..4] != b"\x7fELF"[..] {
+        return get(url).map(Data::from);
+    }
+    if !(EHDR_SIZE..=MAX_SIZE).contains(&size) {
+        return Err(format!("{}: the server gives its size as {} bytes", url, size).into());
+    }
+    let file = Remote::new(url, size)?;
+    file.copy(0, &head[..EHDR_SIZE as usize]);
+    file.fetched.lock().unwrap().push(0..EHDR_SIZE);
+
+    let phoff = file.u64_at(0x20);
+    let shoff = file.u64_at(0x28);
+    let (phentsize, mut phnum) = (file.u16_at(0x36) as u64, file.u16_at(0x38) as u64);
+    let (shentsize, mut shnum) = (file.u16_at(0x3a) as u64, file.u16_at(0x3c) as u64);
+    // Counts that don't fit in the header live in the first section header
+    if shoff != 0 && (shnum == 0 || phnum == 0xffff) {
+        file.load(iter::once(shoff..shoff.saturating_add(shentsize)))?;
+        if shnum == 0 {
+            shnum = file.u64_at(shoff + 0x20);
+        }
+        if phnum == 0xffff {
+            phnum = file.u32_at(shoff + 0x2c) as u64;
+        }
+    }
+    let phdrs = phoff..phoff.saturating_add(phentsize.saturating_mul(phnum));
+    let shdrs = shoff..shoff.saturating_add(shentsize.saturating_mul(shnum));
+    file.load([phdrs.clone(), shdrs.clone()])?;
+
+    let mut wanted = Vec::new();
+    for ph in phdrs.step_by(phentsize.max(1) as usize) {
+        // DYNAMIC, NOTE, INTERP and the like; LOAD segments are made of sections
+        let (offset, size) = (file.u64_at(ph + 0x8), file.u64_at(ph + 0x20));
+        if file.u32_at(ph) != PT_LOAD {
+            wanted.push(offset..offset.saturating_add(size));
+        }
+    }
+    for sh in shdrs.step_by(shentsize.max(1) as usize) {
+        // Symbols, strings, relocations and the like are parsed whatever their size
+        let (typ, offset, size) = (
+            file.u32_at(sh + 0x4),
+            file.u64_at(sh + 0x18),
+            file.u64_at(sh + 0x20),
+        );
+        if typ != SHT_NOBITS && (typ != SHT_PROGBITS || size <= MAX_SECTION) {
+            wanted.push(offset..offset.saturating_add(size));
+        }
+    }
+    file.load(wanted)?;
+    Ok(Data::new(file))
+}
+
+// A file being fetched: an anonymous mapping its size, so what isn't fetched takes no memory,
+// and the ranges of it fetched so far
+struct Remote {
+    url: String,
+    map: *mut u8,
+    len: usize,
+    fetched: Mutex<Vec<Range<u64>>>,
+}
+
+// Bytes are only written into the mapping once, while `fetched` is locked and before the range
+// is marked fetched and handed out
+unsafe impl Send for Remote {}
+unsafe impl Sync for Remote {}
+
+impl Remote {
+    fn new(url: &str, size: u64) -> io::Result<Self> {
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            url: url.to_string(),
+            map: map as *mut u8,
+            len: size as usize,
+            fetched: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn copy(&self, offset: u64, bytes: &[u8]) {
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), self.map.add(offset as usize), bytes.len())
+        }
+    }
+
+    // Fetches what isn't yet of `ranges`, clamped to the file and merged where they are close
+    fn load(&self, ranges: impl IntoIterator<Item = Range<u64>>) -> Result<(), Box<dyn Error>> {
+        let len = self.len as u64;
+        let mut fetched = self.fetched.lock().unwrap_or_else(|e| e.into_inner());
+        let mut missing = Vec::new();
+        for range in ranges {
+            let mut range = range.start.min(len)..range.end.min(len);
+            for have in fetched.iter() {
+                if range.is_empty() || have.start >= range.end {
+                    break;
+                }
+                if have.end <= range.start {
+                    continue;
+                }
+                if have.start > range.start {
+                    missing.push(range.start..have.start);
+                }
+                range.start = range.start.max(have.end);
+            }
+            if !range.is_empty() {
+                missing.push(range);
+            }
+        }
+        for range in merge(missing, MERGE_GAP) {
+            if interrupt::interrupted() {
+                return Err(Failure::new(
+                    Status::Interrupted,
+                    format!("{}: interrupted", self.url),
+                )
+                .into());
+            }
+            let bytes = match get_range(&self.url, range.clone())? {
+                Ranged::Part(_, size) if size != len => {
+                    return Err(format!(
+                        "{}: the file went from {} to {} bytes while being read",
+                        self.url, len, size
+                    )
+                    .into())
+                }
+                Ranged::Part(bytes, _) => bytes,
+                // The server ignored the range
+                Ranged::Whole(data) => data
+                    .get(range.start as usize..range.end as usize)
+                    .map(<[u8]>::to_vec)
+                    .unwrap_or_default(),
+            };
+            if bytes.len() as u64 != range.end - range.start {
+                return Err(format!(
+                    "{}: asked for {} bytes at {:#x}, the server sent {}",
+                    self.url,
+                    range.end - range.start,
+                    range.start,
+                    bytes.len()
+                )
+                .into());
+            }
+            self.copy(range.start, &bytes);
+            fetched.push(range);
+            let ranges = merge(fetched.drain(..).collect(), 0);
+            *fetched = ranges;
+        }
+        Ok(())
+    }
+
+    fn array<const N: usize>(&self, offset: u64) -> [u8; N] {
+        let mut out = [0; N];
+        if let Some(bytes) = self.present().get(offset as usize..offset as usize + N) {
+            out.copy_from_slice(bytes);
+        }
+        out
+    }
+
+    fn u16_at(&self, offset: u64) -> u16 {
+        u16::from_le_bytes(self.array(offset))
+    }
+
+    fn u32_at(&self, offset: u64) -> u32 {
+        u32::from_le_bytes(self.array(offset))
+    }
+
+    fn u64_at(&self, offset: u64) -> u64 {
+        u64::from_le_bytes(self.array(offset))
+    }
+}
+
+// Reading what couldn't be fetched kills elk, like reading a memory-mapped file cut short does
+impl Buffer for Remote {
+    fn present(&self) -> &[u8] {
+        unsafe { from_raw_parts(self.map, self.len) }
+    }
+
+    fn bytes(&self, range: Range<usize>) -> &[u8] {
+        let start = range.start as u64 / CHUNK * CHUNK;
+        let end = (range.end as u64).saturating_add(CHUNK - 1) / CHUNK * CHUNK;
+        if let Err(e) = self.load(iter::once(start..end)) {
+            panic!("{}", e);
+        }
+        &self.present()[range]
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map as *mut libc::c_void, self.len) };
+    }
+}
+
+// `ranges` sorted, those less than `gap` apart made one
+fn merge(mut ranges: Vec<Range<u64>>, gap: u64) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end + gap => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+enum Ranged {
+    // The bytes asked for, and the size of the whole file
+    Part(Vec<u8>, u64),
+    // The server sent everything
+    Whole(Vec<u8>),
+}
+
+fn get(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    read_capped(url, ureq::get(url).call()?)
+}
+
+fn get_range(url: &str, range: Range<u64>) -> Result<Ranged, Box<dyn Error>> {
+    let response = ureq::get(url)
+        .set("Range", &format!("bytes={}-{}", range.start, range.end - 1))
+        .call()?;
+    // Content-Range: bytes 0-63/123456
+    let size = match response.status() {
+        206 => response
+            .header("Content-Range")
+            .and_then(|r| r.rsplit_once('/'))
+            .and_then(|(_, size)| size.parse::<u64>().ok()),
+        _ => None,
+    };
+    let data = read_capped(url, response)?;
+    Ok(match size {
+        Some(size) => Ranged::Part(data, size),
+        None => Ranged::Whole(data),
+    })
+}
+
+fn read_capped(url: &str, response: ureq::Response) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_SIZE + 1)
+        .read_to_end(&mut data)?;
+    match data.len() as u64 > MAX_SIZE {
+        true => Err(format!("{}: more than {} bytes", url, MAX_SIZE).into()),
+        false => Ok(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use delf::{
+        detect::Class,
+        types::{Addr, Machine, SectionBits, SectionType, Type},
+        write::{FileBuilder, SectionBuilder, SectionData},
+        FileHeader,
+    };
+
+    use super::*;
+
+    // How the test server answers
+    #[derive(Clone, Copy, Default)]
+    struct Server {
+        ignore_ranges: bool,
+        // Claimed in Content-Range instead of the real size
+        size: Option<u64>,
+        // Bodies of range requests past the first are cut to this
+        cut: Option<usize>,
+    }
+
+    // Serves `file` on a local port, answering range requests as `how` says; the ranges it was
+    // asked for are logged
+    fn serve(file: Vec<u8>, how: Server) -> (String, Arc<Mutex<Vec<Range<u64>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let asked = log.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(bytes) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = bytes.split_once('-').unwrap();
+                        let (start, end): (u64, u64) =
+                            (start.parse().unwrap(), end.parse().unwrap());
+                        range = Some(start..(end + 1).min(file.len() as u64));
+                    }
+                }
+                let (status, body, extra) = match range {
+                    Some(range) if !how.ignore_ranges => {
+                        let first = asked.lock().unwrap().is_empty();
+                        asked.lock().unwrap().push(range.clone());
+                        let mut body = &file[range.start as usize..range.end as usize];
+                        if let (Some(cut), false) = (how.cut, first) {
+                            body = &body[..cut.min(body.len())];
+                        }
+                        let size = how.size.unwrap_or(file.len() as u64);
+                        let header = format!(
+                            "Content-Range: bytes {}-{}/{}\r\n",
+                            range.start,
+                            range.end - 1,
+                            size
+                        );
+                        ("206 Partial Content", body, header)
+                    }
+                    _ => ("200 OK", &file[..], String::new()),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+                    status,
+                    body.len(),
+                    extra
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+        (url, log)
+    }
+
+    // An ELF file with a .big section, bigger than what is fetched up front, and a small .small
+    fn file() -> Vec<u8> {
+        let mut file = FileBuilder::new(Class::Elf64, Type::Exec, Machine::X86_64);
+        for (name, len) in [(".big", 3 * MAX_SECTION as usize), (".small", 0x100)] {
+            file.sections.push(SectionBuilder {
+                name: name.into(),
+                typ: SectionType::ProgBits,
+                flags: SectionBits::from_bits(0),
+                align: 1,
+                entsize: 0,
+                link: 0,
+                info: 0,
+                data: SectionData::Bytes {
+                    addr: Addr(0),
+                    data: (0..len).map(|i| (i % 251) as u8 + 1).collect(),
+                },
+            });
+        }
+        file.to_bytes().unwrap()
+    }
+
+    fn fetched(log: &Mutex<Vec<Range<u64>>>) -> u64 {
+        log.lock().unwrap().iter().map(|r| r.end - r.start).sum()
+    }
+
+    #[test]
+    fn big_sections_are_fetched_when_read() {
+        let bytes = file();
+        let (url, log) = serve(bytes.clone(), Server::default());
+        let data = fetch(&url, false).unwrap();
+        let file = FileHeader::parse_or_describe(&data).unwrap();
+        assert!(fetched(&log) < MAX_SECTION);
+
+        let small = file.section_by_name(".small").unwrap();
+        let big = file.section_by_name(".big").unwrap();
+        assert_eq!(small.data[0], 1);
+        let offset = big.offset.0 as usize;
+        assert_eq!(big.data[..], bytes[offset..offset + big.data.len()]);
+        assert!(fetched(&log) >= 3 * MAX_SECTION);
+        assert_eq!(data[..], bytes[..]);
+    }
+
+    #[test]
+    fn servers_ignoring_ranges_send_everything() {
+        let bytes = file();
+        let (url, _) = serve(
+            bytes.clone(),
+            Server {
+                ignore_ranges: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(fetch(&url, false).unwrap()[..], bytes[..]);
+    }
+
+    #[test]
+    fn short_bodies_and_unlikely_sizes_fail() {
+        let (url, _) = serve(
+            file(),
+            Server {
+                cut: Some(10),
+                ..Default::default()
+            },
+        );
+        let error = fetch(&url, false).err().unwrap().to_string();
+        assert!(error.contains("the server sent 10"), "{}", error);
+
+        let (url, _) = serve(
+            file(),
+            Server {
+                size: Some(MAX_SIZE + 1),
+                ..Default::default()
+            },
+        );
+        let error = fetch(&url, false).err().unwrap().to_string();
+        assert!(error.contains("gives its size as"), "{}", error);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    cell::UnsafeCell,
+    ffi::{CStr, CString},
+    fs,
+    ops::Range,
+    ptr, slice,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use libc::{c_char, c_int, c_void};
+
+// <link.h>'s link_map and r_debug: how a debugger finds out which objects a process has mapped,
+// where, and from which file. gdb reads the list through DT_DEBUG of the executable and puts a
+// breakpoint on `brk` to hear about changes.
+#[repr(C)]
+struct LinkMap {
+    addr: u64,
+    name: *const c_char,
+    ld: u64,
+    next: *mut LinkMap,
+    prev: *mut LinkMap,
+}
+
+#[repr(C)]
+struct RDebug {
+    version: c_int,
+    map: *mut LinkMap,
+    brk: u64,
+    state: c_int,
+    ldbase: u64,
+}
+
+const RT_CONSISTENT: c_int = 0;
+const RT_ADD: c_int = 1;
+const RT_DELETE: c_int = 2;
+
+const DT_NULL: u64 = 0;
+const DT_DEBUG: u64 = 21;
+const PAGE_SIZE: u64 = 0x1000;
+
+// Only written with LIST locked. Debuggers read it while the process is stopped.
+struct Rendezvous(UnsafeCell<RDebug>);
+
+unsafe impl Sync for Rendezvous {}
+
+#[export_name = "elk_r_debug"]
+static R_DEBUG: Rendezvous = Rendezvous(UnsafeCell::new(RDebug {
+    version: 1,
+    map: ptr::null_mut(),
+    brk: 0,
+    state: RT_CONSISTENT,
+    ldbase: 0,
+}));
+
+struct Node {
+    // 0 for objects elk itself was loaded with
+    id: u64,
+    map: Box<LinkMap>,
+    // What `map.name` points into
+    _name: CString,
+}
+
+// The pointers only lead to other nodes of the list and to `_name`, all owned by LIST
+unsafe impl Send for Node {}
+
+impl Node {
+    fn new(id: u64, addr: u64, name: CString, ld: u64) -> Self {
+        let map = Box::new(LinkMap {
+            addr,
+            name: name.as_ptr(),
+            ld,
+            next: ptr::null_mut(),
+            prev: ptr::null_mut(),
+        });
+        Self {
+            id,
+            map,
+            _name: name,
+        }
+    }
+}
+
+// elk's own objects, then the ones the loader added, in the order they were added. Empty until
+// the first entry is added.
+static LIST: Mutex<Vec<Node>> = Mutex::new(Vec::new());
+static NEXT_ENTRY: AtomicU64 = AtomicU64::new(1);
+
+// Where a debugger's breakpoint goes, called before and after every change to the list
+#[no_mangle]
+#[inline(never)]
+extern "C" fn elk_debug_state() {
+    // Something for the breakpoint to land on that calls can't be optimized away around
+    unsafe { std::arch::asm!("nop") };
+}
+
+// Address of the r_debug, for the DT_DEBUG entries of loaded programs
+pub fn address() -> u64 {
+    R_DEBUG.0.get() as u64
+}
+
+// Changes the list the way ld.so announces changes: state set and breakpoint hit before, list
+// relinked, then consistent again and the breakpoint hit once more
+fn update(state: c_int, change: impl FnOnce(&mut Vec<Node>)) {
+    let mut list = LIST.lock().unwrap_or_else(PoisonError::into_inner);
+    let debug = R_DEBUG.0.get();
+    if list.is_empty() {
+        unsafe {
+            (*debug).brk = elk_debug_state as extern "C" fn() as usize as u64;
+            (*debug).ldbase = libc::getauxval(libc::AT_BASE);
+            libc::dl_iterate_phdr(
+                Some(own_object),
+                &mut *list as *mut Vec<Node> as *mut c_void,
+            );
+        }
+    }
+    unsafe { (*debug).state = state };
+    elk_debug_state();
+    change(&mut list);
+    let maps: Vec<*mut LinkMap> = list
+        .iter_mut()
+        .map(|node| &mut *node.map as *mut _)
+        .collect();
+    for (i, &map) in maps.iter().enumerate() {
+        unsafe {
+            (*map).prev = i.checked_sub(1).map_or(ptr::null_mut(), |prev| maps[prev]);
+            (*map).next = maps.get(i + 1).copied().unwrap_or(ptr::null_mut());
+        }
+    }
+    unsafe {
+        (*debug).map = maps.first().copied().unwrap_or(ptr::null_mut());
+        (*debug).state = RT_CONSISTENT;
+    }
+    elk_debug_state();
+}
+
+// Copies one of elk's own objects into the list, the first being elk's executable. Objects
+// the C library opens later aren't followed.
+unsafe extern "C" fn own_object(
+    info: *mut libc::dl_phdr_info,
+    _: usize,
+    list: *mut c_void,
+) -> c_int {
+    let (info, list) = (&*info, &mut *(list as *mut Vec<Node>));
+    let phdrs = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+    let segment = |typ| phdrs.iter().find(|ph| ph.p_type == typ);
+    let ld = segment(libc::PT_DYNAMIC).map_or(0, |ph| info.dlpi_addr + ph.p_vaddr);
+    let name = match info.dlpi_name.is_null() {
+        true => CString::default(),
+        false => CStr::from_ptr(info.dlpi_name).to_owned(),
+    };
+    if list.is_empty() && ld != 0 {
+        let relro = segment(libc::PT_GNU_RELRO)
+            .map(|ph| info.dlpi_addr + ph.p_vaddr..info.dlpi_addr + ph.p_vaddr + ph.p_memsz);
+        point_dt_debug(ld, relro);
+    }
+    list.push(Node::new(0, info.dlpi_addr, name, ld));
+    0
+}
+
+// Sets DT_DEBUG in elk's own dynamic section, which is where debuggers attaching to elk look
+// for the rendezvous. It may sit in RELRO, which is opened up for the one write.
+unsafe fn point_dt_debug(dynamic: u64, relro: Option<Range<u64>>) {
+    let mut entry = dynamic as *mut u64;
+    loop {
+        match *entry {
+            DT_NULL => return,
+            DT_DEBUG => break,
+            _ => entry = entry.add(2),
+        }
+    }
+    let slot = entry.add(1);
+    let page = (slot as u64 & !(PAGE_SIZE - 1)) as *mut c_void;
+    let sealed = relro.is_some_and(|relro| relro.contains(&(slot as u64)));
+    if sealed && libc::mprotect(page, PAGE_SIZE as usize, libc::PROT_READ | libc::PROT_WRITE) != 0 {
+        return;
+    }
+    ptr::write_volatile(slot, address());
+    if sealed {
+        libc::mprotect(page, PAGE_SIZE as usize, libc::PROT_READ);
+    }
+}
+
+// An object's place in the list, for as long as it is mapped. Dropping it takes it out.
+pub struct Entry {
+    id: u64,
+}
+
+impl Entry {
+    // `dynamic` is the runtime address of the object's dynamic section, 0 if it has none
+    pub fn new(base: u64, path: &str, dynamic: u64) -> Self {
+        let id = NEXT_ENTRY.fetch_add(1, Ordering::Relaxed);
+        // Debuggers open the file relative to their own working directory, not elk's
+        let path = fs::canonicalize(path).map_or_else(
+            |_| path.to_string(),
+            |path| path.to_string_lossy().into_owned(),
+        );
+        let name = CString::new(path).unwrap_or_default();
+        update(RT_ADD, |list| list.push(Node::new(id, base, name, dynamic)));
+        Self { id }
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let id = self.id;
+        update(RT_DELETE, |list| list.retain(|node| node.id != id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deps,
+        loader::{LoadOptions, Process, DEFAULT_BASE, MAIN_OBJECT},
+    };
+    use delf::FileHeader;
+
+    const LADDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/");
+
+    // (addr, name, ld) of every link_map reachable from r_debug, checking each one's `prev` on
+    // the way
+    fn chain() -> Vec<(u64, String, u64)> {
+        let _list = LIST.lock().unwrap_or_else(PoisonError::into_inner);
+        let debug = unsafe { &*R_DEBUG.0.get() };
+        assert_eq!(debug.state, RT_CONSISTENT);
+        let mut maps = Vec::new();
+        let (mut map, mut prev) = (debug.map, ptr::null_mut());
+        while !map.is_null() {
+            let link = unsafe { &*map };
+            assert_eq!(link.prev, prev);
+            let name = unsafe { CStr::from_ptr(link.name) };
+            maps.push((link.addr, name.to_string_lossy().into_owned(), link.ld));
+            (prev, map) = (map, link.next);
+        }
+        maps
+    }
+
+    // 3-needed and libgreet.so join the list as they are announced, after elk's own objects,
+    // and leave it with the process
+    #[test]
+    fn loaded_objects_are_linked_into_r_debug() {
+        let path = format!("{}3-needed", LADDER);
+        let input = crate::source::read(&path).unwrap();
+        let objects = deps::objects(&path, FileHeader::parse_or_describe(&input).unwrap());
+        let main = &objects[0].file;
+        // Clear of the bases other tests load at in this process
+        let base = DEFAULT_BASE + 0x2000_0000;
+        let process =
+            Process::load_with_libraries(main, base, LoadOptions::default(), &objects[1..])
+                .unwrap();
+        process.announce_main(&path);
+        let module = |name: &str| {
+            process
+                .modules()
+                .into_iter()
+                .find(|m| m.name == name)
+                .unwrap()
+        };
+        let canonical = |path: &str| {
+            fs::canonicalize(path)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        };
+        let library = canonical(&format!("{}libgreet.so", LADDER));
+        let executable = canonical(&path);
+
+        let maps = chain();
+        // After elk's own objects, with the debugger's breakpoint on elk_debug_state
+        assert_eq!(
+            unsafe { (*R_DEBUG.0.get()).brk },
+            elk_debug_state as extern "C" fn() as usize as u64
+        );
+        let find = |name: &str| maps.iter().position(|(_, n, _)| n == name);
+        let (at_library, at_main) = (find(&library).unwrap(), find(&executable).unwrap());
+        assert!(at_library > 0 && at_library < at_main);
+        let (addr, _, ld) = &maps[at_library];
+        let greet = module("libgreet.so");
+        assert_eq!(*addr, greet.base);
+        assert!((greet.start..greet.end).contains(ld));
+        assert_eq!(maps[at_main].0, module(MAIN_OBJECT).base);
+
+        drop(process);
+        let maps = chain();
+        assert!(maps
+            .iter()
+            .all(|(_, name, _)| *name != library && *name != executable));
+    }
+}
@@ -0,0 +1,324 @@
+use std::{collections::HashSet, error::Error, fs, path::Path};
+
+use delf::{types::*, FileHeader};
+
+use crate::{exit::Failure, relocs, source, tables::escape_html};
+
+// Clicking a column header sorts by it; numbers, hexadecimal included, sort by value
+const SCRIPT: &str = r#"
+document.querySelectorAll("th").forEach(th => th.addEventListener("click", () => {
+  const body = th.closest("table").tBodies[0], col = th.cellIndex;
+  const asc = th.dataset.order !== "asc";
+  th.dataset.order = asc ? "asc" : "desc";
+  const key = row => {
+    const text = row.cells[col].textContent.trim();
+    return /^(0x[0-9a-f]+|-?\d+)$/i.test(text) ? Number(text) : text;
+  };
+  const rows = [...body.rows].sort((a, b) => {
+    const [x, y] = [key(a), key(b)];
+    const order = typeof x === typeof y ? (x < y ? -1 : x > y ? 1 : 0) : typeof x === "number" ? -1 : 1;
+    return asc ? order : -order;
+  });
+  rows.forEach(row => body.appendChild(row));
+}));
+"#;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th { cursor: pointer; background: #eee; }
+th, td { border: 1px solid #bbb; padding: 2px 8px; font-family: monospace; }
+tr:target { background: #ffe680; }
+";
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Write a self-contained HTML report with sortable, cross-linked tables")]
+pub struct Args {
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = clap::ValueHint::DirPath,
+        help = "Directory to write index.html to"
+    )]
+    html: String,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+// One table of the report; cells are HTML already
+struct Section {
+    id: &'static str,
+    title: String,
+    labels: &'static [&'static str],
+    rows: Vec<(String, Vec<String>)>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+
+    let symbols = symbols(&file);
+    let known: HashSet<&str> = symbols.iter().map(|sym| sym.name.as_str()).collect();
+    let sections = [
+        segments(&file),
+        section_table(&file),
+        symbol_table(&file, &symbols),
+        relocation_table(&file, &known)?,
+    ];
+
+    let dir = Path::new(&args.html);
+    fs::create_dir_all(dir)?;
+    let out = dir.join("index.html");
+    fs::write(&out, render(path, &file, &sections))?;
+    println!("Wrote {}", out.display());
+    Ok(())
+}
+
+fn link(anchor: &str, text: &str) -> String {
+    format!(
+        "<a href=\"#{}\">{}</a>",
+        escape_html(anchor),
+        escape_html(text)
+    )
+}
+
+fn hex(value: u64) -> String {
+    format!("{:#x}", value)
+}
+
+fn symbol_anchor(name: &str) -> String {
+    format!("sym-{}", name)
+}
+
+// Index of the LOAD segment holding `addr`
+fn load_segment(file: &FileHeader, addr: Addr) -> Option<usize> {
+    file.program_headers
+        .iter()
+        .position(|ph| ph.typ == SegmentType::Load && ph.mem_range().contains(&addr))
+}
+
+fn section_index(file: &FileHeader, addr: Addr) -> Option<usize> {
+    file.section_headers
+        .iter()
+        .position(|sh| sh.addr.0 != 0 && sh.mem_range().contains(&addr))
+}
+
+fn segments(file: &FileHeader) -> Section {
+    let rows = file
+        .program_headers
+        .iter()
+        .enumerate()
+        .map(|(i, ph)| {
+            let contents: Vec<String> = file
+                .section_headers
+                .iter()
+                .enumerate()
+                .filter(|(_, sh)| {
+                    sh.addr.0 != 0 && sh.addr >= ph.virt_addr && sh.addr < ph.mem_range().end
+                })
+                .map(|(j, sh)| link(&format!("sec-{}", j), &sh.name))
+                .collect();
+            (
+                format!("seg-{}", i),
+                vec![
+                    i.to_string(),
+                    escape_html(&format!("{:?}", ph.typ)),
+                    escape_html(&format!("{:?}", ph.flags)),
+                    hex(ph.virt_addr.0),
+                    hex(ph.mem_size.0),
+                    hex(ph.offset.0),
+                    hex(ph.data.len() as u64),
+                    contents.join(" "),
+                ],
+            )
+        })
+        .collect();
+    Section {
+        id: "segments",
+        title: "Segments".into(),
+        labels: &[
+            "#",
+            "Type",
+            "Flags",
+            "Address",
+            "Mem size",
+            "Offset",
+            "File size",
+            "Sections",
+        ],
+        rows,
+    }
+}
+
+fn section_table(file: &FileHeader) -> Section {
+    let rows = file
+        .section_headers
+        .iter()
+        .enumerate()
+        .map(|(i, sh)| {
+            let segment = match sh.addr.0 {
+                0 => String::new(),
+                _ => load_segment(file, sh.addr)
+                    .map(|seg| link(&format!("seg-{}", seg), &format!("LOAD[{}]", seg)))
+                    .unwrap_or_default(),
+            };
+            (
+                format!("sec-{}", i),
+                vec![
+                    i.to_string(),
+                    escape_html(&sh.name),
+                    escape_html(&format!("{:?}", sh.typ)),
+                    escape_html(&format!("{:?}", sh.flags)),
+                    hex(sh.addr.0),
+                    hex(sh.size.0),
+                    segment,
+                ],
+            )
+        })
+        .collect();
+    Section {
+        id: "sections",
+        title: "Sections".into(),
+        labels: &["#", "Name", "Type", "Flags", "Address", "Size", "Segment"],
+        rows,
+    }
+}
+
+// Named symbols from .symtab and .dynsym, each name once. Without section headers the dynamic
+// symbols are still found through the dynamic table.
+fn symbols(file: &FileHeader) -> Vec<Symbol> {
+    let dynsym = file
+        .section_headers
+        .iter()
+        .position(|sh| sh.typ == SectionType::DynSym)
+        .map(|idx| file.symbols_in(idx))
+        .unwrap_or_else(|| file.read_syms());
+    let mut seen = HashSet::new();
+    file.read_section_syms()
+        .into_iter()
+        .chain(dynsym)
+        .filter(|sym| !sym.name.is_empty() && seen.insert(sym.name.clone()))
+        .collect()
+}
+
+fn symbol_table(file: &FileHeader, symbols: &[Symbol]) -> Section {
+    let rows = symbols
+        .iter()
+        .map(|sym| {
+            let section = match sym.section_index() {
+                Some(i) => file
+                    .section_headers
+                    .get(i)
+                    .map(|sh| link(&format!("sec-{}", i), &sh.name))
+                    .unwrap_or_default(),
+                None => escape_html(&format!("{:?}", sym.shndx)),
+            };
+            (
+                symbol_anchor(&sym.name),
+                vec![
+                    escape_html(&sym.name),
+                    hex(sym.value.0),
+                    sym.size.to_string(),
+                    sym.typ().map_or("-".into(), |typ| format!("{:?}", typ)),
+                    sym.bind().map_or("-".into(), |bind| format!("{:?}", bind)),
+                    section,
+                ],
+            )
+        })
+        .collect();
+    Section {
+        id: "symbols",
+        title: format!("Symbols ({})", symbols.len()),
+        labels: &["Name", "Value", "Size", "Type", "Bind", "Section"],
+        rows,
+    }
+}
+
+fn relocation_table(file: &FileHeader, known: &HashSet<&str>) -> Result<Section, Box<dyn Error>> {
+    let relocs = relocs::annotate(file)?;
+    let rows = relocs
+        .iter()
+        .enumerate()
+        .map(|(i, reloc)| {
+            let region = match section_index(file, Addr(reloc.offset)) {
+                Some(sec) => link(&format!("sec-{}", sec), &reloc.region),
+                None => escape_html(&reloc.region),
+            };
+            // Relative relocations name the symbol their target lands in as `name+0x10`
+            let symbol = match &reloc.symbol {
+                Some(symbol) => {
+                    let name = symbol.split('+').next().unwrap_or(symbol);
+                    match known.contains(name) {
+                        true => link(&symbol_anchor(name), symbol),
+                        false => escape_html(symbol),
+                    }
+                }
+                None => String::new(),
+            };
+            (
+                format!("rel-{}", i),
+                vec![
+                    hex(reloc.offset),
+                    escape_html(&reloc.typ),
+                    region,
+                    symbol,
+                    escape_html(&Addend(reloc.addend).to_string()),
+                ],
+            )
+        })
+        .collect();
+    Ok(Section {
+        id: "relocations",
+        title: format!("Relocations ({})", relocs.len()),
+        labels: &["Slot", "Type", "Section", "Symbol", "Addend"],
+        rows,
+    })
+}
+
+fn render(path: &str, file: &FileHeader, sections: &[Section]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>elk report: {}</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape_html(path),
+        STYLE,
+        escape_html(path)
+    );
+    let entry = match file.entry_symbol() {
+        Some(sym) => link(&symbol_anchor(&sym.name), &hex(file.entry_point.0)),
+        None => hex(file.entry_point.0),
+    };
+    out += &format!(
+        "<p>{:?} for {:?}, entry point {}</p>\n<p>",
+        file.typ, file.machine, entry
+    );
+    let contents: Vec<_> = sections
+        .iter()
+        .map(|section| link(section.id, &section.title))
+        .collect();
+    out += &contents.join(" · ");
+    out += "</p>\n";
+
+    for section in sections {
+        out += &format!(
+            "<h2 id=\"{}\">{}</h2>\n<table>\n<thead><tr>",
+            section.id,
+            escape_html(&section.title)
+        );
+        for label in section.labels {
+            out += &format!("<th>{}</th>", label);
+        }
+        out += "</tr></thead>\n<tbody>\n";
+        for (id, cells) in &section.rows {
+            out += &format!("<tr id=\"{}\">", escape_html(id));
+            for cell in cells {
+                out += &format!("<td>{}</td>", cell);
+            }
+            out += "</tr>\n";
+        }
+        out += "</tbody>\n</table>\n";
+    }
+    out += &format!("<script>{}</script>\n</body>\n</html>\n", SCRIPT);
+    out
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    audit, capabilities, check, coredump, deps, difftest, dig, exit, exports, grep_symbol, hash,
+    linkage, relocs, similarity, tables, trace_relro, vtables, xref,
+};
+
+// Bumped only when a field is renamed, removed or changes meaning. New fields can appear
+// within a version; consumers should ignore the ones they don't know.
+pub const VERSION: &str = "elk/1";
+
+// Every JSON document elk prints: the schema version, the command that produced it, then
+// the command's own output. Fields come out in declaration order and maps sorted by key, so
+// the same input always gives the same bytes.
+#[derive(Serialize)]
+pub struct Envelope<'a, T> {
+    schema: &'static str,
+    command: &'a str,
+    data: T,
+}
+
+pub fn envelope<T: Serialize>(command: &str, data: T) -> Envelope<'_, T> {
+    Envelope {
+        schema: VERSION,
+        command,
+        data,
+    }
+}
+
+pub fn print_json<T: Serialize>(command: &str, data: &T) -> Result<(), Box<dyn Error>> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&envelope(command, data))?
+    );
+    Ok(())
+}
+
+// The `data` of each command's output
+fn outputs(gen: &mut SchemaGenerator) -> Vec<(&'static str, Schema)> {
+    vec![
+        ("audit-system", audit::json_schema(gen)),
+        ("capabilities", capabilities::json_schema(gen)),
+        ("check", check::json_schema(gen)),
+        ("core", coredump::json_schema(gen)),
+        ("deps", deps::json_schema(gen)),
+        ("difftest", difftest::json_schema(gen)),
+        ("dig", dig::json_schema(gen)),
+        ("error", exit::json_schema(gen)),
+        ("grep-symbol", grep_symbol::json_schema(gen)),
+        ("hash", hash::json_schema(gen)),
+        // delf's types only derive Serialize, so this one is left open
+        ("inspect", gen.subschema_for::<Value>()),
+        ("linkage", linkage::json_schema(gen)),
+        ("match", similarity::json_schema(gen)),
+        ("relocs", gen.subschema_for::<Vec<relocs::Reloc>>()),
+        ("tables", gen.subschema_for::<Vec<tables::Table>>()),
+        ("trace-relro", trace_relro::json_schema(gen)),
+        ("unused-exports", exports::json_schema(gen)),
+        ("vtables", vtables::json_schema(gen)),
+        ("xref", xref::json_schema(gen)),
+    ]
+}
+
+// JSON Schema (draft 7) for everything elk prints with `--format json`, `--output *.json`
+// and `--error-format json`
+pub fn document() -> Value {
+    let mut gen = SchemaSettings::draft07().into_generator();
+    let outputs = outputs(&mut gen);
+    let commands: Vec<_> = outputs.iter().map(|(command, _)| *command).collect();
+    let variants: Vec<_> = outputs
+        .iter()
+        .map(|(command, data)| {
+            json!({
+                "properties": {
+                    "command": { "const": command },
+                    "data": data,
+                }
+            })
+        })
+        .collect();
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "$id": VERSION,
+        "title": "elk JSON output",
+        "type": "object",
+        "required": ["schema", "command", "data"],
+        "properties": {
+            "schema": { "const": VERSION },
+            "command": { "enum": commands },
+            "data": {},
+        },
+        "oneOf": variants,
+        "definitions": gen.definitions(),
+    })
+}
+
+pub fn print_schema() -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(&document())?);
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::{error::Error, fs, path::Path};
+
+use delf::{types::*, FileHeader};
+use rhai::{Array, Dynamic, Engine, Map, Scope};
+
+// Exposes the parsed object model to a Rhai script. The script sees the parsed files as `ELFS`
+// (and the first one as `ELF`), the raw arguments as `ARGS`, and can call `parse_elf(path)` and
+// `list_dir(path)` to analyze more files on its own.
+#[derive(clap::Args, Debug)]
+#[command(about = "Run a Rhai script against parsed binaries")]
+pub struct Args {
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    script: String,
+    // Passed through untouched, a script may take flags of its own
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_hint = clap::ValueHint::FilePath,
+        help = "Arguments for the script, parsed into ELFS where they are ELF files"
+    )]
+    files: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let (script, paths) = (&args.script, &args.files);
+
+    let mut engine = Engine::new();
+    engine.register_fn("parse_elf", |path: &str| -> Dynamic {
+        elf_map(path).map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+    });
+    engine.register_fn("list_dir", |path: &str| -> Array {
+        let mut files = Vec::new();
+        walk(Path::new(path), &mut files);
+        files.into_iter().map(Dynamic::from).collect()
+    });
+
+    let elfs: Array = paths
+        .iter()
+        .map(|p| elf_map(p).map(Dynamic::from).unwrap_or(Dynamic::UNIT))
+        .collect();
+    let mut scope = Scope::new();
+    scope.push(
+        "ARGS",
+        paths.iter().cloned().map(Dynamic::from).collect::<Array>(),
+    );
+    scope.push("ELF", elfs.first().cloned().unwrap_or(Dynamic::UNIT));
+    scope.push("ELFS", elfs);
+
+    let source = fs::read_to_string(script)?;
+    engine
+        .run_with_scope(&mut scope, &source)
+        .map_err(|e| format!("{}: {}", script, e))?;
+    Ok(())
+}
+
+fn walk(dir: &Path, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => walk(&path, files),
+            Ok(t) if t.is_file() => files.push(path.display().to_string()),
+            _ => {}
+        }
+    }
+}
+
+fn map<const N: usize>(fields: [(&str, Dynamic); N]) -> Dynamic {
+    let map: Map = fields.iter().cloned().map(|(k, v)| (k.into(), v)).collect();
+    Dynamic::from(map)
+}
+
+fn int(value: u64) -> Dynamic {
+    Dynamic::from(value as i64)
+}
+
+fn name(value: impl std::fmt::Debug) -> Dynamic {
+    Dynamic::from(format!("{:?}", value))
+}
+
+fn elf_map(path: &str) -> Option<Map> {
+    let input = crate::source::read(path).ok()?;
+    let file = FileHeader::parse_or_describe(&input).ok()?;
+
+    let segments: Array = file
+        .program_headers
+        .iter()
+        .map(|ph| {
+            let flags: String = [
+                (SegmentFlags::Read, "R"),
+                (SegmentFlags::Write, "W"),
+                (SegmentFlags::Execute, "X"),
+            ]
+            .iter()
+            .filter(|(f, _)| ph.flags.contains(*f))
+            .map(|(_, l)| *l)
+            .collect();
+            map([
+                ("type", name(ph.typ)),
+                ("flags", flags.into()),
+                ("offset", int(ph.offset.0)),
+                ("vaddr", int(ph.virt_addr.0)),
+                ("file_size", int(ph.file_size.0)),
+                ("mem_size", int(ph.mem_size.0)),
+                ("align", int(ph.align.0)),
+            ])
+        })
+        .collect();
+    let sections: Array = file
+        .section_headers
+        .iter()
+        .map(|sh| {
+            map([
+                ("name", sh.name.clone().into()),
+                ("type", name(sh.typ)),
+                ("flags", int(sh.flags.bits())),
+                ("addr", int(sh.addr.0)),
+                ("offset", int(sh.offset.0)),
+                ("size", int(sh.size.0)),
+            ])
+        })
+        .collect();
+    let symbols: Array = file
+        .read_section_syms()
+        .iter()
+        .map(|sym| {
+            map([
+                ("name", sym.name.clone().into()),
+                ("value", int(sym.value.0)),
+                ("size", int(sym.size)),
+                ("type", int(u64::from(sym.info & 0xf))),
+                ("section", int(sym.section_index().unwrap_or(0) as u64)),
+            ])
+        })
+        .collect();
+    let relocations: Array = file
+        .read_rela_entries()
+        .unwrap_or_default()
+        .iter()
+        .map(|rel| {
+            map([
+                ("offset", int(rel.offset.0)),
+                ("type", name(rel.typ)),
+                ("sym", int(rel.sym as u64)),
+                ("addend", Dynamic::from(rel.addend.0)),
+            ])
+        })
+        .collect();
+    let dynamic: Array = match file.segments_of_type(SegmentType::Dynamic).next() {
+        Some(ProgramHeader {
+            contents: SegmentContent::Dynamic(entries),
+            ..
+        }) => entries
+            .iter()
+            .map(|e| map([("tag", name(e.tag)), ("value", int(e.addr.0))]))
+            .collect(),
+        _ => Array::new(),
+    };
+
+    let mut elf = Map::new();
+    elf.insert("path".into(), path.to_string().into());
+    elf.insert("type".into(), name(file.typ));
+    elf.insert("machine".into(), name(file.machine));
+    elf.insert("entry".into(), int(file.entry_point.0));
+    elf.insert("segments".into(), segments.into());
+    elf.insert("sections".into(), sections.into());
+    elf.insert("symbols".into(), symbols.into());
+    elf.insert("relocations".into(), relocations.into());
+    elf.insert("dynamic".into(), dynamic.into());
+    Some(elf)
+}
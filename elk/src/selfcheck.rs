@@ -0,0 +1,154 @@
+use std::{error::Error, fs, process::Command};
+
+use delf::{types::SegmentType, FileHeader};
+
+use crate::{exit::Failure, source, tables::Table};
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Report which features this elk was built with and whether they work here")]
+pub struct Args {}
+
+struct Feature {
+    name: &'static str,
+    built: bool,
+    // What the environment has to offer for it, or why it can't be used here
+    status: Result<String, String>,
+}
+
+// Whether ndisasm runs, with the version it reports
+fn ndisasm() -> Result<String, String> {
+    let output = Command::new("ndisasm")
+        .arg("-v")
+        .output()
+        .map_err(|e| format!("ndisasm: {}", e))?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    match output.status.success() {
+        true => Ok(version
+            .lines()
+            .next()
+            .unwrap_or("ndisasm")
+            .trim()
+            .to_string()),
+        false => Err(format!("ndisasm -v exited with {}", output.status)),
+    }
+}
+
+// Yama's ptrace_scope: 0 and 1 let elk trace the children it spawns, 2 and 3 don't
+fn ptrace() -> Result<String, String> {
+    match fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope") {
+        Ok(scope) => match scope.trim() {
+            scope @ ("0" | "1") => Ok(format!("ptrace_scope is {}", scope)),
+            scope => Err(format!(
+                "ptrace_scope is {}, children can't be traced",
+                scope
+            )),
+        },
+        Err(_) => Ok("no Yama restrictions".to_string()),
+    }
+}
+
+// How elk itself is linked, going by its own program headers
+fn linking() -> Result<String, Box<dyn Error>> {
+    let input = source::read("/proc/self/exe")?;
+    let file = FileHeader::parse_or_describe(&input).map_err(Failure::parse)?;
+    let interp = file.segments_of_type(SegmentType::Interp).next().map(|ph| {
+        String::from_utf8_lossy(&ph.data)
+            .trim_end_matches('\0')
+            .to_string()
+    });
+    Ok(match interp {
+        Some(interp) => format!("dynamically linked, interpreter {}", interp),
+        None => "statically linked".to_string(),
+    })
+}
+
+pub fn run(_: Args) -> Result<(), Box<dyn Error>> {
+    let in_process = || Ok("in-process".to_string());
+    let features = [
+        Feature {
+            name: "ndisasm",
+            built: cfg!(feature = "ndisasm"),
+            status: ndisasm(),
+        },
+        Feature {
+            name: "iced",
+            built: cfg!(feature = "iced"),
+            status: in_process(),
+        },
+        Feature {
+            name: "ptrace",
+            built: cfg!(feature = "ptrace"),
+            status: ptrace(),
+        },
+        Feature {
+            name: "sandbox",
+            built: cfg!(feature = "sandbox"),
+            status: in_process(),
+        },
+        Feature {
+            name: "tui",
+            built: cfg!(feature = "tui"),
+            status: in_process(),
+        },
+        Feature {
+            name: "script",
+            built: cfg!(feature = "script"),
+            status: in_process(),
+        },
+        Feature {
+            name: "decompress",
+            built: cfg!(feature = "decompress"),
+            status: in_process(),
+        },
+        Feature {
+            name: "dwarf",
+            built: cfg!(feature = "dwarf"),
+            status: in_process(),
+        },
+        Feature {
+            name: "http",
+            built: cfg!(feature = "http"),
+            status: in_process(),
+        },
+        Feature {
+            name: "emulate",
+            built: cfg!(feature = "emulate"),
+            status: in_process(),
+        },
+    ];
+
+    Table {
+        header: format!("elk {}, {}", env!("CARGO_PKG_VERSION"), linking()?),
+        labels: vec!["Feature".into(), "Built".into(), "Available".into()],
+        rows: features
+            .iter()
+            .map(|f| {
+                let available = match (f.built, &f.status) {
+                    (false, _) => "-".to_string(),
+                    (true, Ok(detail)) => format!("yes, {}", detail),
+                    (true, Err(reason)) => format!("no, {}", reason),
+                };
+                vec![
+                    f.name.to_string(),
+                    if f.built { "yes" } else { "no" }.to_string(),
+                    available,
+                ]
+            })
+            .collect(),
+    }
+    .print();
+
+    let broken: Vec<_> = features
+        .iter()
+        .filter(|f| f.built && f.status.is_err())
+        .map(|f| f.name)
+        .collect();
+    match broken.is_empty() {
+        true => Ok(()),
+        false => Err(Failure::findings(format!(
+            "built with {}, which can't be used here",
+            broken.join(", ")
+        ))
+        .into()),
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    error::Error,
+};
+
+use delf::{types::*, FileHeader};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg, disasm_listing, exit::Failure, schema, size::demangle, source, tables::Table,
+};
+
+// Tokens per shingle when scoring how much of a modified function survived
+const SHINGLE: usize = 4;
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Basis {
+    // Exact bytes; anything that moved, and so has new call displacements, counts as modified
+    Bytes,
+    // Instruction mnemonics from ndisasm, blind to addresses and immediates
+    Mnemonics,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Pair up the functions of two versions of a binary and report what changed")]
+pub struct Args {
+    #[arg(
+        long,
+        value_enum,
+        default_value = "bytes",
+        help = "What function hashes are computed from"
+    )]
+    by: Basis,
+    #[arg(long, help = "Also list functions that are identical")]
+    all: bool,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    old: String,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    new: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(rename = "MatchStatus")]
+enum Status {
+    Modified,
+    Renamed,
+    Removed,
+    Added,
+    Identical,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Pair {
+    name: String,
+    status: Status,
+    // Name in the old binary, when it differs
+    old_name: Option<String>,
+    old_size: Option<u64>,
+    new_size: Option<u64>,
+    hash: Option<String>,
+    similarity: Option<f64>,
+}
+
+struct Function {
+    size: u64,
+    hash: u64,
+    tokens: Vec<u64>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Vec<Pair>>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let old = functions(&args.old, args.by)?;
+    let new = functions(&args.new, args.by)?;
+    let pairs = pair(&old, &new);
+
+    if args.format.json() {
+        schema::print_json("match", &pairs)?;
+    } else {
+        print_report(&pairs, &args, args.all);
+    }
+    Ok(())
+}
+
+fn functions(path: &str, basis: Basis) -> Result<BTreeMap<String, Function>, Box<dyn Error>> {
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let symbols: Vec<Symbol> = file
+        .read_section_syms()
+        .into_iter()
+        .filter(|sym| sym.typ() == Some(SymType::Func) && sym.size > 0 && !sym.name.is_empty())
+        .collect();
+    if symbols.is_empty() {
+        return Err(format!("{} has no sized function symbols (stripped?)", path).into());
+    }
+
+    // One listing per section instead of one ndisasm run per function
+    let mut listings: HashMap<usize, Vec<(u64, String)>> = HashMap::new();
+    let mut functions = BTreeMap::new();
+    for sym in symbols {
+        let (index, sh) = match sym
+            .section_index()
+            .and_then(|i| Some((i, file.section_headers.get(i)?)))
+        {
+            Some((i, sh)) if sh.typ != SectionType::NoBits => (i, sh),
+            _ => continue,
+        };
+        let start = (sym.value.0 - sh.addr.0) as usize;
+        let bytes = match sh.data.get(start..start + sym.size as usize) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        let tokens: Vec<u64> = match basis {
+            Basis::Bytes => bytes.iter().map(|&b| u64::from(b)).collect(),
+            Basis::Mnemonics => {
+                if let Entry::Vacant(entry) = listings.entry(index) {
+                    entry.insert(mnemonics(file.machine, sh)?);
+                }
+                let range = sym.value.0..sym.value.0 + sym.size;
+                listings[&index]
+                    .iter()
+                    .filter(|(addr, _)| range.contains(addr))
+                    .map(|(_, mnemonic)| fnv(mnemonic.as_bytes()))
+                    .collect()
+            }
+        };
+        functions.entry(sym.name).or_insert(Function {
+            size: sym.size,
+            hash: fnv_tokens(&tokens),
+            tokens,
+        });
+    }
+    Ok(functions)
+}
+
+// (address, mnemonic) for every instruction ndisasm decodes in `sh`. Machines without a
+// disassembler get their `.inst` annotations, so their functions compare by instruction count.
+fn mnemonics(machine: Machine, sh: &SectionHeader) -> Result<Vec<(u64, String)>, Box<dyn Error>> {
+    let listing = disasm_listing(machine, &sh.data, sh.addr.0, &[])?;
+    Ok(listing
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+            let _bytes = fields.next()?;
+            Some((addr, fields.next()?.to_string()))
+        })
+        .collect())
+}
+
+pub fn fnv(bytes: &[u8]) -> u64 {
+    fnv_step(FNV_OFFSET, bytes)
+}
+
+fn fnv_step(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |h, &b| (h ^ u64::from(b)).wrapping_mul(FNV_PRIME))
+}
+
+// Jaccard index of the two functions' token shingles
+fn similarity(a: &Function, b: &Function) -> f64 {
+    let shingles = |f: &Function| -> HashSet<u64> {
+        match f.tokens.len() {
+            n if n < SHINGLE => HashSet::from([fnv_tokens(&f.tokens)]),
+            _ => f.tokens.windows(SHINGLE).map(fnv_tokens).collect(),
+        }
+    };
+    let (a, b) = (shingles(a), shingles(b));
+    let union = a.union(&b).count();
+    match union {
+        0 => 1.0,
+        n => a.intersection(&b).count() as f64 / n as f64,
+    }
+}
+
+fn fnv_tokens(tokens: &[u64]) -> u64 {
+    tokens
+        .iter()
+        .fold(FNV_OFFSET, |h, t| fnv_step(h, &t.to_le_bytes()))
+}
+
+// Functions are paired by name first; what is left on either side is paired by identical hash,
+// which catches renames
+fn pair(old: &BTreeMap<String, Function>, new: &BTreeMap<String, Function>) -> Vec<Pair> {
+    let mut pairs = Vec::new();
+    for (name, o) in old {
+        if let Some(n) = new.get(name) {
+            let identical = o.hash == n.hash;
+            pairs.push(Pair {
+                name: name.clone(),
+                status: match identical {
+                    true => Status::Identical,
+                    false => Status::Modified,
+                },
+                old_name: None,
+                old_size: Some(o.size),
+                new_size: Some(n.size),
+                hash: Some(format!("{:016x}", n.hash)),
+                similarity: Some(match identical {
+                    true => 1.0,
+                    false => similarity(o, n),
+                }),
+            });
+        }
+    }
+
+    let mut removed: HashMap<u64, Vec<&String>> = HashMap::new();
+    for (name, o) in old.iter().filter(|(name, _)| !new.contains_key(*name)) {
+        removed.entry(o.hash).or_default().push(name);
+    }
+    for (name, n) in new.iter().filter(|(name, _)| !old.contains_key(*name)) {
+        let renamed = removed.get_mut(&n.hash).and_then(|names| names.pop());
+        pairs.push(Pair {
+            name: name.clone(),
+            status: match renamed {
+                Some(_) => Status::Renamed,
+                None => Status::Added,
+            },
+            old_size: renamed.map(|_| n.size),
+            old_name: renamed.cloned(),
+            new_size: Some(n.size),
+            hash: Some(format!("{:016x}", n.hash)),
+            similarity: renamed.map(|_| 1.0),
+        });
+    }
+    for name in removed.into_values().flatten() {
+        pairs.push(Pair {
+            name: name.clone(),
+            status: Status::Removed,
+            old_name: None,
+            old_size: Some(old[name].size),
+            new_size: None,
+            hash: Some(format!("{:016x}", old[name].hash)),
+            similarity: None,
+        });
+    }
+    // Least similar first: the functions worth reading in a patch diff
+    pairs.sort_by(|a, b| {
+        (a.status, a.similarity.unwrap_or(0.0))
+            .partial_cmp(&(b.status, b.similarity.unwrap_or(0.0)))
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    pairs
+}
+
+fn print_report(pairs: &[Pair], args: &Args, all: bool) {
+    let count = |status| pairs.iter().filter(|p| p.status == status).count();
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".into());
+    let table = Table {
+        header: format!(
+            "{} vs {}: {} identical, {} modified, {} renamed, {} removed, {} added",
+            args.old,
+            args.new,
+            count(Status::Identical),
+            count(Status::Modified),
+            count(Status::Renamed),
+            count(Status::Removed),
+            count(Status::Added)
+        ),
+        labels: vec![
+            "Function".into(),
+            "Status".into(),
+            "Old size".into(),
+            "New size".into(),
+            "Similarity".into(),
+        ],
+        rows: pairs
+            .iter()
+            .filter(|p| all || p.status != Status::Identical)
+            .map(|p| {
+                let status = match &p.old_name {
+                    Some(old) => format!("renamed from {}", demangle(old)),
+                    None => format!("{:?}", p.status).to_lowercase(),
+                };
+                vec![
+                    demangle(&p.name),
+                    status,
+                    optional(p.old_size.map(|s| s.to_string())),
+                    optional(p.new_size.map(|s| s.to_string())),
+                    optional(p.similarity.map(|s| format!("{:.0}%", s * 100.0))),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
@@ -0,0 +1,411 @@
+use std::{collections::BTreeMap, error::Error, path::Path};
+
+use delf::{style, types::*, FileHeader};
+
+use crate::{exit::Failure, tables::Table};
+
+const BAR_WIDTH: usize = 20;
+const DEFAULT_TOP: usize = 20;
+#[derive(clap::Args, Debug)]
+#[command(about = "Break down where the bytes of a binary go")]
+pub struct Args {
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        value_hint = clap::ValueHint::FilePath,
+        conflicts_with_all = ["symbols", "file"],
+        help = "Compare two builds section by section"
+    )]
+    compare: Option<Vec<String>>,
+    #[arg(long, help = "Largest symbols instead of sections")]
+    symbols: bool,
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_TOP, requires = "symbols")]
+    top: usize,
+    #[arg(required_unless_present = "compare", value_hint = clap::ValueHint::FilePath)]
+    file: Option<String>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    match (args.compare.as_deref(), args.file) {
+        (Some([old, new]), _) => compare(old, new),
+        (_, Some(path)) if args.symbols => symbols(&path, args.top),
+        (_, Some(path)) => report(&path),
+        _ => Err("expected a file or --compare <old> <new>".into()),
+    }
+}
+
+struct Entry {
+    // What compare matches an entry on across two builds; the label is only for display
+    key: String,
+    label: String,
+    file_size: u64,
+    mem_size: u64,
+}
+
+fn load(path: &str) -> Result<(FileHeader, u64), Box<dyn Error>> {
+    let input = crate::source::read(path)?;
+    let file = FileHeader::parse_or_print_error(&input)
+        .ok_or_else(|| Failure::parse(format!("could not parse {}", path)))?;
+    Ok((file, input.len() as u64))
+}
+
+// A segment's index moves whenever one is added or dropped before it, so it's keyed by its
+// type and which of that type it is instead
+fn segments(file: &FileHeader) -> Vec<Entry> {
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    file.program_headers
+        .iter()
+        .enumerate()
+        .map(|(i, ph)| {
+            let typ = format!("{:?}", ph.typ);
+            let ordinal = seen.entry(typ.clone()).or_default();
+            let key = format!("{}#{}", typ, ordinal);
+            *ordinal += 1;
+            Entry {
+                key,
+                label: format!("{}[{}]", typ, i),
+                file_size: ph.file_size.0,
+                mem_size: ph.mem_size.0,
+            }
+        })
+        .collect()
+}
+
+fn sections(file: &FileHeader) -> Vec<Entry> {
+    file.section_headers
+        .iter()
+        .filter(|sh| sh.typ != SectionType::Null)
+        .map(|sh| Entry {
+            key: sh.name.clone(),
+            label: sh.name.clone(),
+            file_size: sh.file_range().end.0 - sh.file_range().start.0,
+            mem_size: if sh.addr.0 == 0 { 0 } else { sh.size.0 },
+        })
+        .collect()
+}
+
+fn report(path: &str) -> Result<(), Box<dyn Error>> {
+    let (file, total) = load(path)?;
+
+    let mut segs = segments(&file);
+    let loaded = segs
+        .iter()
+        .zip(&file.program_headers)
+        .filter(|(_, ph)| ph.typ == SegmentType::Load)
+        .fold((0, 0), |(f, m), (e, _)| (f + e.file_size, m + e.mem_size));
+    segs.push(Entry {
+        key: "LOAD total".into(),
+        label: "LOAD total".into(),
+        file_size: loaded.0,
+        mem_size: loaded.1,
+    });
+    size_table("Segments", &segs, total).print();
+
+    let mut secs = sections(&file);
+    if !secs.is_empty() {
+        let attributed: u64 = secs.iter().map(|e| e.file_size).sum();
+        secs.push(Entry {
+            key: "[unattributed]".into(),
+            label: "[unattributed]".into(),
+            file_size: total.saturating_sub(attributed),
+            mem_size: 0,
+        });
+        size_table("Sections", &secs, total).print();
+    }
+
+    println!("File size: {}", human(total));
+    Ok(())
+}
+
+fn size_table(header: &str, entries: &[Entry], total: u64) -> Table {
+    Table {
+        header: header.into(),
+        labels: ["name", "file size", "mem size", "% of file", ""]
+            .iter()
+            .map(|l| l.to_string())
+            .collect(),
+        rows: entries
+            .iter()
+            .map(|e| {
+                let ratio = if total == 0 {
+                    0.0
+                } else {
+                    e.file_size as f64 / total as f64
+                };
+                vec![
+                    e.label.clone(),
+                    human(e.file_size),
+                    human(e.mem_size),
+                    format!("{:.1}%", ratio * 100.0),
+                    bar(ratio),
+                ]
+            })
+            .collect(),
+    }
+}
+
+fn compare(old_path: &str, new_path: &str) -> Result<(), Box<dyn Error>> {
+    let (old, old_total) = load(old_path)?;
+    let (new, new_total) = load(new_path)?;
+
+    compare_table("Segments", &segments(&old), &segments(&new)).print();
+    compare_table("Sections", &sections(&old), &sections(&new)).print();
+    println!(
+        "File size: {} -> {} ({})",
+        human(old_total),
+        human(new_total),
+        delta(old_total, new_total)
+    );
+    Ok(())
+}
+
+fn compare_table(header: &str, old: &[Entry], new: &[Entry]) -> Table {
+    // Rows show the new build's label where the entry is still there
+    let mut merged: BTreeMap<&str, (&str, Option<u64>, Option<u64>)> = BTreeMap::new();
+    for e in old {
+        let row = merged.entry(&e.key).or_insert((&e.label, None, None));
+        row.1 = Some(e.file_size);
+    }
+    for e in new {
+        let row = merged.entry(&e.key).or_insert((&e.label, None, None));
+        row.0 = &e.label;
+        row.2 = Some(e.file_size);
+    }
+
+    let mut rows: Vec<(i64, Vec<String>)> = merged
+        .into_values()
+        .map(|(label, o, n)| {
+            let (o_size, n_size) = (o.unwrap_or(0), n.unwrap_or(0));
+            let show = |s: Option<u64>| s.map(human).unwrap_or_else(|| "-".into());
+            let change = match (o, n) {
+                (None, _) => "new".to_string(),
+                (_, None) => "removed".to_string(),
+                _ if o_size == 0 => "-".to_string(),
+                _ => format!("{:+.1}%", (n_size as f64 / o_size as f64 - 1.0) * 100.0),
+            };
+            (
+                n_size as i64 - o_size as i64,
+                vec![
+                    label.to_string(),
+                    show(o),
+                    show(n),
+                    delta(o_size, n_size),
+                    change,
+                ],
+            )
+        })
+        .collect();
+    // Biggest growth first
+    rows.sort_by_key(|(d, _)| -d);
+
+    Table {
+        header: header.into(),
+        labels: ["name", "old", "new", "delta", "change"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect(),
+        rows: rows.into_iter().map(|(_, r)| r).collect(),
+    }
+}
+
+fn symbols(path: &str, top: usize) -> Result<(), Box<dyn Error>> {
+    let (file, _) = load(path)?;
+    let mut syms: Vec<Symbol> = file
+        .read_section_syms()
+        .into_iter()
+        .filter(|s| s.size > 0)
+        .filter(|s| matches!(s.typ(), Some(SymType::Func | SymType::Object)))
+        .collect();
+    if syms.is_empty() {
+        return Err(format!("{} has no sized symbols (stripped?)", path).into());
+    }
+    syms.sort_by_key(|s| std::cmp::Reverse(s.size));
+    let total: u64 = syms.iter().map(|s| s.size).sum();
+
+    let section_name = |sym: &Symbol| match sym
+        .section_index()
+        .and_then(|i| file.section_headers.get(i))
+    {
+        Some(sh) => sh.name.clone(),
+        None => format!("[{:?}]", sym.shndx),
+    };
+
+    let mut by_section: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    let mut by_namespace: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for sym in &syms {
+        let e = by_section.entry(section_name(sym)).or_default();
+        *e = (e.0 + 1, e.1 + sym.size);
+        let e = by_namespace
+            .entry(namespace(&demangle(&sym.name)))
+            .or_default();
+        *e = (e.0 + 1, e.1 + sym.size);
+    }
+
+    let rows = syms
+        .iter()
+        .take(top)
+        .map(|s| {
+            let ratio = s.size as f64 / total as f64;
+            vec![
+                style::file_link(
+                    Path::new(path),
+                    &format!("{:#x}", s.value.0),
+                    demangle(&s.name),
+                ),
+                section_name(s),
+                human(s.size),
+                format!("{:.1}%", ratio * 100.0),
+                bar(ratio),
+            ]
+        })
+        .collect();
+    let labels = ["symbol", "section", "size", "% of symbols", ""];
+    Table {
+        header: format!("Top {} symbols", top.min(syms.len())),
+        labels: labels.iter().map(|l| l.to_string()).collect(),
+        rows,
+    }
+    .print();
+    group_table("By section", "section", by_section, total).print();
+    group_table("By namespace", "namespace", by_namespace, total).print();
+    println!("Symbol total: {} in {} symbols", human(total), syms.len());
+    Ok(())
+}
+
+fn group_table(
+    header: &str,
+    label: &str,
+    groups: BTreeMap<String, (usize, u64)>,
+    total: u64,
+) -> Table {
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, (_, size))| std::cmp::Reverse(*size));
+    Table {
+        header: header.into(),
+        labels: [label, "symbols", "size", "% of symbols", ""]
+            .iter()
+            .map(|l| l.to_string())
+            .collect(),
+        rows: groups
+            .into_iter()
+            .map(|(name, (count, size))| {
+                let ratio = size as f64 / total as f64;
+                vec![
+                    name,
+                    count.to_string(),
+                    human(size),
+                    format!("{:.1}%", ratio * 100.0),
+                    bar(ratio),
+                ]
+            })
+            .collect(),
+    }
+}
+
+pub fn demangle(name: &str) -> String {
+    if let Ok(sym) = rustc_demangle::try_demangle(name) {
+        return format!("{:#}", sym);
+    }
+    cpp_demangle::Symbol::new(name)
+        .ok()
+        .and_then(|sym| sym.demangle(&Default::default()).ok())
+        .unwrap_or_else(|| name.to_string())
+}
+
+// First path component of a demangled name, looking through `<T as Trait>` wrappers
+fn namespace(name: &str) -> String {
+    let mut path = name.trim_start_matches(|c| "<&*(".contains(c));
+    for qualifier in &["mut ", "const ", "dyn "] {
+        path = path.trim_start_matches(qualifier);
+    }
+    if path.starts_with('[') {
+        return "[slice]".to_string();
+    }
+    let mut depth = 0;
+    for (i, c) in path.char_indices() {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            ':' if depth == 0 && path[i..].starts_with("::") => return path[..i].to_string(),
+            ' ' if depth == 0 => break,
+            _ => {}
+        }
+    }
+    "[no namespace]".to_string()
+}
+
+fn bar(ratio: f64) -> String {
+    let filled = ((ratio * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+    format!("{}{}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled))
+}
+
+fn delta(old: u64, new: u64) -> String {
+    if new >= old {
+        format!("+{}", human(new - old))
+    } else {
+        format!("-{}", human(old - new))
+    }
+}
+
+pub fn human(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, label: &str, file_size: u64) -> Entry {
+        Entry {
+            key: key.into(),
+            label: label.into(),
+            file_size,
+            mem_size: file_size,
+        }
+    }
+
+    #[test]
+    fn segments_are_keyed_by_type_and_ordinal() {
+        let input =
+            std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/2-pie")).unwrap();
+        let file = FileHeader::parse_or_print_error(&input.into()).unwrap();
+        let segs = segments(&file);
+        let loads: Vec<_> = segs.iter().filter(|e| e.key.starts_with("Load#")).collect();
+        assert!(loads.len() > 1);
+        for (n, e) in loads.iter().enumerate() {
+            assert_eq!(e.key, format!("Load#{}", n));
+        }
+    }
+
+    // A segment added in front moves every index after it, and shouldn't show up as all of
+    // them changing
+    #[test]
+    fn compare_matches_segments_that_moved() {
+        let old = [
+            entry("Load#0", "Load[0]", 10),
+            entry("Load#1", "Load[1]", 20),
+        ];
+        let new = [
+            entry("Interp#0", "Interp[0]", 5),
+            entry("Load#0", "Load[1]", 10),
+            entry("Load#1", "Load[2]", 20),
+        ];
+        let rows = compare_table("Segments", &old, &new).rows;
+        assert_eq!(rows.len(), 3);
+        let row = |label: &str| rows.iter().find(|r| r[0] == label).unwrap().clone();
+        assert_eq!(row("Interp[0]")[4], "new");
+        assert_eq!(row("Load[1]")[3], delta(10, 10));
+        assert_eq!(row("Load[2]")[3], delta(20, 20));
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    io::{self, Write},
+    os::{raw::c_int, unix::io::RawFd},
+    time::{Duration, Instant},
+};
+
+use delf::FileHeader;
+
+use crate::{
+    error::LoadError,
+    exit::Status,
+    loader::{Process, Startup},
+    stack::{self, Program, Stack},
+};
+
+// A loaded, relocated program stopped short of its constructors. Every run forks elk, so the
+// program gets a copy-on-write copy of the image and whatever it writes is gone again for the
+// next run: loading and relocating is paid for once, not per run.
+pub struct Snapshot<'a> {
+    process: &'a Process,
+    path: &'a str,
+    startup: Startup,
+    fini: u64,
+    entry: u64,
+    phdr: u64,
+    phent: usize,
+    phnum: usize,
+    // PT_GNU_STACK of the executable asks for one
+    executable_stack: bool,
+    pub stack_size: usize,
+    pub poison_stack: Option<u8>,
+    // KEY=VALUE strings, elk's own environment unless set otherwise
+    pub env: Vec<Vec<u8>>,
+    // Where the program's standard output goes, elk's own unless set
+    pub stdout: Option<RawFd>,
+}
+
+// How one run went
+#[derive(Debug, Clone, Copy)]
+pub struct Run {
+    // As from waitpid
+    pub status: c_int,
+    // From the fork to the jump to the entry point: the fork itself, the stack and the
+    // constructors. Equal to `elapsed` if the program died before getting there.
+    pub overhead: Duration,
+    // From the fork until the program was done
+    pub elapsed: Duration,
+}
+
+impl Process {
+    // Only for programs loaded into elk itself, which a fork copies along with elk
+    pub fn snapshot<'a>(
+        &'a self,
+        file: &FileHeader,
+        path: &'a str,
+    ) -> Result<Snapshot<'a>, LoadError> {
+        if !self.space().in_process() {
+            return Err(LoadError::Init {
+                object: path.to_string(),
+                reason: "only programs loaded into elk itself can be snapshotted".into(),
+            });
+        }
+        let startup = self.startup();
+        // Set up ahead of the first fork, which every run then inherits
+        let fini = startup.fini(None, true);
+        Ok(Snapshot {
+            process: self,
+            path,
+            startup,
+            fini,
+            entry: self.addr(file.entry_point),
+            phdr: file
+                .program_headers_addr()
+                .map_or(0, |addr| self.addr(addr)),
+            phent: file.program_header_info.size,
+            phnum: file.program_header_info.count,
+            executable_stack: file.executable_stack(),
+            stack_size: stack::DEFAULT_SIZE,
+            poison_stack: None,
+            env: stack::environment(false, &[]),
+            stdout: None,
+        })
+    }
+}
+
+impl Snapshot<'_> {
+    // Runs the program once with `args` past argv[0], and waits for it. elk may have other
+    // threads, whose locks a fork copies as they are, so its stack is laid out ahead and the
+    // child does nothing but system calls and the program's own code until the entry point.
+    pub fn run(&self, args: &[String]) -> io::Result<Run> {
+        let space = self.process.space();
+        let stack = Stack::new_in(space.clone(), self.stack_size, self.poison_stack)?;
+        if self.executable_stack {
+            stack.make_executable()?;
+        }
+        let (guard, top) = (stack.guard(), stack.top());
+        let program = Program {
+            entry: self.entry,
+            phdr: self.phdr,
+            phent: self.phent,
+            phnum: self.phnum,
+            execfn: self.path,
+        };
+        let args: Vec<Vec<u8>> = std::iter::once(self.path)
+            .chain(args.iter().map(String::as_str))
+            .map(|arg| arg.as_bytes().to_vec())
+            .collect();
+        let sp = stack.frame(&program, &args, &self.env)?.sp;
+        let ran = self.fork(args.len() as u64, sp);
+        // The child had its own copy, the parent's goes
+        space.unmap(guard, top - guard)?;
+        ran
+    }
+
+    fn fork(&self, argc: u64, sp: u64) -> io::Result<Run> {
+        io::stdout().flush()?;
+        let mut pipe = [0; 2];
+        if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let [read_end, write_end] = pipe;
+        let started = Instant::now();
+        let child = unsafe { libc::fork() };
+        if child == 0 {
+            unsafe { libc::close(read_end) };
+            // Nothing to return to in the child: a run that can't start is a failed run, and
+            // the parent says why
+            let e = self.start(argc, sp, started, write_end).unwrap_err();
+            let errno = e.raw_os_error().unwrap_or(libc::EIO).to_le_bytes();
+            unsafe {
+                libc::write(write_end, errno.as_ptr().cast(), errno.len());
+                libc::_exit(Status::Load as c_int)
+            };
+        }
+        unsafe { libc::close(write_end) };
+        if child < 0 {
+            unsafe { libc::close(read_end) };
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut status = 0;
+        let waited = unsafe { libc::waitpid(child, &mut status, 0) };
+        let elapsed = started.elapsed();
+        let mut report = [0u8; 8];
+        let reported = unsafe { libc::read(read_end, report.as_mut_ptr().cast(), report.len()) };
+        unsafe { libc::close(read_end) };
+        if waited < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let overhead = match reported {
+            8 => Duration::from_nanos(u64::from_le_bytes(report)),
+            4 => {
+                let errno = i32::from_le_bytes([report[0], report[1], report[2], report[3]]);
+                let e = io::Error::from_raw_os_error(errno);
+                eprintln!("Could not start {}: {}", self.path, e);
+                elapsed
+            }
+            _ => elapsed,
+        };
+        Ok(Run {
+            status,
+            overhead,
+            elapsed,
+        })
+    }
+
+    // In the child: runs the constructors the way `elk run` does, tells the parent how long
+    // getting there took through `report` and jumps to the entry point. Only returns on failure.
+    fn start(&self, argc: u64, sp: u64, started: Instant, report: c_int) -> io::Result<()> {
+        if let Some(fd) = self.stdout {
+            if unsafe { libc::dup2(fd, libc::STDOUT_FILENO) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let (argv, envp) = (sp + 8, sp + 8 * (argc + 2));
+        self.startup
+            .initialize(&**self.process.space(), argc, argv, envp, true)?;
+        let nanos = (started.elapsed().as_nanos() as u64).to_le_bytes();
+        unsafe {
+            libc::write(report, nanos.as_ptr().cast(), nanos.len());
+            libc::close(report);
+            stack::jmp_on_stack(self.entry, sp, self.fini)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deps,
+        loader::{LoadOptions, DEFAULT_BASE},
+    };
+
+    const LADDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/");
+
+    // 5-lazy binds its PLT slot in each run; the image the next run starts from still has it
+    // unbound, and prints as the first did
+    #[test]
+    fn every_run_starts_from_the_snapshot() {
+        let path = format!("{}5-lazy", LADDER);
+        let input = crate::source::read(&path).unwrap();
+        let objects = deps::objects(&path, FileHeader::parse_or_describe(&input).unwrap());
+        let main = &objects[0].file;
+        // Clear of the base the loader's own tests load at
+        let base = DEFAULT_BASE + 0x1000_0000;
+        let process =
+            Process::load_with_libraries(main, base, LoadOptions::default(), &objects[1..])
+                .unwrap();
+        let slot = main.read_plt_rela_entries().unwrap()[0].offset.0 + base;
+        let unbound = process.space().read_u64(slot).unwrap();
+
+        let mut pipe = [0; 2];
+        assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+        let mut snapshot = process.snapshot(main, &path).unwrap();
+        snapshot.stdout = Some(pipe[1]);
+        for _ in 0..2 {
+            let run = snapshot.run(&[]).unwrap();
+            assert!(libc::WIFEXITED(run.status) && libc::WEXITSTATUS(run.status) == 0);
+            assert!(run.overhead <= run.elapsed);
+            assert_eq!(process.space().read_u64(slot).unwrap(), unbound);
+        }
+        unsafe { libc::close(pipe[1]) };
+        let mut output = vec![0u8; 256];
+        let read = unsafe { libc::read(pipe[0], output.as_mut_ptr().cast(), output.len()) };
+        unsafe { libc::close(pipe[0]) };
+        output.truncate(read.max(0) as usize);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Hello from liblazy.so, bound on first call!\n".repeat(2)
+        );
+    }
+}
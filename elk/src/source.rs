@@ -0,0 +1,286 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    fs,
+    io::{self, Read},
+    os::unix::io::AsRawFd,
+    path::Path,
+    slice::from_raw_parts,
+    sync::OnceLock,
+};
+
+use delf::{
+    data::Data,
+    detect::{detect, Compression, Format},
+    types::{SectionFlags, SectionHeader},
+};
+use mmap::{MapOption, MemoryMap};
+
+const ELFCOMPRESS_ZLIB: u32 = 1;
+const ELFCOMPRESS_ZSTD: u32 = 2;
+// Elf64_Chdr: type, reserved, uncompressed size, alignment
+const CHDR_SIZE: usize = 24;
+// Beyond any real kernel or module, so a few crafted bytes can't inflate to fill memory
+pub const MAX_DECOMPRESSED: usize = 1 << 30;
+
+// Path naming standard input
+pub const STDIN: &str = "-";
+
+static STDIN_DATA: OnceLock<Vec<u8>> = OnceLock::new();
+
+// A file mapped read-only. MemoryMap rounds its length up to whole pages, so the file's own
+// length is kept alongside.
+struct Mapped {
+    map: MemoryMap,
+    len: usize,
+}
+
+// Nothing ever writes through the mapping, and it is unmapped only once every Data viewing it is
+// gone
+unsafe impl Send for Mapped {}
+unsafe impl Sync for Mapped {}
+
+impl AsRef<[u8]> for Mapped {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { from_raw_parts(self.map.data(), self.len) }
+    }
+}
+
+// Reads an input file, unwrapping compressed payloads (`.ko.zst`, `vmlinuz`, ...) so the rest of
+// elk only ever sees the ELF inside. `image:path/inside` names a member of a cpio or tar
+// archive, and nests (`initrd.img:lib/modules.tar:foo.ko`). `-` reads standard input, and
+// http(s) URLs are fetched in part, see `remote::fetch`. Plain files are memory-mapped rather
+// than read, and only compressed ones get copied, to inflate them.
+pub fn read(path: &str) -> Result<Data, Box<dyn Error>> {
+    let input = match path.rsplit_once(':') {
+        _ if is_url(path) => fetch(path, false)?,
+        Some((outer, inner)) if !Path::new(path).exists() => {
+            crate::container::member(&read(outer)?, inner)?.into()
+        }
+        _ => map_raw(path)?,
+    };
+    if !matches!(detect(input.present()), Format::Compressed(_)) && !is_bzimage(input.present()) {
+        return Ok(input);
+    }
+    let decoded = decode(input.to_vec()).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(decoded.into())
+}
+
+// Like `read`, but URLs are downloaded in full, for callers that need every byte (running it)
+pub fn read_whole(path: &str) -> Result<Data, Box<dyn Error>> {
+    match is_url(path) {
+        true => Ok(decode(fetch(path, true)?.to_vec())?.into()),
+        false => read(path),
+    }
+}
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+// The file's bytes as they are. Standard input can only be read once, so it is kept for any
+// later read of `-` in the same run.
+pub fn read_raw(path: &str) -> io::Result<Vec<u8>> {
+    if path != STDIN {
+        return fs::read(path);
+    }
+    if let Some(data) = STDIN_DATA.get() {
+        return Ok(data.clone());
+    }
+    let mut data = Vec::new();
+    io::stdin().lock().read_to_end(&mut data)?;
+    Ok(STDIN_DATA.get_or_init(|| data).clone())
+}
+
+// Like `read_raw`, but regular files are mapped, so pages are read in as they are looked at.
+// Truncating the file while it is mapped kills elk with SIGBUS, as it would any mmap user.
+pub fn map_raw(path: impl AsRef<Path>) -> io::Result<Data> {
+    let path = path.as_ref();
+    if path == Path::new(STDIN) {
+        return read_raw(STDIN).map(Data::from);
+    }
+    let file = fs::File::open(path)?;
+    let meta = file.metadata()?;
+    // procfs and sysfs files claim to be empty, pipes and devices can't be mapped
+    if !meta.is_file() || meta.len() == 0 {
+        return fs::read(path).map(Data::from);
+    }
+    let len = meta.len() as usize;
+    let map = MemoryMap::new(
+        len,
+        &[MapOption::MapReadable, MapOption::MapFd(file.as_raw_fd())],
+    )
+    .map_err(|e| io::Error::other(format!("could not map {}: {}", path.display(), e)))?;
+    Ok(Data::new(Mapped { map, len }))
+}
+
+pub fn decode(input: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    match detect(&input) {
+        Format::Compressed(c) => {
+            let mut out = Vec::new();
+            decompress(c, &input, &mut out, MAX_DECOMPRESSED)?;
+            Ok(out)
+        }
+        _ if is_bzimage(&input) => embedded_elf(&input)
+            .ok_or_else(|| "no compressed ELF payload found in kernel image".into()),
+        _ => Ok(input),
+    }
+}
+
+// Contents of a section, inflated if it is SHF_COMPRESSED (as `--compress-debug-sections` does)
+pub fn section_data(sh: &SectionHeader) -> Result<Cow<'_, [u8]>, Box<dyn Error>> {
+    if !sh.flags.contains(SectionFlags::Compressed) {
+        return Ok(Cow::Borrowed(&sh.data));
+    }
+    let header = sh
+        .data
+        .get(..CHDR_SIZE)
+        .ok_or_else(|| format!("{}: truncated compression header", sh.name))?;
+    let typ = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let payload = &sh.data[CHDR_SIZE..];
+    let mut out = Vec::new();
+    match typ {
+        ELFCOMPRESS_ZLIB => inflate_zlib(payload, &mut out)?,
+        ELFCOMPRESS_ZSTD => decompress(Compression::Zstd, payload, &mut out, MAX_DECOMPRESSED)?,
+        _ => return Err(format!("{}: unknown compression type {}", sh.name, typ).into()),
+    }
+    Ok(Cow::Owned(out))
+}
+
+// x86 boot protocol images carry "HdrS" in their setup header
+fn is_bzimage(input: &[u8]) -> bool {
+    input.get(0x202..0x206) == Some(b"HdrS")
+}
+
+// Like extract-vmlinux: try every compression magic in the image until one inflates to an ELF
+fn embedded_elf(input: &[u8]) -> Option<Vec<u8>> {
+    let magics: &[(&[u8], Compression)] = &[
+        (&[0x1f, 0x8b, 0x08], Compression::Gzip),
+        (b"\xfd7zXZ\0", Compression::Xz),
+        (&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd),
+    ];
+    for start in 0..input.len() {
+        for (magic, c) in magics {
+            if !input[start..].starts_with(magic) {
+                continue;
+            }
+            // Kernel payloads are followed by trailing data, so keep whatever was inflated
+            let mut out = Vec::new();
+            let _ = decompress(*c, &input[start..], &mut out, MAX_DECOMPRESSED);
+            if matches!(detect(&out), Format::Elf { .. }) {
+                return Some(out);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(feature = "http")]
+fn fetch(url: &str, whole: bool) -> Result<Data, Box<dyn Error>> {
+    // ureq's errors already name the URL
+    crate::remote::fetch(url, whole)
+}
+
+#[cfg(not(feature = "http"))]
+fn fetch(url: &str, _: bool) -> Result<Data, Box<dyn Error>> {
+    Err(format!("{}: URLs need elk built with the http feature", url).into())
+}
+
+// Inflates `data` into `out`, failing once it would hold more than `limit` bytes. Whatever was
+// inflated until then is kept.
+#[cfg(feature = "decompress")]
+fn decompress(
+    c: Compression,
+    data: &[u8],
+    out: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut out = Capped { out, limit };
+    match c {
+        Compression::Gzip => {
+            io::copy(&mut flate2::read::GzDecoder::new(data), &mut out)?;
+        }
+        Compression::Xz => lzma_rs::xz_decompress(&mut &data[..], &mut out)?,
+        Compression::Zstd => {
+            io::copy(&mut ruzstd::StreamingDecoder::new(data)?, &mut out)?;
+        }
+        _ => return Err(format!("no decoder for {:?} compressed input", c).into()),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "decompress")]
+fn inflate_zlib(data: &[u8], out: &mut Vec<u8>) -> Result<(), Box<dyn Error>> {
+    let mut out = Capped {
+        out,
+        limit: MAX_DECOMPRESSED,
+    };
+    io::copy(&mut flate2::read::ZlibDecoder::new(data), &mut out)?;
+    Ok(())
+}
+
+// Where decompressors write, refusing to grow past `limit`
+#[cfg(feature = "decompress")]
+struct Capped<'a> {
+    out: &'a mut Vec<u8>,
+    limit: usize,
+}
+
+#[cfg(feature = "decompress")]
+impl io::Write for Capped<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.out.len() + buf.len() > self.limit {
+            return Err(io::Error::other(format!(
+                "decompresses to more than {:#x} bytes",
+                self.limit
+            )));
+        }
+        self.out.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "decompress"))]
+fn inflate_zlib(_: &[u8], _: &mut Vec<u8>) -> Result<(), Box<dyn Error>> {
+    Err("compressed sections need elk built with the decompress feature".into())
+}
+
+#[cfg(not(feature = "decompress"))]
+fn decompress(c: Compression, _: &[u8], _: &mut Vec<u8>, _: usize) -> Result<(), Box<dyn Error>> {
+    Err(format!(
+        "{:?} compressed input needs elk built with the decompress feature",
+        c
+    )
+    .into())
+}
+
+#[cfg(all(test, feature = "decompress"))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // A few hundred bytes that inflate to a megabyte
+    #[test]
+    fn decompressing_stops_at_the_limit() {
+        let compressed = gzip(&vec![0; 1 << 20]);
+        assert!(compressed.len() < 4096);
+        let mut out = Vec::new();
+        decompress(Compression::Gzip, &compressed, &mut out, 1 << 20).unwrap();
+        assert_eq!(out.len(), 1 << 20);
+
+        let mut out = Vec::new();
+        let e = decompress(Compression::Gzip, &compressed, &mut out, 1 << 16).unwrap_err();
+        assert_eq!(e.to_string(), "decompresses to more than 0x10000 bytes");
+        assert!(out.len() <= 1 << 16);
+    }
+}
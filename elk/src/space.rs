@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    mem::transmute,
+    ops::Range,
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use libc::c_void;
+use region::Protection;
+
+use crate::image::PAGE_SIZE;
+
+// Where the loader builds an image: elk's own address space, a forked child it controls with
+// ptrace, or a buffer standing in for a process. Addresses are always the target's.
+pub trait AddressSpace: Send + Sync {
+    // Maps `len` zeroed, writable bytes at `at`, replacing whatever was there, or wherever the
+    // target has room. Returns where.
+    fn map(&self, at: Option<u64>, len: u64) -> io::Result<u64>;
+    fn unmap(&self, start: u64, len: u64) -> io::Result<()>;
+    fn protect(&self, start: u64, len: u64, protection: Protection) -> io::Result<()>;
+    fn read(&self, addr: u64, buf: &mut [u8]) -> io::Result<()>;
+    fn write(&self, addr: u64, bytes: &[u8]) -> io::Result<()>;
+    // Calls the function at `addr` with up to three integer arguments and returns what it
+    // returns, for IFUNC resolvers and constructors
+    fn call(&self, addr: u64, args: &[u64]) -> io::Result<u64>;
+    // Whether the space is elk's own, where loaded code can call back into elk
+    fn in_process(&self) -> bool {
+        false
+    }
+
+    fn read_u64(&self, addr: u64) -> io::Result<u64> {
+        let mut word = [0; 8];
+        self.read(addr, &mut word)?;
+        Ok(u64::from_le_bytes(word))
+    }
+
+    fn write_u64(&self, addr: u64, value: u64) -> io::Result<()> {
+        self.write(addr, &value.to_le_bytes())
+    }
+}
+
+fn page_span(start: u64, len: u64) -> Range<u64> {
+    let end = (start + len).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    start & !(PAGE_SIZE - 1)..end
+}
+
+// Fixed mappings never replace what is already there: the kernel fails them instead, or, before
+// MAP_FIXED_NOREPLACE (Linux 4.17), takes the address as a hint and maps somewhere else
+fn map_flags(at: Option<u64>) -> i32 {
+    let fixed = match at {
+        Some(_) => libc::MAP_FIXED_NOREPLACE,
+        None => 0,
+    };
+    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE | fixed
+}
+
+fn occupied(at: u64, len: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AddrInUse,
+        format!("{:#x}..{:#x} is already mapped", at, at + len),
+    )
+}
+
+// The result of an mmap with `map_flags(at)`, as an error unless it landed at `at`. A mapping
+// placed elsewhere is undone with `unmap` first.
+fn placed(
+    at: Option<u64>,
+    len: u64,
+    result: io::Result<u64>,
+    unmap: impl FnOnce(u64, u64) -> io::Result<()>,
+) -> io::Result<u64> {
+    match (at, result) {
+        (Some(at), Err(e)) if e.raw_os_error() == Some(libc::EEXIST) => Err(occupied(at, len)),
+        (Some(at), Ok(addr)) if addr != at => {
+            unmap(addr, len)?;
+            Err(occupied(at, len))
+        }
+        (_, result) => result,
+    }
+}
+
+// elk's own address space, for running the program in-process. Reads and writes go straight
+// through pointers: callers only pass addresses they mapped.
+pub struct Local;
+
+impl AddressSpace for Local {
+    fn map(&self, at: Option<u64>, len: u64) -> io::Result<u64> {
+        let addr = unsafe {
+            libc::mmap(
+                at.unwrap_or(0) as *mut c_void,
+                len as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                map_flags(at),
+                -1,
+                0,
+            )
+        };
+        let result = match addr {
+            libc::MAP_FAILED => Err(io::Error::last_os_error()),
+            addr => Ok(addr as u64),
+        };
+        placed(at, len, result, |addr, len| self.unmap(addr, len))
+    }
+
+    fn unmap(&self, start: u64, len: u64) -> io::Result<()> {
+        match unsafe { libc::munmap(start as *mut c_void, len as usize) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    fn protect(&self, start: u64, len: u64, protection: Protection) -> io::Result<()> {
+        unsafe { region::protect(start as *const u8, len as usize, protection) }
+            .map_err(io::Error::other)
+    }
+
+    fn read(&self, addr: u64, buf: &mut [u8]) -> io::Result<()> {
+        unsafe { ptr::copy_nonoverlapping(addr as *const u8, buf.as_mut_ptr(), buf.len()) };
+        Ok(())
+    }
+
+    fn write(&self, addr: u64, bytes: &[u8]) -> io::Result<()> {
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), addr as *mut u8, bytes.len()) };
+        Ok(())
+    }
+
+    // Pointer-sized slots (GOT entries) are written atomically so threads reading them through a
+    // PLT never see a torn address; text relocations can be unaligned and fall back to a plain
+    // write
+    fn write_u64(&self, addr: u64, value: u64) -> io::Result<()> {
+        unsafe {
+            match addr.is_multiple_of(8) {
+                true => (*(addr as *const AtomicU64)).store(value, Ordering::Release),
+                false => (addr as *mut u64).write_unaligned(value),
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&self, addr: u64, args: &[u64]) -> io::Result<u64> {
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        let function: extern "C" fn(u64, u64, u64) -> u64 = unsafe { transmute(addr) };
+        Ok(function(arg(0), arg(1), arg(2)))
+    }
+
+    fn in_process(&self) -> bool {
+        true
+    }
+}
+
+// Pages in memory standing in for a process, for building an image without running it. Writes
+// ignore page protection the way ptrace pokes do; the loader lifts it itself where it has to.
+#[derive(Default)]
+pub struct Buffer {
+    pages: Mutex<BTreeMap<u64, (Vec<u8>, Protection)>>,
+}
+
+// Where Buffer::map places mappings that don't ask for an address
+const BUFFER_START: u64 = 0x7f00_0000_0000;
+
+impl Buffer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Protection of the page holding `addr`, None if it isn't mapped
+    pub fn protection(&self, addr: u64) -> Option<Protection> {
+        let pages = self.pages.lock().unwrap_or_else(PoisonError::into_inner);
+        pages.get(&(addr & !(PAGE_SIZE - 1))).map(|(_, p)| *p)
+    }
+
+    // Calls `f` with each mapped page overlapping `len` bytes at `addr`, and the part of the
+    // page and of the `len` bytes that overlap
+    fn each_page(
+        &self,
+        addr: u64,
+        len: usize,
+        mut f: impl FnMut(&mut Vec<u8>, Range<usize>, Range<usize>),
+    ) -> io::Result<()> {
+        let mut pages = self.pages.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut done = 0;
+        while done < len {
+            let at = addr + done as u64;
+            let page = at & !(PAGE_SIZE - 1);
+            let (bytes, _) = pages
+                .get_mut(&page)
+                .ok_or_else(|| io::Error::from_raw_os_error(libc::EFAULT))?;
+            let offset = (at - page) as usize;
+            let count = (PAGE_SIZE as usize - offset).min(len - done);
+            f(bytes, offset..offset + count, done..done + count);
+            done += count;
+        }
+        Ok(())
+    }
+}
+
+impl AddressSpace for Buffer {
+    fn map(&self, at: Option<u64>, len: u64) -> io::Result<u64> {
+        let mut pages = self.pages.lock().unwrap_or_else(PoisonError::into_inner);
+        let start = match at {
+            Some(at) => match page_span(at, len)
+                .step_by(PAGE_SIZE as usize)
+                .any(|page| pages.contains_key(&page))
+            {
+                true => return Err(occupied(at, len)),
+                false => at,
+            },
+            None => pages
+                .keys()
+                .next_back()
+                .map_or(BUFFER_START, |&last| last + 2 * PAGE_SIZE)
+                .max(BUFFER_START),
+        };
+        for page in page_span(start, len).step_by(PAGE_SIZE as usize) {
+            pages.insert(page, (vec![0; PAGE_SIZE as usize], Protection::READ_WRITE));
+        }
+        Ok(start)
+    }
+
+    fn unmap(&self, start: u64, len: u64) -> io::Result<()> {
+        let mut pages = self.pages.lock().unwrap_or_else(PoisonError::into_inner);
+        for page in page_span(start, len).step_by(PAGE_SIZE as usize) {
+            pages.remove(&page);
+        }
+        Ok(())
+    }
+
+    fn protect(&self, start: u64, len: u64, protection: Protection) -> io::Result<()> {
+        let mut pages = self.pages.lock().unwrap_or_else(PoisonError::into_inner);
+        for page in page_span(start, len).step_by(PAGE_SIZE as usize) {
+            match pages.get_mut(&page) {
+                Some((_, p)) => *p = protection,
+                None => return Err(io::Error::from_raw_os_error(libc::ENOMEM)),
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, addr: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.each_page(addr, buf.len(), |page, from, to| {
+            buf[to].copy_from_slice(&page[from])
+        })
+    }
+
+    fn write(&self, addr: u64, bytes: &[u8]) -> io::Result<()> {
+        self.each_page(addr, bytes.len(), |page, to, from| {
+            page[to].copy_from_slice(&bytes[from])
+        })
+    }
+
+    fn call(&self, addr: u64, _: &[u64]) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("can't run the code at {:#x} in a buffer", addr),
+        ))
+    }
+}
+
+#[cfg(feature = "ptrace")]
+mod child;
+#[cfg(feature = "ptrace")]
+pub use child::Child;
+
+// Stands in for the ptrace-controlled child in builds without the ptrace feature. There is
+// never one: `spawn` always fails.
+#[cfg(not(feature = "ptrace"))]
+pub enum Child {}
+
+#[cfg(not(feature = "ptrace"))]
+impl Child {
+    pub fn spawn(_: bool) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "running in a child needs elk built with the ptrace feature",
+        ))
+    }
+
+    pub fn pid(&self) -> libc::pid_t {
+        match *self {}
+    }
+
+    pub fn set_thread_pointer(&self, _: u64) {
+        match *self {}
+    }
+
+    pub fn start(&self, _: u64, _: u64, _: u64) -> io::Result<i32> {
+        match *self {}
+    }
+
+    pub fn detach_stopped(&self, _: u64, _: u64, _: u64) -> io::Result<()> {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "ptrace"))]
+impl AddressSpace for Child {
+    fn map(&self, _: Option<u64>, _: u64) -> io::Result<u64> {
+        match *self {}
+    }
+
+    fn unmap(&self, _: u64, _: u64) -> io::Result<()> {
+        match *self {}
+    }
+
+    fn protect(&self, _: u64, _: u64, _: Protection) -> io::Result<()> {
+        match *self {}
+    }
+
+    fn read(&self, _: u64, _: &mut [u8]) -> io::Result<()> {
+        match *self {}
+    }
+
+    fn write(&self, _: u64, _: &[u8]) -> io::Result<()> {
+        match *self {}
+    }
+
+    fn call(&self, _: u64, _: &[u64]) -> io::Result<u64> {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_pages() {
+        let buffer = Buffer::new();
+        let start = buffer.map(Some(0x10000), 0x1800).unwrap();
+        assert_eq!(start, 0x10000);
+        assert!(buffer.map(Some(0x11000), 0x1000).is_err());
+        // Across the page boundary
+        buffer.write(0x10ffc, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(buffer.read_u64(0x10ffc).unwrap(), 0x0807_0605_0403_0201);
+        assert!(buffer.read_u64(0x11ffc).is_err());
+
+        buffer.protect(0x11000, 8, Protection::READ).unwrap();
+        assert_eq!(buffer.protection(0x10000), Some(Protection::READ_WRITE));
+        assert_eq!(buffer.protection(0x11800), Some(Protection::READ));
+        assert!(buffer.call(0x10000, &[]).is_err());
+
+        let anywhere = buffer.map(None, 0x1000).unwrap();
+        assert!(anywhere >= BUFFER_START);
+        buffer.unmap(0x10000, 0x2000).unwrap();
+        assert_eq!(buffer.protection(0x10000), None);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    mem::MaybeUninit,
+    os::unix::fs::FileExt,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use libc::{pid_t, user_regs_struct};
+use region::Protection;
+
+use super::{map_flags, page_span, placed, AddressSpace};
+
+fn prot_bits(protection: Protection) -> i32 {
+    [
+        (Protection::READ, libc::PROT_READ),
+        (Protection::WRITE, libc::PROT_WRITE),
+        (Protection::EXECUTE, libc::PROT_EXEC),
+    ]
+    .iter()
+    .filter(|(flag, _)| protection.contains(*flag))
+    .fold(libc::PROT_NONE, |bits, (_, bit)| bits | bit)
+}
+
+// `syscall; int3`: a child made to run this does one system call and stops with SIGTRAP. The
+// child is a fork of elk, so the gadget is at the same address in both.
+std::arch::global_asm!(
+    ".globl elk_remote_syscall",
+    "elk_remote_syscall:",
+    "syscall",
+    "int3",
+);
+
+extern "C" {
+    fn elk_remote_syscall();
+}
+
+fn gadget() -> u64 {
+    elk_remote_syscall as *const () as u64
+}
+
+// Stack for functions called in the child, which return to the gadget's int3
+const SCRATCH_STACK: u64 = 0x10000;
+
+// A forked copy of elk stopped under ptrace, for building the image in a fresh process rather
+// than elk's own. Memory goes through /proc/<pid>/mem, which ignores page protection like
+// ptrace pokes; mappings are made by having the child run system calls. The child dies with elk
+// unless `start` hands it over to the program.
+pub struct Child {
+    pid: pid_t,
+    mem: File,
+    // Registers the child stopped with, which every remote syscall and call starts from
+    regs: Mutex<user_regs_struct>,
+    scratch: u64,
+    started: AtomicBool,
+}
+
+impl Child {
+    // A child that will be left stopped for `detach_stopped` gets a session of its own: left in
+    // elk's process group, it would be sent SIGHUP as soon as elk exits and orphans the group.
+    // Others stay in elk's, so the terminal still treats the program and elk as one job.
+    pub fn spawn(detached: bool) -> io::Result<Self> {
+        io::stdout().flush()?;
+        let pid = match unsafe { libc::fork() } {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => unsafe {
+                if detached {
+                    libc::setsid();
+                }
+                libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+                libc::raise(libc::SIGSTOP);
+                // Only reached if elk lets go without starting a program
+                libc::_exit(127)
+            },
+            pid => pid,
+        };
+        let stopped = Self::wait_stop(pid).and_then(|signal| match signal {
+            libc::SIGSTOP => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "child stopped with signal {} instead of SIGSTOP",
+                signal
+            ))),
+        });
+        if let Err(e) = stopped {
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            return Err(e);
+        }
+        let mut child = Self {
+            pid,
+            mem: OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(format!("/proc/{}/mem", pid))?,
+            regs: Mutex::new(unsafe { MaybeUninit::zeroed().assume_init() }),
+            scratch: 0,
+            started: AtomicBool::new(false),
+        };
+        let options = libc::PTRACE_O_EXITKILL as usize;
+        child.ptrace(libc::PTRACE_SETOPTIONS, 0, options)?;
+        let mut regs = child.get_regs()?;
+        // Stopped inside raise's kill(): no restarting it when the child resumes
+        regs.orig_rax = u64::MAX;
+        *child.regs.get_mut().unwrap_or_else(PoisonError::into_inner) = regs;
+        child.scratch = child.map(None, SCRATCH_STACK)? + SCRATCH_STACK;
+        Ok(child)
+    }
+
+    pub fn pid(&self) -> pid_t {
+        self.pid
+    }
+
+    fn ptrace(&self, request: libc::c_uint, addr: usize, data: usize) -> io::Result<()> {
+        if self.started.load(Ordering::SeqCst) {
+            return Err(io::Error::other("the child is running the program"));
+        }
+        match unsafe { libc::ptrace(request, self.pid, addr, data) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    fn get_regs(&self) -> io::Result<user_regs_struct> {
+        let mut regs = MaybeUninit::<user_regs_struct>::zeroed();
+        self.ptrace(libc::PTRACE_GETREGS, 0, regs.as_mut_ptr() as usize)?;
+        Ok(unsafe { regs.assume_init() })
+    }
+
+    fn set_regs(&self, regs: &user_regs_struct) -> io::Result<()> {
+        self.ptrace(
+            libc::PTRACE_SETREGS,
+            0,
+            regs as *const user_regs_struct as usize,
+        )
+    }
+
+    // The signal the child stopped with
+    fn wait_stop(pid: pid_t) -> io::Result<i32> {
+        let mut status = 0;
+        if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        match libc::WIFSTOPPED(status) {
+            true => Ok(libc::WSTOPSIG(status)),
+            false => Err(io::Error::other(format!(
+                "child exited with status {:#x}",
+                status
+            ))),
+        }
+    }
+
+    // Runs the child from `regs` until it traps, and returns its registers then
+    fn run(&self, regs: &user_regs_struct) -> io::Result<user_regs_struct> {
+        self.set_regs(regs)?;
+        self.ptrace(libc::PTRACE_CONT, 0, 0)?;
+        match Self::wait_stop(self.pid)? {
+            libc::SIGTRAP => self.get_regs(),
+            signal => Err(io::Error::other(format!(
+                "child stopped with signal {} at {:#x}",
+                signal,
+                self.get_regs().map_or(0, |regs| regs.rip)
+            ))),
+        }
+    }
+
+    fn base_regs(&self) -> user_regs_struct {
+        *self.regs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn syscall(&self, number: i64, args: [u64; 6]) -> io::Result<u64> {
+        let mut regs = self.base_regs();
+        regs.rax = number as u64;
+        regs.rdi = args[0];
+        regs.rsi = args[1];
+        regs.rdx = args[2];
+        regs.r10 = args[3];
+        regs.r8 = args[4];
+        regs.r9 = args[5];
+        regs.rip = gadget();
+        match self.run(&regs)?.rax as i64 {
+            error @ -4095..=-1 => Err(io::Error::from_raw_os_error(-error as i32)),
+            value => Ok(value as u64),
+        }
+    }
+
+    // %fs for functions called in the child from now on, and for the program once started
+    pub fn set_thread_pointer(&self, tp: u64) {
+        self.regs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fs_base = tp;
+    }
+
+    // Lets the child go at `entry` with the stack pointer and %rdx the ELF entry point expects,
+    // and waits for it. Returns the child's wait status.
+    pub fn start(&self, entry: u64, sp: u64, rdx: u64) -> io::Result<i32> {
+        self.release(entry, sp, rdx, 0)?;
+        let mut status = 0;
+        if unsafe { libc::waitpid(self.pid, &mut status, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(status)
+    }
+
+    // Like `start`, but the child stays stopped at `entry` once elk lets go of it, for a
+    // debugger to attach to or SIGCONT to resume
+    pub fn detach_stopped(&self, entry: u64, sp: u64, rdx: u64) -> io::Result<()> {
+        self.release(entry, sp, rdx, libc::SIGSTOP)
+    }
+
+    // Detaches with the registers the program starts with, delivering `signal` unless 0
+    fn release(&self, entry: u64, sp: u64, rdx: u64, signal: i32) -> io::Result<()> {
+        let base = self.base_regs();
+        let mut regs: user_regs_struct = unsafe { MaybeUninit::zeroed().assume_init() };
+        regs.rip = entry;
+        regs.rsp = sp;
+        regs.rdx = rdx;
+        regs.orig_rax = u64::MAX;
+        regs.fs_base = base.fs_base;
+        regs.eflags = base.eflags;
+        (regs.cs, regs.ss, regs.ds, regs.es) = (base.cs, base.ss, base.ds, base.es);
+        self.set_regs(&regs)?;
+        self.ptrace(libc::PTRACE_DETACH, 0, signal as usize)?;
+        self.started.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        if !self.started.load(Ordering::SeqCst) {
+            unsafe {
+                libc::kill(self.pid, libc::SIGKILL);
+                libc::waitpid(self.pid, ptr::null_mut(), 0);
+            }
+        }
+    }
+}
+
+impl AddressSpace for Child {
+    fn map(&self, at: Option<u64>, len: u64) -> io::Result<u64> {
+        let prot = (libc::PROT_READ | libc::PROT_WRITE) as u64;
+        let args = [
+            at.unwrap_or(0),
+            len,
+            prot,
+            map_flags(at) as u64,
+            u64::MAX,
+            0,
+        ];
+        let result = self.syscall(libc::SYS_mmap, args);
+        placed(at, len, result, |addr, len| self.unmap(addr, len))
+    }
+
+    fn unmap(&self, start: u64, len: u64) -> io::Result<()> {
+        self.syscall(libc::SYS_munmap, [start, len, 0, 0, 0, 0])
+            .map(drop)
+    }
+
+    fn protect(&self, start: u64, len: u64, protection: Protection) -> io::Result<()> {
+        let pages = page_span(start, len);
+        let args = [
+            pages.start,
+            pages.end - pages.start,
+            prot_bits(protection) as u64,
+            0,
+            0,
+            0,
+        ];
+        self.syscall(libc::SYS_mprotect, args).map(drop)
+    }
+
+    fn read(&self, addr: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.mem.read_exact_at(buf, addr)
+    }
+
+    fn write(&self, addr: u64, bytes: &[u8]) -> io::Result<()> {
+        self.mem.write_all_at(bytes, addr)
+    }
+
+    fn call(&self, addr: u64, args: &[u64]) -> io::Result<u64> {
+        let mut regs = self.base_regs();
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        (regs.rdi, regs.rsi, regs.rdx) = (arg(0), arg(1), arg(2));
+        // Returns to the int3, with the stack aligned as after a call
+        regs.rsp = (self.scratch & !15) - 8;
+        self.write_u64(regs.rsp, gadget() + 2)?;
+        regs.rip = addr;
+        regs.rax = 0;
+        Ok(self.run(&regs)?.rax)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Forked with the test, so at the same address in the child
+    extern "C" fn exit_7() {
+        unsafe { libc::_exit(7) }
+    }
+
+    fn stack(child: &Child) -> u64 {
+        (child.map(None, 0x4000).unwrap() + 0x4000) & !15
+    }
+
+    #[test]
+    fn started_children_run_and_are_reaped() {
+        let child = Child::spawn(false).unwrap();
+        assert_eq!(unsafe { libc::getsid(child.pid()) }, unsafe {
+            libc::getsid(0)
+        });
+        let status = child
+            .start(exit_7 as *const () as u64, stack(&child), 0)
+            .unwrap();
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 7);
+    }
+
+    #[test]
+    fn detached_children_stop_in_a_session_of_their_own() {
+        let child = Child::spawn(true).unwrap();
+        let pid = child.pid();
+        assert_eq!(unsafe { libc::getsid(pid) }, pid);
+        child
+            .detach_stopped(exit_7 as *const () as u64, stack(&child), 0)
+            .unwrap();
+        let mut status = 0;
+        assert_eq!(
+            unsafe { libc::waitpid(pid, &mut status, libc::WUNTRACED) },
+            pid
+        );
+        assert!(libc::WIFSTOPPED(status));
+        unsafe { libc::kill(pid, libc::SIGCONT) };
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert_eq!(libc::WEXITSTATUS(status), 7);
+    }
+}
@@ -0,0 +1,262 @@
+use std::{env, io, ops::Range, os::unix::ffi::OsStrExt, sync::Arc};
+
+use libc::c_void;
+use region::Protection;
+
+use crate::space::{AddressSpace, Local};
+
+const PAGE_SIZE: usize = 0x1000;
+// What `ulimit -s` gives most systems
+pub const DEFAULT_SIZE: usize = 8 << 20;
+
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_BASE: u64 = 7;
+const AT_FLAGS: u64 = 8;
+const AT_ENTRY: u64 = 9;
+const AT_UID: u64 = 11;
+const AT_EUID: u64 = 12;
+const AT_GID: u64 = 13;
+const AT_EGID: u64 = 14;
+const AT_PLATFORM: u64 = 15;
+const AT_HWCAP: u64 = 16;
+const AT_CLKTCK: u64 = 17;
+const AT_SECURE: u64 = 23;
+const AT_RANDOM: u64 = 25;
+const AT_HWCAP2: u64 = 26;
+const AT_EXECFN: u64 = 31;
+const AT_SYSINFO_EHDR: u64 = 33;
+const AT_MINSIGSTKSZ: u64 = 51;
+
+const PLATFORM: &[u8] = b"x86_64";
+// Most entries `frame` puts in the auxiliary vector, AT_NULL included
+const AUX_ENTRIES: usize = 21;
+
+// What the auxiliary vector tells a program about itself. Everything else in it is what the
+// kernel told elk.
+pub struct Program<'a> {
+    pub entry: u64,
+    // Runtime address, entry size and number of its program headers
+    pub phdr: u64,
+    pub phent: usize,
+    pub phnum: usize,
+    pub execfn: &'a str,
+}
+
+// KEY=VALUE strings for a program's envp: elk's own environment unless `clear`, with `set`
+// added, replacing variables of the same name
+pub fn environment(clear: bool, set: &[(String, String)]) -> Vec<Vec<u8>> {
+    let mut vars: Vec<(Vec<u8>, Vec<u8>)> = match clear {
+        true => Vec::new(),
+        false => env::vars_os()
+            .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()))
+            .collect(),
+    };
+    for (key, value) in set {
+        vars.retain(|(k, _)| k != key.as_bytes());
+        vars.push((key.as_bytes().to_vec(), value.as_bytes().to_vec()));
+    }
+    vars.into_iter()
+        .map(|(key, value)| [key, b"=".to_vec(), value].concat())
+        .collect()
+}
+
+// A stack as `Stack::frame` left it: the stack pointer to start the program with, and what
+// each part of the stack holds, from the guard page up
+pub struct Frame {
+    pub sp: u64,
+    pub regions: Vec<(Range<u64>, &'static str)>,
+}
+
+// A stack for the loaded program, separate from elk's own, with an inaccessible guard page below
+// it so overflows fault right away instead of running into whatever is mapped there
+pub struct Stack {
+    space: Arc<dyn AddressSpace>,
+    // Start of the guard page
+    map: u64,
+    len: usize,
+}
+
+impl Stack {
+    // `size` is rounded up to whole pages. With `poison`, every byte of the stack starts out as
+    // that value instead of zero, so reads of uninitialized locals give the same garbage every run.
+    pub fn new(size: usize, poison: Option<u8>) -> io::Result<Self> {
+        Self::new_in(Arc::new(Local), size, poison)
+    }
+
+    // A stack in `space`, for a program loaded somewhere other than elk's own address space
+    pub fn new_in(
+        space: Arc<dyn AddressSpace>,
+        size: usize,
+        poison: Option<u8>,
+    ) -> io::Result<Self> {
+        let size = size.div_ceil(PAGE_SIZE).max(1) * PAGE_SIZE;
+        let len = size + PAGE_SIZE;
+        let map = space.map(None, len as u64)?;
+        let stack = Self { space, map, len };
+        stack
+            .space
+            .protect(map, PAGE_SIZE as u64, Protection::NONE)?;
+        if let Some(byte) = poison {
+            stack.space.write(stack.bottom(), &vec![byte; size])?;
+        }
+        Ok(stack)
+    }
+
+    // For programs whose PT_GNU_STACK asks for it, as the kernel would map their stack
+    pub fn make_executable(&self) -> io::Result<()> {
+        self.space.protect(
+            self.bottom(),
+            self.top() - self.bottom(),
+            Protection::READ_WRITE_EXECUTE,
+        )
+    }
+
+    pub fn guard(&self) -> u64 {
+        self.map
+    }
+
+    pub fn bottom(&self) -> u64 {
+        self.guard() + PAGE_SIZE as u64
+    }
+
+    pub fn top(&self) -> u64 {
+        self.map + self.len as u64
+    }
+
+    // Lays out argc, argv, envp and the auxiliary vector the way the kernel does at the top of the
+    // stack, below the strings they point to. The stack stays mapped for good: the program never
+    // hands it back.
+    pub fn frame(self, program: &Program, args: &[Vec<u8>], env: &[Vec<u8>]) -> io::Result<Frame> {
+        let strings: usize = [program.execfn.as_bytes(), PLATFORM, &[0; 16]]
+            .iter()
+            .copied()
+            .chain(args.iter().chain(env).map(Vec::as_slice))
+            .map(|s| s.len() + 1)
+            .sum();
+        let words = 1 + args.len() + 1 + env.len() + 1 + 2 * AUX_ENTRIES;
+        // Plus the alignment of the stack pointer
+        let needed = strings + words * 8 + 15;
+        if needed as u64 > self.top() - self.bottom() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "arguments and environment need {:#x} bytes of stack",
+                    needed
+                ),
+            ));
+        }
+
+        let mut random = [0u8; 16];
+        if unsafe { libc::getrandom(random.as_mut_ptr() as *mut c_void, random.len(), 0) } < 16 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut sp = self.top() as usize;
+        // The strings, built top down and written in one go once they're all there
+        let mut strings = Vec::with_capacity(strings);
+        // Puts `bytes` and a NUL below everything pushed so far
+        let mut push = |bytes: &[u8]| {
+            sp -= bytes.len() + 1;
+            strings.splice(0..0, bytes.iter().copied().chain([0]));
+            sp as u64
+        };
+        let execfn = push(program.execfn.as_bytes());
+        let platform = push(PLATFORM);
+        let random = push(&random);
+        let argv: Vec<u64> = args.iter().map(|arg| push(arg)).collect();
+        let envp: Vec<u64> = env.iter().map(|var| push(var)).collect();
+        self.space.write(sp as u64, &strings)?;
+        let strings = sp as u64..self.top();
+
+        let inherited = |key| unsafe { libc::getauxval(key) };
+        let mut aux = vec![
+            (AT_PHDR, program.phdr),
+            (AT_PHENT, program.phent as u64),
+            (AT_PHNUM, program.phnum as u64),
+            (AT_PAGESZ, PAGE_SIZE as u64),
+            // No interpreter: elk did its job
+            (AT_BASE, 0),
+            (AT_FLAGS, 0),
+            (AT_ENTRY, program.entry),
+            (AT_UID, inherited(AT_UID)),
+            (AT_EUID, inherited(AT_EUID)),
+            (AT_GID, inherited(AT_GID)),
+            (AT_EGID, inherited(AT_EGID)),
+            (AT_SECURE, inherited(AT_SECURE)),
+            (AT_RANDOM, random),
+            (AT_PLATFORM, platform),
+            (AT_EXECFN, execfn),
+        ];
+        // The vDSO and CPU features are the same for the program as for elk; kernels too old to
+        // know an entry leave it 0
+        for key in [
+            AT_HWCAP,
+            AT_HWCAP2,
+            AT_CLKTCK,
+            AT_SYSINFO_EHDR,
+            AT_MINSIGSTKSZ,
+        ] {
+            match inherited(key) {
+                0 => {}
+                value => aux.push((key, value)),
+            }
+        }
+
+        let mut words = vec![args.len() as u64];
+        words.extend(argv);
+        words.push(0);
+        words.extend(envp);
+        words.push(0);
+        let auxv = words.len();
+        for (key, value) in aux.into_iter().chain([(AT_NULL, 0)]) {
+            words.extend([key, value]);
+        }
+        // The ABI wants the stack pointer 16-byte aligned on entry, pointing at argc
+        sp = (sp - words.len() * 8) & !15;
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        self.space.write(sp as u64, &bytes)?;
+        let sp = sp as u64;
+        let word = |i: usize| sp + 8 * i as u64;
+        let envp = 1 + args.len() + 1;
+        let regions = vec![
+            (self.guard()..self.bottom(), "stack guard page"),
+            (self.bottom()..sp, "stack"),
+            (word(0)..word(1), "argc"),
+            (word(1)..word(envp), "argv"),
+            (word(envp)..word(auxv), "envp"),
+            (word(auxv)..word(words.len()), "auxiliary vector"),
+            (word(words.len())..strings.start, "stack alignment"),
+            (
+                strings,
+                "argument, environment and auxiliary vector strings",
+            ),
+        ];
+        std::mem::forget(self);
+        Ok(Frame { sp, regions })
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        let _ = self.space.unmap(self.map, self.len as u64);
+    }
+}
+
+// Jumps to `addr` on the stack at `sp`, for good. rdx holds a function for the program to run
+// at exit, or 0 for none. Whatever is at `addr` takes over the process, so the caller vouches for
+// it being a loaded program's entry point and `sp` a stack `Stack::frame` laid out.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn jmp_on_stack(addr: u64, sp: u64, fini: u64) -> ! {
+    std::arch::asm!(
+        "mov rsp, {sp}",
+        "xor ebp, ebp",
+        "jmp {addr}",
+        sp = in(reg) sp,
+        addr = in(reg) addr,
+        in("rdx") fini,
+        options(noreturn)
+    );
+}
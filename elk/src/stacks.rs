@@ -0,0 +1,161 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::File,
+    io::{stdin, Read},
+    path::PathBuf,
+};
+
+use crate::symbolize::{parse_line, Symbolizer};
+
+// Collapses samples into the `frame;frame;frame count` lines flamegraph tools read
+struct Folder {
+    symbolizer: Symbolizer,
+    with_object: bool,
+    stacks: BTreeMap<String, u64>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Fold perf script output or folded stacks into symbolized flamegraph input")]
+pub struct Args {
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Read samples from FILE instead of stdin"
+    )]
+    input: Option<String>,
+    #[arg(
+        long = "debug-dir",
+        value_name = "DIR",
+        value_hint = clap::ValueHint::DirPath,
+        help = "Extra directory to look for separate debuginfo in"
+    )]
+    debug_dirs: Vec<PathBuf>,
+    #[arg(long, help = "Prefix frames with the object they are in")]
+    with_object: bool,
+    #[arg(
+        value_hint = clap::ValueHint::FilePath,
+        help = "Objects to resolve addresses against when a frame doesn't name one"
+    )]
+    objects: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut input: Box<dyn Read> = match &args.input {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(stdin()),
+    };
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    let mut folder = Folder {
+        symbolizer: Symbolizer::new(args.objects, args.debug_dirs),
+        with_object: args.with_object,
+        stacks: BTreeMap::new(),
+    };
+    // perf script indents the frames under each sample header; folded input has no indentation
+    if text
+        .lines()
+        .any(|line| line.starts_with(char::is_whitespace) && !line.trim().is_empty())
+    {
+        folder.perf_script(&text);
+    } else {
+        folder.folded(&text)?;
+    }
+    for (stack, count) in &folder.stacks {
+        println!("{} {}", stack, count);
+    }
+    Ok(())
+}
+
+impl Folder {
+    fn perf_script(&mut self, text: &str) {
+        let mut comm = None;
+        let mut frames = Vec::new();
+        for line in text.lines() {
+            if line.trim().is_empty() {
+                self.add_sample(comm.take(), &mut frames);
+            } else if line.starts_with(char::is_whitespace) {
+                frames.push(self.frame(line.trim(), perf_symbol(line.trim())));
+            } else {
+                self.add_sample(comm.take(), &mut frames);
+                comm = Some(sample_comm(line));
+            }
+        }
+        self.add_sample(comm, &mut frames);
+    }
+
+    // perf lists the innermost frame first, folded stacks start at the root
+    fn add_sample(&mut self, comm: Option<String>, frames: &mut Vec<String>) {
+        let comm = match comm {
+            Some(comm) => comm,
+            None => return frames.clear(),
+        };
+        let stack: Vec<_> = std::iter::once(comm)
+            .chain(frames.drain(..).rev())
+            .collect();
+        *self.stacks.entry(stack.join(";")).or_default() += 1;
+    }
+
+    fn folded(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (stack, count) = line
+                .rsplit_once(' ')
+                .and_then(|(stack, count)| Some((stack, count.parse::<u64>().ok()?)))
+                .ok_or_else(|| format!("not a folded stack: {}", line))?;
+            let frames: Vec<_> = stack
+                .split(';')
+                .map(|frame| self.frame(frame, frame.to_string()))
+                .collect();
+            *self.stacks.entry(frames.join(";")).or_default() += count;
+        }
+        Ok(())
+    }
+
+    // Name for one frame, keeping `fallback` when delf can't resolve it
+    fn frame(&mut self, frame: &str, fallback: String) -> String {
+        let location = parse_line(frame)
+            .and_then(|(object, target)| self.symbolizer.locate(object.as_deref(), target));
+        let name = match location {
+            Some(location) => match (location.symbol, self.with_object) {
+                (Some((name, _)), true) => format!("{}!{}", location.object, name),
+                (Some((name, _)), false) => name.to_string(),
+                (None, _) => format!("{}!{:#x}", location.object, location.addr),
+            },
+            None => fallback,
+        };
+        // Semicolons separate frames in the output
+        name.replace(';', ":")
+    }
+}
+
+// `prog 1234/1234 5.678: 250000 cycles:u:` -> `prog`; flamegraph tools want no spaces
+fn sample_comm(header: &str) -> String {
+    let is_pid = |token: &&str| {
+        token
+            .split('/')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+    };
+    let tokens: Vec<_> = header.split_whitespace().collect();
+    let end = tokens.iter().position(is_pid).unwrap_or(1).max(1);
+    tokens[..end.min(tokens.len())].join("_")
+}
+
+// Symbol perf itself printed for `ip symbol+0xoff (dso)`, `[unknown]` if there is none
+fn perf_symbol(frame: &str) -> String {
+    let without_dso = match frame.rfind(" (") {
+        Some(end) if frame.ends_with(')') => &frame[..end],
+        _ => frame,
+    };
+    let symbol = without_dso
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, rest)| rest.trim());
+    let symbol = match symbol.rsplit_once("+0x") {
+        Some((symbol, _)) => symbol,
+        None => symbol,
+    };
+    match symbol {
+        "" => "[unknown]".into(),
+        symbol => symbol.to_string(),
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fmt,
+    fs::File,
+    io::{stdin, stdout, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use delf::{index::AddressIndex, strtab::Interner, types::*, FileHeader};
+
+use crate::{exit::Failure, size::demangle, source};
+
+// Where distributions install separate debuginfo, and perf's build-id cache (relative to $HOME)
+const DEBUG_DIR: &str = "/usr/lib/debug";
+const PERF_CACHE: &str = ".debug";
+
+#[cfg(feature = "dwarf")]
+type Lines = addr2line::Context<gimli::EndianRcSlice<gimli::LittleEndian>>;
+
+pub struct Location {
+    pub object: Arc<str>,
+    pub addr: u64,
+    pub symbol: Option<(Arc<str>, u64)>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+// Everything needed to turn addresses of one object into names, built once and kept around
+pub struct SymbolIndex {
+    name: Arc<str>,
+    // Function and data symbols, demangled
+    symbols: AddressIndex<Arc<str>>,
+    by_name: HashMap<Arc<str>, u64>,
+    loads: AddressIndex<()>,
+    #[cfg(feature = "dwarf")]
+    lines: Option<Lines>,
+}
+
+// Resolves lines of addresses, caching one SymbolIndex per object
+pub struct Symbolizer {
+    defaults: Vec<String>,
+    debug_dirs: Vec<PathBuf>,
+    indexes: HashMap<String, Option<SymbolIndex>>,
+    // Shared by every index, so names that several objects define are stored once
+    names: Interner,
+}
+
+pub enum Target {
+    Addr(u64),
+    // `symbol+offset`, as printed by backtraces and perf
+    Symbol(String, u64),
+}
+
+#[derive(clap::Args, Debug)]
+#[command(about = "Resolve addresses in backtraces and logs to symbol+offset and file:line")]
+pub struct Args {
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        help = "Read lines from FILE instead of stdin"
+    )]
+    input: Option<String>,
+    #[arg(
+        long = "debug-dir",
+        value_name = "DIR",
+        value_hint = clap::ValueHint::DirPath,
+        help = "Extra directory to look for separate debuginfo in"
+    )]
+    debug_dirs: Vec<PathBuf>,
+    #[arg(
+        value_hint = clap::ValueHint::FilePath,
+        help = "Objects to resolve addresses against when a line doesn't name one"
+    )]
+    objects: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let input: Box<dyn BufRead> = match &args.input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(BufReader::new(stdin())),
+    };
+    let mut symbolizer = Symbolizer::new(args.objects, args.debug_dirs);
+    let out = stdout();
+    let mut out = out.lock();
+    for line in input.lines() {
+        writeln!(out, "{}", symbolizer.symbolize_line(&line?))?;
+        // Flush per line so elk can sit behind a pipe as a long-running coprocess
+        out.flush()?;
+    }
+    Ok(())
+}
+
+impl SymbolIndex {
+    // Indexes `path`, or its separate debuginfo from `debug_dirs` when the file itself is
+    // stripped of symbols or line tables
+    pub fn open(
+        path: &str,
+        debug_dirs: &[PathBuf],
+        names: &mut Interner,
+    ) -> Result<Self, Box<dyn Error>> {
+        let input = source::read(path)?;
+        let file = FileHeader::parse_or_describe(&input)
+            .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+        let name = Path::new(path)
+            .file_name()
+            .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned());
+
+        let stripped = file.section_by_name(".symtab").is_none()
+            || file.section_by_name(".debug_line").is_none();
+        if let Some(debug) = file
+            .build_id()
+            .filter(|_| stripped)
+            .and_then(|id| find_debuginfo(&id, debug_dirs))
+        {
+            let input = source::map_raw(&debug)?;
+            if let Ok(debug) = FileHeader::parse_or_describe(&input) {
+                return Ok(Self::build(&name, &debug, names));
+            }
+        }
+        Ok(Self::build(&name, &file, names))
+    }
+
+    // Names come from `names`, and anything new is added to it
+    pub fn build(name: &str, file: &FileHeader, names: &mut Interner) -> Self {
+        let mut symbols: Vec<_> = file
+            .read_section_syms()
+            .into_iter()
+            .filter(|sym| sym.section_index().is_some() && !sym.name.is_empty())
+            .filter(|sym| matches!(sym.typ(), Some(SymType::Func | SymType::Object)))
+            .collect();
+        // Functions win over data at the same address, then the first name wins
+        symbols.sort_by_key(|sym| (sym.value, sym.typ() != Some(SymType::Func)));
+        symbols.dedup_by_key(|sym| sym.value);
+        let by_name = symbols
+            .iter()
+            .map(|sym| (names.intern(&sym.name), sym.value.0))
+            .collect();
+        let symbols = symbols
+            .into_iter()
+            .map(|sym| {
+                let range = sym.value.0..sym.value.0 + sym.size;
+                (range, names.intern(&demangle(&sym.name)))
+            })
+            .collect();
+        let loads = file
+            .program_headers
+            .iter()
+            .filter(|ph| ph.typ == SegmentType::Load)
+            .map(|ph| (ph.virt_addr.0..ph.virt_addr.0 + ph.mem_size.0, ()))
+            .collect();
+
+        Self {
+            name: names.intern(name),
+            symbols,
+            by_name,
+            loads,
+            #[cfg(feature = "dwarf")]
+            lines: load_lines(file),
+        }
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        self.loads.contains(addr)
+    }
+
+    // Symbol covering `addr` and the offset into it. Zero-sized symbols only match exactly.
+    pub fn symbol(&self, addr: u64) -> Option<(&str, u64)> {
+        self.covering(addr).map(|(name, offset)| (&**name, offset))
+    }
+
+    fn covering(&self, addr: u64) -> Option<(&Arc<str>, u64)> {
+        let (range, name) = self.symbols.get(addr)?;
+        Some((name, addr - range.start))
+    }
+
+    pub fn symbol_addr(&self, name: &str) -> Option<u64> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn locate(&self, addr: u64) -> Location {
+        let (file, line) = self.source_line(addr);
+        Location {
+            object: self.name.clone(),
+            addr,
+            symbol: self.covering(addr).map(|(name, off)| (name.clone(), off)),
+            file,
+            line,
+        }
+    }
+
+    #[cfg(feature = "dwarf")]
+    fn source_line(&self, addr: u64) -> (Option<String>, Option<u32>) {
+        match self
+            .lines
+            .as_ref()
+            .and_then(|l| l.find_location(addr).ok().flatten())
+        {
+            Some(loc) => (loc.file.map(String::from), loc.line),
+            None => (None, None),
+        }
+    }
+
+    #[cfg(not(feature = "dwarf"))]
+    fn source_line(&self, _: u64) -> (Option<String>, Option<u32>) {
+        (None, None)
+    }
+}
+
+// Debuginfo packages use `.build-id/ab/cdef….debug`, perf's cache `.build-id/ab/cdef…/{debug,elf}`
+fn find_debuginfo(build_id: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    if build_id.len() < 3 {
+        return None;
+    }
+    let (head, rest) = build_id.split_at(2);
+    dirs.iter()
+        .flat_map(|dir| {
+            let base = dir.join(".build-id").join(head);
+            vec![
+                base.join(format!("{}.debug", rest)),
+                base.join(rest).join("debug"),
+                base.join(rest).join("elf"),
+            ]
+        })
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(feature = "dwarf")]
+fn load_lines(file: &FileHeader) -> Option<Lines> {
+    use std::rc::Rc;
+
+    file.section_by_name(".debug_line")?;
+    let dwarf = gimli::Dwarf::load(|id| -> Result<_, gimli::Error> {
+        let data = file
+            .section_by_name(id.name())
+            .and_then(|sh| source::section_data(sh).ok())
+            .map(|data| data.into_owned())
+            .unwrap_or_default();
+        Ok(gimli::EndianRcSlice::new(
+            Rc::from(data),
+            gimli::LittleEndian,
+        ))
+    })
+    .ok()?;
+    addr2line::Context::from_dwarf(dwarf).ok()
+}
+
+impl Symbolizer {
+    // `defaults` are searched for bare addresses that don't name their object, `debug_dirs` for
+    // separate debuginfo before the system locations
+    pub fn new(defaults: Vec<String>, mut debug_dirs: Vec<PathBuf>) -> Self {
+        debug_dirs.push(PathBuf::from(DEBUG_DIR));
+        debug_dirs.extend(env::var_os("HOME").map(|home| Path::new(&home).join(PERF_CACHE)));
+        Self {
+            defaults,
+            debug_dirs,
+            indexes: HashMap::new(),
+            names: Interner::new(),
+        }
+    }
+
+    // `path` if it still exists, else debuginfo found by build ID, for objects recorded on
+    // another machine
+    pub fn object_path(&self, path: &str, build_id: Option<&str>) -> String {
+        match build_id {
+            Some(id) if !Path::new(path).exists() => find_debuginfo(id, &self.debug_dirs)
+                .map_or_else(|| path.to_string(), |p| p.display().to_string()),
+            _ => path.to_string(),
+        }
+    }
+
+    fn index(&mut self, path: &str) -> Option<&SymbolIndex> {
+        let (debug_dirs, names) = (&self.debug_dirs, &mut self.names);
+        self.indexes
+            .entry(path.to_string())
+            .or_insert_with(|| SymbolIndex::open(path, debug_dirs, names).ok())
+            .as_ref()
+    }
+
+    pub fn locate(&mut self, object: Option<&str>, target: Target) -> Option<Location> {
+        let path = match object {
+            Some(path) => path.to_string(),
+            None => {
+                let addr = match target {
+                    Target::Addr(addr) => addr,
+                    Target::Symbol(..) => return None,
+                };
+                let defaults = self.defaults.clone();
+                defaults
+                    .into_iter()
+                    .find(|p| self.index(p).is_some_and(|i| i.contains(addr)))?
+            }
+        };
+        let index = self.index(&path)?;
+        let addr = match target {
+            Target::Addr(addr) => addr,
+            Target::Symbol(name, offset) => index.symbol_addr(&name)? + offset,
+        };
+        Some(index.locate(addr))
+    }
+
+    // The symbolized location, or the line unchanged if it holds no address elk understands
+    pub fn symbolize_line(&mut self, line: &str) -> String {
+        let (object, target) = match parse_line(line) {
+            Some(parsed) => parsed,
+            None => return line.to_string(),
+        };
+        match self.locate(object.as_deref(), target) {
+            Some(location) => location.to_string(),
+            None => line.to_string(),
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+// `sym+0x1d`, `+0x1d` or `path+0x1d`
+fn split_offset(s: &str) -> Option<(&str, u64)> {
+    let (head, offset) = s.rsplit_once('+')?;
+    Some((head, parse_hex(offset)?))
+}
+
+// Understands glibc backtrace_symbols (`/lib/libc.so.6(+0x29d90) [0x7f..]`,
+// `./prog(main+0x1d) [0x..]`), sanitizer frames (`... (/path/prog+0x4f1d)`), perf script
+// (`55d0c4a1b139 main+0x10 (/tmp/prog)`) and bare addresses
+pub fn parse_line(line: &str) -> Option<(Option<String>, Target)> {
+    let trimmed = line.trim();
+    if let (Some(open), Some(close)) = (trimmed.rfind('('), trimmed.rfind(')')) {
+        if open < close {
+            let inner = &trimmed[open + 1..close];
+            let before = trimmed[..open].trim_end();
+            // glibc: the object is glued to the parenthesis
+            if !trimmed[..open].ends_with(char::is_whitespace) && !before.is_empty() {
+                let object = before.rsplit(char::is_whitespace).next()?.to_string();
+                let (sym, offset) = split_offset(inner)?;
+                return Some(match sym {
+                    "" => (Some(object), Target::Addr(offset)),
+                    sym => (Some(object), Target::Symbol(sym.to_string(), offset)),
+                });
+            }
+            // sanitizers: `(object+offset)`
+            if let Some((object, offset)) = split_offset(inner) {
+                return Some((Some(object.to_string()), Target::Addr(offset)));
+            }
+            // perf script: `ip symbol+offset (dso)`
+            let (sym, offset) = split_offset(before.rsplit(char::is_whitespace).next()?)?;
+            if sym.starts_with('[') {
+                return None;
+            }
+            return Some((
+                Some(inner.to_string()),
+                Target::Symbol(sym.to_string(), offset),
+            ));
+        }
+    }
+    let token = trimmed.rsplit(char::is_whitespace).next()?;
+    let addr = match token.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None if token.chars().all(|c| c.is_ascii_hexdigit()) => {
+            u64::from_str_radix(token, 16).ok()?
+        }
+        None => return None,
+    };
+    Some((None, Target::Addr(addr)))
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.symbol {
+            Some((name, 0)) => write!(f, "{}!{}", self.object, name)?,
+            Some((name, offset)) => write!(f, "{}!{}+{:#x}", self.object, name, offset)?,
+            None => write!(f, "{}!{:#x}", self.object, self.addr)?,
+        }
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, " ({}:{})", file, line),
+            (Some(file), None) => write!(f, " ({})", file),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,81 +1,398 @@
+use std::{borrow::Cow, error::Error, fs, path::PathBuf, sync::Mutex};
+
+use delf::style;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{exit::Failure, schema};
+
+#[derive(Clone, Serialize, JsonSchema)]
 pub struct Table {
     pub header: String,
     pub labels: Vec<String>,
     pub rows: Vec<Vec<String>>,
 }
 
-struct Struct {}
+// Formats `--output` writes, picked by the file's extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Export {
+    Html,
+    Markdown,
+    Csv,
+    Json,
+}
+
+// Tables collected for `--output`. The file is rewritten after every table, since `elk run`
+// never gets back from the program it jumps into.
+struct Document {
+    path: PathBuf,
+    format: Export,
+    tables: Vec<Table>,
+}
+
+static DOCUMENT: Mutex<Option<Document>> = Mutex::new(None);
+
+// How tables are drawn on the terminal. Documents for `--output` always get every row in full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Layout {
+    // Plain ASCII borders instead of box drawing characters
+    pub ascii: bool,
+    // Cells wider than this many columns are cut short with an ellipsis
+    pub max_width: Option<usize>,
+    // Rows past this many are left out, with a footer counting them
+    pub max_rows: Option<usize>,
+}
+
+static LAYOUT: Mutex<Layout> = Mutex::new(Layout {
+    ascii: false,
+    max_width: None,
+    max_rows: None,
+});
+
+// The characters a table is drawn with
+struct Borders {
+    // Fill and junction of the lines above the title, below the title, below the labels and
+    // below the rows
+    lines: [(char, char); 4],
+    // The left and right ends of those lines
+    ends: [(char, char); 4],
+    outer: char,
+    inner: char,
+    ellipsis: &'static str,
+}
+
+const BOX: Borders = Borders {
+    lines: [('━', '━'), ('━', '┯'), ('─', '┼'), ('━', '┻')],
+    ends: [('┏', '┓'), ('┣', '┫'), ('┠', '┨'), ('┗', '┛')],
+    outer: '┃',
+    inner: '│',
+    ellipsis: "…",
+};
+
+const ASCII: Borders = Borders {
+    lines: [('-', '-'), ('-', '+'), ('-', '+'), ('-', '+')],
+    ends: [('+', '+'); 4],
+    outer: '|',
+    inner: '|',
+    ellipsis: "...",
+};
+
+// Applies to every table printed from now on
+pub fn set_layout(layout: Layout) {
+    *LAYOUT.lock().unwrap() = layout;
+}
+
+impl Export {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "html" | "htm" => Some(Self::Html),
+            "md" | "markdown" => Some(Self::Markdown),
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+// Sends every table printed from now on to `path` instead of stdout, in the format its
+// extension names
+pub fn export_to(path: &str) -> Result<(), Box<dyn Error>> {
+    let format = Export::from_path(path).ok_or_else(|| {
+        Failure::parse(format!(
+            "can't tell the format of {:?}, expected .html, .md, .csv or .json",
+            path
+        ))
+    })?;
+    let document = Document {
+        path: path.into(),
+        format,
+        tables: Vec::new(),
+    };
+    document
+        .write()
+        .map_err(|e| format!("Could not write {}: {}", path, e))?;
+    *DOCUMENT.lock().unwrap() = Some(document);
+    Ok(())
+}
+
+impl Document {
+    fn write(&self) -> std::io::Result<()> {
+        // Colors and hyperlinks are for terminals
+        let tables: Vec<Table> = self.tables.iter().map(Table::plain).collect();
+        let content = match self.format {
+            Export::Html => html(&tables),
+            Export::Markdown => tables.iter().map(markdown).collect::<Vec<_>>().join("\n"),
+            Export::Csv => tables.iter().map(csv).collect::<Vec<_>>().join("\n"),
+            Export::Json => {
+                serde_json::to_string_pretty(&schema::envelope("tables", tables))? + "\n"
+            }
+        };
+        fs::write(&self.path, content)
+    }
+}
 
 impl Table {
+    // Prints the table, or adds it to the `--output` document when there is one
+    pub fn print(&self) {
+        let mut document = DOCUMENT.lock().unwrap();
+        match document.as_mut() {
+            Some(document) => {
+                document.tables.push(self.clone());
+                if let Err(e) = document.write() {
+                    eprintln!("Could not write {}: {}", document.path.display(), e);
+                }
+            }
+            None => println!("{}", self.build()),
+        }
+    }
+
+    fn plain(&self) -> Self {
+        Self {
+            header: style::strip(&self.header),
+            labels: self.labels.iter().map(|l| style::strip(l)).collect(),
+            rows: self
+                .rows
+                .iter()
+                .map(|row| row.iter().map(|v| style::strip(v)).collect())
+                .collect(),
+        }
+    }
+
     pub fn build(&self) -> String {
+        self.build_with(*LAYOUT.lock().unwrap())
+    }
+
+    pub fn build_with(&self, layout: Layout) -> String {
+        let theme = style::theme();
+        let borders = if layout.ascii { &ASCII } else { &BOX };
+
+        let shown = layout
+            .max_rows
+            .map_or(self.rows.len(), |max| max.min(self.rows.len()));
+        let fit = |cell| truncate(cell, layout.max_width, borders.ellipsis);
+        let labels: Vec<Cow<str>> = self.labels.iter().map(|l| fit(l)).collect();
+        let rows: Vec<Vec<Cow<str>>> = self.rows[..shown]
+            .iter()
+            .map(|r| r.iter().map(|v| fit(v)).collect())
+            .collect();
+
+        // Visible width of every cell, measured once for sizing the columns and centering
+        let widths: Vec<Vec<usize>> = rows
+            .iter()
+            .map(|r| r.iter().map(|v| style::visible_width(v)).collect())
+            .collect();
+
         //Get the minimum width for each column
-        let col_widths: Vec<usize> = self
-            .labels
+        let col_widths: Vec<usize> = labels
             .iter()
             .enumerate()
             .map(|(i, l)| {
-                l.len()
-                    .max(self.rows.iter().max_by_key(|r| r[i].len()).unwrap()[i].len())
+                widths
+                    .iter()
+                    .map(|w| w[i])
+                    .fold(l.chars().count(), usize::max)
                     + 4
             })
             .collect();
+        let total = col_widths.iter().sum::<usize>() + labels.len() - 1;
 
         // Build the header/title row
-        let header = format!(
-            "[1;34m{:^1$}[0m",
-            self.header,
-            col_widths.iter().sum::<usize>() + self.labels.len() - 1
-        );
+        let header = theme.title.paint(format!("{:^1$}", self.header, total));
 
         // Build the top and bottom row, as well the separator rows around title and labels
-        let [top, head_sep, label_sep, bot] = [('━', '━'), ('━', '┯'), ('─', '┼'), ('━', '┻')]
+        let [top, head_sep, label_sep, bot] = borders
+            .lines
             .map(|(fc, jc)| make_separator(fc, jc, &col_widths));
 
         // Build the row with labels
-        let label_row = self
-            .labels
+        let label_row = labels
             .iter()
             .zip(&col_widths)
-            .map(|(l, w)| format!("[1;35m{:^1$}[0m", l, w))
+            .map(|(l, w)| theme.label.paint(format!("{:^1$}", l, w)))
             .collect::<Vec<String>>()
-            .join("│");
-
-        // Build the actual table rows. One text row per table row.
-        let rows = self
-            .rows
-            .to_owned()
-            .into_iter()
-            .map(|r| {
-                r.iter()
-                    .zip(col_widths.iter())
-                    .map(|(v, w)| format!("{:^1$}", v, w))
-                    .collect::<Vec<String>>()
-                    .join("│")
-            })
-            .map(|r| format!("┃{}┃", r))
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        //put it all together
-        format!(
-            "
-┏{}┓
-┃{}┃
-┣{}┫
-┃{}┃
-┠{}┨
-{}
-┗{}┛
-",
-            top, header, head_sep, label_row, label_sep, rows, bot
-        )
+            .join(&borders.inner.to_string());
+
+        // Separators are 3 bytes a character, cells mostly ASCII
+        let line = top.len() + 2 * 3;
+        let mut out = String::with_capacity((rows.len() + 8) * line);
+        out.push('\n');
+        let [top_ends, head_ends, label_ends, bot_ends] = borders.ends;
+        for ((left, right), middle) in [
+            (top_ends, &top),
+            ((borders.outer, borders.outer), &header),
+            (head_ends, &head_sep),
+            ((borders.outer, borders.outer), &label_row),
+            (label_ends, &label_sep),
+        ] {
+            out.push(left);
+            out.push_str(middle);
+            out.push(right);
+            out.push('\n');
+        }
+
+        // The actual table rows. One text row per table row.
+        for (row, widths) in rows.iter().zip(&widths) {
+            out.push(borders.outer);
+            for (i, ((v, w), col)) in row.iter().zip(widths).zip(&col_widths).enumerate() {
+                if i > 0 {
+                    out.push(borders.inner);
+                }
+                center(&mut out, v, *w, *col);
+            }
+            out.push(borders.outer);
+            out.push('\n');
+        }
+        if shown < self.rows.len() {
+            let footer = format!("{} {} more", borders.ellipsis, self.rows.len() - shown);
+            out.push(borders.outer);
+            let width = footer.chars().count();
+            center(&mut out, &theme.dim.paint(footer), width, total);
+            out.push(borders.outer);
+            out.push('\n');
+        } else if self.rows.is_empty() {
+            out.push('\n');
+        }
+        out.push(bot_ends.0);
+        out.push_str(&bot);
+        out.push(bot_ends.1);
+        out.push('\n');
+        out
     }
 }
 
-fn make_separator(fillchar: char, joinchar: char, col_spans: &Vec<usize>) -> String {
+// `cell` cut down to `max` columns, ending in `ellipsis`. What is left loses its colors, as the
+// cut could fall inside an escape sequence's span.
+fn truncate<'a>(cell: &'a str, max: Option<usize>, ellipsis: &str) -> Cow<'a, str> {
+    let max = match max {
+        Some(max) if style::visible_width(cell) > max => max,
+        _ => return Cow::Borrowed(cell),
+    };
+    let keep = max.saturating_sub(ellipsis.chars().count());
+    let cut = style::strip(cell)
+        .chars()
+        .take(keep)
+        .chain(ellipsis.chars())
+        .take(max)
+        .collect();
+    Cow::Owned(cut)
+}
+
+// Appends `content`, `visible` columns wide, centered in `width` columns like `{:^w$}` would
+// if it weren't for escape sequences
+fn center(out: &mut String, content: &str, visible: usize, width: usize) {
+    let padding = width.saturating_sub(visible);
+    out.extend(std::iter::repeat_n(' ', padding / 2));
+    out.push_str(content);
+    out.extend(std::iter::repeat_n(' ', padding - padding / 2));
+}
+
+fn make_separator(fillchar: char, joinchar: char, col_spans: &[usize]) -> String {
     col_spans
         .iter()
         .map(|w| fillchar.to_string().repeat(*w))
         .collect::<Vec<String>>()
         .join(&joinchar.to_string())
 }
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html(tables: &[Table]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>elk report</title>\n\
+         <style>table { border-collapse: collapse; margin-bottom: 2em; } \
+         th, td { border: 1px solid #999; padding: 2px 8px; font-family: monospace; }</style>\n\
+         </head>\n<body>\n",
+    );
+    for table in tables {
+        out += &format!("<h2>{}</h2>\n<table>\n<tr>", escape_html(&table.header));
+        for label in &table.labels {
+            out += &format!("<th>{}</th>", escape_html(label));
+        }
+        out += "</tr>\n";
+        for row in &table.rows {
+            out += "<tr>";
+            for value in row {
+                out += &format!("<td>{}</td>", escape_html(value));
+            }
+            out += "</tr>\n";
+        }
+        out += "</table>\n";
+    }
+    out + "</body>\n</html>\n"
+}
+
+fn markdown(table: &Table) -> String {
+    let line = |cells: &[String]| {
+        let cells: Vec<_> = cells.iter().map(|c| c.replace('|', "\\|")).collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut out = format!("## {}\n\n", table.header);
+    out += &line(&table.labels);
+    out += &format!("|{}\n", " --- |".repeat(table.labels.len()));
+    for row in &table.rows {
+        out += &line(row);
+    }
+    out
+}
+
+// One block per table: its title on a line of its own, then the labels and rows
+fn csv(table: &Table) -> String {
+    let field = |s: &String| match s.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.clone(),
+    };
+    let line = |cells: &[String]| cells.iter().map(field).collect::<Vec<_>>().join(",") + "\n";
+    let mut out = line(std::slice::from_ref(&table.header));
+    out += &line(&table.labels);
+    for row in &table.rows {
+        out += &line(row);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use delf::style;
+
+    use super::{Layout, Table};
+
+    fn table(rows: usize) -> Table {
+        Table {
+            header: "Symbols".into(),
+            labels: vec!["name".into(), "value".into()],
+            rows: (0..rows)
+                .map(|i| vec![format!("symbol_with_a_long_name_{}", i), i.to_string()])
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn ascii_truncated_and_limited() {
+        let layout = Layout {
+            ascii: true,
+            max_width: Some(10),
+            max_rows: Some(2),
+        };
+        let out = table(5).build_with(layout);
+        assert!(out.is_ascii());
+        assert!(out.contains("symbol_..."));
+        assert!(!out.contains("symbol_w"));
+        assert!(!out.contains("  2  "));
+        assert!(out.contains("... 3 more"));
+        let widths: Vec<_> = out.lines().skip(1).map(style::visible_width).collect();
+        assert!(widths.iter().all(|&w| w == widths[0]));
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let out = table(5).build_with(Layout::default());
+        assert!(out.contains("symbol_with_a_long_name_4"));
+        assert!(!out.contains("more"));
+    }
+}
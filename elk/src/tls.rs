@@ -0,0 +1,232 @@
+use std::{
+    io,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use delf::types::TlsTemplate;
+
+// Room above the thread pointer for the thread control block. Code built against glibc reads
+// the fields of its tcbhead_t there, the rest of struct pthread starts out zeroed.
+const TCB_SIZE: usize = 0x1000;
+// Offsets of the tcbhead_t fields elk fills in: the TCB's own address, the DTV, the thread
+// descriptor's address (the same in glibc) and the stack protector canary
+const TCB_SELF: usize = 0x0;
+const TCB_DTV: usize = 0x8;
+const TCB_THREAD: usize = 0x10;
+const TCB_STACK_GUARD: usize = 0x28;
+
+const ARCH_SET_FS: libc::c_int = 0x1002;
+const ARCH_GET_FS: libc::c_int = 0x1003;
+
+// elk's own thread pointer once the program has been given its own, 0 until then
+static HOST: AtomicU64 = AtomicU64::new(0);
+
+// Where an object's thread-locals live in the static TLS area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Module {
+    // Index in the DTV, counting from 1, what DTPMOD64 relocations resolve to
+    pub id: u64,
+    // Start of the object's block relative to the thread pointer
+    pub offset: i64,
+    pub template: TlsTemplate,
+}
+
+// Static TLS with x86-64's variant II layout: the thread pointer points at the TCB, blocks are
+// below it in the order their objects are given. The executable has to come first, its
+// local-exec accesses were resolved at link time assuming its block ends at the thread pointer.
+pub fn layout(templates: &[Option<TlsTemplate>]) -> Vec<Option<Module>> {
+    let mut end = 0u64;
+    let mut id = 0;
+    templates
+        .iter()
+        .map(|template| {
+            let template = (*template)?;
+            end = align_up(end + template.mem_size, template.align);
+            id += 1;
+            Some(Module {
+                id,
+                offset: -(end as i64),
+                template,
+            })
+        })
+        .collect()
+}
+
+// The main thread's DTV, TLS blocks and TCB, laid out for where the loader maps them in the
+// target, for as long as the program lives. The DTV has glibc's dtv_t layout, two words per
+// entry: the slot count, the generation, then each module's block.
+pub struct Area {
+    memory: Vec<u8>,
+    start: u64,
+    thread_pointer: u64,
+}
+
+impl Area {
+    // Bytes to map for the area of `modules`
+    pub fn size(modules: &[Module]) -> usize {
+        let (dtv_len, below, align) = Self::extent(modules);
+        dtv_len + below + align as usize + TCB_SIZE
+    }
+
+    fn extent(modules: &[Module]) -> (usize, usize, u64) {
+        let slots = modules.iter().map(|m| m.id).max().unwrap_or(0) as usize;
+        let below = modules.iter().map(|m| -m.offset as u64).max().unwrap_or(0) as usize;
+        let align = modules.iter().map(|m| m.template.align).max().unwrap_or(1);
+        (16 * (slots + 2), below, align)
+    }
+
+    // The area as it goes at `start`, with `size` bytes mapped there
+    pub fn new(modules: &[Module], start: u64) -> Self {
+        let (dtv_len, below, align) = Self::extent(modules);
+        let mut memory = vec![0u8; Self::size(modules)];
+        let thread_pointer = align_up(start + (dtv_len + below) as u64, align);
+
+        let slots = (dtv_len / 16 - 2) as u64;
+        let mut words = vec![(0, slots)];
+        for module in modules {
+            let block = (thread_pointer as i64 + module.offset) as u64;
+            words.push((16 * (module.id as usize + 1), block));
+        }
+        let mut guard = [0u8; 8];
+        unsafe { libc::getrandom(guard.as_mut_ptr() as *mut libc::c_void, guard.len(), 0) };
+        // The low byte stays zero, like glibc's, so string overflows can't reproduce it
+        guard[0] = 0;
+        let tcb = (thread_pointer - start) as usize;
+        words.extend([
+            (tcb + TCB_SELF, thread_pointer),
+            (tcb + TCB_DTV, start + 16),
+            (tcb + TCB_THREAD, thread_pointer),
+            (tcb + TCB_STACK_GUARD, u64::from_le_bytes(guard)),
+        ]);
+        for (offset, value) in words {
+            memory[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        Self {
+            memory,
+            start,
+            thread_pointer,
+        }
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    // What to write at `start`
+    pub fn bytes(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn thread_pointer(&self) -> u64 {
+        self.thread_pointer
+    }
+
+    // Everything mapped for the area
+    pub fn range(&self) -> Range<u64> {
+        self.start..self.start + self.memory.len() as u64
+    }
+
+    // The DTV at the start of the area, its slot count in the first word
+    pub fn dtv(&self) -> Range<u64> {
+        let mut slots = [0; 8];
+        slots.copy_from_slice(&self.memory[..8]);
+        self.start..self.start + 16 * (u64::from_le_bytes(slots) + 2)
+    }
+
+    // The module's block, for its .tdata image to be copied to; .tbss is already zero
+    pub fn block(&mut self, module: &Module) -> &mut [u8] {
+        let start = (self.thread_pointer as i64 + module.offset) as u64;
+        let start = (start - self.start) as usize;
+        &mut self.memory[start..start + module.template.mem_size as usize]
+    }
+}
+
+// Hands %fs to the program, whose thread pointer is `tp`, right before jumping to it. Whatever
+// elk does afterwards that touches its own thread-locals, errno and malloc included, has to go
+// through `as_host`.
+pub fn enter(tp: u64) -> io::Result<()> {
+    HOST.store(thread_pointer()?, Ordering::SeqCst);
+    set_thread_pointer(tp)
+}
+
+// Runs `f`, a callback from the program into elk, on elk's own thread pointer
+pub fn as_host<T>(f: impl FnOnce() -> T) -> T {
+    let host = HOST.load(Ordering::SeqCst);
+    let program = match host {
+        0 => return f(),
+        _ => thread_pointer().unwrap_or(0),
+    };
+    let _ = set_thread_pointer(host);
+    let result = f();
+    let _ = set_thread_pointer(program);
+    result
+}
+
+// Takes %fs back for good, for code that won't return to the program
+pub fn leave() {
+    match HOST.load(Ordering::SeqCst) {
+        0 => {}
+        host => {
+            let _ = set_thread_pointer(host);
+        }
+    }
+}
+
+fn set_thread_pointer(tp: u64) -> io::Result<()> {
+    match unsafe { libc::syscall(libc::SYS_arch_prctl, ARCH_SET_FS, tp) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+fn thread_pointer() -> io::Result<u64> {
+    let mut tp = 0u64;
+    match unsafe { libc::syscall(libc::SYS_arch_prctl, ARCH_GET_FS, &mut tp as *mut u64) } {
+        0 => Ok(tp),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+fn align_up(addr: u64, align: u64) -> u64 {
+    addr.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delf::types::Addr;
+    use std::convert::TryInto;
+
+    #[test]
+    fn variant_ii_layout() {
+        let template = |mem_size, align| TlsTemplate {
+            image: Addr(0),
+            file_size: 0,
+            mem_size,
+            align,
+        };
+        let modules = layout(&[Some(template(0x14, 8)), None, Some(template(0x10, 0x40))]);
+        let placed: Vec<_> = modules
+            .iter()
+            .map(|m| m.map(|m| (m.id, m.offset)))
+            .collect();
+        // The executable's block ends at the thread pointer, the next starts aligned below it
+        assert_eq!(placed, [Some((1, -0x18)), None, Some((2, -0x40))]);
+
+        let present: Vec<_> = modules.into_iter().flatten().collect();
+        let start = 0x7000_0000_1000;
+        let area = Area::new(&present, start);
+        assert_eq!(area.bytes().len(), Area::size(&present));
+        let tp = area.thread_pointer();
+        assert_eq!(tp % 0x40, 0);
+        let word = |addr: u64| {
+            let offset = (addr - start) as usize;
+            u64::from_le_bytes(area.bytes()[offset..offset + 8].try_into().unwrap())
+        };
+        assert_eq!(word(tp + TCB_SELF as u64), tp);
+        let dtv = word(tp + TCB_DTV as u64);
+        assert_eq!(word(dtv - 16), 2);
+        assert_eq!(word(dtv + 2 * 16), tp - 0x40);
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt, fs,
+    ops::Range,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use delf::FileHeader;
+use region::Protection;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg,
+    deps,
+    exit::Failure,
+    image::PAGE_SIZE,
+    loader::{LoadOptions, Process},
+    parse_number,
+    progress::Silenced,
+    schema, source,
+    tables::Table,
+};
+
+#[derive(clap::Args, Debug)]
+#[command(
+    about = "Load a binary without running it and list every protection change the loader \
+             made, then check the pages ended up as their segments and RELRO ask"
+)]
+pub struct Args {
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        value_parser = parse_number,
+        help = "Load address for position independent binaries"
+    )]
+    base: Option<u64>,
+    #[arg(
+        long,
+        help = "Apply text relocations, making read-only segments writable while they are patched"
+    )]
+    allow_textrel: bool,
+    #[command(flatten)]
+    format: FormatArg,
+}
+
+// What the loader was doing when it changed a range's protection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    // A LOAD segment mapped, writable so its contents and relocations can go in
+    Map,
+    // The segment given the permissions its flags ask for, once relocated
+    Segment,
+    // A slot in a read-only segment made writable for an IFUNC's value, then protected again
+    SlotWrite,
+    SlotRestore,
+    // PT_GNU_RELRO made read-only, after everything that writes to it
+    Relro,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Map => "map",
+            Phase::Segment => "segment",
+            Phase::SlotWrite => "slot-write",
+            Phase::SlotRestore => "slot-restore",
+            Phase::Relro => "relro",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    // Since the timeline was started
+    pub at: Duration,
+    pub phase: Phase,
+    pub object: String,
+    pub range: Range<u64>,
+    // None for pages that weren't mapped before
+    pub before: Option<Protection>,
+    pub after: Protection,
+}
+
+// Every protection change the loader makes, in order, handed to it through
+// `LoadOptions::protections`. Protections before a change are the ones the timeline itself saw
+// last: the loader only ever changes pages it mapped.
+#[derive(Debug)]
+pub struct Timeline {
+    started: Instant,
+    changes: Mutex<(Vec<Change>, BTreeMap<u64, Protection>)>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            changes: Default::default(),
+        }
+    }
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn record(&self, phase: Phase, object: &str, range: Range<u64>, after: Protection) {
+        let mut changes = self.changes.lock().unwrap_or_else(PoisonError::into_inner);
+        let (log, pages) = &mut *changes;
+        let first = range.start & !(PAGE_SIZE - 1);
+        let before = match phase {
+            Phase::Map => None,
+            _ => pages.get(&first).copied(),
+        };
+        for page in (first..range.end).step_by(PAGE_SIZE as usize) {
+            pages.insert(page, after);
+        }
+        log.push(Change {
+            at: self.started.elapsed(),
+            phase,
+            object: object.to_string(),
+            range,
+            before,
+            after,
+        });
+    }
+
+    pub fn changes(&self) -> Vec<Change> {
+        let changes = self.changes.lock().unwrap_or_else(PoisonError::into_inner);
+        changes.0.clone()
+    }
+}
+
+// A page whose permissions once loaded aren't what its segment's flags and RELRO ask for
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub object: String,
+    pub page: u64,
+    pub expected: Protection,
+    // None if the page isn't mapped at all
+    pub actual: Option<Protection>,
+}
+
+// Compares what `process` meant each page to allow against `actual`, the protection of the
+// page holding an address
+pub fn check(process: &Process, actual: impl Fn(u64) -> Option<Protection>) -> Vec<Mismatch> {
+    process
+        .page_protections()
+        .into_iter()
+        .filter_map(|(object, page, expected)| {
+            let actual = actual(page);
+            match actual == Some(expected) {
+                true => None,
+                false => Some(Mismatch {
+                    object,
+                    page,
+                    expected,
+                    actual,
+                }),
+            }
+        })
+        .collect()
+}
+
+// Protection of every mapping in elk, as the kernel has it
+fn kernel_protections() -> Vec<(Range<u64>, Protection)> {
+    let maps = fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    maps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (s, e) = fields.next()?.split_once('-')?;
+            let range = u64::from_str_radix(s, 16).ok()?..u64::from_str_radix(e, 16).ok()?;
+            let perms = fields.next()?.as_bytes();
+            let protection = [Protection::READ, Protection::WRITE, Protection::EXECUTE]
+                .iter()
+                .zip(perms)
+                .filter(|&(_, &flag)| flag != b'-')
+                .fold(Protection::NONE, |acc, (bit, _)| acc | *bit);
+            Some((range, protection))
+        })
+        .collect()
+}
+
+// "r-x" and the like; "unmapped" for None
+fn permissions(protection: Option<Protection>) -> String {
+    let protection = match protection {
+        Some(protection) => protection,
+        None => return "unmapped".into(),
+    };
+    [
+        (Protection::READ, 'r'),
+        (Protection::WRITE, 'w'),
+        (Protection::EXECUTE, 'x'),
+    ]
+    .iter()
+    .map(|&(bit, c)| match protection.contains(bit) {
+        true => c,
+        false => '-',
+    })
+    .collect()
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Trace {
+    changes: Vec<ChangeEntry>,
+    // Pages the end-state check covered
+    pages: usize,
+    mismatches: Vec<MismatchEntry>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "ProtectionChange")]
+struct ChangeEntry {
+    micros: u64,
+    phase: Phase,
+    object: String,
+    start: u64,
+    end: u64,
+    before: String,
+    after: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "ProtectionMismatch")]
+struct MismatchEntry {
+    object: String,
+    page: u64,
+    expected: String,
+    actual: String,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Trace>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let input = source::read_whole(&args.file)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", args.file, e)))?;
+    let base = args.base.unwrap_or_else(|| Process::default_base(&file));
+    let objects = deps::objects(&args.file, file);
+    let timeline = Arc::new(Timeline::new());
+    let options = LoadOptions {
+        allow_textrel: args.allow_textrel,
+        protections: Some(timeline.clone()),
+        ..Default::default()
+    };
+    // The loader says what it does on stdout, which is this command's output
+    let silenced = Silenced::new()?;
+    let loaded = Process::load_with_libraries(&objects[0].file, base, options, &objects[1..]);
+    silenced.restore()?;
+    let process = loaded.map_err(|e| Failure::load(format!("{}: {}", args.file, e)))?;
+
+    let mappings = kernel_protections();
+    let mismatches = check(&process, |page| {
+        mappings
+            .iter()
+            .find(|(range, _)| range.contains(&page))
+            .map(|&(_, protection)| protection)
+    });
+    let trace = Trace {
+        changes: timeline
+            .changes()
+            .into_iter()
+            .map(|change| ChangeEntry {
+                micros: change.at.as_micros() as u64,
+                phase: change.phase,
+                object: change.object,
+                start: change.range.start,
+                end: change.range.end,
+                before: permissions(change.before),
+                after: permissions(Some(change.after)),
+            })
+            .collect(),
+        pages: process.page_protections().len(),
+        mismatches: mismatches
+            .into_iter()
+            .map(|mismatch| MismatchEntry {
+                object: mismatch.object,
+                page: mismatch.page,
+                expected: permissions(Some(mismatch.expected)),
+                actual: permissions(mismatch.actual),
+            })
+            .collect(),
+    };
+
+    if args.format.json() {
+        schema::print_json("trace-relro", &trace)?;
+    } else {
+        print_trace(&trace);
+    }
+    match trace.mismatches.len() {
+        0 => Ok(()),
+        n => Err(Failure::findings(format!(
+            "{}: {} of {} pages don't have the permissions their segment and RELRO ask for",
+            args.file, n, trace.pages
+        ))
+        .into()),
+    }
+}
+
+fn print_trace(trace: &Trace) {
+    Table {
+        header: "Protection changes".into(),
+        labels: vec![
+            "Time".into(),
+            "Phase".into(),
+            "Object".into(),
+            "Range".into(),
+            "Before".into(),
+            "After".into(),
+        ],
+        rows: trace
+            .changes
+            .iter()
+            .map(|change| {
+                vec![
+                    format!("{:.3} ms", change.micros as f64 / 1000.0),
+                    change.phase.to_string(),
+                    change.object.clone(),
+                    format!("{:#x}..{:#x}", change.start, change.end),
+                    change.before.clone(),
+                    change.after.clone(),
+                ]
+            })
+            .collect(),
+    }
+    .print();
+    if trace.mismatches.is_empty() {
+        println!(
+            "End state: all {} pages match their segment's flags and RELRO",
+            trace.pages
+        );
+        return;
+    }
+    Table {
+        header: "Pages not as their segment and RELRO ask".into(),
+        labels: vec![
+            "Object".into(),
+            "Page".into(),
+            "Expected".into(),
+            "Actual".into(),
+        ],
+        rows: trace
+            .mismatches
+            .iter()
+            .map(|mismatch| {
+                vec![
+                    mismatch.object.clone(),
+                    format!("{:#x}", mismatch.page),
+                    mismatch.expected.clone(),
+                    mismatch.actual.clone(),
+                ]
+            })
+            .collect(),
+    }
+    .print();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loader::MAIN_OBJECT, space::Buffer};
+
+    const LADDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/ladder/");
+
+    // Loads 3-needed into a buffer, which keeps the protection of each page as the loader set it
+    fn loaded() -> (Arc<Buffer>, Process) {
+        let path = format!("{}3-needed", LADDER);
+        let input = source::read(&path).unwrap();
+        let objects = deps::objects(&path, FileHeader::parse_or_describe(&input).unwrap());
+        let main = &objects[0].file;
+        let buffer = Arc::new(Buffer::new());
+        let process = Process::load_into(
+            buffer.clone(),
+            main,
+            Process::default_base(main),
+            LoadOptions::default(),
+            &objects[1..],
+        )
+        .unwrap();
+        (buffer, process)
+    }
+
+    #[test]
+    fn pages_left_as_the_loader_meant_pass() {
+        let (buffer, process) = loaded();
+        assert!(!process.page_protections().is_empty());
+        assert!(check(&process, |page| buffer.protection(page)).is_empty());
+    }
+
+    // The executable's text made writable, as a text relocation left unprotected would be
+    #[test]
+    fn pages_that_allow_more_are_mismatches() {
+        let (buffer, process) = loaded();
+        let text = process
+            .page_protections()
+            .into_iter()
+            .find(|(object, _, protection)| {
+                object == MAIN_OBJECT && protection.contains(Protection::EXECUTE)
+            })
+            .unwrap()
+            .1;
+        let mismatches = check(&process, |page| match page == text {
+            true => Some(Protection::READ_WRITE_EXECUTE),
+            false => buffer.protection(page),
+        });
+        assert_eq!(mismatches.len(), 1);
+        let mismatch = &mismatches[0];
+        assert_eq!(
+            (mismatch.object.as_str(), mismatch.page),
+            (MAIN_OBJECT, text)
+        );
+        assert_eq!(mismatch.expected, Protection::READ_EXECUTE);
+        assert_eq!(mismatch.actual, Some(Protection::READ_WRITE_EXECUTE));
+    }
+}
@@ -0,0 +1,192 @@
+use std::error::Error;
+
+use delf::{detect::Class, types::*, FileHeader, RelaReadError};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::FormatArg, exit::Failure, relocs::region, schema, size::demangle, source, tables::Table,
+};
+
+#[derive(clap::Args, Debug)]
+#[command(
+    about = "Rebuild vtables and function pointer arrays from the relocations that fill them"
+)]
+pub struct Args {
+    #[arg(
+        long,
+        default_value_t = 2,
+        help = "Fewest adjacent function pointers that make a table"
+    )]
+    min: usize,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+}
+
+// A run of adjacent pointer slots that relocations point at functions
+#[derive(Serialize, JsonSchema)]
+struct PointerTable {
+    address: u64,
+    // Symbol the table starts in, as `vtable for Shape+0x10`
+    name: Option<String>,
+    region: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Entry {
+    slot: u64,
+    // None for functions imported from another object
+    target: Option<u64>,
+    symbol: Option<String>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Vec<PointerTable>>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let tables = pointer_tables(&file, args.min)?;
+
+    if args.format.json() {
+        return schema::print_json("vtables", &tables);
+    }
+    if tables.is_empty() {
+        println!(
+            "{}: no tables of {} or more function pointers",
+            path, args.min
+        );
+    }
+    for table in &tables {
+        Table {
+            header: format!(
+                "{} at {:#x} in {}, {} entries",
+                table.name.as_deref().unwrap_or("?"),
+                table.address,
+                table.region,
+                table.entries.len()
+            ),
+            labels: vec!["Slot".into(), "Target".into(), "Symbol".into()],
+            rows: table
+                .entries
+                .iter()
+                .map(|entry| {
+                    vec![
+                        format!("{:#x}", entry.slot),
+                        entry
+                            .target
+                            .map_or_else(|| "-".into(), |target| format!("{:#x}", target)),
+                        entry.symbol.clone().unwrap_or_else(|| "-".into()),
+                    ]
+                })
+                .collect(),
+        }
+        .print();
+    }
+    Ok(())
+}
+
+fn pointer_tables(file: &FileHeader, min: usize) -> Result<Vec<PointerTable>, Box<dyn Error>> {
+    let word = match file.class {
+        Class::Elf32 => 4,
+        _ => 8,
+    };
+    let syms = file.read_syms();
+    let mut entries: Vec<Entry> = match file.read_rela_entries() {
+        Err(RelaReadError::RelaNotFound) => Vec::new(),
+        entries => entries?,
+    }
+    .iter()
+    .filter(|rela| !region(file, rela.offset).starts_with(".got"))
+    .filter_map(|rela| function_pointer(file, &syms, rela))
+    .collect();
+    entries.sort_by_key(|entry| entry.slot);
+
+    // Split wherever a slot isn't the word right after the previous one, or crosses into another
+    // section as .init_array does into .fini_array
+    let mut runs: Vec<Vec<Entry>> = Vec::new();
+    for entry in entries {
+        let follows = |last: &Entry| {
+            last.slot + word == entry.slot
+                && region(file, Addr(last.slot)) == region(file, Addr(entry.slot))
+        };
+        match runs.last_mut() {
+            Some(run) if run.last().is_some_and(follows) => run.push(entry),
+            _ => runs.push(vec![entry]),
+        }
+    }
+    Ok(runs
+        .into_iter()
+        .filter(|run| run.len() >= min)
+        .map(|entries| {
+            let address = entries[0].slot;
+            PointerTable {
+                address,
+                name: file
+                    .symbol_at(Addr(address))
+                    .map(|(sym, offset)| named(&sym.name, offset)),
+                region: region(file, Addr(address)),
+                entries,
+            }
+        })
+        .collect())
+}
+
+// The function a relocation stores a pointer to, if it stores one. Relative relocations count
+// when their addend lands in code, symbolic ones when they bind a function symbol.
+fn function_pointer(file: &FileHeader, syms: &[Symbol], rela: &RelaEntry) -> Option<Entry> {
+    let (target, symbol) = match rela.typ {
+        RelType::Relative => {
+            let target = Addr(0) + rela.addend;
+            if !is_code(file, target) {
+                return None;
+            }
+            let symbol = file
+                .symbol_at(target)
+                .map(|(sym, offset)| named(&sym.name, offset));
+            (Some(target.0), symbol)
+        }
+        RelType::Abs64 | RelType::GlobalData => {
+            let sym = syms.get(rela.sym as usize)?;
+            if sym.typ() != Some(SymType::Func) {
+                return None;
+            }
+            let offset = rela.addend.0 as u64;
+            let target = sym
+                .section_index()
+                .map(|_| sym.value.0.wrapping_add(offset));
+            (target, Some(named(&sym.name, offset)))
+        }
+        _ => return None,
+    };
+    Some(Entry {
+        slot: rela.offset.0,
+        target,
+        symbol,
+    })
+}
+
+fn is_code(file: &FileHeader, addr: Addr) -> bool {
+    if file.section_headers.is_empty() {
+        return file
+            .segment_at(addr)
+            .is_some_and(|ph| ph.flags.contains(SegmentFlags::Execute));
+    }
+    file.section_headers
+        .iter()
+        .any(|sh| sh.flags.contains(SectionFlags::ExecInstr) && sh.mem_range().contains(&addr))
+}
+
+fn named(name: &str, offset: u64) -> String {
+    match offset {
+        0 => demangle(name),
+        _ => format!("{}+{:#x}", demangle(name), offset),
+    }
+}
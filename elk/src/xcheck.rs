@@ -0,0 +1,467 @@
+use std::{collections::HashMap, error::Error};
+
+use delf::{
+    data::Data,
+    detect::Class,
+    types::{Addr, DynamicTag, SectionIdx, SectionType, Symbol},
+    FileHeader,
+};
+use object::{
+    elf,
+    read::elf::{ElfFile, ProgramHeader as _, SectionHeader as _, Sym as _, SymbolTable},
+    Endianness, Object, ObjectSection, ReadRef, Relocation, RelocationFlags, RelocationTarget,
+};
+
+const DT_RELA: u64 = 7;
+
+use crate::{exit::Failure, source, tables::Table};
+
+#[derive(clap::Args, Debug)]
+#[command(
+    about = "Parse binaries with delf and with the object crate, and list every field they \
+             read differently"
+)]
+pub struct Args {
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    files: Vec<String>,
+}
+
+// Field name and value, in the order the fields are read
+type Facts = Vec<(String, String)>;
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut differing = 0;
+    for path in &args.files {
+        let input = source::read_whole(path)?;
+        let (ours, rows) = compare(path, &input)?;
+        if rows.is_empty() {
+            println!(
+                "{}: delf and object agree on all {} fields",
+                path,
+                ours.len()
+            );
+            continue;
+        }
+        differing += 1;
+        Table {
+            header: format!("{}: {} of {} fields differ", path, rows.len(), ours.len()),
+            labels: vec!["Field".into(), "delf".into(), "object".into()],
+            rows,
+        }
+        .print();
+    }
+    match differing {
+        0 => Ok(()),
+        n => Err(Failure::findings(format!(
+            "delf and object disagree on {} of {} files",
+            n,
+            args.files.len()
+        ))
+        .into()),
+    }
+}
+
+// What delf reads from `input`, and every field where object reads something else: field, delf's
+// value and object's, "-" for a field only the other one has
+fn compare(path: &str, input: &Data) -> Result<(Facts, Vec<Vec<String>>), Failure> {
+    let file = FileHeader::parse_or_describe(input)
+        .map_err(|e| Failure::parse(format!("{}: delf: {}", path, e)))?;
+    let theirs = object::File::parse(&input[..])
+        .map_err(|e| Failure::parse(format!("{}: object: {}", path, e)))
+        .and_then(|parsed| match parsed {
+            object::File::Elf32(parsed) => Ok(object_facts(&parsed)),
+            object::File::Elf64(parsed) => Ok(object_facts(&parsed)),
+            _ => Err(Failure::parse(format!("{}: object: not an ELF file", path))),
+        })?;
+    let ours = delf_facts(&file);
+
+    let mut by_field: HashMap<&str, &str> = theirs
+        .iter()
+        .map(|(field, value)| (field.as_str(), value.as_str()))
+        .collect();
+    let mut rows = Vec::new();
+    for (field, value) in &ours {
+        match by_field.remove(field.as_str()) {
+            Some(other) if other == value => {}
+            other => rows.push(vec![
+                field.clone(),
+                value.clone(),
+                other.unwrap_or("-").to_string(),
+            ]),
+        }
+    }
+    // Only object has them, in its own order
+    for (field, value) in &theirs {
+        if by_field.contains_key(field.as_str()) {
+            rows.push(vec![field.clone(), "-".into(), value.clone()]);
+        }
+    }
+    Ok((ours, rows))
+}
+
+fn hex(value: impl Into<u64>) -> String {
+    format!("{:#x}", value.into())
+}
+
+fn delf_facts(file: &FileHeader) -> Facts {
+    let mut facts: Facts = vec![
+        ("class".into(), format!("{:?}", file.class)),
+        ("e_type".into(), hex(file.typ as u16)),
+        ("e_machine".into(), hex(u16::from(file.machine))),
+        ("e_flags".into(), hex(file.flags)),
+        ("e_entry".into(), hex(file.entry_point.0)),
+        (
+            "program headers".into(),
+            file.program_headers.len().to_string(),
+        ),
+        (
+            "section headers".into(),
+            file.section_headers.len().to_string(),
+        ),
+    ];
+    for (i, ph) in file.program_headers.iter().enumerate() {
+        let field = |name| format!("segment {} {}", i, name);
+        facts.extend([
+            (field("p_type"), hex(u32::from(ph.typ))),
+            (field("p_flags"), hex(ph.flags.bits())),
+            (field("p_offset"), hex(ph.offset.0)),
+            (field("p_vaddr"), hex(ph.virt_addr.0)),
+            (field("p_filesz"), hex(ph.file_size.0)),
+            (field("p_memsz"), hex(ph.mem_size.0)),
+            (field("p_align"), hex(ph.align.0)),
+        ]);
+    }
+    for (i, sh) in file.section_headers.iter().enumerate() {
+        let field = |name| format!("section {} {}", i, name);
+        facts.extend([
+            (field("name"), sh.name.clone()),
+            (field("sh_type"), hex(u32::from(sh.typ))),
+            (field("sh_flags"), hex(sh.flags.bits())),
+            (field("sh_addr"), hex(sh.addr.0)),
+            (field("sh_offset"), hex(sh.offset.0)),
+            (field("sh_size"), hex(sh.size.0)),
+            (field("sh_link"), hex(sh.link)),
+            (field("sh_info"), hex(sh.info)),
+            (field("sh_addralign"), hex(sh.align.0)),
+            (field("sh_entsize"), hex(sh.entsize.0)),
+        ]);
+    }
+    let symtab = file
+        .section_headers
+        .iter()
+        .position(|sh| sh.typ == SectionType::SymTab);
+    if let Some(index) = symtab {
+        symbol_facts(&mut facts, "symtab", &file.symbols_in(index));
+    }
+    // Through the dynamic section, where object goes by .dynsym's section header
+    symbol_facts(&mut facts, "dynsym", &file.read_syms());
+    // Addends of REL entries are in the slots, which delf reads and object leaves to the caller
+    let explicit = file.dynamic_entry(DynamicTag::Rela).is_some();
+    let plt_explicit = file.dynamic_entry(DynamicTag::PltRel) == Some(Addr(DT_RELA));
+    let relocations = file
+        .read_rela_entries()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|reloc| (reloc, explicit))
+        .chain(
+            file.read_plt_rela_entries()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|reloc| (reloc, plt_explicit)),
+        );
+    for (reloc, explicit) in relocations {
+        let field = |name: &str| format!("dynamic relocation at {:#x} {}", reloc.offset.0, name);
+        relocation_facts(
+            &mut facts,
+            field,
+            reloc.sym,
+            format!("{:?}", reloc.typ),
+            explicit.then_some(reloc.addend.0),
+        );
+    }
+    // Those of relocatable objects, numbered by the section they apply to
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for sh in &file.section_headers {
+        let explicit = sh.typ == SectionType::Rela;
+        for reloc in file.section_rela_entries(sh).unwrap_or_default() {
+            let count = counts.entry(sh.info).or_default();
+            let field = |name: &str| format!("section {} relocation {} {}", sh.info, count, name);
+            facts.push((field("r_offset"), hex(reloc.offset.0)));
+            relocation_facts(
+                &mut facts,
+                field,
+                reloc.sym,
+                format!("{:?}", reloc.typ),
+                explicit.then_some(reloc.addend.0),
+            );
+            *count += 1;
+        }
+    }
+    facts
+}
+
+fn relocation_facts(
+    facts: &mut Facts,
+    field: impl Fn(&str) -> String,
+    sym: u32,
+    typ: String,
+    addend: Option<i64>,
+) {
+    facts.extend([(field("symbol"), sym.to_string()), (field("type"), typ)]);
+    if let Some(addend) = addend {
+        facts.push((field("addend"), format!("{:#x}", addend)));
+    }
+}
+
+fn symbol_facts(facts: &mut Facts, table: &str, symbols: &[Symbol]) {
+    for (i, sym) in symbols.iter().enumerate() {
+        let field = |name| format!("{} {} {}", table, i, name);
+        let shndx = match sym.shndx {
+            SectionIdx::Undef => "undef".into(),
+            SectionIdx::Abs => "abs".into(),
+            SectionIdx::Common => "common".into(),
+            SectionIdx::Index(index) => index.to_string(),
+            SectionIdx::Xindex => "xindex".into(),
+            SectionIdx::Reserved(shndx) => format!("reserved {:#x}", shndx),
+        };
+        facts.extend([
+            (field("name"), sym.name.clone()),
+            (field("st_value"), hex(sym.value.0)),
+            (field("st_size"), hex(sym.size)),
+            (field("st_info"), hex(sym.info)),
+            (field("st_other"), hex(sym.other)),
+            (field("st_shndx"), shndx),
+        ]);
+    }
+}
+
+fn object_facts<'data, Elf, R>(file: &ElfFile<'data, Elf, R>) -> Facts
+where
+    Elf: object::read::elf::FileHeader<Endian = Endianness>,
+    R: ReadRef<'data>,
+{
+    let endian = file.endian();
+    let header = file.elf_header();
+    let class = match header.is_type_64() {
+        true => Class::Elf64,
+        false => Class::Elf32,
+    };
+    let sections = file.elf_section_table();
+    let mut facts: Facts = vec![
+        ("class".into(), format!("{:?}", class)),
+        ("e_type".into(), hex(header.e_type(endian))),
+        ("e_machine".into(), hex(header.e_machine(endian))),
+        ("e_flags".into(), hex(header.e_flags(endian))),
+        ("e_entry".into(), hex(header.e_entry(endian).into())),
+        (
+            "program headers".into(),
+            file.elf_program_headers().len().to_string(),
+        ),
+        ("section headers".into(), sections.len().to_string()),
+    ];
+    for (i, ph) in file.elf_program_headers().iter().enumerate() {
+        let field = |name| format!("segment {} {}", i, name);
+        facts.extend([
+            (field("p_type"), hex(ph.p_type(endian))),
+            (field("p_flags"), hex(ph.p_flags(endian))),
+            (field("p_offset"), hex(ph.p_offset(endian).into())),
+            (field("p_vaddr"), hex(ph.p_vaddr(endian).into())),
+            (field("p_filesz"), hex(ph.p_filesz(endian).into())),
+            (field("p_memsz"), hex(ph.p_memsz(endian).into())),
+            (field("p_align"), hex(ph.p_align(endian).into())),
+        ]);
+    }
+    for (i, sh) in sections.iter().enumerate() {
+        let field = |name| format!("section {} {}", i, name);
+        let name = sections.section_name(endian, sh).unwrap_or_default();
+        facts.extend([
+            (field("name"), String::from_utf8_lossy(name).into_owned()),
+            (field("sh_type"), hex(sh.sh_type(endian))),
+            (field("sh_flags"), hex(sh.sh_flags(endian).into())),
+            (field("sh_addr"), hex(sh.sh_addr(endian).into())),
+            (field("sh_offset"), hex(sh.sh_offset(endian).into())),
+            (field("sh_size"), hex(sh.sh_size(endian).into())),
+            (field("sh_link"), hex(sh.sh_link(endian))),
+            (field("sh_info"), hex(sh.sh_info(endian))),
+            (field("sh_addralign"), hex(sh.sh_addralign(endian).into())),
+            (field("sh_entsize"), hex(sh.sh_entsize(endian).into())),
+        ]);
+    }
+    symbol_table_facts(&mut facts, "symtab", file.elf_symbol_table(), endian);
+    symbol_table_facts(
+        &mut facts,
+        "dynsym",
+        file.elf_dynamic_symbol_table(),
+        endian,
+    );
+    let machine = header.e_machine(endian);
+    let relocation = |facts: &mut Facts, field: &dyn Fn(&str) -> String, reloc: Relocation| {
+        let sym = match reloc.target() {
+            RelocationTarget::Symbol(index) => index.0 as u32,
+            _ => 0,
+        };
+        let typ = match reloc.flags() {
+            RelocationFlags::Elf { r_type } => rel_type(machine, class, r_type),
+            flags => format!("{:?}", flags),
+        };
+        let addend = (!reloc.has_implicit_addend()).then_some(reloc.addend());
+        relocation_facts(facts, field, sym, typ, addend);
+    };
+    for (offset, reloc) in file.dynamic_relocations().into_iter().flatten() {
+        let field = |name: &str| format!("dynamic relocation at {:#x} {}", offset, name);
+        relocation(&mut facts, &field, reloc);
+    }
+    for section in file.sections() {
+        for (i, (offset, reloc)) in section.relocations().enumerate() {
+            let field =
+                |name: &str| format!("section {} relocation {} {}", section.index().0, i, name);
+            facts.push((field("r_offset"), hex(offset)));
+            relocation(&mut facts, &field, reloc);
+        }
+    }
+    facts
+}
+
+// delf's name for a relocation type, worked out from object's constants rather than delf's own
+// tables
+fn rel_type(machine: u16, class: Class, r_type: u32) -> String {
+    let word = |elf32, elf64| match class {
+        Class::Elf32 => elf32,
+        Class::Elf64 => elf64,
+    };
+    let names: &[(u32, &str)] = match machine {
+        elf::EM_X86_64 => &[
+            (elf::R_X86_64_64, "Abs64"),
+            (elf::R_X86_64_COPY, "Copy"),
+            (elf::R_X86_64_GLOB_DAT, "GlobalData"),
+            (elf::R_X86_64_JUMP_SLOT, "JumpSlot"),
+            (elf::R_X86_64_RELATIVE, "Relative"),
+            (elf::R_X86_64_DTPMOD64, "DtpMod64"),
+            (elf::R_X86_64_DTPOFF64, "DtpOff64"),
+            (elf::R_X86_64_TPOFF64, "TpOff64"),
+            (elf::R_X86_64_IRELATIVE, "IRelative"),
+        ],
+        elf::EM_386 => &[
+            (elf::R_386_32, "Abs64"),
+            (elf::R_386_COPY, "Copy"),
+            (elf::R_386_GLOB_DAT, "GlobalData"),
+            (elf::R_386_JMP_SLOT, "JumpSlot"),
+            (elf::R_386_RELATIVE, "Relative"),
+            (elf::R_386_TLS_DTPMOD32, "DtpMod64"),
+            (elf::R_386_TLS_DTPOFF32, "DtpOff64"),
+            (elf::R_386_TLS_TPOFF, "TpOff64"),
+            (elf::R_386_IRELATIVE, "IRelative"),
+        ],
+        elf::EM_ARM => &[
+            (elf::R_ARM_ABS32, "Abs64"),
+            (elf::R_ARM_COPY, "Copy"),
+            (elf::R_ARM_GLOB_DAT, "GlobalData"),
+            (elf::R_ARM_JUMP_SLOT, "JumpSlot"),
+            (elf::R_ARM_RELATIVE, "Relative"),
+            (elf::R_ARM_TLS_DTPMOD32, "DtpMod64"),
+            (elf::R_ARM_TLS_DTPOFF32, "DtpOff64"),
+            (elf::R_ARM_TLS_TPOFF32, "TpOff64"),
+            (elf::R_ARM_IRELATIVE, "IRelative"),
+        ],
+        elf::EM_AARCH64 => &[
+            (elf::R_AARCH64_ABS64, "Abs64"),
+            (elf::R_AARCH64_COPY, "Copy"),
+            (elf::R_AARCH64_GLOB_DAT, "GlobalData"),
+            (elf::R_AARCH64_JUMP_SLOT, "JumpSlot"),
+            (elf::R_AARCH64_RELATIVE, "Relative"),
+            (elf::R_AARCH64_TLS_DTPMOD, "DtpMod64"),
+            (elf::R_AARCH64_TLS_DTPREL, "DtpOff64"),
+            (elf::R_AARCH64_TLS_TPREL, "TpOff64"),
+            (elf::R_AARCH64_IRELATIVE, "IRelative"),
+        ],
+        elf::EM_RISCV => &[
+            (word(elf::R_RISCV_32, elf::R_RISCV_64), "Abs64"),
+            (elf::R_RISCV_COPY, "Copy"),
+            (elf::R_RISCV_JUMP_SLOT, "JumpSlot"),
+            (elf::R_RISCV_RELATIVE, "Relative"),
+            (
+                word(elf::R_RISCV_TLS_DTPMOD32, elf::R_RISCV_TLS_DTPMOD64),
+                "DtpMod64",
+            ),
+            (
+                word(elf::R_RISCV_TLS_DTPREL32, elf::R_RISCV_TLS_DTPREL64),
+                "DtpOff64",
+            ),
+            (
+                word(elf::R_RISCV_TLS_TPREL32, elf::R_RISCV_TLS_TPREL64),
+                "TpOff64",
+            ),
+            (elf::R_RISCV_IRELATIVE, "IRelative"),
+        ],
+        _ => &[],
+    };
+    names
+        .iter()
+        .find(|&&(number, _)| number == r_type)
+        .map_or_else(
+            || format!("Unknown({})", r_type),
+            |(_, name)| name.to_string(),
+        )
+}
+
+fn symbol_table_facts<'data, Elf, R>(
+    facts: &mut Facts,
+    table: &str,
+    symbols: &SymbolTable<'data, Elf, R>,
+    endian: Endianness,
+) where
+    Elf: object::read::elf::FileHeader<Endian = Endianness>,
+    R: ReadRef<'data>,
+{
+    for (index, sym) in symbols.enumerate() {
+        let field = |name| format!("{} {} {}", table, index.0, name);
+        let name = symbols.symbol_name(endian, sym).unwrap_or_default();
+        let shndx = match sym.st_shndx(endian) {
+            elf::SHN_UNDEF => "undef".into(),
+            elf::SHN_ABS => "abs".into(),
+            elf::SHN_COMMON => "common".into(),
+            elf::SHN_XINDEX => match symbols.symbol_section(endian, sym, index) {
+                Ok(Some(section)) => section.0.to_string(),
+                _ => "xindex".into(),
+            },
+            shndx if shndx >= elf::SHN_LORESERVE => format!("reserved {:#x}", shndx),
+            shndx => shndx.to_string(),
+        };
+        facts.extend([
+            (field("name"), String::from_utf8_lossy(name).into_owned()),
+            (field("st_value"), hex(sym.st_value(endian).into())),
+            (field("st_size"), hex(sym.st_size(endian).into())),
+            (field("st_info"), hex(sym.st_info())),
+            (field("st_other"), hex(sym.st_other())),
+            (field("st_shndx"), shndx),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every file of delf's multi-architecture corpus, linked and relocatable
+    #[test]
+    fn delf_and_object_agree_on_the_corpus() {
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/../delf/corpus");
+        let mut paths: Vec<_> = std::fs::read_dir(corpus)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_none_or(|e| e == "o"))
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 12);
+        for path in paths {
+            let path = path.display().to_string();
+            let input = source::map_raw(&path).unwrap();
+            let (ours, rows) = compare(&path, &input).unwrap();
+            assert!(rows.is_empty(), "{}: {:?}", path, rows);
+            if path.ends_with(".o") {
+                let types = ours.iter().filter(|(field, _)| field.ends_with(" type"));
+                assert!(types.count() > 0, "{}: no relocations compared", path);
+            }
+        }
+    }
+}
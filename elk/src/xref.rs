@@ -0,0 +1,287 @@
+use std::{collections::HashSet, error::Error};
+
+use clap_complete::engine::ArgValueCompleter;
+use delf::{types::*, FileHeader};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::Serialize;
+
+use crate::{
+    cli::{self, FormatArg},
+    deps, disasm_listing,
+    exit::Failure,
+    relocs, schema, source,
+    tables::Table,
+};
+
+// PLT stubs are 16 bytes in .plt, .plt.sec and .plt.got alike
+pub const PLT_ENTRY_SIZE: u64 = 16;
+
+#[derive(clap::Args, Debug)]
+#[command(about = "List the relocations and call sites that reference a symbol")]
+pub struct Args {
+    #[arg(
+        long,
+        help = "Disassemble code sections with ndisasm and list calls and jumps to the symbol"
+    )]
+    calls: bool,
+    #[arg(
+        long,
+        help = "Search every library the file pulls in, not just the file itself"
+    )]
+    deps: bool,
+    #[command(flatten)]
+    format: FormatArg,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    file: String,
+    #[arg(add = ArgValueCompleter::new(cli::symbol_names))]
+    symbol: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "XrefDefinition")]
+struct Definition {
+    object: String,
+    address: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Reference {
+    object: String,
+    site: u64,
+    // Relocation type, or the branch instruction
+    kind: String,
+    // Section or symbol the site is in
+    location: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "XrefReport")]
+struct Report {
+    symbol: String,
+    definitions: Vec<Definition>,
+    references: Vec<Reference>,
+}
+
+// `data` of the command's JSON output
+pub fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<Report>()
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let path = &args.file;
+    let input = source::read(path)?;
+    let file = FileHeader::parse_or_describe(&input)
+        .map_err(|e| Failure::parse(format!("{}: {}", path, e)))?;
+    let objects = match args.deps {
+        true => deps::objects(path, file),
+        false => vec![deps::Object {
+            name: path.clone(),
+            path: path.into(),
+            file,
+        }],
+    };
+
+    let mut report = Report {
+        symbol: args.symbol.clone(),
+        definitions: Vec::new(),
+        references: Vec::new(),
+    };
+    for object in &objects {
+        let (definitions, references) =
+            search(&object.name, &object.file, &args.symbol, args.calls)?;
+        report.definitions.extend(definitions);
+        report.references.extend(references);
+    }
+
+    if args.format.json() {
+        schema::print_json("xref", &report)?;
+    } else {
+        print_report(&report);
+    }
+    Ok(())
+}
+
+fn search(
+    object: &str,
+    file: &FileHeader,
+    name: &str,
+    calls: bool,
+) -> Result<(Vec<Definition>, Vec<Reference>), Box<dyn Error>> {
+    let symbols: Vec<Symbol> = file
+        .read_section_syms()
+        .into_iter()
+        .chain(
+            file.section_headers
+                .iter()
+                .position(|sh| sh.typ == SectionType::DynSym)
+                .map(|idx| file.symbols_in(idx))
+                .unwrap_or_default(),
+        )
+        .filter(|sym| sym.name == name)
+        .collect();
+    let mut addresses: HashSet<u64> = symbols
+        .iter()
+        .filter(|sym| sym.section_index().is_some())
+        .map(|sym| sym.value.0)
+        .collect();
+    let definitions = addresses
+        .iter()
+        .map(|&address| Definition {
+            object: object.to_string(),
+            address,
+        })
+        .collect();
+
+    let mut references = Vec::new();
+    // Slots the dynamic linker fills with the symbol's address, such as its GOT entry
+    let mut slots = HashSet::new();
+    for reloc in relocs::annotate(file)? {
+        let matches = reloc.symbol.as_deref().is_some_and(|symbol| {
+            symbol == name
+                || symbol
+                    .strip_prefix(name)
+                    .is_some_and(|r| r.starts_with('+'))
+        });
+        if !matches {
+            continue;
+        }
+        if reloc.typ != "Relative" {
+            slots.insert(reloc.offset);
+        }
+        references.push(Reference {
+            object: object.to_string(),
+            site: reloc.offset,
+            kind: reloc.typ,
+            location: reloc.region,
+        });
+    }
+
+    if calls {
+        let code: Vec<&SectionHeader> = file
+            .section_headers
+            .iter()
+            .filter(|sh| {
+                sh.flags.contains(SectionFlags::ExecInstr) && sh.typ != SectionType::NoBits
+            })
+            .collect();
+        let (plt, other): (Vec<_>, Vec<_>) =
+            code.into_iter().partition(|sh| sh.name.starts_with(".plt"));
+        // A PLT stub jumps through the symbol's slot; calls to the stub are calls to the symbol
+        for sh in plt {
+            for branch in branches(file.machine, sh)? {
+                if let Target::Slot(slot) = branch.target {
+                    if slots.contains(&slot) {
+                        let stub = (branch.addr - sh.addr.0) / PLT_ENTRY_SIZE * PLT_ENTRY_SIZE;
+                        addresses.insert(sh.addr.0 + stub);
+                    }
+                }
+            }
+        }
+        for sh in other {
+            for Branch {
+                addr,
+                mnemonic,
+                target,
+            } in branches(file.machine, sh)?
+            {
+                let hit = match target {
+                    Target::Direct(to) => addresses.contains(&to),
+                    Target::Slot(slot) => slots.contains(&slot),
+                };
+                if hit {
+                    references.push(Reference {
+                        object: object.to_string(),
+                        site: addr,
+                        kind: mnemonic,
+                        location: match file.symbol_at(Addr(addr)) {
+                            Some((sym, 0)) => sym.name,
+                            Some((sym, offset)) => format!("{}+{:#x}", sym.name, offset),
+                            None => sh.name.clone(),
+                        },
+                    });
+                }
+            }
+        }
+    }
+    Ok((definitions, references))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Direct(u64),
+    // Through a pointer in memory, as `call [rel 0x3fd8]`
+    Slot(u64),
+}
+
+pub struct Branch {
+    pub addr: u64,
+    pub mnemonic: String,
+    pub target: Target,
+}
+
+// Calls and jumps in a section with a target ndisasm could work out. Other machines' listings
+// have no mnemonics, so nothing is found in them.
+pub fn branches(machine: Machine, sh: &SectionHeader) -> Result<Vec<Branch>, Box<dyn Error>> {
+    let listing = disasm_listing(machine, &sh.data, sh.addr.0, &[])?;
+    Ok(listing.lines().filter_map(branch).collect())
+}
+
+// Parses one ndisasm line, such as `0000113D  E8EEFEFFFF  call 0x1030`
+pub fn branch(line: &str) -> Option<Branch> {
+    let mut fields = line.split_whitespace();
+    let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let _bytes = fields.next()?;
+    let mut mnemonic = fields.next()?;
+    if mnemonic == "bnd" {
+        mnemonic = fields.next()?;
+    }
+    if !(mnemonic == "call" || mnemonic.starts_with('j')) {
+        return None;
+    }
+    let operand: String = fields.collect::<Vec<_>>().join(" ");
+    let hex = |s: &str| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok();
+    let target = match operand.split_once("[rel ") {
+        Some((_, rest)) => Target::Slot(hex(rest.trim_end_matches(']'))?),
+        None => Target::Direct(hex(operand
+            .trim_start_matches("near ")
+            .trim_start_matches("short "))?),
+    };
+    Some(Branch {
+        addr,
+        mnemonic: mnemonic.to_string(),
+        target,
+    })
+}
+
+fn print_report(report: &Report) {
+    for def in &report.definitions {
+        println!(
+            "{} is defined in {} at {:#x}",
+            report.symbol, def.object, def.address
+        );
+    }
+    if report.definitions.is_empty() {
+        println!("{} is not defined in any object searched", report.symbol);
+    }
+    let table = Table {
+        header: format!(
+            "{} references to {}",
+            report.references.len(),
+            report.symbol
+        ),
+        labels: vec!["Object".into(), "Site".into(), "Kind".into(), "In".into()],
+        rows: report
+            .references
+            .iter()
+            .map(|r| {
+                vec![
+                    r.object.clone(),
+                    format!("{:#x}", r.site),
+                    r.kind.clone(),
+                    r.location.clone(),
+                ]
+            })
+            .collect(),
+    };
+    table.print();
+}
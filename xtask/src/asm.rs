@@ -0,0 +1,131 @@
+// Just the x86-64 the fixtures are written in, encoded by hand. Code knows the address it runs
+// at, so RIP-relative operands are given the address they refer to.
+pub struct Code {
+    pub addr: u64,
+    pub bytes: Vec<u8>,
+}
+
+const SYS_WRITE: u32 = 1;
+const SYS_EXIT: u32 = 60;
+
+impl Code {
+    pub fn at(addr: u64) -> Self {
+        Self {
+            addr,
+            bytes: Vec::new(),
+        }
+    }
+
+    // Where the next instruction goes
+    pub fn here(&self) -> u64 {
+        self.addr + self.bytes.len() as u64
+    }
+
+    fn imm32(&mut self, opcode: u8, imm: u32) -> &mut Self {
+        self.bytes.push(opcode);
+        self.bytes.extend(imm.to_le_bytes());
+        self
+    }
+
+    fn rip_relative(&mut self, opcode: &[u8], target: u64) -> &mut Self {
+        let next = self.here() + opcode.len() as u64 + 4;
+        self.bytes.extend(opcode);
+        self.bytes
+            .extend((target.wrapping_sub(next) as i32).to_le_bytes());
+        self
+    }
+
+    // mov eax, imm32
+    pub fn mov_eax(&mut self, imm: u32) -> &mut Self {
+        self.imm32(0xb8, imm)
+    }
+
+    // mov edi, imm32
+    pub fn mov_edi(&mut self, imm: u32) -> &mut Self {
+        self.imm32(0xbf, imm)
+    }
+
+    // mov edx, imm32
+    pub fn mov_edx(&mut self, imm: u32) -> &mut Self {
+        self.imm32(0xba, imm)
+    }
+
+    // lea rsi, [rip + target]
+    pub fn lea_rsi(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0x48, 0x8d, 0x35], target)
+    }
+
+    // mov rsi, [rip + target]
+    pub fn load_rsi(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0x48, 0x8b, 0x35], target)
+    }
+
+    // lea rax, [rip + target]
+    pub fn lea_rax(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0x48, 0x8d, 0x05], target)
+    }
+
+    // mov rsi, rax
+    pub fn mov_rsi_rax(&mut self) -> &mut Self {
+        self.bytes.extend([0x48, 0x89, 0xc6]);
+        self
+    }
+
+    // call [rip + target]
+    pub fn call_indirect(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0xff, 0x15], target)
+    }
+
+    // call target
+    pub fn call(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0xe8], target)
+    }
+
+    // jmp [rip + target]
+    pub fn jmp_indirect(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0xff, 0x25], target)
+    }
+
+    // jmp target
+    pub fn jmp(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0xe9], target)
+    }
+
+    // push qword [rip + target]
+    pub fn push_indirect(&mut self, target: u64) -> &mut Self {
+        self.rip_relative(&[0xff, 0x35], target)
+    }
+
+    // push imm32
+    pub fn push(&mut self, imm: u32) -> &mut Self {
+        self.imm32(0x68, imm)
+    }
+
+    // A 4-byte nop, for padding
+    pub fn nop4(&mut self) -> &mut Self {
+        self.bytes.extend([0x0f, 0x1f, 0x40, 0x00]);
+        self
+    }
+
+    pub fn syscall(&mut self) -> &mut Self {
+        self.bytes.extend([0x0f, 0x05]);
+        self
+    }
+
+    pub fn ret(&mut self) -> &mut Self {
+        self.bytes.push(0xc3);
+        self
+    }
+
+    // write(1, rsi, len), with rsi already pointing at the bytes
+    pub fn write_rsi(&mut self, len: usize) -> &mut Self {
+        self.mov_eax(SYS_WRITE)
+            .mov_edi(1)
+            .mov_edx(len as u32)
+            .syscall()
+    }
+
+    pub fn exit(&mut self, status: u32) -> &mut Self {
+        self.mov_eax(SYS_EXIT).mov_edi(status).syscall()
+    }
+}
@@ -0,0 +1,959 @@
+use delf::{detect::Class, types::*, write::*};
+
+use crate::asm::Code;
+
+const PAGE: u64 = 0x1000;
+const INTERP: &[u8] = b"/lib64/ld-linux-x86-64.so.2\0";
+// Elf64_Ehdr, Elf64_Phdr, Elf64_Sym and Elf64_Rela
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const SYM_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+const STB_GLOBAL_STT_FUNC: u8 = 0x12;
+const R_X86_64_GLOB_DAT: u64 = 6;
+const R_X86_64_JUMP_SLOT: u64 = 7;
+const R_X86_64_RELATIVE: u64 = 8;
+const R_X86_64_IRELATIVE: u64 = 37;
+const DT_RELA: u64 = 7;
+// PLT0 and each entry after it
+const PLT_ENTRY: u64 = 16;
+const DF_1_PIE: u64 = 0x0800_0000;
+
+const STATIC: &str = "Hello from a static executable!\n";
+const PIE: &str = "Hello from a relocated PIE!\n";
+const GREET: &str = "Hello from libgreet.so!\n";
+const GREET_V2: &str = "Hello from the second libgreet.so, swapped in by elk!\n";
+const BACKREF: &str = "Hello from libouter.so, called back by libinner.so!\n";
+const LAZY: &str = "Hello from liblazy.so, bound on first call!\n";
+// How every rung's code ends, with Code::exit(0)
+const EXIT: [&str; 3] = ["mov eax,0x3c", "mov edi,0x0", "syscall"];
+
+// One rung of the ladder: each needs one more thing from the loader than the one before
+pub struct Fixture {
+    pub name: &'static str,
+    pub about: &'static str,
+    // What it prints when elk runs it; None for libraries, which only come along
+    pub expected: Option<&'static str>,
+    // Its .text as `elk dis` lists it, in the form `normalize` puts instructions in; empty for
+    // libraries
+    pub disassembly: &'static [&'static str],
+    pub build: fn() -> FileBuilder,
+}
+
+pub const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "1-static",
+        about: "static, no libc: map it and jump, nothing to relocate",
+        expected: Some(STATIC),
+        disassembly: &[
+            "lea rsi,[rel 0x4000b0]",
+            "mov eax,0x1",
+            "mov edi,0x1",
+            "mov edx,0x20",
+            "syscall",
+            EXIT[0],
+            EXIT[1],
+            EXIT[2],
+        ],
+        build: static_executable,
+    },
+    Fixture {
+        name: "2-pie",
+        about: "position independent, one R_X86_64_RELATIVE",
+        expected: Some(PIE),
+        disassembly: &[
+            "mov rsi,[rel 0x1000]",
+            "mov eax,0x1",
+            "mov edi,0x1",
+            "mov edx,0x1c",
+            "syscall",
+            EXIT[0],
+            EXIT[1],
+            EXIT[2],
+        ],
+        build: pie,
+    },
+    Fixture {
+        name: "libgreet.so",
+        about: "the library 3-needed needs, exporting greet",
+        expected: None,
+        disassembly: &[],
+        build: library,
+    },
+    Fixture {
+        name: "libgreet-v2.so",
+        about:
+            "a libgreet.so to replace the first with, its greet elsewhere and returning its message",
+        expected: None,
+        disassembly: &[],
+        build: library_v2,
+    },
+    Fixture {
+        name: "3-needed",
+        about: "one DT_NEEDED, found through $ORIGIN, and a GLOB_DAT bound to it",
+        expected: Some(GREET),
+        disassembly: &["call [rel 0x1000]", EXIT[0], EXIT[1], EXIT[2]],
+        build: needed,
+    },
+    Fixture {
+        name: "libinner.so",
+        about: "the library libouter.so needs, calling back into libouter.so",
+        expected: None,
+        disassembly: &[],
+        build: inner_library,
+    },
+    Fixture {
+        name: "libouter.so",
+        about: "the library 4-backref needs, needing libinner.so",
+        expected: None,
+        disassembly: &[],
+        build: outer_library,
+    },
+    Fixture {
+        name: "4-backref",
+        about: "a library bound to one loaded before it, through the global scope",
+        expected: Some(BACKREF),
+        disassembly: &["call [rel 0x1000]", EXIT[0], EXIT[1], EXIT[2]],
+        build: backref,
+    },
+    Fixture {
+        name: "liblazy.so",
+        about: "the library 5-lazy needs, calling through its own PLT from an IFUNC resolver",
+        expected: None,
+        disassembly: &[],
+        build: lazy_library,
+    },
+    Fixture {
+        name: "5-lazy",
+        about: "PLT entries bound on first call, the program's and its library's",
+        expected: Some(LAZY),
+        disassembly: &[
+            "push [rel 0x1008]",
+            "jmp [rel 0x1010]",
+            "nop [rax]",
+            "jmp [rel 0x1018]",
+            "push 0x0",
+            "jmp 0x1b0",
+            "call 0x1c0",
+            "mov rsi,rax",
+            "mov eax,0x1",
+            "mov edi,0x1",
+            "mov edx,0x2c",
+            "syscall",
+            EXIT[0],
+            EXIT[1],
+            EXIT[2],
+        ],
+        build: lazy,
+    },
+];
+
+// Addresses the code of a fixture refers to
+struct Addrs {
+    rodata: u64,
+    // The first 8-byte slot of the RW segment
+    data: u64,
+    // The first PLT entry past PLT0
+    plt: u64,
+}
+
+struct Symbol {
+    name: &'static str,
+    // Offset in .text of a defined one, which runs to the end of it
+    defined: Option<u64>,
+}
+
+struct Reloc {
+    // Of the slot, in the RW segment
+    slot: usize,
+    typ: u64,
+    symbol: u32,
+    // Added to the rodata address for RELATIVE, to the .text one for IRELATIVE
+    addend: u64,
+}
+
+#[derive(Default)]
+struct Dynamic {
+    needed: Option<&'static str>,
+    soname: Option<&'static str>,
+    runpath: Option<&'static str>,
+    symbols: Vec<Symbol>,
+    relocs: Vec<Reloc>,
+    // Symbols called through the PLT, by index, each bound on first call
+    plt: Vec<u32>,
+    pie: bool,
+}
+
+// The layout every fixture shares: headers, .interp, .rodata, .text and the dynamic symbol and
+// relocation tables in an RX LOAD segment at `base`, the writable slots and .dynamic in an RW one
+// a page above it
+struct Image {
+    typ: Type,
+    base: u64,
+    interp: bool,
+    rodata: &'static [u8],
+    slots: usize,
+    dynamic: Option<Dynamic>,
+    text: fn(&Addrs, &mut Code),
+}
+
+fn static_executable() -> FileBuilder {
+    Image {
+        typ: Type::Exec,
+        base: 0x400000,
+        interp: false,
+        rodata: STATIC.as_bytes(),
+        slots: 0,
+        dynamic: None,
+        text: |at, code| {
+            code.lea_rsi(at.rodata).write_rsi(STATIC.len()).exit(0);
+        },
+    }
+    .build()
+}
+
+// The message is found through a pointer, which is only right once relocated
+fn pie() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: true,
+        rodata: PIE.as_bytes(),
+        slots: 1,
+        dynamic: Some(Dynamic {
+            relocs: vec![Reloc {
+                slot: 0,
+                typ: R_X86_64_RELATIVE,
+                symbol: 0,
+                addend: 0,
+            }],
+            pie: true,
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.load_rsi(at.data).write_rsi(PIE.len()).exit(0);
+        },
+    }
+    .build()
+}
+
+fn library() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: false,
+        rodata: GREET.as_bytes(),
+        slots: 0,
+        dynamic: Some(Dynamic {
+            soname: Some("libgreet.so"),
+            symbols: vec![Symbol {
+                name: "greet",
+                defined: Some(0),
+            }],
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.lea_rsi(at.rodata).write_rsi(GREET.len()).ret();
+        },
+    }
+    .build()
+}
+
+// libgreet.so with a longer message, so greet moves, and returning it instead of writing it
+fn library_v2() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: false,
+        rodata: GREET_V2.as_bytes(),
+        slots: 0,
+        dynamic: Some(Dynamic {
+            soname: Some("libgreet.so"),
+            symbols: vec![Symbol {
+                name: "greet",
+                defined: Some(0),
+            }],
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.lea_rax(at.rodata).ret();
+        },
+    }
+    .build()
+}
+
+fn needed() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: true,
+        rodata: b"",
+        slots: 1,
+        dynamic: Some(Dynamic {
+            needed: Some("libgreet.so"),
+            runpath: Some("$ORIGIN"),
+            symbols: vec![Symbol {
+                name: "greet",
+                defined: None,
+            }],
+            relocs: vec![Reloc {
+                slot: 0,
+                typ: R_X86_64_GLOB_DAT,
+                symbol: 1,
+                addend: 0,
+            }],
+            pie: true,
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.call_indirect(at.data).exit(0);
+        },
+    }
+    .build()
+}
+
+// Calls outer_write, which it doesn't need: only the global scope, where libouter.so comes
+// before it, has it
+fn inner_library() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: false,
+        rodata: b"",
+        slots: 1,
+        dynamic: Some(Dynamic {
+            soname: Some("libinner.so"),
+            symbols: vec![
+                Symbol {
+                    name: "inner",
+                    defined: Some(0),
+                },
+                Symbol {
+                    name: "outer_write",
+                    defined: None,
+                },
+            ],
+            relocs: vec![Reloc {
+                slot: 0,
+                typ: R_X86_64_GLOB_DAT,
+                symbol: 2,
+                addend: 0,
+            }],
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.call_indirect(at.data).ret();
+        },
+    }
+    .build()
+}
+
+fn outer_library() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: false,
+        rodata: BACKREF.as_bytes(),
+        slots: 0,
+        dynamic: Some(Dynamic {
+            needed: Some("libinner.so"),
+            soname: Some("libouter.so"),
+            runpath: Some("$ORIGIN"),
+            symbols: vec![Symbol {
+                name: "outer_write",
+                defined: Some(0),
+            }],
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.lea_rsi(at.rodata).write_rsi(BACKREF.len()).ret();
+        },
+    }
+    .build()
+}
+
+// Needs libouter.so, which needs libinner.so, and calls inner
+fn backref() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: true,
+        rodata: b"",
+        slots: 1,
+        dynamic: Some(Dynamic {
+            needed: Some("libouter.so"),
+            runpath: Some("$ORIGIN"),
+            symbols: vec![Symbol {
+                name: "inner",
+                defined: None,
+            }],
+            relocs: vec![Reloc {
+                slot: 0,
+                typ: R_X86_64_GLOB_DAT,
+                symbol: 1,
+                addend: 0,
+            }],
+            pie: true,
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.call_indirect(at.data).exit(0);
+        },
+    }
+    .build()
+}
+
+// Its own answer, the message, through the PLT: from lazy, called by 5-lazy through its PLT,
+// and from the IFUNC resolver of the first slot, which is lazy too
+fn lazy_library() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: false,
+        rodata: LAZY.as_bytes(),
+        slots: 1,
+        dynamic: Some(Dynamic {
+            soname: Some("liblazy.so"),
+            symbols: vec![
+                Symbol {
+                    name: "answer",
+                    defined: Some(0),
+                },
+                Symbol {
+                    name: "lazy",
+                    defined: Some(8),
+                },
+            ],
+            relocs: vec![Reloc {
+                slot: 0,
+                typ: R_X86_64_IRELATIVE,
+                symbol: 0,
+                addend: 8,
+            }],
+            plt: vec![1],
+            ..Default::default()
+        }),
+        text: |at, code| {
+            // answer is the 8 bytes before lazy
+            code.lea_rax(at.rodata).ret().call(at.plt).ret();
+        },
+    }
+    .build()
+}
+
+fn lazy() -> FileBuilder {
+    Image {
+        typ: Type::Dyn,
+        base: 0,
+        interp: true,
+        rodata: b"",
+        slots: 0,
+        dynamic: Some(Dynamic {
+            needed: Some("liblazy.so"),
+            runpath: Some("$ORIGIN"),
+            symbols: vec![Symbol {
+                name: "lazy",
+                defined: None,
+            }],
+            plt: vec![1],
+            pie: true,
+            ..Default::default()
+        }),
+        text: |at, code| {
+            code.call(at.plt)
+                .mov_rsi_rax()
+                .write_rsi(LAZY.len())
+                .exit(0);
+        },
+    }
+    .build()
+}
+
+// A section that is the part of `segment` at `offset`
+fn section(
+    name: &str,
+    typ: SectionType,
+    flags: u64,
+    segment: usize,
+    offset: usize,
+    size: usize,
+) -> SectionBuilder {
+    SectionBuilder {
+        name: name.into(),
+        typ,
+        flags: SectionBits::from_bits(flags),
+        align: 1,
+        entsize: 0,
+        link: 0,
+        info: 0,
+        data: SectionData::Within {
+            segment,
+            offset: offset as u64,
+            size: size as u64,
+        },
+    }
+}
+
+// Appends `bytes` to `segment`, 8-byte aligned, and returns their offset in it
+fn append(segment: &mut Vec<u8>, bytes: &[u8]) -> usize {
+    segment.resize((segment.len() + 7) & !7, 0);
+    segment.extend(bytes);
+    segment.len() - bytes.len()
+}
+
+fn words(words: &[u64]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+impl Image {
+    fn build(&self) -> FileBuilder {
+        let (alloc, exec, write) = (0x2, 0x4, 0x1);
+        let rw = self.slots > 0 || self.dynamic.is_some();
+        // PT_PHDR and PT_INTERP: ld.so works out where the program was loaded from PT_PHDR
+        let interp = 2 * self.interp as usize;
+        let (rx_index, rw_index) = (interp, interp + 1);
+        // Those, the LOADs, PT_DYNAMIC and PT_GNU_STACK
+        let count = interp + 1 + rw as usize + self.dynamic.is_some() as usize + 1;
+        let mut rx = vec![0; EHDR_SIZE + count * PHDR_SIZE];
+        let mut sections = Vec::new();
+
+        let interp_at = match self.interp {
+            true => {
+                let at = append(&mut rx, INTERP);
+                sections.push(section(
+                    ".interp",
+                    SectionType::ProgBits,
+                    alloc,
+                    rx_index,
+                    at,
+                    INTERP.len(),
+                ));
+                Some(at)
+            }
+            false => None,
+        };
+        let rodata_at = append(&mut rx, self.rodata);
+        if !self.rodata.is_empty() {
+            sections.push(section(
+                ".rodata",
+                SectionType::ProgBits,
+                alloc,
+                rx_index,
+                rodata_at,
+                self.rodata.len(),
+            ));
+        }
+        // PLT0 pushes GOT[1] and jumps to GOT[2]; entry i jumps through GOT[3 + i], which
+        // starts out pointing at its push of i
+        let plt = self.dynamic.as_ref().map_or(&[][..], |d| &d.plt[..]);
+        let got = self.base + PAGE + 8 * self.slots as u64;
+        let plt_at = append(&mut rx, &[]);
+        let mut plt_code = Code::at(self.base + plt_at as u64);
+        let mut got_words = vec![0; 3];
+        let plt0 = plt_code.addr;
+        if !plt.is_empty() {
+            plt_code
+                .push_indirect(got + 8)
+                .jmp_indirect(got + 16)
+                .nop4();
+            for i in 0..plt.len() {
+                plt_code.jmp_indirect(got + 8 * (3 + i as u64));
+                got_words.push(plt_code.here());
+                plt_code.push(i as u32).jmp(plt0);
+            }
+            rx.extend(&plt_code.bytes);
+            let mut sh = section(
+                ".plt",
+                SectionType::ProgBits,
+                alloc | exec,
+                rx_index,
+                plt_at,
+                plt_code.bytes.len(),
+            );
+            (sh.align, sh.entsize) = (PLT_ENTRY, PLT_ENTRY);
+            sections.push(sh);
+        }
+        let text_at = append(&mut rx, &[]);
+        let addrs = Addrs {
+            rodata: self.base + rodata_at as u64,
+            data: self.base + PAGE,
+            plt: plt0 + PLT_ENTRY,
+        };
+        let mut code = Code::at(self.base + text_at as u64);
+        (self.text)(&addrs, &mut code);
+        rx.extend(&code.bytes);
+        sections.push(section(
+            ".text",
+            SectionType::ProgBits,
+            alloc | exec,
+            rx_index,
+            text_at,
+            code.bytes.len(),
+        ));
+        let text = sections.len() as u16;
+
+        let mut rw_bytes = vec![0; self.slots * 8];
+        if self.slots > 0 {
+            let mut sh = section(
+                ".data",
+                SectionType::ProgBits,
+                alloc | write,
+                rw_index,
+                0,
+                rw_bytes.len(),
+            );
+            sh.align = 8;
+            sections.push(sh);
+        }
+        let got_at = rw_bytes.len();
+        if !plt.is_empty() {
+            rw_bytes.extend(words(&got_words));
+            let mut sh = section(
+                ".got.plt",
+                SectionType::ProgBits,
+                alloc | write,
+                rw_index,
+                got_at,
+                got_words.len() * 8,
+            );
+            sh.align = 8;
+            sections.push(sh);
+        }
+        let mut dynamic_at = None;
+        if let Some(dynamic) = &self.dynamic {
+            let mut dynstr = vec![0u8];
+            let mut string = |s: &str| {
+                dynstr.extend(s.as_bytes());
+                dynstr.push(0);
+                (dynstr.len() - s.len() - 1) as u64
+            };
+            let mut dynsym = vec![0; SYM_SIZE as usize];
+            for symbol in &dynamic.symbols {
+                dynsym.extend((string(symbol.name) as u32).to_le_bytes());
+                dynsym.extend([STB_GLOBAL_STT_FUNC, 0]);
+                let (shndx, value, size) = match symbol.defined {
+                    Some(offset) => (text, code.addr + offset, code.bytes.len() as u64 - offset),
+                    None => (0, 0, 0),
+                };
+                dynsym.extend(shndx.to_le_bytes());
+                dynsym.extend(words(&[value, size]));
+            }
+            let needed = dynamic.needed.map(&mut string);
+            let soname = dynamic.soname.map(&mut string);
+            let runpath = dynamic.runpath.map(&mut string);
+            // One bucket chaining every symbol, last to first
+            let symbols = dynamic.symbols.len() as u32 + 1;
+            let hash: Vec<u8> = [1, symbols, symbols - 1]
+                .iter()
+                .copied()
+                .chain((0..symbols).map(|i| i.saturating_sub(1)))
+                .flat_map(u32::to_le_bytes)
+                .collect();
+            let rela: Vec<u8> = dynamic
+                .relocs
+                .iter()
+                .flat_map(|reloc| {
+                    words(&[
+                        addrs.data + 8 * reloc.slot as u64,
+                        (u64::from(reloc.symbol) << 32) | reloc.typ,
+                        match reloc.typ {
+                            R_X86_64_RELATIVE => addrs.rodata + reloc.addend,
+                            R_X86_64_IRELATIVE => code.addr + reloc.addend,
+                            _ => reloc.addend,
+                        },
+                    ])
+                })
+                .collect();
+            let rela_plt: Vec<u8> = dynamic
+                .plt
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &symbol)| {
+                    words(&[
+                        got + 8 * (3 + i as u64),
+                        (u64::from(symbol) << 32) | R_X86_64_JUMP_SLOT,
+                        0,
+                    ])
+                })
+                .collect();
+
+            let dynsym_at = append(&mut rx, &dynsym);
+            let dynstr_at = append(&mut rx, &dynstr);
+            let hash_at = append(&mut rx, &hash);
+            let rela_at = append(&mut rx, &rela);
+            let rela_plt_at = append(&mut rx, &rela_plt);
+            // .dynsym, then .dynstr right after it
+            let (dynsym_index, dynstr_index) =
+                (sections.len() as u32 + 1, sections.len() as u32 + 2);
+            let tables = [
+                (
+                    ".dynsym",
+                    SectionType::DynSym,
+                    dynsym_at,
+                    dynsym.len(),
+                    dynstr_index,
+                    SYM_SIZE,
+                ),
+                (
+                    ".dynstr",
+                    SectionType::StrTab,
+                    dynstr_at,
+                    dynstr.len(),
+                    0,
+                    0,
+                ),
+                (
+                    ".hash",
+                    SectionType::Hash,
+                    hash_at,
+                    hash.len(),
+                    dynsym_index,
+                    4,
+                ),
+                (
+                    ".rela.dyn",
+                    SectionType::Rela,
+                    rela_at,
+                    rela.len(),
+                    dynsym_index,
+                    RELA_SIZE,
+                ),
+                (
+                    ".rela.plt",
+                    SectionType::Rela,
+                    rela_plt_at,
+                    rela_plt.len(),
+                    dynsym_index,
+                    RELA_SIZE,
+                ),
+            ];
+            for (name, typ, offset, size, link, entsize) in tables {
+                if size == 0 {
+                    continue;
+                }
+                let mut sh = section(name, typ, alloc, rx_index, offset, size);
+                (sh.link, sh.entsize) = (link, entsize);
+                if typ == SectionType::DynSym {
+                    // Index of the first global symbol
+                    sh.info = 1;
+                }
+                if typ != SectionType::StrTab {
+                    sh.align = 8;
+                }
+                sections.push(sh);
+            }
+
+            let at = |offset: usize| self.base + offset as u64;
+            let mut entries = vec![];
+            entries.extend(needed.map(|name| (DynamicTag::Needed, name)));
+            entries.extend(soname.map(|name| (DynamicTag::SOName, name)));
+            entries.extend(runpath.map(|path| (DynamicTag::Runpath, path)));
+            entries.extend([
+                (DynamicTag::Hash, at(hash_at)),
+                (DynamicTag::StrTab, at(dynstr_at)),
+                (DynamicTag::SymTab, at(dynsym_at)),
+                (DynamicTag::StrSz, dynstr.len() as u64),
+                (DynamicTag::SymEnt, SYM_SIZE),
+            ]);
+            if !rela.is_empty() {
+                let relative = dynamic
+                    .relocs
+                    .iter()
+                    .filter(|reloc| reloc.typ == R_X86_64_RELATIVE)
+                    .count();
+                entries.extend([
+                    (DynamicTag::Rela, at(rela_at)),
+                    (DynamicTag::RelaSz, rela.len() as u64),
+                    (DynamicTag::RelaEnt, RELA_SIZE),
+                    (DynamicTag::RelaCount, relative as u64),
+                ]);
+            }
+            if !rela_plt.is_empty() {
+                entries.extend([
+                    (DynamicTag::PltGot, got),
+                    (DynamicTag::PltRelSz, rela_plt.len() as u64),
+                    (DynamicTag::PltRel, DT_RELA),
+                    (DynamicTag::JmpRel, at(rela_plt_at)),
+                ]);
+            }
+            if dynamic.pie {
+                entries.push((DynamicTag::Flags1, DF_1_PIE));
+            }
+            entries.push((DynamicTag::Null, 0));
+            let bytes: Vec<u8> = entries
+                .iter()
+                .flat_map(|&(tag, value)| words(&[u64::from(tag), value]))
+                .collect();
+            let offset = append(&mut rw_bytes, &bytes);
+            dynamic_at = Some((offset, bytes.len()));
+            // GOT[0] is the link-time address of _DYNAMIC
+            if !plt.is_empty() {
+                let dynamic = (addrs.data + offset as u64).to_le_bytes();
+                rw_bytes[got_at..got_at + 8].copy_from_slice(&dynamic);
+            }
+            let mut sh = section(
+                ".dynamic",
+                SectionType::Dynamic,
+                alloc | write,
+                rw_index,
+                offset,
+                bytes.len(),
+            );
+            (sh.link, sh.entsize, sh.align) = (dynstr_index, 16, 8);
+            sections.push(sh);
+        }
+        assert!(rx.len() as u64 <= PAGE, "fixture outgrew its first page");
+
+        let mut file = FileBuilder::new(Class::Elf64, self.typ, Machine::X86_64);
+        // Libraries have no entry point of their own
+        let library = matches!(&self.dynamic, Some(d) if d.soname.is_some());
+        if !library {
+            file.entry = Addr(code.addr);
+        }
+        let read = SegmentBits::from_bits(SegmentFlags::Read as u32);
+        let read_write: SegmentBits = (SegmentFlags::Read | SegmentFlags::Write).into();
+        if let Some(at) = interp_at {
+            file.segments.push(SegmentBuilder {
+                typ: SegmentType::ProgHeader,
+                flags: read,
+                align: 8,
+                data: SegmentData::Within {
+                    segment: rx_index,
+                    offset: EHDR_SIZE as u64,
+                    file_size: (count * PHDR_SIZE) as u64,
+                    mem_size: (count * PHDR_SIZE) as u64,
+                },
+            });
+            file.segments.push(SegmentBuilder {
+                typ: SegmentType::Interp,
+                flags: read,
+                align: 1,
+                data: SegmentData::Within {
+                    segment: rx_index,
+                    offset: at as u64,
+                    file_size: INTERP.len() as u64,
+                    mem_size: INTERP.len() as u64,
+                },
+            });
+        }
+        file.segments.push(SegmentBuilder {
+            typ: SegmentType::Load,
+            flags: (SegmentFlags::Read | SegmentFlags::Execute).into(),
+            align: PAGE,
+            data: SegmentData::Bytes {
+                vaddr: Addr(self.base),
+                mem_size: rx.len() as u64,
+                data: rx,
+                headers: true,
+            },
+        });
+        if rw {
+            file.segments.push(SegmentBuilder {
+                typ: SegmentType::Load,
+                flags: read_write,
+                align: PAGE,
+                data: SegmentData::Bytes {
+                    vaddr: Addr(addrs.data),
+                    mem_size: rw_bytes.len() as u64,
+                    data: rw_bytes,
+                    headers: false,
+                },
+            });
+        }
+        if let Some((offset, size)) = dynamic_at {
+            file.segments.push(SegmentBuilder {
+                typ: SegmentType::Dynamic,
+                flags: read_write,
+                align: 8,
+                data: SegmentData::Within {
+                    segment: rw_index,
+                    offset: offset as u64,
+                    file_size: size as u64,
+                    mem_size: size as u64,
+                },
+            });
+        }
+        file.segments.push(SegmentBuilder {
+            typ: SegmentType::GnuStack,
+            flags: read_write,
+            align: 16,
+            data: SegmentData::Bytes {
+                vaddr: Addr(0),
+                data: Vec::new(),
+                mem_size: 0,
+                headers: false,
+            },
+        });
+        file.sections = sections;
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use delf::{data::Data, FileHeader};
+
+    use super::*;
+
+    fn parse(build: fn() -> FileBuilder) -> FileHeader {
+        let bytes = build().to_bytes().unwrap();
+        FileHeader::parse_or_describe(&Data::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn rungs_need_what_they_say() {
+        let file = parse(static_executable);
+        assert!(file.segments_of_type(SegmentType::Dynamic).next().is_none());
+        assert!(file.check_dynamic().is_ok());
+
+        let file = parse(pie);
+        let relocs = file.read_rela_entries().unwrap();
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].typ, RelType::Relative);
+        let rodata = file.section_by_name(".rodata").unwrap();
+        assert!(file
+            .bytes_at(rodata.addr)
+            .unwrap()
+            .starts_with(PIE.as_bytes()));
+
+        let file = parse(needed);
+        assert_eq!(file.dynamic_strings(DynamicTag::Needed), ["libgreet.so"]);
+        assert_eq!(file.dynamic_strings(DynamicTag::Runpath), ["$ORIGIN"]);
+        let library = parse(library);
+        let greet = library.read_syms();
+        assert!(greet
+            .iter()
+            .any(|sym| sym.name == "greet" && sym.value.0 != 0));
+        let greet_at = |file: &FileHeader| {
+            let syms = file.read_syms();
+            syms.iter().find(|sym| sym.name == "greet").unwrap().value
+        };
+        let v2 = parse(library_v2);
+        assert_eq!(v2.dynamic_strings(DynamicTag::SOName), ["libgreet.so"]);
+        assert_ne!(greet_at(&v2), greet_at(&library));
+
+        let inner = parse(inner_library);
+        assert!(inner.dynamic_strings(DynamicTag::Needed).is_empty());
+        let relocs = inner.read_rela_entries().unwrap();
+        assert_eq!(
+            inner.read_syms()[relocs[0].sym as usize].name,
+            "outer_write"
+        );
+        let outer = parse(outer_library);
+        assert_eq!(outer.dynamic_strings(DynamicTag::Needed), ["libinner.so"]);
+
+        let library = parse(lazy_library);
+        let syms = library.read_syms();
+        let relocs = library.read_plt_rela_entries().unwrap();
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].typ, RelType::JumpSlot);
+        assert_eq!(syms[relocs[0].sym as usize].name, "answer");
+        let ifunc = library.read_rela_entries().unwrap();
+        let lazy_sym = syms.iter().find(|sym| sym.name == "lazy").unwrap();
+        assert_eq!(ifunc[0].typ, RelType::IRelative);
+        assert_eq!(ifunc[0].addend.0 as u64, lazy_sym.value.0);
+        let program = parse(lazy);
+        assert_eq!(program.read_plt_rela_entries().unwrap().len(), 1);
+        assert!(!program.binds_now());
+    }
+}
@@ -0,0 +1,206 @@
+mod asm;
+mod fixtures;
+
+use std::{
+    env,
+    error::Error,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use fixtures::FIXTURES;
+
+const USAGE: &str = "\
+Usage: cargo xtask <TASK>
+
+Tasks:
+  fixtures [--check]  Write the ladder's binaries to elk/samples/ladder, or check they're current
+  ladder [ELK]        Run and disassemble each rung of the ladder with elk, built from elk/
+                      unless ELK is given";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[..] {
+        ["fixtures"] => write_fixtures(),
+        ["fixtures", "--check"] => check_fixtures(),
+        ["ladder"] => build_elk().and_then(|elk| ladder(&elk)),
+        ["ladder", elk] => ladder(Path::new(elk)),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives in the repository")
+        .to_path_buf()
+}
+
+fn ladder_dir() -> PathBuf {
+    root().join("elk/samples/ladder")
+}
+
+fn write_fixtures() -> Result<(), Box<dyn Error>> {
+    let dir = ladder_dir();
+    fs::create_dir_all(&dir)?;
+    for fixture in FIXTURES {
+        let path = dir.join(fixture.name);
+        fs::write(&path, (fixture.build)().to_bytes()?)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+// The committed binaries are what `fixtures` would write now
+fn check_fixtures() -> Result<(), Box<dyn Error>> {
+    let mut stale = Vec::new();
+    for fixture in FIXTURES {
+        let written = fs::read(ladder_dir().join(fixture.name)).ok();
+        if written != Some((fixture.build)().to_bytes()?) {
+            stale.push(fixture.name);
+        }
+    }
+    match stale.len() {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} out of date, run `cargo xtask fixtures`",
+            stale.join(", ")
+        )
+        .into()),
+    }
+}
+
+// With iced on, the dis step disassembles in-process and doesn't need ndisasm installed
+fn build_elk() -> Result<PathBuf, Box<dyn Error>> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let status = Command::new(cargo)
+        .args(["build", "--features", "iced", "--manifest-path"])
+        .arg(root().join("elk/Cargo.toml"))
+        .status()?;
+    if !status.success() {
+        return Err("building elk failed".into());
+    }
+    Ok(root().join("elk/target/debug/elk"))
+}
+
+// Runs the rungs in order and stops at the first that fails: the ones below it are what the
+// loader gets right so far. Each has to disassemble to the code it was written with too.
+fn ladder(elk: &Path) -> Result<(), Box<dyn Error>> {
+    let rungs: Vec<_> = FIXTURES.iter().filter(|f| f.expected.is_some()).collect();
+    for (i, fixture) in rungs.iter().enumerate() {
+        let path = ladder_dir().join(fixture.name);
+        let output = Command::new(elk)
+            .args(["run", "--quiet"])
+            .arg(&path)
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let rung = format!("{}/{} {}", i + 1, rungs.len(), fixture.name);
+        let failed = || format!("rung {} of {} failed", i + 1, rungs.len());
+        if !output.status.success() || Some(&*stdout) != fixture.expected {
+            println!("{}: FAILED ({})", rung, fixture.about);
+            println!("  expected {:?}", fixture.expected.unwrap_or_default());
+            println!("  printed  {:?}, {}", stdout, output.status);
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                println!("  {}", line);
+            }
+            return Err(failed().into());
+        }
+
+        let output = Command::new(elk)
+            .args(["dis", "--color", "never"])
+            .arg(&path)
+            .output()?;
+        let listing: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(instruction)
+            .collect();
+        if !output.status.success() || listing != fixture.disassembly {
+            println!("{}: FAILED to disassemble ({})", rung, fixture.about);
+            println!("  expected {:?}", fixture.disassembly);
+            println!("  listed   {:?}, {}", listing, output.status);
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                println!("  {}", line);
+            }
+            return Err(failed().into());
+        }
+        println!("{}: ok ({})", rung, fixture.about);
+    }
+    Ok(())
+}
+
+// The instruction on a line of `elk dis`, after its address and bytes, with what ndisasm and
+// iced spell differently evened out: numbers in hex, no operand sizes, no zero displacements
+// and no comments
+fn instruction(line: &str) -> Option<String> {
+    let line = line.split(" ;").next()?;
+    let mut columns = line.split_whitespace();
+    let (_addr, _bytes) = (columns.next()?, columns.next()?);
+    let text = columns
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("qword ", "")
+        .replace("dword ", "")
+        .replace("+0x0]", "]");
+    let mut out = String::new();
+    let mut word = String::new();
+    for c in text.chars().chain(Some(' ')) {
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+        let number = match word.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => word.parse::<u64>().ok(),
+        };
+        match number {
+            Some(n) => out += &format!("{:#x}", n),
+            None => out += &word,
+        }
+        word.clear();
+        out.push(c);
+    }
+    Some(out.trim_end().to_string()).filter(|text| !text.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_are_current() {
+        check_fixtures().unwrap();
+    }
+
+    #[test]
+    fn ndisasm_and_iced_listings_read_the_same() {
+        let ndisasm = "000001BC  0F1F4000          nop dword [rax+0x0]";
+        let iced = "000001BC  0F1F4000          nop dword [rax]";
+        assert_eq!(instruction(ndisasm).as_deref(), Some("nop [rax]"));
+        assert_eq!(instruction(iced), instruction(ndisasm));
+        let iced = "000001CB  E9E0FFFFFF        jmp 0x00000000000001b0";
+        assert_eq!(instruction(iced).as_deref(), Some("jmp 0x1b0"));
+        let iced = "000001B0  FF154A0E0000      call qword [rel 0x1000]  ; greet@got";
+        assert_eq!(instruction(iced).as_deref(), Some("call [rel 0x1000]"));
+        assert_eq!(
+            instruction("004000D7  B801000000        mov eax,1").as_deref(),
+            Some("mov eax,0x1")
+        );
+    }
+
+    // Builds elk, then runs and disassembles every rung with it
+    #[test]
+    fn ladder_passes() {
+        ladder(&build_elk().unwrap()).unwrap();
+    }
+}